@@ -0,0 +1,124 @@
+//! Declarative global toolset
+//!
+//! `~/.apl/Aplfile` lists the packages a machine should have, each with a
+//! version requirement. `apl apply` brings the installed packages in line
+//! with it and `apl dump` writes it from the current state.
+//!
+//! ```toml
+//! [packages]
+//! jq = "*"              # any version; the latest when installing
+//! terraform = "1.5.x"   # newest installed or released 1.5
+//! ripgrep = "=14.1.0"   # exactly this version
+//! ```
+//!
+//! Requirements are matched like `apl.toml` dependencies.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+const HEADER: &str = "# Packages this machine should have. Apply with `apl apply`.\n\n";
+
+#[derive(Error, Debug)]
+pub enum AplfileError {
+    #[error("Failed to read {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Invalid Aplfile {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: toml::de::Error,
+    },
+
+    #[error("Failed to write {}: {source}", path.display())]
+    Write {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Aplfile {
+    /// Version requirements by package name
+    pub packages: BTreeMap<String, String>,
+}
+
+impl Aplfile {
+    pub fn load(path: &Path) -> Result<Self, AplfileError> {
+        let content = std::fs::read_to_string(path).map_err(|source| AplfileError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        Self::parse(&content).map_err(|source| AplfileError::Parse {
+            path: path.to_path_buf(),
+            source,
+        })
+    }
+
+    pub fn parse(content: &str) -> Result<Self, toml::de::Error> {
+        toml::from_str(content)
+    }
+
+    /// The file's contents, with a header comment.
+    pub fn render(&self) -> String {
+        let body = toml::to_string(self).expect("string maps always serialize");
+        format!("{HEADER}{body}")
+    }
+
+    /// Writes the file atomically.
+    pub fn save(&self, path: &Path) -> Result<(), AplfileError> {
+        let write_err = |source| AplfileError::Write {
+            path: path.to_path_buf(),
+            source,
+        };
+        let temp = path.with_extension("tmp");
+        std::fs::write(&temp, self.render()).map_err(write_err)?;
+        std::fs::rename(&temp, path).map_err(write_err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_roundtrip() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("Aplfile");
+        let mut aplfile = Aplfile::default();
+        aplfile.packages.insert("terraform".into(), "1.5.x".into());
+        aplfile.packages.insert("jq".into(), "*".into());
+
+        aplfile.save(&path).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# Packages"));
+        assert!(content.contains("[packages]\njq = \"*\"\nterraform = \"1.5.x\"\n"));
+        assert_eq!(Aplfile::load(&path).unwrap(), aplfile);
+    }
+
+    #[test]
+    fn test_invalid_file_is_reported() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("Aplfile");
+        std::fs::write(&path, "[packages]\njq = 1\n").unwrap();
+        assert!(matches!(
+            Aplfile::load(&path),
+            Err(AplfileError::Parse { .. })
+        ));
+        assert!(matches!(
+            Aplfile::load(&dir.path().join("missing")),
+            Err(AplfileError::Io { .. })
+        ));
+    }
+}
@@ -0,0 +1,151 @@
+//! Apply and dump commands
+use crate::aplfile::Aplfile;
+use crate::db::{InstallReason, StateDb};
+use crate::ops::install::InstallTask;
+use crate::ui::Output;
+use crate::{DbHandle, aplfile_path};
+use anyhow::{Context, Result};
+use crossterm::style::Stylize;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+/// Install, switch and (with `prune`) remove packages until the installed
+/// set matches the Aplfile
+pub async fn apply(file: Option<&Path>, prune: bool, yes: bool, dry_run: bool) -> Result<()> {
+    let path = file.map_or_else(aplfile_path, Path::to_path_buf);
+    let aplfile = Aplfile::load(&path)?;
+    let output = Output::new();
+
+    let db = StateDb::open().context("Failed to open state database")?;
+    let index = crate::store::source::load_index().ok().flatten();
+    let client = super::install::http_client()?;
+    let reporter = Arc::new(output.clone());
+    let ctx = crate::ops::Context::new(
+        DbHandle::spawn().context("Failed to open database")?,
+        index,
+        client,
+        reporter.clone(),
+    );
+
+    let plan = crate::ops::apply::plan(&ctx, &db, &aplfile)
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+    let removals: &[String] = if prune { &plan.extras } else { &[] };
+
+    if !plan.installs_anything() && removals.is_empty() {
+        output.success(&format!("Installed packages match {}", path.display()));
+        print_unlisted(&output, &plan.extras, prune);
+        return Ok(());
+    }
+
+    println!();
+    println!("{}", "Plan".bold());
+    for task in &plan.tasks {
+        match task {
+            InstallTask::Download(name, version) => println!(
+                "  {} {name} {}",
+                "+".green(),
+                version.as_deref().unwrap_or("latest")
+            ),
+            InstallTask::Switch(name, version) => {
+                println!("  {} {name} {version} (switch)", "~".yellow());
+            }
+            InstallTask::AlreadyInstalled(..) => {}
+        }
+    }
+    for name in removals {
+        println!("  {} {name}", "-".red());
+    }
+    println!();
+    print_unlisted(&output, &plan.extras, prune);
+
+    if dry_run {
+        output.info("(dry run) No changes made");
+        return Ok(());
+    }
+
+    if !yes && !removals.is_empty() {
+        use std::io::Write;
+        print!("Remove {} package(s)? (y/N) ", removals.len());
+        std::io::stdout().flush()?;
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        if !input.trim().eq_ignore_ascii_case("y") {
+            output.info("Apply cancelled.");
+            return Ok(());
+        }
+    }
+
+    if plan.installs_anything() {
+        crate::ops::install::install_packages(&ctx, &plan.install, false)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+    }
+    if !removals.is_empty() {
+        crate::ops::remove::remove_packages(&output, removals, false, false)
+            .await
+            .map_err(|e| anyhow::anyhow!(e))?;
+    }
+
+    // Brief sleep to ensure UI actor completes rendering
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    Ok(())
+}
+
+/// Write the explicitly installed packages to an Aplfile (`-` for stdout).
+///
+/// Versions are left open (`*`) unless `exact`; pinned packages keep their pin.
+pub fn dump(file: Option<&Path>, exact: bool, dry_run: bool) -> Result<()> {
+    let db = StateDb::open().context("Failed to open state database")?;
+    let pins = db.list_pins()?;
+
+    let mut aplfile = Aplfile::default();
+    for package in db.list_packages()? {
+        if package.reason != InstallReason::Explicit {
+            continue;
+        }
+        let requirement = match pins.get(&package.name) {
+            Some(pin) => pin
+                .requirement
+                .clone()
+                .unwrap_or_else(|| format!("={}", package.version)),
+            None if exact => format!("={}", package.version),
+            None => "*".to_string(),
+        };
+        aplfile.packages.insert(package.name, requirement);
+    }
+
+    let path: PathBuf = match file {
+        Some(path) if path == Path::new("-") => {
+            print!("{}", aplfile.render());
+            return Ok(());
+        }
+        Some(path) => path.to_path_buf(),
+        None => aplfile_path(),
+    };
+    let output = Output::new();
+    if dry_run {
+        output.info(&format!(
+            "(dry run) Would write {} package(s) to {}",
+            aplfile.packages.len(),
+            path.display()
+        ));
+        return Ok(());
+    }
+    aplfile.save(&path)?;
+    output.success(&format!(
+        "Wrote {} package(s) to {}",
+        aplfile.packages.len(),
+        path.display()
+    ));
+    Ok(())
+}
+
+fn print_unlisted(output: &Output, extras: &[String], prune: bool) {
+    if !prune && !extras.is_empty() {
+        output.info(&format!(
+            "Not in the Aplfile: {} (use --prune to remove)",
+            extras.join(", ")
+        ));
+    }
+}
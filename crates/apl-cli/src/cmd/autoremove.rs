@@ -0,0 +1,47 @@
+//! Autoremove command
+use crate::db::StateDb;
+use crate::ui::Output;
+use anyhow::{Context, Result};
+use crossterm::style::Stylize;
+
+/// Remove packages that were only installed as dependencies and are no longer needed
+pub async fn autoremove(yes: bool, dry_run: bool) -> Result<()> {
+    let db = StateDb::open().context("Failed to open state database")?;
+    let reporter = Output::new();
+
+    let orphans = db.list_orphans()?;
+    drop(db);
+
+    if orphans.is_empty() {
+        reporter.info("No orphaned packages to remove.");
+        return Ok(());
+    }
+
+    if !yes && !dry_run {
+        use std::io::Write;
+        println!();
+        print!(
+            "  {} This will remove {} package(s) no longer needed: {}. Continue? (y/N) ",
+            "NOTE:".bold().yellow(),
+            orphans.len(),
+            orphans.join(", ")
+        );
+        std::io::stdout().flush()?;
+
+        let mut input = String::new();
+        std::io::stdin().read_line(&mut input)?;
+        if !input.trim().eq_ignore_ascii_case("y") {
+            reporter.error("Operation cancelled");
+            return Ok(());
+        }
+    }
+
+    crate::ops::remove::remove_packages(&reporter, &orphans, false, dry_run)
+        .await
+        .map_err(|e| anyhow::anyhow!(e))?;
+
+    // Brief sleep to ensure UI actor completes rendering
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+
+    Ok(())
+}
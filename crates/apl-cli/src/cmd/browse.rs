@@ -0,0 +1,315 @@
+//! Browse command - full-screen package browser
+
+use crate::DbHandle;
+use crate::db::StateDb;
+use crate::ops;
+use crate::store::lock::{LockMode, ProcessLock};
+use crate::store::source::Source;
+use crate::ui::Output;
+use crate::ui::actor::UiEvent;
+use crate::ui::browser::{Action, Browser, LocalState, View};
+use anyhow::{Context, Result, anyhow, bail};
+use apl_schema::index::PackageIndex;
+use apl_schema::types::{PackageName, Version};
+use crossterm::event::{self, Event, KeyEventKind};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute};
+use std::io::{self, IsTerminal};
+use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::runtime::Handle;
+use tokio::task::JoinHandle;
+
+/// How long to wait for a key before checking on a running operation
+const TICK: Duration = Duration::from_millis(50);
+
+/// How often to check whether another command replaced the index
+const RELOAD_CHECK: Duration = Duration::from_secs(1);
+
+/// Browse the index and installed packages in a full-screen terminal UI
+pub async fn browse(dry_run: bool) -> Result<()> {
+    if crate::ui::json::format().is_machine() || !io::stdout().is_terminal() {
+        bail!("apl browse needs an interactive terminal");
+    }
+    let loaded = Loaded::load()?;
+    let runtime = Handle::current();
+    // Terminal reads block, so the screen gets a thread of its own while
+    // operations run on the runtime.
+    tokio::task::spawn_blocking(move || run(loaded, &runtime, dry_run))
+        .await
+        .context("Browser thread panicked")?
+}
+
+/// Restores the terminal when the browser exits, however it exits.
+struct Screen;
+
+impl Screen {
+    fn enter() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(Self)
+    }
+}
+
+impl Drop for Screen {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// The index the browser shows, with when its files were last replaced.
+struct Loaded {
+    index: Arc<PackageIndex>,
+    stamp: Vec<Option<SystemTime>>,
+}
+
+impl Loaded {
+    fn load() -> Result<Self> {
+        // Taken first, so a change made while loading is seen later.
+        let stamp = index_stamp();
+        Ok(Self {
+            index: Arc::new(super::provides::load_index()?),
+            stamp,
+        })
+    }
+}
+
+/// Modification times of every source's index file. `apl update` replaces
+/// the files rather than rewriting them, so a change shows up here.
+fn index_stamp() -> Vec<Option<SystemTime>> {
+    Source::list()
+        .unwrap_or_default()
+        .iter()
+        .map(|source| {
+            std::fs::metadata(source.index_path())
+                .and_then(|m| m.modified())
+                .ok()
+        })
+        .collect()
+}
+
+/// What the browser keeps while the index under it is reloaded.
+struct Session<'a> {
+    db: StateDb,
+    events: mpsc::Receiver<UiEvent>,
+    output: Output,
+    runtime: &'a Handle,
+    dry_run: bool,
+}
+
+fn run(mut loaded: Loaded, runtime: &Handle, dry_run: bool) -> Result<()> {
+    let (sender, events) = mpsc::channel();
+    let session = Session {
+        db: StateDb::open().context("Failed to open state database")?,
+        events,
+        output: Output::with_sender(sender),
+        runtime,
+        dry_run,
+    };
+    let _screen = Screen::enter().context("Failed to set up the terminal")?;
+    let mut view = None;
+    // The index is reloaded after every operation and whenever another
+    // command replaces it; the browser is rebuilt on it, keeping its view.
+    while let Some((reloaded, next)) = session.show(&loaded, view.take())? {
+        loaded = reloaded;
+        view = Some(next);
+    }
+    Ok(())
+}
+
+impl Session<'_> {
+    /// Runs the browser on `loaded` until the user quits (`None`) or the
+    /// index is reloaded.
+    fn show(&self, loaded: &Loaded, view: Option<View>) -> Result<Option<(Loaded, View)>> {
+        let (db, output, runtime, dry_run) = (&self.db, &self.output, self.runtime, self.dry_run);
+        let index = &loaded.index;
+        let mut browser = match view {
+            None => Browser::new(index, local_state(db, index)?),
+            Some(view) => match local_state(db, index) {
+                Ok(local) => Browser::with_view(index, local, view),
+                Err(e) => {
+                    let mut browser = Browser::with_view(index, LocalState::default(), view);
+                    browser.report_error(&format!("{e:#}"));
+                    browser
+                }
+            },
+        };
+
+        let mut stdout = io::stdout();
+        let mut running: Option<JoinHandle<Result<()>>> = None;
+        let mut dirty = true;
+        let mut reload = false;
+        let mut seen = loaded.stamp.clone();
+        let mut checked = Instant::now();
+
+        loop {
+            while let Ok(event) = self.events.try_recv() {
+                browser.on_event(event);
+                dirty = true;
+            }
+            if let Some(task) = running.take_if(|task| task.is_finished()) {
+                let result = runtime
+                    .block_on(task)
+                    .unwrap_or_else(|e| Err(anyhow!("Operation panicked: {e}")));
+                browser.set_busy(false);
+                finish(&mut browser, db, index, result);
+                reload = true;
+                dirty = true;
+            }
+            if running.is_none() && checked.elapsed() >= RELOAD_CHECK {
+                checked = Instant::now();
+                let stamp = index_stamp();
+                if stamp != seen {
+                    seen = stamp;
+                    reload = true;
+                }
+            }
+            if std::mem::take(&mut reload) {
+                match Loaded::load() {
+                    Ok(next) => return Ok(Some((next, browser.into_view()))),
+                    Err(e) => {
+                        browser.report_error(&format!("{e:#}"));
+                        dirty = true;
+                    }
+                }
+            }
+
+            if dirty {
+                let (width, height) = terminal::size()?;
+                browser.render(&mut stdout, width, height)?;
+                dirty = false;
+            }
+            if !event::poll(TICK)? {
+                continue;
+            }
+            let key = match event::read()? {
+                Event::Key(key) if key.kind == KeyEventKind::Press => key,
+                Event::Resize(..) => {
+                    dirty = true;
+                    continue;
+                }
+                _ => continue,
+            };
+            dirty = true;
+            let Some(action) = browser.handle_key(key) else {
+                continue;
+            };
+
+            match action {
+                Action::Quit if running.is_some() => {
+                    browser.report_error("An operation is still running; quit once it finishes");
+                }
+                Action::Quit => return Ok(None),
+                Action::Install(spec) => {
+                    let reporter = Arc::new(output.clone());
+                    let index = Arc::clone(index);
+                    browser.set_busy(true);
+                    running = Some(runtime.spawn(async move {
+                        let _lock = operation_lock(&reporter, dry_run).await?;
+                        let ctx = ops::Context {
+                            db: DbHandle::spawn().context("Failed to open database")?,
+                            index: Some(index),
+                            client: super::install::http_client()?,
+                            reporter,
+                        };
+                        ops::install::install_packages(&ctx, &[spec], dry_run)
+                            .await
+                            .map_err(|e| anyhow!(e))
+                    }));
+                }
+                Action::Remove(name) => {
+                    let reporter = output.clone();
+                    browser.set_busy(true);
+                    running = Some(runtime.spawn(async move {
+                        let _lock = operation_lock(&reporter, dry_run).await?;
+                        ops::remove::remove_packages(&reporter, &[name], false, dry_run)
+                            .await
+                            .map_err(|e| anyhow!(e))
+                    }));
+                }
+                Action::Switch { name, version } => {
+                    let result = locked(runtime, output, dry_run, || {
+                        ops::switch::switch_version(
+                            &PackageName::new(&name),
+                            &Version::from(version),
+                            dry_run,
+                            output,
+                        )
+                    });
+                    finish(&mut browser, db, index, result);
+                    reload = true;
+                }
+                Action::Pin(name) => {
+                    let result = locked(runtime, output, dry_run, || {
+                        ops::pin::pin(db, &name, None, dry_run, output)
+                    });
+                    finish(&mut browser, db, index, result);
+                    reload = true;
+                }
+                Action::Unpin(name) => {
+                    let result = locked(runtime, output, dry_run, || {
+                        ops::pin::unpin(db, &[name], dry_run, output)
+                    });
+                    finish(&mut browser, db, index, result);
+                    reload = true;
+                }
+            }
+        }
+    }
+}
+
+/// Reloads what is installed once an operation ends, reporting its error.
+fn finish(browser: &mut Browser<'_>, db: &StateDb, index: &PackageIndex, result: Result<()>) {
+    if let Err(e) = result.and_then(|()| {
+        browser.set_local(local_state(db, index)?);
+        Ok(())
+    }) {
+        browser.report_error(&format!("{e:#}"));
+    }
+}
+
+/// Takes the process lock for one operation, so other apl commands only
+/// wait while the browser is actually changing something.
+async fn operation_lock(output: &Output, dry_run: bool) -> Result<ProcessLock> {
+    let mode = if dry_run {
+        LockMode::Shared
+    } else {
+        LockMode::Exclusive
+    };
+    let output = output.clone();
+    tokio::task::spawn_blocking(move || {
+        ProcessLock::acquire(&crate::lock_path(), mode, true, |pid| {
+            output.info(&format!("Waiting for another apl process (pid {pid})..."));
+        })
+    })
+    .await
+    .context("Lock thread panicked")?
+    .map_err(Into::into)
+}
+
+/// Runs a synchronous operation under the process lock.
+fn locked(
+    runtime: &Handle,
+    output: &Output,
+    dry_run: bool,
+    operation: impl FnOnce() -> Result<(), ops::InstallError>,
+) -> Result<()> {
+    let _lock = runtime.block_on(operation_lock(output, dry_run))?;
+    operation().map_err(|e| anyhow!(e))
+}
+
+/// Installed versions, outdated packages and pins from the state database.
+fn local_state(db: &StateDb, index: &PackageIndex) -> Result<LocalState> {
+    let mut local = LocalState::default();
+    for pkg in db.list_packages()? {
+        let versions = db.list_package_versions(&pkg.name)?;
+        local.versions.insert(pkg.name, versions);
+    }
+    local.outdated = super::outdated::collect(db, index)?
+        .into_iter()
+        .map(|p| p.name)
+        .collect();
+    local.pinned = db.list_pins()?.into_keys().collect();
+    Ok(local)
+}
@@ -0,0 +1,157 @@
+//! Config command - show and change settings
+
+use crate::config::{self, Origin, Settings, Sources};
+use crate::ui::Output;
+use anyhow::{Context, Result, bail};
+use crossterm::style::Stylize;
+use serde::Serialize;
+use toml::Value;
+
+/// `apl config get --format json` document
+#[derive(Serialize)]
+struct GetDocument<'a> {
+    key: &'a str,
+    value: &'a Value,
+    /// `None` for tables, whose values may come from several layers
+    origin: Option<String>,
+}
+
+/// `apl config list --format json` document
+#[derive(Serialize)]
+struct ListDocument<'a> {
+    settings: Vec<Setting<'a>>,
+}
+
+#[derive(Serialize)]
+struct Setting<'a> {
+    key: &'a str,
+    value: &'a Value,
+    origin: String,
+}
+
+fn load(overrides: &[String]) -> Result<Settings> {
+    Settings::load(&Sources::discover(overrides.to_vec())).context("Failed to load settings")
+}
+
+/// Print the value of `key`
+pub fn get(key: &str, show_origin: bool, overrides: &[String]) -> Result<()> {
+    let settings = load(overrides)?;
+    let Some(value) = settings.get(key) else {
+        if config::is_known_key(key) {
+            bail!("{key} is not set");
+        }
+        bail!("Unknown config key '{key}'");
+    };
+
+    if crate::ui::json::format().is_machine() {
+        let document = GetDocument {
+            key,
+            value,
+            origin: settings.origin(key).map(ToString::to_string),
+        };
+        crate::ui::json::print_document("setting", &document)?;
+        return Ok(());
+    }
+
+    match (value, settings.origin(key)) {
+        (Value::Table(_), _) | (_, None) => {
+            let prefix = format!("{key}.");
+            print_entries(
+                settings
+                    .entries()
+                    .filter(|(k, _, _)| k.starts_with(&prefix)),
+                show_origin,
+            );
+        }
+        (value, Some(origin)) => {
+            let text = match value {
+                Value::String(s) => s.clone(),
+                value => value.to_string(),
+            };
+            if show_origin {
+                println!("{text}\t{}", origin.to_string().dark_grey());
+            } else {
+                println!("{text}");
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Set `key` to `value` in the user config file
+pub fn set(key: &str, value: &str, overrides: &[String], dry_run: bool) -> Result<()> {
+    let output = Output::new();
+    let path = crate::config_path();
+    if !config::is_known_key(key) {
+        bail!("Unknown config key '{key}'");
+    }
+    if dry_run {
+        output.info(&format!(
+            "(dry run) Would set {key} = {} in {}",
+            config::parse_value(value),
+            path.display()
+        ));
+        return Ok(());
+    }
+
+    config::set_value(&path, key, value)?;
+    output.success(&format!(
+        "Set {key} = {} in {}",
+        config::parse_value(value),
+        path.display()
+    ));
+
+    // A project, environment variable or --config flag may still win.
+    if let Ok(settings) = load(overrides) {
+        if let Some(origin) = settings
+            .origin(key)
+            .filter(|o| !matches!(o, Origin::User(_)))
+        {
+            output.warning(&format!("{key} is overridden by {origin}"));
+        }
+    }
+    Ok(())
+}
+
+/// Print every setting, optionally with where it comes from
+pub fn list(show_origin: bool, overrides: &[String]) -> Result<()> {
+    let settings = load(overrides)?;
+
+    if crate::ui::json::format().is_machine() {
+        let document = ListDocument {
+            settings: settings
+                .entries()
+                .map(|(key, value, origin)| Setting {
+                    key,
+                    value,
+                    origin: origin.to_string(),
+                })
+                .collect(),
+        };
+        crate::ui::json::print_document("config", &document)?;
+        return Ok(());
+    }
+
+    print_entries(settings.entries(), show_origin);
+    Ok(())
+}
+
+fn print_entries<'a>(
+    entries: impl Iterator<Item = (&'a str, &'a Value, &'a Origin)>,
+    show_origin: bool,
+) {
+    let entries: Vec<_> = entries
+        .map(|(key, value, origin)| (key, value, origin.to_string()))
+        .collect();
+    let width = entries.iter().map(|(_, _, o)| o.len()).max().unwrap_or(0);
+    for (key, value, origin) in entries {
+        if show_origin {
+            println!(
+                "{}  {key} = {value}",
+                format!("{origin:<width$}").dark_grey()
+            );
+        } else {
+            println!("{key} = {value}");
+        }
+    }
+}
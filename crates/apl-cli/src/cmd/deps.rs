@@ -0,0 +1,324 @@
+//! Deps and rdeps commands - dependency trees and graph export
+
+use crate::db::StateDb;
+use anyhow::{Context, Result, bail};
+use apl_core::resolver::{DepGraph, DepKind};
+use apl_schema::index::{IndexEntry, PackageIndex};
+use apl_schema::types::PackageName;
+use crossterm::style::Stylize;
+use serde::Serialize;
+use std::collections::{BTreeSet, HashSet};
+use std::fmt::Write as _;
+
+/// How to print a dependency graph
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Render {
+    /// Indented tree (default)
+    #[default]
+    Tree,
+    /// Graphviz DOT
+    Dot,
+    /// JSON document, as with `--format json`
+    Json,
+}
+
+impl Render {
+    /// Picks the renderer from the `--dot` / `--json` flags.
+    pub fn from_flags(dot: bool, json: bool) -> Self {
+        if json || crate::ui::json::format().is_machine() {
+            Self::Json
+        } else if dot {
+            Self::Dot
+        } else {
+            Self::Tree
+        }
+    }
+}
+
+/// `apl deps|rdeps --format json` document
+#[derive(Serialize)]
+struct GraphDocument<'a> {
+    package: &'a str,
+    build: bool,
+    nodes: Vec<Node<'a>>,
+    /// Edges point from a package to a package it depends on
+    edges: Vec<Edge<'a>>,
+    /// Dependency order; each layer depends only on earlier ones
+    layers: &'a [Vec<PackageName>],
+    /// Packages on a dependency cycle
+    cycles: &'a BTreeSet<PackageName>,
+}
+
+#[derive(Serialize)]
+struct Node<'a> {
+    name: &'a str,
+    /// Latest release in the index; `None` if the index lacks the package
+    version: Option<&'a str>,
+    installed: bool,
+}
+
+#[derive(Serialize)]
+struct Edge<'a> {
+    package: &'a str,
+    depends_on: &'a str,
+    kind: DepKind,
+}
+
+/// What a graph's edges mean
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Direction {
+    /// `a -> b`: a depends on b
+    Dependencies,
+    /// `a -> b`: b depends on a
+    Dependents,
+}
+
+/// A graph rooted at one package, with what is needed to print it
+struct View<'a> {
+    root: PackageName,
+    graph: DepGraph,
+    direction: Direction,
+    build: bool,
+    index: &'a PackageIndex,
+    installed: HashSet<String>,
+}
+
+/// Show the dependency tree of `package`, resolved from the index
+pub fn deps(package: &str, build: bool, render: Render) -> Result<()> {
+    let index = super::provides::load_index()?;
+    let root = PackageName::new(package);
+    if index.find(&root).is_none() {
+        bail!("Package '{package}' not found in index");
+    }
+    let view = View {
+        graph: DepGraph::reachable_from(&index, std::slice::from_ref(&root), build),
+        root,
+        direction: Direction::Dependencies,
+        build,
+        index: &index,
+        installed: installed_names(&StateDb::open().context("Failed to open state database")?)?,
+    };
+    view.print("deps", render)
+}
+
+/// Show the installed and indexed packages that depend on `package`
+pub fn rdeps(package: &str, build: bool, installed_only: bool, render: Render) -> Result<()> {
+    let index = super::provides::load_index()?;
+    let db = StateDb::open().context("Failed to open state database")?;
+    let root = PackageName::new(package);
+
+    // Dependencies recorded at install time, then those the index declares
+    let mut forward = if installed_only {
+        DepGraph::default()
+    } else {
+        DepGraph::from_index(&index, build)
+    };
+    for pkg in db.list_packages()? {
+        let name = PackageName::new(&pkg.name);
+        forward.add_node(name.clone());
+        for dep in db.get_dependencies(&pkg.name)? {
+            forward.add_edge(name.clone(), PackageName::new(&dep), DepKind::Runtime);
+        }
+    }
+    if !forward.contains(&root) {
+        bail!("Package '{package}' not found");
+    }
+
+    let view = View {
+        graph: forward.reverse().subgraph(std::slice::from_ref(&root)),
+        root,
+        direction: Direction::Dependents,
+        build,
+        index: &index,
+        installed: installed_names(&db)?,
+    };
+    view.print("rdeps", render)
+}
+
+fn installed_names(db: &StateDb) -> Result<HashSet<String>> {
+    Ok(db.list_packages()?.into_iter().map(|p| p.name).collect())
+}
+
+impl View<'_> {
+    fn print(&self, kind: &str, render: Render) -> Result<()> {
+        let cycles = self.graph.cyclic();
+        match render {
+            Render::Json => {
+                let layering = self.graph.layers();
+                let document = GraphDocument {
+                    package: self.root.as_str(),
+                    build: self.build,
+                    nodes: self
+                        .graph
+                        .nodes()
+                        .map(|name| Node {
+                            name: name.as_str(),
+                            version: self.version(name),
+                            installed: self.installed.contains(name.as_str()),
+                        })
+                        .collect(),
+                    edges: self.edges(),
+                    layers: &layering.layers,
+                    cycles: &cycles,
+                };
+                crate::ui::json::print_document(kind, &document)?;
+            }
+            Render::Dot => print!("{}", self.dot(&cycles)),
+            Render::Tree => self.print_tree(&cycles),
+        }
+        Ok(())
+    }
+
+    fn version(&self, name: &PackageName) -> Option<&str> {
+        self.index
+            .find(name)
+            .and_then(IndexEntry::latest)
+            .map(|r| r.version.as_str())
+    }
+
+    /// Edges oriented from dependent to dependency, whatever the view.
+    fn edges(&self) -> Vec<Edge<'_>> {
+        let mut edges: Vec<Edge<'_>> = self
+            .graph
+            .nodes()
+            .flat_map(|from| {
+                self.graph.deps(from).map(move |(to, kind)| {
+                    let (package, depends_on) = match self.direction {
+                        Direction::Dependencies => (from, to),
+                        Direction::Dependents => (to, from),
+                    };
+                    Edge {
+                        package: package.as_str(),
+                        depends_on: depends_on.as_str(),
+                        kind,
+                    }
+                })
+            })
+            .collect();
+        edges.sort_by(|a, b| (a.package, a.depends_on).cmp(&(b.package, b.depends_on)));
+        edges
+    }
+
+    /// Graphviz DOT source; cycles are drawn in red and build edges dashed.
+    fn dot(&self, cycles: &BTreeSet<PackageName>) -> String {
+        let mut out = String::from("digraph deps {\n    rankdir=LR;\n");
+        for name in self.graph.nodes() {
+            let label = match self.version(name) {
+                Some(version) => format!("{name}\\n{version}"),
+                None => name.to_string(),
+            };
+            let mut attrs = vec![format!("label=\"{label}\"")];
+            if *name == self.root {
+                attrs.push("style=bold".to_string());
+            }
+            if cycles.contains(name) {
+                attrs.push("color=red".to_string());
+            }
+            let _ = writeln!(out, "    \"{name}\" [{}];", attrs.join(", "));
+        }
+        for edge in self.edges() {
+            let mut attrs = Vec::new();
+            if edge.kind == DepKind::Build {
+                attrs.push("style=dashed");
+            }
+            if cycles.contains(edge.package) && cycles.contains(edge.depends_on) {
+                attrs.push("color=red");
+            }
+            let attrs = if attrs.is_empty() {
+                String::new()
+            } else {
+                format!(" [{}]", attrs.join(", "))
+            };
+            let _ = writeln!(
+                out,
+                "    \"{}\" -> \"{}\"{attrs};",
+                edge.package, edge.depends_on
+            );
+        }
+        out.push_str("}\n");
+        out
+    }
+
+    fn print_tree(&self, cycles: &BTreeSet<PackageName>) {
+        println!();
+        println!("  {}", self.label(&self.root, cycles));
+        let mut expanded = HashSet::from([self.root.clone()]);
+        let mut path = vec![self.root.clone()];
+        self.print_children(&self.root, "  ", &mut path, &mut expanded, cycles);
+        println!();
+
+        let count = self.graph.nodes().count() - 1;
+        let noun = match (self.direction, count == 1) {
+            (Direction::Dependencies, true) => "dependency",
+            (Direction::Dependencies, false) => "dependencies",
+            (Direction::Dependents, true) => "dependent",
+            (Direction::Dependents, false) => "dependents",
+        };
+        println!("  {count} {noun}");
+
+        if self.direction == Direction::Dependencies && count > 0 {
+            let layering = self.graph.layers();
+            println!("  {}", "install order".dark_grey());
+            for (i, layer) in layering.layers.iter().enumerate() {
+                let names: Vec<&str> = layer.iter().map(PackageName::as_str).collect();
+                println!("  {:>3}  {}", i + 1, names.join(", "));
+            }
+        }
+        if !cycles.is_empty() {
+            let names: Vec<&str> = cycles.iter().map(PackageName::as_str).collect();
+            crate::ui::Output::new()
+                .warning(&format!("Dependency cycle between {}", names.join(", ")));
+        }
+    }
+
+    fn print_children(
+        &self,
+        name: &PackageName,
+        prefix: &str,
+        path: &mut Vec<PackageName>,
+        expanded: &mut HashSet<PackageName>,
+        cycles: &BTreeSet<PackageName>,
+    ) {
+        let children: Vec<_> = self.graph.deps(name).collect();
+        for (i, (child, kind)) in children.iter().enumerate() {
+            let last = i + 1 == children.len();
+            let branch = if last { "└── " } else { "├── " };
+            let mut line = format!("{prefix}{branch}{}", self.label(child, cycles));
+            if *kind == DepKind::Build {
+                let _ = write!(line, " {}", "[build]".dark_grey());
+            }
+            if path.contains(child) {
+                println!("{line} {}", "(cycle)".red());
+                continue;
+            }
+            if !expanded.insert((*child).clone()) {
+                println!("{line} {}", "(*)".dark_grey());
+                continue;
+            }
+            println!("{line}");
+            let child_prefix = format!("{prefix}{}", if last { "    " } else { "│   " });
+            path.push((*child).clone());
+            self.print_children(child, &child_prefix, path, expanded, cycles);
+            path.pop();
+        }
+    }
+
+    fn label(&self, name: &PackageName, cycles: &BTreeSet<PackageName>) -> String {
+        let theme = crate::ui::Theme::default();
+        let styled = if cycles.contains(name) {
+            name.as_str().red().to_string()
+        } else {
+            name.as_str().with(theme.colors.package_name).to_string()
+        };
+        let version = match self.version(name) {
+            Some(version) => version.dark_grey().to_string(),
+            None => "not in index".red().to_string(),
+        };
+        let installed = if self.installed.contains(name.as_str()) {
+            format!(" {}", "(installed)".green())
+        } else {
+            String::new()
+        };
+        format!("{styled} {version}{installed}")
+    }
+}
@@ -0,0 +1,224 @@
+//! Generations command
+use crate::db::StateDb;
+use crate::store::journal::{Journal, OpKind};
+use crate::store::profile::{Change, Generation, Profiles};
+use crate::ui::Output;
+use crate::{journal_path, store_path};
+use anyhow::{Context, Result, bail};
+use chrono::DateTime;
+use crossterm::style::Stylize;
+
+/// List every profile generation, marking the current one
+pub fn list() -> Result<()> {
+    let db = StateDb::open().context("Failed to open state database")?;
+    let profiles = crate::ops::profiles_blocking(&db)?;
+    let current = profiles.current()?;
+    let generations = profiles.list()?;
+
+    println!();
+    println!("{}", "Profile generations".bold());
+    for generation in &generations {
+        let time_str = DateTime::from_timestamp(generation.created_at, 0)
+            .unwrap_or_default()
+            .with_timezone(&chrono::Local)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string();
+        let row = format!(
+            "{:>4}  [{time_str}]  {} ({} packages)",
+            generation.id,
+            generation.description,
+            generation.packages.len()
+        );
+        if Some(generation.id) == current {
+            println!("{} {}", "*".green().bold(), row.bold());
+        } else {
+            println!("  {row}");
+        }
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Show package changes between two generations (`to` defaults to the current one)
+pub fn diff(from: u64, to: Option<u64>) -> Result<()> {
+    let db = StateDb::open().context("Failed to open state database")?;
+    let profiles = crate::ops::profiles_blocking(&db)?;
+    let to = match to {
+        Some(id) => id,
+        None => current(&profiles)?,
+    };
+
+    let old = profiles.load(from)?;
+    let new = profiles.load(to)?;
+    print_changes(&old, &new);
+    Ok(())
+}
+
+/// Make an earlier or later generation current, bringing the database along
+pub fn switch(id: u64, dry_run: bool) -> Result<()> {
+    let db = StateDb::open().context("Failed to open state database")?;
+    let profiles = crate::ops::profiles_blocking(&db)?;
+    let output = Output::new();
+
+    let current_id = current(&profiles)?;
+    if id == current_id {
+        output.info(&format!("Generation {id} is already current."));
+        return Ok(());
+    }
+
+    let old = profiles.load(current_id)?;
+    let new = profiles.load(id)?;
+    let changes = old.diff(&new);
+
+    // Packages are never deleted from the store by `apl remove`, but the
+    // user may have cleaned it up by hand.
+    for change in &changes {
+        if let Change::Added(p) | Change::Changed { to: p, .. } = change {
+            let sides = p.side_by_side.iter().map(|s| &s.version);
+            for version in std::iter::once(&p.version).chain(sides) {
+                if !store_path().join(&p.name).join(version).exists() {
+                    bail!(
+                        "Generation {id} needs {} {version}, which is no longer in the store",
+                        p.name
+                    );
+                }
+            }
+        }
+    }
+
+    print_changes(&old, &new);
+    if dry_run {
+        output.info(&format!("(dry run) Would switch to generation {id}"));
+        return Ok(());
+    }
+
+    let installs: Vec<_> = changes
+        .iter()
+        .filter_map(|c| match c {
+            Change::Added(p) | Change::Changed { to: p, .. } => Some(p.record(profiles.bin_dir())),
+            Change::Removed(_) => None,
+        })
+        .collect();
+    let removals: Vec<String> = changes
+        .iter()
+        .filter_map(|c| match c {
+            Change::Removed(p) => Some(p.name.clone()),
+            _ => None,
+        })
+        .collect();
+
+    let journal = Journal::begin(&journal_path(), OpKind::Switch)
+        .context("Failed to start operation journal")?;
+    let switched = profiles
+        .activate(id, Some(&journal))
+        .context("Failed to switch generation")
+        .and_then(|()| {
+            db.commit_operation(
+                journal.id(),
+                OpKind::Switch,
+                &installs,
+                &removals,
+                Some((current_id, id)),
+            )
+            .context("Failed to update database records")
+        });
+    if let Err(e) = switched {
+        journal
+            .abort()
+            .context("Failed to roll back generation switch")?;
+        return Err(e);
+    }
+    if let Err(e) = journal.finish() {
+        output.warning(&format!("Failed to remove operation journal: {e}"));
+    }
+
+    output.success(&format!("Switched to generation {id}"));
+    Ok(())
+}
+
+/// Delete all but the current generation and the `keep` most recent ones
+pub fn prune(keep: usize, dry_run: bool) -> Result<()> {
+    let db = StateDb::open().context("Failed to open state database")?;
+    let profiles = crate::ops::profiles_blocking(&db)?;
+    let output = Output::new();
+
+    let current = profiles.current()?;
+    let ids = profiles.ids()?;
+    let doomed: Vec<u64> = ids
+        .iter()
+        .rev()
+        .skip(keep)
+        .copied()
+        .filter(|id| Some(*id) != current)
+        .collect();
+
+    if doomed.is_empty() {
+        output.info("No generations to prune.");
+        return Ok(());
+    }
+
+    for id in &doomed {
+        if dry_run {
+            output.info(&format!("(dry run) Would delete generation {id}"));
+        } else {
+            profiles
+                .delete(*id)
+                .with_context(|| format!("Failed to delete generation {id}"))?;
+        }
+    }
+
+    if !dry_run {
+        output.success(&format!("Deleted {} generation(s)", doomed.len()));
+    }
+    Ok(())
+}
+
+fn current(profiles: &Profiles) -> Result<u64> {
+    profiles
+        .current()?
+        .context("No current generation; run an install first")
+}
+
+fn print_changes(from: &Generation, to: &Generation) {
+    let output = Output::new();
+    let changes = from.diff(to);
+    if changes.is_empty() {
+        output.info(&format!(
+            "No package changes between generations {} and {}",
+            from.id, to.id
+        ));
+        return;
+    }
+
+    println!();
+    println!("{}", format!("Generation {} -> {}", from.id, to.id).bold());
+    for change in changes {
+        match change {
+            Change::Added(p) => println!("  {} {} {}", "+".green(), p.name, p.version),
+            Change::Removed(p) => println!("  {} {} {}", "-".red(), p.name, p.version),
+            Change::Changed { from, to } if from.version == to.version => {
+                let sides: Vec<&str> = to.side_by_side.iter().map(|s| s.version.as_str()).collect();
+                let sides = if sides.is_empty() {
+                    "none".to_string()
+                } else {
+                    sides.join(", ")
+                };
+                println!(
+                    "  {} {} {} (side-by-side: {sides})",
+                    "~".yellow(),
+                    to.name,
+                    to.version
+                );
+            }
+            Change::Changed { from, to } => println!(
+                "  {} {} {} -> {}",
+                "~".yellow(),
+                to.name,
+                from.version,
+                to.version
+            ),
+        }
+    }
+    println!();
+}
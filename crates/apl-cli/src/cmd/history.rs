@@ -1,12 +1,47 @@
 //! History command
 
+use std::collections::HashMap;
+
 use crate::db::StateDb;
+use crate::store::history::{HistoryEvent, Operation};
 use anyhow::{Context, Result};
 use chrono::DateTime;
+use crossterm::style::Stylize;
+use serde::Serialize;
+
+/// `apl history --format json` document
+#[derive(Serialize)]
+struct HistoryDocument<'a> {
+    package: Option<&'a str>,
+    events: Vec<HistoryEntry<'a>>,
+}
+
+#[derive(Serialize)]
+struct HistoryEntry<'a> {
+    id: Option<i64>,
+    /// Milliseconds since the Unix epoch
+    timestamp: i64,
+    package: &'a str,
+    action: &'a str,
+    version_from: Option<&'a str>,
+    version_to: Option<&'a str>,
+    success: bool,
+    operation: Option<&'a Operation>,
+}
 
-pub fn history(pkg_name: &str) -> Result<()> {
+/// Show the history of one package, or of every operation when `pkg_name` is `None`
+pub fn history(pkg_name: Option<&str>) -> Result<()> {
     let db = StateDb::open().context("Failed to open state database")?;
+    if crate::ui::json::format().is_machine() {
+        return history_document(&db, pkg_name);
+    }
+    match pkg_name {
+        Some(pkg_name) => package_history(&db, pkg_name),
+        None => timeline(&db),
+    }
+}
 
+fn package_history(db: &StateDb, pkg_name: &str) -> Result<()> {
     let history = db.get_history(pkg_name)?;
 
     let output = crate::ui::Output::new();
@@ -19,42 +54,129 @@ pub fn history(pkg_name: &str) -> Result<()> {
     output.section(&format!("History for '{pkg_name}'"));
 
     for event in history {
-        // Convert timestamp (millis) to DateTime
-        let dt = DateTime::from_timestamp_millis(event.timestamp)
-            .unwrap_or_default()
-            .with_timezone(&chrono::Local);
-
-        let time_str = dt.format("%Y-%m-%d %H:%M:%S").to_string();
-
-        // Format action description
-        let desc = match event.action.as_str() {
-            "install" => {
-                let v = event.version_to.as_deref().unwrap_or("?");
-                if let Some(from) = event.version_from.as_ref() {
-                    format!("Updated from {from} to {v}")
-                } else {
-                    format!("Installed {v}")
-                }
-            }
-            "switch" => {
-                let from = event.version_from.as_deref().unwrap_or("?");
-                let to = event.version_to.as_deref().unwrap_or("?");
-                format!("Switched from {from} to {to}")
-            }
-            "remove" => {
-                let from = event.version_from.as_deref().unwrap_or("?");
-                format!("Removed {from}")
-            }
-            _ => format!(
-                "{} {}",
-                event.action,
-                event.version_to.as_deref().unwrap_or("")
-            ),
-        };
-
-        println!("[{time_str}] {desc}");
+        let time_str = format_timestamp(event.timestamp);
+        println!("[{time_str}] {} {}", event_id(&event), describe(&event));
+    }
+    println!();
+
+    Ok(())
+}
+
+/// Every event across all packages, grouped by the operation that wrote it
+fn timeline(db: &StateDb) -> Result<()> {
+    let events = db.get_timeline()?;
+    if events.is_empty() {
+        crate::ui::Output::new().info("No history yet");
+        return Ok(());
+    }
+
+    let operations: HashMap<String, Operation> = db
+        .list_operations()?
+        .into_iter()
+        .map(|op| (op.id.clone(), op))
+        .collect();
+
+    println!();
+    println!("{}", "History".bold());
+
+    // Events of one operation share a transaction, so they are adjacent.
+    // Events from before operations were tracked stand on their own.
+    for group in events.chunk_by(|a, b| a.operation.is_some() && a.operation == b.operation) {
+        let first = &group[0];
+        let operation = first.operation.as_ref().and_then(|id| operations.get(id));
+        let kind = operation.map_or(first.action.as_str(), |op| op.kind.as_str());
+
+        let mut header = format!("[{}] {}", format_timestamp(first.timestamp), kind.bold());
+        if let Some(generation) = operation.and_then(|op| op.generation_to) {
+            header.push_str(&format!(" (generation {generation})").dim().to_string());
+        }
+        println!();
+        println!("{header}");
+
+        for event in group {
+            println!(
+                "  {} {}: {}",
+                event_id(event),
+                event.package,
+                describe(event)
+            );
+        }
     }
     println!();
 
     Ok(())
 }
+
+fn history_document(db: &StateDb, pkg_name: Option<&str>) -> Result<()> {
+    let events = match pkg_name {
+        Some(pkg_name) => db.get_history(pkg_name)?,
+        None => db.get_timeline()?,
+    };
+    let operations: HashMap<String, Operation> = db
+        .list_operations()?
+        .into_iter()
+        .map(|op| (op.id.clone(), op))
+        .collect();
+
+    let document = HistoryDocument {
+        package: pkg_name,
+        events: events
+            .iter()
+            .map(|event| HistoryEntry {
+                id: event.id,
+                timestamp: event.timestamp,
+                package: &event.package,
+                action: &event.action,
+                version_from: event.version_from.as_deref(),
+                version_to: event.version_to.as_deref(),
+                success: event.success,
+                operation: event.operation.as_ref().and_then(|id| operations.get(id)),
+            })
+            .collect(),
+    };
+    crate::ui::json::print_document("history", &document)?;
+    Ok(())
+}
+
+/// Formats a history timestamp (millis) in local time
+pub fn format_timestamp(millis: i64) -> String {
+    DateTime::from_timestamp_millis(millis)
+        .unwrap_or_default()
+        .with_timezone(&chrono::Local)
+        .format("%Y-%m-%d %H:%M:%S")
+        .to_string()
+}
+
+fn event_id(event: &HistoryEvent) -> String {
+    let id = event.id.map(|id| format!("#{id}")).unwrap_or_default();
+    format!("{id:>5}").dim().to_string()
+}
+
+fn describe(event: &HistoryEvent) -> String {
+    match event.action.as_str() {
+        "install" => {
+            let v = event.version_to.as_deref().unwrap_or("?");
+            if let Some(from) = event.version_from.as_ref() {
+                format!("Updated from {from} to {v}")
+            } else {
+                format!("Installed {v}")
+            }
+        }
+        "switch" => {
+            let to = event.version_to.as_deref().unwrap_or("?");
+            match event.version_from.as_deref() {
+                Some(from) => format!("Switched from {from} to {to}"),
+                None => format!("Restored {to}"),
+            }
+        }
+        "remove" => {
+            let from = event.version_from.as_deref().unwrap_or("?");
+            format!("Removed {from}")
+        }
+        _ => format!(
+            "{} {}",
+            event.action,
+            event.version_to.as_deref().unwrap_or("")
+        ),
+    }
+}
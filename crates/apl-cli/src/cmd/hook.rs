@@ -0,0 +1,76 @@
+//! Hook command - shell integration
+
+use crate::config::Config;
+use crate::db::StateDb;
+use crate::store::lock::LockMode;
+use anyhow::Result;
+
+/// Shells with a command-not-found hook
+#[derive(Debug, Clone, Copy, clap::ValueEnum)]
+pub enum Shell {
+    Zsh,
+    Bash,
+    Fish,
+}
+
+/// Print the command-not-found handler for `shell`
+///
+/// Meant for `eval "$(apl hook command-not-found zsh)"` in the shell's rc file.
+pub fn command_not_found(shell: Shell) -> Result<()> {
+    // The absolute path keeps the handler working if `apl` itself leaves PATH.
+    let apl = std::env::current_exe()?;
+    let apl = shell_quote(&apl.to_string_lossy());
+    let script = match shell {
+        Shell::Zsh => {
+            format!("command_not_found_handler() {{\n    {apl} hook handle -- \"$@\"\n}}\n")
+        }
+        Shell::Bash => {
+            format!("command_not_found_handle() {{\n    {apl} hook handle -- \"$@\"\n}}\n")
+        }
+        Shell::Fish => {
+            format!("function fish_command_not_found\n    {apl} hook handle -- $argv\nend\n")
+        }
+    };
+    print!("{script}");
+    Ok(())
+}
+
+/// Suggest the package providing an unknown `command`, or run it when the
+/// user opted in and exactly one package provides it
+///
+/// Exits with status 127, as the shell would, unless the command ran.
+pub async fn handle(command: &str, args: &[String]) -> Result<()> {
+    let providers = {
+        let _lock = crate::store::lock::lock(LockMode::Shared, true)?;
+        match (crate::cmd::provides::load_index(), StateDb::open()) {
+            (Ok(index), Ok(db)) => crate::cmd::provides::providers(&index, &db, command)?,
+            _ => Vec::new(),
+        }
+    };
+
+    if let [provider] = providers.as_slice() {
+        if Config::global().command_not_found.run {
+            eprintln!(
+                "apl: running '{command}' from {} via 'apl run'",
+                provider.name
+            );
+            return crate::cmd::run::run(&provider.name, Some(command), args, false).await;
+        }
+    }
+
+    if providers.is_empty() {
+        eprintln!("{command}: command not found");
+    } else {
+        eprintln!("{command}: command not found, but it is provided by:");
+        for provider in &providers {
+            let latest = provider.versions.first().map_or("", String::as_str);
+            eprintln!("  apl install {}  ({latest})", provider.name);
+        }
+    }
+    std::process::exit(127);
+}
+
+/// Single-quotes `s` for POSIX shells and fish.
+fn shell_quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
@@ -1,80 +1,379 @@
 //! Info command
 
-use crate::db::StateDb;
+use crate::db::{InstalledFile, StateDb};
 use crate::index::PackageIndex;
 use crate::ui::theme::format_size;
 use anyhow::{Context, Result, bail};
-use apl_core::paths::apl_home;
+use apl_core::io::extract::format_from_extension;
+use apl_schema::Arch;
+use apl_schema::index::{HashType, IndexEntry, VersionInfo};
 use apl_schema::types::PackageName;
 use crossterm::style::Stylize;
+use serde::Serialize;
+use std::collections::HashSet;
+use std::path::Path;
 
-/// Show info about a specific package
-pub fn info(package_str: &str) -> Result<()> {
-    let package = PackageName::new(package_str);
+/// `apl info --format json` document
+#[derive(Serialize)]
+struct InfoDocument<'a> {
+    name: &'a str,
+    /// Release described by the rest of the document
+    version: Option<&'a str>,
+    latest: Option<&'a str>,
+    description: Option<&'a str>,
+    homepage: Option<&'a str>,
+    license: Option<&'a str>,
+    tags: &'a [String],
+    #[serde(rename = "type")]
+    type_: Option<&'a str>,
+    deps: &'a [String],
+    build_deps: &'a [String],
+    hints: Option<&'a str>,
+    versions: Vec<AvailableVersion<'a>>,
+    artifacts: Vec<Artifact>,
+    dependency_tree: Vec<DepNode>,
+    installed: Option<InstalledInfo<'a>>,
+    installed_versions: Vec<InstalledInfo<'a>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    files: Option<Vec<&'a str>>,
+}
+
+#[derive(Serialize)]
+struct InstalledInfo<'a> {
+    version: &'a str,
+    reason: &'static str,
+    size_bytes: u64,
+    installed_at: i64,
+    active: bool,
+}
+
+/// A release in the index
+#[derive(Serialize)]
+struct AvailableVersion<'a> {
+    version: &'a str,
+    installed: bool,
+    active: bool,
+}
+
+/// A downloadable artifact of the selected release
+#[derive(Serialize)]
+struct Artifact {
+    /// `binary` or `source`
+    kind: &'static str,
+    /// `None` for source artifacts
+    arch: Option<Arch>,
+    url: String,
+    hash: String,
+    hash_type: HashType,
+    format: String,
+    /// Content-addressed copy on the index's mirror, if it has one
+    mirror_url: Option<String>,
+}
+
+/// A runtime dependency and, unless already shown, its own dependencies
+#[derive(Serialize)]
+struct DepNode {
+    name: String,
+    /// Latest release in the index; `None` if the index lacks the package
+    version: Option<String>,
+    deps: Vec<DepNode>,
+    /// Expanded elsewhere in the tree
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    repeated: bool,
+}
+
+/// Show info about a specific package (`pkg` or `pkg@version`)
+pub fn info(spec: &str, show_files: bool) -> Result<()> {
+    let (name, requested) = match spec.split_once('@') {
+        Some((name, version)) if !version.is_empty() => (name, Some(version)),
+        Some(_) => bail!("Invalid package '{spec}': missing version after @"),
+        None => (spec, None),
+    };
+    let package = PackageName::new(name);
     let db = StateDb::open().context("Failed to open state database")?;
 
     let installed = db.get_package(package.as_str())?;
+    let installed_versions = db.list_package_versions(package.as_str())?;
+
+    let index = crate::store::source::load_index().ok().flatten();
+    let index_entry = index.as_ref().and_then(|idx| idx.find(&package));
 
-    let index_path = apl_home().join("index");
-    let index_entry = if index_path.exists() {
-        PackageIndex::load(&index_path)
-            .ok()
-            .and_then(|idx| idx.find(&package).cloned())
+    if installed.is_none() && index_entry.is_none() {
+        bail!("Package '{package}' not found");
+    }
+
+    let release = match (index_entry, requested) {
+        (Some(entry), Some(version)) => Some(
+            entry
+                .find_version(version)
+                .with_context(|| format!("Version {version} of '{package}' not found"))?,
+        ),
+        (Some(entry), None) => entry.latest(),
+        (None, _) => None,
+    };
+    let mirror = index
+        .as_ref()
+        .and_then(|i| crate::store::source::mirror_for(i, &package));
+    let artifacts = release.map_or_else(Vec::new, |r| artifacts(r, mirror));
+    let tree = match (index.as_ref(), release) {
+        (Some(index), Some(release)) => {
+            let mut seen = HashSet::from([package.to_string()]);
+            release
+                .deps
+                .iter()
+                .map(|dep| dep_tree(index, dep, &mut seen))
+                .collect()
+        }
+        _ => Vec::new(),
+    };
+    let files = if show_files {
+        let mut files = db.get_package_files(package.as_str())?;
+        files.sort_by(|a, b| a.path.cmp(&b.path));
+        Some(files)
     } else {
         None
     };
 
-    if installed.is_none() && index_entry.is_none() {
-        bail!("Package '{package}' not found");
+    let versions: Vec<AvailableVersion<'_>> = index_entry
+        .map(|entry| entry.releases.as_slice())
+        .unwrap_or_default()
+        .iter()
+        .map(|r| {
+            let local = installed_versions.iter().find(|p| p.version == r.version);
+            AvailableVersion {
+                version: &r.version,
+                installed: local.is_some(),
+                active: local.is_some_and(|p| p.active),
+            }
+        })
+        .collect();
+
+    if crate::ui::json::format().is_machine() {
+        let document = InfoDocument {
+            name: package.as_str(),
+            version: release.map(|r| r.version.as_str()),
+            latest: index_entry
+                .and_then(IndexEntry::latest)
+                .map(|v| v.version.as_str()),
+            description: index_entry.and_then(|e| non_empty(&e.description)),
+            homepage: index_entry.and_then(|e| non_empty(&e.homepage)),
+            license: index_entry.and_then(|e| non_empty(&e.license)),
+            tags: index_entry.map_or(&[], |e| e.tags.as_slice()),
+            type_: index_entry.and_then(|e| non_empty(&e.type_)),
+            deps: release.map_or(&[], |r| r.deps.as_slice()),
+            build_deps: release.map_or(&[], |r| r.build_deps.as_slice()),
+            hints: release.and_then(|r| non_empty(&r.hints)),
+            versions,
+            artifacts,
+            dependency_tree: tree,
+            installed: installed.as_ref().map(installed_info),
+            installed_versions: installed_versions.iter().map(installed_info).collect(),
+            files: files
+                .as_ref()
+                .map(|files| files.iter().map(|f| f.path.as_str()).collect()),
+        };
+        crate::ui::json::print_document("info", &document)?;
+        return Ok(());
     }
 
     let lw = 12;
 
     println!();
-    if let Some(entry) = &index_entry {
-        let version = entry.latest().map_or("?", |v| v.version.as_str());
-        println!(
-            "  {} {}",
-            package.as_str().white().bold(),
-            version.dark_grey()
-        );
-
+    let version = release
+        .map(|r| r.version.as_str())
+        .or(installed.as_ref().map(|p| p.version.as_str()))
+        .unwrap_or("?");
+    println!(
+        "  {} {}",
+        package.as_str().white().bold(),
+        version.dark_grey()
+    );
+    if let Some(entry) = index_entry {
         if !entry.description.is_empty() {
             println!("  {}", entry.description);
         }
         println!();
 
-        if !entry.homepage.is_empty() {
-            println!("  {:<lw$}{}", "homepage", entry.homepage);
+        for (label, value) in [
+            ("homepage", entry.homepage.clone()),
+            ("license", entry.license.clone()),
+            ("type", entry.type_.clone()),
+            ("tags", entry.tags.join(", ")),
+        ] {
+            if !value.is_empty() {
+                println!("  {label:<lw$}{value}");
+            }
+        }
+        if !versions.is_empty() {
+            let list: Vec<String> = versions
+                .iter()
+                .map(|v| match (v.active, v.installed) {
+                    (true, _) => format!("{} {}", v.version, "*".green()),
+                    (false, true) => format!("{} {}", v.version, "(installed)".dark_grey()),
+                    (false, false) => v.version.to_string(),
+                })
+                .collect();
+            println!("  {:<lw$}{}", "versions", list.join(", "));
+        }
+    } else {
+        println!();
+    }
+
+    if let Some(release) = release {
+        if !release.build_deps.is_empty() {
+            println!("  {:<lw$}{}", "build deps", release.build_deps.join(", "));
         }
-        if let Some(latest) = entry.latest() {
-            if !latest.deps.is_empty() {
-                println!("  {:<lw$}{}", "requires", latest.deps.join(", "));
+    }
+
+    if !artifacts.is_empty() {
+        println!();
+        println!("  {}", "artifacts".dark_grey());
+        for artifact in &artifacts {
+            let target = artifact
+                .arch
+                .map_or_else(|| artifact.kind.to_string(), |arch| arch.to_string());
+            println!("  {:<lw$}{} ({})", target, artifact.url, artifact.format);
+            println!("  {:<lw$}{}", "", artifact.hash.as_str().dark_grey());
+            if let Some(mirror) = &artifact.mirror_url {
+                println!("  {:<lw$}{} {mirror}", "", "mirror".dark_grey());
             }
         }
+        if mirror.is_none() {
+            println!("  {:<lw$}{}", "", "no mirror configured".dark_grey());
+        }
+    }
+
+    if !tree.is_empty() {
+        println!();
+        println!("  {}", "dependencies".dark_grey());
+        print_tree(&tree, "  ");
+    }
+
+    if let Some(hints) = release.map(|r| r.hints.trim()).filter(|h| !h.is_empty()) {
+        println!();
+        println!("  {}", "hints".dark_grey());
+        for line in hints.lines() {
+            println!("  {line}");
+        }
+    }
 
-        if let Some(pkg) = &installed {
-            let size_str = format_size(pkg.size_bytes);
+    if !installed_versions.is_empty() {
+        println!();
+        println!("  {}", "installed".dark_grey());
+        for pkg in &installed_versions {
             let dt = chrono::DateTime::from_timestamp(pkg.installed_at, 0)
                 .unwrap_or_default()
                 .format("%Y-%m-%d")
                 .to_string();
-            println!("  {:<lw$}{}, {}", "installed", size_str, dt);
+            let marker = if pkg.active {
+                "*".green().to_string()
+            } else {
+                " ".to_string()
+            };
+            println!(
+                "  {marker} {:<width$}{}, {}, {}",
+                pkg.version,
+                format_size(pkg.size_bytes),
+                dt,
+                pkg.reason.as_str(),
+                width = lw - 2
+            );
         }
-    } else if let Some(pkg) = &installed {
-        println!(
-            "  {} {}",
-            package.as_str().white().bold(),
-            pkg.version.as_str().dark_grey()
-        );
+    }
+
+    if let Some(files) = &files {
         println!();
-        let size_str = format_size(pkg.size_bytes);
-        let dt = chrono::DateTime::from_timestamp(pkg.installed_at, 0)
-            .unwrap_or_default()
-            .format("%Y-%m-%d")
-            .to_string();
-        println!("  {:<lw$}{}, {}", "installed", size_str, dt);
+        println!("  {}", "files".dark_grey());
+        print_files(files);
     }
 
     Ok(())
 }
+
+fn installed_info(pkg: &crate::db::Package) -> InstalledInfo<'_> {
+    InstalledInfo {
+        version: &pkg.version,
+        reason: pkg.reason.as_str(),
+        size_bytes: pkg.size_bytes,
+        installed_at: pkg.installed_at,
+        active: pkg.active,
+    }
+}
+
+fn non_empty(s: &str) -> Option<&str> {
+    Some(s).filter(|s| !s.is_empty())
+}
+
+/// Binary artifacts of `release`, then its source, with mirror URLs.
+fn artifacts(release: &VersionInfo, mirror: Option<&str>) -> Vec<Artifact> {
+    let mirror_url = |hash: &str| mirror.map(|base| format!("{base}/cas/{hash}"));
+    let binaries = release.binaries.iter().map(|b| Artifact {
+        kind: "binary",
+        arch: Some(b.arch),
+        url: b.url.clone(),
+        hash: b.hash.to_string(),
+        hash_type: b.hash_type,
+        format: format_from_extension(Path::new(&b.url)).to_string(),
+        mirror_url: mirror_url(b.hash.as_ref()),
+    });
+    let source = release.source.iter().map(|s| Artifact {
+        kind: "source",
+        arch: None,
+        url: s.url.clone(),
+        hash: s.hash.to_string(),
+        hash_type: s.hash_type,
+        format: format_from_extension(Path::new(&s.url)).to_string(),
+        mirror_url: mirror_url(s.hash.as_ref()),
+    });
+    binaries.chain(source).collect()
+}
+
+/// Expands `name`'s runtime dependencies from the index. Packages already
+/// in `seen` are marked repeated instead of expanded, which also stops
+/// dependency cycles.
+fn dep_tree(index: &PackageIndex, name: &str, seen: &mut HashSet<String>) -> DepNode {
+    let latest = index.find(name).and_then(IndexEntry::latest);
+    let repeated = !seen.insert(name.to_string());
+    let deps = match latest {
+        Some(release) if !repeated => release
+            .deps
+            .iter()
+            .map(|dep| dep_tree(index, dep, seen))
+            .collect(),
+        _ => Vec::new(),
+    };
+    DepNode {
+        name: name.to_string(),
+        version: latest.map(|r| r.version.clone()),
+        deps,
+        repeated,
+    }
+}
+
+fn print_tree(nodes: &[DepNode], prefix: &str) {
+    for (i, node) in nodes.iter().enumerate() {
+        let last = i + 1 == nodes.len();
+        let branch = if last { "└── " } else { "├── " };
+        let version = node.version.as_deref().unwrap_or("not in index");
+        let repeated = if node.repeated { " (*)" } else { "" };
+        println!(
+            "  {prefix}{branch}{} {}{repeated}",
+            node.name,
+            version.dark_grey()
+        );
+        let child_prefix = format!("{prefix}{}", if last { "    " } else { "│   " });
+        print_tree(&node.deps, &child_prefix);
+    }
+}
+
+fn print_files(files: &[InstalledFile]) {
+    if files.is_empty() {
+        println!("  {}", "(none)".dark_grey());
+    }
+    for file in files {
+        match &file.version {
+            Some(version) => println!("  {} {}", file.path, format!("({version})").dark_grey()),
+            None => println!("  {}", file.path),
+        }
+    }
+}
@@ -1,7 +1,8 @@
+use crate::DbHandle;
 use crate::ui::Output;
-use crate::{DbHandle, apl_home};
 use anyhow::{Context, Result};
 use std::sync::Arc;
+use std::time::Duration;
 
 /// Install one or more packages.
 pub async fn install(packages: &[String], dry_run: bool, _verbose: bool) -> Result<()> {
@@ -9,18 +10,29 @@ pub async fn install(packages: &[String], dry_run: bool, _verbose: bool) -> Resu
 
     // Initialize dependencies formerly done inside install_packages
     let db = DbHandle::spawn().context("Failed to open database")?;
-    let index_path = apl_home().join("index");
-    let index = apl_schema::index::PackageIndex::load(&index_path).ok();
+    let index = crate::store::source::load_index().ok().flatten();
 
-    let client = reqwest::Client::builder()
-        .tcp_nodelay(true)
-        .pool_max_idle_per_host(20)
-        .build()
-        .context("Failed to build HTTP client")?;
-
-    let ctx = crate::ops::Context::new(db, index, client, reporter);
+    let ctx = crate::ops::Context::new(db, index, http_client()?, reporter);
 
     crate::ops::install::install_packages(&ctx, packages, dry_run)
         .await
         .map_err(|e| anyhow::anyhow!(e))
 }
+
+/// HTTP client tuned for parallel package downloads, with the configured
+/// proxy and timeouts.
+pub(crate) fn http_client() -> Result<reqwest::Client> {
+    let config = crate::config::Config::global();
+    let mut builder = reqwest::Client::builder()
+        .tcp_nodelay(true)
+        .pool_max_idle_per_host(config.network.parallel)
+        .connect_timeout(Duration::from_secs(config.timeouts.connect));
+    if let Some(secs) = config.timeouts.request {
+        builder = builder.timeout(Duration::from_secs(secs));
+    }
+    if let Some(proxy) = &config.network.proxy {
+        builder = builder
+            .proxy(reqwest::Proxy::all(proxy).with_context(|| format!("Invalid proxy '{proxy}'"))?);
+    }
+    builder.build().context("Failed to build HTTP client")
+}
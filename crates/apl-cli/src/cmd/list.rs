@@ -2,11 +2,46 @@ use crate::db::StateDb;
 use crate::ui::list::{print_list_footer, print_list_header, print_list_row};
 use anyhow::{Context, Result};
 use apl_schema::types::{PackageName, Version};
+use serde::Serialize;
+
+/// `apl list --format json` document
+#[derive(Serialize)]
+struct ListDocument {
+    packages: Vec<ListedPackage>,
+    total_size_bytes: u64,
+}
+
+#[derive(Serialize)]
+struct ListedPackage {
+    name: String,
+    version: String,
+    reason: &'static str,
+    size_bytes: u64,
+    installed_at: i64,
+}
 
 /// List all installed packages
 pub fn list() -> Result<()> {
     let db = StateDb::open().context("Failed to open state database")?;
     let packages = db.list_packages()?;
+
+    if crate::ui::json::format().is_machine() {
+        let document = ListDocument {
+            total_size_bytes: packages.iter().map(|p| p.size_bytes).sum(),
+            packages: packages
+                .into_iter()
+                .map(|p| ListedPackage {
+                    name: p.name,
+                    version: p.version,
+                    reason: p.reason.as_str(),
+                    size_bytes: p.size_bytes,
+                    installed_at: p.installed_at,
+                })
+                .collect(),
+        };
+        crate::ui::json::print_document("list", &document)?;
+        return Ok(());
+    }
     let mut buffer = crate::ui::buffer::OutputBuffer::default();
 
     if packages.is_empty() {
@@ -1,20 +1,34 @@
 //! Command modules - one file per CLI command
 
+pub mod apply;
+pub mod autoremove;
+pub mod browse;
 pub mod clean;
 pub mod completions;
+pub mod config;
+pub mod deps;
+pub mod generations;
 pub mod hash;
 pub mod history;
+pub mod hook;
 pub mod info;
 pub mod install;
 pub mod list;
+pub mod outdated;
 pub mod package;
+pub mod pin;
+pub mod provides;
 pub mod remove;
 pub mod rollback;
 pub mod run;
 pub mod search;
 pub mod self_update;
 pub mod shell;
+pub mod source;
 pub mod status;
+pub mod undo;
 pub mod update;
 pub mod upgrade;
 pub mod r#use;
+pub mod verify;
+pub mod which;
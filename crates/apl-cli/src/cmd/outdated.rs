@@ -0,0 +1,103 @@
+//! Outdated command
+
+use crate::db::StateDb;
+use crate::store::pin::outdated as newer_release;
+use crate::store::source::installed_name;
+use anyhow::{Context, Result};
+use apl_schema::index::PackageIndex;
+use crossterm::style::Stylize;
+use serde::Serialize;
+
+/// An installed package with a newer release in the index
+#[derive(Debug, Serialize)]
+pub struct OutdatedPackage {
+    pub name: String,
+    pub installed: String,
+    /// Newest release in the index
+    pub latest: String,
+    /// Newest release `apl upgrade` would install, `None` if a pin holds it
+    pub target: Option<String>,
+    /// The package's pin: its requirement, or the held version
+    pub pin: Option<String>,
+}
+
+impl OutdatedPackage {
+    /// Whether a pin keeps the package below `latest`.
+    pub fn is_held(&self) -> bool {
+        self.target.as_ref() != Some(&self.latest)
+    }
+}
+
+/// `apl outdated --format json` document
+#[derive(Serialize)]
+struct OutdatedDocument {
+    packages: Vec<OutdatedPackage>,
+}
+
+/// Finds every active package with a newer release in `index`.
+pub fn collect(db: &StateDb, index: &PackageIndex) -> Result<Vec<OutdatedPackage>> {
+    let pins = db.list_pins()?;
+    let mut packages = Vec::new();
+    for pkg in db.list_packages()? {
+        let name = installed_name(index, &pkg.name, pkg.source.as_deref());
+        let Some(entry) = index.find(&name) else {
+            continue;
+        };
+        let pin = pins.get(&pkg.name);
+        // Only releases that are actually newer (not just different) count
+        let Some(outdated) = newer_release(entry, &pkg.version, pin) else {
+            continue;
+        };
+        packages.push(OutdatedPackage {
+            pin: pin.map(|p| p.requirement.clone().unwrap_or_else(|| pkg.version.clone())),
+            name: pkg.name,
+            installed: pkg.version,
+            latest: outdated.latest,
+            target: outdated.target,
+        });
+    }
+    Ok(packages)
+}
+
+/// List installed packages with newer releases
+pub fn outdated() -> Result<()> {
+    let db = StateDb::open().context("Failed to open state database")?;
+    let index = crate::store::source::load_index()
+        .context("Failed to load index")?
+        .unwrap_or_default();
+    let packages = collect(&db, &index)?;
+
+    if crate::ui::json::format().is_machine() {
+        crate::ui::json::print_document("outdated", &OutdatedDocument { packages })?;
+        return Ok(());
+    }
+
+    if packages.is_empty() {
+        crate::ui::Output::new().success("All packages are up to date");
+        return Ok(());
+    }
+
+    let theme = crate::ui::Theme::default();
+    println!();
+    for pkg in &packages {
+        let name_part = format!("{:<width$}", pkg.name, width = theme.layout.name_width);
+        let target = match &pkg.target {
+            Some(target) => target.as_str().with(theme.colors.success).to_string(),
+            None => "held".dark_grey().to_string(),
+        };
+        print!(
+            "  {} {}  ->  {target}",
+            name_part.with(theme.colors.package_name),
+            pkg.installed.as_str().dark_grey(),
+        );
+        match &pkg.pin {
+            Some(pin) if pkg.is_held() => {
+                println!(" ({} available, pinned to {pin})", pkg.latest);
+            }
+            _ => println!(),
+        }
+    }
+    println!();
+
+    Ok(())
+}
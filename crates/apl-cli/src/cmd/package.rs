@@ -2,6 +2,7 @@
 
 use anyhow::{Context, Result};
 use apl_core::package::Package;
+use apl_core::types::ArtifactFormat;
 use std::path::Path;
 
 /// Create a new package template
@@ -58,9 +59,13 @@ bin = ["{name}"]
 }
 
 /// Validate a package file
-pub fn check(path: &Path) -> Result<()> {
+///
+/// Besides parsing, fetches the first bytes of the source artifact and warns
+/// when the declared `format` disagrees with the real content.
+pub async fn check(path: &Path) -> Result<()> {
     let content = std::fs::read_to_string(path).context("Failed to read package file")?;
     let output = crate::ui::Output::new();
+    let client = super::install::http_client()?;
 
     if let Ok(template) = apl_core::package::PackageTemplate::parse(&content) {
         output.success("Package (Template) is valid");
@@ -69,6 +74,14 @@ pub fn check(path: &Path) -> Result<()> {
             "  Source: {}",
             template.discovery.github_repo().unwrap_or_default()
         );
+        if let Some(source) = &template.source {
+            match concrete_url(&template.discovery, &source.url) {
+                Some(url) => verify_format(&client, &url, source.format, &output).await,
+                None => {
+                    output.info("Skipping format check: the source URL needs a discovered version");
+                }
+            }
+        }
         return Ok(());
     }
 
@@ -81,11 +94,55 @@ pub fn check(path: &Path) -> Result<()> {
         output.warning("No source URL defined");
     } else {
         println!("  Source: {}", pkg.source.url);
+        verify_format(&client, &pkg.source.url, pkg.source.format, &output).await;
     }
 
     Ok(())
 }
 
+/// Fill in a template URL when the version is known without discovery.
+fn concrete_url(discovery: &apl_core::package::DiscoveryConfig, template: &str) -> Option<String> {
+    use apl_core::package::DiscoveryConfig;
+
+    let url = match discovery {
+        DiscoveryConfig::Manual { manual } => {
+            let version = manual.last()?;
+            template
+                .replace("{{tag}}", version)
+                .replace("{{version}}", version)
+        }
+        DiscoveryConfig::GitHub { github, .. } => template.replace("{{github}}", github),
+        DiscoveryConfig::Ports { .. } => template.to_string(),
+    };
+    (!url.contains("{{")).then_some(url)
+}
+
+/// Warn when the content behind `url` is not what `declared` says.
+async fn verify_format(
+    client: &reqwest::Client,
+    url: &str,
+    declared: ArtifactFormat,
+    output: &crate::ui::Output,
+) {
+    match apl_core::io::download::sniff_remote(client, url).await {
+        Ok(detected) => {
+            let actual = apl_core::io::extract::reconcile_format(declared, detected);
+            if actual != declared {
+                output.warning(&format!(
+                    "Declared format is '{declared}' but the artifact is '{actual}'"
+                ));
+            } else if detected.is_some() {
+                println!("  Format: {declared} (matches content)");
+            } else {
+                println!("  Format: {declared} (content not recognised)");
+            }
+        }
+        Err(e) => output.warning(&format!(
+            "Could not fetch the artifact to verify its format: {e}"
+        )),
+    }
+}
+
 /// Bump a package version (mostly legacy, but keeping skeleton for now)
 pub fn bump(path: &Path, version: &str, _url: &str) -> Result<()> {
     let output = crate::ui::Output::new();
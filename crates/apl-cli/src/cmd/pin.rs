@@ -0,0 +1,59 @@
+//! Pin and unpin commands
+
+use crate::db::StateDb;
+use crate::ops;
+use crate::store::pin::Pin;
+use crate::ui::Output;
+use anyhow::{Context, Result, bail};
+use crossterm::style::Stylize;
+
+/// Pin a package (`pkg`) to its installed version, or to a semver range
+/// (`pkg@1.5.x`). Without a spec, list the current pins.
+pub fn pin(spec: Option<&str>, dry_run: bool) -> Result<()> {
+    let db = StateDb::open().context("Failed to open state database")?;
+    let output = Output::new();
+
+    let Some(spec) = spec else {
+        return list(&db);
+    };
+    let (name, requirement) = match spec.split_once('@') {
+        Some((name, requirement)) if !requirement.is_empty() => (name, Some(requirement)),
+        Some(_) => bail!("Invalid pin '{spec}': missing version requirement after @"),
+        None => (spec, None),
+    };
+    ops::pin::pin(&db, name, requirement, dry_run, &output)?;
+    Ok(())
+}
+
+/// Remove the pins of one or more packages
+pub fn unpin(packages: &[String], dry_run: bool) -> Result<()> {
+    let db = StateDb::open().context("Failed to open state database")?;
+    ops::pin::unpin(&db, packages, dry_run, &Output::new())?;
+    Ok(())
+}
+
+fn list(db: &StateDb) -> Result<()> {
+    let mut pins: Vec<Pin> = db.list_pins()?.into_values().collect();
+    if pins.is_empty() {
+        Output::new().info("No packages are pinned.");
+        return Ok(());
+    }
+    pins.sort_by(|a, b| a.package.cmp(&b.package));
+
+    println!();
+    println!("{}", "Pinned packages".bold());
+    for pin in pins {
+        let installed = db.get_package(&pin.package)?.map_or_else(
+            || "not installed".to_string(),
+            |p| format!("installed {}", p.version),
+        );
+        println!(
+            "  {:<20} {:<12} {}",
+            pin.package,
+            pin.requirement.as_deref().unwrap_or("held"),
+            installed.dark_grey()
+        );
+    }
+    println!();
+    Ok(())
+}
@@ -0,0 +1,97 @@
+//! Provides command - which packages ship a binary
+
+use crate::db::StateDb;
+use anyhow::{Context, Result};
+use apl_schema::index::{IndexEntry, PackageIndex};
+use crossterm::style::Stylize;
+use serde::Serialize;
+
+/// A package that provides a command
+#[derive(Debug, Serialize)]
+pub struct Provider {
+    pub name: String,
+    /// Releases that ship the command, newest first
+    pub versions: Vec<String>,
+    /// Active installed version, if any
+    pub installed: Option<String>,
+}
+
+/// `apl provides --format json` document
+#[derive(Serialize)]
+struct ProvidesDocument<'a> {
+    command: &'a str,
+    providers: &'a [Provider],
+}
+
+/// Finds the packages in `index` that provide `command`.
+pub fn providers(index: &PackageIndex, db: &StateDb, command: &str) -> Result<Vec<Provider>> {
+    index
+        .providers(command)
+        .into_iter()
+        .map(|entry| {
+            Ok(Provider {
+                name: entry.name.clone(),
+                versions: versions_providing(entry, command),
+                installed: db.get_package(&entry.name)?.map(|p| p.version),
+            })
+        })
+        .collect()
+}
+
+/// Releases of `entry` that ship `command`. Releases that list no binaries
+/// count if the package as a whole provides it.
+fn versions_providing(entry: &IndexEntry, command: &str) -> Vec<String> {
+    let package_wide = entry.provided_bins().contains(command);
+    entry
+        .releases
+        .iter()
+        .filter(|r| r.bin.iter().any(|b| b == command) || (r.bin.is_empty() && package_wide))
+        .map(|r| r.version.clone())
+        .collect()
+}
+
+/// Loads the local index, failing if there is none.
+pub(crate) fn load_index() -> Result<PackageIndex> {
+    crate::store::source::load_index()
+        .context("Failed to load index")?
+        .context("No index found. Run 'apl update' first.")
+}
+
+/// List the packages that provide `command`
+pub fn provides(command: &str) -> Result<()> {
+    let index = load_index()?;
+    let db = StateDb::open().context("Failed to open state database")?;
+    let providers = providers(&index, &db, command)?;
+
+    if crate::ui::json::format().is_machine() {
+        let document = ProvidesDocument {
+            command,
+            providers: &providers,
+        };
+        crate::ui::json::print_document("provides", &document)?;
+        return Ok(());
+    }
+
+    if providers.is_empty() {
+        crate::ui::Output::new().info(&format!("No package provides '{command}'"));
+        return Ok(());
+    }
+
+    let theme = crate::ui::Theme::default();
+    println!();
+    for provider in &providers {
+        let name_part = format!("{:<width$}", provider.name, width = theme.layout.name_width);
+        print!(
+            "  {} {}",
+            name_part.with(theme.colors.package_name),
+            provider.versions.join(", ").dark_grey()
+        );
+        match &provider.installed {
+            Some(version) => println!("  {}", format!("(installed {version})").green()),
+            None => println!(),
+        }
+    }
+    println!();
+
+    Ok(())
+}
@@ -53,6 +53,17 @@ pub async fn remove(
         .await
         .map_err(|e| anyhow::anyhow!(e))?;
 
+    if !dry_run {
+        let orphans = db.list_orphans()?;
+        if !orphans.is_empty() {
+            reporter.info(&format!(
+                "{} package(s) no longer needed: {}. Run 'apl autoremove' to remove them.",
+                orphans.len(),
+                orphans.join(", ")
+            ));
+        }
+    }
+
     // Brief sleep to ensure UI actor completes rendering
     tokio::time::sleep(std::time::Duration::from_millis(100)).await;
 
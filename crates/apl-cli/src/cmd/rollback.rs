@@ -1,7 +1,9 @@
 //! Rollback command
 
+use crate::cmd::history::format_timestamp;
 use crate::db::StateDb;
 use anyhow::{Context, Result, bail};
+use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, TimeDelta};
 // use crate::ui::Output; // Not needed if switch handles output
 
 /// Rollback a package to its previous state
@@ -48,3 +50,74 @@ pub async fn rollback(pkg_name: &str, dry_run: bool) -> Result<()> {
 
     Ok(())
 }
+
+/// Restore the active versions of every package as of a history event or timestamp
+///
+/// The point is resolved to the last operation that switched profile
+/// generations at or before it, and that generation is made current again.
+pub fn rollback_to(point: &str, dry_run: bool) -> Result<()> {
+    let db = StateDb::open().context("Failed to open state database")?;
+
+    let target = if let Ok(event_id) = point.parse::<i64>() {
+        let event = db
+            .get_history_event(event_id)?
+            .with_context(|| format!("No history event #{event_id}"))?;
+        let operation = match event.operation.as_deref() {
+            Some(id) => db.get_operation(id)?,
+            None => None,
+        };
+        match operation.filter(|op| op.generation_to.is_some()) {
+            Some(op) => Some(op),
+            None => db.operation_at(event.timestamp)?,
+        }
+    } else {
+        db.operation_at(parse_timestamp(point)?)?
+    };
+
+    let Some((operation, generation)) =
+        target.and_then(|op| op.generation_to.map(|generation| (op, generation)))
+    else {
+        bail!("No profile generation was recorded at or before '{point}', cannot rollback.");
+    };
+    drop(db);
+
+    crate::ui::Output::new().info(&format!(
+        "Rolling back to generation {generation} ({} at {})...",
+        operation.kind,
+        format_timestamp(operation.committed_at)
+    ));
+    crate::cmd::generations::switch(generation, dry_run)
+}
+
+/// Parses a local date or date-time into milliseconds.
+///
+/// A bare date means the end of that day.
+fn parse_timestamp(point: &str) -> Result<i64> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(point) {
+        return Ok(dt.timestamp_millis());
+    }
+
+    let naive = [
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+    ]
+    .iter()
+    .find_map(|fmt| NaiveDateTime::parse_from_str(point, fmt).ok())
+    .or_else(|| {
+        NaiveDate::parse_from_str(point, "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.succ_opt()?.and_hms_opt(0, 0, 0))
+            .map(|end| end - TimeDelta::milliseconds(1))
+    })
+    .with_context(|| {
+        format!("'{point}' is neither a history event id nor a timestamp like \"2026-01-31 14:00\"")
+    })?;
+
+    naive
+        .and_local_timezone(Local)
+        .earliest()
+        .map(|dt| dt.timestamp_millis())
+        .with_context(|| format!("'{point}' does not exist in the local time zone"))
+}
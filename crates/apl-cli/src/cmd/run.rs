@@ -1,21 +1,24 @@
 //! Run command - transient execution without global install
 
 use anyhow::{Context, Result};
-use reqwest::Client;
 
 use crate::ops::flow::UnresolvedPackage;
+use crate::store::lock::LockMode;
 use crate::ui::Output;
-use apl_core::paths::apl_home;
 use apl_schema::types::PackageName;
 
 /// Run a package transiently without global installation
-pub async fn run(pkg_name: &str, args: &[String], _dry_run: bool) -> Result<()> {
-    let client = Client::new();
+///
+/// Runs `bin` from the package, or its first binary when `None`. The
+/// process lock is only held while the package is resolved and fetched, not
+/// while the program runs.
+pub async fn run(pkg_name: &str, bin: Option<&str>, args: &[String], _dry_run: bool) -> Result<()> {
+    let client = super::install::http_client()?;
+    let lock = crate::store::lock::lock(LockMode::Shared, true)?;
 
     // 1. Resolve and download
     let output = Output::new();
-    let index_path = apl_home().join("index");
-    let index = apl_schema::index::PackageIndex::load(&index_path).ok();
+    let index = crate::store::source::load_index().ok().flatten();
 
     let pkg_name_new = PackageName::from(pkg_name);
     let unresolved = UnresolvedPackage::new(pkg_name_new, None);
@@ -25,11 +28,10 @@ pub async fn run(pkg_name: &str, args: &[String], _dry_run: bool) -> Result<()>
     // 2. Already Extracted (by prepare_download_new)
     let extract_dir = prepared.extracted_path;
 
-    // Identify the binary to run (first in bin_list or package name)
-    let bin_name = prepared
-        .bin_list
-        .first()
-        .cloned()
+    // Identify the binary to run (requested, first in bin_list or package name)
+    let bin_name = bin
+        .map(str::to_string)
+        .or_else(|| prepared.bin_list.first().cloned())
         .unwrap_or_else(|| prepared.resolved.name.to_string());
 
     // Find the binary path in the extracted files
@@ -65,6 +67,7 @@ pub async fn run(pkg_name: &str, args: &[String], _dry_run: bool) -> Result<()>
         std::fs::set_permissions(&bin_path, perms)?;
     }
 
+    drop(lock);
     let status = std::process::Command::new(&bin_path)
         .args(args)
         .status()
@@ -1,31 +1,84 @@
 //! Search command
 
-use anyhow::{Context, Result, bail};
-use apl_core::paths::apl_home;
-use apl_schema::index::PackageIndex;
+use crate::db::StateDb;
+use anyhow::{Context, Result};
+use apl_schema::search::{self, Query, SearchOptions};
+use serde::Serialize;
+use std::collections::HashSet;
+
+/// `apl search --format json` document
+#[derive(Serialize)]
+struct SearchDocument<'a> {
+    query: &'a str,
+    /// Matches across all pages
+    total: usize,
+    page: u32,
+    per_page: u32,
+    results: Vec<SearchResult<'a>>,
+}
+
+#[derive(Serialize)]
+struct SearchResult<'a> {
+    name: &'a str,
+    version: Option<&'a str>,
+    description: &'a str,
+    score: i64,
+}
 
 /// Search packages in the local index
-pub fn search(query: &str) -> Result<()> {
+pub fn search(query: &str, page: u32, per_page: u32) -> Result<()> {
     use crossterm::style::Stylize;
 
-    let index_path = apl_home().join("index");
-    if !index_path.exists() {
-        bail!("No index found. Run 'apl update' first.");
-    }
+    let parsed: Query = query.parse().context("Invalid search query")?;
+    let index = super::provides::load_index()?;
+    let db = StateDb::open().context("Failed to open state database")?;
+    let installed: HashSet<String> = db.list_packages()?.into_iter().map(|p| p.name).collect();
+    let is_installed = |name: &str| installed.contains(name);
 
-    let index = PackageIndex::load(&index_path).context("Failed to load index")?;
+    let options = SearchOptions {
+        installed: Some(&is_installed),
+        offset: (page as usize - 1) * per_page as usize,
+        limit: Some(per_page as usize),
+    };
+    let results = search::search(&index, &parsed, &options);
 
-    let results = index.search(query);
+    if crate::ui::json::format().is_machine() {
+        let document = SearchDocument {
+            query,
+            total: results.total,
+            page,
+            per_page,
+            results: results
+                .hits
+                .iter()
+                .map(|hit| SearchResult {
+                    name: hit.entry.name.as_str(),
+                    version: hit.entry.latest().map(|v| v.version.as_str()),
+                    description: &hit.entry.description,
+                    score: hit.score,
+                })
+                .collect(),
+        };
+        crate::ui::json::print_document("search", &document)?;
+        return Ok(());
+    }
 
     let theme = crate::ui::Theme::default();
 
-    if results.is_empty() {
+    if results.hits.is_empty() {
         println!();
-        println!(
-            "  {} No packages found matching '{}'",
-            theme.icons.info.blue(),
-            query.white()
-        );
+        if results.total == 0 {
+            println!(
+                "  {} No packages found matching '{}'",
+                theme.icons.info.blue(),
+                query.white()
+            );
+        } else {
+            println!(
+                "  {} Page {page} is past the last page of results",
+                theme.icons.info.blue()
+            );
+        }
         println!();
         return Ok(());
     }
@@ -35,10 +88,10 @@ pub fn search(query: &str) -> Result<()> {
 
     crate::ui::list::print_search_header(&mut buffer);
 
-    for entry in &results {
-        let name = entry.name.to_string();
-        let version = entry.latest().map_or("?", |v| v.version.as_str());
-        let description = &entry.description;
+    for hit in &results.hits {
+        let name = hit.entry.name.to_string();
+        let version = hit.entry.latest().map_or("?", |v| v.version.as_str());
+        let description = &hit.entry.description;
 
         crate::ui::list::print_search_row(&mut buffer, &name, version, description);
     }
@@ -46,9 +99,14 @@ pub fn search(query: &str) -> Result<()> {
     buffer.flush();
 
     println!();
-    let count = results.len();
+    let count = results.total;
     let plural = if count == 1 { "" } else { "s" };
-    println!("  {count} result{plural}");
+    let pages = count.div_ceil(per_page as usize);
+    if pages > 1 {
+        println!("  {count} result{plural}, page {page} of {pages}");
+    } else {
+        println!("  {count} result{plural}");
+    }
 
     Ok(())
 }
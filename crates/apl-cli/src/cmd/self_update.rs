@@ -31,12 +31,11 @@ pub async fn self_update(dry_run: bool) -> Result<()> {
     output.info("Checking for APL updates via apl.pub...");
 
     // 1. Fetch index from apl.pub
-    let client = Client::new();
-    let index_url =
-        std::env::var("APL_INDEX_URL").unwrap_or_else(|_| "https://apl.pub/index".to_string());
+    let client = super::install::http_client()?;
+    let index_url = &crate::config::Config::global().index.url;
 
     let response = client
-        .get(&index_url)
+        .get(index_url)
         .header("User-Agent", crate::USER_AGENT)
         .send()
         .await
@@ -1,3 +1,4 @@
+use crate::store::lock::LockMode;
 use crate::ui::Output;
 use anyhow::{Context, Result, anyhow};
 use apl_core::manifest::{Lockfile, Manifest};
@@ -64,16 +65,20 @@ pub async fn shell(frozen: bool, update: bool, command: Option<Vec<String>>) ->
     };
 
     // 4. Ensure Installed (in store)
-    let client = reqwest::Client::new();
-    ensure_installed(&lockfile, &index, &output, &client).await?;
+    let client = super::install::http_client()?;
+    // Only populating the store needs the lock; the shell itself may live for
+    // hours and must not block other apl commands.
+    {
+        let _lock = crate::store::lock::lock(LockMode::Exclusive, true)?;
+        ensure_installed(&lockfile, &index, &output, &client).await?;
+    }
 
     run_shell(&output, &lockfile, root_dir, command.as_deref())
 }
 
-/// Load the package index from APL home
+/// Load the package index of every source
 fn load_index() -> Result<PackageIndex> {
-    let index_path = apl_core::paths::apl_home().join("index");
-    PackageIndex::load(&index_path).context("Failed to load index. Run 'apl update' first.")
+    crate::store::source::load_index()?.context("Failed to load index. Run 'apl update' first.")
 }
 
 /// Spawns the shell with the configured environment
@@ -127,7 +132,8 @@ fn run_shell(
     let new_path = env::join_paths(all_paths).context("Failed to join paths")?;
 
     // 4. Spawn Shell
-    let shell_bin = env::var("SHELL").unwrap_or_else(|_| "/bin/zsh".to_string());
+    let shell_bin = env::var("SHELL")
+        .unwrap_or_else(|_| crate::config::Config::global().shell.fallback.clone());
 
     // Get project name for prompt prefix
     let project_name = root_dir
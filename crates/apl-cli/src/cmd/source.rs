@@ -0,0 +1,121 @@
+//! Source command - add, remove and list package sources
+
+use crate::store::source::{Source, SourceError};
+use crate::ui::Output;
+use anyhow::{Result, bail};
+use crossterm::style::Stylize;
+use serde::Serialize;
+
+/// `apl source list --format json` document
+#[derive(Serialize)]
+struct ListDocument {
+    sources: Vec<SourceSummary>,
+}
+
+#[derive(Serialize)]
+struct SourceSummary {
+    name: String,
+    url: String,
+    key: String,
+    priority: i32,
+    /// `None` until the index is fetched with `apl update`
+    packages: Option<usize>,
+    updated_at: Option<i64>,
+}
+
+/// Add a named source and fetch its index
+pub async fn add(name: &str, url: &str, key: &str, priority: i32, dry_run: bool) -> Result<()> {
+    let output = Output::new();
+    let source = Source::new(name, url, key, priority)?;
+    if Source::list()?.iter().any(|s| s.name == source.name) {
+        return Err(SourceError::Exists(source.name).into());
+    }
+
+    if dry_run {
+        output.info(&format!(
+            "(dry run) Would add source '{name}' at {url} with priority {priority}"
+        ));
+        return Ok(());
+    }
+
+    // The index is checked against the key before anything is written
+    let client = super::install::http_client()?;
+    let index = super::update::fetch_index(&client, &source, &output).await?;
+    source.save()?;
+    index.save(&source.index_path())?;
+    output.success(&format!(
+        "Added source '{name}' with {} packages",
+        index.len()
+    ));
+    Ok(())
+}
+
+/// Remove a named source and its index
+pub fn remove(name: &str, dry_run: bool) -> Result<()> {
+    let output = Output::new();
+    let source = Source::get(name)?;
+    if source.is_default() {
+        bail!(SourceError::Default);
+    }
+
+    if dry_run {
+        output.info(&format!("(dry run) Would remove source '{name}'"));
+        return Ok(());
+    }
+
+    source.remove()?;
+    output.success(&format!("Removed source '{name}'"));
+
+    // Packages stay installed; they just no longer get upgrades from it
+    let db = crate::db::StateDb::open()?;
+    let orphaned: Vec<_> = db
+        .list_packages()?
+        .into_iter()
+        .filter(|p| p.source.as_deref() == Some(name))
+        .map(|p| p.name)
+        .collect();
+    if !orphaned.is_empty() {
+        output.info(&format!(
+            "Still installed from '{name}': {}",
+            orphaned.join(", ")
+        ));
+    }
+    Ok(())
+}
+
+/// List every source, highest priority first
+pub fn list() -> Result<()> {
+    let mut summaries = Vec::new();
+    for source in Source::list()? {
+        let index = source.load_index()?;
+        summaries.push(SourceSummary {
+            packages: index.as_ref().map(apl_schema::index::PackageIndex::len),
+            updated_at: index.as_ref().map(|i| i.updated_at),
+            name: source.name,
+            url: source.url,
+            key: source.key,
+            priority: source.priority,
+        });
+    }
+
+    if crate::ui::json::format().is_machine() {
+        let document = ListDocument { sources: summaries };
+        crate::ui::json::print_document("sources", &document)?;
+        return Ok(());
+    }
+
+    let width = summaries.iter().map(|s| s.name.len()).max().unwrap_or(0);
+    for summary in summaries {
+        let packages = summary
+            .packages
+            .map_or_else(|| "not fetched".to_string(), |n| format!("{n} packages"));
+        println!(
+            "{}  {:>4}  {}  {}",
+            format!("{:<width$}", summary.name).bold(),
+            summary.priority,
+            summary.url,
+            packages.dark_grey()
+        );
+    }
+    Ok(())
+}
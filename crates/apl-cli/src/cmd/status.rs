@@ -1,9 +1,26 @@
 //! Status command to check for updates and health
+use crate::cmd::outdated::OutdatedPackage;
 use crate::db::StateDb;
 use anyhow::{Context, Result};
 use apl_core::paths::apl_home;
-use apl_schema::index::PackageIndex;
-use apl_schema::types::{PackageName, Version};
+use serde::Serialize;
+
+/// `apl status --format json` document
+#[derive(Serialize)]
+struct StatusDocument {
+    version: &'static str,
+    /// Date the local index was last written, `None` without an index
+    index_updated: Option<String>,
+    packages: usize,
+    cache: CacheStatus,
+    outdated: Vec<OutdatedPackage>,
+}
+
+#[derive(Serialize)]
+struct CacheStatus {
+    size_bytes: u64,
+    items: usize,
+}
 
 /// Check status of installed packages
 pub fn status() -> Result<()> {
@@ -27,11 +44,7 @@ pub fn status() -> Result<()> {
         },
     );
 
-    let index = if index_path.exists() {
-        PackageIndex::load(&index_path).ok()
-    } else {
-        None
-    };
+    let index = crate::store::source::load_index().ok().flatten();
 
     // 3. Packages and Cache
     let packages = db.list_packages()?;
@@ -51,28 +64,29 @@ pub fn status() -> Result<()> {
     }
 
     // 4. Updates
-    let mut update_list = Vec::new();
-    if let Some(idx) = &index {
-        for pkg in &packages {
-            if !pkg.active {
-                continue;
-            }
-            let pkg_name = PackageName::new(&pkg.name);
-            let pkg_version = Version::from(pkg.version.as_str());
-
-            if let Some(entry) = idx.find(&pkg_name) {
-                let latest = match entry.latest() {
-                    Some(v) => v.version.clone(),
-                    None => continue,
-                };
-                // Only show update if latest is actually newer (not just different)
-                if apl_schema::version::is_newer(pkg_version.as_str(), &latest) {
-                    update_list.push((pkg_name, pkg_version, latest));
-                }
-            }
-        }
+    let outdated = match &index {
+        Some(idx) => crate::cmd::outdated::collect(&db, idx)?,
+        None => Vec::new(),
+    };
+
+    if crate::ui::json::format().is_machine() {
+        let document = StatusDocument {
+            version: pkg_version,
+            index_updated: index.is_some().then_some(index_date),
+            packages: packages.len(),
+            cache: CacheStatus {
+                size_bytes: total_size,
+                items: cache_items,
+            },
+            outdated,
+        };
+        crate::ui::json::print_document("status", &document)?;
+        return Ok(());
     }
 
+    let update_list: Vec<_> = outdated.iter().filter(|p| p.target.is_some()).collect();
+    let held_list: Vec<_> = outdated.iter().filter(|p| p.is_held()).collect();
+
     // --- RENDER ---
     let theme = Theme::default();
     let label_width = 12;
@@ -119,13 +133,35 @@ pub fn status() -> Result<()> {
         );
         println!();
 
-        for (name, old, new) in update_list {
-            let name_part = format!("{:<width$}", name, width = theme.layout.name_width);
+        for pkg in update_list {
+            let name_part = format!("{:<width$}", pkg.name, width = theme.layout.name_width);
+            let target = pkg.target.as_deref().unwrap_or_default();
             println!(
                 "  {} {}  ->  {}",
                 name_part.with(theme.colors.package_name),
-                old.as_str().dark_grey(),
-                new.with(theme.colors.success)
+                pkg.installed.as_str().dark_grey(),
+                target.with(theme.colors.success)
+            );
+        }
+    }
+
+    // Section 3: Held packages (if any)
+    if !held_list.is_empty() {
+        println!();
+        println!(
+            "{}",
+            format!("{} packages are held by pins", held_list.len()).dark_grey()
+        );
+        println!();
+
+        for pkg in held_list {
+            let name_part = format!("{:<width$}", pkg.name, width = theme.layout.name_width);
+            println!(
+                "  {} {}  held ({} available, pinned to {})",
+                name_part.with(theme.colors.package_name),
+                pkg.installed.as_str().dark_grey(),
+                pkg.latest,
+                pkg.pin.as_deref().unwrap_or_default()
             );
         }
     }
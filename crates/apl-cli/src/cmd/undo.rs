@@ -0,0 +1,28 @@
+//! Undo command
+
+use crate::cmd::history::format_timestamp;
+use crate::db::StateDb;
+use anyhow::{Context, Result, bail};
+
+/// Revert the last operation by switching back to the generation it replaced
+///
+/// The undo is itself an operation, so running it twice redoes the change.
+pub fn undo(dry_run: bool) -> Result<()> {
+    let db = StateDb::open().context("Failed to open state database")?;
+
+    let Some(operation) = db.last_operation()? else {
+        bail!("Nothing to undo.");
+    };
+    let time_str = format_timestamp(operation.committed_at);
+
+    let Some(previous) = operation.generation_from else {
+        bail!(
+            "The last operation ({} at {time_str}) did not change ~/.apl/bin, cannot undo.",
+            operation.kind
+        );
+    };
+    drop(db);
+
+    crate::ui::Output::new().info(&format!("Undoing {} from {time_str}...", operation.kind));
+    crate::cmd::generations::switch(previous, dry_run)
+}
@@ -1,19 +1,17 @@
 //! Upgrade command - upgrade installed packages to latest versions
 
-use crate::index::PackageIndex;
+use crate::store::pin::outdated;
+use crate::store::source::installed_name;
 use anyhow::Result;
-use apl_core::paths::apl_home;
-use apl_schema::version::is_newer;
 
 /// Upgrade installed packages
 pub async fn upgrade(packages: &[String], skip_confirm: bool, dry_run: bool) -> Result<()> {
     use crossterm::style::Stylize;
 
-    let index_path = apl_home().join("index");
     let output = crate::ui::Output::new();
 
     // Load index
-    let Ok(index) = PackageIndex::load(&index_path) else {
+    let Ok(Some(index)) = crate::store::source::load_index() else {
         output.error("No index found. Run 'apl update' first.");
         return Ok(());
     };
@@ -22,43 +20,45 @@ pub async fn upgrade(packages: &[String], skip_confirm: bool, dry_run: bool) ->
     let db = crate::db::StateDb::open()?;
     let installed = db.list_packages()?;
 
-    // Determine which packages to upgrade
-    let to_upgrade: Vec<_> = if packages.is_empty() {
-        // Upgrade all
-        installed
-            .iter()
-            .filter_map(|pkg| {
-                if let Some(entry) = index.find(&pkg.name) {
-                    let latest = &entry.latest()?.version;
-                    // Only upgrade if latest is actually newer (not just different)
-                    if is_newer(&pkg.version, latest) {
-                        Some((pkg.name.clone(), pkg.version.clone(), latest.clone()))
-                    } else {
-                        None::<(_, _, _)>
-                    }
-                } else {
-                    None
-                }
-            })
-            .collect()
+    let pins = db.list_pins()?;
+
+    // Determine which packages to upgrade (all if none were named)
+    let candidates: Vec<_> = if packages.is_empty() {
+        installed.iter().collect()
     } else {
-        // Upgrade specific packages
         packages
             .iter()
-            .filter_map(|name| {
-                let pkg = installed.iter().find(|p| &p.name == name)?;
-                let entry = index.find(name)?;
-                let latest = &entry.latest()?.version;
-                // Only upgrade if latest is actually newer (not just different)
-                if is_newer(&pkg.version, latest) {
-                    Some((pkg.name.clone(), pkg.version.clone(), latest.clone()))
-                } else {
-                    None
-                }
-            })
+            .filter_map(|name| installed.iter().find(|p| &p.name == name))
             .collect()
     };
 
+    let mut to_upgrade = Vec::new();
+    for pkg in candidates {
+        // Upgrade from the source the package was installed from
+        let name = installed_name(&index, &pkg.name, pkg.source.as_deref());
+        let Some(entry) = index.find(&name) else {
+            continue;
+        };
+        // Only upgrade if a release is actually newer (not just different),
+        // and only as far as the package's pin allows
+        let pin = pins.get(&pkg.name);
+        let Some(outdated) = outdated(entry, &pkg.version, pin) else {
+            continue;
+        };
+        if outdated.is_held() {
+            let pinned = pin
+                .and_then(|p| p.requirement.as_deref())
+                .unwrap_or(&pkg.version);
+            output.info(&format!(
+                "Holding {} ({} available, pinned to {pinned})",
+                pkg.name, outdated.latest
+            ));
+        }
+        if let Some(target) = outdated.target {
+            to_upgrade.push((name, pkg.version.clone(), target));
+        }
+    }
+
     if to_upgrade.is_empty() {
         output.success("All packages are up to date.");
         return Ok(());
@@ -105,14 +105,14 @@ pub async fn upgrade(packages: &[String], skip_confirm: bool, dry_run: bool) ->
         println!();
     }
 
-    // Extract package names and call install
-    let package_names: Vec<String> = to_upgrade.iter().map(|(name, _, _)| name.clone()).collect();
+    // Install the exact target versions, so pins are honored
+    let package_names: Vec<String> = to_upgrade
+        .iter()
+        .map(|(name, _, new)| format!("{name}@{new}"))
+        .collect();
 
     // Initialize full context for install
-    let client = reqwest::Client::builder()
-        .tcp_nodelay(true)
-        .pool_max_idle_per_host(20)
-        .build()?;
+    let client = super::install::http_client()?;
 
     // We already have db and index loaded, reuse them if possible, or recreate handles
     // Since DbHandle is cloneable and we have index, we can construct context.
@@ -2,8 +2,9 @@
 use anyhow::{Context, Result};
 use apl_schema::version::PackageSpec;
 
-/// Switch the active version of a package (CLI Entry Point)
-pub fn use_package(pkg_spec: &str, dry_run: bool) -> Result<()> {
+/// Switch the active version of a package, or link/unlink another version
+/// side-by-side (CLI Entry Point)
+pub fn use_package(pkg_spec: &str, side_by_side: bool, unlink: bool, dry_run: bool) -> Result<()> {
     let output = crate::ui::Output::new();
 
     // Parse input
@@ -13,6 +14,12 @@ pub fn use_package(pkg_spec: &str, dry_run: bool) -> Result<()> {
         .clone()
         .context("Version is required for use (e.g., 'apl use jq@1.6')")?;
 
-    crate::ops::switch::switch_version(&spec.name, &version, dry_run, &output)
-        .map_err(|e| anyhow::anyhow!(e))
+    if side_by_side {
+        crate::ops::switch::link_side_by_side(&spec.name, &version, dry_run, &output)
+    } else if unlink {
+        crate::ops::switch::unlink_side_by_side(&spec.name, &version, dry_run, &output)
+    } else {
+        crate::ops::switch::switch_version(&spec.name, &version, dry_run, &output)
+    }
+    .map_err(|e| anyhow::anyhow!(e))
 }
@@ -0,0 +1,122 @@
+//! Verify command
+
+use crate::db::{Package, StateDb};
+use crate::ops::verify::{self, Problem};
+use crate::ui::Output;
+use anyhow::{Context, Result, bail};
+use crossterm::style::Stylize;
+
+/// Check installed packages against their recorded file hashes and links,
+/// optionally re-extracting the damaged ones
+pub async fn verify(
+    packages: &[String],
+    all_versions: bool,
+    repair: bool,
+    dry_run: bool,
+) -> Result<()> {
+    let db = StateDb::open().context("Failed to open state database")?;
+    let output = Output::new();
+
+    let names: Vec<String> = if packages.is_empty() {
+        db.list_packages()?.into_iter().map(|p| p.name).collect()
+    } else {
+        packages.to_vec()
+    };
+
+    let mut selected: Vec<Package> = Vec::new();
+    for name in &names {
+        if all_versions {
+            let versions = db.list_package_versions(name)?;
+            if versions.is_empty() {
+                bail!("Package '{name}' is not installed.");
+            }
+            selected.extend(versions);
+        } else {
+            let package = db
+                .get_package(name)?
+                .with_context(|| format!("Package '{name}' is not installed."))?;
+            selected.push(package);
+        }
+    }
+
+    if selected.is_empty() {
+        output.info("No packages installed.");
+        return Ok(());
+    }
+
+    let mut damaged = Vec::new();
+    let mut unverified = 0;
+    println!();
+    for package in selected {
+        let problems = verify::check(&db, &package)?;
+        let label = format!("{} {}", package.name, package.version);
+        if problems.is_empty() {
+            println!("  {} {label}", "✓".green());
+        } else if problems == [Problem::Unrecorded] {
+            println!(
+                "  {} {label}  {}",
+                "?".yellow(),
+                Problem::Unrecorded.to_string().dim()
+            );
+            unverified += 1;
+            if repair {
+                damaged.push((package, problems));
+            }
+        } else {
+            println!("  {} {label}", "✗".red());
+            for problem in &problems {
+                println!("      {problem}");
+            }
+            damaged.push((package, problems));
+        }
+    }
+    println!();
+
+    if unverified > 0 && !repair {
+        output.info(&format!(
+            "{unverified} package(s) could not be verified; `apl verify --repair` records their hashes"
+        ));
+    }
+    if damaged.is_empty() {
+        return Ok(());
+    }
+    if !repair {
+        bail!(
+            "{} package(s) failed verification; run `apl verify --repair` to fix them",
+            damaged.len()
+        );
+    }
+
+    let index = crate::store::source::load_index().ok().flatten();
+    let client = super::install::http_client()?;
+
+    let mut failed = 0;
+    for (package, problems) in &damaged {
+        if dry_run {
+            output.info(&format!(
+                "(dry run) Would repair {} {}",
+                package.name, package.version
+            ));
+            continue;
+        }
+        output.info(&format!(
+            "Repairing {} {}...",
+            package.name, package.version
+        ));
+        match verify::repair(&db, package, problems, index.as_ref(), &client, &output).await {
+            Ok(()) => output.success(&format!("Repaired {} {}", package.name, package.version)),
+            Err(e) => {
+                output.error(&format!(
+                    "Failed to repair {} {}: {e}",
+                    package.name, package.version
+                ));
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("{failed} package(s) could not be repaired");
+    }
+    Ok(())
+}
@@ -0,0 +1,69 @@
+//! Which command
+
+use crate::config::Config;
+use crate::db::StateDb;
+use crate::ops::stored_binaries;
+use crate::{bin_path, store_path};
+use anyhow::{Context, Result, bail};
+use crossterm::style::Stylize;
+
+/// Show which package owns a link in `~/.apl/bin` and which others provide it
+pub fn which(bin: &str) -> Result<()> {
+    let db = StateDb::open().context("Failed to open state database")?;
+    let profiles = crate::ops::profiles_blocking(&db)?;
+    let config = Config::global();
+
+    let generation = match profiles.current()? {
+        Some(id) => Some(profiles.load(id)?),
+        None => None,
+    };
+    // Side-by-side links belong to the package too, at another version.
+    let owner = generation.as_ref().and_then(|g| {
+        g.packages
+            .iter()
+            .find_map(|p| Some((p, p.link_version(bin)?)))
+    });
+
+    // Installed packages that would link a binary under this name too.
+    let others: Vec<_> = db
+        .list_packages()?
+        .into_iter()
+        .filter(|p| owner.is_none_or(|(o, _)| o.name != p.name))
+        .filter(|p| {
+            let store_dir = store_path().join(&p.name).join(&p.version);
+            config
+                .alias_links(&p.name, stored_binaries(&store_dir))
+                .iter()
+                .any(|(name, _)| name == bin)
+        })
+        .collect();
+
+    let link = bin_path().join(bin);
+    match owner {
+        Some((p, version)) => {
+            let target = std::fs::read_link(&link)
+                .map(|t| t.display().to_string())
+                .unwrap_or_else(|_| "?".to_string());
+            println!("{} {version} -> {target}", p.name.as_str().bold());
+        }
+        None if link.exists() => {
+            println!("{} is not managed by a package", link.display());
+        }
+        None if others.is_empty() => bail!("No installed package provides '{bin}'"),
+        None => println!("'{bin}' is not linked"),
+    }
+
+    if !others.is_empty() {
+        println!("Also provided by:");
+        for p in others {
+            println!(
+                "  {} {} (priority {})",
+                p.name,
+                p.version,
+                config.priority(&p.name)
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -23,13 +23,17 @@
 //!
 //! ```text
 //! ~/.apl/
-//! ├── bin/        # Symlinks to active binaries
+//! ├── Aplfile     # Declarative package list (apl apply / apl dump)
+//! ├── bin         # -> profiles/current/bin
+//! ├── config.toml # User configuration
+//! ├── profiles/   # Generations of symlinks to active binaries
 //! ├── store/      # Package artifacts by name/version
 //! ├── cache/      # Downloaded archives (by hash)
 //! ├── index   # Binary package index
 //! └── state.db    # SQLite database
 //! ```
 
+pub mod aplfile;
 pub mod cmd;
 pub mod ops;
 pub mod store;
@@ -39,6 +43,7 @@ pub mod ui;
 pub use crate::store::DbHandle;
 pub use crate::store::db;
 pub use apl_core::Strategy;
+pub use apl_core::config;
 pub use apl_core::io::download as downloader;
 pub use apl_core::io::extract as extractor;
 pub use apl_core::package::{self, Package};
@@ -85,6 +90,19 @@ pub struct Cli {
     #[arg(short, long, global = true)]
     pub quiet: bool,
 
+    /// Fail instead of waiting when another apl process holds the lock
+    #[arg(long, global = true)]
+    pub no_wait: bool,
+
+    /// Output format: human-readable text, or JSON for scripts [default: the
+    /// `ui.format` setting, else text]
+    #[arg(long, global = true, value_enum)]
+    pub format: Option<ui::Format>,
+
+    /// Override a setting for this run, e.g. `--config network.parallel=4`
+    #[arg(long = "config", global = true, value_name = "KEY=VALUE")]
+    pub overrides: Vec<String>,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -111,31 +129,64 @@ pub enum Commands {
         /// Skip confirmation prompt
         #[arg(long, short = 'y')]
         yes: bool,
-        /// Force removal of package metadata even if files are missing
+        /// Remove even if other packages depend on it, or if files are missing
         #[arg(long, short = 'f')]
         force: bool,
     },
+    /// Remove packages that were only installed as dependencies and are no longer needed
+    Autoremove {
+        /// Skip confirmation prompt
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
     /// Switch active version of a package
     Use {
         /// Package spec (e.g. jq@1.6)
         spec: String,
+        /// Keep the active version and also link this one under versioned
+        /// names (e.g. jq-1.6)
+        #[arg(long, conflicts_with = "unlink")]
+        side_by_side: bool,
+        /// Remove the versioned links of a side-by-side version
+        #[arg(long)]
+        unlink: bool,
     },
-    /// View package history
+    /// View history for one package, or a timeline of all operations
     History {
-        /// Package name
-        package: String,
+        /// Package name (default: all packages)
+        package: Option<String>,
     },
-    /// Rollback package to previous state
+    /// Rollback a package to its previous state, or everything to an earlier point
     Rollback {
         /// Package name
-        package: String,
+        #[arg(required_unless_present = "to", conflicts_with = "to")]
+        package: Option<String>,
+        /// History event id (as shown by `apl history`) or timestamp
+        /// (e.g. "2026-01-31 14:00") to restore the active versions of
+        #[arg(long)]
+        to: Option<String>,
+    },
+    /// Revert the last operation
+    Undo,
+    /// Manage profile generations of ~/.apl/bin
+    Generations {
+        #[command(subcommand)]
+        command: GenerationCommands,
     },
     /// List installed packages
     List,
     /// Show package info
     Info {
-        /// Package name
+        /// Package name, optionally with a version (`pkg@1.2.3`)
         package: String,
+        /// List the files the package installed
+        #[arg(long)]
+        files: bool,
+    },
+    /// Show which package provides a binary in ~/.apl/bin
+    Which {
+        /// Binary name
+        bin: String,
     },
     /// Compute SHA256 hash of a file (for package authoring)
     #[command(hide = true)]
@@ -145,17 +196,33 @@ pub enum Commands {
         files: Vec<PathBuf>,
     },
     /// Search available packages
+    ///
+    /// Words match package names, binaries, tags and descriptions. Filters:
+    /// tag:<tag>, type:cli|app, license:<spdx>, has-bin:<binary>,
+    /// installed:yes|no. Join alternatives with OR.
     Search {
-        /// Search query
-        query: String,
+        /// Search query (e.g. `json tag:cli OR has-bin:jq`)
+        #[arg(required = true)]
+        query: Vec<String>,
+        /// Page of results to show
+        #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+        page: u32,
+        /// Results per page
+        #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..))]
+        per_page: u32,
     },
+    /// Browse the index and installed packages in a full-screen UI
+    ///
+    /// Search as you type, filter to installed or outdated packages, and
+    /// install, remove, switch versions or pin from the package list.
+    Browse,
     /// Remove orphaned CAS blobs and temp files
     Clean,
     /// Update package index from CDN
     Update {
-        /// CDN URL for index
-        #[arg(long, env = "APL_INDEX_URL", default_value = "https://apl.pub/index")]
-        url: String,
+        /// CDN URL for index [default: the `index.url` setting]
+        #[arg(long)]
+        url: Option<String>,
         /// Upgrade all installed packages after updating index
         #[arg(long)]
         all: bool,
@@ -170,6 +237,109 @@ pub enum Commands {
     },
     /// Check status of installed packages
     Status,
+    /// List installed packages with newer releases
+    Outdated,
+    /// List packages that provide a command
+    Provides {
+        /// Command (binary) name
+        command: String,
+    },
+    /// Show the dependency tree of a package
+    Deps {
+        /// Package name
+        package: String,
+        /// Include build dependencies
+        #[arg(long)]
+        build: bool,
+        /// Print an indented tree (default)
+        #[arg(long, conflicts_with_all = ["dot", "json"])]
+        tree: bool,
+        /// Print a Graphviz DOT graph
+        #[arg(long, conflicts_with = "json")]
+        dot: bool,
+        /// Print the graph as JSON (same as `--format json`)
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show which packages depend on a package
+    Rdeps {
+        /// Package name
+        package: String,
+        /// Only consider installed packages
+        #[arg(long)]
+        installed: bool,
+        /// Include build dependencies
+        #[arg(long)]
+        build: bool,
+        /// Print an indented tree (default)
+        #[arg(long, conflicts_with_all = ["dot", "json"])]
+        tree: bool,
+        /// Print a Graphviz DOT graph
+        #[arg(long, conflicts_with = "json")]
+        dot: bool,
+        /// Print the graph as JSON (same as `--format json`)
+        #[arg(long)]
+        json: bool,
+    },
+    /// Show or change settings (`~/.apl/config.toml` and its layers)
+    Config {
+        #[command(subcommand)]
+        command: ConfigCommands,
+    },
+    /// Manage package sources (third-party registries)
+    Source {
+        #[command(subcommand)]
+        command: SourceCommands,
+    },
+    /// Shell integration hooks
+    Hook {
+        #[command(subcommand)]
+        command: HookCommands,
+    },
+    /// Hold a package at its installed version or within a semver range,
+    /// or list pins
+    Pin {
+        /// Package, optionally with a version requirement: pkg or pkg@1.5.x
+        spec: Option<String>,
+    },
+    /// Install, switch and remove packages to match ~/.apl/Aplfile
+    Apply {
+        /// Aplfile to apply (default: ~/.apl/Aplfile)
+        #[arg(long, short = 'f')]
+        file: Option<PathBuf>,
+        /// Remove explicitly installed packages the Aplfile doesn't list
+        #[arg(long)]
+        prune: bool,
+        /// Skip confirmation prompt
+        #[arg(long, short = 'y')]
+        yes: bool,
+    },
+    /// Write the installed packages to ~/.apl/Aplfile
+    Dump {
+        /// File to write, or - for stdout (default: ~/.apl/Aplfile)
+        #[arg(long, short = 'f')]
+        file: Option<PathBuf>,
+        /// Require the installed versions exactly instead of any version
+        #[arg(long)]
+        exact: bool,
+    },
+    /// Let pinned packages upgrade freely again
+    Unpin {
+        /// Package name(s)
+        #[arg(required = true)]
+        packages: Vec<String>,
+    },
+    /// Check installed files against the hashes recorded at install time
+    Verify {
+        /// Packages to check (default: all installed)
+        packages: Vec<String>,
+        /// Check every installed version, not just the active one
+        #[arg(long)]
+        all_versions: bool,
+        /// Re-extract damaged packages from the cached or downloaded artifact
+        #[arg(long)]
+        repair: bool,
+    },
     /// Package management commands
     Package {
         #[command(subcommand)]
@@ -187,6 +357,9 @@ pub enum Commands {
     Run {
         /// Package name
         package: String,
+        /// Binary to run, if not the package's first
+        #[arg(long)]
+        bin: Option<String>,
         /// Arguments for the package
         #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
         args: Vec<String>,
@@ -205,6 +378,104 @@ pub enum Commands {
     },
 }
 
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Print the value of a setting, e.g. `network.parallel`
+    Get {
+        /// Dotted key
+        key: String,
+        /// Also show which layer the value comes from
+        #[arg(long)]
+        show_origin: bool,
+    },
+    /// Change a setting in the user config file
+    ///
+    /// Values are read as TOML when they parse (`20`, `true`, `["a", "b"]`)
+    /// and as strings otherwise.
+    Set {
+        /// Dotted key
+        key: String,
+        /// New value
+        value: String,
+    },
+    /// Print every setting
+    List {
+        /// Also show which layer each value comes from
+        #[arg(long)]
+        show_origin: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum SourceCommands {
+    /// Add a source and fetch its index
+    Add {
+        /// Source name; install from it with `name::package`
+        name: String,
+        /// Index URL; the signature must be at `<url>.sig`
+        url: String,
+        /// Base64 Ed25519 public key the index is signed with
+        #[arg(long)]
+        key: String,
+        /// Sources with a higher priority win packages both have (the
+        /// default source has 0)
+        #[arg(long, default_value_t = 0, allow_hyphen_values = true)]
+        priority: i32,
+    },
+    /// Remove a source and its index
+    Remove {
+        /// Source name
+        name: String,
+    },
+    /// List sources, highest priority first
+    List,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum HookCommands {
+    /// Print a handler that suggests packages for unknown commands
+    ///
+    /// Add `eval "$(apl hook command-not-found zsh)"` to ~/.zshrc (or the
+    /// bash/fish equivalent).
+    CommandNotFound {
+        /// Shell to print the handler for
+        shell: cmd::hook::Shell,
+    },
+    /// Handle an unknown command (called by the shell handler)
+    #[command(hide = true)]
+    Handle {
+        /// The command that was not found
+        command: String,
+        /// Its arguments
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true)]
+        args: Vec<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+pub enum GenerationCommands {
+    /// List all generations
+    List,
+    /// Make another generation current
+    Switch {
+        /// Generation number
+        generation: u64,
+    },
+    /// Show package changes between two generations
+    Diff {
+        /// Generation to compare from
+        from: u64,
+        /// Generation to compare to (default: current)
+        to: Option<u64>,
+    },
+    /// Delete old generations
+    Prune {
+        /// Number of most recent generations to keep besides the current one
+        #[arg(long, default_value_t = 5)]
+        keep: usize,
+    },
+}
+
 #[derive(Subcommand, Debug)]
 pub enum PackageCommands {
     /// Create a new package template
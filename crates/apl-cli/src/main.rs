@@ -6,7 +6,14 @@ use clap::Parser;
 use tracing_subscriber::EnvFilter;
 
 use apl_cli::cmd;
-use apl_cli::{Cli, Commands, PackageCommands};
+use apl_cli::config::{ColorMode, Config, Settings, Sources};
+use apl_cli::store::lock::{self, LockMode};
+use apl_cli::ui::Reporter;
+use apl_cli::ui::json::JsonReporter;
+use apl_cli::{
+    Cli, Commands, ConfigCommands, GenerationCommands, HookCommands, PackageCommands,
+    SourceCommands,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -34,8 +41,44 @@ async fn main() -> Result<()> {
 
     let cli = Cli::parse_from(args);
     let dry_run = cli.dry_run;
+    let config = match Settings::load(&Sources::discover(cli.overrides.clone()))
+        .and_then(|settings| settings.config())
+    {
+        Ok(config) => config,
+        // `apl config` still runs, so a broken file can be fixed with it.
+        Err(e) if matches!(cli.command, Commands::Config { .. }) => {
+            eprintln!("warning: {e}");
+            Config::default()
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let format = cli.format.unwrap_or_else(|| config.ui.format.into());
+    match config.ui.color {
+        ColorMode::Always => crossterm::style::force_color_output(true),
+        ColorMode::Never => crossterm::style::force_color_output(false),
+        ColorMode::Auto => {}
+    }
+    config.set_global();
+    apl_cli::ui::json::set_format(format);
+    let lock = match lock_mode(&cli.command, dry_run) {
+        Some(mode) => Some(lock::lock(mode, !cli.no_wait)?),
+        None => None,
+    };
+
+    let result = run(cli.command, dry_run, &cli.overrides).await;
+    drop(lock);
+    if format.is_machine() {
+        if let Err(e) = &result {
+            // Scripts get the failure as an event; the exit code still says so.
+            JsonReporter::new(format).error(&format!("{e:#}"));
+            std::process::exit(1);
+        }
+    }
+    result
+}
 
-    match cli.command {
+async fn run(command: Commands, dry_run: bool, overrides: &[String]) -> Result<()> {
+    match command {
         Commands::Install { packages, verbose } => {
             cmd::install::install(&packages, dry_run, verbose).await
         }
@@ -45,21 +88,100 @@ async fn main() -> Result<()> {
             yes,
             force,
         } => cmd::remove::remove(&packages, all, yes, force, dry_run).await,
-        Commands::Use { spec } => cmd::r#use::use_package(&spec, dry_run),
-        Commands::History { package } => cmd::history::history(&package),
-        Commands::Rollback { package } => cmd::rollback::rollback(&package, dry_run).await,
+        Commands::Autoremove { yes } => cmd::autoremove::autoremove(yes, dry_run).await,
+        Commands::Use {
+            spec,
+            side_by_side,
+            unlink,
+        } => cmd::r#use::use_package(&spec, side_by_side, unlink, dry_run),
+        Commands::History { package } => cmd::history::history(package.as_deref()),
+        Commands::Rollback { package, to } => match to {
+            Some(point) => cmd::rollback::rollback_to(&point, dry_run),
+            None => cmd::rollback::rollback(&package.unwrap_or_default(), dry_run).await,
+        },
+        Commands::Undo => cmd::undo::undo(dry_run),
+        Commands::Generations { command } => match command {
+            GenerationCommands::List => cmd::generations::list(),
+            GenerationCommands::Switch { generation } => {
+                cmd::generations::switch(generation, dry_run)
+            }
+            GenerationCommands::Diff { from, to } => cmd::generations::diff(from, to),
+            GenerationCommands::Prune { keep } => cmd::generations::prune(keep, dry_run),
+        },
         Commands::List => cmd::list::list(),
-        Commands::Info { package } => cmd::info::info(&package),
+        Commands::Info { package, files } => cmd::info::info(&package, files),
+        Commands::Which { bin } => cmd::which::which(&bin),
         Commands::Hash { files } => cmd::hash::hash(&files),
-        Commands::Search { query } => cmd::search::search(&query),
+        Commands::Search {
+            query,
+            page,
+            per_page,
+        } => cmd::search::search(&query.join(" "), page, per_page),
+        Commands::Browse => cmd::browse::browse(dry_run).await,
         Commands::Clean => cmd::clean::clean(dry_run),
-        Commands::Update { url, all } => cmd::update::update(&url, all, dry_run).await,
+        Commands::Update { url, all } => cmd::update::update(url.as_deref(), all, dry_run).await,
         Commands::Upgrade { packages, yes } => cmd::upgrade::upgrade(&packages, yes, dry_run).await,
 
         Commands::Status => cmd::status::status(),
+        Commands::Outdated => cmd::outdated::outdated(),
+        Commands::Provides { command } => cmd::provides::provides(&command),
+        Commands::Deps {
+            package,
+            build,
+            dot,
+            json,
+            ..
+        } => cmd::deps::deps(&package, build, cmd::deps::Render::from_flags(dot, json)),
+        Commands::Rdeps {
+            package,
+            installed,
+            build,
+            dot,
+            json,
+            ..
+        } => cmd::deps::rdeps(
+            &package,
+            build,
+            installed,
+            cmd::deps::Render::from_flags(dot, json),
+        ),
+        Commands::Config { command } => match command {
+            ConfigCommands::Get { key, show_origin } => {
+                cmd::config::get(&key, show_origin, overrides)
+            }
+            ConfigCommands::Set { key, value } => {
+                cmd::config::set(&key, &value, overrides, dry_run)
+            }
+            ConfigCommands::List { show_origin } => cmd::config::list(show_origin, overrides),
+        },
+        Commands::Source { command } => match command {
+            SourceCommands::Add {
+                name,
+                url,
+                key,
+                priority,
+            } => cmd::source::add(&name, &url, &key, priority, dry_run).await,
+            SourceCommands::Remove { name } => cmd::source::remove(&name, dry_run),
+            SourceCommands::List => cmd::source::list(),
+        },
+        Commands::Hook { command } => match command {
+            HookCommands::CommandNotFound { shell } => cmd::hook::command_not_found(shell),
+            HookCommands::Handle { command, args } => cmd::hook::handle(&command, &args).await,
+        },
+        Commands::Pin { spec } => cmd::pin::pin(spec.as_deref(), dry_run),
+        Commands::Unpin { packages } => cmd::pin::unpin(&packages, dry_run),
+        Commands::Apply { file, prune, yes } => {
+            cmd::apply::apply(file.as_deref(), prune, yes, dry_run).await
+        }
+        Commands::Dump { file, exact } => cmd::apply::dump(file.as_deref(), exact, dry_run),
+        Commands::Verify {
+            packages,
+            all_versions,
+            repair,
+        } => cmd::verify::verify(&packages, all_versions, repair, dry_run).await,
         Commands::Package { command } => match command {
             PackageCommands::New { name, output_dir } => cmd::package::new(&name, &output_dir),
-            PackageCommands::Check { path } => cmd::package::check(&path),
+            PackageCommands::Check { path } => cmd::package::check(&path).await,
             PackageCommands::Bump { path, version, url } => {
                 cmd::package::bump(&path, &version, &url)
             }
@@ -69,9 +191,9 @@ async fn main() -> Result<()> {
             Ok(())
         }
         Commands::SelfUpdate => cmd::self_update::self_update(dry_run).await,
-        Commands::Run { package, args } => {
+        Commands::Run { package, bin, args } => {
             println!("Preparing to run '{package}'...");
-            cmd::run::run(&package, &args, dry_run).await
+            cmd::run::run(&package, bin.as_deref(), &args, dry_run).await
         }
         Commands::Shell {
             frozen,
@@ -81,6 +203,63 @@ async fn main() -> Result<()> {
     }
 }
 
+/// How a command holds the global process lock, if at all.
+///
+/// Commands that touch the store, `~/.apl/bin`, the index or the state
+/// database run exclusively; a dry run only reads, so it shares the lock.
+fn lock_mode(command: &Commands, dry_run: bool) -> Option<LockMode> {
+    let mutates = match command {
+        Commands::Install { .. }
+        | Commands::Remove { .. }
+        | Commands::Autoremove { .. }
+        | Commands::Use { .. }
+        | Commands::Rollback { .. }
+        | Commands::Undo
+        | Commands::Unpin { .. }
+        | Commands::Apply { .. }
+        | Commands::Clean
+        | Commands::Update { .. }
+        | Commands::Upgrade { .. }
+        | Commands::SelfUpdate
+        | Commands::Generations {
+            command: GenerationCommands::Switch { .. } | GenerationCommands::Prune { .. },
+        } => true,
+        Commands::Verify { repair, .. } => *repair,
+        Commands::Source { command } => !matches!(command, SourceCommands::List),
+        Commands::Pin { spec } => spec.is_some(),
+        Commands::Generations {
+            command: GenerationCommands::List | GenerationCommands::Diff { .. },
+        }
+        | Commands::History { .. }
+        | Commands::Dump { .. }
+        | Commands::List
+        | Commands::Info { .. }
+        | Commands::Which { .. }
+        | Commands::Search { .. }
+        | Commands::Status
+        | Commands::Outdated
+        | Commands::Provides { .. }
+        | Commands::Deps { .. }
+        | Commands::Rdeps { .. } => false,
+        // `shell` locks only while it populates the store; `run` and the
+        // hook that calls it release the lock before starting the program.
+        // `browse` locks for each operation it runs, not the whole session.
+        Commands::Browse
+        | Commands::Hash { .. }
+        | Commands::Package { .. }
+        | Commands::Completions { .. }
+        | Commands::Config { .. }
+        | Commands::Hook { .. }
+        | Commands::Run { .. }
+        | Commands::Shell { .. } => return None,
+    };
+    Some(if mutates && !dry_run {
+        LockMode::Exclusive
+    } else {
+        LockMode::Shared
+    })
+}
+
 fn has_manifest(start: &std::path::Path) -> bool {
     let mut current = start;
     loop {
@@ -0,0 +1,162 @@
+//! Bringing the installed packages in line with an Aplfile.
+
+use std::collections::HashSet;
+
+use apl_schema::index::IndexEntry;
+use apl_schema::version::version_satisfies_requirement;
+
+use crate::aplfile::Aplfile;
+use crate::db::{InstallReason, Package, StateDb};
+use crate::ops::install::{InstallTask, plan_install};
+use crate::ops::{Context, InstallError};
+
+/// What `apl apply` will do.
+#[derive(Debug, Clone)]
+pub struct Plan {
+    /// Exact `name@version` specs to hand to the installer
+    pub install: Vec<String>,
+    /// The installer's tasks for them, dependencies included
+    pub tasks: Vec<InstallTask>,
+    /// Explicitly installed packages the Aplfile doesn't list
+    pub extras: Vec<String>,
+}
+
+impl Plan {
+    /// Whether installing would change anything.
+    pub fn installs_anything(&self) -> bool {
+        self.tasks
+            .iter()
+            .any(|t| !matches!(t, InstallTask::AlreadyInstalled(..)))
+    }
+}
+
+/// Works out which version of each Aplfile package to end up with and what
+/// the installer has to do to get there.
+pub async fn plan(ctx: &Context, db: &StateDb, aplfile: &Aplfile) -> Result<Plan, InstallError> {
+    let query = |e| InstallError::context("Failed to query installed packages", e);
+    let mut install = Vec::new();
+    for (name, requirement) in &aplfile.packages {
+        let installed = db.list_package_versions(name).map_err(query)?;
+        let entry = ctx.index.as_deref().and_then(|index| index.find(name));
+        let version = target(requirement, &installed, entry).ok_or_else(|| {
+            InstallError::Validation(format!(
+                "No installed or released version of {name} matches '{requirement}'"
+            ))
+        })?;
+        install.push(format!("{name}@{version}"));
+    }
+
+    let tasks = if install.is_empty() {
+        Vec::new()
+    } else {
+        plan_install(ctx, &install).await?
+    };
+
+    // Dependencies of listed packages are needed even if explicitly installed.
+    let needed: HashSet<&str> = tasks
+        .iter()
+        .map(|t| match t {
+            InstallTask::Download(name, _)
+            | InstallTask::Switch(name, _)
+            | InstallTask::AlreadyInstalled(name, _) => name.as_str(),
+        })
+        .collect();
+    let extras = db
+        .list_packages()
+        .map_err(query)?
+        .into_iter()
+        .filter(|p| p.reason == InstallReason::Explicit)
+        .filter(|p| !aplfile.packages.contains_key(&p.name) && !needed.contains(p.name.as_str()))
+        .map(|p| p.name)
+        .collect();
+
+    Ok(Plan {
+        install,
+        tasks,
+        extras,
+    })
+}
+
+/// The version to end up with: the active one if it satisfies `requirement`,
+/// else the newest installed one that does (a switch), else the newest
+/// matching release (a download).
+fn target(requirement: &str, installed: &[Package], entry: Option<&IndexEntry>) -> Option<String> {
+    let matches = |version: &str| version_satisfies_requirement(version, requirement);
+
+    if let Some(active) = installed.iter().find(|p| p.active && matches(&p.version)) {
+        return Some(active.version.clone());
+    }
+    let newest_installed = installed
+        .iter()
+        .map(|p| p.version.as_str())
+        .filter(|v| matches(v))
+        .reduce(|best, v| {
+            if apl_schema::version::is_newer(best, v) {
+                v
+            } else {
+                best
+            }
+        });
+    if let Some(version) = newest_installed {
+        return Some(version.to_string());
+    }
+    // Releases are sorted newest first.
+    entry?
+        .releases
+        .iter()
+        .find(|r| matches(&r.version))
+        .map(|r| r.version.clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apl_schema::index::VersionInfo;
+
+    fn package(version: &str, active: bool) -> Package {
+        Package {
+            name: "terraform".to_string(),
+            version: version.to_string(),
+            sha256: String::new(),
+            installed_at: 0,
+            active,
+            size_bytes: 0,
+            reason: InstallReason::Explicit,
+            source: None,
+        }
+    }
+
+    #[test]
+    fn test_target_prefers_installed_versions() {
+        let entry = IndexEntry {
+            name: "terraform".to_string(),
+            releases: ["1.6.0", "1.5.7", "1.5.6"]
+                .iter()
+                .map(|v| VersionInfo {
+                    version: (*v).to_string(),
+                    ..VersionInfo::default()
+                })
+                .collect(),
+            ..IndexEntry::default()
+        };
+        let installed = [package("1.5.6", false), package("1.6.0", true)];
+
+        // The active version already matches.
+        assert_eq!(
+            target("*", &installed, Some(&entry)).as_deref(),
+            Some("1.6.0")
+        );
+        // An installed version matches: switch rather than download.
+        assert_eq!(
+            target("1.5.x", &installed, Some(&entry)).as_deref(),
+            Some("1.5.6")
+        );
+        // Nothing installed matches: the newest matching release.
+        assert_eq!(
+            target("=1.5.7", &installed, Some(&entry)).as_deref(),
+            Some("1.5.7")
+        );
+        assert_eq!(target("1.5.x", &[], Some(&entry)).as_deref(), Some("1.5.7"));
+        assert_eq!(target("2.x", &installed, Some(&entry)), None);
+    }
+}
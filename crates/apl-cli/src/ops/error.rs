@@ -21,6 +21,11 @@ pub enum InstallError {
     #[error("Validation failed: {0}")]
     Validation(String),
 
+    #[error(
+        "Binary name conflict:\n{0}\nSet a link alias or priority in ~/.apl/config.toml to choose"
+    )]
+    Conflict(String),
+
     #[error("Build/Install script failed: {0}")]
     Script(String),
 
@@ -141,6 +141,8 @@ pub struct ResolvedPackage {
     pub def: Package,
     /// The artifact (binary or source) to download.
     pub artifact: ArtifactKind,
+    /// Package source it was resolved from (None = local file).
+    pub source: Option<String>,
 }
 
 /// State 3: A package that has been downloaded and extracted.
@@ -202,6 +204,7 @@ impl UnresolvedPackage {
                     hash: package_def.source.sha256.clone(),
                 },
                 def: package_def,
+                source: None,
             })
         }
     }
@@ -221,8 +224,8 @@ impl UnresolvedPackage {
             .ok_or_else(|| InstallError::Validation(format!("Package {name} not found")))?;
 
         let release = Self::select_release(name, requested, entry)?;
-        let (artifact, current_arch) =
-            Self::select_artifact(name, release, index_ref.mirror_base_url.as_deref())?;
+        let mirror = crate::store::source::mirror_for(index_ref, &entry.name);
+        let (artifact, current_arch) = Self::select_artifact(name, release, mirror)?;
         let package_def = Self::build_synthetic_package(entry, release, &artifact, current_arch);
 
         Ok(ResolvedPackage {
@@ -230,6 +233,7 @@ impl UnresolvedPackage {
             version: package_def.package.version.clone(),
             def: package_def,
             artifact,
+            source: index_ref.source_of(&entry.name).map(str::to_string),
         })
     }
 
@@ -309,9 +313,9 @@ impl UnresolvedPackage {
                 name: PackageName::from(entry.name.clone()),
                 version: Version::from(release.version.clone()),
                 description: entry.description.clone(),
-                homepage: String::new(),
-                license: String::new(),
-                tags: vec![],
+                homepage: entry.homepage.clone(),
+                license: entry.license.clone(),
+                tags: entry.tags.clone(),
                 type_: if entry.type_ == "app" {
                     Some(PackageType::App)
                 } else {
@@ -389,20 +393,9 @@ impl ResolvedPackage {
             ArtifactKind::Source { .. } => self.def.source.format,
             ArtifactKind::Binary { .. } => {
                 // Infer format from URL since it's not explicitly in ArtifactKind yet
-                let url = self.artifact.url().to_lowercase();
-                let url_path = std::path::Path::new(url.as_str());
-                if url.ends_with(".tar.gz") || url_path.extension().is_some_and(|ext| ext == "tgz")
-                {
-                    ArtifactFormat::TarGz
-                } else if url_path.extension().is_some_and(|ext| ext == "zip") {
-                    ArtifactFormat::Zip
-                } else if url_path.extension().is_some_and(|ext| ext == "dmg") {
-                    ArtifactFormat::Dmg
-                } else if url_path.extension().is_some_and(|ext| ext == "pkg") {
-                    ArtifactFormat::Pkg
-                } else {
-                    ArtifactFormat::Binary
-                }
+                apl_core::io::extract::format_from_extension(std::path::Path::new(
+                    self.artifact.url(),
+                ))
             }
         };
 
@@ -412,21 +405,26 @@ impl ResolvedPackage {
             .strategy
             .clone()
             .unwrap_or(InstallStrategy::Link);
+        // Extension-less app downloads are most likely disk images; fetch
+        // them whole and let the content decide below.
         let is_dmg = (strategy == InstallStrategy::App || strategy == InstallStrategy::Pkg)
-            && (pkg_format == ArtifactFormat::Dmg
-                || self.artifact.url().to_lowercase().ends_with(".dmg")
-                || self.artifact.url().to_lowercase().ends_with(".pkg"));
+            && matches!(
+                pkg_format,
+                ArtifactFormat::Dmg | ArtifactFormat::Pkg | ArtifactFormat::Binary
+            );
 
         let download_or_extract_path: PathBuf;
 
         if is_dmg {
-            let dest_file = temp_dir.path().join(
-                self.artifact
-                    .url()
-                    .split('/')
-                    .next_back()
-                    .unwrap_or("pkg.dmg"),
-            );
+            let file_name = self
+                .artifact
+                .url()
+                .split(['?', '#'])
+                .next()
+                .and_then(|u| u.split('/').next_back())
+                .filter(|n| !n.is_empty())
+                .unwrap_or("download");
+            let mut dest_file = temp_dir.path().join(file_name);
 
             // Try primary URL (mirror if available), fall back to upstream on 404
             let download_result = apl_core::io::download::DownloadRequest::new(
@@ -466,7 +464,42 @@ impl ResolvedPackage {
                 Err(e) => return Err(e.into()),
             }
 
-            download_or_extract_path = dest_file;
+            // Installers dispatch on the file extension, so make it match
+            // what was actually downloaded.
+            let actual = apl_core::io::extract::reconcile_format(
+                pkg_format,
+                apl_core::io::extract::sniff_format(&dest_file).map_err(InstallError::Io)?,
+            );
+            if actual != pkg_format && pkg_format != ArtifactFormat::Binary {
+                reporter.warning(&format!(
+                    "{}: declared as {pkg_format} but the download is {actual}",
+                    self.name
+                ));
+            }
+            download_or_extract_path = match actual {
+                ArtifactFormat::Dmg | ArtifactFormat::Pkg => {
+                    let named = dest_file.with_extension(actual.as_str());
+                    if named != dest_file {
+                        std::fs::rename(&dest_file, &named).map_err(InstallError::Io)?;
+                        dest_file = named;
+                    }
+                    dest_file
+                }
+                _ => {
+                    // e.g. an app shipped as a zip behind an extension-less URL.
+                    let extract_dir = temp_dir.path().join("extracted");
+                    apl_core::io::extract::extract_auto(
+                        &dest_file,
+                        &extract_dir,
+                        reporter,
+                        &self.name,
+                        &self.version,
+                        None,
+                    )
+                    .map_err(|e| InstallError::Other(e.to_string()))?;
+                    extract_dir
+                }
+            };
         } else {
             let cache_file = crate::cache_path().join(self.artifact.hash());
             if let Some(p) = cache_file.parent() {
@@ -487,6 +520,7 @@ impl ResolvedPackage {
                 reporter,
             )
             .with_extract_dest(&extract_dir)
+            .with_format(pkg_format)
             .execute()
             .await;
 
@@ -510,6 +544,7 @@ impl ResolvedPackage {
                         reporter,
                     )
                     .with_extract_dest(&extract_dir)
+                    .with_format(pkg_format)
                     .execute()
                     .await?;
                 }
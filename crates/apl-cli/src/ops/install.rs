@@ -5,21 +5,30 @@
 //! - Resolving package names to specific versions
 //! - Downloading and verifying artifacts
 //! - Extracting archives and linking binaries
-//! - Managing symlinks in `~/.apl/bin`
+//! - Publishing binaries through a new profile generation of `~/.apl/bin`
 //!
 //! The main entry point is [`install_packages`], which handles the full
 //! installation workflow including dependency resolution and parallel downloads.
 
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use std::sync::Arc;
 use std::time::Instant;
 
-use crate::DbHandle;
+use crate::config::Config;
+use crate::db::{InstallReason, InstallRecord};
+use crate::ops::verify::hash_tree;
+use crate::ops::{Context, InstallError, find_binaries, link_records};
+use crate::store::journal::{Journal, OpKind, Step, crash_point};
+use crate::store::profile::ProfileEntry;
 use crate::ui::Reporter;
-use crate::{bin_path, ops::Context, ops::InstallError, ops::link_binaries, store_path};
+use crate::{bin_path, journal_path, store_path};
 use apl_core::io::dmg;
+use apl_core::io::extract::ExtractError;
 use apl_core::package::{InstallStrategy, Package, PackageInfo};
 use apl_core::relinker::Relinker;
+use apl_schema::index::split_source;
 use apl_schema::types::{PackageName, Version};
 use apl_schema::version::PackageSpec;
 
@@ -33,12 +42,43 @@ use crate::ops::flow::{PreparedPackage, UnresolvedPackage};
 /// 3. `AlreadyInstalled`: Identical version is already active. No-op (but we report it).
 ///
 /// This separation allows us to be efficient (don't re-download) and concurrent (downloads happen in parallel).
-enum InstallTask {
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum InstallTask {
     Download(PackageName, Option<Version>),
     Switch(PackageName, Version),
     AlreadyInstalled(PackageName, Version),
 }
 
+/// Points each `source::name` request at its source in a copy of the index
+/// and strips the qualifier, so the rest of the pipeline sees plain names.
+fn select_sources(
+    ctx: &Context,
+    packages: &[String],
+) -> Result<(Context, Vec<String>), InstallError> {
+    if packages.iter().all(|p| split_source(p).0.is_none()) {
+        return Ok((ctx.clone(), packages.to_vec()));
+    }
+    let mut index = ctx.index.as_deref().cloned().ok_or_else(|| {
+        InstallError::Validation("No index found. Run 'apl update' first.".to_string())
+    })?;
+    let mut plain = Vec::with_capacity(packages.len());
+    for package in packages {
+        let (source, spec) = split_source(package);
+        if let Some(source) = source {
+            let name = spec.split_once('@').map_or(spec, |(name, _)| name);
+            if !index.select(name, source) {
+                return Err(InstallError::Validation(format!(
+                    "Package {name} not found in source {source}"
+                )));
+            }
+        }
+        plain.push(spec.to_string());
+    }
+    let mut ctx = ctx.clone();
+    ctx.index = Some(Arc::new(index));
+    Ok((ctx, plain))
+}
+
 async fn resolve_and_filter_packages(
     packages: &[String],
     ctx: &Context,
@@ -164,12 +204,27 @@ async fn plan_install_tasks(
     Ok(tasks)
 }
 
+/// What installing `packages` (with their dependencies) would do, without
+/// doing it.
+pub async fn plan_install(
+    ctx: &Context,
+    packages: &[String],
+) -> Result<Vec<InstallTask>, InstallError> {
+    let (ctx, packages) = select_sources(ctx, packages)?;
+    let (ctx, packages) = (&ctx, packages.as_slice());
+    let (resolved_names, specs) = resolve_and_filter_packages(packages, ctx).await?;
+    plan_install_tasks(&resolved_names, &specs, ctx).await
+}
+
 /// Resolves, downloads, and installs a set of packages.
 pub async fn install_packages(
     ctx: &Context,
     packages: &[String],
     dry_run: bool,
 ) -> Result<(), InstallError> {
+    let (ctx, packages) = select_sources(ctx, packages)?;
+    let (ctx, packages) = (&ctx, packages.as_slice());
+
     // Phase 1: Logic - Resolve Dependencies
     let (resolved_names, specs) = resolve_and_filter_packages(packages, ctx).await?;
 
@@ -193,12 +248,31 @@ pub async fn install_packages(
     ctx.reporter.prepare_pipeline(&table_items);
 
     let start_time = Instant::now();
-    let install_count = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let mut install_count = 0;
 
     let ctx_clone = ctx.clone();
 
+    // Anything the user named is explicit; the rest was pulled in by the resolver.
+    let explicit: std::collections::HashSet<PackageName> =
+        specs.iter().map(|s| s.name.clone()).collect();
+    let reason_for = move |name: &PackageName| {
+        if explicit.contains(name) {
+            InstallReason::Explicit
+        } else {
+            InstallReason::Dependency
+        }
+    };
+
     let mut already_installed_count = 0;
     for task in &tasks {
+        if let InstallTask::AlreadyInstalled(name, _) | InstallTask::Switch(name, _) = task {
+            if !dry_run && reason_for(name) == InstallReason::Explicit {
+                ctx.db
+                    .set_install_reason(name.to_string(), InstallReason::Explicit)
+                    .await
+                    .map_err(|e| InstallError::context("Failed to record install reason", e))?;
+            }
+        }
         match task {
             InstallTask::AlreadyInstalled(name, version) => {
                 let size = if dry_run {
@@ -221,7 +295,7 @@ pub async fn install_packages(
                 } else {
                     crate::ops::switch::switch_version(name, version, dry_run, &ctx.reporter)
                         .map_err(|e| InstallError::Other(e.to_string()))?;
-                    install_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                    install_count += 1;
                 }
             }
             InstallTask::Download(..) => {}
@@ -237,65 +311,119 @@ pub async fn install_packages(
         .collect();
 
     if !to_download.is_empty() {
-        let mut set: tokio::task::JoinSet<Result<Option<PackageName>, InstallError>> =
-            tokio::task::JoinSet::new();
+        // Implementation Note: Journaled Batches
+        //
+        // Downloads and staging run in parallel, but nothing becomes visible
+        // until every package is staged. Then binaries are linked and the
+        // whole batch is committed in one SQLite transaction. Each filesystem
+        // mutation is written to the journal first, so a crash at any point is
+        // rolled back by the next `StateDb::open`, and a failure here is
+        // rolled back immediately.
+        let journal = if dry_run {
+            None
+        } else {
+            let journal = Journal::begin(&journal_path(), OpKind::Install)
+                .map_err(|e| InstallError::context("Failed to start operation journal", e))?;
+            crash_point("journaled");
+            Some(Arc::new(journal))
+        };
+
+        let mut set: tokio::task::JoinSet<
+            Result<Option<(InstallInfo, InstallReason)>, InstallError>,
+        > = tokio::task::JoinSet::new();
 
         for (name, version) in to_download {
             let ctx = ctx_clone.clone();
-            let install_count = install_count.clone();
+            let journal = journal.clone();
+            let reason = reason_for(&name);
 
             // Implementation Note: Parallel Downloads
             //
             // We use `JoinSet` here to run downloads concurrently. The concurrency limit
             // is implicitly controlled by the reqwest connection pool (set to 20 earlier).
-            // Each task is independent; they don't share state except via the DB (which is locked)
+            // Each task is independent; they don't share state except via the journal
             // and the filesystem (which they write to unique temp dirs).
             set.spawn(async move {
                 let unresolved = UnresolvedPackage::new(name, version);
                 let resolved = unresolved.resolve(ctx.index.as_deref())?;
 
-                if dry_run {
+                let Some(journal) = journal else {
                     ctx.reporter
                         .done(&resolved.name, &resolved.version, "installed", None);
                     return Ok(None);
-                }
+                };
 
                 let prepared = resolved.prepare(&ctx.client, &ctx.reporter).await?;
-                let pkg_name = prepared.resolved.name.clone();
-                let pkg_version = prepared.resolved.version.clone();
-
-                ctx.reporter.installing(&pkg_name, &pkg_version, None, None);
+                ctx.reporter.installing(
+                    &prepared.resolved.name,
+                    &prepared.resolved.version,
+                    None,
+                    None,
+                );
 
                 let installer = get_installer(&prepared);
                 let reporter_arc: Arc<dyn Reporter> = Arc::new(ctx.reporter.clone());
-                let info = installer.install(prepared, reporter_arc).await?;
-
-                let result = commit_installation(&ctx.db, &info).await;
-
-                if result.is_ok() {
-                    ctx.reporter
-                        .done(&pkg_name, &pkg_version, "installed", Some(info.size_bytes));
-                    install_count.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
-                    Ok(Some(pkg_name))
-                } else {
-                    Ok(None)
-                }
+                let info = installer.install(prepared, reporter_arc, journal).await?;
+                Ok(Some((info, reason)))
             });
         }
 
+        let mut staged = Vec::new();
+        let mut failed = 0;
         while let Some(res) = set.join_next().await {
             match res {
-                Ok(Ok(None | Some(_))) => {}
-                Ok(Err(e)) => ctx.reporter.error(&format!("Install failed: {e}")),
-                Err(e) => ctx.reporter.error(&format!("Internal error: {e}")),
+                Ok(Ok(Some(staged_pkg))) => staged.push(staged_pkg),
+                Ok(Ok(None)) => {}
+                Ok(Err(e)) => {
+                    ctx.reporter.error(&format!("Install failed: {e}"));
+                    failed += 1;
+                }
+                Err(e) => {
+                    ctx.reporter.error(&format!("Internal error: {e}"));
+                    failed += 1;
+                }
+            }
+        }
+
+        if let Some(journal) = journal {
+            let journal = Arc::try_unwrap(journal)
+                .map_err(|_| InstallError::Other("Operation journal still in use".to_string()))?;
+            crash_point("staged");
+
+            if failed > 0 {
+                journal
+                    .abort()
+                    .map_err(|e| InstallError::context("Failed to roll back installation", e))?;
+                for (info, _) in &staged {
+                    ctx.reporter
+                        .failed(&info.package.name, &info.package.version, "rolled back");
+                }
+                return Err(InstallError::Other(format!(
+                    "{failed} package(s) failed to install; all changes were rolled back"
+                )));
+            }
+
+            commit_batch(ctx, journal, &mut staged).await?;
+            for (info, _) in &staged {
+                ctx.reporter.done(
+                    &info.package.name,
+                    &info.package.version,
+                    "installed",
+                    Some(info.size_bytes),
+                );
+                install_count += 1;
             }
         }
     }
 
-    let count = install_count.load(std::sync::atomic::Ordering::Relaxed);
-    if count > 0 {
+    if install_count > 0 {
         ctx.reporter
-            .summary(count, "install", start_time.elapsed().as_secs_f64());
+            .summary(install_count, "install", start_time.elapsed().as_secs_f64());
+        if let Some(max_size) = Config::global().cache.max_size {
+            if let Err(e) = crate::store::cache::trim(&crate::cache_path(), max_size.0) {
+                tracing::warn!("Failed to trim download cache: {e}");
+            }
+        }
     } else if already_installed_count > 0 {
         ctx.reporter
             .summary_plain(already_installed_count, "already installed");
@@ -319,9 +447,34 @@ pub async fn install_packages(
 
 struct InstallInfo {
     package: PackageInfo,
+    /// Runtime dependencies, recorded so removal can respect them
+    dependencies: Vec<String>,
     sha256: String,
+    /// Staged store directory whose binaries still need linking
+    store_path: Option<PathBuf>,
+    bins: Vec<String>,
     files_to_record: Vec<(String, String)>,
+    /// Hashes of every file in the store directory, for `apl verify`
+    artifacts: Vec<(String, String)>,
     size_bytes: u64,
+    source: Option<String>,
+}
+
+impl InstallInfo {
+    fn record(&self, reason: InstallReason) -> InstallRecord {
+        InstallRecord {
+            name: self.package.name.to_string(),
+            version: self.package.version.to_string(),
+            sha256: self.sha256.clone(),
+            size_bytes: self.size_bytes,
+            reason,
+            dependencies: self.dependencies.clone(),
+            artifacts: self.artifacts.clone(),
+            active_files: self.files_to_record.clone(),
+            side_by_side: None,
+            source: self.source.clone(),
+        }
+    }
 }
 
 #[async_trait::async_trait]
@@ -330,6 +483,7 @@ trait Installer {
         &self,
         pkg: PreparedPackage,
         reporter: Arc<dyn Reporter>,
+        journal: Arc<Journal>,
     ) -> Result<InstallInfo, InstallError>;
 }
 
@@ -344,22 +498,9 @@ impl Installer for BinInstaller {
         &self,
         pkg: PreparedPackage,
         reporter: Arc<dyn Reporter>,
+        journal: Arc<Journal>,
     ) -> Result<InstallInfo, InstallError> {
-        let sha256_copy = pkg.resolved.artifact.hash().to_string();
-        let (package_def, pkg_store_path, size_bytes) =
-            tokio::task::spawn_blocking(move || install_to_store_only(pkg, reporter))
-                .await
-                .map_err(|e| InstallError::Other(format!("Task panic: {e}")))??;
-
-        let bins = package_def.install.effective_bin(&package_def.package.name);
-        let files_to_record = link_binaries(&bins, &pkg_store_path)?;
-
-        Ok(InstallInfo {
-            package: package_def.package,
-            sha256: sha256_copy,
-            files_to_record,
-            size_bytes,
-        })
+        stage_in_store(pkg, reporter, &journal).await
     }
 }
 
@@ -369,8 +510,9 @@ impl Installer for AppInstaller {
         &self,
         pkg: PreparedPackage,
         _reporter: Arc<dyn Reporter>,
+        journal: Arc<Journal>,
     ) -> Result<InstallInfo, InstallError> {
-        match tokio::task::spawn_blocking(move || perform_app_install(pkg)).await {
+        match tokio::task::spawn_blocking(move || perform_app_install(pkg, &journal)).await {
             Ok(res) => res,
             Err(e) => Err(InstallError::Other(format!("Task panic: {e}"))),
         }
@@ -381,42 +523,241 @@ impl Installer for AppInstaller {
 impl Installer for PkgInstaller {
     async fn install(
         &self,
-        pkg: PreparedPackage,
-        _reporter: Arc<dyn Reporter>,
+        mut pkg: PreparedPackage,
+        reporter: Arc<dyn Reporter>,
+        journal: Arc<Journal>,
     ) -> Result<InstallInfo, InstallError> {
-        match tokio::task::spawn_blocking(move || perform_app_install(pkg)).await {
+        match tokio::task::spawn_blocking(move || {
+            if pkg.extracted_path.is_file()
+                && pkg
+                    .extracted_path
+                    .extension()
+                    .is_some_and(|e| e.eq_ignore_ascii_case("pkg"))
+            {
+                pkg.extracted_path = expand_pkg(&pkg, &reporter)?;
+            }
+            perform_app_install(pkg, &journal)
+        })
+        .await
+        {
             Ok(res) => res,
             Err(e) => Err(InstallError::Other(format!("Task panic: {e}"))),
         }
     }
 }
 
+/// Unpack a downloaded `.pkg` next to it and return the payload root.
+///
+/// Installer scripts are extracted but never executed; we surface them as a
+/// warning so the user knows the vendor expected extra setup.
+fn expand_pkg(
+    pkg: &PreparedPackage,
+    reporter: &Arc<dyn Reporter>,
+) -> Result<PathBuf, InstallError> {
+    let root = pkg.temp_dir.path().join("pkg-root");
+    let (_, meta) = apl_core::io::pkg::extract(
+        &pkg.extracted_path,
+        &root,
+        reporter,
+        &pkg.resolved.name,
+        &pkg.resolved.version,
+    )
+    .map_err(|e| InstallError::Other(e.to_string()))?;
+
+    if meta.has_scripts() {
+        reporter.warning(&format!(
+            "{} ships installer scripts which apl does not run (see {})",
+            pkg.resolved.name,
+            apl_core::io::pkg::metadata_dir(&root).display()
+        ));
+    }
+
+    Ok(root)
+}
+
 #[async_trait::async_trait]
 impl Installer for ScriptInstaller {
     async fn install(
         &self,
         pkg: PreparedPackage,
         reporter: Arc<dyn Reporter>,
+        journal: Arc<Journal>,
     ) -> Result<InstallInfo, InstallError> {
-        let sha256_copy = pkg.resolved.artifact.hash().to_string();
-        let (package_def, pkg_store_path, size_bytes) =
-            tokio::task::spawn_blocking(move || install_to_store_only(pkg, reporter))
-                .await
-                .map_err(|e| InstallError::Other(format!("Task panic: {e}")))??;
-
-        let bins = package_def.install.effective_bin(&package_def.package.name);
-        let files_to_record = link_binaries(&bins, &pkg_store_path)?;
-
-        Ok(InstallInfo {
-            package: package_def.package,
-            sha256: sha256_copy,
-            files_to_record,
-            size_bytes,
+        stage_in_store(pkg, reporter, &journal).await
+    }
+}
+
+/// Moves a prepared package into the store, leaving linking to the commit phase.
+async fn stage_in_store(
+    pkg: PreparedPackage,
+    reporter: Arc<dyn Reporter>,
+    journal: &Journal,
+) -> Result<InstallInfo, InstallError> {
+    let sha256_copy = pkg.resolved.artifact.hash().to_string();
+    let source = pkg.resolved.source.clone();
+    journal.record(&Step::Stage {
+        path: store_path()
+            .join(&pkg.resolved.name)
+            .join(&pkg.resolved.version),
+    })?;
+
+    let (package_def, pkg_store_path, size_bytes, artifacts) =
+        tokio::task::spawn_blocking(move || {
+            let (def, path, size) = install_to_store_only(pkg, reporter)?;
+            let artifacts = hash_tree(&path)?;
+            Ok::<_, InstallError>((def, path, size, artifacts))
         })
+        .await
+        .map_err(|e| InstallError::Other(format!("Task panic: {e}")))??;
+
+    Ok(InstallInfo {
+        bins: package_def.install.effective_bin(&package_def.package.name),
+        package: package_def.package,
+        dependencies: package_def.dependencies.runtime,
+        sha256: sha256_copy,
+        store_path: Some(pkg_store_path),
+        files_to_record: vec![],
+        artifacts,
+        size_bytes,
+        source,
+    })
+}
+
+/// Links every staged package and commits the batch in one transaction.
+///
+/// Any failure before the commit rolls the whole batch back.
+async fn commit_batch(
+    ctx: &Context,
+    journal: Journal,
+    staged: &mut [(InstallInfo, InstallReason)],
+) -> Result<(), InstallError> {
+    let generations = match link_generation(ctx, &journal, staged).await {
+        Ok(generations) => generations,
+        Err(e) => {
+            journal
+                .abort()
+                .map_err(|e| InstallError::context("Failed to roll back installation", e))?;
+            return Err(e);
+        }
+    };
+    crash_point("linked");
+
+    let records = staged
+        .iter()
+        .map(|(info, reason)| info.record(*reason))
+        .collect();
+    if let Err(e) = ctx
+        .db
+        .commit_operation(
+            journal.id().to_string(),
+            OpKind::Install,
+            records,
+            vec![],
+            generations,
+        )
+        .await
+    {
+        journal
+            .abort()
+            .map_err(|e| InstallError::context("Failed to roll back installation", e))?;
+        return Err(InstallError::context(
+            "Failed to record installation in DB",
+            e,
+        ));
     }
+    crash_point("committed");
+
+    // The batch is committed; a leftover journal is cleaned up on next open.
+    if let Err(e) = journal.finish() {
+        ctx.reporter
+            .warning(&format!("Failed to remove operation journal: {e}"));
+    }
+    Ok(())
 }
 
-// Shared link_binaries used from crate::ops
+/// Builds the next profile generation with the staged packages and makes it
+/// current, returning the previous and new generation ids.
+///
+/// A link name already owned by another package goes to whichever has the
+/// higher configured priority; on a tie the whole batch is refused.
+async fn link_generation(
+    ctx: &Context,
+    journal: &Journal,
+    staged: &mut [(InstallInfo, InstallReason)],
+) -> Result<Option<(u64, u64)>, InstallError> {
+    // App bundles are copied to /Applications and have nothing to link.
+    if staged.iter().all(|(info, _)| info.store_path.is_none()) {
+        return Ok(None);
+    }
+
+    let profiles = crate::ops::profiles(&ctx.db).await?;
+    let names: Vec<&str> = staged
+        .iter()
+        .map(|(info, _)| info.package.name.as_str())
+        .collect();
+    let mut next = profiles.stage(&format!("install {}", names.join(" ")))?;
+    let config = Config::global();
+
+    // Links claimed by earlier packages in this batch, which the database
+    // doesn't know about yet.
+    let mut claimed: HashMap<String, String> = HashMap::new();
+    let mut conflicts = Vec::new();
+
+    for (info, reason) in staged.iter_mut() {
+        let Some(store) = &info.store_path else {
+            continue;
+        };
+        let name = info.package.name.to_string();
+        let mut links = Vec::new();
+
+        for (link, target) in config.alias_links(&name, find_binaries(&info.bins, store)) {
+            let owner = match claimed.get(&link) {
+                Some(owner) => Some(owner.clone()),
+                None => ctx
+                    .db
+                    .find_file_owner(bin_path().join(&link).to_string_lossy().to_string())
+                    .await
+                    .map_err(|e| InstallError::context("Failed to look up link owner", e))?,
+            };
+            let Some(owner) = owner.filter(|owner| *owner != name) else {
+                links.push((link, target));
+                continue;
+            };
+
+            match config.priority(&name).cmp(&config.priority(&owner)) {
+                Ordering::Greater => {
+                    ctx.reporter.warning(&format!(
+                        "{name} takes over `{link}` from {owner} (higher priority)"
+                    ));
+                    links.push((link, target));
+                }
+                Ordering::Less => ctx.reporter.info(&format!(
+                    "Not linking `{link}` from {name}: {owner} has a higher priority"
+                )),
+                Ordering::Equal => {
+                    conflicts.push(format!(
+                        "  `{link}` from {name} is already provided by {owner}"
+                    ));
+                }
+            }
+        }
+
+        for (link, _) in &links {
+            claimed.insert(link.clone(), name.clone());
+        }
+        info.files_to_record = link_records(&links);
+        next.add(ProfileEntry::from(&info.record(*reason)), links);
+    }
+
+    if !conflicts.is_empty() {
+        return Err(InstallError::Conflict(conflicts.join("\n")));
+    }
+
+    let previous = profiles.current()?;
+    let generation = next.finish(Some(journal))?;
+    profiles.activate(generation.id, Some(journal))?;
+    Ok(previous.map(|previous| (previous, generation.id)))
+}
 
 fn get_installer(pkg: &PreparedPackage) -> Box<dyn Installer + Send + Sync> {
     let strategy = pkg
@@ -541,7 +882,10 @@ fn perform_source_build(
 
 // pkg is taken by value to keep the TempDir alive during installation.
 #[allow(clippy::needless_pass_by_value)]
-fn perform_app_install(pkg: PreparedPackage) -> Result<InstallInfo, InstallError> {
+fn perform_app_install(
+    pkg: PreparedPackage,
+    journal: &Journal,
+) -> Result<InstallInfo, InstallError> {
     let app_name = pkg.resolved.def.install.app.as_ref().ok_or_else(|| {
         InstallError::Validation("type='app' requires [install] app='Name.app'".to_string())
     })?;
@@ -558,10 +902,25 @@ fn perform_app_install(pkg: PreparedPackage) -> Result<InstallInfo, InstallError
         .to_lowercase()
         .ends_with(".dmg")
     {
-        let mount =
-            dmg::attach(&pkg.extracted_path).map_err(|e| InstallError::Other(e.to_string()))?;
-        let path = mount.path.clone();
-        (Some(mount), path)
+        // Copy the volume out natively; only mount when the image uses
+        // something the reader can't decode.
+        let root = pkg.temp_dir.path().join("dmg-root");
+        match dmg::extract(&pkg.extracted_path, &root) {
+            Ok(()) => (None, root),
+            // A hostile image is refused outright rather than mounted
+            Err(e) if matches!(e.downcast_ref(), Some(ExtractError::Unsafe { .. })) => {
+                let _ = std::fs::remove_dir_all(&root);
+                return Err(InstallError::Other(format!("{e:#}")));
+            }
+            Err(e) => {
+                tracing::debug!("Native DMG extraction failed, mounting instead: {e:#}");
+                let _ = std::fs::remove_dir_all(&root);
+                let mount = dmg::attach(&pkg.extracted_path)
+                    .map_err(|e| InstallError::Other(e.to_string()))?;
+                let path = mount.path.clone();
+                (Some(mount), path)
+            }
+        }
     } else {
         (None, pkg.extracted_path.clone())
     };
@@ -590,9 +949,19 @@ fn perform_app_install(pkg: PreparedPackage) -> Result<InstallInfo, InstallError
     };
 
     let target_app = applications_dir.join(app_name);
-    if target_app.exists() {
-        std::fs::remove_dir_all(&target_app).map_err(InstallError::Io)?;
+    // The old bundle is moved aside rather than deleted so a failed batch
+    // can put it back; the journal deletes it once the batch commits.
+    if std::fs::symlink_metadata(&target_app).is_ok() {
+        let backup = applications_dir.join(format!(".{app_name}.{}.old", journal.id()));
+        journal.record(&Step::Backup {
+            path: target_app.clone(),
+            backup: backup.clone(),
+        })?;
+        std::fs::rename(&target_app, &backup).map_err(InstallError::Io)?;
     }
+    journal.record(&Step::Stage {
+        path: target_app.clone(),
+    })?;
 
     if std::fs::rename(&extracted_app, &target_app).is_err() {
         apl_core::builder::copy_dir_all(&extracted_app, &target_app)
@@ -612,38 +981,20 @@ fn perform_app_install(pkg: PreparedPackage) -> Result<InstallInfo, InstallError
     // allows them to run (otherwise it complains they are from an unidentified developer).
     Ok(InstallInfo {
         package: pkg.resolved.def.package.clone(),
+        dependencies: pkg.resolved.def.dependencies.runtime.clone(),
         sha256: pkg.resolved.artifact.hash().to_string(),
+        store_path: None,
+        bins: vec![],
         files_to_record: vec![(
             target_app.to_string_lossy().to_string(),
             "APP_BUNDLE".to_string(),
         )],
+        artifacts: vec![],
         size_bytes: 0,
+        source: pkg.resolved.source.clone(),
     })
 }
 
-async fn commit_installation(db: &DbHandle, info: &InstallInfo) -> Result<(), InstallError> {
-    db.install_complete_package(
-        info.package.name.to_string(),
-        info.package.version.to_string(),
-        info.sha256.clone(),
-        info.size_bytes,
-        vec![],
-        info.files_to_record.clone(),
-    )
-    .await
-    .map_err(|e| InstallError::context("Failed to record installation in DB", e))?;
-
-    db.add_history(
-        info.package.name.to_string(),
-        "install".to_string(),
-        None,
-        Some(info.package.version.to_string()),
-        true,
-    )
-    .await
-    .map_err(|e| InstallError::context("Failed to add history entry", e))
-}
-
 pub fn perform_ux_checks(names: &[PackageName], reporter: &impl Reporter) {
     let path_env = std::env::var_os("PATH").unwrap_or_default();
     let bin_dir = bin_path();
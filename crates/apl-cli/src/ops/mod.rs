@@ -1,23 +1,28 @@
+pub mod apply;
 pub mod context;
 pub mod error;
 pub mod flow;
 pub mod install;
+pub mod pin;
 pub mod remove;
 pub mod resolve;
 pub mod switch;
+pub mod verify;
 
 pub use context::Context;
 pub use error::InstallError;
 
+use crate::DbHandle;
 use crate::bin_path;
-use std::path::Path;
+use crate::store::db::StateDb;
+use crate::store::profile::Profiles;
+use std::path::{Path, PathBuf};
 
-/// Shared utility to link binaries from a package store path to the global bin directory.
-pub fn link_binaries(
-    bin_list: &[String],
-    pkg_store_path: &Path,
-) -> Result<Vec<(String, String)>, InstallError> {
-    let mut files_to_record = Vec::new();
+/// Finds the binaries a package exposes, as (link name, path in the store) pairs.
+///
+/// The links themselves are placed when the next profile generation is
+/// written (see [`crate::store::profile`]).
+pub fn find_binaries(bin_list: &[String], pkg_store_path: &Path) -> Vec<(String, PathBuf)> {
     let mut bins_to_link = Vec::new();
 
     if bin_list.is_empty() {
@@ -55,6 +60,7 @@ pub fn link_binaries(
         }
     }
 
+    let mut links = Vec::new();
     for (src_rel, target_name) in bins_to_link {
         let src_path = pkg_store_path.join(&src_rel);
         if !src_path.exists() || src_path.is_dir() {
@@ -63,35 +69,67 @@ pub fn link_binaries(
             let nested = src_path.join(&src_rel);
             if nested.exists() && nested.is_file() {
                 // Use the nested one instead
-                link_single_bin(&nested, &target_name)?;
-                files_to_record.push((
-                    bin_path().join(target_name).to_string_lossy().to_string(),
-                    "SYMLINK".to_string(),
-                ));
+                links.push((target_name, nested));
             }
             continue;
         }
-
-        link_single_bin(&src_path, &target_name)?;
-        files_to_record.push((
-            bin_path().join(target_name).to_string_lossy().to_string(),
-            "SYMLINK".to_string(),
-        ));
+        links.push((target_name, src_path));
     }
 
-    Ok(files_to_record)
+    links
 }
 
-fn link_single_bin(src_path: &Path, target_name: &str) -> Result<(), InstallError> {
-    let target = bin_path().join(target_name);
-    if let Some(parent) = target.parent() {
-        std::fs::create_dir_all(parent).ok();
-    }
-    if target.exists() || target.is_symlink() {
-        std::fs::remove_file(&target).ok();
+/// Finds the binaries of a package already in the store, using the bin list
+/// recorded in its `.apl-meta.json` when it was installed.
+pub fn stored_binaries(pkg_store_path: &Path) -> Vec<(String, PathBuf)> {
+    let bin_list: Vec<String> = std::fs::read_to_string(pkg_store_path.join(".apl-meta.json"))
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .and_then(|meta| {
+            meta.get("bin")?.as_array().map(|bins| {
+                bins.iter()
+                    .filter_map(|b| b.as_str().map(std::string::ToString::to_string))
+                    .collect()
+            })
+        })
+        .unwrap_or_default();
+    find_binaries(&bin_list, pkg_store_path)
+}
+
+/// Database file records for a package's links in `~/.apl/bin`.
+pub fn link_records(links: &[(String, PathBuf)]) -> Vec<(String, String)> {
+    links
+        .iter()
+        .map(|(name, _)| {
+            (
+                bin_path().join(name).to_string_lossy().to_string(),
+                "SYMLINK".to_string(),
+            )
+        })
+        .collect()
+}
+
+/// Opens the profile generations, converting a plain `~/.apl/bin` first.
+pub async fn profiles(db: &DbHandle) -> Result<Profiles, InstallError> {
+    if let Some(profiles) = Profiles::open()? {
+        return Ok(profiles);
     }
+    let installed = db
+        .install_records()
+        .await
+        .map_err(|e| InstallError::context("Failed to read installed packages", e))?;
+    Profiles::init(&installed)
+        .map_err(|e| InstallError::context("Failed to create the first profile generation", e))
+}
 
-    #[cfg(unix)]
-    std::os::unix::fs::symlink(src_path, &target).map_err(InstallError::Io)?;
-    Ok(())
+/// Blocking variant of [`profiles`] for callers holding a [`StateDb`].
+pub fn profiles_blocking(db: &StateDb) -> Result<Profiles, InstallError> {
+    if let Some(profiles) = Profiles::open()? {
+        return Ok(profiles);
+    }
+    let installed = db
+        .install_records()
+        .map_err(|e| InstallError::context("Failed to read installed packages", e))?;
+    Profiles::init(&installed)
+        .map_err(|e| InstallError::context("Failed to create the first profile generation", e))
 }
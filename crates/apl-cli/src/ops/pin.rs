@@ -0,0 +1,85 @@
+//! Pinning and unpinning packages
+//!
+//! Shared by `apl pin`/`apl unpin` and the browser.
+
+use crate::ops::InstallError;
+use crate::store::db::StateDb;
+use crate::store::pin::Pin;
+use crate::ui::Reporter;
+
+/// Pins an installed package to its version, or lets it float within
+/// `requirement` (e.g. `1.5.x`).
+pub fn pin<R: Reporter>(
+    db: &StateDb,
+    name: &str,
+    requirement: Option<&str>,
+    dry_run: bool,
+    reporter: &R,
+) -> Result<(), InstallError> {
+    if let Some(requirement) = requirement {
+        Pin::validate(requirement).map_err(|e| {
+            InstallError::Other(format!("Invalid version requirement '{requirement}': {e}"))
+        })?;
+    }
+
+    let package = db
+        .get_package(name)
+        .map_err(|e| InstallError::context("Failed to query package in DB", e))?
+        .ok_or_else(|| InstallError::Other(format!("Package '{name}' is not installed.")))?;
+    let described = requirement.map_or_else(
+        || format!("{name} at {}", package.version),
+        |r| format!("{name} to {r}"),
+    );
+    if dry_run {
+        reporter.info(&format!("(dry run) Would pin {described}"));
+        return Ok(());
+    }
+
+    db.set_pin(name, requirement)
+        .map_err(|e| InstallError::context("Failed to save pin", e))?;
+    reporter.success(&format!("Pinned {described}"));
+
+    if let Some(requirement) = requirement {
+        let pin = Pin {
+            package: name.to_string(),
+            requirement: Some(requirement.to_string()),
+            pinned_at: 0,
+        };
+        if !pin.allows(&package.version) {
+            reporter.warning(&format!(
+                "Installed version {} is outside {requirement}; install a matching one with `apl install {name}@<version>`",
+                package.version
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Removes the pins of `packages`, changing nothing if any isn't pinned.
+pub fn unpin<R: Reporter>(
+    db: &StateDb,
+    packages: &[String],
+    dry_run: bool,
+    reporter: &R,
+) -> Result<(), InstallError> {
+    for name in packages {
+        let pin = db
+            .get_pin(name)
+            .map_err(|e| InstallError::context("Failed to query pin in DB", e))?;
+        if pin.is_none() {
+            return Err(InstallError::Other(format!(
+                "Package '{name}' is not pinned."
+            )));
+        }
+    }
+    for name in packages {
+        if dry_run {
+            reporter.info(&format!("(dry run) Would unpin {name}"));
+        } else {
+            db.remove_pin(name)
+                .map_err(|e| InstallError::context("Failed to remove pin", e))?;
+            reporter.success(&format!("Unpinned {name}"));
+        }
+    }
+    Ok(())
+}
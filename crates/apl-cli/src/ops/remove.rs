@@ -1,13 +1,18 @@
 use futures::future::join_all;
+use std::collections::HashSet;
 use std::path::PathBuf;
 use std::time::Instant;
 
-use crate::DbHandle;
+use crate::db::InstalledFile;
 use crate::ops::InstallError;
+use crate::store::journal::{Journal, OpKind, Step, crash_point};
 use crate::ui::Reporter;
+use crate::{DbHandle, journal_path};
 use apl_schema::types::{PackageName, Version};
 
 /// Resolves and removes packages, deleting active files and updating the database.
+///
+/// The database changes for the whole batch are committed in one transaction.
 pub async fn remove_packages<R: Reporter + Clone + 'static>(
     reporter: &R,
     packages: &[String],
@@ -36,7 +41,8 @@ pub async fn remove_packages<R: Reporter + Clone + 'static>(
     reporter.prepare_pipeline(&task_list);
 
     let mut remove_count = 0;
-    let mut handles = Vec::new();
+    let mut planned = Vec::new();
+    let batch: HashSet<String> = task_list.iter().map(|(n, ..)| n.to_string()).collect();
 
     for (pkg, version_opt, _) in task_list {
         if version_opt.is_none() {
@@ -45,6 +51,26 @@ pub async fn remove_packages<R: Reporter + Clone + 'static>(
         }
         let version = version_opt.unwrap();
 
+        // Packages removed in the same batch don't count as dependents
+        let dependents: Vec<String> = db
+            .get_dependents(pkg.to_string())
+            .await
+            .map_err(|e| InstallError::context("Failed to query dependents from DB", e))?
+            .into_iter()
+            .filter(|d| !batch.contains(d))
+            .collect();
+
+        if !dependents.is_empty() {
+            let required_by = dependents.join(", ");
+            if !force {
+                reporter.failed(&pkg, &version, &format!("required by {required_by}"));
+                continue;
+            }
+            reporter.warning(&format!(
+                "Removing {pkg} even though {required_by} depends on it"
+            ));
+        }
+
         // Get files for this package
         let files = db
             .get_package_files(pkg.to_string())
@@ -62,16 +88,65 @@ pub async fn remove_packages<R: Reporter + Clone + 'static>(
         }
 
         reporter.removing(&pkg, &version);
+        planned.push((pkg, version, files));
+    }
+
+    // Implementation Note: Journaled Removal
+    //
+    // Every file and database record about to go is journaled before anything
+    // is deleted. Deletions can't be undone, so an interrupted removal is
+    // rolled forward by the next `StateDb::open`; the database side is a
+    // single transaction for the whole batch.
+    //
+    // Links in `~/.apl/bin` are never deleted in place: the removed packages
+    // are left out of a new profile generation instead. Switching to it is
+    // journaled last, so a crash while the generation is being written rolls
+    // the whole removal back.
+    let mut generations = None;
+    let journal = if dry_run || planned.is_empty() {
+        None
+    } else {
+        let journal = Journal::begin(&journal_path(), OpKind::Remove)
+            .map_err(|e| InstallError::context("Failed to start operation journal", e))?;
+        for (pkg, _, files) in &planned {
+            journal.record(&Step::Forget {
+                package: pkg.to_string(),
+            })?;
+            for file in files.iter().filter(|f| !is_profile_link(f)) {
+                journal.record(&Step::Unlink {
+                    path: PathBuf::from(&file.path),
+                })?;
+            }
+        }
 
-        let files_to_delete = files;
-        let pkg_name = pkg;
-        let version_final = version;
+        let profiles = crate::ops::profiles(&db).await?;
+        let names: Vec<&str> = planned.iter().map(|(pkg, ..)| pkg.as_str()).collect();
+        let mut next = profiles.stage(&format!("remove {}", names.join(" ")))?;
+        let mut changed = false;
+        for name in &names {
+            changed |= next.remove(name).is_some();
+        }
+        if changed {
+            let previous = profiles.current()?;
+            let generation = next.finish(Some(&journal))?;
+            profiles.activate(generation.id, Some(&journal))?;
+            generations = previous.map(|previous| (previous, generation.id));
+        }
+        crash_point("journaled");
+        Some(journal)
+    };
+
+    let mut handles = Vec::new();
+    for (pkg_name, version_final, files_to_delete) in planned {
         let reporter_clone = reporter.clone();
 
         handles.push(tokio::spawn(async move {
             let mut success = true;
             if !dry_run {
                 for file_record in files_to_delete {
+                    if is_profile_link(&file_record) {
+                        continue;
+                    }
                     let path = PathBuf::from(&file_record.path);
                     if path.exists() {
                         let is_app_bundle = file_record.sha256 == "APP_BUNDLE";
@@ -98,21 +173,32 @@ pub async fn remove_packages<R: Reporter + Clone + 'static>(
         }));
     }
 
-    let results = join_all(handles).await;
-
-    for (name, version) in results.into_iter().flatten().flatten() {
-        if !dry_run {
-            let _ = db.remove_package(name.to_string()).await;
-            let _ = db
-                .add_history(
-                    name.to_string(),
-                    "remove".to_string(),
-                    Some(version.to_string()),
-                    None,
-                    true,
-                )
-                .await;
+    let removed: Vec<(PackageName, Version)> = join_all(handles)
+        .await
+        .into_iter()
+        .flatten()
+        .flatten()
+        .collect();
+
+    if let Some(journal) = journal {
+        crash_point("unlinked");
+        let names = removed.iter().map(|(name, _)| name.to_string()).collect();
+        db.commit_operation(
+            journal.id().to_string(),
+            OpKind::Remove,
+            vec![],
+            names,
+            generations,
+        )
+        .await
+        .map_err(|e| InstallError::context("Failed to update database", e))?;
+        crash_point("committed");
+        if let Err(e) = journal.finish() {
+            reporter.warning(&format!("Failed to remove operation journal: {e}"));
         }
+    }
+
+    for (name, version) in removed {
         reporter.done(
             &name,
             &version,
@@ -132,3 +218,9 @@ pub async fn remove_packages<R: Reporter + Clone + 'static>(
 
     Ok(())
 }
+
+/// Links in `~/.apl/bin` belong to profile generations and are dropped by
+/// switching generations rather than deleted.
+fn is_profile_link(file: &InstalledFile) -> bool {
+    file.sha256 == "SYMLINK"
+}
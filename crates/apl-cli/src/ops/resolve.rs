@@ -160,6 +160,7 @@ mod tests {
                 })
                 .collect(),
             tags: vec![],
+            license: String::new(),
         }
     }
 
@@ -225,7 +226,7 @@ mod tests {
         // Create index with same version
         let mut index = PackageIndex::default();
         let entry = make_entry("node", vec!["20.12.0"]);
-        index.packages.push(entry);
+        index.upsert(entry);
 
         // Create manifest requesting same version
         let manifest = Manifest {
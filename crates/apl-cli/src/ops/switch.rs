@@ -1,9 +1,13 @@
-use crate::db::StateDb;
+use crate::config::Config;
+use crate::db::{InstallRecord, Package, StateDb};
+use crate::store::journal::{Journal, OpKind};
+use crate::store::profile::{ProfileBuilder, ProfileEntry};
 use crate::ui::Reporter;
-use crate::{ops::InstallError, store_path};
+use crate::{journal_path, ops::InstallError, store_path};
 use apl_schema::types::{PackageName, Version};
 
-/// Updates symlinks and database state to point to a different installed version.
+/// Publishes a different installed version in a new profile generation and
+/// marks it active in the database.
 pub fn switch_version<R: Reporter>(
     name: &PackageName,
     version: &Version,
@@ -29,44 +33,7 @@ pub fn switch_version<R: Reporter>(
             return Ok(());
         }
 
-        let store_dir = store_path().join(&p.name).join(&p.version);
-        if !store_dir.exists() {
-            return Err(InstallError::Validation(format!(
-                "Package artifacts missing at {}",
-                store_dir.display()
-            )));
-        }
-
-        let mut bin_list = Vec::new();
-        let meta_path = store_dir.join(".apl-meta.json");
-        if meta_path.exists() {
-            if let Ok(content) = std::fs::read_to_string(&meta_path) {
-                if let Ok(meta) = serde_json::from_str::<serde_json::Value>(&content) {
-                    if let Some(bins) = meta.get("bin").and_then(|b| b.as_array()) {
-                        bin_list = bins
-                            .iter()
-                            .filter_map(|b| b.as_str().map(std::string::ToString::to_string))
-                            .collect();
-                    }
-                }
-            }
-        }
-
-        let files_to_record = crate::ops::link_binaries(&bin_list, &store_dir)?;
-
-        // Persistence: record active version AND active files
-        db.install_complete_package(
-            &p.name,
-            &p.version,
-            &p.sha256,
-            p.size_bytes,
-            &[], // No artifacts table update needed (already there)
-            &files_to_record,
-        )
-        .map_err(|e| InstallError::context("Failed to update database records", e))?;
-
-        db.add_history(&p.name, "switch", None, Some(&p.version), true)
-            .map_err(|e| InstallError::context("Failed to record history entry", e))?;
+        publish_version(&db, &p, "switch", reporter)?;
 
         reporter.done(
             &PackageName::new(&p.name),
@@ -75,23 +42,234 @@ pub fn switch_version<R: Reporter>(
             None,
         );
     } else {
-        let versions = db
-            .list_package_versions(name.as_str())
-            .map_err(|e| InstallError::Io(std::io::Error::other(e)))?;
-        if versions.is_empty() {
-            return Err(InstallError::Validation(format!(
-                "Package '{name}' is not installed."
-            )));
-        }
-        let available = versions
-            .iter()
-            .map(|v| v.version.as_str())
-            .collect::<Vec<_>>()
-            .join(", ");
+        return Err(not_installed(&db, name, version));
+    }
+
+    Ok(())
+}
+
+/// Links an installed version under versioned names (e.g. `terraform-1.5`)
+/// next to the active version's links, in a new profile generation.
+pub fn link_side_by_side<R: Reporter>(
+    name: &PackageName,
+    version: &Version,
+    dry_run: bool,
+    reporter: &R,
+) -> Result<(), InstallError> {
+    let db = StateDb::open().map_err(|e| InstallError::context("Failed to open database", e))?;
+    let p = db
+        .get_package_version(name.as_str(), version.as_str())
+        .map_err(|e| InstallError::context("Failed to query package version in DB", e))?
+        .ok_or_else(|| not_installed(&db, name, version))?;
+
+    let store_dir = store_path().join(&p.name).join(&p.version);
+    if !store_dir.exists() {
         return Err(InstallError::Validation(format!(
-            "Version '{version}' of '{name}' is not installed.\nInstalled versions: {available}"
+            "Package artifacts missing at {}",
+            store_dir.display()
         )));
     }
+    let config = Config::global();
+    let links = config.versioned_links(
+        &p.name,
+        &p.version,
+        config.alias_links(&p.name, crate::ops::stored_binaries(&store_dir)),
+    );
+    if links.is_empty() {
+        return Err(InstallError::Validation(format!(
+            "{name} {version} has no binaries to link"
+        )));
+    }
+    let names = links
+        .iter()
+        .map(|(link, _)| link.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    if dry_run {
+        reporter.info(&format!("(dry run) Would link {name} {version} as {names}"));
+        return Ok(());
+    }
+
+    update_side_by_side(
+        &db,
+        &p.name,
+        &format!("link {name}@{version}"),
+        reporter,
+        |next| {
+            next.add_side_by_side(&p.name, &p.version, links)
+                .map_err(|e| match e.kind() {
+                    std::io::ErrorKind::AlreadyExists => InstallError::Conflict(format!("  {e}")),
+                    _ => e.into(),
+                })
+        },
+    )?;
+    reporter.success(&format!("Linked {name} {version} as {names}"));
+    Ok(())
+}
 
+/// Removes the versioned links of a side-by-side version.
+pub fn unlink_side_by_side<R: Reporter>(
+    name: &PackageName,
+    version: &Version,
+    dry_run: bool,
+    reporter: &R,
+) -> Result<(), InstallError> {
+    let db = StateDb::open().map_err(|e| InstallError::context("Failed to open database", e))?;
+    let profiles = crate::ops::profiles_blocking(&db)?;
+    let linked = match profiles.current()? {
+        Some(id) => profiles.load(id)?.package(name.as_str()).is_some_and(|p| {
+            p.side_by_side
+                .iter()
+                .any(|side| side.version == version.as_str())
+        }),
+        None => false,
+    };
+    if !linked {
+        return Err(InstallError::Validation(format!(
+            "{name} {version} is not linked side-by-side"
+        )));
+    }
+
+    if dry_run {
+        reporter.info(&format!("(dry run) Would unlink {name} {version}"));
+        return Ok(());
+    }
+
+    update_side_by_side(
+        &db,
+        name.as_str(),
+        &format!("unlink {name}@{version}"),
+        reporter,
+        |next| {
+            next.remove_side_by_side(name.as_str(), version.as_str());
+            Ok(())
+        },
+    )?;
+    reporter.success(&format!("Unlinked {name} {version}"));
+    Ok(())
+}
+
+/// Publishes a generation with `name`'s side-by-side versions changed by
+/// `change`, and records its links, as one journaled operation.
+fn update_side_by_side<R: Reporter>(
+    db: &StateDb,
+    name: &str,
+    description: &str,
+    reporter: &R,
+    change: impl FnOnce(&mut ProfileBuilder) -> Result<(), InstallError>,
+) -> Result<(), InstallError> {
+    let journal = Journal::begin(&journal_path(), OpKind::Switch)
+        .map_err(|e| InstallError::context("Failed to start operation journal", e))?;
+    let profiles = crate::ops::profiles_blocking(db)?;
+
+    let published = (|| {
+        let mut next = profiles.stage(description)?;
+        change(&mut next)?;
+        let record = next
+            .package(name)
+            .ok_or_else(|| InstallError::Validation(format!("{name} is not linked")))?
+            .record(profiles.bin_dir());
+        let previous = profiles.current()?;
+        let generation = next.finish(Some(&journal))?;
+        profiles.activate(generation.id, Some(&journal))?;
+        let generations = previous.map(|previous| (previous, generation.id));
+        db.commit_operation(journal.id(), OpKind::Switch, &[record], &[], generations)
+            .map_err(|e| InstallError::context("Failed to update database records", e))
+    })();
+    if let Err(e) = published {
+        journal
+            .abort()
+            .map_err(|e| InstallError::context("Failed to roll back switch", e))?;
+        return Err(e);
+    }
+    if let Err(e) = journal.finish() {
+        reporter.warning(&format!("Failed to remove operation journal: {e}"));
+    }
+    Ok(())
+}
+
+fn not_installed(db: &StateDb, name: &PackageName, version: &Version) -> InstallError {
+    let versions = match db.list_package_versions(name.as_str()) {
+        Ok(versions) => versions,
+        Err(e) => return InstallError::Io(std::io::Error::other(e)),
+    };
+    if versions.is_empty() {
+        return InstallError::Validation(format!("Package '{name}' is not installed."));
+    }
+    let available = versions
+        .iter()
+        .map(|v| v.version.as_str())
+        .collect::<Vec<_>>()
+        .join(", ");
+    InstallError::Validation(format!(
+        "Version '{version}' of '{name}' is not installed.\nInstalled versions: {available}"
+    ))
+}
+
+/// Publishes an installed version's binaries in a new profile generation and
+/// marks it active in the database, as one journaled operation.
+///
+/// `action` describes the generation, e.g. "switch jq@1.7".
+pub fn publish_version<R: Reporter>(
+    db: &StateDb,
+    p: &Package,
+    action: &str,
+    reporter: &R,
+) -> Result<(), InstallError> {
+    let store_dir = store_path().join(&p.name).join(&p.version);
+    if !store_dir.exists() {
+        return Err(InstallError::Validation(format!(
+            "Package artifacts missing at {}",
+            store_dir.display()
+        )));
+    }
+
+    // Implementation Note: Journaled Switch
+    //
+    // The new version is published as a fresh profile generation and the
+    // database is updated in one transaction. A crash in between is
+    // rolled back to the previous generation by the next `StateDb::open`.
+    let journal = Journal::begin(&journal_path(), OpKind::Switch)
+        .map_err(|e| InstallError::context("Failed to start operation journal", e))?;
+    let profiles = crate::ops::profiles_blocking(db)?;
+    let config = Config::global();
+    let links = config.alias_links(&p.name, crate::ops::stored_binaries(&store_dir));
+    let record = InstallRecord {
+        name: p.name.clone(),
+        version: p.version.clone(),
+        sha256: p.sha256.clone(),
+        size_bytes: p.size_bytes,
+        reason: p.reason,
+        dependencies: vec![], // Recorded when this version was installed
+        artifacts: vec![],    // No artifacts table update needed (already there)
+        active_files: crate::ops::link_records(&links),
+        side_by_side: None,
+        source: p.source.clone(),
+    };
+
+    let published = (|| {
+        let mut next = profiles.stage(&format!("{action} {}@{}", p.name, p.version))?;
+        let mut entry = ProfileEntry::from(&record);
+        entry.dependencies = db
+            .get_version_dependencies(&p.name, &p.version)
+            .map_err(|e| InstallError::context("Failed to read dependencies", e))?;
+        next.add(entry, links);
+        let previous = profiles.current()?;
+        let generation = next.finish(Some(&journal))?;
+        profiles.activate(generation.id, Some(&journal))?;
+        let generations = previous.map(|previous| (previous, generation.id));
+        db.commit_operation(journal.id(), OpKind::Switch, &[record], &[], generations)
+            .map_err(|e| InstallError::context("Failed to update database records", e))
+    })();
+    if let Err(e) = published {
+        journal
+            .abort()
+            .map_err(|e| InstallError::context("Failed to roll back switch", e))?;
+        return Err(e);
+    }
+    if let Err(e) = journal.finish() {
+        reporter.warning(&format!("Failed to remove operation journal: {e}"));
+    }
     Ok(())
 }
@@ -0,0 +1,232 @@
+//! Integrity checks for installed packages.
+//!
+//! Every file of a package's store directory is hashed when it is installed
+//! and recorded in the `artifacts` table. `apl verify` re-hashes the store
+//! against those records and checks that the package's links in `~/.apl/bin`
+//! still point into its store directory.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use sha2::{Digest, Sha256};
+
+use crate::db::{Package, StateDb};
+use crate::ops::InstallError;
+use crate::ops::flow::UnresolvedPackage;
+use crate::store_path;
+use crate::ui::Reporter;
+use apl_schema::index::PackageIndex;
+use apl_schema::types::{PackageName, Version};
+
+/// Something wrong with an installed package.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Problem {
+    /// The whole store directory is gone
+    MissingStore,
+    /// The package was installed before file hashes were recorded
+    Unrecorded,
+    /// A recorded file is missing
+    Missing(String),
+    /// A file's content differs from what was installed
+    Modified(String),
+    /// A file that was not part of the package
+    Extra(String),
+    /// A link in `~/.apl/bin` is missing or points outside the package
+    Link {
+        path: PathBuf,
+        target: Option<PathBuf>,
+    },
+}
+
+impl Problem {
+    /// Whether re-extracting the package can fix this.
+    pub fn is_store(&self) -> bool {
+        !matches!(self, Self::Link { .. })
+    }
+}
+
+impl fmt::Display for Problem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingStore => write!(f, "store directory is missing"),
+            Self::Unrecorded => write!(f, "no file hashes recorded (installed by an older apl)"),
+            Self::Missing(path) => write!(f, "missing   {path}"),
+            Self::Modified(path) => write!(f, "modified  {path}"),
+            Self::Extra(path) => write!(f, "extra     {path}"),
+            Self::Link {
+                path,
+                target: Some(target),
+            } => write!(f, "link      {} -> {}", path.display(), target.display()),
+            Self::Link { path, target: None } => {
+                write!(f, "link      {} is missing", path.display())
+            }
+        }
+    }
+}
+
+/// Checks one installed package version against its recorded hashes and, if
+/// it is the active version, the package's links (side-by-side ones included).
+pub fn check(db: &StateDb, package: &Package) -> Result<Vec<Problem>, InstallError> {
+    let store_dir = store_path().join(&package.name).join(&package.version);
+    let mut problems = Vec::new();
+
+    if store_dir.is_dir() {
+        let recorded = db
+            .get_artifacts(&package.name, &package.version)
+            .map_err(|e| InstallError::context("Failed to read recorded hashes", e))?;
+        // Rows migrated from the first schema hold absolute paths and no
+        // real hashes.
+        if recorded.is_empty() || recorded.iter().any(|a| Path::new(&a.path).is_absolute()) {
+            problems.push(Problem::Unrecorded);
+        } else {
+            let mut actual: BTreeMap<String, String> = hash_tree(&store_dir)?.into_iter().collect();
+            for artifact in recorded {
+                match actual.remove(&artifact.path) {
+                    None => problems.push(Problem::Missing(artifact.path)),
+                    Some(hash) if hash != artifact.sha256 => {
+                        problems.push(Problem::Modified(artifact.path));
+                    }
+                    Some(_) => {}
+                }
+            }
+            problems.extend(actual.into_keys().map(Problem::Extra));
+        }
+    } else {
+        problems.push(Problem::MissingStore);
+    }
+
+    if package.active {
+        let files = db
+            .get_package_files(&package.name)
+            .map_err(|e| InstallError::context("Failed to read package files", e))?;
+        for file in files.into_iter().filter(|f| f.sha256 == "SYMLINK") {
+            // Side-by-side links point at their own version.
+            let expected = match &file.version {
+                Some(version) => store_path().join(&package.name).join(version),
+                None => store_dir.clone(),
+            };
+            let path = PathBuf::from(file.path);
+            let target = std::fs::read_link(&path).ok();
+            let valid = target
+                .as_ref()
+                .is_some_and(|t| t.starts_with(&expected) && path.exists());
+            if !valid {
+                problems.push(Problem::Link { path, target });
+            }
+        }
+    }
+
+    Ok(problems)
+}
+
+/// Re-extracts a package from its cached (or freshly downloaded) artifact,
+/// records new hashes and republishes its links if they are broken.
+///
+/// The store directory is replaced in place, so an interrupted repair leaves
+/// the package damaged but still repairable.
+pub async fn repair<R: Reporter + Clone + 'static>(
+    db: &StateDb,
+    package: &Package,
+    problems: &[Problem],
+    index: Option<&PackageIndex>,
+    client: &reqwest::Client,
+    reporter: &R,
+) -> Result<(), InstallError> {
+    let name = PackageName::new(&package.name);
+    let version = Version::from(package.version.as_str());
+
+    if problems.iter().any(Problem::is_store) {
+        let resolved =
+            UnresolvedPackage::new(name.clone(), Some(version.clone())).resolve(index)?;
+        if resolved.artifact.hash() != package.sha256 {
+            reporter.warning(&format!(
+                "The index now has a different artifact for {name} {version} than was installed"
+            ));
+        }
+        let prepared = resolved.prepare(client, reporter).await?;
+
+        let reporter_arc: Arc<dyn Reporter> = Arc::new(reporter.clone());
+        let store_dir = tokio::task::spawn_blocking(move || {
+            crate::ops::install::install_to_store_only(prepared, reporter_arc)
+        })
+        .await
+        .map_err(|e| InstallError::Other(format!("Task panic: {e}")))??
+        .1;
+        db.replace_artifacts(&package.name, &package.version, &hash_tree(&store_dir)?)
+            .map_err(|e| InstallError::context("Failed to record file hashes", e))?;
+    }
+
+    if package.active && check(db, package)?.iter().any(|p| !p.is_store()) {
+        crate::ops::switch::publish_version(db, package, "repair", reporter)?;
+    }
+
+    Ok(())
+}
+
+/// Hashes every file and symlink under `dir`, as (relative path, sha256) pairs.
+///
+/// A symlink is hashed by its target path, so retargeting it counts as a change.
+pub fn hash_tree(dir: &Path) -> io::Result<Vec<(String, String)>> {
+    let mut hashes = Vec::new();
+    for entry in walkdir::WalkDir::new(dir).min_depth(1).sort_by_file_name() {
+        let entry = entry.map_err(io::Error::other)?;
+        let file_type = entry.file_type();
+        let hash = if file_type.is_symlink() {
+            let target = std::fs::read_link(entry.path())?;
+            hex::encode(Sha256::digest(target.as_os_str().as_encoded_bytes()))
+        } else if file_type.is_file() {
+            hash_file(entry.path())?
+        } else {
+            continue;
+        };
+        let relative = entry
+            .path()
+            .strip_prefix(dir)
+            .map_err(io::Error::other)?
+            .to_string_lossy()
+            .to_string();
+        hashes.push((relative, hash));
+    }
+    Ok(hashes)
+}
+
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut file = std::fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 65536];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(hex::encode(hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_hash_tree() {
+        let dir = tempdir().unwrap();
+        std::fs::create_dir_all(dir.path().join("bin")).unwrap();
+        std::fs::write(dir.path().join("bin/jq"), "jq").unwrap();
+        std::os::unix::fs::symlink("jq", dir.path().join("bin/jq2")).unwrap();
+
+        let hashes = hash_tree(dir.path()).unwrap();
+        let paths: Vec<&str> = hashes.iter().map(|(p, _)| p.as_str()).collect();
+        assert_eq!(paths, vec!["bin/jq", "bin/jq2"]);
+        assert_eq!(hashes[0].1, hex::encode(Sha256::digest(b"jq")));
+
+        // Retargeting the symlink changes its hash.
+        std::fs::remove_file(dir.path().join("bin/jq2")).unwrap();
+        std::os::unix::fs::symlink("jq3", dir.path().join("bin/jq2")).unwrap();
+        assert_ne!(hash_tree(dir.path()).unwrap()[1].1, hashes[1].1);
+    }
+}
@@ -10,7 +10,8 @@ use std::sync::mpsc;
 use std::thread;
 use tokio::sync::oneshot;
 
-use super::db::{DbError, InstalledFile, Package, StateDb};
+use super::db::{DbError, InstallReason, InstallRecord, InstalledFile, Package, StateDb};
+use super::journal::OpKind;
 
 /// Events that can be sent to the DB actor
 pub enum DbEvent {
@@ -44,16 +45,35 @@ pub enum DbEvent {
         success: bool,
         resp: oneshot::Sender<Result<(), DbError>>,
     },
-    /// Record a complete package installation
-    InstallComplete {
+    /// Get installed packages that depend on a package
+    GetDependents {
         name: String,
-        version: String,
-        sha256: String,
-        size_bytes: u64,
-        artifacts: Vec<(String, String)>,
-        active_files: Vec<(String, String)>,
+        resp: oneshot::Sender<Result<Vec<String>, DbError>>,
+    },
+    /// Change why a package is considered installed
+    SetInstallReason {
+        name: String,
+        reason: InstallReason,
+        resp: oneshot::Sender<Result<(), DbError>>,
+    },
+    /// Find which package owns a tracked file
+    FindFileOwner {
+        path: String,
+        resp: oneshot::Sender<Result<Option<String>, DbError>>,
+    },
+    /// Commit a journaled operation in a single transaction
+    CommitOperation {
+        id: String,
+        kind: OpKind,
+        installs: Vec<InstallRecord>,
+        removals: Vec<String>,
+        generations: Option<(u64, u64)>,
         resp: oneshot::Sender<Result<(), DbError>>,
     },
+    /// Snapshot the active packages for seeding a profile generation
+    InstallRecords {
+        resp: oneshot::Sender<Result<Vec<InstallRecord>, DbError>>,
+    },
     /// Shutdown the actor
     Shutdown,
 }
@@ -89,11 +109,25 @@ impl fmt::Debug for DbEvent {
                 .field("action", action)
                 .field("version", version)
                 .finish_non_exhaustive(),
-            Self::InstallComplete { name, version, .. } => f
-                .debug_struct("InstallComplete")
+            Self::GetDependents { name, .. } => f
+                .debug_struct("GetDependents")
                 .field("name", name)
-                .field("version", version)
                 .finish_non_exhaustive(),
+            Self::SetInstallReason { name, reason, .. } => f
+                .debug_struct("SetInstallReason")
+                .field("name", name)
+                .field("reason", reason)
+                .finish_non_exhaustive(),
+            Self::FindFileOwner { path, .. } => f
+                .debug_struct("FindFileOwner")
+                .field("path", path)
+                .finish_non_exhaustive(),
+            Self::CommitOperation { id, kind, .. } => f
+                .debug_struct("CommitOperation")
+                .field("id", id)
+                .field("kind", kind)
+                .finish_non_exhaustive(),
+            Self::InstallRecords { .. } => f.debug_struct("InstallRecords").finish_non_exhaustive(),
             Self::Shutdown => write!(f, "Shutdown"),
         }
     }
@@ -181,26 +215,47 @@ impl DbHandle {
         .await
     }
 
-    pub async fn install_complete_package(
+    pub async fn get_dependents(&self, name: String) -> Result<Vec<String>, DbError> {
+        self.request(|resp| DbEvent::GetDependents { name, resp })
+            .await
+    }
+
+    pub async fn set_install_reason(
         &self,
         name: String,
-        version: String,
-        sha256: String,
-        size_bytes: u64,
-        artifacts: Vec<(String, String)>,
-        active_files: Vec<(String, String)>,
+        reason: InstallReason,
     ) -> Result<(), DbError> {
-        self.request(|resp| DbEvent::InstallComplete {
-            name,
-            version,
-            sha256,
-            size_bytes,
-            artifacts,
-            active_files,
+        self.request(|resp| DbEvent::SetInstallReason { name, reason, resp })
+            .await
+    }
+
+    pub async fn find_file_owner(&self, path: String) -> Result<Option<String>, DbError> {
+        self.request(|resp| DbEvent::FindFileOwner { path, resp })
+            .await
+    }
+
+    pub async fn commit_operation(
+        &self,
+        id: String,
+        kind: OpKind,
+        installs: Vec<InstallRecord>,
+        removals: Vec<String>,
+        generations: Option<(u64, u64)>,
+    ) -> Result<(), DbError> {
+        self.request(|resp| DbEvent::CommitOperation {
+            id,
+            kind,
+            installs,
+            removals,
+            generations,
             resp,
         })
         .await
     }
+
+    pub async fn install_records(&self) -> Result<Vec<InstallRecord>, DbError> {
+        self.request(|resp| DbEvent::InstallRecords { resp }).await
+    }
 }
 
 /// The actual event loop running in the background thread
@@ -242,23 +297,28 @@ fn run_db_event_loop(db: StateDb, receiver: mpsc::Receiver<DbEvent>) {
                     success,
                 ));
             }
-            DbEvent::InstallComplete {
-                name,
-                version,
-                sha256,
-                size_bytes,
-                artifacts,
-                active_files,
+            DbEvent::GetDependents { name, resp } => {
+                let _ = resp.send(db.get_dependents(&name));
+            }
+            DbEvent::SetInstallReason { name, reason, resp } => {
+                let _ = resp.send(db.set_install_reason(&name, reason));
+            }
+            DbEvent::FindFileOwner { path, resp } => {
+                let _ = resp.send(db.find_file_owner(&path));
+            }
+            DbEvent::CommitOperation {
+                id,
+                kind,
+                installs,
+                removals,
+                generations,
                 resp,
             } => {
-                let _ = resp.send(db.install_complete_package(
-                    &name,
-                    &version,
-                    &sha256,
-                    size_bytes,
-                    &artifacts,
-                    &active_files,
-                ));
+                let _ =
+                    resp.send(db.commit_operation(&id, kind, &installs, &removals, generations));
+            }
+            DbEvent::InstallRecords { resp } => {
+                let _ = resp.send(db.install_records());
             }
             DbEvent::Shutdown => break,
         }
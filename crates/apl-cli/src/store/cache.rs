@@ -0,0 +1,65 @@
+//! Download cache
+//!
+//! Downloaded archives are kept in `~/.apl/cache` by hash, so reinstalling
+//! a version needs no download. `cache.max_size` bounds the directory.
+
+use std::io;
+use std::path::Path;
+use std::time::SystemTime;
+
+/// Removes the least recently modified files in `dir` until the rest take
+/// at most `max_size` bytes. Returns the number of bytes freed.
+pub fn trim(dir: &Path, max_size: u64) -> io::Result<u64> {
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_file() {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((modified, metadata.len(), entry.path()));
+        }
+    }
+
+    let mut total: u64 = files.iter().map(|(_, len, _)| len).sum();
+    let mut freed = 0;
+    files.sort();
+    for (_, len, path) in files {
+        if total <= max_size {
+            break;
+        }
+        std::fs::remove_file(&path)?;
+        total -= len;
+        freed += len;
+    }
+    Ok(freed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_trim_evicts_oldest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        for (i, name) in ["old", "mid", "new"].iter().enumerate() {
+            let path = dir.path().join(name);
+            std::fs::write(&path, vec![0u8; 100]).unwrap();
+            let file = std::fs::File::options().write(true).open(&path).unwrap();
+            file.set_modified(now - Duration::from_secs(100 * (3 - i as u64)))
+                .unwrap();
+        }
+
+        assert_eq!(trim(dir.path(), 250).unwrap(), 100);
+        assert!(!dir.path().join("old").exists());
+        assert!(dir.path().join("mid").exists());
+        assert_eq!(trim(dir.path(), 250).unwrap(), 0);
+        assert_eq!(trim(&dir.path().join("missing"), 0).unwrap(), 0);
+    }
+}
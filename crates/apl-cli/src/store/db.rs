@@ -2,15 +2,20 @@
 //!
 //! Manages the SQLite state database for tracking packages, versions, and file artifacts.
 
+use std::collections::{HashMap, HashSet};
 use std::fmt;
 use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use rusqlite::{Connection, Result, params};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::db_path;
-use crate::store::history::HistoryEvent;
+use crate::store::history::{HistoryEvent, Operation};
+use crate::store::journal::{self, OpKind, Step};
+use crate::store::pin::Pin;
+use crate::store::profile;
+use crate::{db_path, journal_path};
 
 #[derive(Error, Debug)]
 pub enum DbError {
@@ -22,6 +27,9 @@ pub enum DbError {
 
     #[error("Database actor died")]
     ActorDied,
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 /// Metadata for a specific package version stored in the database.
@@ -34,6 +42,68 @@ pub struct Package {
     /// Whether this is the currently linked version
     pub active: bool,
     pub size_bytes: u64,
+    /// Why the package is on the system
+    pub reason: InstallReason,
+    /// Source the version was installed from; `None` for local package
+    /// files and versions installed before sources were recorded
+    pub source: Option<String>,
+}
+
+/// Why a package was installed.
+///
+/// Packages pulled in only to satisfy another package's dependencies are
+/// candidates for `apl autoremove` once nothing installed needs them anymore.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InstallReason {
+    /// Requested by the user by name
+    Explicit,
+    /// Installed to satisfy another package's dependencies
+    Dependency,
+}
+
+impl InstallReason {
+    /// Database representation of the reason.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Explicit => "explicit",
+            Self::Dependency => "dependency",
+        }
+    }
+
+    // Unknown values are treated as explicit so we never autoremove them.
+    fn from_db(value: &str) -> Self {
+        if value == "dependency" {
+            Self::Dependency
+        } else {
+            Self::Explicit
+        }
+    }
+}
+
+impl fmt::Display for InstallReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Everything recorded when a package version becomes the active one.
+#[derive(Debug, Clone)]
+pub struct InstallRecord {
+    pub name: String,
+    pub version: String,
+    pub sha256: String,
+    pub size_bytes: u64,
+    pub reason: InstallReason,
+    /// Runtime dependencies; empty leaves previously recorded ones untouched
+    pub dependencies: Vec<String>,
+    pub artifacts: Vec<(String, String)>,    // (path, sha256)
+    pub active_files: Vec<(String, String)>, // (path, sha256)
+    /// Links of other versions kept side-by-side, as (path, version);
+    /// `None` leaves the recorded ones untouched
+    pub side_by_side: Option<Vec<(String, String)>>,
+    /// Source the version comes from
+    pub source: Option<String>,
 }
 
 /// Artifact mapping (for a specific package version)
@@ -51,6 +121,8 @@ pub struct InstalledFile {
     pub path: String, // Absolute path
     pub package: String,
     pub sha256: String,
+    /// Version a side-by-side link points to; `None` for the active version's files
+    pub version: Option<String>,
 }
 
 /// SQLite database handle.
@@ -72,12 +144,21 @@ impl fmt::Debug for StateDb {
 
 impl StateDb {
     /// Opens the default state database, initializing it if necessary.
+    ///
+    /// Any operation journal left behind by a crashed `apl` process is
+    /// recovered before the handle is returned.
     pub fn open() -> Result<Self, DbError> {
         let path = db_path();
         if let Some(parent) = path.parent() {
             std::fs::create_dir_all(parent).ok();
         }
-        Self::open_at(&path)
+        let db = Self::open_at(&path)?;
+        match db.recover(&journal_path()) {
+            Ok(0) => {}
+            Ok(n) => tracing::info!("Recovered {n} interrupted operation(s)"),
+            Err(e) => tracing::warn!("Failed to recover interrupted operations: {e}"),
+        }
+        Ok(db)
     }
 
     /// Open database at a specific path (for testing)
@@ -125,8 +206,8 @@ impl StateDb {
                 // V1 -> V2
                 self.migrate_v1_to_v2()?;
             } else {
-                // Fresh Init (includes V3)
-                self.init_schema_v3()?;
+                // Fresh Init (includes V9)
+                self.init_schema_v9()?;
                 return Ok(());
             }
         }
@@ -145,6 +226,207 @@ impl StateDb {
             self.migrate_v2_to_v3()?;
         }
 
+        // 3. Check V4 (install reason)
+        let has_reason: u32 = self
+            .conn
+            .query_row(
+                "SELECT count(*) FROM pragma_table_info('packages') WHERE name='reason'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap_or(0);
+
+        if has_reason == 0 {
+            self.migrate_v3_to_v4()?;
+        }
+
+        // 4. Check V5 (operations)
+        let has_operations: u32 = self
+            .conn
+            .query_row(
+                "SELECT count(*) FROM sqlite_master WHERE type='table' AND name='operations'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap_or(0);
+
+        if has_operations == 0 {
+            self.migrate_v4_to_v5()?;
+        }
+
+        // 5. Check V6 (history grouped by operation)
+        let has_operation: u32 = self
+            .conn
+            .query_row(
+                "SELECT count(*) FROM pragma_table_info('history') WHERE name='operation'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap_or(0);
+
+        if has_operation == 0 {
+            self.migrate_v5_to_v6()?;
+        }
+
+        // 6. Check V7 (pins)
+        let has_pins: u32 = self
+            .conn
+            .query_row(
+                "SELECT count(*) FROM sqlite_master WHERE type='table' AND name='pins'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap_or(0);
+
+        if has_pins == 0 {
+            self.migrate_v6_to_v7()?;
+        }
+
+        // 7. Check V8 (side-by-side links)
+        let has_file_version: u32 = self
+            .conn
+            .query_row(
+                "SELECT count(*) FROM pragma_table_info('files') WHERE name='version'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap_or(0);
+
+        if has_file_version == 0 {
+            self.migrate_v7_to_v8()?;
+        }
+
+        // 8. Check V9 (package sources)
+        let has_source: u32 = self
+            .conn
+            .query_row(
+                "SELECT count(*) FROM pragma_table_info('packages') WHERE name='source'",
+                [],
+                |r| r.get(0),
+            )
+            .unwrap_or(0);
+
+        if has_source == 0 {
+            self.migrate_v8_to_v9()?;
+        }
+
+        Ok(())
+    }
+
+    fn init_schema_v9(&self) -> Result<(), DbError> {
+        // Includes V8 schema + V9 additions
+        self.init_schema_v8()?;
+        self.migrate_v8_to_v9()
+    }
+
+    fn migrate_v8_to_v9(&self) -> Result<(), DbError> {
+        // Versions installed before sources existed came from the default
+        // source, but may also be local files, so they are left unknown.
+        self.conn
+            .execute("ALTER TABLE packages ADD COLUMN source TEXT", [])?;
+        Ok(())
+    }
+
+    fn init_schema_v8(&self) -> Result<(), DbError> {
+        // Includes V7 schema + V8 additions
+        self.init_schema_v7()?;
+        self.migrate_v7_to_v8()
+    }
+
+    fn migrate_v7_to_v8(&self) -> Result<(), DbError> {
+        // Links to a non-active version kept side-by-side name that version;
+        // everything else belongs to the active version.
+        self.conn
+            .execute("ALTER TABLE files ADD COLUMN version TEXT", [])?;
+        Ok(())
+    }
+
+    fn init_schema_v7(&self) -> Result<(), DbError> {
+        // Includes V6 schema + V7 additions
+        self.init_schema_v6()?;
+        self.migrate_v6_to_v7()
+    }
+
+    fn migrate_v6_to_v7(&self) -> Result<(), DbError> {
+        // Pins outlive the packages they hold, so reinstalling a removed
+        // package keeps its pin.
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS pins (
+                package TEXT PRIMARY KEY,
+                requirement TEXT,
+                pinned_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn init_schema_v6(&self) -> Result<(), DbError> {
+        // Includes V5 schema + V6 additions
+        self.init_schema_v5()?;
+        self.migrate_v5_to_v6()
+    }
+
+    fn migrate_v5_to_v6(&self) -> Result<(), DbError> {
+        // History rows point at the operation that wrote them, and operations
+        // remember which profile generations they moved between. Older rows
+        // have neither and cannot be rolled back to.
+        self.conn.execute_batch(
+            "
+            ALTER TABLE history ADD COLUMN operation TEXT;
+            ALTER TABLE operations ADD COLUMN generation_from INTEGER;
+            ALTER TABLE operations ADD COLUMN generation_to INTEGER;
+
+            CREATE INDEX IF NOT EXISTS idx_history_operation ON history(operation);
+            ",
+        )?;
+        Ok(())
+    }
+
+    fn init_schema_v5(&self) -> Result<(), DbError> {
+        // Includes V4 schema + V5 additions
+        self.init_schema_v4()?;
+        self.migrate_v4_to_v5()
+    }
+
+    fn migrate_v4_to_v5(&self) -> Result<(), DbError> {
+        // One row per committed journaled operation, written in the same
+        // transaction as its changes. Recovery uses it to tell whether a
+        // crashed operation made it into the database.
+        self.conn.execute(
+            "CREATE TABLE IF NOT EXISTS operations (
+                id TEXT PRIMARY KEY,
+                kind TEXT NOT NULL,
+                committed_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        Ok(())
+    }
+
+    fn init_schema_v4(&self) -> Result<(), DbError> {
+        // Includes V3 schema + V4 additions
+        self.init_schema_v3()?;
+        self.migrate_v3_to_v4()
+    }
+
+    fn migrate_v3_to_v4(&self) -> Result<(), DbError> {
+        // Everything installed before we tracked reasons was asked for by the
+        // user as far as we know, so it defaults to explicit.
+        self.conn.execute_batch(
+            "
+            ALTER TABLE packages ADD COLUMN reason TEXT NOT NULL DEFAULT 'explicit';
+
+            CREATE TABLE IF NOT EXISTS dependencies (
+                package TEXT NOT NULL,
+                version TEXT NOT NULL,
+                dependency TEXT NOT NULL,
+                PRIMARY KEY (package, version, dependency)
+            );
+
+            CREATE INDEX IF NOT EXISTS idx_dependencies_dep ON dependencies(dependency);
+            ",
+        )?;
         Ok(())
     }
 
@@ -278,59 +560,243 @@ impl StateDb {
 
     /// Records a complete package installation atomically.
     ///
-    /// This updates the package version record, artifact links, and current
-    /// active files in a single transaction.
-    pub fn install_complete_package(
-        &self,
-        name: &str,
-        version: &str,
-        sha256: &str,
-        size_bytes: u64,
-        artifacts: &[(String, String)],    // (path, sha256)
-        active_files: &[(String, String)], // (path, sha256)
-    ) -> Result<(), DbError> {
+    /// This updates the package version record, artifact links, runtime
+    /// dependencies, and current active files in a single transaction.
+    pub fn install_complete_package(&self, record: &InstallRecord) -> Result<(), DbError> {
         // Implementation Note: Atomic Transactions
         //
         // We use a single transaction for all 4 distinct write operations.
         // If the power goes out after step 2, NO changes are persisted.
         // The database is always in a valid state: either the old package active, or the new one.
         // Never "half-installed".
+        let tx = self.conn.unchecked_transaction()?;
+        Self::write_install(&tx, record)?;
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Commits a whole journaled operation in one transaction.
+    ///
+    /// Installs and removals are applied together with their history entries
+    /// and the operation id, so either the entire batch is visible or none of
+    /// it is. `generations` is the profile generation switch it made, if any.
+    pub fn commit_operation(
+        &self,
+        id: &str,
+        kind: OpKind,
+        installs: &[InstallRecord],
+        removals: &[String],
+        generations: Option<(u64, u64)>,
+    ) -> Result<(), DbError> {
         let now = SystemTime::now()
             .duration_since(UNIX_EPOCH)
             .unwrap()
-            .as_secs() as i64;
+            .as_millis() as i64;
 
         let tx = self.conn.unchecked_transaction()?;
 
-        // 1. Deactivate others
+        let action = match kind {
+            OpKind::Switch => "switch",
+            OpKind::Install | OpKind::Remove => "install",
+        };
+        for record in installs {
+            let previous: Option<String> = tx
+                .query_row(
+                    "SELECT version FROM packages WHERE name = ?1 AND active = 1",
+                    params![record.name],
+                    |r| r.get(0),
+                )
+                .ok();
+            Self::write_install(&tx, record)?;
+            // Republishing the active version (after a repair) is not a change.
+            if previous.as_deref() == Some(record.version.as_str()) {
+                continue;
+            }
+            tx.execute(
+                "INSERT INTO history (timestamp, action, package, version_from, version_to, success, operation)
+                 VALUES (?1, ?2, ?3, ?4, ?5, 1, ?6)",
+                params![now, action, record.name, previous, record.version, id],
+            )?;
+        }
+
+        for name in removals {
+            let version: Option<String> = tx
+                .query_row(
+                    "SELECT version FROM packages WHERE name = ?1 AND active = 1",
+                    params![name],
+                    |r| r.get(0),
+                )
+                .ok();
+            if Self::delete_package(&tx, name)? == 0 {
+                continue;
+            }
+            tx.execute(
+                "INSERT INTO history (timestamp, action, package, version_from, version_to, success, operation)
+                 VALUES (?1, 'remove', ?2, ?3, NULL, 1, ?4)",
+                params![now, name, version, id],
+            )?;
+        }
+
+        let (generation_from, generation_to) = generations.unzip();
         tx.execute(
-            "UPDATE packages SET active = 0 WHERE name = ?1",
+            "INSERT INTO operations (id, kind, committed_at, generation_from, generation_to)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![id, kind.as_str(), now, generation_from, generation_to],
+        )?;
+
+        tx.commit()?;
+        Ok(())
+    }
+
+    /// Whether a journaled operation reached the database.
+    pub fn is_committed(&self, id: &str) -> Result<bool, DbError> {
+        let committed = self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM operations WHERE id = ?1)",
+            params![id],
+            |r| r.get(0),
+        )?;
+        Ok(committed)
+    }
+
+    /// Replays journals left behind by crashed operations.
+    ///
+    /// Installs and switches that never committed are rolled back: staged
+    /// directories and generations are deleted, replaced app bundles are put
+    /// back and the profile points at its previous generation again. Removals are rolled forward, since deleted files cannot be
+    /// brought back. Journals owned by a live process are left alone.
+    /// Returns the number of operations recovered.
+    pub fn recover(&self, journal_dir: &Path) -> Result<usize, DbError> {
+        let mut recovered = 0;
+
+        for (path, steps) in journal::pending(journal_dir)? {
+            let Some(Step::Begin { id, kind, pid }) = steps.first() else {
+                // Died before the header reached the disk; nothing was touched.
+                std::fs::remove_file(&path)?;
+                continue;
+            };
+            if journal::is_alive(*pid) {
+                continue;
+            }
+
+            let committed = self.is_committed(id)?;
+            match kind {
+                OpKind::Install | OpKind::Switch if !committed => journal::roll_back(&steps),
+                OpKind::Install | OpKind::Switch => journal::discard_backups(&steps),
+                OpKind::Remove => {
+                    if journal::roll_forward(&steps) && !committed {
+                        let forgotten: Vec<String> = steps
+                            .iter()
+                            .filter_map(|s| match s {
+                                Step::Forget { package } => Some(package.clone()),
+                                _ => None,
+                            })
+                            .collect();
+                        let generations = profile::activation(&steps);
+                        self.commit_operation(id, OpKind::Remove, &[], &forgotten, generations)?;
+                    }
+                }
+            }
+
+            std::fs::remove_file(&path)?;
+            recovered += 1;
+        }
+
+        Ok(recovered)
+    }
+
+    fn write_install(conn: &Connection, record: &InstallRecord) -> Result<(), DbError> {
+        let InstallRecord {
+            name,
+            version,
+            sha256,
+            size_bytes,
+            reason,
+            dependencies,
+            artifacts,
+            active_files,
+            side_by_side,
+            source,
+        } = record;
+
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        // A package that was ever installed explicitly stays explicit, even
+        // when a later install only pulls it in as a dependency.
+        let was_explicit: bool = conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM packages WHERE name = ?1 AND reason = 'explicit')",
             params![name],
+            |r| r.get(0),
+        )?;
+        let reason = if was_explicit {
+            InstallReason::Explicit
+        } else {
+            *reason
+        };
+
+        // 1. Deactivate others
+        conn.execute(
+            "UPDATE packages SET active = 0, reason = ?2 WHERE name = ?1",
+            params![name, reason.as_str()],
         )?;
 
         // 2. Insert package
-        tx.execute(
-            "INSERT OR REPLACE INTO packages (name, version, sha256, installed_at, active, size_bytes)
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
-            params![name, version, sha256, now, true, size_bytes],
+        conn.execute(
+            "INSERT OR REPLACE INTO packages (name, version, sha256, installed_at, active, size_bytes, reason, source)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            params![name, version, sha256, now, true, size_bytes, reason.as_str(), source],
         )?;
 
+        // Dependencies are recorded per version so switching back to an older
+        // version keeps its own requirements. An empty list leaves them as-is.
+        if !dependencies.is_empty() {
+            conn.execute(
+                "DELETE FROM dependencies WHERE package = ?1 AND version = ?2",
+                params![name, version],
+            )?;
+            let mut stmt_dep = conn.prepare(
+                "INSERT OR IGNORE INTO dependencies (package, version, dependency) VALUES (?1, ?2, ?3)",
+            )?;
+            for dep in dependencies {
+                stmt_dep.execute(params![name, version, dep])?;
+            }
+        }
+
         // 3. Insert artifacts
-        let mut stmt_art = tx.prepare("INSERT OR REPLACE INTO artifacts (package, version, path, sha256) VALUES (?1, ?2, ?3, ?4)")?;
+        let mut stmt_art = conn.prepare("INSERT OR REPLACE INTO artifacts (package, version, path, sha256) VALUES (?1, ?2, ?3, ?4)")?;
         for (path, hash) in artifacts {
             stmt_art.execute(params![name, version, path, hash])?;
         }
         drop(stmt_art);
 
-        // 4. Insert active files
-        let mut stmt_file =
-            tx.prepare("INSERT OR REPLACE INTO files (path, package, sha256) VALUES (?1, ?2, ?3)")?;
+        // 4. Replace active files, so links the previous version had and
+        //    this one lacks don't linger
+        conn.execute(
+            "DELETE FROM files WHERE package = ?1 AND version IS NULL",
+            params![name],
+        )?;
+        let mut stmt_file = conn
+            .prepare("INSERT OR REPLACE INTO files (path, package, sha256) VALUES (?1, ?2, ?3)")?;
         for (path, hash) in active_files {
             stmt_file.execute(params![path, name, hash])?;
         }
-        drop(stmt_file);
 
-        tx.commit()?;
+        // 5. Replace side-by-side links
+        if let Some(side_by_side) = side_by_side {
+            conn.execute(
+                "DELETE FROM files WHERE package = ?1 AND version IS NOT NULL",
+                params![name],
+            )?;
+            let mut stmt_side = conn.prepare(
+                "INSERT OR REPLACE INTO files (path, package, sha256, version) VALUES (?1, ?2, 'SYMLINK', ?3)",
+            )?;
+            for (path, version) in side_by_side {
+                stmt_side.execute(params![path, name, version])?;
+            }
+        }
+
         Ok(())
     }
 
@@ -404,12 +870,7 @@ impl StateDb {
         let files = self.get_package_files(name)?;
 
         let tx = self.conn.unchecked_transaction()?;
-
-        // Delete from all tables (manual cascade for files since FK removed)
-        tx.execute("DELETE FROM files WHERE package = ?1", params![name])?;
-        tx.execute("DELETE FROM artifacts WHERE package = ?1", params![name])?;
-        let deleted = tx.execute("DELETE FROM packages WHERE name = ?1", params![name])?;
-
+        let deleted = Self::delete_package(&tx, name)?;
         tx.commit()?;
 
         if deleted == 0 {
@@ -419,10 +880,19 @@ impl StateDb {
         Ok(files.into_iter().map(|f| f.path).collect())
     }
 
+    fn delete_package(conn: &Connection, name: &str) -> Result<usize, DbError> {
+        // Delete from all tables (manual cascade for files since FK removed)
+        conn.execute("DELETE FROM files WHERE package = ?1", params![name])?;
+        conn.execute("DELETE FROM artifacts WHERE package = ?1", params![name])?;
+        conn.execute("DELETE FROM dependencies WHERE package = ?1", params![name])?;
+        let deleted = conn.execute("DELETE FROM packages WHERE name = ?1", params![name])?;
+        Ok(deleted)
+    }
+
     /// Retrieves the currently active version of a package.
     pub fn get_package(&self, name: &str) -> Result<Option<Package>, DbError> {
         let mut stmt = self.conn.prepare(
-            "SELECT name, version, sha256, installed_at, active, size_bytes FROM packages WHERE name = ?1 AND active = 1",
+            "SELECT name, version, sha256, installed_at, active, size_bytes, reason, source FROM packages WHERE name = ?1 AND active = 1",
         )?;
 
         let mut rows = stmt.query(params![name])?;
@@ -435,6 +905,8 @@ impl StateDb {
                 installed_at: row.get(3)?,
                 active: row.get(4)?,
                 size_bytes: row.get(5)?,
+                reason: InstallReason::from_db(&row.get::<_, String>(6)?),
+                source: row.get(7)?,
             }))
         } else {
             Ok(None)
@@ -448,7 +920,7 @@ impl StateDb {
         version: &str,
     ) -> Result<Option<Package>, DbError> {
         let mut stmt = self.conn.prepare(
-            "SELECT name, version, sha256, installed_at, active, size_bytes FROM packages WHERE name = ?1 AND version = ?2",
+            "SELECT name, version, sha256, installed_at, active, size_bytes, reason, source FROM packages WHERE name = ?1 AND version = ?2",
         )?;
 
         let mut rows = stmt.query(params![name, version])?;
@@ -461,6 +933,8 @@ impl StateDb {
                 installed_at: row.get(3)?,
                 active: row.get(4)?,
                 size_bytes: row.get(5)?,
+                reason: InstallReason::from_db(&row.get::<_, String>(6)?),
+                source: row.get(7)?,
             }))
         } else {
             Ok(None)
@@ -470,7 +944,7 @@ impl StateDb {
     /// List all ACTIVE installed packages
     pub fn list_packages(&self) -> Result<Vec<Package>, DbError> {
         let mut stmt = self.conn.prepare(
-            "SELECT name, version, sha256, installed_at, active, size_bytes FROM packages WHERE active = 1 ORDER BY name",
+            "SELECT name, version, sha256, installed_at, active, size_bytes, reason, source FROM packages WHERE active = 1 ORDER BY name",
         )?;
 
         let packages = stmt.query_map([], |row| {
@@ -481,16 +955,47 @@ impl StateDb {
                 installed_at: row.get(3)?,
                 active: row.get(4)?,
                 size_bytes: row.get(5)?,
+                reason: InstallReason::from_db(&row.get::<_, String>(6)?),
+                source: row.get(7)?,
             })
         })?;
 
         packages.collect::<Result<Vec<_>, _>>().map_err(Into::into)
     }
 
+    /// Everything recorded about the active packages, as written at install.
+    ///
+    /// Artifacts are left out; the result is used to seed the first profile
+    /// generation from an existing installation.
+    pub fn install_records(&self) -> Result<Vec<InstallRecord>, DbError> {
+        self.list_packages()?
+            .into_iter()
+            .map(|p| {
+                Ok(InstallRecord {
+                    dependencies: self.get_dependencies(&p.name)?,
+                    active_files: self
+                        .get_package_files(&p.name)?
+                        .into_iter()
+                        .filter(|f| f.version.is_none())
+                        .map(|f| (f.path, f.sha256))
+                        .collect(),
+                    artifacts: vec![],
+                    side_by_side: None,
+                    name: p.name,
+                    version: p.version,
+                    sha256: p.sha256,
+                    size_bytes: p.size_bytes,
+                    reason: p.reason,
+                    source: p.source,
+                })
+            })
+            .collect()
+    }
+
     /// List ALL installed versions of a package
     pub fn list_package_versions(&self, name: &str) -> Result<Vec<Package>, DbError> {
         let mut stmt = self.conn.prepare(
-            "SELECT name, version, sha256, installed_at, active, size_bytes FROM packages WHERE name = ?1 ORDER BY version DESC",
+            "SELECT name, version, sha256, installed_at, active, size_bytes, reason, source FROM packages WHERE name = ?1 ORDER BY version DESC",
         )?;
 
         let packages = stmt.query_map(params![name], |row| {
@@ -501,6 +1006,8 @@ impl StateDb {
                 installed_at: row.get(3)?,
                 active: row.get(4)?,
                 size_bytes: row.get(5)?,
+                reason: InstallReason::from_db(&row.get::<_, String>(6)?),
+                source: row.get(7)?,
             })
         })?;
 
@@ -523,6 +1030,29 @@ impl StateDb {
         rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
     }
 
+    /// Replaces the recorded file hashes of a package version.
+    pub fn replace_artifacts(
+        &self,
+        package: &str,
+        version: &str,
+        artifacts: &[(String, String)],
+    ) -> Result<(), DbError> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "DELETE FROM artifacts WHERE package = ?1 AND version = ?2",
+            params![package, version],
+        )?;
+        let mut stmt = tx.prepare(
+            "INSERT INTO artifacts (package, version, path, sha256) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        for (path, hash) in artifacts {
+            stmt.execute(params![package, version, path, hash])?;
+        }
+        drop(stmt);
+        tx.commit()?;
+        Ok(())
+    }
+
     // History methods
 
     pub fn add_history(
@@ -547,43 +1077,39 @@ impl StateDb {
     }
 
     pub fn get_history(&self, package: &str) -> Result<Vec<HistoryEvent>, DbError> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, timestamp, action, package, version_from, version_to, success 
-             FROM history WHERE package = ?1 ORDER BY timestamp ASC",
-        )?;
-        let rows = stmt.query_map(params![package], |row| {
-            Ok(HistoryEvent {
-                id: row.get(0)?,
-                timestamp: row.get(1)?,
-                action: row.get(2)?,
-                package: row.get(3)?,
-                version_from: row.get(4)?,
-                version_to: row.get(5)?,
-                success: row.get(6)?,
-            })
-        })?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {HISTORY_COLUMNS} FROM history WHERE package = ?1 ORDER BY timestamp ASC, id ASC"
+        ))?;
+        let rows = stmt.query_map(params![package], history_row)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Every history event across all packages, oldest first.
+    pub fn get_timeline(&self) -> Result<Vec<HistoryEvent>, DbError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {HISTORY_COLUMNS} FROM history ORDER BY timestamp ASC, id ASC"
+        ))?;
+        let rows = stmt.query_map([], history_row)?;
         rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
     }
 
+    pub fn get_history_event(&self, id: i64) -> Result<Option<HistoryEvent>, DbError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {HISTORY_COLUMNS} FROM history WHERE id = ?1"
+        ))?;
+        let mut rows = stmt.query_map(params![id], history_row)?;
+        rows.next().transpose().map_err(Into::into)
+    }
+
     pub fn get_last_successful_history(
         &self,
         package: &str,
     ) -> Result<Option<HistoryEvent>, DbError> {
-        let mut stmt = self.conn.prepare(
-            "SELECT id, timestamp, action, package, version_from, version_to, success 
-             FROM history WHERE package = ?1 AND success = 1 ORDER BY timestamp DESC LIMIT 1",
-        )?;
-        let mut rows = stmt.query_map(params![package], |row| {
-            Ok(HistoryEvent {
-                id: row.get(0)?,
-                timestamp: row.get(1)?,
-                action: row.get(2)?,
-                package: row.get(3)?,
-                version_from: row.get(4)?,
-                version_to: row.get(5)?,
-                success: row.get(6)?,
-            })
-        })?;
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {HISTORY_COLUMNS} FROM history WHERE package = ?1 AND success = 1
+             ORDER BY timestamp DESC, id DESC LIMIT 1"
+        ))?;
+        let mut rows = stmt.query_map(params![package], history_row)?;
 
         if let Some(res) = rows.next() {
             Ok(Some(res?))
@@ -592,17 +1118,100 @@ impl StateDb {
         }
     }
 
+    /// All committed operations, oldest first.
+    pub fn list_operations(&self) -> Result<Vec<Operation>, DbError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {OPERATION_COLUMNS} FROM operations ORDER BY committed_at ASC, rowid ASC"
+        ))?;
+        let rows = stmt.query_map([], operation_row)?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    pub fn get_operation(&self, id: &str) -> Result<Option<Operation>, DbError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {OPERATION_COLUMNS} FROM operations WHERE id = ?1"
+        ))?;
+        let mut rows = stmt.query_map(params![id], operation_row)?;
+        rows.next().transpose().map_err(Into::into)
+    }
+
+    /// The most recently committed operation.
+    pub fn last_operation(&self) -> Result<Option<Operation>, DbError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {OPERATION_COLUMNS} FROM operations ORDER BY committed_at DESC, rowid DESC LIMIT 1"
+        ))?;
+        let mut rows = stmt.query_map([], operation_row)?;
+        rows.next().transpose().map_err(Into::into)
+    }
+
+    /// The last operation committed at or before `timestamp` (millis) that
+    /// switched profile generations, i.e. the one that decided what was
+    /// active at that point.
+    pub fn operation_at(&self, timestamp: i64) -> Result<Option<Operation>, DbError> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {OPERATION_COLUMNS} FROM operations
+             WHERE committed_at <= ?1 AND generation_to IS NOT NULL
+             ORDER BY committed_at DESC, rowid DESC LIMIT 1"
+        ))?;
+        let mut rows = stmt.query_map(params![timestamp], operation_row)?;
+        rows.next().transpose().map_err(Into::into)
+    }
+
+    // Pin methods
+
+    /// Pins a package, replacing any existing pin.
+    pub fn set_pin(&self, package: &str, requirement: Option<&str>) -> Result<(), DbError> {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+
+        self.conn.execute(
+            "INSERT OR REPLACE INTO pins (package, requirement, pinned_at) VALUES (?1, ?2, ?3)",
+            params![package, requirement, now],
+        )?;
+        Ok(())
+    }
+
+    /// Removes a package's pin. Returns whether it was pinned.
+    pub fn remove_pin(&self, package: &str) -> Result<bool, DbError> {
+        let removed = self
+            .conn
+            .execute("DELETE FROM pins WHERE package = ?1", params![package])?;
+        Ok(removed > 0)
+    }
+
+    pub fn get_pin(&self, package: &str) -> Result<Option<Pin>, DbError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT package, requirement, pinned_at FROM pins WHERE package = ?1")?;
+        let mut rows = stmt.query_map(params![package], pin_row)?;
+        rows.next().transpose().map_err(Into::into)
+    }
+
+    /// All pins, by package name.
+    pub fn list_pins(&self) -> Result<HashMap<String, Pin>, DbError> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT package, requirement, pinned_at FROM pins")?;
+        let rows = stmt.query_map([], pin_row)?;
+        rows.map(|pin| pin.map(|pin| (pin.package.clone(), pin)))
+            .collect::<Result<_, _>>()
+            .map_err(Into::into)
+    }
+
     /// Get all active files for a package
     pub fn get_package_files(&self, package: &str) -> Result<Vec<InstalledFile>, DbError> {
         let mut stmt = self
             .conn
-            .prepare("SELECT path, package, sha256 FROM files WHERE package = ?1")?;
+            .prepare("SELECT path, package, sha256, version FROM files WHERE package = ?1")?;
 
         let files = stmt.query_map(params![package], |row| {
             Ok(InstalledFile {
                 path: row.get(0)?,
                 package: row.get(1)?,
                 sha256: row.get(2)?,
+                version: row.get(3)?,
             })
         })?;
 
@@ -622,6 +1231,131 @@ impl StateDb {
             Ok(None)
         }
     }
+
+    // Dependency tracking
+
+    /// Marks every installed version of a package with the given reason.
+    pub fn set_install_reason(&self, name: &str, reason: InstallReason) -> Result<(), DbError> {
+        let updated = self.conn.execute(
+            "UPDATE packages SET reason = ?2 WHERE name = ?1",
+            params![name, reason.as_str()],
+        )?;
+        if updated == 0 {
+            return Err(DbError::PackageNotFound(name.to_string()));
+        }
+        Ok(())
+    }
+
+    /// Runtime dependencies recorded for the active version of a package.
+    pub fn get_dependencies(&self, name: &str) -> Result<Vec<String>, DbError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT d.dependency FROM dependencies d
+             JOIN packages p ON p.name = d.package AND p.version = d.version AND p.active = 1
+             WHERE d.package = ?1 ORDER BY d.dependency",
+        )?;
+        let rows = stmt.query_map(params![name], |row| row.get(0))?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Runtime dependencies recorded for one version of a package.
+    pub fn get_version_dependencies(
+        &self,
+        name: &str,
+        version: &str,
+    ) -> Result<Vec<String>, DbError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT dependency FROM dependencies
+             WHERE package = ?1 AND version = ?2 ORDER BY dependency",
+        )?;
+        let rows = stmt.query_map(params![name, version], |row| row.get(0))?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Installed packages whose active version depends on `name`.
+    pub fn get_dependents(&self, name: &str) -> Result<Vec<String>, DbError> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT d.package FROM dependencies d
+             JOIN packages p ON p.name = d.package AND p.version = d.version AND p.active = 1
+             WHERE d.dependency = ?1 ORDER BY d.package",
+        )?;
+        let rows = stmt.query_map(params![name], |row| row.get(0))?;
+        rows.collect::<Result<Vec<_>, _>>().map_err(Into::into)
+    }
+
+    /// Dependency-only packages that no explicitly installed package needs.
+    ///
+    /// Walks the dependency graph from every explicit package; anything that
+    /// was installed as a dependency and is not reachable is an orphan. This
+    /// also catches chains and cycles of dependencies left behind by a removal.
+    pub fn list_orphans(&self) -> Result<Vec<String>, DbError> {
+        let packages = self.list_packages()?;
+
+        let mut stmt = self.conn.prepare(
+            "SELECT d.package, d.dependency FROM dependencies d
+             JOIN packages p ON p.name = d.package AND p.version = d.version AND p.active = 1",
+        )?;
+        let mut edges: HashMap<String, Vec<String>> = HashMap::new();
+        for row in stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))? {
+            let (package, dependency): (String, String) = row?;
+            edges.entry(package).or_default().push(dependency);
+        }
+
+        let mut needed: HashSet<&str> = HashSet::new();
+        let mut stack: Vec<&str> = packages
+            .iter()
+            .filter(|p| p.reason == InstallReason::Explicit)
+            .map(|p| p.name.as_str())
+            .collect();
+        while let Some(name) = stack.pop() {
+            if needed.insert(name) {
+                if let Some(deps) = edges.get(name) {
+                    stack.extend(deps.iter().map(String::as_str));
+                }
+            }
+        }
+
+        Ok(packages
+            .iter()
+            .filter(|p| !needed.contains(p.name.as_str()))
+            .map(|p| p.name.clone())
+            .collect())
+    }
+}
+
+const HISTORY_COLUMNS: &str =
+    "id, timestamp, action, package, version_from, version_to, success, operation";
+
+fn history_row(row: &rusqlite::Row) -> Result<HistoryEvent> {
+    Ok(HistoryEvent {
+        id: row.get(0)?,
+        timestamp: row.get(1)?,
+        action: row.get(2)?,
+        package: row.get(3)?,
+        version_from: row.get(4)?,
+        version_to: row.get(5)?,
+        success: row.get(6)?,
+        operation: row.get(7)?,
+    })
+}
+
+const OPERATION_COLUMNS: &str = "id, kind, committed_at, generation_from, generation_to";
+
+fn operation_row(row: &rusqlite::Row) -> Result<Operation> {
+    Ok(Operation {
+        id: row.get(0)?,
+        kind: row.get(1)?,
+        committed_at: row.get(2)?,
+        generation_from: row.get(3)?,
+        generation_to: row.get(4)?,
+    })
+}
+
+fn pin_row(row: &rusqlite::Row) -> Result<Pin> {
+    Ok(Pin {
+        package: row.get(0)?,
+        requirement: row.get(1)?,
+        pinned_at: row.get(2)?,
+    })
 }
 
 #[cfg(test)]
@@ -698,4 +1432,196 @@ mod tests {
 
         assert!(db.get_package("neovim").unwrap().is_none());
     }
+
+    fn record(name: &str, reason: InstallReason, deps: &[String]) -> InstallRecord {
+        InstallRecord {
+            name: name.to_string(),
+            version: "1.0".to_string(),
+            sha256: "abc".to_string(),
+            size_bytes: 0,
+            reason,
+            dependencies: deps.to_vec(),
+            artifacts: vec![],
+            active_files: vec![],
+            side_by_side: None,
+            source: None,
+        }
+    }
+
+    fn install(db: &StateDb, name: &str, reason: InstallReason, deps: &[&str]) {
+        let deps: Vec<String> = deps.iter().map(ToString::to_string).collect();
+        db.install_complete_package(&record(name, reason, &deps))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_install_reason() {
+        let dir = tempdir().unwrap();
+        let db = StateDb::open_at(&dir.path().join("state.db")).unwrap();
+
+        install(&db, "libuv", InstallReason::Dependency, &[]);
+        let pkg = db.get_package("libuv").unwrap().unwrap();
+        assert_eq!(pkg.reason, InstallReason::Dependency);
+
+        // An explicit install promotes it...
+        install(&db, "libuv", InstallReason::Explicit, &[]);
+        let pkg = db.get_package("libuv").unwrap().unwrap();
+        assert_eq!(pkg.reason, InstallReason::Explicit);
+
+        // ...and pulling it in as a dependency again does not demote it.
+        install(&db, "libuv", InstallReason::Dependency, &[]);
+        let pkg = db.get_package("libuv").unwrap().unwrap();
+        assert_eq!(pkg.reason, InstallReason::Explicit);
+    }
+
+    #[test]
+    fn test_dependents_and_orphans() {
+        let dir = tempdir().unwrap();
+        let db = StateDb::open_at(&dir.path().join("state.db")).unwrap();
+
+        install(&db, "libuv", InstallReason::Dependency, &[]);
+        install(&db, "luajit", InstallReason::Dependency, &["libuv"]);
+        install(&db, "neovim", InstallReason::Explicit, &["luajit", "libuv"]);
+        install(&db, "jq", InstallReason::Explicit, &[]);
+
+        assert_eq!(
+            db.get_dependents("libuv").unwrap(),
+            vec!["luajit", "neovim"]
+        );
+        assert_eq!(
+            db.get_dependencies("neovim").unwrap(),
+            vec!["libuv", "luajit"]
+        );
+        assert!(db.list_orphans().unwrap().is_empty());
+
+        db.remove_package("neovim").unwrap();
+        assert_eq!(db.get_dependents("libuv").unwrap(), vec!["luajit"]);
+        assert_eq!(db.list_orphans().unwrap(), vec!["libuv", "luajit"]);
+    }
+
+    #[test]
+    fn test_operations_group_history() {
+        let dir = tempdir().unwrap();
+        let db = StateDb::open_at(&dir.path().join("state.db")).unwrap();
+
+        let both = [
+            record("jq", InstallReason::Explicit, &[]),
+            record("rg", InstallReason::Explicit, &[]),
+        ];
+        db.commit_operation("a", OpKind::Install, &both, &[], Some((1, 2)))
+            .unwrap();
+        db.commit_operation("b", OpKind::Remove, &[], &["rg".to_string()], Some((2, 3)))
+            .unwrap();
+
+        let timeline = db.get_timeline().unwrap();
+        let ops: Vec<_> = timeline
+            .iter()
+            .map(|e| {
+                (
+                    e.operation.as_deref(),
+                    e.package.as_str(),
+                    e.action.as_str(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            ops,
+            vec![
+                (Some("a"), "jq", "install"),
+                (Some("a"), "rg", "install"),
+                (Some("b"), "rg", "remove"),
+            ]
+        );
+
+        let last = db.last_operation().unwrap().unwrap();
+        assert_eq!(last.id, "b");
+        assert_eq!(
+            (last.generation_from, last.generation_to),
+            (Some(2), Some(3))
+        );
+
+        let event = db
+            .get_history_event(timeline[0].id.unwrap())
+            .unwrap()
+            .unwrap();
+        assert_eq!(event.operation.as_deref(), Some("a"));
+        assert_eq!(db.operation_at(last.committed_at).unwrap().unwrap().id, "b");
+        assert!(
+            db.operation_at(last.committed_at - 60_000)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_pins() {
+        let dir = tempdir().unwrap();
+        let db = StateDb::open_at(&dir.path().join("state.db")).unwrap();
+
+        db.set_pin("terraform", Some("1.5.x")).unwrap();
+        db.set_pin("jq", None).unwrap();
+        assert_eq!(
+            db.get_pin("terraform")
+                .unwrap()
+                .unwrap()
+                .requirement
+                .as_deref(),
+            Some("1.5.x")
+        );
+
+        // Pinning again replaces the requirement.
+        db.set_pin("terraform", Some("~1.6")).unwrap();
+        let pins = db.list_pins().unwrap();
+        assert_eq!(pins.len(), 2);
+        assert_eq!(pins["terraform"].requirement.as_deref(), Some("~1.6"));
+        assert_eq!(pins["jq"].requirement, None);
+
+        assert!(db.remove_pin("jq").unwrap());
+        assert!(!db.remove_pin("jq").unwrap());
+        assert!(db.get_pin("jq").unwrap().is_none());
+    }
+
+    #[test]
+    fn test_migrate_v3_to_v4() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("state.db");
+        {
+            let db = StateDb::open_at(&path).unwrap();
+            db.install_package("jq", "1.7", "abc").unwrap();
+            db.conn
+                .execute_batch("ALTER TABLE packages DROP COLUMN reason; DROP TABLE dependencies;")
+                .unwrap();
+        }
+
+        let db = StateDb::open_at(&path).unwrap();
+        let pkg = db.get_package("jq").unwrap().unwrap();
+        assert_eq!(pkg.reason, InstallReason::Explicit);
+        assert!(db.list_orphans().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_source_is_recorded() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("state.db");
+        {
+            let db = StateDb::open_at(&path).unwrap();
+            db.install_package("jq", "1.7", "abc").unwrap();
+            db.conn
+                .execute_batch("ALTER TABLE packages DROP COLUMN source")
+                .unwrap();
+        }
+
+        let db = StateDb::open_at(&path).unwrap();
+        assert_eq!(db.get_package("jq").unwrap().unwrap().source, None);
+
+        let mut internal = record("tool", InstallReason::Explicit, &[]);
+        internal.source = Some("internal".to_string());
+        db.install_complete_package(&internal).unwrap();
+        let pkg = db.get_package("tool").unwrap().unwrap();
+        assert_eq!(pkg.source.as_deref(), Some("internal"));
+        assert_eq!(
+            db.install_records().unwrap()[1].source.as_deref(),
+            Some("internal")
+        );
+    }
 }
@@ -10,4 +10,19 @@ pub struct HistoryEvent {
     pub version_from: Option<String>,
     pub version_to: Option<String>,
     pub success: bool,
+    /// Id of the operation that wrote this event, if it was journaled
+    pub operation: Option<String>,
+}
+
+/// A committed journaled operation
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Operation {
+    pub id: String,
+    pub kind: String, // "install", "remove", "switch"
+    /// Commit time in milliseconds
+    pub committed_at: i64,
+    /// Profile generation current before the operation, if it switched
+    pub generation_from: Option<u64>,
+    /// Profile generation the operation made current, if it switched
+    pub generation_to: Option<u64>,
 }
@@ -0,0 +1,429 @@
+//! Operation journal
+//!
+//! Multi-package operations touch the store, `~/.apl/bin` and the state
+//! database. None of those can share a transaction, so every filesystem
+//! mutation is first appended to an on-disk journal. If the process dies
+//! part-way, the next `StateDb::open` replays the journal: an install whose
+//! database transaction never committed is rolled back, a removal is rolled
+//! forward.
+//!
+//! The journal is newline-delimited JSON, one [`Step`] per line, synced to
+//! disk before the mutation it describes happens. A torn final line is
+//! simply ignored during recovery.
+
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+/// Kind of operation a journal describes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OpKind {
+    Install,
+    Remove,
+    /// Activating an existing version or profile generation
+    Switch,
+}
+
+impl OpKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Install => "install",
+            Self::Remove => "remove",
+            Self::Switch => "switch",
+        }
+    }
+}
+
+/// A single journaled intent.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "step", rename_all = "snake_case")]
+pub enum Step {
+    /// Always the first line of a journal
+    Begin { id: String, kind: OpKind, pid: u32 },
+    /// A directory is about to be created (store entry or app bundle)
+    Stage { path: PathBuf },
+    /// An existing directory is about to be moved aside to `backup`; it is
+    /// put back on rollback and deleted once the operation completes
+    Backup { path: PathBuf, backup: PathBuf },
+    /// A symlink to `target` is about to be placed, replacing `previous` if set
+    Link {
+        path: PathBuf,
+        target: PathBuf,
+        previous: Option<PathBuf>,
+    },
+    /// A tracked file is about to be deleted
+    Unlink { path: PathBuf },
+    /// A package's records are about to be dropped from the database
+    Forget { package: String },
+}
+
+/// An open journal for an in-flight operation.
+///
+/// Dropping a journal without calling [`Journal::finish`] leaves it on disk
+/// for recovery, which is exactly what a failed operation wants.
+#[derive(Debug)]
+pub struct Journal {
+    id: String,
+    kind: OpKind,
+    path: PathBuf,
+    file: Mutex<File>,
+}
+
+impl Journal {
+    /// Creates a new journal in `dir` and records its `Begin` step.
+    pub fn begin(dir: &Path, kind: OpKind) -> io::Result<Self> {
+        std::fs::create_dir_all(dir)?;
+
+        let millis = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis();
+        let pid = std::process::id();
+        let id = format!("{millis}-{pid}");
+        let path = dir.join(format!("{id}.jsonl"));
+
+        let file = OpenOptions::new()
+            .create_new(true)
+            .append(true)
+            .open(&path)?;
+
+        let journal = Self {
+            id: id.clone(),
+            kind,
+            path,
+            file: Mutex::new(file),
+        };
+        journal.record(&Step::Begin { id, kind, pid })?;
+        Ok(journal)
+    }
+
+    /// Operation id, also stored in the database when the operation commits.
+    pub fn id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn kind(&self) -> OpKind {
+        self.kind
+    }
+
+    /// Appends a step and syncs it to disk.
+    pub fn record(&self, step: &Step) -> io::Result<()> {
+        let mut line = serde_json::to_vec(step).map_err(io::Error::other)?;
+        line.push(b'\n');
+
+        let mut file = self
+            .file
+            .lock()
+            .map_err(|_| io::Error::other("journal lock poisoned"))?;
+        file.write_all(&line)?;
+        file.sync_data()
+    }
+
+    /// Records a symlink to `target` about to be placed at `path`.
+    pub fn record_link(&self, path: &Path, target: &Path) -> io::Result<()> {
+        let previous = std::fs::read_link(path).ok();
+        self.record(&Step::Link {
+            path: path.to_path_buf(),
+            target: target.to_path_buf(),
+            previous,
+        })
+    }
+
+    /// Marks the operation complete by deleting the journal and any
+    /// directories it moved aside.
+    pub fn finish(self) -> io::Result<()> {
+        discard_backups(&read_steps(&self.path)?);
+        std::fs::remove_file(&self.path)
+    }
+
+    /// Undoes everything recorded so far and deletes the journal.
+    ///
+    /// Used when an operation fails before its database commit.
+    pub fn abort(self) -> io::Result<()> {
+        let steps = read_steps(&self.path)?;
+        match self.kind {
+            OpKind::Install | OpKind::Switch => roll_back(&steps),
+            OpKind::Remove => {
+                roll_forward(&steps);
+            }
+        }
+        self.finish()
+    }
+}
+
+/// Undoes the filesystem side of an install that never committed.
+pub fn roll_back(steps: &[Step]) {
+    for step in steps.iter().rev() {
+        match step {
+            Step::Link { path, previous, .. } => match previous {
+                Some(previous) => {
+                    let _ = replace_symlink(previous, path);
+                }
+                None if path.is_symlink() => {
+                    let _ = std::fs::remove_file(path);
+                }
+                None => {}
+            },
+            Step::Stage { path } => {
+                let _ = std::fs::remove_dir_all(path);
+            }
+            // Only move it back if the rename actually happened
+            Step::Backup { path, backup } if backup.exists() => {
+                let _ = std::fs::remove_dir_all(path);
+                let _ = std::fs::rename(backup, path);
+            }
+            Step::Begin { .. }
+            | Step::Backup { .. }
+            | Step::Unlink { .. }
+            | Step::Forget { .. } => {}
+        }
+    }
+}
+
+/// Deletes the directories a completed operation moved aside.
+pub fn discard_backups(steps: &[Step]) {
+    for step in steps {
+        if let Step::Backup { backup, .. } = step {
+            let _ = std::fs::remove_dir_all(backup);
+        }
+    }
+}
+
+/// Finishes deleting the files of an interrupted removal.
+///
+/// A removal that changes the profile journals the switch to its new
+/// generation last; until that step is on disk nothing has been deleted, so
+/// the staged generation is rolled back instead. Returns whether the removal
+/// was rolled forward.
+pub fn roll_forward(steps: &[Step]) -> bool {
+    let staged = steps.iter().any(|s| matches!(s, Step::Stage { .. }));
+    let scheduled = steps.iter().any(|s| matches!(s, Step::Link { .. }));
+    if staged && !scheduled {
+        roll_back(steps);
+        return false;
+    }
+
+    for step in steps {
+        match step {
+            Step::Unlink { path } => match std::fs::symlink_metadata(path) {
+                Ok(meta) if meta.is_dir() => {
+                    let _ = std::fs::remove_dir_all(path);
+                }
+                Ok(_) => {
+                    let _ = std::fs::remove_file(path);
+                }
+                Err(_) => {}
+            },
+            Step::Link { path, target, .. } => {
+                let _ = replace_symlink(target, path);
+            }
+            Step::Begin { .. } | Step::Stage { .. } | Step::Backup { .. } | Step::Forget { .. } => {
+            }
+        }
+    }
+    true
+}
+
+/// Atomically points the symlink at `link` to `target`.
+///
+/// The new link is created beside the old one and renamed over it, so
+/// readers always see either the old or the new target.
+pub fn replace_symlink(target: &Path, link: &Path) -> io::Result<()> {
+    let mut staging = link.as_os_str().to_owned();
+    staging.push(format!(".{}.tmp", std::process::id()));
+    let staging = PathBuf::from(staging);
+
+    let _ = std::fs::remove_file(&staging);
+    std::os::unix::fs::symlink(target, &staging)?;
+    std::fs::rename(&staging, link).inspect_err(|_| {
+        let _ = std::fs::remove_file(&staging);
+    })
+}
+
+/// Reads every journal left in `dir`, oldest first.
+pub fn pending(dir: &Path) -> io::Result<Vec<(PathBuf, Vec<Step>)>> {
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(dir) {
+        Ok(entries) => entries
+            .flatten()
+            .map(|e| e.path())
+            .filter(|p| p.extension().is_some_and(|e| e == "jsonl"))
+            .collect(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    paths.sort();
+
+    let mut journals = Vec::with_capacity(paths.len());
+    for path in paths {
+        let steps = read_steps(&path)?;
+        journals.push((path, steps));
+    }
+    Ok(journals)
+}
+
+fn read_steps(path: &Path) -> io::Result<Vec<Step>> {
+    let reader = BufReader::new(File::open(path)?);
+    Ok(reader
+        .lines()
+        .map_while(Result::ok)
+        .map_while(|line| serde_json::from_str(&line).ok())
+        .collect())
+}
+
+/// Whether a process with this pid is still running.
+#[allow(unsafe_code)]
+pub fn is_alive(pid: u32) -> bool {
+    if pid == std::process::id() {
+        return true;
+    }
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // SAFETY: signal 0 performs only the existence/permission check.
+    let rc = unsafe { libc::kill(pid, 0) };
+    rc == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+/// Aborts the process at a named phase when `APL_CRASH_AT` asks for it.
+///
+/// Only compiled into debug builds; the crash-recovery tests use it to kill
+/// `apl` between the journal, filesystem and database steps.
+pub fn crash_point(phase: &str) {
+    #[cfg(debug_assertions)]
+    if std::env::var("APL_CRASH_AT").is_ok_and(|p| p == phase) {
+        std::process::abort();
+    }
+    #[cfg(not(debug_assertions))]
+    let _ = phase;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_journal_roundtrip() {
+        let dir = tempdir().unwrap();
+        let journal = Journal::begin(dir.path(), OpKind::Install).unwrap();
+        journal
+            .record(&Step::Stage {
+                path: PathBuf::from("/store/jq/1.7"),
+            })
+            .unwrap();
+        let id = journal.id().to_string();
+        drop(journal);
+
+        let pending = pending(dir.path()).unwrap();
+        assert_eq!(pending.len(), 1);
+        let steps = &pending[0].1;
+        assert_eq!(
+            steps[0],
+            Step::Begin {
+                id,
+                kind: OpKind::Install,
+                pid: std::process::id()
+            }
+        );
+        assert_eq!(
+            steps[1],
+            Step::Stage {
+                path: PathBuf::from("/store/jq/1.7")
+            }
+        );
+    }
+
+    #[test]
+    fn test_torn_line_is_ignored() {
+        let dir = tempdir().unwrap();
+        let journal = Journal::begin(dir.path(), OpKind::Remove).unwrap();
+        journal
+            .record(&Step::Forget {
+                package: "jq".to_string(),
+            })
+            .unwrap();
+        let path = journal.path.clone();
+        drop(journal);
+
+        let mut file = OpenOptions::new().append(true).open(&path).unwrap();
+        file.write_all(br#"{"step":"unlink","pa"#).unwrap();
+
+        let pending = pending(dir.path()).unwrap();
+        assert_eq!(pending[0].1.len(), 2);
+    }
+
+    #[test]
+    fn test_abort_restores_previous_links() {
+        let dir = tempdir().unwrap();
+        let bin = dir.path().join("bin");
+        let staged = dir.path().join("store/jq/1.7");
+        std::fs::create_dir_all(&bin).unwrap();
+        std::fs::create_dir_all(&staged).unwrap();
+
+        let link = bin.join("jq");
+        let old_target = dir.path().join("store/jq/1.6/jq");
+        std::os::unix::fs::symlink(&old_target, &link).unwrap();
+
+        let journal = Journal::begin(&dir.path().join("journal"), OpKind::Install).unwrap();
+        journal
+            .record(&Step::Stage {
+                path: staged.clone(),
+            })
+            .unwrap();
+        journal.record_link(&link, &staged.join("jq")).unwrap();
+        std::fs::remove_file(&link).unwrap();
+        std::os::unix::fs::symlink(staged.join("jq"), &link).unwrap();
+
+        journal.abort().unwrap();
+
+        assert_eq!(std::fs::read_link(&link).unwrap(), old_target);
+        assert!(!staged.exists());
+        assert!(pending(&dir.path().join("journal")).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_backup_restored_on_abort_and_dropped_on_finish() {
+        let dir = tempdir().unwrap();
+        let app = dir.path().join("Demo.app");
+        let backup = dir.path().join(".Demo.app.old");
+        let replace = |journal: &Journal| {
+            journal
+                .record(&Step::Backup {
+                    path: app.clone(),
+                    backup: backup.clone(),
+                })
+                .unwrap();
+            std::fs::rename(&app, &backup).unwrap();
+            journal.record(&Step::Stage { path: app.clone() }).unwrap();
+            std::fs::create_dir(&app).unwrap();
+            std::fs::write(app.join("version"), "2").unwrap();
+        };
+        std::fs::create_dir(&app).unwrap();
+        std::fs::write(app.join("version"), "1").unwrap();
+
+        let journal = Journal::begin(&dir.path().join("journal"), OpKind::Install).unwrap();
+        replace(&journal);
+        journal.abort().unwrap();
+        assert_eq!(std::fs::read_to_string(app.join("version")).unwrap(), "1");
+        assert!(!backup.exists());
+
+        let journal = Journal::begin(&dir.path().join("journal"), OpKind::Install).unwrap();
+        replace(&journal);
+        journal.finish().unwrap();
+        assert_eq!(std::fs::read_to_string(app.join("version")).unwrap(), "2");
+        assert!(!backup.exists());
+    }
+
+    #[test]
+    fn test_finish_removes_journal() {
+        let dir = tempdir().unwrap();
+        let journal = Journal::begin(dir.path(), OpKind::Install).unwrap();
+        journal.finish().unwrap();
+        assert!(pending(dir.path()).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,347 @@
+//! Process lock
+//!
+//! Serializes `apl` processes that touch `~/.apl/store`, `~/.apl/bin`,
+//! the index and `state.db`. Mutating commands take the lock exclusively;
+//! read-only commands share it so they never observe a half-written state.
+//!
+//! The lock is a directory of marker files under `APL_HOME` rather than a
+//! kernel lock, so every holder is identified by pid. That gives us useful
+//! "waiting for pid N" messages and lets us detect stale locks left behind by
+//! a process that was killed: a marker whose pid is no longer running is
+//! simply removed.
+//!
+//! A writer first claims `exclusive`, then waits for readers to drain. A
+//! reader registers `shared-<pid>`, then checks for a writer and backs off if
+//! one appeared. Either side always sees the other, so they never both win.
+//!
+//! Clearing a stale `exclusive` marker is the one step that can't be done by
+//! name alone: two waiters may read the same dead pid, and by the time the
+//! slower one unlinks the marker the faster one may have replaced it with its
+//! own live claim. Reapers therefore take a kernel lock on `reap` and re-read
+//! the marker under it; claims only ever create the marker, so what the
+//! reaper read is still what it removes.
+
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use thiserror::Error;
+
+use crate::store::journal::is_alive;
+
+const EXCLUSIVE: &str = "exclusive";
+const REAP: &str = "reap";
+const SHARED_PREFIX: &str = "shared-";
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+#[derive(Error, Debug)]
+pub enum LockError {
+    #[error("Another apl process (pid {0}) is running; retry without --no-wait to wait for it")]
+    Busy(u32),
+
+    #[error("Failed to lock {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+}
+
+/// How a command holds the process lock.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LockMode {
+    /// Read-only commands; any number may run together
+    Shared,
+    /// Mutating commands; runs alone
+    Exclusive,
+}
+
+/// A held process lock, released on drop.
+#[derive(Debug)]
+pub struct ProcessLock {
+    marker: PathBuf,
+}
+
+impl ProcessLock {
+    /// Takes the lock in `dir`.
+    ///
+    /// When another process holds a conflicting lock, `on_wait` is called with
+    /// its pid each time the holder changes, and we poll until it goes away.
+    /// With `wait` unset, [`LockError::Busy`] is returned instead.
+    pub fn acquire(
+        dir: &Path,
+        mode: LockMode,
+        wait: bool,
+        mut on_wait: impl FnMut(u32),
+    ) -> Result<Self, LockError> {
+        fs::create_dir_all(dir).map_err(|e| io_error(dir, e))?;
+
+        let mut waiting_on = None;
+        let mut block = |holder: u32| {
+            if !wait {
+                return Err(LockError::Busy(holder));
+            }
+            if waiting_on != Some(holder) {
+                waiting_on = Some(holder);
+                on_wait(holder);
+            }
+            std::thread::sleep(POLL_INTERVAL);
+            Ok(())
+        };
+
+        match mode {
+            LockMode::Exclusive => {
+                let marker = dir.join(EXCLUSIVE);
+                while let Some(holder) = claim_exclusive(dir)? {
+                    block(holder)?;
+                }
+                let lock = Self { marker };
+                while let Some(reader) = live_reader(dir)? {
+                    // Dropping `lock` on error releases our claim.
+                    block(reader)?;
+                }
+                Ok(lock)
+            }
+            LockMode::Shared => {
+                let marker = dir.join(format!("{SHARED_PREFIX}{}", std::process::id()));
+                loop {
+                    if let Some(holder) = live_writer(dir)? {
+                        block(holder)?;
+                        continue;
+                    }
+                    fs::write(&marker, b"").map_err(|e| io_error(&marker, e))?;
+                    match live_writer(dir)? {
+                        None => return Ok(Self { marker }),
+                        Some(holder) => {
+                            let _ = fs::remove_file(&marker);
+                            block(holder)?;
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Drop for ProcessLock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.marker);
+    }
+}
+
+/// Takes the global lock under `APL_HOME`, telling the user if we have to wait.
+pub fn lock(mode: LockMode, wait: bool) -> Result<ProcessLock, LockError> {
+    ProcessLock::acquire(&crate::lock_path(), mode, wait, |pid| {
+        crate::ui::Output::new().info(&format!("Waiting for another apl process (pid {pid})..."));
+    })
+}
+
+/// Tries to create the exclusive marker, returning the live holder if taken.
+///
+/// The marker is written under a private name and hard-linked into place so
+/// it never exists without its pid.
+fn claim_exclusive(dir: &Path) -> Result<Option<u32>, LockError> {
+    let pid = std::process::id();
+    let marker = dir.join(EXCLUSIVE);
+    let staging = dir.join(format!(".{EXCLUSIVE}-{pid}"));
+
+    let mut file = fs::File::create(&staging).map_err(|e| io_error(&staging, e))?;
+    file.write_all(pid.to_string().as_bytes())
+        .map_err(|e| io_error(&staging, e))?;
+    drop(file);
+
+    let linked = fs::hard_link(&staging, &marker);
+    let _ = fs::remove_file(&staging);
+    match linked {
+        Ok(()) => Ok(None),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => match live_writer(dir)? {
+            // The holder vanished or was stale; try again straight away.
+            None => claim_exclusive(dir),
+            holder => Ok(holder),
+        },
+        Err(e) => Err(io_error(&marker, e)),
+    }
+}
+
+/// The pid holding the exclusive marker, clearing it if the holder is gone.
+fn live_writer(dir: &Path) -> Result<Option<u32>, LockError> {
+    let marker = dir.join(EXCLUSIVE);
+    let Some(content) = read_marker(&marker)? else {
+        return Ok(None);
+    };
+    match content.trim().parse::<u32>() {
+        Ok(pid) if is_alive(pid) => Ok(Some(pid)),
+        _ => {
+            reap_writer(dir, &content)?;
+            Ok(None)
+        }
+    }
+}
+
+/// Contents of the marker at `marker`, or `None` if there isn't one.
+fn read_marker(marker: &Path) -> Result<Option<String>, LockError> {
+    match fs::read_to_string(marker) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(io_error(marker, e)),
+    }
+}
+
+/// Removes the exclusive marker if it still reads `stale`.
+///
+/// The kernel lock on `reap` is dropped with the file, even if we are killed.
+fn reap_writer(dir: &Path, stale: &str) -> Result<(), LockError> {
+    let reap = dir.join(REAP);
+    let guard = fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(&reap)
+        .map_err(|e| io_error(&reap, e))?;
+    guard.lock().map_err(|e| io_error(&reap, e))?;
+
+    let marker = dir.join(EXCLUSIVE);
+    if read_marker(&marker)?.is_some_and(|content| content == stale) {
+        tracing::debug!("Removing stale apl lock {}", marker.display());
+        let _ = fs::remove_file(&marker);
+    }
+    Ok(())
+}
+
+/// Any live reader other than us, clearing markers of dead readers.
+fn live_reader(dir: &Path) -> Result<Option<u32>, LockError> {
+    let own = std::process::id();
+    let entries = fs::read_dir(dir).map_err(|e| io_error(dir, e))?;
+
+    for entry in entries.flatten() {
+        let name = entry.file_name();
+        let Some(pid) = name
+            .to_str()
+            .and_then(|n| n.strip_prefix(SHARED_PREFIX))
+            .and_then(|p| p.parse::<u32>().ok())
+        else {
+            continue;
+        };
+        if pid == own {
+            continue;
+        }
+        if is_alive(pid) {
+            return Ok(Some(pid));
+        }
+        tracing::debug!("Removing stale apl reader lock for pid {pid}");
+        let _ = fs::remove_file(entry.path());
+    }
+    Ok(None)
+}
+
+fn io_error(path: &Path, source: io::Error) -> LockError {
+    LockError::Io {
+        path: path.to_path_buf(),
+        source,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    /// A pid that can never belong to a running process.
+    const DEAD_PID: u32 = u32::MAX;
+
+    fn try_lock(dir: &Path, mode: LockMode) -> Result<ProcessLock, LockError> {
+        ProcessLock::acquire(dir, mode, false, |_| {})
+    }
+
+    #[test]
+    fn test_exclusive_excludes_everyone() {
+        let dir = tempdir().unwrap();
+        let held = try_lock(dir.path(), LockMode::Exclusive).unwrap();
+
+        let pid = std::process::id();
+        assert!(
+            matches!(try_lock(dir.path(), LockMode::Exclusive), Err(LockError::Busy(p)) if p == pid)
+        );
+        assert!(
+            matches!(try_lock(dir.path(), LockMode::Shared), Err(LockError::Busy(p)) if p == pid)
+        );
+
+        drop(held);
+        assert!(try_lock(dir.path(), LockMode::Shared).is_ok());
+    }
+
+    #[test]
+    fn test_shared_blocks_writers_only() {
+        let dir = tempdir().unwrap();
+        // Another live reader; our parent process will do.
+        let parent = std::os::unix::process::parent_id();
+        fs::write(dir.path().join(format!("{SHARED_PREFIX}{parent}")), b"").unwrap();
+
+        let reader = try_lock(dir.path(), LockMode::Shared).unwrap();
+        assert!(
+            matches!(try_lock(dir.path(), LockMode::Exclusive), Err(LockError::Busy(p)) if p == parent)
+        );
+
+        // A failed writer must not leave its claim behind.
+        assert!(!dir.path().join(EXCLUSIVE).exists());
+        drop(reader);
+    }
+
+    #[test]
+    fn test_stale_locks_are_cleared() {
+        let dir = tempdir().unwrap();
+        fs::write(dir.path().join(EXCLUSIVE), DEAD_PID.to_string()).unwrap();
+        fs::write(dir.path().join(format!("{SHARED_PREFIX}{DEAD_PID}")), b"").unwrap();
+
+        let lock = try_lock(dir.path(), LockMode::Exclusive).unwrap();
+        assert!(
+            !dir.path()
+                .join(format!("{SHARED_PREFIX}{DEAD_PID}"))
+                .exists()
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join(EXCLUSIVE)).unwrap(),
+            std::process::id().to_string()
+        );
+        drop(lock);
+        assert!(!dir.path().join(EXCLUSIVE).exists());
+    }
+
+    #[test]
+    fn test_reaper_keeps_a_live_claim() {
+        let dir = tempdir().unwrap();
+        let marker = dir.path().join(EXCLUSIVE);
+
+        // Another waiter already cleared the dead pid and claimed the lock
+        fs::write(&marker, std::process::id().to_string()).unwrap();
+        reap_writer(dir.path(), &DEAD_PID.to_string()).unwrap();
+        assert!(marker.exists());
+
+        fs::write(&marker, DEAD_PID.to_string()).unwrap();
+        reap_writer(dir.path(), &DEAD_PID.to_string()).unwrap();
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn test_waits_for_holder_to_exit() {
+        let dir = tempdir().unwrap();
+        let held = try_lock(dir.path(), LockMode::Exclusive).unwrap();
+
+        let path = dir.path().to_path_buf();
+        let waiter = std::thread::spawn(move || {
+            let mut waited_on = Vec::new();
+            let lock = ProcessLock::acquire(&path, LockMode::Shared, true, |pid| {
+                waited_on.push(pid);
+            });
+            (lock.is_ok(), waited_on)
+        });
+
+        std::thread::sleep(POLL_INTERVAL * 3);
+        drop(held);
+
+        let (acquired, waited_on) = waiter.join().unwrap();
+        assert!(acquired);
+        assert_eq!(waited_on, vec![std::process::id()]);
+    }
+}
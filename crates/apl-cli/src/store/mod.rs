@@ -1,5 +1,11 @@
 pub mod actor;
+pub mod cache;
 pub mod db;
 pub mod history;
+pub mod journal;
+pub mod lock;
+pub mod pin;
+pub mod profile;
+pub mod source;
 
 pub use actor::DbHandle;
@@ -0,0 +1,135 @@
+//! Package pins
+//!
+//! A pin keeps `apl upgrade` (and `apl update --all`) away from a package.
+//! A plain pin holds the installed version; a pin with a semver requirement
+//! lets the package float within it, e.g. `terraform@1.5.x`.
+
+use apl_schema::index::IndexEntry;
+use apl_schema::version::is_newer;
+use serde::{Deserialize, Serialize};
+
+/// A pinned package
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Pin {
+    pub package: String,
+    /// Semver requirement upgrades must satisfy; `None` holds the installed version
+    pub requirement: Option<String>,
+    /// Pin time in seconds
+    pub pinned_at: i64,
+}
+
+impl Pin {
+    /// Checks that `requirement` is a valid semver requirement.
+    pub fn validate(requirement: &str) -> Result<(), semver::Error> {
+        semver::VersionReq::parse(requirement).map(|_| ())
+    }
+
+    /// Whether the pin lets the package move to `version`.
+    pub fn allows(&self, version: &str) -> bool {
+        let Some(requirement) = &self.requirement else {
+            return false;
+        };
+        match (
+            semver::VersionReq::parse(requirement),
+            semver::Version::parse(version.trim_start_matches('v')),
+        ) {
+            (Ok(req), Ok(version)) => req.matches(&version),
+            _ => false,
+        }
+    }
+}
+
+/// A newer release of an installed package
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Outdated {
+    /// Newest release in the index
+    pub latest: String,
+    /// Newest release the package's pin allows, if any is newer than installed
+    pub target: Option<String>,
+}
+
+impl Outdated {
+    /// Whether a pin keeps the package below the latest release.
+    pub fn is_held(&self) -> bool {
+        self.target.as_ref() != Some(&self.latest)
+    }
+}
+
+/// Finds the newest release of `entry` newer than `installed`, and the
+/// newest one `pin` allows.
+pub fn outdated(entry: &IndexEntry, installed: &str, pin: Option<&Pin>) -> Option<Outdated> {
+    let latest = &entry.latest()?.version;
+    if !is_newer(installed, latest) {
+        return None;
+    }
+    // Releases are sorted newest first.
+    let target = entry
+        .releases
+        .iter()
+        .map(|r| &r.version)
+        .filter(|v| is_newer(installed, v))
+        .find(|v| pin.is_none_or(|p| p.allows(v)))
+        .cloned();
+    Some(Outdated {
+        latest: latest.clone(),
+        target,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use apl_schema::index::VersionInfo;
+
+    fn entry(versions: &[&str]) -> IndexEntry {
+        IndexEntry {
+            name: "terraform".to_string(),
+            releases: versions
+                .iter()
+                .map(|v| VersionInfo {
+                    version: (*v).to_string(),
+                    ..VersionInfo::default()
+                })
+                .collect(),
+            ..IndexEntry::default()
+        }
+    }
+
+    fn pin(requirement: Option<&str>) -> Pin {
+        Pin {
+            package: "terraform".to_string(),
+            requirement: requirement.map(str::to_string),
+            pinned_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_outdated_respects_pins() {
+        let entry = entry(&["1.6.0", "1.5.7", "1.5.6", "1.4.0"]);
+
+        let free = outdated(&entry, "1.5.6", None).unwrap();
+        assert_eq!(free.target.as_deref(), Some("1.6.0"));
+        assert!(!free.is_held());
+
+        let range = outdated(&entry, "1.5.6", Some(&pin(Some("1.5.x")))).unwrap();
+        assert_eq!(range.latest, "1.6.0");
+        assert_eq!(range.target.as_deref(), Some("1.5.7"));
+        assert!(range.is_held());
+
+        let hold = outdated(&entry, "1.5.6", Some(&pin(None))).unwrap();
+        assert_eq!(hold.target, None);
+        assert!(hold.is_held());
+
+        assert_eq!(outdated(&entry, "1.6.0", None), None);
+    }
+
+    #[test]
+    fn test_pin_requirements() {
+        assert!(Pin::validate("1.5.x").is_ok());
+        assert!(Pin::validate("~1.5").is_ok());
+        assert!(Pin::validate("not a range").is_err());
+        assert!(pin(Some("~1.5")).allows("1.5.9"));
+        assert!(!pin(Some("~1.5")).allows("1.6.0"));
+        assert!(!pin(Some("1.5.x")).allows("nightly"));
+    }
+}
@@ -0,0 +1,885 @@
+//! Profile generations
+//!
+//! `~/.apl/bin` is a symlink into the current profile generation:
+//!
+//! ```text
+//! ~/.apl/
+//! ├── bin -> profiles/current/bin
+//! └── profiles/
+//!     ├── 1/
+//!     │   ├── bin/            symlinks into the store
+//!     │   └── manifest.json   packages in this generation
+//!     ├── 2/
+//!     └── current -> 2
+//! ```
+//!
+//! A generation is never modified once written. Installs, upgrades and
+//! removals build the next generation from the current one and flip
+//! `current` with a single atomic rename, so the whole toolset can be rolled
+//! back to any generation that hasn't been pruned.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::store::db::{InstallReason, InstallRecord};
+use crate::store::journal::{Journal, Step, replace_symlink};
+
+const CURRENT: &str = "current";
+const MANIFEST: &str = "manifest.json";
+const STAGING_SUFFIX: &str = ".tmp";
+
+/// A package as it appears in a generation.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProfileEntry {
+    pub name: String,
+    pub version: String,
+    pub sha256: String,
+    pub size_bytes: u64,
+    pub reason: InstallReason,
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    /// Links this package owns in the generation's `bin` directory
+    #[serde(default)]
+    pub bins: Vec<String>,
+    /// Other installed versions linked under versioned names, sorted by version
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub side_by_side: Vec<SideBySide>,
+    /// Source the version was installed from
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+}
+
+/// Another version of a package linked next to the active one.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SideBySide {
+    pub version: String,
+    /// Versioned links, e.g. `terraform-1.5`
+    pub bins: Vec<String>,
+}
+
+impl ProfileEntry {
+    /// The database record for this package, with its links under `bin_dir`.
+    pub fn record(&self, bin_dir: &Path) -> InstallRecord {
+        let link = |b: &String| bin_dir.join(b).to_string_lossy().to_string();
+        InstallRecord {
+            name: self.name.clone(),
+            version: self.version.clone(),
+            sha256: self.sha256.clone(),
+            size_bytes: self.size_bytes,
+            reason: self.reason,
+            dependencies: self.dependencies.clone(),
+            artifacts: vec![],
+            active_files: self
+                .bins
+                .iter()
+                .map(|b| (link(b), "SYMLINK".to_string()))
+                .collect(),
+            side_by_side: Some(
+                self.side_by_side
+                    .iter()
+                    .flat_map(|side| side.bins.iter().map(|b| (link(b), side.version.clone())))
+                    .collect(),
+            ),
+            source: self.source.clone(),
+        }
+    }
+
+    /// Which version a link of this package points to, if it owns it.
+    pub fn link_version(&self, link: &str) -> Option<&str> {
+        if self.bins.iter().any(|b| b == link) {
+            return Some(&self.version);
+        }
+        self.side_by_side
+            .iter()
+            .find(|side| side.bins.iter().any(|b| b == link))
+            .map(|side| side.version.as_str())
+    }
+}
+
+impl From<&InstallRecord> for ProfileEntry {
+    fn from(record: &InstallRecord) -> Self {
+        let bins = record
+            .active_files
+            .iter()
+            .filter(|(_, kind)| kind == "SYMLINK")
+            .filter_map(|(path, _)| Path::new(path).file_name())
+            .map(|name| name.to_string_lossy().to_string())
+            .collect();
+
+        Self {
+            name: record.name.clone(),
+            version: record.version.clone(),
+            sha256: record.sha256.clone(),
+            size_bytes: record.size_bytes,
+            reason: record.reason,
+            dependencies: record.dependencies.clone(),
+            bins,
+            side_by_side: Vec::new(),
+            source: record.source.clone(),
+        }
+    }
+}
+
+/// A generation's manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Generation {
+    pub id: u64,
+    /// Unix timestamp in seconds
+    pub created_at: i64,
+    /// The operation that produced this generation, e.g. "install jq"
+    pub description: String,
+    /// Packages sorted by name
+    pub packages: Vec<ProfileEntry>,
+}
+
+impl Generation {
+    pub fn package(&self, name: &str) -> Option<&ProfileEntry> {
+        self.packages.iter().find(|p| p.name == name)
+    }
+
+    /// Package changes going from this generation to `to`, sorted by name.
+    pub fn diff<'a>(&'a self, to: &'a Self) -> Vec<Change<'a>> {
+        let mut changes: Vec<Change<'a>> = Vec::new();
+        for old in &self.packages {
+            match to.package(&old.name) {
+                None => changes.push(Change::Removed(old)),
+                Some(new) if new.version != old.version || new.side_by_side != old.side_by_side => {
+                    changes.push(Change::Changed { from: old, to: new });
+                }
+                Some(_) => {}
+            }
+        }
+        for new in &to.packages {
+            if self.package(&new.name).is_none() {
+                changes.push(Change::Added(new));
+            }
+        }
+        changes.sort_by(|a, b| a.name().cmp(b.name()));
+        changes
+    }
+}
+
+/// One package's difference between two generations.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Change<'a> {
+    Added(&'a ProfileEntry),
+    Removed(&'a ProfileEntry),
+    Changed {
+        from: &'a ProfileEntry,
+        to: &'a ProfileEntry,
+    },
+}
+
+impl Change<'_> {
+    pub fn name(&self) -> &str {
+        match self {
+            Self::Added(p) | Self::Removed(p) | Self::Changed { to: p, .. } => &p.name,
+        }
+    }
+}
+
+/// The generations under a profiles directory.
+#[derive(Debug)]
+pub struct Profiles {
+    root: PathBuf,
+    bin: PathBuf,
+}
+
+impl Profiles {
+    /// Opens `~/.apl/profiles`, or returns `None` if `~/.apl/bin` hasn't been
+    /// converted into a generation yet (see [`Profiles::init`]).
+    pub fn open() -> io::Result<Option<Self>> {
+        Self::open_at(&crate::profiles_path(), &crate::bin_path())
+    }
+
+    pub fn open_at(root: &Path, bin: &Path) -> io::Result<Option<Self>> {
+        let profiles = Self {
+            root: root.to_path_buf(),
+            bin: bin.to_path_buf(),
+        };
+        let ready = profiles.current()?.is_some() && bin.is_symlink();
+        Ok(ready.then_some(profiles))
+    }
+
+    /// Converts a plain `~/.apl/bin` directory into the first generation.
+    ///
+    /// Existing links are moved as they are; `installed` describes the
+    /// packages that own them.
+    pub fn init(installed: &[InstallRecord]) -> io::Result<Self> {
+        Self::init_at(&crate::profiles_path(), &crate::bin_path(), installed)
+    }
+
+    pub fn init_at(root: &Path, bin: &Path, installed: &[InstallRecord]) -> io::Result<Self> {
+        fs::create_dir_all(root)?;
+        let profiles = Self {
+            root: root.to_path_buf(),
+            bin: bin.to_path_buf(),
+        };
+
+        let bin_is_dir = bin.is_dir() && !bin.is_symlink();
+        if bin_is_dir || profiles.current()?.is_none() {
+            let id = profiles.next_id()?;
+            let staging = profiles.staging_dir(id);
+            let links = staging.join("bin");
+
+            if bin_is_dir {
+                let _ = fs::remove_dir_all(&staging);
+                fs::create_dir_all(&staging)?;
+                fs::rename(bin, &links)?;
+            } else if !links.exists() {
+                // A leftover `links` means an earlier attempt died after
+                // moving `bin` away; carry on from there.
+                if bin.is_symlink() {
+                    fs::remove_file(bin)?;
+                }
+                fs::create_dir_all(&links)?;
+            }
+
+            let generation = Generation {
+                id,
+                created_at: now(),
+                description: "initial".to_string(),
+                packages: installed
+                    .iter()
+                    // App bundles live in /Applications, outside any profile
+                    .filter(|r| !r.active_files.iter().any(|(_, kind)| kind == "APP_BUNDLE"))
+                    .map(ProfileEntry::from)
+                    .collect(),
+            };
+            write_manifest(&staging, &generation)?;
+            fs::rename(&staging, profiles.dir(id))?;
+            profiles.activate(id, None)?;
+        }
+
+        if !bin.is_symlink() {
+            let current_bin = root.join(CURRENT).join("bin");
+            let target = bin
+                .parent()
+                .and_then(|home| current_bin.strip_prefix(home).ok())
+                .map_or_else(|| current_bin.clone(), Path::to_path_buf);
+            std::os::unix::fs::symlink(target, bin)?;
+        }
+
+        Ok(profiles)
+    }
+
+    /// The generation `current` points at.
+    pub fn current(&self) -> io::Result<Option<u64>> {
+        match fs::read_link(self.root.join(CURRENT)) {
+            Ok(target) => Ok(target
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.parse().ok())),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Ids of all complete generations, oldest first.
+    pub fn ids(&self) -> io::Result<Vec<u64>> {
+        let mut ids: Vec<u64> = match fs::read_dir(&self.root) {
+            Ok(entries) => entries
+                .flatten()
+                .filter(|e| e.file_type().is_ok_and(|t| t.is_dir()))
+                .filter_map(|e| e.file_name().to_str()?.parse().ok())
+                .collect(),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e),
+        };
+        ids.sort_unstable();
+        Ok(ids)
+    }
+
+    /// All generations, oldest first.
+    pub fn list(&self) -> io::Result<Vec<Generation>> {
+        self.ids()?.into_iter().map(|id| self.load(id)).collect()
+    }
+
+    pub fn load(&self, id: u64) -> io::Result<Generation> {
+        let path = self.dir(id).join(MANIFEST);
+        let content = fs::read(&path).map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                io::Error::new(e.kind(), format!("generation {id} does not exist"))
+            } else {
+                e
+            }
+        })?;
+        serde_json::from_slice(&content).map_err(io::Error::other)
+    }
+
+    /// Starts the next generation from the current one.
+    pub fn stage(&self, description: &str) -> io::Result<ProfileBuilder> {
+        self.clean_staging()?;
+
+        let (packages, links) = match self.current()? {
+            Some(current) => {
+                let generation = self.load(current)?;
+                let links = read_entries(&self.dir(current).join("bin"))?;
+                (generation.packages, links)
+            }
+            None => (Vec::new(), BTreeMap::new()),
+        };
+
+        Ok(ProfileBuilder {
+            id: self.next_id()?,
+            root: self.root.clone(),
+            description: description.to_string(),
+            packages: packages.into_iter().map(|p| (p.name.clone(), p)).collect(),
+            links,
+        })
+    }
+
+    /// Atomically makes `id` the current generation.
+    pub fn activate(&self, id: u64, journal: Option<&Journal>) -> io::Result<()> {
+        if !self.dir(id).is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("generation {id} does not exist"),
+            ));
+        }
+        let link = self.root.join(CURRENT);
+        let target = PathBuf::from(id.to_string());
+        if let Some(journal) = journal {
+            journal.record_link(&link, &target)?;
+        }
+        replace_symlink(&target, &link)
+    }
+
+    /// Deletes a generation other than the current one.
+    pub fn delete(&self, id: u64) -> io::Result<()> {
+        if self.current()? == Some(id) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("generation {id} is the current generation"),
+            ));
+        }
+        fs::remove_dir_all(self.dir(id))
+    }
+
+    /// Directory the active links are reached through (`~/.apl/bin`).
+    pub fn bin_dir(&self) -> &Path {
+        &self.bin
+    }
+
+    fn dir(&self, id: u64) -> PathBuf {
+        self.root.join(id.to_string())
+    }
+
+    fn staging_dir(&self, id: u64) -> PathBuf {
+        self.root.join(format!("{id}{STAGING_SUFFIX}"))
+    }
+
+    fn next_id(&self) -> io::Result<u64> {
+        Ok(self.ids()?.last().map_or(1, |last| last + 1))
+    }
+
+    /// Removes generations that died before they were complete.
+    fn clean_staging(&self) -> io::Result<()> {
+        for entry in fs::read_dir(&self.root)?.flatten() {
+            let name = entry.file_name();
+            let is_staging = name
+                .to_str()
+                .and_then(|n| n.strip_suffix(STAGING_SUFFIX))
+                .is_some_and(|id| id.parse::<u64>().is_ok());
+            if is_staging {
+                fs::remove_dir_all(entry.path())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The next generation, built in memory and written out by [`ProfileBuilder::finish`].
+#[derive(Debug)]
+pub struct ProfileBuilder {
+    id: u64,
+    root: PathBuf,
+    description: String,
+    packages: BTreeMap<String, ProfileEntry>,
+    /// Contents of the generation's `bin` directory by name
+    links: BTreeMap<String, BinEntry>,
+}
+
+impl ProfileBuilder {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn package(&self, name: &str) -> Option<&ProfileEntry> {
+        self.packages.get(name)
+    }
+
+    /// Drops a package and its links, including side-by-side ones.
+    pub fn remove(&mut self, name: &str) -> Option<ProfileEntry> {
+        let entry = self.packages.remove(name)?;
+        let sides = entry.side_by_side.iter().flat_map(|side| &side.bins);
+        for bin in entry.bins.iter().chain(sides) {
+            self.links.remove(bin);
+        }
+        Some(entry)
+    }
+
+    /// Adds a package, replacing any version already in the profile. Versions
+    /// linked side-by-side stay linked.
+    ///
+    /// A link name owned by another package is taken over.
+    pub fn add(&mut self, mut entry: ProfileEntry, links: Vec<(String, PathBuf)>) {
+        if let Some(previous) = self.packages.remove(&entry.name) {
+            for bin in &previous.bins {
+                self.links.remove(bin);
+            }
+            entry.side_by_side = previous.side_by_side;
+        }
+        for (name, _) in &links {
+            let entries = self
+                .packages
+                .values_mut()
+                .chain(std::iter::once(&mut entry));
+            for other in entries {
+                other.bins.retain(|b| b != name);
+                for side in &mut other.side_by_side {
+                    side.bins.retain(|b| b != name);
+                }
+                other.side_by_side.retain(|side| !side.bins.is_empty());
+            }
+        }
+
+        entry.bins = links.iter().map(|(name, _)| name.clone()).collect();
+        entry.bins.sort();
+        entry.bins.dedup();
+        self.links.extend(
+            links
+                .into_iter()
+                .map(|(name, target)| (name, BinEntry::Link(target))),
+        );
+        self.packages.insert(entry.name.clone(), entry);
+    }
+
+    /// Links another installed version of a package under versioned names,
+    /// replacing the links it had if it was already side-by-side.
+    ///
+    /// Unlike [`ProfileBuilder::add`], taking over a link is refused.
+    pub fn add_side_by_side(
+        &mut self,
+        name: &str,
+        version: &str,
+        links: Vec<(String, PathBuf)>,
+    ) -> io::Result<()> {
+        if !self.packages.contains_key(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{name} is not in the profile"),
+            ));
+        }
+        self.remove_side_by_side(name, version);
+        if let Some((link, _)) = links.iter().find(|(link, _)| self.links.contains_key(link)) {
+            let owner = self
+                .packages
+                .values()
+                .find(|p| p.link_version(link).is_some())
+                .map_or("a file", |p| p.name.as_str());
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("`{link}` is already provided by {owner}"),
+            ));
+        }
+
+        let mut bins: Vec<String> = links.iter().map(|(link, _)| link.clone()).collect();
+        bins.sort();
+        bins.dedup();
+        self.links.extend(
+            links
+                .into_iter()
+                .map(|(link, target)| (link, BinEntry::Link(target))),
+        );
+        let entry = self.packages.get_mut(name).expect("checked above");
+        entry.side_by_side.push(SideBySide {
+            version: version.to_string(),
+            bins,
+        });
+        entry.side_by_side.sort_by(|a, b| {
+            match (
+                semver::Version::parse(&a.version),
+                semver::Version::parse(&b.version),
+            ) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                _ => a.version.cmp(&b.version),
+            }
+        });
+        Ok(())
+    }
+
+    /// Unlinks a side-by-side version. Returns whether it was linked.
+    pub fn remove_side_by_side(&mut self, name: &str, version: &str) -> bool {
+        let Some(entry) = self.packages.get_mut(name) else {
+            return false;
+        };
+        let Some(index) = entry.side_by_side.iter().position(|s| s.version == version) else {
+            return false;
+        };
+        for bin in entry.side_by_side.remove(index).bins {
+            self.links.remove(&bin);
+        }
+        true
+    }
+
+    /// Writes the generation to disk. It becomes visible only once complete.
+    ///
+    /// With a journal, the new generation is recorded as staged first so an
+    /// interrupted operation removes it again.
+    pub fn finish(self, journal: Option<&Journal>) -> io::Result<Generation> {
+        if let Some(journal) = journal {
+            journal.record(&Step::Stage {
+                path: self.root.join(self.id.to_string()),
+            })?;
+        }
+
+        let staging = self.root.join(format!("{}{STAGING_SUFFIX}", self.id));
+        let _ = fs::remove_dir_all(&staging);
+        let bin = staging.join("bin");
+        fs::create_dir_all(&bin)?;
+
+        for (name, entry) in &self.links {
+            let path = bin.join(name);
+            match entry {
+                BinEntry::Link(target) => std::os::unix::fs::symlink(target, &path)?,
+                BinEntry::File(source) => {
+                    if fs::hard_link(source, &path).is_err() {
+                        fs::copy(source, &path)?;
+                    }
+                }
+            }
+        }
+
+        let generation = Generation {
+            id: self.id,
+            created_at: now(),
+            description: self.description,
+            packages: self.packages.into_values().collect(),
+        };
+        write_manifest(&staging, &generation)?;
+        fs::rename(&staging, self.root.join(self.id.to_string()))?;
+        Ok(generation)
+    }
+}
+
+/// The generation switch recorded in a journal, as `(previous, new)`.
+///
+/// Used when recovery commits an operation on behalf of a crashed process.
+pub fn activation(steps: &[Step]) -> Option<(u64, u64)> {
+    let generation = |path: &Path| path.file_name()?.to_str()?.parse().ok();
+    steps.iter().rev().find_map(|step| match step {
+        Step::Link {
+            path,
+            target,
+            previous: Some(previous),
+        } if path.file_name().is_some_and(|n| n == CURRENT) => {
+            Some((generation(previous)?, generation(target)?))
+        }
+        _ => None,
+    })
+}
+
+/// An entry in a generation's `bin` directory.
+#[derive(Debug, Clone)]
+enum BinEntry {
+    /// Symlink into the store
+    Link(PathBuf),
+    /// Plain file carried over from the previous generation, such as the
+    /// `apl` binary itself
+    File(PathBuf),
+}
+
+fn read_entries(dir: &Path) -> io::Result<BTreeMap<String, BinEntry>> {
+    let mut entries = BTreeMap::new();
+    for entry in fs::read_dir(dir)?.flatten() {
+        let name = entry.file_name().to_string_lossy().to_string();
+        let path = entry.path();
+        match fs::read_link(&path) {
+            Ok(target) => {
+                entries.insert(name, BinEntry::Link(target));
+            }
+            Err(_) if path.is_file() => {
+                entries.insert(name, BinEntry::File(path));
+            }
+            Err(_) => {}
+        }
+    }
+    Ok(entries)
+}
+
+fn write_manifest(dir: &Path, generation: &Generation) -> io::Result<()> {
+    let content = serde_json::to_vec_pretty(generation).map_err(io::Error::other)?;
+    fs::write(dir.join(MANIFEST), content)
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs() as i64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::journal::OpKind;
+    use tempfile::tempdir;
+
+    struct Fixture {
+        _dir: tempfile::TempDir,
+        root: PathBuf,
+        bin: PathBuf,
+        store: PathBuf,
+    }
+
+    fn fixture() -> Fixture {
+        let dir = tempdir().unwrap();
+        let home = dir.path().to_path_buf();
+        let store = home.join("store");
+        for (name, version) in [("jq", "1.6"), ("jq", "1.7"), ("rg", "14.0")] {
+            let pkg = store.join(name).join(version);
+            fs::create_dir_all(&pkg).unwrap();
+            fs::write(pkg.join(name), version).unwrap();
+        }
+        Fixture {
+            root: home.join("profiles"),
+            bin: home.join("bin"),
+            store,
+            _dir: dir,
+        }
+    }
+
+    fn entry(name: &str, version: &str) -> ProfileEntry {
+        ProfileEntry {
+            name: name.to_string(),
+            version: version.to_string(),
+            sha256: String::new(),
+            size_bytes: 0,
+            reason: InstallReason::Explicit,
+            dependencies: vec![],
+            bins: vec![],
+            side_by_side: vec![],
+            source: None,
+        }
+    }
+
+    impl Fixture {
+        fn link(&self, name: &str, version: &str) -> (String, PathBuf) {
+            (
+                name.to_string(),
+                self.store.join(name).join(version).join(name),
+            )
+        }
+
+        /// What `~/.apl/bin/<name>` currently runs.
+        fn resolved(&self, name: &str) -> Option<String> {
+            fs::read_to_string(self.bin.join(name)).ok()
+        }
+    }
+
+    #[test]
+    fn test_init_adopts_existing_bin() {
+        let fx = fixture();
+        fs::create_dir_all(&fx.bin).unwrap();
+        let (name, target) = fx.link("jq", "1.6");
+        std::os::unix::fs::symlink(&target, fx.bin.join(&name)).unwrap();
+
+        let record = ProfileEntry {
+            bins: vec![name],
+            ..entry("jq", "1.6")
+        }
+        .record(&fx.bin);
+        let profiles = Profiles::init_at(&fx.root, &fx.bin, &[record]).unwrap();
+
+        assert!(fx.bin.is_symlink());
+        assert_eq!(profiles.current().unwrap(), Some(1));
+        assert_eq!(fx.resolved("jq").as_deref(), Some("1.6"));
+        assert_eq!(profiles.load(1).unwrap().packages[0].bins, vec!["jq"]);
+        assert!(Profiles::open_at(&fx.root, &fx.bin).unwrap().is_some());
+    }
+
+    #[test]
+    fn test_generations_switch_whole_toolset() {
+        let fx = fixture();
+        let profiles = Profiles::init_at(&fx.root, &fx.bin, &[]).unwrap();
+
+        let mut next = profiles.stage("install jq rg").unwrap();
+        next.add(entry("jq", "1.6"), vec![fx.link("jq", "1.6")]);
+        next.add(entry("rg", "14.0"), vec![fx.link("rg", "14.0")]);
+        let second = next.finish(None).unwrap();
+        profiles.activate(second.id, None).unwrap();
+
+        let mut next = profiles.stage("upgrade jq, remove rg").unwrap();
+        next.add(entry("jq", "1.7"), vec![fx.link("jq", "1.7")]);
+        next.remove("rg");
+        let third = next.finish(None).unwrap();
+        profiles.activate(third.id, None).unwrap();
+
+        assert_eq!(fx.resolved("jq").as_deref(), Some("1.7"));
+        assert!(fx.resolved("rg").is_none());
+
+        profiles.activate(second.id, None).unwrap();
+        assert_eq!(fx.resolved("jq").as_deref(), Some("1.6"));
+        assert_eq!(fx.resolved("rg").as_deref(), Some("14.0"));
+        assert_eq!(profiles.ids().unwrap(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    fn test_plain_files_are_carried_over() {
+        let fx = fixture();
+        fs::create_dir_all(&fx.bin).unwrap();
+        fs::write(fx.bin.join("apl"), "apl").unwrap();
+        let profiles = Profiles::init_at(&fx.root, &fx.bin, &[]).unwrap();
+
+        let mut next = profiles.stage("install jq").unwrap();
+        next.add(entry("jq", "1.6"), vec![fx.link("jq", "1.6")]);
+        next.finish(None).unwrap();
+        profiles.activate(2, None).unwrap();
+
+        assert_eq!(fx.resolved("apl").as_deref(), Some("apl"));
+        assert_eq!(fx.resolved("jq").as_deref(), Some("1.6"));
+    }
+
+    #[test]
+    fn test_add_takes_over_conflicting_links() {
+        let fx = fixture();
+        let profiles = Profiles::init_at(&fx.root, &fx.bin, &[]).unwrap();
+
+        let mut next = profiles.stage("install").unwrap();
+        next.add(entry("jq", "1.6"), vec![fx.link("jq", "1.6")]);
+        next.add(
+            entry("gojq", "1.0"),
+            vec![("jq".to_string(), fx.link("rg", "14.0").1)],
+        );
+        // Removing the previous owner must not take the link with it.
+        next.remove("jq");
+        next.finish(None).unwrap();
+        profiles.activate(2, None).unwrap();
+
+        assert_eq!(fx.resolved("jq").as_deref(), Some("14.0"));
+    }
+
+    #[test]
+    fn test_side_by_side_links_survive_upgrades() {
+        let fx = fixture();
+        let profiles = Profiles::init_at(&fx.root, &fx.bin, &[]).unwrap();
+
+        let mut next = profiles.stage("install jq").unwrap();
+        next.add(entry("jq", "1.7"), vec![fx.link("jq", "1.7")]);
+        let side = ("jq-1.6".to_string(), fx.link("jq", "1.6").1);
+        next.add_side_by_side("jq", "1.6", vec![side.clone()])
+            .unwrap();
+        // Another package can't take a versioned link over.
+        next.add(entry("rg", "14.0"), vec![fx.link("rg", "14.0")]);
+        let taken = ("rg".to_string(), fx.link("jq", "1.6").1);
+        assert!(next.add_side_by_side("jq", "1.6", vec![taken]).is_err());
+        next.add_side_by_side("jq", "1.6", vec![side]).unwrap();
+
+        // Re-adding the package (an upgrade) keeps its side-by-side versions.
+        next.add(entry("jq", "1.7"), vec![fx.link("jq", "1.7")]);
+        let generation = next.finish(None).unwrap();
+        profiles.activate(generation.id, None).unwrap();
+
+        assert_eq!(fx.resolved("jq").as_deref(), Some("1.7"));
+        assert_eq!(fx.resolved("jq-1.6").as_deref(), Some("1.6"));
+        let jq = generation.package("jq").unwrap();
+        assert_eq!(jq.link_version("jq-1.6"), Some("1.6"));
+        let record = jq.record(&fx.bin);
+        assert_eq!(
+            record.side_by_side,
+            Some(vec![(
+                fx.bin.join("jq-1.6").to_string_lossy().to_string(),
+                "1.6".to_string()
+            )])
+        );
+
+        let mut next = profiles.stage("unlink jq@1.6").unwrap();
+        assert!(next.remove_side_by_side("jq", "1.6"));
+        assert!(!next.remove_side_by_side("jq", "1.6"));
+        let unlinked = next.finish(None).unwrap();
+        profiles.activate(unlinked.id, None).unwrap();
+        assert!(fx.resolved("jq-1.6").is_none());
+        assert_eq!(generation.diff(&unlinked).len(), 1);
+    }
+
+    #[test]
+    fn test_diff() {
+        let from = Generation {
+            id: 1,
+            created_at: 0,
+            description: String::new(),
+            packages: vec![entry("fd", "9.0"), entry("jq", "1.6"), entry("rg", "14.0")],
+        };
+        let to = Generation {
+            id: 2,
+            packages: vec![
+                entry("bat", "0.24"),
+                entry("jq", "1.7"),
+                entry("rg", "14.0"),
+            ],
+            ..from.clone()
+        };
+
+        let changes = from.diff(&to);
+        assert_eq!(
+            changes,
+            vec![
+                Change::Added(&to.packages[0]),
+                Change::Removed(&from.packages[0]),
+                Change::Changed {
+                    from: &from.packages[1],
+                    to: &to.packages[1]
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_abort_restores_current_generation() {
+        let fx = fixture();
+        let profiles = Profiles::init_at(&fx.root, &fx.bin, &[]).unwrap();
+        let journal = Journal::begin(&fx.bin.with_file_name("journal"), OpKind::Install).unwrap();
+
+        let mut next = profiles.stage("install jq").unwrap();
+        next.add(entry("jq", "1.6"), vec![fx.link("jq", "1.6")]);
+        let generation = next.finish(Some(&journal)).unwrap();
+        profiles.activate(generation.id, Some(&journal)).unwrap();
+        assert_eq!(fx.resolved("jq").as_deref(), Some("1.6"));
+
+        journal.abort().unwrap();
+        assert_eq!(profiles.current().unwrap(), Some(1));
+        assert_eq!(profiles.ids().unwrap(), vec![1]);
+        assert!(fx.resolved("jq").is_none());
+    }
+
+    #[test]
+    fn test_activation_is_read_back_from_journal() {
+        let fx = fixture();
+        let profiles = Profiles::init_at(&fx.root, &fx.bin, &[]).unwrap();
+        let journal_dir = fx.bin.with_file_name("journal");
+        let journal = Journal::begin(&journal_dir, OpKind::Remove).unwrap();
+
+        let generation = profiles
+            .stage("remove jq")
+            .unwrap()
+            .finish(Some(&journal))
+            .unwrap();
+        profiles.activate(generation.id, Some(&journal)).unwrap();
+
+        let pending = crate::store::journal::pending(&journal_dir).unwrap();
+        assert_eq!(activation(&pending[0].1), Some((1, 2)));
+        journal.finish().unwrap();
+    }
+
+    #[test]
+    fn test_current_generation_cannot_be_deleted() {
+        let fx = fixture();
+        let profiles = Profiles::init_at(&fx.root, &fx.bin, &[]).unwrap();
+        assert!(profiles.delete(1).is_err());
+    }
+}
@@ -0,0 +1,330 @@
+//! Package sources
+//!
+//! Besides the default registry `apl`, whose index is `~/.apl/index` and
+//! whose URL is `index.url`, packages can come from named sources, each with
+//! its own signed index and trust key:
+//!
+//! ```text
+//! ~/.apl/sources/
+//! └── internal/
+//!     ├── source.toml   url, key and priority
+//!     └── index         local copy of its index
+//! ```
+//!
+//! The indexes are merged into one [`PackageIndex`]: a package comes from
+//! the source with the highest priority that has it, the default source
+//! winning ties, and `source::name` picks a source explicitly.
+
+use std::cmp::Reverse;
+use std::io;
+use std::path::PathBuf;
+
+use apl_schema::index::{IndexError, PackageIndex};
+use base64::Engine;
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::config::Config;
+use crate::{index_path, sources_path};
+
+/// Name of the default registry.
+pub const DEFAULT_SOURCE: &str = "apl";
+
+const SOURCE_FILE: &str = "source.toml";
+
+#[derive(Error, Debug)]
+pub enum SourceError {
+    #[error("Invalid source name '{0}': use lowercase letters, digits, '-' and '_'")]
+    InvalidName(String),
+
+    #[error("Source '{0}' already exists")]
+    Exists(String),
+
+    #[error("Unknown source '{0}'")]
+    Unknown(String),
+
+    #[error("The default source '{DEFAULT_SOURCE}' is configured with index.url")]
+    Default,
+
+    #[error("Invalid key for source '{0}': expected a base64 Ed25519 public key")]
+    InvalidKey(String),
+
+    #[error("Index signature of source '{0}' is invalid")]
+    BadSignature(String),
+
+    #[error("Invalid source file {}: {source}", path.display())]
+    Parse {
+        path: PathBuf,
+        #[source]
+        source: Box<toml::de::Error>,
+    },
+
+    #[error("Failed to access {}: {source}", path.display())]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: io::Error,
+    },
+
+    #[error("Failed to load index of source '{name}': {source}")]
+    Index {
+        name: String,
+        #[source]
+        source: IndexError,
+    },
+}
+
+/// A package source
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    #[serde(skip)]
+    pub name: String,
+    /// Index URL; its signature is at `{url}.sig`
+    pub url: String,
+    /// Base64 Ed25519 public key the index signature must verify against
+    pub key: String,
+    /// Sources with a higher priority win packages both have
+    #[serde(default)]
+    pub priority: i32,
+}
+
+impl Source {
+    /// A new named source, checking its name and key.
+    pub fn new(name: &str, url: &str, key: &str, priority: i32) -> Result<Self, SourceError> {
+        let valid = !name.is_empty()
+            && name
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+        if !valid {
+            return Err(SourceError::InvalidName(name.to_string()));
+        }
+        if name == DEFAULT_SOURCE {
+            return Err(SourceError::Default);
+        }
+        let source = Self {
+            name: name.to_string(),
+            url: url.to_string(),
+            key: key.to_string(),
+            priority,
+        };
+        source.verifying_key()?;
+        Ok(source)
+    }
+
+    /// The default registry, at `index.url` unless `url` is given.
+    pub fn default_source(url: Option<&str>) -> Self {
+        Self {
+            name: DEFAULT_SOURCE.to_string(),
+            url: url.map_or_else(|| Config::global().index.url.clone(), str::to_string),
+            key: crate::APL_PUBLIC_KEY.to_string(),
+            priority: 0,
+        }
+    }
+
+    pub fn is_default(&self) -> bool {
+        self.name == DEFAULT_SOURCE
+    }
+
+    /// Directory holding a named source's file and index.
+    fn dir(&self) -> PathBuf {
+        sources_path().join(&self.name)
+    }
+
+    /// Local copy of the source's index.
+    pub fn index_path(&self) -> PathBuf {
+        if self.is_default() {
+            index_path()
+        } else {
+            self.dir().join("index")
+        }
+    }
+
+    /// Loads the local copy of the index; `None` if it was never fetched.
+    pub fn load_index(&self) -> Result<Option<PackageIndex>, SourceError> {
+        let path = self.index_path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        PackageIndex::load(&path)
+            .map(Some)
+            .map_err(|source| SourceError::Index {
+                name: self.name.clone(),
+                source,
+            })
+    }
+
+    fn verifying_key(&self) -> Result<VerifyingKey, SourceError> {
+        let invalid = || SourceError::InvalidKey(self.name.clone());
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(self.key.trim())
+            .map_err(|_| invalid())?;
+        let bytes: [u8; 32] = bytes.as_slice().try_into().map_err(|_| invalid())?;
+        VerifyingKey::from_bytes(&bytes).map_err(|_| invalid())
+    }
+
+    /// Checks a base64 `signature` of the index bytes against the source key.
+    pub fn verify(&self, index: &[u8], signature: &str) -> Result<(), SourceError> {
+        let bad = || SourceError::BadSignature(self.name.clone());
+        let bytes = base64::engine::general_purpose::STANDARD
+            .decode(signature.trim())
+            .map_err(|_| bad())?;
+        let signature = Signature::from_bytes(bytes.as_slice().try_into().map_err(|_| bad())?);
+        self.verifying_key()?
+            .verify(index, &signature)
+            .map_err(|_| bad())
+    }
+
+    /// Writes a named source's file.
+    pub fn save(&self) -> Result<(), SourceError> {
+        if self.is_default() {
+            return Err(SourceError::Default);
+        }
+        let dir = self.dir();
+        let io_error = |source| SourceError::Io {
+            path: dir.clone(),
+            source,
+        };
+        let content = toml::to_string(self).map_err(|e| io_error(io::Error::other(e)))?;
+        std::fs::create_dir_all(&dir).map_err(io_error)?;
+        std::fs::write(dir.join(SOURCE_FILE), content).map_err(io_error)
+    }
+
+    /// Deletes a named source with its index.
+    pub fn remove(&self) -> Result<(), SourceError> {
+        if self.is_default() {
+            return Err(SourceError::Default);
+        }
+        let dir = self.dir();
+        std::fs::remove_dir_all(&dir).map_err(|source| SourceError::Io { path: dir, source })
+    }
+
+    /// The source called `name`.
+    pub fn get(name: &str) -> Result<Self, SourceError> {
+        Self::list()?
+            .into_iter()
+            .find(|s| s.name == name)
+            .ok_or_else(|| SourceError::Unknown(name.to_string()))
+    }
+
+    /// Every source, highest priority first.
+    pub fn list() -> Result<Vec<Self>, SourceError> {
+        let mut sources = vec![Self::default_source(None)];
+        let dir = sources_path();
+        let entries = match std::fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(sources),
+            Err(source) => return Err(SourceError::Io { path: dir, source }),
+        };
+        for entry in entries {
+            let path = entry
+                .map_err(|source| SourceError::Io {
+                    path: dir.clone(),
+                    source,
+                })?
+                .path()
+                .join(SOURCE_FILE);
+            let content = match std::fs::read_to_string(&path) {
+                Ok(content) => content,
+                Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+                Err(source) => return Err(SourceError::Io { path, source }),
+            };
+            let mut source: Self =
+                toml::from_str(&content).map_err(|source| SourceError::Parse {
+                    path: path.clone(),
+                    source: Box::new(source),
+                })?;
+            source.name = path
+                .parent()
+                .and_then(|dir| dir.file_name())
+                .map(|name| name.to_string_lossy().to_string())
+                .unwrap_or_default();
+            sources.push(source);
+        }
+        sources.sort_by_key(|s| (Reverse(s.priority), !s.is_default(), s.name.clone()));
+        Ok(sources)
+    }
+}
+
+/// Merges the local indexes of every source; `None` if none was fetched.
+pub fn load_index() -> Result<Option<PackageIndex>, SourceError> {
+    let mut layers = Vec::new();
+    for source in Source::list()? {
+        if let Some(index) = source.load_index()? {
+            layers.push((source.name, index));
+        }
+    }
+    Ok((!layers.is_empty()).then(|| PackageIndex::merge(layers)))
+}
+
+/// Mirror base URL to download package `name` from. The configured
+/// `index.mirror` stands in for the default source's mirror only.
+pub fn mirror_for<'a>(index: &'a PackageIndex, name: &str) -> Option<&'a str> {
+    match index.source_of(name) {
+        Some(source) if source != DEFAULT_SOURCE => index.mirror_of(name),
+        _ => Config::global()
+            .index
+            .mirror
+            .as_deref()
+            .or(index.mirror_of(name)),
+    }
+}
+
+/// The name to find an installed package by in `index`: qualified with the
+/// source it was installed from when another source would win it, as long
+/// as that source is still configured.
+pub fn installed_name(index: &PackageIndex, name: &str, source: Option<&str>) -> String {
+    match source {
+        Some(source)
+            if index.source_of(name) != Some(source)
+                && index.source_names().any(|s| s == source) =>
+        {
+            format!("{source}{}{name}", apl_schema::index::SOURCE_SEPARATOR)
+        }
+        _ => name.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_names_and_keys_are_checked() {
+        let key = crate::APL_PUBLIC_KEY;
+        assert!(Source::new("internal", "https://example.com/index", key, 0).is_ok());
+        assert!(matches!(
+            Source::new("In::ternal", "https://example.com/index", key, 0),
+            Err(SourceError::InvalidName(_))
+        ));
+        assert!(matches!(
+            Source::new(DEFAULT_SOURCE, "https://example.com/index", key, 0),
+            Err(SourceError::Default)
+        ));
+        assert!(matches!(
+            Source::new("internal", "https://example.com/index", "bm9wZQ==", 0),
+            Err(SourceError::InvalidKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_signature_is_verified() {
+        use ed25519_dalek::{Signer, SigningKey};
+
+        let signing = SigningKey::from_bytes(&[7; 32]);
+        let engine = base64::engine::general_purpose::STANDARD;
+        let source = Source::new(
+            "internal",
+            "https://example.com/index",
+            &engine.encode(signing.verifying_key().as_bytes()),
+            0,
+        )
+        .unwrap();
+        let signature = engine.encode(signing.sign(b"index").to_bytes());
+        assert!(source.verify(b"index", &signature).is_ok());
+        assert!(matches!(
+            source.verify(b"tampered", &signature),
+            Err(SourceError::BadSignature(_))
+        ));
+    }
+}
@@ -0,0 +1,957 @@
+//! Full-screen package browser for `apl browse`
+//!
+//! [`Browser`] holds what is on screen and turns key presses into
+//! [`Action`]s. `cmd::browse` owns the terminal, runs the actions through
+//! the ops layer and feeds their [`UiEvent`]s back into the status line.
+
+use super::actor::UiEvent;
+use super::theme::{Theme, format_size};
+use crate::db::Package;
+use apl_schema::index::{IndexEntry, PackageIndex, VersionInfo};
+use apl_schema::search::{self, Query, SearchOptions};
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use crossterm::style::{Color, ContentStyle, PrintStyledContent, StyledContent, Stylize};
+use crossterm::terminal::{self, ClearType};
+use crossterm::{cursor, queue};
+use std::collections::{HashMap, HashSet};
+use std::io::{self, Write};
+
+/// Which packages the list shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Filter {
+    /// Everything in the index
+    #[default]
+    All,
+    /// Packages with an installed version
+    Installed,
+    /// Installed packages with a newer release
+    Outdated,
+}
+
+impl Filter {
+    const ALL: [Self; 3] = [Self::All, Self::Installed, Self::Outdated];
+
+    fn next(self) -> Self {
+        match self {
+            Self::All => Self::Installed,
+            Self::Installed => Self::Outdated,
+            Self::Outdated => Self::All,
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Self::All => "all",
+            Self::Installed => "installed",
+            Self::Outdated => "outdated",
+        }
+    }
+}
+
+/// What is installed, as far as the browser cares
+#[derive(Debug, Default)]
+pub struct LocalState {
+    /// Installed versions by package name
+    pub versions: HashMap<String, Vec<Package>>,
+    /// Installed packages with a newer release in the index
+    pub outdated: HashSet<String>,
+    /// Pinned packages
+    pub pinned: HashSet<String>,
+}
+
+impl LocalState {
+    fn active(&self, name: &str) -> Option<&Package> {
+        self.versions.get(name)?.iter().find(|p| p.active)
+    }
+
+    fn installed(&self, name: &str, version: &str) -> Option<&Package> {
+        self.versions
+            .get(name)?
+            .iter()
+            .find(|p| p.version == version)
+    }
+}
+
+/// An operation requested from the browser
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Action {
+    /// Install a `pkg` or `pkg@version` spec
+    Install(String),
+    /// Remove every installed version of a package
+    Remove(String),
+    /// Make an installed version the active one
+    Switch { name: String, version: String },
+    /// Hold a package at its installed version
+    Pin(String),
+    /// Release a package's pin
+    Unpin(String),
+    /// Leave the browser
+    Quit,
+}
+
+/// How keys are interpreted
+#[derive(Debug)]
+enum Mode {
+    Normal,
+    /// Typing into the search box
+    Search,
+    /// Waiting for `y` before running the action
+    Confirm(Action),
+}
+
+/// Severity of the status line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Level {
+    Info,
+    Progress,
+    Success,
+    Warning,
+    Error,
+}
+
+/// What the browser shows besides the index and the installed state,
+/// carried over when `apl browse` reloads the index.
+#[derive(Debug)]
+pub struct View {
+    query: String,
+    filter: Filter,
+    mode: Mode,
+    selected: Option<String>,
+    scroll: usize,
+    release: usize,
+    status: (Level, String),
+}
+
+/// Browser state: the query, the matching packages and the selection.
+#[derive(Debug)]
+pub struct Browser<'a> {
+    index: &'a PackageIndex,
+    local: LocalState,
+    query: String,
+    /// Why the query does not parse; the list keeps the last valid results
+    query_error: Option<String>,
+    filter: Filter,
+    mode: Mode,
+    rows: Vec<&'a IndexEntry>,
+    selected: usize,
+    /// First list row on screen
+    scroll: usize,
+    /// Highlighted release of the selected package, newest first
+    release: usize,
+    status: (Level, String),
+    /// An action is running; further actions are refused until it ends
+    busy: bool,
+    theme: Theme,
+}
+
+impl<'a> Browser<'a> {
+    /// A browser listing every package in `index`.
+    pub fn new(index: &'a PackageIndex, local: LocalState) -> Self {
+        let mut browser = Self {
+            index,
+            local,
+            query: String::new(),
+            query_error: None,
+            filter: Filter::All,
+            mode: Mode::Normal,
+            rows: Vec::new(),
+            selected: 0,
+            scroll: 0,
+            release: 0,
+            status: (
+                Level::Info,
+                format!("{} packages in the index", index.len()),
+            ),
+            busy: false,
+            theme: Theme::default(),
+        };
+        browser.refresh();
+        browser
+    }
+
+    /// A browser over a reloaded `index` that looks like the one `view` was
+    /// taken from, as far as the new index allows.
+    pub fn with_view(index: &'a PackageIndex, local: LocalState, view: View) -> Self {
+        let mut browser = Self {
+            query: view.query,
+            filter: view.filter,
+            mode: view.mode,
+            scroll: view.scroll,
+            status: view.status,
+            ..Self::new(index, local)
+        };
+        browser.refresh();
+        let position = view
+            .selected
+            .and_then(|name| browser.rows.iter().position(|e| e.name == name));
+        if let Some(position) = position {
+            browser.selected = position;
+            let releases = browser.rows[position].releases.len();
+            browser.release = view.release.min(releases.saturating_sub(1));
+        }
+        browser
+    }
+
+    /// What to carry over to a browser on a reloaded index.
+    pub fn into_view(self) -> View {
+        View {
+            selected: self.selected().map(str::to_string),
+            query: self.query,
+            filter: self.filter,
+            mode: self.mode,
+            scroll: self.scroll,
+            release: self.release,
+            status: self.status,
+        }
+    }
+
+    /// Replaces the installed state, e.g. after an action finished.
+    pub fn set_local(&mut self, local: LocalState) {
+        self.local = local;
+        self.refresh();
+    }
+
+    /// Marks an action as running or finished.
+    pub fn set_busy(&mut self, busy: bool) {
+        self.busy = busy;
+    }
+
+    /// Shows `message` as an error in the status line.
+    pub fn report_error(&mut self, message: &str) {
+        self.status = (Level::Error, message.to_string());
+    }
+
+    /// Name of the selected package, if the list is not empty.
+    pub fn selected(&self) -> Option<&str> {
+        self.rows.get(self.selected).map(|e| e.name.as_str())
+    }
+
+    /// Reruns the search and filter, keeping the selected package if it is
+    /// still listed.
+    fn refresh(&mut self) {
+        let previous = self.selected().map(str::to_string);
+        match self.query.parse::<Query>() {
+            Ok(query) => {
+                self.query_error = None;
+                let installed = |name: &str| self.local.versions.contains_key(name);
+                let options = SearchOptions {
+                    installed: Some(&installed),
+                    ..SearchOptions::default()
+                };
+                let hits = search::search(self.index, &query, &options).hits;
+                self.rows = hits
+                    .into_iter()
+                    .map(|hit| hit.entry)
+                    .filter(|entry| match self.filter {
+                        Filter::All => true,
+                        Filter::Installed => self.local.versions.contains_key(entry.name.as_str()),
+                        Filter::Outdated => self.local.outdated.contains(entry.name.as_str()),
+                    })
+                    .collect();
+            }
+            // Half-typed filters like `tag:` are expected while typing.
+            Err(e) => self.query_error = Some(e.to_string()),
+        }
+        let position = previous.and_then(|name| self.rows.iter().position(|e| e.name == name));
+        if position.is_none() {
+            self.release = 0;
+        }
+        self.selected = position.unwrap_or(0);
+    }
+
+    fn select(&mut self, row: usize) {
+        let row = row.min(self.rows.len().saturating_sub(1));
+        if row != self.selected {
+            self.selected = row;
+            self.release = 0;
+        }
+    }
+
+    fn current(&self) -> Option<&'a IndexEntry> {
+        self.rows.get(self.selected).copied()
+    }
+
+    fn current_release(&self) -> Option<(&'a IndexEntry, &'a VersionInfo)> {
+        let entry = self.current()?;
+        Some((entry, entry.releases.get(self.release)?))
+    }
+
+    /// Applies a key press; returns the action it asks for, if any.
+    pub fn handle_key(&mut self, key: KeyEvent) -> Option<Action> {
+        if key.modifiers.contains(KeyModifiers::CONTROL) && key.code == KeyCode::Char('c') {
+            return Some(Action::Quit);
+        }
+        match std::mem::replace(&mut self.mode, Mode::Normal) {
+            Mode::Confirm(action) => {
+                if key.code == KeyCode::Char('y') {
+                    return Some(action);
+                }
+                self.status = (Level::Info, "Cancelled".to_string());
+                None
+            }
+            Mode::Search => {
+                self.search_key(key);
+                None
+            }
+            Mode::Normal => self.normal_key(key),
+        }
+    }
+
+    fn search_key(&mut self, key: KeyEvent) {
+        self.mode = Mode::Search;
+        match key.code {
+            KeyCode::Enter => self.mode = Mode::Normal,
+            KeyCode::Esc => {
+                self.mode = Mode::Normal;
+                self.query.clear();
+                self.refresh();
+            }
+            KeyCode::Backspace => {
+                self.query.pop();
+                self.refresh();
+            }
+            KeyCode::Char(c) => {
+                self.query.push(c);
+                self.refresh();
+            }
+            _ => self.move_key(key.code),
+        }
+    }
+
+    fn move_key(&mut self, code: KeyCode) {
+        const PAGE: usize = 10;
+        match code {
+            KeyCode::Up => self.select(self.selected.saturating_sub(1)),
+            KeyCode::Down => self.select(self.selected + 1),
+            KeyCode::PageUp => self.select(self.selected.saturating_sub(PAGE)),
+            KeyCode::PageDown => self.select(self.selected + PAGE),
+            KeyCode::Home => self.select(0),
+            KeyCode::End => self.select(usize::MAX),
+            KeyCode::Left => self.release = self.release.saturating_sub(1),
+            KeyCode::Right => {
+                let releases = self.current().map_or(0, |e| e.releases.len());
+                self.release = (self.release + 1).min(releases.saturating_sub(1));
+            }
+            _ => {}
+        }
+    }
+
+    fn normal_key(&mut self, key: KeyEvent) -> Option<Action> {
+        let code = match key.code {
+            KeyCode::Char('k') => KeyCode::Up,
+            KeyCode::Char('j') => KeyCode::Down,
+            KeyCode::Char('h') => KeyCode::Left,
+            KeyCode::Char('l') => KeyCode::Right,
+            KeyCode::Char('g') => KeyCode::Home,
+            KeyCode::Char('G') => KeyCode::End,
+            code => code,
+        };
+        match code {
+            KeyCode::Char('q') | KeyCode::Esc => return Some(Action::Quit),
+            KeyCode::Char('/') => self.mode = Mode::Search,
+            KeyCode::Tab => {
+                self.filter = self.filter.next();
+                self.refresh();
+            }
+            KeyCode::Char(c @ ('i' | 'r' | 's' | 'p')) => return self.request(c),
+            code => self.move_key(code),
+        }
+        None
+    }
+
+    /// Turns an action key into an [`Action`] for the selected package.
+    fn request(&mut self, key: char) -> Option<Action> {
+        if self.busy {
+            self.status = (
+                Level::Warning,
+                "Wait for the current operation to finish".to_string(),
+            );
+            return None;
+        }
+        let (entry, release) = self.current_release()?;
+        let name = entry.name.as_str();
+        let version = release.version.as_str();
+        let active = self.local.active(name);
+        let result = match key {
+            'i' if self.local.installed(name, version).is_some() => {
+                Err(format!("{name} {version} is already installed"))
+            }
+            'i' if self.release == 0 => Ok(Action::Install(name.to_string())),
+            'i' => Ok(Action::Install(format!("{name}@{version}"))),
+            'r' if active.is_none() => Err(format!("{name} is not installed")),
+            'r' => {
+                self.mode = Mode::Confirm(Action::Remove(name.to_string()));
+                return None;
+            }
+            's' if self.local.installed(name, version).is_none() => Err(format!(
+                "{name} {version} is not installed; press i to install it"
+            )),
+            's' if active.is_some_and(|p| p.version == version) => {
+                Err(format!("{name} {version} is already active"))
+            }
+            's' => Ok(Action::Switch {
+                name: name.to_string(),
+                version: version.to_string(),
+            }),
+            'p' if self.local.pinned.contains(name) => Ok(Action::Unpin(name.to_string())),
+            'p' if active.is_none() => Err(format!("{name} is not installed")),
+            _ => Ok(Action::Pin(name.to_string())),
+        };
+        match result {
+            Ok(action) => Some(action),
+            Err(message) => {
+                self.status = (Level::Warning, message);
+                None
+            }
+        }
+    }
+
+    /// Shows an event from a running action in the status line.
+    pub fn on_event(&mut self, event: UiEvent) {
+        let status = match event {
+            UiEvent::PreparePipeline { items } => {
+                let plural = if items.len() == 1 { "" } else { "s" };
+                (
+                    Level::Progress,
+                    format!("Preparing {} package{plural}", items.len()),
+                )
+            }
+            UiEvent::PrintHeader { title } | UiEvent::LivePhase { title } => {
+                (Level::Progress, title)
+            }
+            UiEvent::LivePhaseUpdate { status, success } => {
+                let level = if success {
+                    Level::Progress
+                } else {
+                    Level::Warning
+                };
+                (level, format!("{} {status}", self.status.1))
+            }
+            UiEvent::Downloading {
+                name,
+                version,
+                current,
+                total,
+            } => {
+                let progress = match total {
+                    Some(total) if total > 0 => format!("{}%", current * 100 / total),
+                    _ => format_size(current),
+                };
+                (
+                    Level::Progress,
+                    format!("Downloading {name} {version} {progress}"),
+                )
+            }
+            UiEvent::Extracting { name, version, .. } => {
+                (Level::Progress, format!("Extracting {name} {version}"))
+            }
+            UiEvent::Installing { name, version, .. } => {
+                (Level::Progress, format!("Installing {name} {version}"))
+            }
+            UiEvent::Removing { name, version } => {
+                (Level::Progress, format!("Removing {name} {version}"))
+            }
+            UiEvent::Done {
+                name,
+                version,
+                detail,
+                ..
+            } => (Level::Success, format!("{name} {version} {detail}")),
+            UiEvent::Failed {
+                name,
+                version,
+                reason,
+            } => (Level::Error, format!("{name} {version} failed: {reason}")),
+            UiEvent::Info(message) => (Level::Info, message),
+            UiEvent::Success(message) => (Level::Success, message),
+            UiEvent::Warning(message) => (Level::Warning, message),
+            UiEvent::Error(message) => (Level::Error, message),
+            UiEvent::Summary {
+                count,
+                action,
+                elapsed_secs,
+            } => {
+                let plural = if count == 1 { "" } else { "s" };
+                (
+                    Level::Success,
+                    format!("{count} package{plural} {action} in {elapsed_secs:.1}s"),
+                )
+            }
+            // Nothing is buffered, so there is nothing to wait for.
+            UiEvent::Sync(done) => {
+                let _ = done.send(());
+                return;
+            }
+            UiEvent::Shutdown => return,
+        };
+        self.status = status;
+    }
+
+    /// Draws the whole screen.
+    pub fn render(&mut self, out: &mut impl Write, width: u16, height: u16) -> io::Result<()> {
+        let width = usize::from(width);
+        let height = usize::from(height);
+        // Header (3 lines), status and help
+        let body = height.saturating_sub(5);
+        if self.selected < self.scroll {
+            self.scroll = self.selected;
+        } else if body > 0 && self.selected >= self.scroll + body {
+            self.scroll = self.selected + 1 - body;
+        }
+
+        let mut lines: Vec<Line> = Vec::with_capacity(height);
+        lines.push(self.title_line());
+        lines.push(self.search_line());
+        lines.push(vec![styled("─".repeat(width), self.theme.colors.border)]);
+
+        let list_width = (width * 2 / 5).clamp(24.min(width), width);
+        let detail_width = width.saturating_sub(list_width + 3);
+        let detail = self.detail_lines(detail_width);
+        for i in 0..body {
+            let mut line = self.list_row(self.scroll + i, list_width);
+            line.push(styled(" │ ".to_string(), self.theme.colors.border));
+            line.extend(detail.get(i).cloned().unwrap_or_default());
+            lines.push(line);
+        }
+
+        lines.push(self.status_line());
+        lines.push(vec![styled(
+            self.help().to_string(),
+            self.theme.colors.secondary,
+        )]);
+
+        queue!(out, terminal::BeginSynchronizedUpdate, cursor::Hide)?;
+        for (y, line) in lines.iter().take(height).enumerate() {
+            let y = u16::try_from(y).unwrap_or(u16::MAX);
+            queue!(
+                out,
+                cursor::MoveTo(0, y),
+                terminal::Clear(ClearType::UntilNewLine)
+            )?;
+            let mut room = width;
+            for span in line {
+                if room == 0 {
+                    break;
+                }
+                let text = fit(span.content(), room);
+                room -= text.chars().count();
+                queue!(
+                    out,
+                    PrintStyledContent(StyledContent::new(*span.style(), text))
+                )?;
+            }
+        }
+        if matches!(self.mode, Mode::Search) {
+            let column = 2 + self.query.chars().count();
+            queue!(
+                out,
+                cursor::MoveTo(u16::try_from(column).unwrap_or(u16::MAX), 1),
+                cursor::Show
+            )?;
+        }
+        queue!(out, terminal::EndSynchronizedUpdate)?;
+        out.flush()
+    }
+
+    fn title_line(&self) -> Line {
+        let mut line = vec![StyledContent::new(
+            ContentStyle::new().bold(),
+            " apl browse  ".to_string(),
+        )];
+        for filter in Filter::ALL {
+            let label = format!(" {} ", filter.label());
+            line.push(if filter == self.filter {
+                StyledContent::new(ContentStyle::new().reverse(), label)
+            } else {
+                styled(label, self.theme.colors.secondary)
+            });
+        }
+        let plural = if self.rows.len() == 1 { "" } else { "s" };
+        line.push(styled(
+            format!("  {} package{plural}", self.rows.len()),
+            self.theme.colors.secondary,
+        ));
+        line
+    }
+
+    fn search_line(&self) -> Line {
+        let prompt_color = if matches!(self.mode, Mode::Search) {
+            self.theme.colors.package_name
+        } else {
+            self.theme.colors.secondary
+        };
+        let mut line = vec![
+            styled("/ ".to_string(), prompt_color),
+            StyledContent::new(ContentStyle::new(), self.query.clone()),
+        ];
+        if let Some(error) = &self.query_error {
+            line.push(styled(format!("  {error}"), self.theme.colors.error));
+        } else if self.query.is_empty() && !matches!(self.mode, Mode::Search) {
+            line.push(styled(
+                "press / to search".to_string(),
+                self.theme.colors.secondary,
+            ));
+        }
+        line
+    }
+
+    fn list_row(&self, row: usize, width: usize) -> Line {
+        let Some(entry) = self.rows.get(row) else {
+            return vec![StyledContent::new(ContentStyle::new(), " ".repeat(width))];
+        };
+        let name = entry.name.as_str();
+        let (marker, color) = if self.local.outdated.contains(name) {
+            ("↑", self.theme.colors.warning)
+        } else if self.local.versions.contains_key(name) {
+            (self.theme.icons.active, self.theme.colors.success)
+        } else {
+            (" ", self.theme.colors.secondary)
+        };
+        let pin = if self.local.pinned.contains(name) {
+            "="
+        } else {
+            " "
+        };
+        let version = entry.latest().map_or("?", |r| r.version.as_str());
+        let name_width = width.saturating_sub(version.chars().count() + 5);
+        let text = format!(" {pin}{:<name_width$} {version}", fit(name, name_width));
+        let row_width = width.saturating_sub(1);
+        let mut style = ContentStyle::new();
+        if row == self.selected {
+            style = style.reverse();
+        }
+        vec![
+            styled(marker.to_string(), color),
+            StyledContent::new(style, format!("{:<row_width$}", fit(&text, row_width))),
+        ]
+    }
+
+    fn detail_lines(&self, width: usize) -> Vec<Line> {
+        let Some(entry) = self.current() else {
+            return vec![vec![styled(
+                "No packages match".to_string(),
+                self.theme.colors.secondary,
+            )]];
+        };
+        let secondary = self.theme.colors.secondary;
+        let plain = |text: String| StyledContent::new(ContentStyle::new(), text);
+        let label = |name: &str| styled(format!("{name:<11}"), secondary);
+        let name = entry.name.as_str();
+
+        let mut lines: Vec<Line> = vec![vec![
+            StyledContent::new(
+                ContentStyle::new()
+                    .with(self.theme.colors.package_name)
+                    .bold(),
+                name.to_string(),
+            ),
+            styled(
+                format!(" {}", entry.latest().map_or("", |r| r.version.as_str())),
+                secondary,
+            ),
+        ]];
+        lines.extend(
+            wrap(&entry.description, width)
+                .into_iter()
+                .map(|l| vec![plain(l)]),
+        );
+        lines.push(Vec::new());
+        for (name, value) in [
+            ("homepage", entry.homepage.clone()),
+            ("license", entry.license.clone()),
+            ("type", entry.type_.clone()),
+            ("tags", entry.tags.join(", ")),
+        ] {
+            if !value.is_empty() {
+                lines.push(vec![label(name), plain(value)]);
+            }
+        }
+        if self.local.pinned.contains(name) {
+            lines.push(vec![label("pinned"), plain("yes".to_string())]);
+        }
+
+        lines.push(Vec::new());
+        lines.push(vec![styled("versions".to_string(), secondary)]);
+        for (i, release) in entry.releases.iter().enumerate() {
+            let cursor = if i == self.release { "› " } else { "  " };
+            let mut line = vec![plain(format!("{cursor}{}", release.version))];
+            if let Some(pkg) = self.local.installed(name, &release.version) {
+                line.push(if pkg.active {
+                    styled(" (active)".to_string(), self.theme.colors.success)
+                } else {
+                    styled(" (installed)".to_string(), secondary)
+                });
+            }
+            lines.push(line);
+        }
+
+        if let Some((_, release)) = self.current_release() {
+            let list = |deps: &[String]| {
+                if deps.is_empty() {
+                    "none".to_string()
+                } else {
+                    deps.join(", ")
+                }
+            };
+            lines.push(Vec::new());
+            lines.push(vec![label("deps"), plain(list(&release.deps))]);
+            if !release.build_deps.is_empty() {
+                lines.push(vec![label("build deps"), plain(list(&release.build_deps))]);
+            }
+            let hints = release.hints.trim();
+            if !hints.is_empty() {
+                lines.push(Vec::new());
+                lines.push(vec![styled("hints".to_string(), secondary)]);
+                for hint in hints.lines() {
+                    lines.extend(wrap(hint, width).into_iter().map(|l| vec![plain(l)]));
+                }
+            }
+        }
+        lines
+    }
+
+    fn status_line(&self) -> Line {
+        let (level, message) = &self.status;
+        let colors = &self.theme.colors;
+        let (icon, color) = match level {
+            Level::Info => (self.theme.icons.info, colors.secondary),
+            Level::Progress => (self.theme.icons.active, colors.package_name),
+            Level::Success => (self.theme.icons.success, colors.success),
+            Level::Warning => (self.theme.icons.warning, colors.warning),
+            Level::Error => (self.theme.icons.error, colors.error),
+        };
+        vec![
+            styled(format!(" {icon} "), color),
+            StyledContent::new(ContentStyle::new(), message.clone()),
+        ]
+    }
+
+    fn help(&self) -> String {
+        match &self.mode {
+            Mode::Normal => " ↑↓ move  ←→ version  / search  tab filter  i install  r remove  s switch  p pin  q quit".to_string(),
+            Mode::Search => " type to search  ↑↓ move  enter done  esc clear".to_string(),
+            Mode::Confirm(Action::Remove(name)) => {
+                format!(" Remove {name}? y to confirm, any other key to cancel")
+            }
+            Mode::Confirm(_) => " y to confirm, any other key to cancel".to_string(),
+        }
+    }
+}
+
+type Span = StyledContent<String>;
+type Line = Vec<Span>;
+
+fn styled(text: String, color: Color) -> Span {
+    StyledContent::new(ContentStyle::new().with(color), text)
+}
+
+/// Cuts `text` to at most `width` characters, marking the cut with `…`.
+fn fit(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    let mut cut: String = text.chars().take(width.saturating_sub(1)).collect();
+    if width > 0 {
+        cut.push('…');
+    }
+    cut
+}
+
+/// Breaks `text` into lines of at most `width` characters at spaces.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.chars().count() + 1 + word.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::InstallReason;
+
+    fn entry(name: &str, versions: &[&str], description: &str) -> IndexEntry {
+        IndexEntry {
+            name: name.into(),
+            description: description.to_string(),
+            releases: versions
+                .iter()
+                .map(|v| VersionInfo {
+                    version: (*v).to_string(),
+                    ..VersionInfo::default()
+                })
+                .collect(),
+            ..IndexEntry::default()
+        }
+    }
+
+    fn index() -> PackageIndex {
+        let mut index = PackageIndex::new();
+        index.upsert(entry("jq", &["1.7.1", "1.6"], "JSON processor"));
+        index.upsert(entry("ripgrep", &["14.1.0"], "Recursive line search"));
+        index.upsert(entry("fd", &["10.2.0"], "Find files"));
+        index
+    }
+
+    fn installed(name: &str, version: &str, active: bool) -> Package {
+        Package {
+            name: name.to_string(),
+            version: version.to_string(),
+            sha256: String::new(),
+            installed_at: 0,
+            active,
+            size_bytes: 0,
+            reason: InstallReason::Explicit,
+            source: None,
+        }
+    }
+
+    fn key(code: KeyCode) -> KeyEvent {
+        KeyEvent::new(code, KeyModifiers::NONE)
+    }
+
+    fn names(browser: &Browser<'_>) -> Vec<String> {
+        browser.rows.iter().map(|e| e.name.to_string()).collect()
+    }
+
+    #[test]
+    fn test_search_and_filters() {
+        let index = index();
+        let mut local = LocalState::default();
+        local
+            .versions
+            .insert("jq".to_string(), vec![installed("jq", "1.6", true)]);
+        local.outdated.insert("jq".to_string());
+        let mut browser = Browser::new(&index, local);
+        assert_eq!(names(&browser), ["fd", "jq", "ripgrep"]);
+
+        browser.handle_key(key(KeyCode::Char('/')));
+        for c in "search".chars() {
+            browser.handle_key(key(KeyCode::Char(c)));
+        }
+        assert_eq!(names(&browser), ["ripgrep"]);
+
+        // A half-typed filter keeps the last results
+        for c in " OR fd".chars() {
+            browser.handle_key(key(KeyCode::Char(c)));
+        }
+        assert_eq!(names(&browser), ["fd", "ripgrep"]);
+        for c in " OR tag:".chars() {
+            browser.handle_key(key(KeyCode::Char(c)));
+        }
+        assert!(browser.query_error.is_some());
+        assert_eq!(names(&browser), ["fd", "ripgrep"]);
+
+        browser.handle_key(key(KeyCode::Esc));
+        browser.handle_key(key(KeyCode::Tab));
+        assert_eq!(names(&browser), ["jq"]);
+        browser.handle_key(key(KeyCode::Tab));
+        assert_eq!(names(&browser), ["jq"]);
+        browser.handle_key(key(KeyCode::Tab));
+        assert_eq!(browser.filter, Filter::All);
+    }
+
+    #[test]
+    fn test_keys_request_actions() {
+        let index = index();
+        let mut local = LocalState::default();
+        local.versions.insert(
+            "jq".to_string(),
+            vec![
+                installed("jq", "1.7.1", true),
+                installed("jq", "1.6", false),
+            ],
+        );
+        let mut browser = Browser::new(&index, local);
+
+        assert_eq!(
+            browser.handle_key(key(KeyCode::Char('i'))),
+            Some(Action::Install("fd".to_string()))
+        );
+        assert_eq!(browser.handle_key(key(KeyCode::Char('r'))), None);
+        assert_eq!(browser.status.0, Level::Warning);
+
+        browser.handle_key(key(KeyCode::Down));
+        assert_eq!(browser.selected(), Some("jq"));
+        // The active version is highlighted first
+        assert_eq!(browser.handle_key(key(KeyCode::Char('s'))), None);
+        browser.handle_key(key(KeyCode::Right));
+        assert_eq!(
+            browser.handle_key(key(KeyCode::Char('s'))),
+            Some(Action::Switch {
+                name: "jq".to_string(),
+                version: "1.6".to_string()
+            })
+        );
+        assert_eq!(
+            browser.handle_key(key(KeyCode::Char('p'))),
+            Some(Action::Pin("jq".to_string()))
+        );
+
+        // Removal asks first
+        assert_eq!(browser.handle_key(key(KeyCode::Char('r'))), None);
+        assert_eq!(browser.handle_key(key(KeyCode::Char('n'))), None);
+        assert_eq!(browser.handle_key(key(KeyCode::Char('r'))), None);
+        assert_eq!(
+            browser.handle_key(key(KeyCode::Char('y'))),
+            Some(Action::Remove("jq".to_string()))
+        );
+
+        browser.set_busy(true);
+        assert_eq!(browser.handle_key(key(KeyCode::Char('p'))), None);
+        assert_eq!(
+            browser.handle_key(key(KeyCode::Char('q'))),
+            Some(Action::Quit)
+        );
+    }
+
+    #[test]
+    fn test_view_survives_reload() {
+        let index = index();
+        let mut browser = Browser::new(&index, LocalState::default());
+        browser.handle_key(key(KeyCode::Char('/')));
+        browser.handle_key(key(KeyCode::Char('j')));
+        browser.handle_key(key(KeyCode::Enter));
+        browser.handle_key(key(KeyCode::Right));
+        assert_eq!((browser.selected(), browser.release), (Some("jq"), 1));
+        let view = browser.into_view();
+
+        let mut reloaded = index.clone();
+        reloaded.upsert(entry("jo", &["1.9"], "JSON output"));
+        let browser = Browser::with_view(&reloaded, LocalState::default(), view);
+        assert_eq!(names(&browser), ["jo", "jq"]);
+        assert_eq!((browser.selected(), browser.release), (Some("jq"), 1));
+
+        // A package that went away leaves the selection at the top.
+        let view = browser.into_view();
+        let mut smaller = index.clone();
+        smaller.retain(|e| e.name != "jq");
+        let browser = Browser::with_view(&smaller, LocalState::default(), view);
+        assert_eq!(browser.query, "j");
+        assert_eq!((browser.selected(), browser.release), (None, 0));
+    }
+
+    #[test]
+    fn test_render_fits_screen() {
+        let index = index();
+        let mut browser = Browser::new(&index, LocalState::default());
+        browser.on_event(UiEvent::Info("x".repeat(200)));
+        let mut out = Vec::new();
+        browser.render(&mut out, 60, 10).unwrap();
+        let screen = String::from_utf8(out).unwrap();
+        assert!(screen.contains("ripgrep"));
+        assert!(screen.contains("Find files"));
+        assert!(!screen.contains(&"x".repeat(60)));
+    }
+}
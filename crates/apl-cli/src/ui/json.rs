@@ -0,0 +1,460 @@
+//! Machine-readable output (`--format json|ndjson`).
+//!
+//! Two kinds of values are written:
+//!
+//! - **Events** (`{"event": "done", ...}`) mirror the [`Reporter`] calls that
+//!   drive the terminal table: phases, download progress, done/failed and
+//!   summaries. They are always one compact object per line.
+//! - **Documents** (`{"schema": 1, "kind": "list", ...}`) are the result of a
+//!   query command such as `apl list` or `apl status`.
+//!
+//! With `json`, stdout carries exactly the command's document (pretty
+//! printed) and events go to stderr. With `ndjson`, events and documents are
+//! interleaved on stdout, one per line. The schema is documented in
+//! `docs/json-output.md`; bump [`SCHEMA_VERSION`] on incompatible changes.
+
+use super::actor::UiEvent;
+use super::reporter::Reporter;
+use apl_schema::types::{PackageName, Version};
+use serde::Serialize;
+use std::io::Write;
+use std::sync::OnceLock;
+
+/// Version of the document and event schema.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// Output format selected with the global `--format` flag.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// Human-readable, colored terminal output
+    #[default]
+    Text,
+    /// One JSON document on stdout, events as NDJSON on stderr
+    Json,
+    /// Events and documents as newline-delimited JSON on stdout
+    Ndjson,
+}
+
+impl From<apl_core::config::OutputFormat> for Format {
+    fn from(format: apl_core::config::OutputFormat) -> Self {
+        use apl_core::config::OutputFormat;
+        match format {
+            OutputFormat::Text => Self::Text,
+            OutputFormat::Json => Self::Json,
+            OutputFormat::Ndjson => Self::Ndjson,
+        }
+    }
+}
+
+impl Format {
+    /// Whether output is meant for programs rather than people.
+    pub fn is_machine(self) -> bool {
+        self != Self::Text
+    }
+}
+
+static FORMAT: OnceLock<Format> = OnceLock::new();
+
+/// Selects the output format for the rest of the process.
+///
+/// Must be called before the first [`super::Output`] is created; later calls
+/// are ignored.
+pub fn set_format(format: Format) {
+    let _ = FORMAT.set(format);
+}
+
+/// The output format selected for this process.
+pub fn format() -> Format {
+    FORMAT.get().copied().unwrap_or_default()
+}
+
+/// A progress or status event.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Phase {
+        title: String,
+    },
+    PhaseStatus {
+        status: String,
+        success: bool,
+    },
+    Plan {
+        packages: Vec<PlannedPackage>,
+    },
+    Section {
+        title: String,
+    },
+    Download {
+        name: String,
+        version: String,
+        current: u64,
+        total: Option<u64>,
+    },
+    Extract {
+        name: String,
+        version: String,
+        current: u64,
+        total: Option<u64>,
+    },
+    Install {
+        name: String,
+        version: String,
+        current: Option<u64>,
+        total: Option<u64>,
+    },
+    Remove {
+        name: String,
+        version: String,
+    },
+    Done {
+        name: String,
+        version: String,
+        detail: String,
+        size: Option<u64>,
+    },
+    Failed {
+        name: String,
+        version: String,
+        reason: String,
+    },
+    Info {
+        message: String,
+    },
+    Success {
+        message: String,
+    },
+    Warning {
+        message: String,
+    },
+    Error {
+        message: String,
+    },
+    Summary {
+        count: usize,
+        action: String,
+        elapsed_secs: f64,
+    },
+}
+
+/// A package announced by [`Event::Plan`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PlannedPackage {
+    pub name: String,
+    pub version: Option<String>,
+    pub size: usize,
+}
+
+impl Event {
+    /// Translates a terminal UI event; returns `None` for control events.
+    pub(crate) fn from_ui(event: UiEvent) -> Option<Self> {
+        Some(match event {
+            UiEvent::LivePhase { title } => Self::Phase { title },
+            UiEvent::LivePhaseUpdate { status, success } => Self::PhaseStatus { status, success },
+            UiEvent::PreparePipeline { items } => Self::Plan {
+                packages: items
+                    .into_iter()
+                    .map(|(name, version, size)| PlannedPackage {
+                        name: name.to_string(),
+                        version: version.map(|v| v.to_string()),
+                        size,
+                    })
+                    .collect(),
+            },
+            UiEvent::PrintHeader { title } => Self::Section { title },
+            UiEvent::Downloading {
+                name,
+                version,
+                current,
+                total,
+            } => Self::Download {
+                name: name.to_string(),
+                version: version.to_string(),
+                current,
+                total,
+            },
+            UiEvent::Extracting {
+                name,
+                version,
+                current,
+                total,
+            } => Self::Extract {
+                name: name.to_string(),
+                version: version.to_string(),
+                current,
+                total,
+            },
+            UiEvent::Installing {
+                name,
+                version,
+                current,
+                total,
+            } => Self::Install {
+                name: name.to_string(),
+                version: version.to_string(),
+                current,
+                total,
+            },
+            UiEvent::Removing { name, version } => Self::Remove {
+                name: name.to_string(),
+                version: version.to_string(),
+            },
+            UiEvent::Done {
+                name,
+                version,
+                detail,
+                size,
+            } => Self::Done {
+                name: name.to_string(),
+                version: version.to_string(),
+                detail,
+                size,
+            },
+            UiEvent::Failed {
+                name,
+                version,
+                reason,
+            } => Self::Failed {
+                name: name.to_string(),
+                version: version.to_string(),
+                reason,
+            },
+            UiEvent::Info(message) => Self::Info { message },
+            UiEvent::Success(message) => Self::Success { message },
+            UiEvent::Warning(message) => Self::Warning { message },
+            UiEvent::Error(message) => Self::Error { message },
+            UiEvent::Summary {
+                count,
+                action,
+                elapsed_secs,
+            } => Self::Summary {
+                count,
+                action,
+                elapsed_secs,
+            },
+            UiEvent::Sync(_) | UiEvent::Shutdown => return None,
+        })
+    }
+}
+
+/// A [`Reporter`] that writes each call as an [`Event`].
+#[derive(Debug, Clone, Copy)]
+pub struct JsonReporter {
+    format: Format,
+}
+
+impl JsonReporter {
+    /// Create a reporter for a machine-readable `format`.
+    pub fn new(format: Format) -> Self {
+        Self { format }
+    }
+
+    /// Writes one event as a single line.
+    pub fn emit(&self, event: &Event) {
+        let Ok(line) = serde_json::to_string(event) else {
+            return;
+        };
+        // Locking keeps concurrent download tasks from interleaving lines.
+        let _ = if self.format == Format::Ndjson {
+            writeln!(std::io::stdout().lock(), "{line}")
+        } else {
+            writeln!(std::io::stderr().lock(), "{line}")
+        };
+    }
+}
+
+impl Reporter for JsonReporter {
+    fn live_phase(&self, title: &str) {
+        self.emit(&Event::Phase {
+            title: title.to_string(),
+        });
+    }
+
+    fn live_phase_update(&self, status: &str, success: bool) {
+        self.emit(&Event::PhaseStatus {
+            status: status.to_string(),
+            success,
+        });
+    }
+
+    fn prepare_pipeline(&self, packages: &[(PackageName, Option<Version>, usize)]) {
+        if let Some(event) = Event::from_ui(UiEvent::PreparePipeline {
+            items: packages.to_vec(),
+        }) {
+            self.emit(&event);
+        }
+    }
+
+    fn section(&self, title: &str) {
+        self.emit(&Event::Section {
+            title: title.to_string(),
+        });
+    }
+
+    fn downloading(&self, name: &PackageName, version: &Version, current: u64, total: Option<u64>) {
+        self.emit(&Event::Download {
+            name: name.to_string(),
+            version: version.to_string(),
+            current,
+            total,
+        });
+    }
+
+    fn extracting(&self, name: &PackageName, version: &Version, current: u64, total: Option<u64>) {
+        self.emit(&Event::Extract {
+            name: name.to_string(),
+            version: version.to_string(),
+            current,
+            total,
+        });
+    }
+
+    fn installing(
+        &self,
+        name: &PackageName,
+        version: &Version,
+        current: Option<u64>,
+        total: Option<u64>,
+    ) {
+        self.emit(&Event::Install {
+            name: name.to_string(),
+            version: version.to_string(),
+            current,
+            total,
+        });
+    }
+
+    fn removing(&self, name: &PackageName, version: &Version) {
+        self.emit(&Event::Remove {
+            name: name.to_string(),
+            version: version.to_string(),
+        });
+    }
+
+    fn done(&self, name: &PackageName, version: &Version, detail: &str, size: Option<u64>) {
+        self.emit(&Event::Done {
+            name: name.to_string(),
+            version: version.to_string(),
+            detail: detail.to_string(),
+            size,
+        });
+    }
+
+    fn failed(&self, name: &PackageName, version: &Version, reason: &str) {
+        self.emit(&Event::Failed {
+            name: name.to_string(),
+            version: version.to_string(),
+            reason: reason.to_string(),
+        });
+    }
+
+    fn info(&self, msg: &str) {
+        self.emit(&Event::Info {
+            message: msg.to_string(),
+        });
+    }
+
+    fn success(&self, msg: &str) {
+        self.emit(&Event::Success {
+            message: msg.to_string(),
+        });
+    }
+
+    fn warning(&self, msg: &str) {
+        self.emit(&Event::Warning {
+            message: msg.to_string(),
+        });
+    }
+
+    fn error(&self, msg: &str) {
+        self.emit(&Event::Error {
+            message: msg.to_string(),
+        });
+    }
+
+    fn summary(&self, count: usize, action: &str, elapsed_secs: f64) {
+        self.emit(&Event::Summary {
+            count,
+            action: action.to_string(),
+            elapsed_secs,
+        });
+    }
+
+    fn summary_plain(&self, count: usize, status: &str) {
+        let plural = if count == 1 { "" } else { "s" };
+        self.success(&format!("{count} package{plural} {status}"));
+    }
+}
+
+#[derive(Serialize)]
+struct Envelope<'a, T> {
+    schema: u32,
+    kind: &'a str,
+    #[serde(flatten)]
+    body: &'a T,
+}
+
+/// Writes the result document of a query command to stdout.
+///
+/// `kind` names the document (`"list"`, `"status"`, ...) and `body` must
+/// serialize to a JSON object; its fields follow `schema` and `kind`.
+pub fn print_document<T: Serialize>(kind: &str, body: &T) -> serde_json::Result<()> {
+    let envelope = Envelope {
+        schema: SCHEMA_VERSION,
+        kind,
+        body,
+    };
+    let text = if format() == Format::Ndjson {
+        serde_json::to_string(&envelope)?
+    } else {
+        serde_json::to_string_pretty(&envelope)?
+    };
+    let _ = writeln!(std::io::stdout().lock(), "{text}");
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_event_shape() {
+        let event = Event::from_ui(UiEvent::Done {
+            name: PackageName::new("jq"),
+            version: Version::from("1.7.1"),
+            detail: "installed".to_string(),
+            size: Some(42),
+        })
+        .unwrap();
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({
+                "event": "done",
+                "name": "jq",
+                "version": "1.7.1",
+                "detail": "installed",
+                "size": 42,
+            })
+        );
+        assert!(Event::from_ui(UiEvent::Shutdown).is_none());
+    }
+
+    #[test]
+    fn test_envelope_flattens_body() {
+        #[derive(Serialize)]
+        struct Body {
+            packages: Vec<&'static str>,
+        }
+        let envelope = Envelope {
+            schema: SCHEMA_VERSION,
+            kind: "list",
+            body: &Body {
+                packages: vec!["jq"],
+            },
+        };
+        assert_eq!(
+            serde_json::to_value(&envelope).unwrap(),
+            serde_json::json!({"schema": 1, "kind": "list", "packages": ["jq"]})
+        );
+    }
+}
@@ -40,6 +40,8 @@
 //! - [`actor`] - Message-passing event loop
 //! - [`output`] - Public API for commands to use
 //! - [`list`] - List formatting for installed packages
+//! - [`json`] - Machine-readable events and documents for `--format json|ndjson`
+//! - [`browser`] - Full-screen package browser for `apl browse`
 //!
 //! # Example
 //!
@@ -67,7 +69,9 @@
 //! ```
 
 pub mod actor;
+pub mod browser;
 pub mod buffer;
+pub mod json;
 pub mod list;
 pub mod output;
 pub mod progress;
@@ -79,6 +83,7 @@ pub mod theme;
 pub mod engine;
 pub use actor::UiActor;
 pub use engine::RelativeFrame;
+pub use json::Format;
 pub use output::Output;
 pub use reporter::{NullReporter, Reporter};
 pub use theme::Theme;
@@ -4,6 +4,7 @@
 //! All operations are sent as events to the UI actor for sequential processing.
 
 use super::actor::{UiActor, UiEvent};
+use super::json::{self, Event, JsonReporter};
 use apl_schema::types::{PackageName, Version};
 use std::fmt;
 use std::sync::{OnceLock, mpsc};
@@ -26,10 +27,19 @@ fn get_actor_sender() -> mpsc::Sender<UiEvent> {
         .clone()
 }
 
+/// Where an [`Output`] delivers its events.
+#[derive(Clone)]
+enum Sink {
+    Terminal(mpsc::Sender<UiEvent>),
+    Json(JsonReporter),
+}
+
 /// A cloneable handle for sending high-level UI events to the terminal actor.
+///
+/// Under `--format json|ndjson` the events are written as JSON instead.
 #[derive(Clone)]
 pub struct Output {
-    sender: mpsc::Sender<UiEvent>,
+    sink: Sink,
 }
 
 impl fmt::Debug for Output {
@@ -41,21 +51,52 @@ impl fmt::Debug for Output {
 impl Output {
     /// Create a new output handle.
     pub fn new() -> Self {
+        let format = json::format();
+        let sink = if format.is_machine() {
+            Sink::Json(JsonReporter::new(format))
+        } else {
+            Sink::Terminal(get_actor_sender())
+        };
+        Self { sink }
+    }
+
+    /// An output handle whose events go to `sender` instead of the terminal
+    /// actor, for screens that draw progress themselves (`apl browse`).
+    pub(crate) fn with_sender(sender: mpsc::Sender<UiEvent>) -> Self {
         Self {
-            sender: get_actor_sender(),
+            sink: Sink::Terminal(sender),
+        }
+    }
+
+    fn send(&self, event: UiEvent) {
+        match &self.sink {
+            Sink::Terminal(sender) => {
+                let _ = sender.send(event);
+            }
+            Sink::Json(reporter) => match event {
+                // Nothing is buffered, so there is nothing to wait for.
+                UiEvent::Sync(done) => {
+                    let _ = done.send(());
+                }
+                event => {
+                    if let Some(event) = Event::from_ui(event) {
+                        reporter.emit(&event);
+                    }
+                }
+            },
         }
     }
 
     /// Prepare a live-updated phase (e.g. "Phase 1: Discovering sources...")
     pub fn live_phase(&self, title: &str) {
-        let _ = self.sender.send(UiEvent::LivePhase {
+        self.send(UiEvent::LivePhase {
             title: title.to_string(),
         });
     }
 
     /// Update the current live phase with a status (e.g. "COMPLETE")
     pub fn live_phase_update(&self, status: &str, success: bool) {
-        let _ = self.sender.send(UiEvent::LivePhaseUpdate {
+        self.send(UiEvent::LivePhaseUpdate {
             status: status.to_string(),
             success,
         });
@@ -63,14 +104,14 @@ impl Output {
 
     /// Prepare table for a pipeline of packages.
     pub fn prepare_pipeline(&self, packages: &[(PackageName, Option<Version>, usize)]) {
-        let _ = self.sender.send(UiEvent::PreparePipeline {
+        self.send(UiEvent::PreparePipeline {
             items: packages.to_vec(),
         });
     }
 
     /// Prints a visual section header for an operation phase.
     pub fn section(&self, title: &str) {
-        let _ = self.sender.send(UiEvent::PrintHeader {
+        self.send(UiEvent::PrintHeader {
             title: title.to_string(),
         });
     }
@@ -83,7 +124,7 @@ impl Output {
         current: u64,
         total: Option<u64>,
     ) {
-        let _ = self.sender.send(UiEvent::Downloading {
+        self.send(UiEvent::Downloading {
             name: name.clone(),
             version: version.clone(),
             current,
@@ -99,7 +140,7 @@ impl Output {
         current: Option<u64>,
         total: Option<u64>,
     ) {
-        let _ = self.sender.send(UiEvent::Installing {
+        self.send(UiEvent::Installing {
             name: name.clone(),
             version: version.clone(),
             current,
@@ -109,7 +150,7 @@ impl Output {
 
     /// Transitions a package display to the 'removing' state.
     pub fn removing(&self, name: &PackageName, version: &Version) {
-        let _ = self.sender.send(UiEvent::Removing {
+        self.send(UiEvent::Removing {
             name: name.clone(),
             version: version.clone(),
         });
@@ -117,7 +158,7 @@ impl Output {
 
     /// Signals completion of a package operation.
     pub fn done(&self, name: &PackageName, version: &Version, detail: &str, size: Option<u64>) {
-        let _ = self.sender.send(UiEvent::Done {
+        self.send(UiEvent::Done {
             name: name.clone(),
             version: version.clone(),
             detail: detail.to_string(),
@@ -127,7 +168,7 @@ impl Output {
 
     /// Marks a package operation as failed with a visible reason.
     pub fn failed(&self, name: &PackageName, version: &Version, reason: &str) {
-        let _ = self.sender.send(UiEvent::Failed {
+        self.send(UiEvent::Failed {
             name: name.clone(),
             version: version.clone(),
             reason: reason.to_string(),
@@ -136,27 +177,27 @@ impl Output {
 
     /// Prints an informational message to the console.
     pub fn info(&self, msg: &str) {
-        let _ = self.sender.send(UiEvent::Info(msg.to_string()));
+        self.send(UiEvent::Info(msg.to_string()));
     }
 
     /// Prints a success message to the console.
     pub fn success(&self, msg: &str) {
-        let _ = self.sender.send(UiEvent::Success(msg.to_string()));
+        self.send(UiEvent::Success(msg.to_string()));
     }
 
     /// Prints a warning message to the console.
     pub fn warning(&self, msg: &str) {
-        let _ = self.sender.send(UiEvent::Warning(msg.to_string()));
+        self.send(UiEvent::Warning(msg.to_string()));
     }
 
     /// Prints an error message to the console.
     pub fn error(&self, msg: &str) {
-        let _ = self.sender.send(UiEvent::Error(msg.to_string()));
+        self.send(UiEvent::Error(msg.to_string()));
     }
 
     /// Prints a summary of operations including the total elapsed time.
     pub fn summary(&self, count: usize, action: &str, elapsed_secs: f64) {
-        let _ = self.sender.send(UiEvent::Summary {
+        self.send(UiEvent::Summary {
             count,
             action: action.to_string(),
             elapsed_secs,
@@ -187,7 +228,7 @@ impl Output {
     /// Block until all pending UI events are processed.
     pub fn wait(&self) {
         let (tx, rx) = tokio::sync::oneshot::channel();
-        let _ = self.sender.send(UiEvent::Sync(tx));
+        self.send(UiEvent::Sync(tx));
 
         // Block effectively without spinning CPU
         let _ = rx.blocking_recv();
@@ -196,14 +237,14 @@ impl Output {
     /// Async version of wait.
     pub async fn wait_async(&self) {
         let (tx, rx) = tokio::sync::oneshot::channel();
-        let _ = self.sender.send(UiEvent::Sync(tx));
+        self.send(UiEvent::Sync(tx));
 
         let _ = rx.await;
     }
 
     /// Shutdown the UI actor.
     pub fn shutdown(&self) {
-        let _ = self.sender.send(UiEvent::Shutdown);
+        self.send(UiEvent::Shutdown);
     }
 }
 
@@ -229,7 +270,7 @@ impl super::reporter::Reporter for Output {
     }
 
     fn extracting(&self, name: &PackageName, version: &Version, current: u64, total: Option<u64>) {
-        let _ = self.sender.send(UiEvent::Extracting {
+        self.send(UiEvent::Extracting {
             name: name.clone(),
             version: version.clone(),
             current,
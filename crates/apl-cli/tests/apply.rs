@@ -0,0 +1,54 @@
+//! End-to-end tests for `apl apply` and `apl dump`
+
+mod common;
+
+use common::TestContext;
+
+#[test]
+fn test_apply_and_dump() {
+    let ctx = TestContext::new();
+    assert!(ctx.apl(&["install", "hello-ng"], None).status.success());
+    let aplfile = ctx.apl_home.join("Aplfile");
+    let active = |name: &str| -> String {
+        let conn = rusqlite::Connection::open(ctx.apl_home.join("state.db")).unwrap();
+        conn.query_row(
+            "SELECT version FROM packages WHERE name = ?1 AND active = 1",
+            [name],
+            |r| r.get(0),
+        )
+        .unwrap_or_default()
+    };
+
+    std::fs::write(&aplfile, "[packages]\ngreet = \"*\"\ntool = \"1.0.x\"\n").unwrap();
+    let output = ctx.apl(&["apply", "--dry-run"], None);
+    assert!(output.status.success(), "dry run failed: {output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("tool 1.0.0"));
+    assert_eq!(active("tool"), "");
+
+    let output = ctx.apl(&["apply"], None);
+    assert!(output.status.success(), "apply failed: {output:?}");
+    assert_eq!(active("tool"), "1.0.0");
+    assert_eq!(active("greet"), "1.0.0");
+    assert_eq!(active("hello-ng"), "1.0.0");
+
+    std::fs::write(&aplfile, "[packages]\ngreet = \"*\"\ntool = \"2\"\n").unwrap();
+    assert!(ctx.apl(&["apply"], None).status.success());
+    assert_eq!(active("tool"), "2.0.0");
+    // An installed version that matches is switched to, not downloaded.
+    std::fs::write(&aplfile, "[packages]\ngreet = \"*\"\ntool = \"=1.0.0\"\n").unwrap();
+    let output = ctx.apl(&["apply", "--prune", "-y"], None);
+    assert!(output.status.success(), "apply failed: {output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("(switch)"));
+    assert_eq!(active("tool"), "1.0.0");
+    assert_eq!(active("hello-ng"), "");
+
+    assert!(ctx.apl(&["pin", "tool"], None).status.success());
+    let output = ctx.apl(&["dump", "-f", "-"], None);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout)
+            .lines()
+            .skip_while(|l| *l != "[packages]")
+            .collect::<Vec<_>>(),
+        vec!["[packages]", "greet = \"*\"", "tool = \"=1.0.0\""]
+    );
+}
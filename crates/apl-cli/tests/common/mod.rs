@@ -0,0 +1,202 @@
+//! Fixture shared by the end-to-end tests
+//!
+//! Every test file runs the real `apl` binary against a temporary APL home
+//! and a mock server publishing a small index.
+
+#![allow(dead_code)]
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output};
+
+use apl_schema::index::{HashType, IndexBinary, IndexEntry, PackageIndex, VersionInfo};
+use apl_schema::{Arch, Sha256Hash};
+use sha2::{Digest, Sha256};
+use tempfile::TempDir;
+
+/// Test context with a temporary APL home and a mock artifact server
+pub(crate) struct TestContext {
+    temp_dir: TempDir,
+    pub(crate) apl_home: PathBuf,
+    /// Kept alive for the duration of the test
+    _server: mockito::ServerGuard,
+}
+
+impl TestContext {
+    /// Publishes `hello` (depending on `greet`), `greet`, `hello-ng` (which
+    /// also ships a `hello` binary) and two versions of `tool` to a mock
+    /// server and writes a matching index.
+    pub(crate) fn new() -> Self {
+        let temp_dir = TempDir::new().expect("failed to create temp dir");
+        let apl_home = temp_dir.path().join(".apl");
+        std::fs::create_dir_all(&apl_home).expect("failed to create apl home");
+
+        let mut server = mockito::Server::new();
+        let mut index = PackageIndex::new();
+        for (name, deps, bin, versions) in [
+            ("hello", vec!["greet".to_string()], "hello", vec!["1.0.0"]),
+            ("greet", vec![], "greet", vec!["1.0.0"]),
+            ("hello-ng", vec![], "hello", vec!["1.0.0"]),
+            ("tool", vec![], "tool", vec!["2.0.0", "1.0.0"]),
+        ] {
+            let releases = versions
+                .into_iter()
+                .map(|version| {
+                    let artifact = tarball(&format!("{name} {version}"), bin);
+                    let path = format!("/{name}-{version}.tar.gz");
+                    server
+                        .mock("GET", path.as_str())
+                        .with_body(&artifact)
+                        .create();
+                    VersionInfo {
+                        version: version.to_string(),
+                        binaries: vec![IndexBinary {
+                            arch: Arch::Universal,
+                            url: format!("{}{path}", server.url()),
+                            hash: Sha256Hash::new(hex::encode(Sha256::digest(&artifact))),
+                            hash_type: HashType::Sha256,
+                        }],
+                        deps: deps.clone(),
+                        bin: vec![bin.to_string()],
+                        ..VersionInfo::default()
+                    }
+                })
+                .collect();
+
+            index.upsert(IndexEntry {
+                name: name.to_string(),
+                releases,
+                ..IndexEntry::default()
+            });
+        }
+        index
+            .save(&apl_home.join("index"))
+            .expect("failed to write index");
+
+        Self {
+            temp_dir,
+            apl_home,
+            _server: server,
+        }
+    }
+
+    pub(crate) fn apl(&self, args: &[&str], crash_at: Option<&str>) -> Output {
+        let mut cmd = Command::new(env!("CARGO_BIN_EXE_apl"));
+        cmd.args(args)
+            .env("HOME", self.temp_dir.path())
+            .env("APL_HOME", &self.apl_home)
+            .env("NO_PROXY", "*")
+            .env_remove("APL_CRASH_AT");
+        if let Some(phase) = crash_at {
+            cmd.env("APL_CRASH_AT", phase);
+        }
+        cmd.output().expect("failed to run apl")
+    }
+
+    pub(crate) fn bin(&self, name: &str) -> PathBuf {
+        self.apl_home.join("bin").join(name)
+    }
+
+    pub(crate) fn store(&self, name: &str) -> PathBuf {
+        self.apl_home.join("store").join(name).join("1.0.0")
+    }
+
+    pub(crate) fn journals(&self) -> usize {
+        std::fs::read_dir(self.apl_home.join("journal")).map_or(0, Iterator::count)
+    }
+
+    /// Installed package names as reported by the database.
+    pub(crate) fn installed(&self) -> Vec<String> {
+        let conn = rusqlite::Connection::open(self.apl_home.join("state.db")).unwrap();
+        let mut stmt = conn
+            .prepare("SELECT name FROM packages WHERE active = 1 ORDER BY name")
+            .unwrap();
+        stmt.query_map([], |r| r.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    /// Opens the state database through `apl`, which runs recovery.
+    pub(crate) fn recover(&self) {
+        let output = self.apl(&["list"], None);
+        assert!(output.status.success(), "apl list failed: {output:?}");
+        assert_eq!(self.journals(), 0, "journal should be cleared by recovery");
+    }
+
+    pub(crate) fn assert_installed(&self) {
+        assert_eq!(self.installed(), vec!["greet", "hello"]);
+        for name in ["hello", "greet"] {
+            assert!(self.store(name).exists(), "{name} missing from store");
+            assert!(
+                self.bin(name).is_symlink() && self.bin(name).exists(),
+                "{name} not linked"
+            );
+        }
+    }
+
+    pub(crate) fn assert_not_installed(&self) {
+        assert!(self.installed().is_empty());
+        for name in ["hello", "greet"] {
+            assert!(!self.store(name).exists(), "{name} left in store");
+            assert!(!self.bin(name).is_symlink(), "{name} link left behind");
+        }
+    }
+}
+
+/// A gzipped tarball holding a single executable shell script `bin` that
+/// prints `label`.
+pub(crate) fn tarball(label: &str, bin: &str) -> Vec<u8> {
+    let script = format!("#!/bin/sh\necho {label}\n");
+    let mut header = tar::Header::new_gnu();
+    header.set_size(script.len() as u64);
+    header.set_mode(0o755);
+    header.set_cksum();
+
+    let encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    let mut builder = tar::Builder::new(encoder);
+    builder
+        .append_data(&mut header, bin, script.as_bytes())
+        .unwrap();
+    let mut encoder = builder.into_inner().unwrap();
+    encoder.flush().unwrap();
+    encoder.finish().unwrap()
+}
+
+/// Runs `apl --format json` and parses its stdout, replacing timestamps,
+/// sizes, database ids and artifact locations with `"*"` so documents can be
+/// compared whole.
+pub(crate) fn json_document(ctx: &TestContext, args: &[&str]) -> serde_json::Value {
+    fn redact(value: &mut serde_json::Value) {
+        match value {
+            serde_json::Value::Object(map) => {
+                for (key, value) in map.iter_mut() {
+                    match key.as_str() {
+                        "installed_at" | "timestamp" | "committed_at" | "size_bytes"
+                        | "total_size_bytes" | "index_updated" | "id" | "url" | "hash" => {
+                            *value = "*".into();
+                        }
+                        _ => redact(value),
+                    }
+                }
+            }
+            serde_json::Value::Array(items) => items.iter_mut().for_each(redact),
+            _ => {}
+        }
+    }
+
+    let output = ctx.apl(&[&["--format", "json"], args].concat(), None);
+    assert!(output.status.success(), "apl {args:?} failed: {output:?}");
+    let mut value: serde_json::Value =
+        serde_json::from_slice(&output.stdout).expect("stdout is one JSON document");
+    redact(&mut value);
+    value
+}
+
+pub(crate) fn assert_crashed(output: &Output) {
+    assert!(
+        !output.status.success(),
+        "apl should have been killed: {}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+}
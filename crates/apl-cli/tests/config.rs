@@ -0,0 +1,72 @@
+//! End-to-end tests for layered configuration
+
+mod common;
+
+use common::{TestContext, json_document};
+
+#[test]
+fn test_config_layers_and_origins() {
+    let ctx = TestContext::new();
+    let user = ctx.apl_home.join("config.toml");
+    std::fs::write(&user, "# kept\n[network]\nparallel = 4\n").unwrap();
+
+    let output = ctx.apl(&["config", "set", "shell.fallback", "/bin/bash"], None);
+    assert!(output.status.success(), "config set failed: {output:?}");
+    let content = std::fs::read_to_string(&user).unwrap();
+    assert!(content.starts_with("# kept\n"), "{content}");
+    assert!(content.contains("fallback = \"/bin/bash\""), "{content}");
+
+    // Values of the wrong type and unknown keys are refused.
+    assert!(
+        !ctx.apl(&["config", "set", "network.parallel", "many"], None)
+            .status
+            .success()
+    );
+    assert!(
+        !ctx.apl(&["config", "set", "network.nope", "1"], None)
+            .status
+            .success()
+    );
+    assert_eq!(std::fs::read_to_string(&user).unwrap(), content);
+
+    let output = ctx.apl(&["config", "get", "shell.fallback"], None);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "/bin/bash\n");
+
+    let setting = json_document(&ctx, &["config", "get", "network.parallel"]);
+    assert_eq!(setting["value"], 4);
+    assert_eq!(
+        setting["origin"],
+        format!("user:{}", user.display()).as_str()
+    );
+
+    // The command line wins over the user file.
+    let setting = json_document(
+        &ctx,
+        &[
+            "--config",
+            "network.parallel=8",
+            "config",
+            "get",
+            "network.parallel",
+        ],
+    );
+    assert_eq!(setting["value"], 8);
+    assert_eq!(setting["origin"], "command line");
+
+    let list = json_document(&ctx, &["config", "list", "--show-origin"]);
+    let origin_of = |key: &str| {
+        list["settings"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|s| s["key"] == key)
+            .map(|s| s["origin"].clone())
+            .unwrap()
+    };
+    assert_eq!(origin_of("timeouts.connect"), "default");
+    assert_eq!(origin_of("shell.fallback"), origin_of("network.parallel"));
+
+    let output = ctx.apl(&["--config", "network.parallel=many", "list"], None);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("network.parallel"));
+}
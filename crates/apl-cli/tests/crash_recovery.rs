@@ -0,0 +1,81 @@
+//! End-to-end crash recovery tests
+//!
+//! Each test kills `apl` at a named phase through the debug-only
+//! `APL_CRASH_AT` hook, then runs it again and checks that the store, the
+//! bin directory and the database agree with each other.
+
+mod common;
+
+use std::path::Path;
+
+use common::{TestContext, assert_crashed};
+
+#[test]
+fn test_install_succeeds_without_crash() {
+    let ctx = TestContext::new();
+    let output = ctx.apl(&["install", "hello"], None);
+    assert!(output.status.success(), "install failed: {output:?}");
+    assert_eq!(ctx.journals(), 0);
+    ctx.assert_installed();
+}
+
+#[test]
+fn test_install_crash_before_commit_rolls_back() {
+    for phase in ["journaled", "staged", "linked"] {
+        let ctx = TestContext::new();
+        assert_crashed(&ctx.apl(&["install", "hello"], Some(phase)));
+        assert_eq!(ctx.journals(), 1, "journal missing after crash at {phase}");
+
+        ctx.recover();
+        ctx.assert_not_installed();
+
+        // The same install goes through cleanly afterwards.
+        let output = ctx.apl(&["install", "hello"], None);
+        assert!(output.status.success(), "reinstall after {phase} failed");
+        ctx.assert_installed();
+    }
+}
+
+#[test]
+fn test_install_crash_after_commit_rolls_forward() {
+    let ctx = TestContext::new();
+    assert_crashed(&ctx.apl(&["install", "hello"], Some("committed")));
+
+    ctx.recover();
+    ctx.assert_installed();
+}
+
+#[test]
+fn test_remove_crash_rolls_forward() {
+    for phase in ["journaled", "unlinked", "committed"] {
+        let ctx = TestContext::new();
+        assert!(ctx.apl(&["install", "hello"], None).status.success());
+
+        assert_crashed(&ctx.apl(&["remove", "hello", "greet"], Some(phase)));
+        ctx.recover();
+
+        assert!(ctx.installed().is_empty(), "db not cleaned after {phase}");
+        assert!(!ctx.bin("hello").exists() && !ctx.bin("greet").exists());
+    }
+}
+
+#[test]
+fn test_recovery_leaves_live_operations_alone() {
+    let ctx = TestContext::new();
+    let journal_dir = ctx.apl_home.join("journal");
+    std::fs::create_dir_all(&journal_dir).unwrap();
+
+    // A journal owned by this (very much alive) test process.
+    let journal = journal_dir.join("0-live.jsonl");
+    std::fs::write(
+        &journal,
+        format!(
+            "{{\"step\":\"begin\",\"id\":\"0-live\",\"kind\":\"install\",\"pid\":{}}}\n",
+            std::process::id()
+        ),
+    )
+    .unwrap();
+
+    assert!(ctx.apl(&["list"], None).status.success());
+    assert!(Path::new(&journal).exists());
+}
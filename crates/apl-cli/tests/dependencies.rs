@@ -0,0 +1,23 @@
+//! End-to-end tests for dependency tracking on removal
+
+mod common;
+
+use common::TestContext;
+
+#[test]
+fn test_remove_dependency_with_its_dependent() {
+    let ctx = TestContext::new();
+    assert!(ctx.apl(&["install", "hello"], None).status.success());
+
+    // `hello` depends on `greet`, so `greet` alone is refused...
+    let output = ctx.apl(&["remove", "greet"], None);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("required by hello"), "{stdout}");
+    ctx.assert_installed();
+
+    // ...but not when both go in one batch, however they are spelled.
+    let output = ctx.apl(&["remove", "Greet", "HELLO"], None);
+    assert!(output.status.success(), "remove failed: {output:?}");
+    assert!(ctx.installed().is_empty());
+    assert!(!ctx.bin("hello").exists() && !ctx.bin("greet").exists());
+}
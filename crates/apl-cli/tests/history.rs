@@ -0,0 +1,66 @@
+//! End-to-end tests for generations, `apl undo` and `apl rollback`
+
+mod common;
+
+use common::{TestContext, assert_crashed};
+
+#[test]
+fn test_generation_switch_restores_removed_packages() {
+    let ctx = TestContext::new();
+    assert!(ctx.apl(&["install", "hello"], None).status.success());
+    assert!(
+        ctx.apl(&["remove", "hello", "greet"], None)
+            .status
+            .success()
+    );
+    assert!(ctx.installed().is_empty());
+    assert!(!ctx.bin("hello").exists());
+
+    // 1: empty initial profile, 2: install, 3: remove
+    let output = ctx.apl(&["generations", "switch", "2"], None);
+    assert!(output.status.success(), "switch failed: {output:?}");
+    ctx.assert_installed();
+
+    let output = ctx.apl(&["generations", "switch", "3"], None);
+    assert!(output.status.success(), "switch failed: {output:?}");
+    assert!(ctx.installed().is_empty());
+    assert!(!ctx.bin("hello").exists());
+}
+
+#[test]
+fn test_undo_and_rollback_to_event() {
+    let ctx = TestContext::new();
+    assert!(ctx.apl(&["install", "hello"], None).status.success());
+    assert!(
+        ctx.apl(&["remove", "hello", "greet"], None)
+            .status
+            .success()
+    );
+
+    let output = ctx.apl(&["undo"], None);
+    assert!(output.status.success(), "undo failed: {output:?}");
+    ctx.assert_installed();
+
+    // The undo is the last operation now, so undoing again redoes the removal.
+    let output = ctx.apl(&["undo"], None);
+    assert!(output.status.success(), "undo failed: {output:?}");
+    assert!(ctx.installed().is_empty());
+
+    // Event #1 is the install of the first package.
+    let output = ctx.apl(&["rollback", "--to", "1"], None);
+    assert!(output.status.success(), "rollback failed: {output:?}");
+    ctx.assert_installed();
+}
+
+#[test]
+fn test_recovered_remove_can_be_undone() {
+    let ctx = TestContext::new();
+    assert!(ctx.apl(&["install", "hello"], None).status.success());
+    assert_crashed(&ctx.apl(&["remove", "hello", "greet"], Some("unlinked")));
+    ctx.recover();
+    assert!(ctx.installed().is_empty());
+
+    let output = ctx.apl(&["undo"], None);
+    assert!(output.status.success(), "undo failed: {output:?}");
+    ctx.assert_installed();
+}
@@ -0,0 +1,144 @@
+//! End-to-end tests for `--format json` documents
+
+mod common;
+
+use common::{TestContext, json_document};
+
+#[test]
+fn test_json_documents() {
+    use serde_json::json;
+
+    let ctx = TestContext::new();
+    assert!(
+        ctx.apl(&["install", "hello", "tool@1.0.0"], None)
+            .status
+            .success()
+    );
+    assert!(ctx.apl(&["pin", "tool"], None).status.success());
+
+    assert_eq!(
+        json_document(&ctx, &["list"]),
+        json!({
+            "schema": 1,
+            "kind": "list",
+            "packages": [
+                {"name": "greet", "version": "1.0.0", "reason": "dependency", "size_bytes": "*", "installed_at": "*"},
+                {"name": "hello", "version": "1.0.0", "reason": "explicit", "size_bytes": "*", "installed_at": "*"},
+                {"name": "tool", "version": "1.0.0", "reason": "explicit", "size_bytes": "*", "installed_at": "*"},
+            ],
+            "total_size_bytes": "*",
+        })
+    );
+    let installed = json!({
+        "version": "1.0.0",
+        "reason": "explicit",
+        "size_bytes": "*",
+        "installed_at": "*",
+        "active": true,
+    });
+    assert_eq!(
+        json_document(&ctx, &["info", "hello"]),
+        json!({
+            "schema": 1,
+            "kind": "info",
+            "name": "hello",
+            "version": "1.0.0",
+            "latest": "1.0.0",
+            "description": null,
+            "homepage": null,
+            "license": null,
+            "tags": [],
+            "type": null,
+            "deps": ["greet"],
+            "build_deps": [],
+            "hints": null,
+            "versions": [{"version": "1.0.0", "installed": true, "active": true}],
+            "artifacts": [{
+                "kind": "binary",
+                "arch": "universal",
+                "url": "*",
+                "hash": "*",
+                "hash_type": "sha256",
+                "format": "tar.gz",
+                "mirror_url": null,
+            }],
+            "dependency_tree": [{"name": "greet", "version": "1.0.0", "deps": []}],
+            "installed": installed.clone(),
+            "installed_versions": [installed],
+        })
+    );
+    assert_eq!(
+        json_document(&ctx, &["search", "hello"]),
+        json!({
+            "schema": 1,
+            "kind": "search",
+            "query": "hello",
+            "total": 2,
+            "page": 1,
+            "per_page": 20,
+            "results": [
+                {"name": "hello", "version": "1.0.0", "description": "", "score": 1000},
+                {"name": "hello-ng", "version": "1.0.0", "description": "", "score": 800},
+            ],
+        })
+    );
+
+    let outdated = json!([{
+        "name": "tool",
+        "installed": "1.0.0",
+        "latest": "2.0.0",
+        "target": null,
+        "pin": "1.0.0",
+    }]);
+    assert_eq!(
+        json_document(&ctx, &["outdated"]),
+        json!({"schema": 1, "kind": "outdated", "packages": outdated})
+    );
+    let status = json_document(&ctx, &["status"]);
+    assert_eq!(status["kind"], "status");
+    assert_eq!(status["packages"], 3);
+    assert_eq!(status["outdated"], outdated);
+
+    assert_eq!(
+        json_document(&ctx, &["history", "tool"]),
+        json!({
+            "schema": 1,
+            "kind": "history",
+            "package": "tool",
+            "events": [{
+                "id": "*",
+                "timestamp": "*",
+                "package": "tool",
+                "action": "install",
+                "version_from": null,
+                "version_to": "1.0.0",
+                "success": true,
+                "operation": {
+                    "id": "*",
+                    "kind": "install",
+                    "committed_at": "*",
+                    "generation_from": 1,
+                    "generation_to": 2,
+                },
+            }],
+        })
+    );
+
+    // ndjson streams events, one object per line, and failures as an event.
+    let output = ctx.apl(&["--format", "ndjson", "remove", "tool"], None);
+    assert!(output.status.success(), "remove failed: {output:?}");
+    let events: Vec<serde_json::Value> = String::from_utf8_lossy(&output.stdout)
+        .lines()
+        .map(|line| serde_json::from_str(line).expect("each line is JSON"))
+        .collect();
+    assert!(
+        events
+            .iter()
+            .any(|e| e["event"] == "done" && e["name"] == "tool"),
+        "no done event for tool: {events:?}"
+    );
+    let output = ctx.apl(&["--format", "ndjson", "info", "missing"], None);
+    assert!(!output.status.success());
+    let error: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+    assert_eq!(error["event"], "error");
+}
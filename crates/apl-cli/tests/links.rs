@@ -0,0 +1,103 @@
+//! End-to-end tests for binary conflicts, link aliases and side-by-side
+//! versions
+
+mod common;
+
+use common::TestContext;
+
+#[test]
+fn test_conflicting_binary_is_refused() {
+    let ctx = TestContext::new();
+    assert!(ctx.apl(&["install", "hello"], None).status.success());
+
+    let output = ctx.apl(&["install", "hello-ng"], None);
+    assert!(!output.status.success(), "conflict not detected");
+    assert!(String::from_utf8_lossy(&output.stderr).contains("already provided by hello"));
+    assert_eq!(ctx.installed(), vec!["greet", "hello"]);
+    assert_eq!(
+        std::fs::read_to_string(ctx.bin("hello")).unwrap(),
+        "#!/bin/sh\necho hello 1.0.0\n"
+    );
+    assert_eq!(ctx.journals(), 0);
+}
+
+#[test]
+fn test_link_alias_and_priority_resolve_conflicts() {
+    let ctx = TestContext::new();
+    assert!(ctx.apl(&["install", "hello"], None).status.success());
+    let config = ctx.apl_home.join("config.toml");
+
+    std::fs::write(
+        &config,
+        "[links.hello-ng]\naliases = { hello = \"hello2\" }\n",
+    )
+    .unwrap();
+    let output = ctx.apl(&["install", "hello-ng"], None);
+    assert!(output.status.success(), "install failed: {output:?}");
+    assert!(ctx.bin("hello2").exists());
+    // The alias leaves `hello` to its original owner alone.
+    let which = String::from_utf8_lossy(&ctx.apl(&["which", "hello"], None).stdout).to_string();
+    assert!(
+        which.contains("hello") && !which.contains("hello-ng"),
+        "{which}"
+    );
+
+    assert!(ctx.apl(&["remove", "hello-ng"], None).status.success());
+    std::fs::write(&config, "[links.hello-ng]\npriority = 1\n").unwrap();
+    let output = ctx.apl(&["install", "hello-ng"], None);
+    assert!(output.status.success(), "install failed: {output:?}");
+    assert_eq!(
+        std::fs::read_to_string(ctx.bin("hello")).unwrap(),
+        "#!/bin/sh\necho hello-ng 1.0.0\n"
+    );
+
+    let which = String::from_utf8_lossy(&ctx.apl(&["which", "hello"], None).stdout).to_string();
+    assert!(which.contains("hello-ng"), "{which}");
+    assert!(
+        which.contains("Also provided by:\n  hello 1.0.0"),
+        "{which}"
+    );
+}
+
+#[test]
+fn test_side_by_side_versions() {
+    let ctx = TestContext::new();
+    assert!(ctx.apl(&["install", "tool@1.0.0"], None).status.success());
+    assert!(ctx.apl(&["install", "tool@2.0.0"], None).status.success());
+    let run = |bin: &str| std::fs::read_to_string(ctx.bin(bin)).unwrap_or_default();
+
+    let output = ctx.apl(&["use", "tool@1.0.0", "--side-by-side"], None);
+    assert!(output.status.success(), "use failed: {output:?}");
+    assert_eq!(run("tool"), "#!/bin/sh\necho tool 2.0.0\n");
+    assert_eq!(run("tool-1.0"), "#!/bin/sh\necho tool 1.0.0\n");
+    assert!(ctx.apl(&["verify"], None).status.success());
+
+    // Switching the primary version keeps the side-by-side one.
+    assert!(ctx.apl(&["use", "tool@1.0.0"], None).status.success());
+    assert_eq!(run("tool"), "#!/bin/sh\necho tool 1.0.0\n");
+    assert!(ctx.bin("tool-1.0").exists());
+    assert!(ctx.apl(&["use", "tool@2.0.0"], None).status.success());
+
+    assert!(
+        ctx.apl(&["use", "tool@1.0.0", "--unlink"], None)
+            .status
+            .success()
+    );
+    assert!(!ctx.bin("tool-1.0").is_symlink());
+    assert!(ctx.apl(&["undo"], None).status.success());
+    assert!(ctx.bin("tool-1.0").exists());
+    assert!(ctx.apl(&["verify"], None).status.success());
+
+    assert!(ctx.apl(&["remove", "tool", "-y"], None).status.success());
+    assert!(!ctx.bin("tool").is_symlink());
+    assert!(!ctx.bin("tool-1.0").is_symlink());
+    let conn = rusqlite::Connection::open(ctx.apl_home.join("state.db")).unwrap();
+    let files: i64 = conn
+        .query_row(
+            "SELECT count(*) FROM files WHERE package = 'tool'",
+            [],
+            |r| r.get(0),
+        )
+        .unwrap();
+    assert_eq!(files, 0);
+}
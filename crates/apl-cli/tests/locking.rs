@@ -0,0 +1,35 @@
+//! End-to-end process lock tests
+
+mod common;
+
+use common::TestContext;
+
+#[test]
+fn test_stale_lock_is_ignored() {
+    let ctx = TestContext::new();
+    let lock_dir = ctx.apl_home.join("lock");
+    std::fs::create_dir_all(&lock_dir).unwrap();
+    // No process can have this pid.
+    std::fs::write(lock_dir.join("exclusive"), u32::MAX.to_string()).unwrap();
+
+    let output = ctx.apl(&["--no-wait", "install", "hello"], None);
+    assert!(output.status.success(), "install failed: {output:?}");
+    assert!(!lock_dir.join("exclusive").exists(), "lock not released");
+}
+
+#[test]
+fn test_no_wait_fails_while_locked() {
+    let ctx = TestContext::new();
+    let lock_dir = ctx.apl_home.join("lock");
+    std::fs::create_dir_all(&lock_dir).unwrap();
+    std::fs::write(lock_dir.join("exclusive"), std::process::id().to_string()).unwrap();
+
+    let output = ctx.apl(&["--no-wait", "install", "hello"], None);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(
+        stderr.contains(&format!("pid {}", std::process::id())),
+        "unexpected error: {stderr}"
+    );
+    assert!(!ctx.store("hello").exists());
+}
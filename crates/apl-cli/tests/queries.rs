@@ -0,0 +1,162 @@
+//! End-to-end tests for the read-only query commands and the
+//! command-not-found hook
+
+mod common;
+
+use common::{TestContext, json_document};
+
+#[test]
+fn test_provides_and_command_not_found() {
+    let ctx = TestContext::new();
+
+    let document = json_document(&ctx, &["provides", "hello"]);
+    let providers: Vec<&str> = document["providers"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|p| p["name"].as_str().unwrap())
+        .collect();
+    assert_eq!(providers, vec!["hello", "hello-ng"]);
+    assert_eq!(
+        json_document(&ctx, &["provides", "tool"])["providers"][0]["versions"],
+        serde_json::json!(["2.0.0", "1.0.0"])
+    );
+
+    let output = ctx.apl(&["hook", "command-not-found", "zsh"], None);
+    assert!(String::from_utf8_lossy(&output.stdout).contains("command_not_found_handler()"));
+
+    // Without opting in, the handler only suggests and fails like the shell.
+    let output = ctx.apl(&["hook", "handle", "--", "hello"], None);
+    assert_eq!(output.status.code(), Some(127));
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("apl install hello-ng"), "{stderr}");
+    let output = ctx.apl(&["hook", "handle", "--", "nope"], None);
+    assert_eq!(output.status.code(), Some(127));
+
+    // Opted in, a command only one package provides is run.
+    std::fs::write(
+        ctx.apl_home.join("config.toml"),
+        "[command_not_found]\nrun = true\n",
+    )
+    .unwrap();
+    let output = ctx.apl(&["hook", "handle", "--", "tool"], None);
+    assert!(output.status.success(), "handler failed: {output:?}");
+    assert!(String::from_utf8_lossy(&output.stdout).contains("tool 2.0.0"));
+    assert!(ctx.installed().is_empty());
+}
+
+#[test]
+fn test_search_filters_and_pages() {
+    let ctx = TestContext::new();
+    let names = |document: &serde_json::Value| -> Vec<String> {
+        document["results"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|r| r["name"].as_str().unwrap().to_string())
+            .collect()
+    };
+
+    let document = json_document(
+        &ctx,
+        &["search", "has-bin:hello", "--per-page", "1", "--page", "2"],
+    );
+    assert_eq!(document["total"], 2);
+    assert_eq!(names(&document), vec!["hello-ng"]);
+
+    assert!(ctx.apl(&["install", "tool"], None).status.success());
+    let document = json_document(&ctx, &["search", "installed:yes", "OR", "greet"]);
+    assert_eq!(names(&document), vec!["greet", "tool"]);
+
+    let output = ctx.apl(&["search", "colour:red"], None);
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("unknown filter"));
+}
+
+#[test]
+fn test_info_versions_and_files() {
+    let ctx = TestContext::new();
+    assert!(ctx.apl(&["install", "tool@1.0.0"], None).status.success());
+
+    let document = json_document(&ctx, &["info", "tool@1.0.0", "--files"]);
+    assert_eq!(document["version"], "1.0.0");
+    assert_eq!(document["latest"], "2.0.0");
+    assert_eq!(
+        document["versions"],
+        serde_json::json!([
+            {"version": "2.0.0", "installed": false, "active": false},
+            {"version": "1.0.0", "installed": true, "active": true},
+        ])
+    );
+    let files = document["files"].as_array().unwrap();
+    assert!(
+        files
+            .iter()
+            .any(|f| f.as_str().unwrap().ends_with("/bin/tool")),
+        "{files:?}"
+    );
+    assert!(
+        json_document(&ctx, &["info", "tool"])
+            .get("files")
+            .is_none()
+    );
+
+    let output = ctx.apl(&["info", "tool@9.9.9"], None);
+    assert!(!output.status.success());
+
+    let output = ctx.apl(&["info", "hello"], None);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("└── greet"), "{stdout}");
+}
+
+#[test]
+fn test_deps_and_rdeps() {
+    use serde_json::json;
+
+    let ctx = TestContext::new();
+    assert_eq!(
+        json_document(&ctx, &["deps", "hello"]),
+        json!({
+            "schema": 1,
+            "kind": "deps",
+            "package": "hello",
+            "build": false,
+            "nodes": [
+                {"name": "greet", "version": "1.0.0", "installed": false},
+                {"name": "hello", "version": "1.0.0", "installed": false},
+            ],
+            "edges": [{"package": "hello", "depends_on": "greet", "kind": "runtime"}],
+            "layers": [["greet"], ["hello"]],
+            "cycles": [],
+        })
+    );
+
+    let output = ctx.apl(&["deps", "hello", "--dot"], None);
+    let dot = String::from_utf8_lossy(&output.stdout);
+    assert!(dot.starts_with("digraph deps {"), "{dot}");
+    assert!(dot.contains("\"hello\" -> \"greet\";"), "{dot}");
+
+    let output = ctx.apl(&["rdeps", "greet"], None);
+    let tree = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        tree.contains("└── ") && tree.contains("1 dependent"),
+        "{tree}"
+    );
+
+    // Only installed dependents count with --installed.
+    assert!(
+        !ctx.apl(&["rdeps", "greet", "--installed"], None)
+            .status
+            .success()
+    );
+    assert!(ctx.apl(&["install", "hello"], None).status.success());
+    let document = json_document(&ctx, &["rdeps", "greet", "--installed"]);
+    assert_eq!(
+        document["edges"],
+        json!([{"package": "hello", "depends_on": "greet", "kind": "runtime"}])
+    );
+    assert_eq!(
+        document["nodes"][1],
+        json!({"name": "hello", "version": "1.0.0", "installed": true})
+    );
+}
@@ -0,0 +1,114 @@
+//! End-to-end tests for named package sources
+
+mod common;
+
+use std::process::Command;
+
+use apl_schema::index::{HashType, IndexBinary, IndexEntry, PackageIndex, VersionInfo};
+use apl_schema::{Arch, Sha256Hash};
+use sha2::{Digest, Sha256};
+
+use common::{TestContext, json_document, tarball};
+
+#[test]
+fn test_named_sources() {
+    use base64::Engine;
+    use ed25519_dalek::{Signer, SigningKey};
+
+    let ctx = TestContext::new();
+
+    // An internal source with a newer `tool`, signed with its own key.
+    let mut server = mockito::Server::new();
+    let artifact = tarball("internal tool 3.0.0", "tool");
+    server
+        .mock("GET", "/tool-3.0.0.tar.gz")
+        .with_body(&artifact)
+        .create();
+    let mut index = PackageIndex::new();
+    index.upsert(IndexEntry {
+        name: "tool".to_string(),
+        releases: vec![VersionInfo {
+            version: "3.0.0".to_string(),
+            binaries: vec![IndexBinary {
+                arch: Arch::Universal,
+                url: format!("{}/tool-3.0.0.tar.gz", server.url()),
+                hash: Sha256Hash::new(hex::encode(Sha256::digest(&artifact))),
+                hash_type: HashType::Sha256,
+            }],
+            bin: vec!["tool".to_string()],
+            ..VersionInfo::default()
+        }],
+        ..IndexEntry::default()
+    });
+    let bytes = index.to_bytes().unwrap();
+    let signing = SigningKey::from_bytes(&[7; 32]);
+    let engine = base64::engine::general_purpose::STANDARD;
+    server.mock("GET", "/index").with_body(&bytes).create();
+    server
+        .mock("GET", "/index.sig")
+        .with_body(engine.encode(signing.sign(&bytes).to_bytes()))
+        .create();
+    let url = format!("{}/index", server.url());
+    let key = engine.encode(signing.verifying_key().as_bytes());
+
+    // A key that does not match the signature is refused.
+    let other = engine.encode(SigningKey::from_bytes(&[8; 32]).verifying_key().as_bytes());
+    let output = ctx.apl(&["source", "add", "internal", &url, "--key", &other], None);
+    assert!(!output.status.success(), "bad signature accepted");
+    assert!(!ctx.apl_home.join("sources/internal").exists());
+
+    let args = ["source", "add", "internal", &url, "--key", &key];
+    let output = ctx.apl(&[&args[..], &["--priority", "10"]].concat(), None);
+    assert!(output.status.success(), "source add failed: {output:?}");
+
+    let list = json_document(&ctx, &["source", "list"]);
+    let names: Vec<_> = list["sources"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|s| (s["name"].clone(), s["packages"].clone()))
+        .collect();
+    assert_eq!(
+        names,
+        vec![("internal".into(), 1.into()), ("apl".into(), 4.into())]
+    );
+
+    let source_of = |name: &str| -> Option<String> {
+        let conn = rusqlite::Connection::open(ctx.apl_home.join("state.db")).unwrap();
+        conn.query_row(
+            "SELECT source FROM packages WHERE name = ?1 AND active = 1",
+            [name],
+            |r| r.get(0),
+        )
+        .unwrap()
+    };
+
+    // The higher priority source wins; `apl::` picks the default one.
+    let output = ctx.apl(&["install", "tool", "greet"], None);
+    assert!(output.status.success(), "install failed: {output:?}");
+    assert_eq!(source_of("tool").as_deref(), Some("internal"));
+    assert_eq!(source_of("greet").as_deref(), Some("apl"));
+    let run = Command::new(ctx.bin("tool")).output().unwrap();
+    assert_eq!(
+        String::from_utf8_lossy(&run.stdout),
+        "internal tool 3.0.0\n"
+    );
+
+    let output = ctx.apl(&["remove", "tool"], None);
+    assert!(output.status.success(), "remove failed: {output:?}");
+    let output = ctx.apl(&["install", "apl::tool"], None);
+    assert!(
+        output.status.success(),
+        "install apl::tool failed: {output:?}"
+    );
+    assert_eq!(source_of("tool").as_deref(), Some("apl"));
+    let run = Command::new(ctx.bin("tool")).output().unwrap();
+    assert_eq!(String::from_utf8_lossy(&run.stdout), "tool 2.0.0\n");
+
+    assert!(!ctx.apl(&["install", "nope::tool"], None).status.success());
+    assert!(!ctx.apl(&["source", "remove", "apl"], None).status.success());
+
+    let output = ctx.apl(&["source", "remove", "internal"], None);
+    assert!(output.status.success(), "source remove failed: {output:?}");
+    assert!(!ctx.apl_home.join("sources/internal").exists());
+}
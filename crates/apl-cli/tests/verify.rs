@@ -0,0 +1,34 @@
+//! End-to-end tests for `apl verify`
+
+mod common;
+
+use common::TestContext;
+
+#[test]
+fn test_verify_detects_and_repairs_damage() {
+    let ctx = TestContext::new();
+    assert!(ctx.apl(&["install", "hello"], None).status.success());
+    assert!(ctx.apl(&["verify"], None).status.success());
+
+    let script = ctx.store("hello").join("hello");
+    std::fs::write(&script, "#!/bin/sh\necho tampered\n").unwrap();
+    std::fs::write(ctx.store("greet").join("stray"), "").unwrap();
+    std::fs::remove_file(ctx.bin("greet")).unwrap();
+
+    let output = ctx.apl(&["verify"], None);
+    assert!(!output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("modified  hello"), "{stdout}");
+    assert!(stdout.contains("extra     stray"), "{stdout}");
+    assert!(stdout.contains("is missing"), "{stdout}");
+
+    let output = ctx.apl(&["verify", "--repair"], None);
+    assert!(output.status.success(), "repair failed: {output:?}");
+    assert_eq!(
+        std::fs::read_to_string(&script).unwrap(),
+        "#!/bin/sh\necho hello 1.0.0\n"
+    );
+    assert!(!ctx.store("greet").join("stray").exists());
+    assert!(ctx.apl(&["verify"], None).status.success());
+    ctx.assert_installed();
+}
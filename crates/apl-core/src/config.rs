@@ -0,0 +1,1099 @@
+//! User configuration
+//!
+//! Settings are read from several layers; each overrides only the keys it
+//! sets, in this order:
+//!
+//! 1. built-in defaults
+//! 2. the system file, `/etc/apl/config.toml` (or `$APL_SYSTEM_CONFIG`)
+//! 3. the user file, `~/.apl/config.toml`
+//! 4. the `[config]` table of the nearest project `apl.toml`
+//! 5. environment variables such as `APL_INDEX_URL` (see [`ENV_VARS`])
+//! 6. `--config key=value` on the command line
+//!
+//! A missing file is the same as an empty one.
+//!
+//! ```toml
+//! [index]
+//! url = "https://apl.pub/index"
+//! # Content-addressed mirror to download from instead of the index's own
+//! mirror = "https://cache.example.com"
+//!
+//! [network]
+//! parallel = 20              # connections kept open per host
+//! proxy = "http://proxy.local:3128"
+//!
+//! [timeouts]
+//! request = 300              # seconds; unset means no limit
+//! connect = 30
+//! dmg_mount = 30
+//!
+//! [cache]
+//! max_size = "2G"            # oldest downloads are evicted past this
+//!
+//! [ui]
+//! color = "auto"             # auto, always or never
+//! format = "text"            # text, json or ndjson
+//!
+//! [shell]
+//! fallback = "/bin/zsh"      # used when $SHELL is unset
+//!
+//! [extract]
+//! max_size = "32G"
+//! max_entries = 2000000
+//! max_ratio = 200
+//!
+//! # Names of links to versions kept side-by-side (`apl use --side-by-side`)
+//! version_template = "{bin}-{major}.{minor}"
+//!
+//! # Link python2's `python` as `python2` so it leaves python3's alone
+//! [links.python2]
+//! aliases = { python = "python2" }
+//!
+//! # When two packages provide the same binary, the higher priority keeps it
+//! [links.python3]
+//! priority = 10
+//! version_template = "python{major}.{minor}"
+//!
+//! # Run unknown commands through `apl run` when one package provides them
+//! [command_not_found]
+//! run = true
+//! ```
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+use toml::{Table, Value};
+
+/// System-wide configuration file.
+pub const SYSTEM_CONFIG: &str = "/etc/apl/config.toml";
+
+/// Environment variables read as configuration, with the key each sets.
+pub const ENV_VARS: &[(&str, &str)] = &[
+    ("APL_INDEX_URL", "index.url"),
+    ("APL_INDEX_MIRROR", "index.mirror"),
+    ("APL_PARALLEL", "network.parallel"),
+    ("APL_PROXY", "network.proxy"),
+    ("APL_CACHE_MAX_SIZE", "cache.max_size"),
+    ("APL_COLOR", "ui.color"),
+    ("APL_FORMAT", "ui.format"),
+    ("APL_EXTRACT_MAX_SIZE", "extract.max_size"),
+    ("APL_EXTRACT_MAX_ENTRIES", "extract.max_entries"),
+    ("APL_EXTRACT_MAX_RATIO", "extract.max_ratio"),
+];
+
+/// Keys that can be set, besides the per-package `links.<package>.*` keys.
+const KEYS: &[&str] = &[
+    "index.url",
+    "index.mirror",
+    "network.parallel",
+    "network.proxy",
+    "timeouts.request",
+    "timeouts.connect",
+    "timeouts.dmg_mount",
+    "cache.max_size",
+    "ui.color",
+    "ui.format",
+    "shell.fallback",
+    "extract.max_size",
+    "extract.max_entries",
+    "extract.max_ratio",
+    "version_template",
+    "command_not_found.run",
+];
+
+/// Errors from reading or changing configuration.
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    /// A configuration file could not be read or written.
+    #[error("Failed to access {}: {source}", path.display())]
+    Io {
+        /// The file
+        path: PathBuf,
+        /// Underlying error
+        #[source]
+        source: io::Error,
+    },
+
+    /// A configuration file is not valid TOML or has values of the wrong type.
+    #[error("Invalid config {}: {source}", path.display())]
+    Parse {
+        /// The file
+        path: PathBuf,
+        /// Underlying error
+        #[source]
+        source: Box<toml::de::Error>,
+    },
+
+    /// A value from the environment or command line has the wrong type.
+    #[error("Invalid value for {key} (from {origin}): {source}")]
+    Value {
+        /// Dotted key
+        key: String,
+        /// Where the value came from
+        origin: Origin,
+        /// Underlying error
+        #[source]
+        source: Box<toml::de::Error>,
+    },
+
+    /// The merged layers do not form a valid configuration.
+    #[error("Invalid config: {0}")]
+    Invalid(#[source] Box<toml::de::Error>),
+
+    /// The key is not a configuration setting.
+    #[error("Unknown config key '{0}'")]
+    UnknownKey(String),
+
+    /// A `--config` override is not of the form `key=value`.
+    #[error("Invalid override '{0}': expected key=value")]
+    Override(String),
+}
+
+/// Where a setting's value came from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Origin {
+    /// Built-in default
+    Default,
+    /// System configuration file
+    System(PathBuf),
+    /// User configuration file
+    User(PathBuf),
+    /// `[config]` table of a project's `apl.toml`
+    Project(PathBuf),
+    /// Environment variable
+    Env(&'static str),
+    /// `--config` on the command line
+    Cli,
+}
+
+impl fmt::Display for Origin {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Default => write!(f, "default"),
+            Self::System(path) => write!(f, "system:{}", path.display()),
+            Self::User(path) => write!(f, "user:{}", path.display()),
+            Self::Project(path) => write!(f, "project:{}", path.display()),
+            Self::Env(var) => write!(f, "env:{var}"),
+            Self::Cli => write!(f, "command line"),
+        }
+    }
+}
+
+/// Typed view of the merged configuration.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Where packages come from
+    pub index: IndexConfig,
+    /// HTTP connections
+    pub network: NetworkConfig,
+    /// Time limits, in seconds
+    pub timeouts: TimeoutConfig,
+    /// Download cache in `~/.apl/cache`
+    pub cache: CacheConfig,
+    /// Terminal output
+    pub ui: UiConfig,
+    /// `apl shell`
+    pub shell: ShellConfig,
+    /// Limits applied while extracting archives
+    pub extract: ExtractConfig,
+    /// Name of side-by-side version links; see [`Config::versioned_links`]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_template: Option<String>,
+    /// How each package's binaries are linked into `~/.apl/bin`, by package name
+    pub links: BTreeMap<String, LinkConfig>,
+    /// Behaviour of the shell's command-not-found hook
+    pub command_not_found: CommandNotFoundConfig,
+}
+
+/// Side-by-side links are named e.g. `terraform-1.5` unless configured.
+pub const DEFAULT_VERSION_TEMPLATE: &str = "{bin}-{major}.{minor}";
+
+/// `[index]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexConfig {
+    /// URL `apl update` downloads the index from
+    pub url: String,
+    /// Content-addressed mirror used instead of the one the index names
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mirror: Option<String>,
+}
+
+impl Default for IndexConfig {
+    fn default() -> Self {
+        Self {
+            url: "https://apl.pub/index".to_string(),
+            mirror: None,
+        }
+    }
+}
+
+/// `[network]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct NetworkConfig {
+    /// Idle connections kept open per host, which bounds parallel downloads
+    pub parallel: usize,
+    /// Proxy for all requests, e.g. `http://proxy.local:3128`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+}
+
+impl Default for NetworkConfig {
+    fn default() -> Self {
+        Self {
+            parallel: 20,
+            proxy: None,
+        }
+    }
+}
+
+/// `[timeouts]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeoutConfig {
+    /// Whole-request limit for HTTP requests; `None` for no limit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request: Option<u64>,
+    /// Limit for establishing a connection
+    pub connect: u64,
+    /// Limit for `hdiutil` to mount a disk image
+    pub dmg_mount: u64,
+}
+
+impl Default for TimeoutConfig {
+    fn default() -> Self {
+        Self {
+            request: None,
+            connect: 30,
+            dmg_mount: 30,
+        }
+    }
+}
+
+/// `[cache]`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CacheConfig {
+    /// Size past which the oldest cached downloads are evicted; `None` to
+    /// keep everything
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<ByteSize>,
+}
+
+/// `[ui]`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct UiConfig {
+    /// Whether to color terminal output
+    pub color: ColorMode,
+    /// Output format when `--format` is not given
+    pub format: OutputFormat,
+}
+
+/// When to color terminal output.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorMode {
+    /// Color when writing to a terminal, unless `NO_COLOR` is set
+    #[default]
+    Auto,
+    /// Always color
+    Always,
+    /// Never color
+    Never,
+}
+
+/// Default output format, as with `--format`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human-readable text
+    #[default]
+    Text,
+    /// One JSON document
+    Json,
+    /// Newline-delimited JSON
+    Ndjson,
+}
+
+/// `[shell]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ShellConfig {
+    /// Shell started by `apl shell` when `$SHELL` is unset
+    pub fallback: String,
+}
+
+impl Default for ShellConfig {
+    fn default() -> Self {
+        Self {
+            fallback: "/bin/zsh".to_string(),
+        }
+    }
+}
+
+/// `[extract]`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ExtractConfig {
+    /// Maximum bytes written across all entries of an archive
+    pub max_size: ByteSize,
+    /// Maximum number of entries in an archive
+    pub max_entries: u64,
+    /// Maximum bytes written per compressed byte read
+    pub max_ratio: u64,
+}
+
+impl Default for ExtractConfig {
+    fn default() -> Self {
+        let policy = crate::io::extract::ExtractPolicy::default();
+        Self {
+            max_size: ByteSize(policy.max_total_size),
+            max_entries: policy.max_entries,
+            max_ratio: policy.max_ratio,
+        }
+    }
+}
+
+/// `[links.<package>]`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct LinkConfig {
+    /// Link names to use instead of the package's own, keyed by the original name
+    pub aliases: BTreeMap<String, String>,
+    /// Which package keeps a link name several packages provide; higher wins
+    pub priority: i32,
+    /// Overrides the global `version_template` for this package
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version_template: Option<String>,
+}
+
+/// `[command_not_found]`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CommandNotFoundConfig {
+    /// Run the command via `apl run` instead of only suggesting a package
+    pub run: bool,
+}
+
+/// A size in bytes, written as a number or with a `K`, `M`, `G` or `T`
+/// suffix (powers of 1024), e.g. `"500M"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+impl ByteSize {
+    /// Parses `1024`, `512K`, `2G` or `2GB`.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        let s = s.strip_suffix(['B', 'b']).unwrap_or(s);
+        let (digits, shift) = match s.chars().last()?.to_ascii_uppercase() {
+            'K' => (&s[..s.len() - 1], 10),
+            'M' => (&s[..s.len() - 1], 20),
+            'G' => (&s[..s.len() - 1], 30),
+            'T' => (&s[..s.len() - 1], 40),
+            _ => (s, 0),
+        };
+        let n: u64 = digits.trim().parse().ok()?;
+        n.checked_mul(1 << shift).map(Self)
+    }
+}
+
+impl Serialize for ByteSize {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for ByteSize {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Bytes(u64),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Bytes(n) => Ok(Self(n)),
+            Raw::Text(s) => Self::parse(&s).ok_or_else(|| {
+                serde::de::Error::custom(format!("invalid size '{s}', expected e.g. 500M or 2G"))
+            }),
+        }
+    }
+}
+
+static GLOBAL: OnceLock<Config> = OnceLock::new();
+
+impl Config {
+    /// Loads the configuration from the standard layers, without
+    /// command-line overrides.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if a layer cannot be read or has invalid values.
+    pub fn load() -> Result<Self, ConfigError> {
+        Settings::load(&Sources::discover(Vec::new()))?.config()
+    }
+
+    /// Loads a single configuration file over the defaults.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or has invalid values.
+    pub fn load_from(path: &Path) -> Result<Self, ConfigError> {
+        let table = read_table(path)?.unwrap_or_default();
+        table.try_into().map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source: Box::new(source),
+        })
+    }
+
+    /// Makes this the configuration [`Config::global`] returns. Only the
+    /// first call has an effect.
+    pub fn set_global(self) {
+        let _ = GLOBAL.set(self);
+    }
+
+    /// The configuration of this process: the one passed to
+    /// [`Config::set_global`], or else the standard layers loaded on first
+    /// use (defaults if they fail to load).
+    pub fn global() -> &'static Self {
+        GLOBAL.get_or_init(|| Self::load().unwrap_or_default())
+    }
+
+    /// Link priority of a package (0 unless configured).
+    pub fn priority(&self, package: &str) -> i32 {
+        self.links.get(package).map_or(0, |l| l.priority)
+    }
+
+    /// Renames a package's links according to its configured aliases.
+    pub fn alias_links(
+        &self,
+        package: &str,
+        links: Vec<(String, PathBuf)>,
+    ) -> Vec<(String, PathBuf)> {
+        let Some(config) = self.links.get(package) else {
+            return links;
+        };
+        links
+            .into_iter()
+            .map(|(name, target)| match config.aliases.get(&name) {
+                Some(alias) => (alias.clone(), target),
+                None => (name, target),
+            })
+            .collect()
+    }
+
+    /// Renames a side-by-side version's links with the package's version
+    /// template, which may use `{bin}`, `{version}`, `{major}`, `{minor}`
+    /// and `{patch}`.
+    pub fn versioned_links(
+        &self,
+        package: &str,
+        version: &str,
+        links: Vec<(String, PathBuf)>,
+    ) -> Vec<(String, PathBuf)> {
+        let template = self
+            .links
+            .get(package)
+            .and_then(|l| l.version_template.as_deref())
+            .or(self.version_template.as_deref())
+            .unwrap_or(DEFAULT_VERSION_TEMPLATE);
+        let mut parts = version.trim_start_matches('v').split(['.', '-', '+']);
+        let major = parts.next().unwrap_or_default();
+        let minor = parts.next().unwrap_or("0");
+        let patch = parts.next().unwrap_or("0");
+
+        links
+            .into_iter()
+            .map(|(name, target)| {
+                let name = template
+                    .replace("{bin}", &name)
+                    .replace("{version}", version)
+                    .replace("{major}", major)
+                    .replace("{minor}", minor)
+                    .replace("{patch}", patch);
+                (name, target)
+            })
+            .collect()
+    }
+}
+
+/// Where each configuration layer is read from.
+#[derive(Debug, Clone, Default)]
+pub struct Sources {
+    /// System file
+    pub system: Option<PathBuf>,
+    /// User file
+    pub user: Option<PathBuf>,
+    /// Project `apl.toml`
+    pub project: Option<PathBuf>,
+    /// Environment variables; only those in [`ENV_VARS`] are used
+    pub env: BTreeMap<String, String>,
+    /// `key=value` overrides from the command line
+    pub overrides: Vec<String>,
+}
+
+impl Sources {
+    /// The standard layers of this process, with `overrides` from the
+    /// command line.
+    pub fn discover(overrides: Vec<String>) -> Self {
+        Self {
+            system: Some(
+                std::env::var_os("APL_SYSTEM_CONFIG")
+                    .map_or_else(|| PathBuf::from(SYSTEM_CONFIG), PathBuf::from),
+            ),
+            user: crate::try_apl_home().map(|home| home.join("config.toml")),
+            project: std::env::current_dir()
+                .ok()
+                .and_then(|dir| find_project(&dir)),
+            env: std::env::vars()
+                .filter(|(var, _)| var.starts_with("APL_"))
+                .collect(),
+            overrides,
+        }
+    }
+}
+
+/// The nearest `apl.toml` in `start` or its parents.
+pub fn find_project(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|dir| dir.join("apl.toml"))
+        .find(|path| path.is_file())
+}
+
+/// Configuration merged from every layer, with where each value came from.
+#[derive(Debug, Clone, Default)]
+pub struct Settings {
+    values: Table,
+    /// Origin of every leaf value, by dotted key
+    origins: BTreeMap<String, Origin>,
+}
+
+impl Settings {
+    /// Reads and merges every layer of `sources`, checking each one's types.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error naming the first layer that cannot be read, has
+    /// invalid values or sets an unknown key.
+    pub fn load(sources: &Sources) -> Result<Self, ConfigError> {
+        let mut settings = Self::default();
+        settings.merge(
+            Table::try_from(Config::default()).unwrap_or_default(),
+            &Origin::Default,
+        );
+
+        let files = [
+            (&sources.system, Origin::System as fn(PathBuf) -> Origin),
+            (&sources.user, Origin::User),
+        ];
+        for (path, origin) in files {
+            let Some(path) = path else { continue };
+            if let Some(table) = read_table(path)? {
+                check(&table, path)?;
+                settings.merge(table, &origin(path.clone()));
+            }
+        }
+        if let Some(path) = &sources.project {
+            if let Some(Value::Table(table)) =
+                read_table(path)?.and_then(|mut t| t.remove("config"))
+            {
+                check(&table, path)?;
+                settings.merge(table, &Origin::Project(path.clone()));
+            }
+        }
+
+        for &(var, key) in ENV_VARS {
+            if let Some(raw) = sources.env.get(var) {
+                settings.merge_value(key, raw, Origin::Env(var))?;
+            }
+        }
+        for item in &sources.overrides {
+            let (key, raw) = item
+                .split_once('=')
+                .ok_or_else(|| ConfigError::Override(item.clone()))?;
+            let key = key.trim();
+            if !is_known_key(key) {
+                return Err(ConfigError::UnknownKey(key.to_string()));
+            }
+            settings.merge_value(key, raw, Origin::Cli)?;
+        }
+        Ok(settings)
+    }
+
+    /// The typed configuration.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the merged layers are not a valid configuration.
+    pub fn config(&self) -> Result<Config, ConfigError> {
+        self.values
+            .clone()
+            .try_into()
+            .map_err(|e| ConfigError::Invalid(Box::new(e)))
+    }
+
+    /// Value at a dotted `key`; a table for keys like `links.python3`.
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        let mut parts = key.split('.');
+        let mut value = self.values.get(parts.next()?)?;
+        for part in parts {
+            value = value.as_table()?.get(part)?;
+        }
+        Some(value)
+    }
+
+    /// Where the value of a dotted `key` came from.
+    pub fn origin(&self, key: &str) -> Option<&Origin> {
+        self.origins.get(key)
+    }
+
+    /// Every value that is not a table, by dotted key in order, with its
+    /// origin.
+    pub fn entries(&self) -> impl Iterator<Item = (&str, &Value, &Origin)> {
+        self.origins
+            .iter()
+            .filter_map(|(key, origin)| Some((key.as_str(), self.get(key)?, origin)))
+    }
+
+    /// Parses one value from the environment or command line, checks its
+    /// type and merges it.
+    fn merge_value(&mut self, key: &str, raw: &str, origin: Origin) -> Result<(), ConfigError> {
+        let mut table = Table::new();
+        insert_dotted(&mut table, key, parse_value(raw));
+        if let Err(source) = table.clone().try_into::<Config>() {
+            return Err(ConfigError::Value {
+                key: key.to_string(),
+                origin,
+                source: Box::new(source),
+            });
+        }
+        self.merge(table, &origin);
+        Ok(())
+    }
+
+    fn merge(&mut self, layer: Table, origin: &Origin) {
+        merge_into(&mut self.values, layer, "", origin, &mut self.origins);
+    }
+}
+
+fn merge_into(
+    base: &mut Table,
+    layer: Table,
+    prefix: &str,
+    origin: &Origin,
+    origins: &mut BTreeMap<String, Origin>,
+) {
+    for (name, value) in layer {
+        let key = if prefix.is_empty() {
+            name.clone()
+        } else {
+            format!("{prefix}.{name}")
+        };
+        match (base.get_mut(&name), value) {
+            (Some(Value::Table(existing)), Value::Table(table)) => {
+                merge_into(existing, table, &key, origin, origins);
+            }
+            (_, value) => {
+                // Whatever was below the replaced value is gone.
+                let nested = format!("{key}.");
+                origins.retain(|k, _| *k != key && !k.starts_with(&nested));
+                record_origins(&value, &key, origin, origins);
+                base.insert(name, value);
+            }
+        }
+    }
+}
+
+fn record_origins(
+    value: &Value,
+    key: &str,
+    origin: &Origin,
+    origins: &mut BTreeMap<String, Origin>,
+) {
+    match value {
+        Value::Table(table) => {
+            for (name, value) in table {
+                record_origins(value, &format!("{key}.{name}"), origin, origins);
+            }
+        }
+        _ => {
+            origins.insert(key.to_string(), origin.clone());
+        }
+    }
+}
+
+/// Whether `key` names a setting (see the module docs).
+pub fn is_known_key(key: &str) -> bool {
+    if KEYS.contains(&key) {
+        return true;
+    }
+    let Some(rest) = key.strip_prefix("links.") else {
+        return false;
+    };
+    match rest.split('.').collect::<Vec<_>>()[..] {
+        [package, "priority" | "version_template"] => !package.is_empty(),
+        [package, "aliases", name] => !package.is_empty() && !name.is_empty(),
+        _ => false,
+    }
+}
+
+/// Reads a TOML file; `None` if it does not exist.
+fn read_table(path: &Path) -> Result<Option<Table>, ConfigError> {
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(source) => {
+            return Err(ConfigError::Io {
+                path: path.to_path_buf(),
+                source,
+            });
+        }
+    };
+    content
+        .parse::<Table>()
+        .map(Some)
+        .map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source: Box::new(source),
+        })
+}
+
+/// Checks the types of one file's settings.
+fn check(table: &Table, path: &Path) -> Result<(), ConfigError> {
+    table
+        .clone()
+        .try_into::<Config>()
+        .map(drop)
+        .map_err(|source| ConfigError::Parse {
+            path: path.to_path_buf(),
+            source: Box::new(source),
+        })
+}
+
+/// Reads a value written on the command line or in the environment: TOML
+/// syntax (`20`, `true`, `["a"]`) if it parses, a plain string otherwise.
+pub fn parse_value(raw: &str) -> Value {
+    format!("v = {raw}")
+        .parse::<Table>()
+        .ok()
+        .and_then(|mut table| table.remove("v"))
+        .unwrap_or_else(|| Value::String(raw.to_string()))
+}
+
+fn insert_dotted(table: &mut Table, key: &str, value: Value) {
+    match key.split_once('.') {
+        Some((head, rest)) => {
+            let entry = table
+                .entry(head)
+                .or_insert_with(|| Value::Table(Table::new()));
+            if !entry.is_table() {
+                *entry = Value::Table(Table::new());
+            }
+            if let Value::Table(nested) = entry {
+                insert_dotted(nested, rest, value);
+            }
+        }
+        None => {
+            table.insert(key.to_string(), value);
+        }
+    }
+}
+
+/// Sets `key` to `raw` (see [`parse_value`]) in the file at `path`,
+/// keeping its comments and layout.
+///
+/// # Errors
+///
+/// Returns an error if `key` is unknown, the value has the wrong type, or
+/// the file cannot be read, parsed or written.
+pub fn set_value(path: &Path, key: &str, raw: &str) -> Result<(), ConfigError> {
+    if !is_known_key(key) {
+        return Err(ConfigError::UnknownKey(key.to_string()));
+    }
+    let content = match std::fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+        Err(source) => {
+            return Err(ConfigError::Io {
+                path: path.to_path_buf(),
+                source,
+            });
+        }
+    };
+    let parse_error = |source: toml::de::Error| ConfigError::Parse {
+        path: path.to_path_buf(),
+        source: Box::new(source),
+    };
+    let mut document: toml_edit::DocumentMut = content
+        .parse()
+        .map_err(|e: toml_edit::TomlError| parse_error(serde::de::Error::custom(e)))?;
+
+    let parts: Vec<&str> = key.split('.').collect();
+    let (name, tables) = parts.split_last().unwrap_or((&key, &[]));
+    let implicit_table = || {
+        let mut table = toml_edit::Table::new();
+        table.set_implicit(true);
+        toml_edit::Item::Table(table)
+    };
+    let mut table: &mut dyn toml_edit::TableLike = document.as_table_mut();
+    for part in tables {
+        let item = table.entry(part).or_insert_with(implicit_table);
+        if !item.is_table_like() {
+            *item = implicit_table();
+        }
+        let Some(nested) = item.as_table_like_mut() else {
+            return Err(ConfigError::UnknownKey(key.to_string()));
+        };
+        table = nested;
+    }
+    let value = raw
+        .parse::<toml_edit::Value>()
+        .unwrap_or_else(|_| toml_edit::Value::from(raw));
+    table.insert(name, toml_edit::Item::Value(value));
+
+    let updated = document.to_string();
+    let checked: Table = updated.parse().map_err(parse_error)?;
+    check(&checked, path).map_err(|e| match e {
+        ConfigError::Parse { source, .. } => ConfigError::Value {
+            key: key.to_string(),
+            origin: Origin::User(path.to_path_buf()),
+            source,
+        },
+        e => e,
+    })?;
+
+    let io_error = |source| ConfigError::Io {
+        path: path.to_path_buf(),
+        source,
+    };
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir).map_err(io_error)?;
+    }
+    std::fs::write(path, updated).map_err(io_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_missing_file_is_empty() {
+        let dir = tempdir().unwrap();
+        let config = Config::load_from(&dir.path().join("config.toml")).unwrap();
+        assert!(config.links.is_empty());
+        assert_eq!(config.priority("jq"), 0);
+        assert!(!config.command_not_found.run);
+    }
+
+    #[test]
+    fn test_aliases_and_priority() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[links.python2]\naliases = { python = \"python2\" }\n\n[links.python3]\npriority = 10\n",
+        )
+        .unwrap();
+
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.priority("python3"), 10);
+        assert_eq!(config.priority("python2"), 0);
+
+        let links = vec![
+            ("python".to_string(), PathBuf::from("bin/python")),
+            ("pip".to_string(), PathBuf::from("bin/pip")),
+        ];
+        let names: Vec<String> = config
+            .alias_links("python2", links.clone())
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, vec!["python2", "pip"]);
+        assert_eq!(config.alias_links("python3", links.clone()), links);
+    }
+
+    #[test]
+    fn test_versioned_links() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        let links = vec![("terraform".to_string(), PathBuf::from("terraform"))];
+
+        let config = Config::load_from(&path).unwrap();
+        let names = |config: &Config, package: &str, version: &str| -> Vec<String> {
+            config
+                .versioned_links(package, version, links.clone())
+                .into_iter()
+                .map(|(name, _)| name)
+                .collect()
+        };
+        assert_eq!(names(&config, "terraform", "1.5.7"), vec!["terraform-1.5"]);
+        assert_eq!(names(&config, "terraform", "2"), vec!["terraform-2.0"]);
+
+        std::fs::write(
+            &path,
+            "version_template = \"{bin}@{version}\"\n\n[links.python]\nversion_template = \"python{major}.{minor}\"\n",
+        )
+        .unwrap();
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(
+            names(&config, "terraform", "1.5.7"),
+            vec!["terraform@1.5.7"]
+        );
+        assert_eq!(names(&config, "python", "3.11.4"), vec!["python3.11"]);
+    }
+
+    #[test]
+    fn test_layers_and_origins() {
+        let dir = tempdir().unwrap();
+        let system = dir.path().join("system.toml");
+        let user = dir.path().join("config.toml");
+        let project = dir.path().join("apl.toml");
+        std::fs::write(
+            &system,
+            "[network]\nparallel = 4\nproxy = \"http://proxy\"\n",
+        )
+        .unwrap();
+        std::fs::write(&user, "[network]\nparallel = 8\n[links.jq]\npriority = 3\n").unwrap();
+        std::fs::write(
+            &project,
+            "[project]\nname = \"demo\"\n\n[config]\nui = { color = \"never\" }\n",
+        )
+        .unwrap();
+
+        let sources = Sources {
+            system: Some(system.clone()),
+            user: Some(user.clone()),
+            project: Some(project.clone()),
+            env: BTreeMap::from([(
+                "APL_INDEX_URL".to_string(),
+                "https://example.com/index".to_string(),
+            )]),
+            overrides: vec!["cache.max_size=500M".to_string()],
+        };
+        let settings = Settings::load(&sources).unwrap();
+        let config = settings.config().unwrap();
+        assert_eq!(config.network.parallel, 8);
+        assert_eq!(config.network.proxy.as_deref(), Some("http://proxy"));
+        assert_eq!(config.ui.color, ColorMode::Never);
+        assert_eq!(config.index.url, "https://example.com/index");
+        assert_eq!(config.cache.max_size, Some(ByteSize(500 << 20)));
+        assert_eq!(config.priority("jq"), 3);
+
+        assert_eq!(
+            settings.origin("network.parallel"),
+            Some(&Origin::User(user))
+        );
+        assert_eq!(
+            settings.origin("network.proxy"),
+            Some(&Origin::System(system))
+        );
+        assert_eq!(settings.origin("ui.color"), Some(&Origin::Project(project)));
+        assert_eq!(
+            settings.origin("index.url"),
+            Some(&Origin::Env("APL_INDEX_URL"))
+        );
+        assert_eq!(settings.origin("cache.max_size"), Some(&Origin::Cli));
+        assert_eq!(settings.origin("timeouts.connect"), Some(&Origin::Default));
+        assert!(settings.get("links.jq").unwrap().is_table());
+        assert!(
+            settings
+                .entries()
+                .any(|(key, _, _)| key == "links.jq.priority")
+        );
+    }
+
+    #[test]
+    fn test_bad_values_name_their_origin() {
+        let sources = Sources {
+            env: BTreeMap::from([("APL_PARALLEL".to_string(), "many".to_string())]),
+            ..Sources::default()
+        };
+        let err = Settings::load(&sources).unwrap_err();
+        assert!(matches!(
+            &err,
+            ConfigError::Value {
+                origin: Origin::Env("APL_PARALLEL"),
+                ..
+            }
+        ));
+
+        let sources = Sources {
+            overrides: vec!["network.paralel=3".to_string()],
+            ..Sources::default()
+        };
+        assert!(matches!(
+            Settings::load(&sources),
+            Err(ConfigError::UnknownKey(_))
+        ));
+    }
+
+    #[test]
+    fn test_set_value_keeps_comments() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "# my settings\n[links.jq]\npriority = 1\n").unwrap();
+
+        set_value(&path, "network.parallel", "6").unwrap();
+        set_value(&path, "index.mirror", "https://cache.example.com").unwrap();
+        set_value(&path, "links.jq.aliases.jq", "jq1").unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        assert!(content.starts_with("# my settings"));
+
+        let config = Config::load_from(&path).unwrap();
+        assert_eq!(config.network.parallel, 6);
+        assert_eq!(
+            config.index.mirror.as_deref(),
+            Some("https://cache.example.com")
+        );
+        assert_eq!(config.links["jq"].aliases["jq"], "jq1");
+        assert_eq!(config.priority("jq"), 1);
+
+        assert!(matches!(
+            set_value(&path, "network.parallel", "lots"),
+            Err(ConfigError::Value { .. })
+        ));
+        assert!(matches!(
+            set_value(&path, "links.jq", "3"),
+            Err(ConfigError::UnknownKey(_))
+        ));
+        assert_eq!(Config::load_from(&path).unwrap().network.parallel, 6);
+    }
+
+    #[test]
+    fn test_defaults_are_known_keys() {
+        let settings = Settings::load(&Sources::default()).unwrap();
+        for (key, _, origin) in settings.entries() {
+            assert_eq!(origin, &Origin::Default);
+            assert!(is_known_key(key), "{key}");
+        }
+    }
+
+    #[test]
+    fn test_byte_size() {
+        assert_eq!(ByteSize::parse("1024"), Some(ByteSize(1024)));
+        assert_eq!(ByteSize::parse("512K"), Some(ByteSize(512 << 10)));
+        assert_eq!(ByteSize::parse("2gb"), Some(ByteSize(2 << 30)));
+        assert_eq!(ByteSize::parse("lots"), None);
+    }
+
+    #[test]
+    fn test_invalid_config_is_reported() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[links.jq]\npriority = \"high\"\n").unwrap();
+        assert!(matches!(
+            Config::load_from(&path),
+            Err(ConfigError::Parse { .. })
+        ));
+    }
+}
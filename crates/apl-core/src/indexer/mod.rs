@@ -66,10 +66,7 @@ pub async fn generate_index_from_registry(
     let mut index = if !force_full && index_path.exists() {
         match PackageIndex::load(&index_path) {
             Ok(existing) => {
-                println!(
-                    "  loaded existing index ({} packages)",
-                    existing.packages.len()
-                );
+                println!("  loaded existing index ({} packages)", existing.len());
                 existing
             }
             Err(e) => {
@@ -185,7 +182,7 @@ pub async fn generate_index_from_registry(
     let mut dirty_repos: Vec<RepoKey> = Vec::new();
     let mut _skipped_count = 0;
 
-    if !force_full && !github_repos.is_empty() && !index.packages.is_empty() {
+    if !force_full && !github_repos.is_empty() && !index.is_empty() {
         // Optional: Add a quick spinner for delta check if needed
         // let pb_delta = multi.add(ProgressBar::new_spinner());
         // pb_delta.set_style(style.clone());
@@ -585,7 +582,10 @@ pub async fn generate_index_from_registry(
                     bins: vec![],
                     releases: vec![],
                     tags: template.package.tags.clone(),
+                    license: template.package.license.clone(),
                 });
+            } else if let Some(entry) = index.find_mut(&pkg_name) {
+                entry.license.clone_from(&template.package.license);
             }
 
             // Print per-package result: success is silent, problems surface with reason
@@ -611,9 +611,9 @@ pub async fn generate_index_from_registry(
     // Phase 4: Pruning stale packages
     // Only prune if we didn't filter by a specific package (which would prune everything else)
     if package_filter.is_none() {
-        let initial_count = index.packages.len();
-        index.packages.retain(|p| valid_packages.contains(&p.name));
-        let pruned = initial_count - index.packages.len();
+        let initial_count = index.len();
+        index.retain(|p| valid_packages.contains(&p.name));
+        let pruned = initial_count - index.len();
         if pruned > 0 {
             println!("    pruned {pruned} stale packages");
         }
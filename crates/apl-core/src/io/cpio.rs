@@ -0,0 +1,387 @@
+//! Streaming reader for cpio archives, as used by `.pkg` `Payload` and
+//! `Scripts` members.
+//!
+//! Supports the portable ASCII (`odc`, magic `070707`) and SVR4 (`newc`,
+//! magic `070701`/`070702`) variants. Regular files, directories, symlinks
+//! and hard links are materialised; device nodes and FIFOs are rejected by
+//! the [`ExtractGuard`].
+
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use super::extract::{ExtractError, ExtractGuard, ExtractedFile};
+
+const TRAILER: &str = "TRAILER!!!";
+/// Longest entry name read; the header field sizes an allocation, and real
+/// names are far below `PATH_MAX`.
+const MAX_NAME_LEN: u64 = 16 * 1024;
+
+const S_IFMT: u32 = 0o170_000;
+const S_IFREG: u32 = 0o100_000;
+const S_IFDIR: u32 = 0o040_000;
+const S_IFLNK: u32 = 0o120_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Variant {
+    Odc,
+    Newc,
+}
+
+#[derive(Debug)]
+struct Header {
+    ino: u64,
+    mode: u32,
+    nlink: u64,
+    name_size: u64,
+    file_size: u64,
+}
+
+/// Extract every entry of a cpio stream into `dest_dir`.
+///
+/// Entry names are resolved relative to `dest_dir` (a leading `./` is
+/// ignored) and checked against `guard`.
+///
+/// # Errors
+///
+/// Returns [`ExtractError`] if the stream is truncated, uses an unknown
+/// cpio variant, breaks the extraction policy, or an entry cannot be written.
+pub fn extract_cpio<R: Read>(
+    mut reader: R,
+    dest_dir: &Path,
+    guard: &mut ExtractGuard,
+) -> Result<Vec<ExtractedFile>, ExtractError> {
+    fs::create_dir_all(dest_dir)?;
+
+    let mut extracted = Vec::new();
+    // newc stores hard-linked data only on the last link; remember earlier names by inode.
+    let mut pending_links: HashMap<u64, Vec<PathBuf>> = HashMap::new();
+    let mut offset: u64 = 0;
+
+    loop {
+        let mut magic = [0u8; 6];
+        reader.read_exact(&mut magic)?;
+        offset += 6;
+
+        let variant = match &magic {
+            b"070707" => Variant::Odc,
+            b"070701" | b"070702" => Variant::Newc,
+            _ => {
+                return Err(ExtractError::Archive(format!(
+                    "Bad cpio magic at offset {}",
+                    offset - 6
+                )));
+            }
+        };
+
+        let header = read_header(&mut reader, variant, &mut offset)?;
+        if header.name_size > MAX_NAME_LEN {
+            return Err(ExtractError::Archive(format!(
+                "cpio entry name of {} bytes at offset {offset}",
+                header.name_size
+            )));
+        }
+
+        let mut name_buf = vec![0u8; header.name_size as usize];
+        reader.read_exact(&mut name_buf)?;
+        offset += header.name_size;
+        skip_padding(&mut reader, variant, &mut offset)?;
+
+        let name =
+            String::from_utf8_lossy(name_buf.strip_suffix(&[0]).unwrap_or(&name_buf)).into_owned();
+        if name == TRAILER {
+            break;
+        }
+
+        let entry_path = Path::new(name.trim_start_matches("./"));
+        let file_type = header.mode & S_IFMT;
+        let mut data = (&mut reader).take(header.file_size);
+
+        if entry_path.as_os_str().is_empty() || entry_path == Path::new(".") {
+            io::copy(&mut data, &mut io::sink())?;
+        } else {
+            let absolute_path = guard.entry(dest_dir, entry_path)?;
+            let relative_path = absolute_path
+                .strip_prefix(dest_dir)
+                .map_or_else(|_| entry_path.to_path_buf(), Path::to_path_buf);
+
+            match file_type {
+                S_IFDIR => {
+                    fs::create_dir_all(&absolute_path)?;
+                    io::copy(&mut data, &mut io::sink())?;
+                }
+                S_IFLNK => {
+                    let mut target = Vec::new();
+                    guard.copy(entry_path, &mut data, &mut target)?;
+                    let target = String::from_utf8_lossy(&target);
+                    let target = Path::new(target.trim_end_matches('\0'));
+                    guard.symlink(dest_dir, entry_path, &absolute_path, target)?;
+                    create_parent(&absolute_path)?;
+                    remove_existing(&absolute_path)?;
+                    #[cfg(unix)]
+                    std::os::unix::fs::symlink(target, &absolute_path)?;
+                }
+                S_IFREG => {
+                    create_parent(&absolute_path)?;
+
+                    if variant == Variant::Newc && header.nlink > 1 && header.file_size == 0 {
+                        // Data arrives with a later link to the same inode.
+                        pending_links
+                            .entry(header.ino)
+                            .or_default()
+                            .push(absolute_path.clone());
+                    } else {
+                        remove_existing(&absolute_path)?;
+                        let mut out = File::create(&absolute_path)?;
+                        guard.copy(entry_path, &mut data, &mut out)?;
+                        set_mode(&absolute_path, header.mode)?;
+
+                        for link in pending_links.remove(&header.ino).unwrap_or_default() {
+                            remove_existing(&link)?;
+                            fs::hard_link(&absolute_path, &link)?;
+                        }
+                    }
+
+                    extracted.push(ExtractedFile {
+                        relative_path,
+                        absolute_path,
+                        is_executable: header.mode & 0o111 != 0,
+                    });
+                }
+                _ => return Err(ExtractGuard::special_file(entry_path)),
+            }
+        }
+
+        // Drain whatever the entry handler did not consume before padding.
+        io::copy(&mut data, &mut io::sink())?;
+        offset += header.file_size;
+        skip_padding(&mut reader, variant, &mut offset)?;
+    }
+
+    Ok(extracted)
+}
+
+fn read_header<R: Read>(
+    reader: &mut R,
+    variant: Variant,
+    offset: &mut u64,
+) -> Result<Header, ExtractError> {
+    match variant {
+        Variant::Odc => {
+            // dev ino mode uid gid nlink rdev mtime namesize filesize
+            let mut buf = [0u8; 70];
+            reader.read_exact(&mut buf)?;
+            *offset += 70;
+            let field = |start: usize, len: usize| parse_radix(&buf[start..start + len], 8);
+            Ok(Header {
+                ino: field(6, 6)?,
+                mode: field(12, 6)? as u32,
+                nlink: field(30, 6)?,
+                name_size: field(53, 6)?,
+                file_size: field(59, 11)?,
+            })
+        }
+        Variant::Newc => {
+            // ino mode uid gid nlink mtime filesize devmajor devminor
+            // rdevmajor rdevminor namesize check
+            let mut buf = [0u8; 104];
+            reader.read_exact(&mut buf)?;
+            *offset += 104;
+            let field = |idx: usize| parse_radix(&buf[idx * 8..idx * 8 + 8], 16);
+            Ok(Header {
+                ino: field(0)?,
+                mode: field(1)? as u32,
+                nlink: field(4)?,
+                file_size: field(6)?,
+                name_size: field(11)?,
+            })
+        }
+    }
+}
+
+fn parse_radix(field: &[u8], radix: u32) -> Result<u64, ExtractError> {
+    let s = std::str::from_utf8(field)
+        .map_err(|_| ExtractError::Archive("Non-ASCII cpio header".to_string()))?;
+    u64::from_str_radix(s, radix)
+        .map_err(|_| ExtractError::Archive(format!("Invalid cpio header field: {s:?}")))
+}
+
+/// newc aligns both the name and the data to 4-byte boundaries.
+fn skip_padding<R: Read>(
+    reader: &mut R,
+    variant: Variant,
+    offset: &mut u64,
+) -> Result<(), ExtractError> {
+    if variant == Variant::Newc {
+        let pad = (4 - (*offset % 4)) % 4;
+        if pad > 0 {
+            let mut buf = [0u8; 3];
+            reader.read_exact(&mut buf[..pad as usize])?;
+            *offset += pad;
+        }
+    }
+    Ok(())
+}
+
+fn create_parent(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
+fn remove_existing(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if meta.is_dir() => Ok(()),
+        Ok(_) => fs::remove_file(path),
+        Err(_) => Ok(()),
+    }
+}
+
+fn set_mode(path: &Path, mode: u32) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o7777))?;
+    }
+    #[cfg(not(unix))]
+    let _ = (path, mode);
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Build an `odc` cpio archive from `(name, mode, contents)` triples.
+    pub(crate) fn build_odc(entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut push = |name: &str, mode: u32, data: &[u8], ino: u32| {
+            out.extend_from_slice(
+                format!(
+                    "070707{:06o}{:06o}{:06o}{:06o}{:06o}{:06o}{:06o}{:011o}{:06o}{:011o}",
+                    0,
+                    ino,
+                    mode,
+                    0,
+                    0,
+                    1,
+                    0,
+                    0,
+                    name.len() + 1,
+                    data.len()
+                )
+                .as_bytes(),
+            );
+            out.extend_from_slice(name.as_bytes());
+            out.push(0);
+            out.extend_from_slice(data);
+        };
+        for (i, (name, mode, data)) in entries.iter().enumerate() {
+            push(name, *mode, data, i as u32 + 1);
+        }
+        push(TRAILER, 0, b"", 0);
+        out
+    }
+
+    fn build_newc(entries: &[(&str, u32, &[u8])]) -> Vec<u8> {
+        fn pad(out: &mut Vec<u8>) {
+            while out.len() % 4 != 0 {
+                out.push(0);
+            }
+        }
+        let mut out = Vec::new();
+        let all = entries
+            .iter()
+            .copied()
+            .chain(std::iter::once((TRAILER, 0, &b""[..])));
+        for (i, (name, mode, data)) in all.enumerate() {
+            out.extend_from_slice(b"070701");
+            for v in [
+                i + 1,
+                mode as usize,
+                0,
+                0,
+                1,
+                0,
+                data.len(),
+                0,
+                0,
+                0,
+                0,
+                name.len() + 1,
+                0,
+            ] {
+                out.extend_from_slice(format!("{v:08x}").as_bytes());
+            }
+            out.extend_from_slice(name.as_bytes());
+            out.push(0);
+            pad(&mut out);
+            out.extend_from_slice(data);
+            pad(&mut out);
+        }
+        out
+    }
+
+    #[test]
+    fn test_extract_odc() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = build_odc(&[
+            (".", S_IFDIR | 0o755, b""),
+            ("./usr/local/bin", S_IFDIR | 0o755, b""),
+            ("./usr/local/bin/tool", S_IFREG | 0o755, b"#!/bin/sh\n"),
+            ("./usr/local/bin/alias", S_IFLNK | 0o777, b"tool"),
+        ]);
+
+        let files =
+            extract_cpio(archive.as_slice(), dir.path(), &mut ExtractGuard::default()).unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].is_executable);
+        assert_eq!(
+            fs::read(dir.path().join("usr/local/bin/tool")).unwrap(),
+            b"#!/bin/sh\n"
+        );
+        assert_eq!(
+            fs::read_link(dir.path().join("usr/local/bin/alias")).unwrap(),
+            Path::new("tool")
+        );
+    }
+
+    #[test]
+    fn test_extract_newc() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = build_newc(&[
+            ("bin", S_IFDIR | 0o755, b""),
+            ("bin/a", S_IFREG | 0o644, b"abc"),
+            ("bin/b", S_IFREG | 0o755, b"hello"),
+        ]);
+
+        let files =
+            extract_cpio(archive.as_slice(), dir.path(), &mut ExtractGuard::default()).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(fs::read(dir.path().join("bin/a")).unwrap(), b"abc");
+        assert_eq!(fs::read(dir.path().join("bin/b")).unwrap(), b"hello");
+    }
+
+    #[test]
+    fn test_rejects_oversized_name() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut archive = build_odc(&[("a", S_IFREG | 0o644, b"x")]);
+        // namesize is the 6 octal digits at offset 59
+        archive[59..65].copy_from_slice(b"777777");
+        assert!(matches!(
+            extract_cpio(archive.as_slice(), dir.path(), &mut ExtractGuard::default()),
+            Err(ExtractError::Archive(_))
+        ));
+    }
+
+    #[test]
+    fn test_rejects_traversal() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = build_odc(&[("../evil", S_IFREG | 0o644, b"x")]);
+        assert!(
+            extract_cpio(archive.as_slice(), dir.path(), &mut ExtractGuard::default()).is_err()
+        );
+    }
+}
@@ -0,0 +1,807 @@
+//! Read-only APFS container walker.
+//!
+//! Locates the newest container superblock, resolves the first volume through
+//! the object maps, and copies its filesystem tree out. Encrypted volumes and
+//! compressed (decmpfs) files are rejected so the caller can fall back to
+//! mounting the image. Every entry goes through the caller's
+//! [`ExtractGuard`].
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use super::ReadAt;
+use crate::io::extract::ExtractGuard;
+
+const NX_MAGIC: &[u8; 4] = b"NXSB";
+const APFS_MAGIC: &[u8; 4] = b"APSB";
+const OBJECT_TYPE_NX_SUPERBLOCK: u32 = 1;
+
+const BTNODE_ROOT: u16 = 0x1;
+const BTNODE_FIXED_KV_SIZE: u16 = 0x4;
+const BTREE_INFO_SIZE: usize = 40;
+const BTNODE_DATA: usize = 56;
+const MAX_TREE_DEPTH: u16 = 16;
+
+const APFS_FS_UNENCRYPTED: u64 = 0x1;
+const APFS_INCOMPAT_CASE_INSENSITIVE: u64 = 0x1;
+const APFS_INCOMPAT_NORMALIZATION_INSENSITIVE: u64 = 0x8;
+
+const ROOT_DIR_INO: u64 = 2;
+const OBJ_ID_MASK: u64 = 0x0fff_ffff_ffff_ffff;
+const OBJ_TYPE_SHIFT: u32 = 60;
+
+const APFS_TYPE_INODE: u8 = 3;
+const APFS_TYPE_XATTR: u8 = 4;
+const APFS_TYPE_FILE_EXTENT: u8 = 8;
+const APFS_TYPE_DIR_REC: u8 = 9;
+
+const INO_EXT_TYPE_DSTREAM: u8 = 8;
+const XATTR_DATA_EMBEDDED: u16 = 0x2;
+const SYMLINK_XATTR: &[u8] = b"com.apple.fs.symlink";
+const DECMPFS_XATTR: &[u8] = b"com.apple.decmpfs";
+const UF_COMPRESSED: u32 = 0x20;
+
+const DT_DIR: u16 = 4;
+const DT_REG: u16 = 8;
+const DT_LNK: u16 = 10;
+
+const SKIPPED_ROOT_ENTRIES: &[&str] = &[".fseventsd", ".Trashes", ".Spotlight-V100"];
+
+/// Whether the partition starts with an APFS container superblock.
+pub fn probe<R: ReadAt>(source: &R) -> bool {
+    let mut magic = [0u8; 4];
+    source.read_at(32, &mut magic).is_ok() && &magic == NX_MAGIC
+}
+
+#[derive(Debug, Default)]
+struct Inode {
+    mode: u16,
+    dstream: u64,
+    size: u64,
+    compressed: bool,
+}
+
+#[derive(Debug)]
+struct DirRecord {
+    parent: u64,
+    name: String,
+    file_id: u64,
+    kind: u16,
+}
+
+#[derive(Debug, Default)]
+struct FsTree {
+    inodes: HashMap<u64, Inode>,
+    dirents: Vec<DirRecord>,
+    extents: HashMap<u64, Vec<(u64, u64, u64)>>,
+    symlinks: HashMap<u64, String>,
+}
+
+type RecordVisitor<'a> = dyn FnMut(&[u8], &[u8]) -> Result<()> + 'a;
+
+struct Container<'a, R: ReadAt> {
+    source: &'a R,
+    block_size: u64,
+}
+
+impl<R: ReadAt> Container<'_, R> {
+    fn read_block(&self, paddr: u64) -> Result<Vec<u8>> {
+        let mut buf = vec![0u8; self.block_size as usize];
+        self.source
+            .read_at(paddr * self.block_size, &mut buf)
+            .with_context(|| format!("Failed to read APFS block {paddr}"))?;
+        Ok(buf)
+    }
+
+    /// Load an object map into an `oid -> paddr` table, keeping the newest
+    /// mapping visible at `max_xid`.
+    fn load_omap(&self, omap_paddr: u64, max_xid: u64) -> Result<HashMap<u64, u64>> {
+        let omap = self.read_block(omap_paddr)?;
+        let tree = le_u64(&omap, 48);
+        let mut newest: HashMap<u64, (u64, u64)> = HashMap::new();
+        self.walk(tree, None, &mut |key, val| {
+            let (oid, xid) = (le_u64(key, 0), le_u64(key, 8));
+            let paddr = le_u64(val, 8);
+            if xid <= max_xid && newest.get(&oid).is_none_or(|(x, _)| *x < xid) {
+                newest.insert(oid, (xid, paddr));
+            }
+            Ok(())
+        })?;
+        Ok(newest.into_iter().map(|(oid, (_, p))| (oid, p)).collect())
+    }
+
+    /// Visit every leaf record of a B-tree. Child pointers are translated
+    /// through `omap` for virtual trees and used directly otherwise.
+    fn walk(
+        &self,
+        oid: u64,
+        omap: Option<&HashMap<u64, u64>>,
+        visit: &mut RecordVisitor<'_>,
+    ) -> Result<()> {
+        self.walk_node(oid, omap, MAX_TREE_DEPTH, visit)
+    }
+
+    fn walk_node(
+        &self,
+        oid: u64,
+        omap: Option<&HashMap<u64, u64>>,
+        depth_left: u16,
+        visit: &mut RecordVisitor<'_>,
+    ) -> Result<()> {
+        if depth_left == 0 {
+            bail!("APFS B-tree is too deep");
+        }
+        let paddr = match omap {
+            Some(map) => *map
+                .get(&oid)
+                .with_context(|| format!("APFS object {oid} missing from object map"))?,
+            None => oid,
+        };
+        let node = self.read_block(paddr)?;
+        let level = u16::from_le_bytes([node[34], node[35]]);
+
+        for (key, val) in node_records(&node, level)? {
+            if level == 0 {
+                visit(key, val)?;
+            } else {
+                self.walk_node(le_u64(val, 0), omap, depth_left - 1, visit)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Split a B-tree node into `(key, value)` slices using its table of contents.
+fn node_records(node: &[u8], level: u16) -> Result<Vec<(&[u8], &[u8])>> {
+    let flags = u16::from_le_bytes([node[32], node[33]]);
+    let count = le_u32(node, 36) as usize;
+    let toc_off = usize::from(u16::from_le_bytes([node[40], node[41]]));
+    let toc_len = usize::from(u16::from_le_bytes([node[42], node[43]]));
+
+    let toc = BTNODE_DATA + toc_off;
+    let key_start = toc + toc_len;
+    let val_end = if flags & BTNODE_ROOT != 0 {
+        node.len() - BTREE_INFO_SIZE
+    } else {
+        node.len()
+    };
+    let fixed = flags & BTNODE_FIXED_KV_SIZE != 0;
+
+    let bad = || anyhow::anyhow!("Malformed APFS B-tree node");
+    let mut out = Vec::with_capacity(count);
+    for i in 0..count {
+        let (k_off, k_len, v_off, v_len) = if fixed {
+            // Only object maps use fixed-size entries: 16-byte keys and
+            // 16-byte values (8-byte child pointers in index nodes).
+            let e = toc + i * 4;
+            let v_len = if level == 0 { 16 } else { 8 };
+            (le_u16(node, e), 16, le_u16(node, e + 2), v_len)
+        } else {
+            let e = toc + i * 8;
+            (
+                le_u16(node, e),
+                le_u16(node, e + 2),
+                le_u16(node, e + 4),
+                le_u16(node, e + 6),
+            )
+        };
+        let key = node
+            .get(key_start + k_off..key_start + k_off + k_len)
+            .ok_or_else(bad)?;
+        let v_start = val_end.checked_sub(v_off).ok_or_else(bad)?;
+        let val = node.get(v_start..v_start + v_len).ok_or_else(bad)?;
+        out.push((key, val));
+    }
+    Ok(out)
+}
+
+/// Copy the first volume of the container into `dest`.
+///
+/// # Errors
+///
+/// Returns an error if the container is malformed, the volume is encrypted,
+/// a file is compressed, an entry is rejected by `guard`, or `dest` can't be
+/// written.
+pub fn extract<R: ReadAt>(source: &R, dest: &Path, guard: &mut ExtractGuard) -> Result<()> {
+    let mut head = [0u8; 4096];
+    source.read_at(0, &mut head)?;
+    if &head[32..36] != NX_MAGIC {
+        bail!("Not an APFS container");
+    }
+    let block_size = u64::from(le_u32(&head, 36));
+    if !(4096..=65536).contains(&block_size) {
+        bail!("Unsupported APFS block size {block_size}");
+    }
+    let container = Container { source, block_size };
+    let nx = latest_superblock(&container)?;
+    let xid = le_u64(&nx, 16);
+
+    let container_omap = container.load_omap(le_u64(&nx, 160), xid)?;
+    let volume_oid = le_u64(&nx, 184);
+    if volume_oid == 0 {
+        bail!("APFS container has no volumes");
+    }
+    let volume_paddr = *container_omap
+        .get(&volume_oid)
+        .context("APFS volume superblock missing from object map")?;
+    let apsb = container.read_block(volume_paddr)?;
+    if &apsb[32..36] != APFS_MAGIC {
+        bail!("Invalid APFS volume superblock");
+    }
+    if le_u64(&apsb, 264) & APFS_FS_UNENCRYPTED == 0 {
+        bail!("APFS volume is encrypted");
+    }
+    let incompat = le_u64(&apsb, 56);
+    let hashed_names =
+        incompat & (APFS_INCOMPAT_CASE_INSENSITIVE | APFS_INCOMPAT_NORMALIZATION_INSENSITIVE) != 0;
+
+    let volume_omap = container.load_omap(le_u64(&apsb, 128), xid)?;
+    let mut tree = FsTree::default();
+    container.walk(le_u64(&apsb, 136), Some(&volume_omap), &mut |key, val| {
+        tree.add(key, val, hashed_names)
+    })?;
+
+    write_tree(&container, &tree, dest, guard)
+}
+
+/// Pick the newest valid superblock from the checkpoint descriptor area,
+/// falling back to block zero.
+fn latest_superblock<R: ReadAt>(container: &Container<'_, R>) -> Result<Vec<u8>> {
+    let block0 = container.read_block(0)?;
+    let desc_blocks = le_u32(&block0, 104);
+    let desc_base = le_u64(&block0, 112);
+
+    let mut best = block0;
+    // A set high bit means the descriptor area is itself a B-tree; block zero
+    // is good enough for read-only images in that case.
+    if desc_blocks & 0x8000_0000 == 0 {
+        for i in 0..u64::from(desc_blocks) {
+            let Ok(block) = container.read_block(desc_base + i) else {
+                continue;
+            };
+            if &block[32..36] == NX_MAGIC
+                && le_u32(&block, 24) & 0xffff == OBJECT_TYPE_NX_SUPERBLOCK
+                && fletcher64(&block) == le_u64(&block, 0)
+                && le_u64(&block, 16) > le_u64(&best, 16)
+            {
+                best = block;
+            }
+        }
+    }
+    Ok(best)
+}
+
+impl FsTree {
+    fn add(&mut self, key: &[u8], val: &[u8], hashed_names: bool) -> Result<()> {
+        let header = le_u64(key, 0);
+        let oid = header & OBJ_ID_MASK;
+        let kind = (header >> OBJ_TYPE_SHIFT) as u8;
+
+        match kind {
+            APFS_TYPE_INODE => {
+                let mut inode = Inode {
+                    mode: le_u16(val, 80) as u16,
+                    dstream: le_u64(val, 8),
+                    compressed: le_u32(val, 68) & UF_COMPRESSED != 0,
+                    ..Inode::default()
+                };
+                if let Some(dstream) =
+                    xfield(val.get(92..).unwrap_or_default(), INO_EXT_TYPE_DSTREAM)
+                {
+                    inode.size = le_u64(dstream, 0);
+                }
+                self.inodes.insert(oid, inode);
+            }
+            APFS_TYPE_DIR_REC => {
+                let (name_len, name_at) = if hashed_names {
+                    ((le_u32(key, 8) & 0x3ff) as usize, 12)
+                } else {
+                    (le_u16(key, 8), 10)
+                };
+                let raw = key
+                    .get(name_at..name_at + name_len)
+                    .context("Truncated APFS directory record")?;
+                let name = String::from_utf8_lossy(raw.strip_suffix(&[0]).unwrap_or(raw));
+                self.dirents.push(DirRecord {
+                    parent: oid,
+                    name: name.into_owned(),
+                    file_id: le_u64(val, 0),
+                    kind: le_u16(val, 16) as u16 & 0xf,
+                });
+            }
+            APFS_TYPE_FILE_EXTENT => {
+                let logical = le_u64(key, 8);
+                let length = le_u64(val, 0) & 0x00ff_ffff_ffff_ffff;
+                let physical = le_u64(val, 8);
+                self.extents
+                    .entry(oid)
+                    .or_default()
+                    .push((logical, length, physical));
+            }
+            APFS_TYPE_XATTR => {
+                let name_len = le_u16(key, 8);
+                let raw = key
+                    .get(10..10 + name_len)
+                    .context("Truncated APFS xattr record")?;
+                let name = raw.strip_suffix(&[0]).unwrap_or(raw);
+                if name == DECMPFS_XATTR {
+                    self.inodes.entry(oid).or_default().compressed = true;
+                } else if name == SYMLINK_XATTR {
+                    if le_u16(val, 0) as u16 & XATTR_DATA_EMBEDDED == 0 {
+                        bail!("APFS symlink target stored out of line");
+                    }
+                    let len = le_u16(val, 2);
+                    let data = val.get(4..4 + len).context("Truncated APFS symlink")?;
+                    let target = data.strip_suffix(&[0]).unwrap_or(data);
+                    self.symlinks
+                        .insert(oid, String::from_utf8_lossy(target).into_owned());
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+/// Find an extended field of the given type in an inode's `xf_blob_t`.
+fn xfield(blob: &[u8], wanted: u8) -> Option<&[u8]> {
+    if blob.len() < 4 {
+        return None;
+    }
+    let count = le_u16(blob, 0);
+    let mut data = 4 + count * 4;
+    for i in 0..count {
+        let entry = 4 + i * 4;
+        let kind = *blob.get(entry)?;
+        let size = le_u16(blob, entry + 2);
+        if kind == wanted {
+            return blob.get(data..data + size);
+        }
+        data += size.next_multiple_of(8);
+    }
+    None
+}
+
+fn write_tree<R: ReadAt>(
+    container: &Container<'_, R>,
+    tree: &FsTree,
+    dest: &Path,
+    guard: &mut ExtractGuard,
+) -> Result<()> {
+    let mut children: HashMap<u64, Vec<&DirRecord>> = HashMap::new();
+    for d in &tree.dirents {
+        children.entry(d.parent).or_default().push(d);
+    }
+
+    fs::create_dir_all(dest)?;
+    // First path each file was written to, relative to `dest`
+    let mut written: HashMap<u64, PathBuf> = HashMap::new();
+    // APFS has no directory hard links, so a directory seen twice is a loop
+    let mut visited = HashSet::from([ROOT_DIR_INO]);
+    let mut stack = vec![(ROOT_DIR_INO, PathBuf::new())];
+
+    while let Some((dir, path)) = stack.pop() {
+        for entry in children.get(&dir).into_iter().flatten() {
+            if path.as_os_str().is_empty() && SKIPPED_ROOT_ENTRIES.contains(&entry.name.as_str()) {
+                continue;
+            }
+            super::check_name(&entry.name)?;
+            let relative = path.join(&entry.name);
+            let target = guard.entry(dest, &relative)?;
+
+            match entry.kind {
+                DT_DIR => {
+                    if !visited.insert(entry.file_id) {
+                        bail!("APFS directory '{}' is linked twice", relative.display());
+                    }
+                    fs::create_dir_all(&target)?;
+                    stack.push((entry.file_id, relative));
+                }
+                DT_LNK => {
+                    let link = tree
+                        .symlinks
+                        .get(&entry.file_id)
+                        .with_context(|| format!("APFS symlink '{}' has no target", entry.name))?;
+                    super::create_symlink(guard, dest, &relative, &target, link)?;
+                }
+                DT_REG => {
+                    let inode = tree
+                        .inodes
+                        .get(&entry.file_id)
+                        .with_context(|| format!("APFS file '{}' has no inode", entry.name))?;
+                    if let Some(first) = written.get(&entry.file_id) {
+                        let first = guard.hardlink(dest, &relative, first)?;
+                        if fs::symlink_metadata(&target).is_ok_and(|m| !m.is_dir()) {
+                            fs::remove_file(&target)?;
+                        }
+                        if fs::hard_link(&first, &target).is_err() {
+                            guard.reserve(&relative, inode.size)?;
+                            io::copy(&mut File::open(&first)?, &mut super::create_file(&target)?)?;
+                        }
+                        continue;
+                    }
+                    if inode.compressed {
+                        bail!("APFS file '{}' is compressed (decmpfs)", entry.name);
+                    }
+                    write_file(container, tree, inode, &relative, &target, guard)?;
+                    written.insert(entry.file_id, relative);
+                }
+                _ => tracing::debug!("Skipping special APFS entry {}", target.display()),
+            }
+        }
+    }
+    Ok(())
+}
+
+fn write_file<R: ReadAt>(
+    container: &Container<'_, R>,
+    tree: &FsTree,
+    inode: &Inode,
+    entry: &Path,
+    target: &Path,
+    guard: &mut ExtractGuard,
+) -> Result<()> {
+    // Extents are clamped to the inode size, holes included
+    guard.reserve(entry, inode.size)?;
+    let mut out = super::create_file(target)?;
+    let mut extents = tree
+        .extents
+        .get(&inode.dstream)
+        .cloned()
+        .unwrap_or_default();
+    extents.sort_by_key(|(logical, _, _)| *logical);
+
+    let mut buf = vec![0u8; 1 << 20];
+    for (logical, length, physical) in extents {
+        // Physical block zero marks a sparse hole.
+        if physical == 0 || logical >= inode.size {
+            continue;
+        }
+        out.seek(SeekFrom::Start(logical))?;
+        let mut remaining = length.min(inode.size - logical);
+        let mut pos = physical
+            .checked_mul(container.block_size)
+            .context("APFS extent is out of range")?;
+        while remaining > 0 {
+            let n = remaining.min(buf.len() as u64) as usize;
+            container.source.read_at(pos, &mut buf[..n])?;
+            out.write_all(&buf[..n])?;
+            pos += n as u64;
+            remaining -= n as u64;
+        }
+    }
+    out.set_len(inode.size)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let perm = if inode.mode == 0 {
+            0o644
+        } else {
+            inode.mode & 0o7777
+        };
+        fs::set_permissions(target, fs::Permissions::from_mode(u32::from(perm)))?;
+    }
+    Ok(())
+}
+
+/// Fletcher-64 checksum over an object, as stored in its first eight bytes.
+fn fletcher64(block: &[u8]) -> u64 {
+    const MOD: u64 = 0xffff_ffff;
+    let (mut s1, mut s2) = (0u64, 0u64);
+    for word in block[8..].chunks_exact(4) {
+        s1 = (s1 + u64::from(u32::from_le_bytes([word[0], word[1], word[2], word[3]]))) % MOD;
+        s2 = (s2 + s1) % MOD;
+    }
+    let c1 = MOD - ((s1 + s2) % MOD);
+    let c2 = MOD - ((s1 + c1) % MOD);
+    (c2 << 32) | c1
+}
+
+fn le_u16(buf: &[u8], off: usize) -> usize {
+    buf.get(off..off + 2)
+        .map_or(0, |b| usize::from(u16::from_le_bytes([b[0], b[1]])))
+}
+
+fn le_u32(buf: &[u8], off: usize) -> u32 {
+    buf.get(off..off + 4)
+        .map_or(0, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+fn le_u64(buf: &[u8], off: usize) -> u64 {
+    buf.get(off..off + 8)
+        .and_then(|b| b.try_into().ok())
+        .map_or(0, u64::from_le_bytes)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::io::extract::{ExtractError, ExtractPolicy, Violation};
+
+    const BS: usize = 4096;
+
+    fn put(buf: &mut [u8], off: usize, bytes: &[u8]) {
+        buf[off..off + bytes.len()].copy_from_slice(bytes);
+    }
+
+    /// Build a single-node root leaf with fixed 16/16 (omap) or variable entries.
+    fn leaf(records: &[(Vec<u8>, Vec<u8>)], fixed: bool) -> Vec<u8> {
+        let mut node = vec![0u8; BS];
+        let flags = BTNODE_ROOT | 0x2 | if fixed { BTNODE_FIXED_KV_SIZE } else { 0 };
+        put(&mut node, 32, &flags.to_le_bytes());
+        put(&mut node, 36, &(records.len() as u32).to_le_bytes());
+        let toc_len = records.len() * if fixed { 4 } else { 8 };
+        put(&mut node, 42, &(toc_len as u16).to_le_bytes());
+
+        let key_start = BTNODE_DATA + toc_len;
+        let val_end = BS - BTREE_INFO_SIZE;
+        let (mut k_off, mut v_off) = (0usize, 0usize);
+        for (i, (k, v)) in records.iter().enumerate() {
+            v_off += v.len();
+            put(&mut node, key_start + k_off, k);
+            put(&mut node, val_end - v_off, v);
+            if fixed {
+                put(
+                    &mut node,
+                    BTNODE_DATA + i * 4,
+                    &(k_off as u16).to_le_bytes(),
+                );
+                put(
+                    &mut node,
+                    BTNODE_DATA + i * 4 + 2,
+                    &(v_off as u16).to_le_bytes(),
+                );
+            } else {
+                let e = BTNODE_DATA + i * 8;
+                put(&mut node, e, &(k_off as u16).to_le_bytes());
+                put(&mut node, e + 2, &(k.len() as u16).to_le_bytes());
+                put(&mut node, e + 4, &(v_off as u16).to_le_bytes());
+                put(&mut node, e + 6, &(v.len() as u16).to_le_bytes());
+            }
+            k_off += k.len();
+        }
+        node
+    }
+
+    fn omap_entry(oid: u64, paddr: u64) -> (Vec<u8>, Vec<u8>) {
+        let mut k = oid.to_le_bytes().to_vec();
+        k.extend_from_slice(&1u64.to_le_bytes());
+        let mut v = vec![0u8; 8];
+        v.extend_from_slice(&paddr.to_le_bytes());
+        (k, v)
+    }
+
+    fn j_key(oid: u64, kind: u8) -> Vec<u8> {
+        (oid | (u64::from(kind) << OBJ_TYPE_SHIFT))
+            .to_le_bytes()
+            .to_vec()
+    }
+
+    fn inode(oid: u64, mode: u16, size: u64) -> (Vec<u8>, Vec<u8>) {
+        let mut v = vec![0u8; 92];
+        put(&mut v, 8, &oid.to_le_bytes());
+        put(&mut v, 80, &mode.to_le_bytes());
+        // xf_blob with a single dstream field.
+        v.extend_from_slice(&1u16.to_le_bytes());
+        v.extend_from_slice(&40u16.to_le_bytes());
+        v.extend_from_slice(&[INO_EXT_TYPE_DSTREAM, 0]);
+        v.extend_from_slice(&40u16.to_le_bytes());
+        let mut dstream = [0u8; 40];
+        put(&mut dstream, 0, &size.to_le_bytes());
+        v.extend_from_slice(&dstream);
+        (j_key(oid, APFS_TYPE_INODE), v)
+    }
+
+    fn drec(parent: u64, name: &str, file_id: u64, kind: u16) -> (Vec<u8>, Vec<u8>) {
+        let mut k = j_key(parent, APFS_TYPE_DIR_REC);
+        k.extend_from_slice(&((name.len() + 1) as u32).to_le_bytes());
+        k.extend_from_slice(name.as_bytes());
+        k.push(0);
+        let mut v = vec![0u8; 18];
+        put(&mut v, 0, &file_id.to_le_bytes());
+        put(&mut v, 16, &kind.to_le_bytes());
+        (k, v)
+    }
+
+    fn extent(oid: u64, length: u64, block: u64) -> (Vec<u8>, Vec<u8>) {
+        let mut k = j_key(oid, APFS_TYPE_FILE_EXTENT);
+        k.extend_from_slice(&0u64.to_le_bytes());
+        let mut v = length.to_le_bytes().to_vec();
+        v.extend_from_slice(&block.to_le_bytes());
+        v.extend_from_slice(&0u64.to_le_bytes());
+        (k, v)
+    }
+
+    fn symlink(oid: u64, target: &str) -> (Vec<u8>, Vec<u8>) {
+        let mut k = j_key(oid, APFS_TYPE_XATTR);
+        k.extend_from_slice(&((SYMLINK_XATTR.len() + 1) as u16).to_le_bytes());
+        k.extend_from_slice(SYMLINK_XATTR);
+        k.push(0);
+        let mut v = XATTR_DATA_EMBEDDED.to_le_bytes().to_vec();
+        v.extend_from_slice(&((target.len() + 1) as u16).to_le_bytes());
+        v.extend_from_slice(target.as_bytes());
+        v.push(0);
+        (k, v)
+    }
+
+    /// A container with one volume holding `Demo.app/Contents/demo` and a
+    /// `Link -> Demo.app` symlink.
+    pub(crate) fn build_apfs(payload: &[u8]) -> Vec<u8> {
+        build_volume(
+            &[
+                drec(ROOT_DIR_INO, "Demo.app", 16, DT_DIR),
+                drec(ROOT_DIR_INO, "Link", 19, DT_LNK),
+                drec(ROOT_DIR_INO, ".fseventsd", 20, DT_DIR),
+                drec(16, "Contents", 17, DT_DIR),
+                drec(17, "demo", 18, DT_REG),
+                inode(18, 0o100_755, payload.len() as u64),
+                extent(18, BS as u64, 7),
+                symlink(19, "Demo.app"),
+            ],
+            payload,
+        )
+    }
+
+    /// A container whose filesystem tree is the single leaf `records`, with
+    /// `payload` in block 7.
+    fn build_volume(records: &[(Vec<u8>, Vec<u8>)], payload: &[u8]) -> Vec<u8> {
+        const VOL_OID: u64 = 1026;
+        const FS_ROOT_OID: u64 = 1027;
+        let mut image = vec![0u8; BS * 8];
+
+        // Block 0: container superblock.
+        let nx = &mut image[..BS];
+        put(nx, 16, &1u64.to_le_bytes());
+        put(nx, 24, &OBJECT_TYPE_NX_SUPERBLOCK.to_le_bytes());
+        put(nx, 32, NX_MAGIC);
+        put(nx, 36, &(BS as u32).to_le_bytes());
+        put(nx, 160, &1u64.to_le_bytes());
+        put(nx, 184, &VOL_OID.to_le_bytes());
+
+        // Block 1/2: container omap and its tree.
+        put(&mut image[BS..2 * BS], 48, &2u64.to_le_bytes());
+        image[2 * BS..3 * BS].copy_from_slice(&leaf(&[omap_entry(VOL_OID, 3)], true));
+
+        // Block 3: volume superblock.
+        let apsb = &mut image[3 * BS..4 * BS];
+        put(apsb, 32, APFS_MAGIC);
+        put(
+            apsb,
+            56,
+            &APFS_INCOMPAT_NORMALIZATION_INSENSITIVE.to_le_bytes(),
+        );
+        put(apsb, 128, &4u64.to_le_bytes());
+        put(apsb, 136, &FS_ROOT_OID.to_le_bytes());
+        put(apsb, 264, &APFS_FS_UNENCRYPTED.to_le_bytes());
+
+        // Block 4/5: volume omap and its tree.
+        put(&mut image[4 * BS..5 * BS], 48, &5u64.to_le_bytes());
+        image[5 * BS..6 * BS].copy_from_slice(&leaf(&[omap_entry(FS_ROOT_OID, 6)], true));
+
+        // Block 6: filesystem tree. Block 7: file data.
+        let fs = leaf(records, false);
+        image[6 * BS..7 * BS].copy_from_slice(&fs);
+        put(&mut image, 7 * BS, payload);
+        image
+    }
+
+    #[test]
+    fn test_extract_apfs() {
+        let image = build_apfs(b"#!/bin/sh\necho apfs\n");
+        assert!(probe(&image.as_slice()));
+
+        let dir = tempfile::tempdir().unwrap();
+        extract(&image.as_slice(), dir.path(), &mut ExtractGuard::default()).unwrap();
+
+        let exe = dir.path().join("Demo.app/Contents/demo");
+        assert_eq!(fs::read(&exe).unwrap(), b"#!/bin/sh\necho apfs\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                fs::metadata(&exe).unwrap().permissions().mode() & 0o777,
+                0o755
+            );
+        }
+        assert_eq!(
+            fs::read_link(dir.path().join("Link")).unwrap(),
+            Path::new("Demo.app")
+        );
+        assert!(!dir.path().join(".fseventsd").exists());
+    }
+
+    #[test]
+    fn test_rejects_encrypted_volume() {
+        let mut image = build_apfs(b"x");
+        put(&mut image, 3 * BS + 264, &0u64.to_le_bytes());
+        let dir = tempfile::tempdir().unwrap();
+        let err = extract(&image.as_slice(), dir.path(), &mut ExtractGuard::default()).unwrap_err();
+        assert!(err.to_string().contains("encrypted"));
+    }
+
+    #[test]
+    fn test_rejects_malicious_volumes() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let dest = sandbox.path().join("dest");
+        let run = |image: Vec<u8>, guard: &mut ExtractGuard| {
+            let _ = fs::remove_dir_all(&dest);
+            extract(&image.as_slice(), &dest, guard)
+        };
+        let violation = |result: Result<()>| match result.unwrap_err().downcast::<ExtractError>() {
+            Ok(ExtractError::Unsafe { violation, .. }) => violation,
+            other => panic!("expected a policy violation, got {other:?}"),
+        };
+
+        // Symlink out of the destination
+        let image = build_volume(
+            &[
+                drec(ROOT_DIR_INO, "Link", 19, DT_LNK),
+                symlink(19, "../../etc"),
+            ],
+            b"",
+        );
+        assert!(matches!(
+            violation(run(image, &mut ExtractGuard::default())),
+            Violation::SymlinkEscape(_)
+        ));
+        assert!(fs::symlink_metadata(dest.join("Link")).is_err());
+
+        // Names that aren't a single path component
+        for name in ["../x", "a/b", "evil\0name", ".."] {
+            let image = build_volume(
+                &[
+                    drec(ROOT_DIR_INO, name, 18, DT_REG),
+                    inode(18, 0o100_644, 1),
+                    extent(18, BS as u64, 7),
+                ],
+                b"x",
+            );
+            let err = run(image, &mut ExtractGuard::default()).unwrap_err();
+            assert!(
+                err.to_string().contains("Invalid file name"),
+                "{name}: {err}"
+            );
+        }
+        assert!(!sandbox.path().join("x").exists());
+
+        // A directory that contains itself
+        let image = build_volume(
+            &[
+                drec(ROOT_DIR_INO, "d", 16, DT_DIR),
+                drec(16, "loop", 16, DT_DIR),
+            ],
+            b"",
+        );
+        let err = run(image, &mut ExtractGuard::default()).unwrap_err();
+        assert!(err.to_string().contains("linked twice"), "{err}");
+
+        // Output past the size limit
+        let policy = ExtractPolicy {
+            max_total_size: 4,
+            ..ExtractPolicy::default()
+        };
+        assert_eq!(
+            violation(run(
+                build_apfs(b"12345"),
+                &mut ExtractGuard::new(policy, None)
+            )),
+            Violation::TooLarge(4)
+        );
+    }
+
+    #[test]
+    fn test_fletcher64() {
+        let mut block = vec![0u8; BS];
+        put(&mut block, 32, NX_MAGIC);
+        let sum = fletcher64(&block);
+        put(&mut block, 0, &sum.to_le_bytes());
+        assert_eq!(fletcher64(&block), le_u64(&block, 0));
+        block[100] ^= 1;
+        assert_ne!(fletcher64(&block), le_u64(&block, 0));
+    }
+}
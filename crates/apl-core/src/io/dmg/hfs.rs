@@ -0,0 +1,669 @@
+//! Read-only HFS+/HFSX volume walker.
+//!
+//! Reads the catalog B-tree leaf chain and copies every file, directory and
+//! symlink out of the volume. Hard links are resolved through the private
+//! metadata directory. Compressed (decmpfs) files are rejected so the
+//! caller can fall back to mounting the image. Every entry goes through the
+//! caller's [`ExtractGuard`].
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result, bail};
+
+use super::ReadAt;
+use super::udif::{be_u32, be_u64};
+use crate::io::extract::ExtractGuard;
+
+/// Offset of the volume header from the start of the partition.
+pub const HEADER_OFFSET: u64 = 1024;
+
+const ROOT_FOLDER_ID: u32 = 2;
+const EXTENTS_FILE_ID: u32 = 3;
+const CATALOG_FILE_ID: u32 = 4;
+
+const RECORD_FOLDER: u16 = 1;
+const RECORD_FILE: u16 = 2;
+
+const S_IFMT: u16 = 0o170_000;
+const S_IFLNK: u16 = 0o120_000;
+const UF_COMPRESSED: u8 = 0x20;
+/// Longest symlink target read from a volume (`PATH_MAX` on macOS).
+const MAX_SYMLINK_LEN: u64 = 1024;
+
+const HARDLINK_TYPE: &[u8; 4] = b"hlnk";
+const HARDLINK_CREATOR: &[u8; 4] = b"hfs+";
+const FILE_PRIVATE_DIR: &str = "\0\0\0\0HFS+ Private Data";
+const SKIPPED_ROOT_ENTRIES: &[&str] = &[
+    FILE_PRIVATE_DIR,
+    ".HFS+ Private Directory Data\r",
+    ".journal",
+    ".journal_info_block",
+    ".Trashes",
+    ".fseventsd",
+    ".Spotlight-V100",
+];
+
+/// Whether the partition starts with an HFS+ (`H+`) or HFSX (`HX`) header.
+pub fn probe<R: ReadAt>(source: &R) -> bool {
+    let mut sig = [0u8; 2];
+    source.read_at(HEADER_OFFSET, &mut sig).is_ok() && matches!(&sig, b"H+" | b"HX")
+}
+
+#[derive(Debug, Clone, Default)]
+struct Fork {
+    size: u64,
+    total_blocks: u64,
+    extents: Vec<(u64, u64)>,
+}
+
+impl Fork {
+    fn parse(buf: &[u8]) -> Self {
+        let extents = (0..8)
+            .map(|i| {
+                let off = 16 + i * 8;
+                (u64::from(be_u32(buf, off)), u64::from(be_u32(buf, off + 4)))
+            })
+            .filter(|(_, count)| *count > 0)
+            .collect();
+        Self {
+            size: be_u64(buf, 0),
+            total_blocks: u64::from(be_u32(buf, 12)),
+            extents,
+        }
+    }
+
+    fn mapped_blocks(&self) -> u64 {
+        self.extents.iter().map(|(_, n)| n).sum()
+    }
+}
+
+#[derive(Debug)]
+enum Kind {
+    Folder,
+    File { fork: Fork, mode: u16 },
+    Symlink { fork: Fork },
+    HardLink { inode: u32 },
+}
+
+#[derive(Debug)]
+struct Record {
+    parent: u32,
+    name: String,
+    id: u32,
+    kind: Kind,
+}
+
+struct Volume<'a, R: ReadAt> {
+    source: &'a R,
+    block_size: u64,
+    extents_tree: Vec<u8>,
+}
+
+impl<R: ReadAt> Volume<'_, R> {
+    fn read_fork(&self, fork: &Fork, file_id: u32) -> Result<Vec<u8>> {
+        let capacity = fork.size.min(self.source.size());
+        let mut out = Vec::with_capacity(usize::try_from(capacity).unwrap_or(0));
+        self.copy_fork(fork, file_id, &mut out)?;
+        Ok(out)
+    }
+
+    /// Stream a fork's bytes into `out`, consulting the extents overflow
+    /// tree when the inline extents don't cover the whole fork.
+    fn copy_fork<W: Write>(&self, fork: &Fork, file_id: u32, out: &mut W) -> Result<()> {
+        let mut extents = fork.extents.clone();
+        if fork.mapped_blocks() < fork.total_blocks {
+            if file_id == EXTENTS_FILE_ID {
+                bail!("HFS+ extents file is fragmented");
+            }
+            extents.extend(overflow_extents(&self.extents_tree, file_id)?);
+        }
+
+        let mut remaining = fork.size;
+        let mut buf = vec![0u8; 1 << 20];
+        for (start, count) in extents {
+            let mut pos = start * self.block_size;
+            let mut extent_left = count * self.block_size;
+            while remaining > 0 && extent_left > 0 {
+                let n = remaining.min(extent_left).min(buf.len() as u64) as usize;
+                self.source.read_at(pos, &mut buf[..n])?;
+                out.write_all(&buf[..n])?;
+                pos += n as u64;
+                extent_left -= n as u64;
+                remaining -= n as u64;
+            }
+        }
+        if remaining > 0 {
+            bail!("HFS+ fork for file {file_id} is truncated");
+        }
+        Ok(())
+    }
+}
+
+/// Copy the whole volume into `dest`.
+///
+/// # Errors
+///
+/// Returns an error if the volume is malformed, uses features we don't
+/// decode (compressed files, directory hard links), an entry is rejected by
+/// `guard`, or `dest` can't be written.
+pub fn extract<R: ReadAt>(source: &R, dest: &Path, guard: &mut ExtractGuard) -> Result<()> {
+    let mut header = [0u8; 512];
+    source.read_at(HEADER_OFFSET, &mut header)?;
+    if !matches!(&header[0..2], b"H+" | b"HX") {
+        bail!("Not an HFS+ volume");
+    }
+
+    let mut volume = Volume {
+        source,
+        block_size: u64::from(be_u32(&header, 40)),
+        extents_tree: Vec::new(),
+    };
+    if volume.block_size == 0 {
+        bail!("HFS+ volume has zero block size");
+    }
+    volume.extents_tree = volume.read_fork(&Fork::parse(&header[192..272]), EXTENTS_FILE_ID)?;
+    let catalog = volume.read_fork(&Fork::parse(&header[272..352]), CATALOG_FILE_ID)?;
+
+    let records = read_catalog(&catalog)?;
+    write_records(&volume, &records, dest, guard)
+}
+
+fn read_catalog(tree: &[u8]) -> Result<Vec<Record>> {
+    let mut records = Vec::new();
+    for_each_leaf_record(tree, |key, data| {
+        if key.len() < 8 {
+            return Ok(());
+        }
+        let parent = be_u32(key, 2);
+        let name_len = usize::from(u16::from_be_bytes([key[6], key[7]]));
+        let units: Vec<u16> = key
+            .get(8..8 + name_len * 2)
+            .context("Truncated HFS+ catalog key")?
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        // POSIX ':' is stored as '/' in the catalog.
+        let name = String::from_utf16_lossy(&units).replace('/', ":");
+
+        let record_type = u16::from_be_bytes([data[0], data[1]]);
+        let kind = match record_type {
+            RECORD_FOLDER => Kind::Folder,
+            RECORD_FILE if data.len() >= 248 => {
+                let mode = u16::from_be_bytes([data[42], data[43]]);
+                let fork = Fork::parse(&data[88..168]);
+                if data[41] & UF_COMPRESSED != 0 {
+                    bail!("HFS+ file '{name}' is compressed (decmpfs)");
+                }
+                if &data[48..52] == HARDLINK_TYPE && &data[52..56] == HARDLINK_CREATOR {
+                    Kind::HardLink {
+                        inode: be_u32(data, 44),
+                    }
+                } else if mode & S_IFMT == S_IFLNK {
+                    Kind::Symlink { fork }
+                } else {
+                    Kind::File { fork, mode }
+                }
+            }
+            _ => return Ok(()),
+        };
+
+        records.push(Record {
+            parent,
+            name,
+            id: be_u32(data, 8),
+            kind,
+        });
+        Ok(())
+    })?;
+    Ok(records)
+}
+
+fn write_records<R: ReadAt>(
+    volume: &Volume<'_, R>,
+    records: &[Record],
+    dest: &Path,
+    guard: &mut ExtractGuard,
+) -> Result<()> {
+    let folders: HashMap<u32, &Record> = records
+        .iter()
+        .filter(|r| matches!(r.kind, Kind::Folder))
+        .map(|r| (r.id, r))
+        .collect();
+    let private_dir = records
+        .iter()
+        .find(|r| r.parent == ROOT_FOLDER_ID && r.name == FILE_PRIVATE_DIR)
+        .map(|r| r.id);
+    let inodes: HashMap<&str, &Record> = records
+        .iter()
+        .filter(|r| Some(r.parent) == private_dir)
+        .map(|r| (r.name.as_str(), r))
+        .collect();
+
+    fs::create_dir_all(dest)?;
+    for record in records {
+        let Some(relative) = resolve_path(&folders, record)? else {
+            continue;
+        };
+        let target = guard.entry(dest, &relative)?;
+
+        match &record.kind {
+            Kind::Folder => fs::create_dir_all(&target)?,
+            Kind::File { fork, mode } => {
+                write_file(volume, fork, record.id, *mode, &relative, &target, guard)?;
+            }
+            Kind::Symlink { fork } => {
+                if fork.size > MAX_SYMLINK_LEN {
+                    bail!("HFS+ symlink '{}' target is too long", relative.display());
+                }
+                let link = volume.read_fork(fork, record.id)?;
+                super::create_symlink(
+                    guard,
+                    dest,
+                    &relative,
+                    &target,
+                    &String::from_utf8_lossy(&link),
+                )?;
+            }
+            Kind::HardLink { inode } => {
+                let Some(Record {
+                    id,
+                    kind: Kind::File { fork, mode },
+                    ..
+                }) = inodes.get(format!("iNode{inode}").as_str())
+                else {
+                    bail!("HFS+ hard link '{}' has no target inode", record.name);
+                };
+                write_file(volume, fork, *id, *mode, &relative, &target, guard)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Build the path of `record` relative to the volume root, or `None` if it
+/// lives in (or is) a hidden system entry or an orphaned folder.
+///
+/// # Errors
+///
+/// Returns an error if the folder chain loops or a name isn't a single
+/// path component.
+fn resolve_path(folders: &HashMap<u32, &Record>, record: &Record) -> Result<Option<PathBuf>> {
+    if record.id == ROOT_FOLDER_ID {
+        return Ok(None);
+    }
+    let mut parts = vec![record.name.as_str()];
+    let mut parent = record.parent;
+    while parent != ROOT_FOLDER_ID {
+        let Some(folder) = folders.get(&parent) else {
+            return Ok(None);
+        };
+        // A chain longer than the number of folders must revisit one
+        if parts.len() > folders.len() {
+            bail!("HFS+ catalog has a folder cycle at '{}'", record.name);
+        }
+        parts.push(folder.name.as_str());
+        parent = folder.parent;
+    }
+    if parts
+        .last()
+        .is_some_and(|top| SKIPPED_ROOT_ENTRIES.contains(top))
+    {
+        return Ok(None);
+    }
+    for part in &parts {
+        super::check_name(part)?;
+    }
+    Ok(Some(parts.iter().rev().collect()))
+}
+
+fn write_file<R: ReadAt>(
+    volume: &Volume<'_, R>,
+    fork: &Fork,
+    id: u32,
+    mode: u16,
+    entry: &Path,
+    target: &Path,
+    guard: &mut ExtractGuard,
+) -> Result<()> {
+    // copy_fork writes exactly `fork.size` bytes or fails
+    guard.reserve(entry, fork.size)?;
+    let mut out = io::BufWriter::new(super::create_file(target)?);
+    volume.copy_fork(fork, id, &mut out)?;
+    out.flush()?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let perm = if mode == 0 { 0o644 } else { mode & 0o7777 };
+        fs::set_permissions(target, fs::Permissions::from_mode(u32::from(perm)))?;
+    }
+    #[cfg(not(unix))]
+    let _ = mode;
+    Ok(())
+}
+
+/// Collect overflow extents for the data fork of `file_id`, in order.
+fn overflow_extents(tree: &[u8], file_id: u32) -> Result<Vec<(u64, u64)>> {
+    let mut found = Vec::new();
+    for_each_leaf_record(tree, |key, data| {
+        // keyLength, forkType, pad, fileID, startBlock
+        if key.len() >= 12 && key[2] == 0 && be_u32(key, 4) == file_id {
+            let start = be_u32(key, 8);
+            let extents: Vec<_> = (0..8)
+                .map(|i| {
+                    (
+                        u64::from(be_u32(data, i * 8)),
+                        u64::from(be_u32(data, i * 8 + 4)),
+                    )
+                })
+                .filter(|(_, n)| *n > 0)
+                .collect();
+            found.push((start, extents));
+        }
+        Ok(())
+    })?;
+    found.sort_by_key(|(start, _)| *start);
+    Ok(found.into_iter().flat_map(|(_, e)| e).collect())
+}
+
+/// Visit every `(key, data)` pair in the leaf chain of a B-tree file.
+fn for_each_leaf_record<F>(tree: &[u8], mut visit: F) -> Result<()>
+where
+    F: FnMut(&[u8], &[u8]) -> Result<()>,
+{
+    if tree.len() < 14 + 106 {
+        return Ok(());
+    }
+    // Header record follows the 14-byte node descriptor of node 0.
+    let first_leaf = be_u32(tree, 14 + 10);
+    let node_size = usize::from(u16::from_be_bytes([tree[14 + 18], tree[14 + 19]]));
+    if node_size < 512 {
+        bail!("Invalid HFS+ B-tree node size {node_size}");
+    }
+
+    let mut node_id = first_leaf;
+    let mut visited = 0usize;
+    while node_id != 0 {
+        visited += 1;
+        if visited > tree.len() / node_size {
+            bail!("HFS+ B-tree leaf chain loops");
+        }
+        let start = node_id as usize * node_size;
+        let node = tree
+            .get(start..start + node_size)
+            .context("HFS+ B-tree node out of range")?;
+        let num_records = usize::from(u16::from_be_bytes([node[10], node[11]]));
+
+        for i in 0..num_records {
+            let at = node_size - 2 * (i + 1);
+            let offset = usize::from(u16::from_be_bytes([node[at], node[at + 1]]));
+            let key_len = usize::from(u16::from_be_bytes([
+                *node.get(offset).context("Bad HFS+ record offset")?,
+                *node.get(offset + 1).context("Bad HFS+ record offset")?,
+            ]));
+            let data_start = offset + 2 + key_len;
+            let end = if i + 1 < num_records {
+                let next = node_size - 2 * (i + 2);
+                usize::from(u16::from_be_bytes([node[next], node[next + 1]]))
+            } else {
+                node_size - 2 * (num_records + 1)
+            };
+            let (Some(key), Some(data)) = (
+                node.get(offset..data_start),
+                node.get(data_start..end.max(data_start)),
+            ) else {
+                bail!("Malformed HFS+ B-tree record");
+            };
+            if data.len() >= 2 {
+                visit(key, data)?;
+            }
+        }
+        node_id = be_u32(node, 0);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::io::extract::{ExtractError, ExtractPolicy, Violation};
+
+    const BLOCK: usize = 4096;
+    const NODE: usize = 4096;
+
+    struct Entry<'a> {
+        parent: u32,
+        id: u32,
+        name: &'a str,
+        data: Option<&'a [u8]>,
+        mode: u16,
+    }
+
+    fn catalog_key(parent: u32, name: &str) -> Vec<u8> {
+        let units: Vec<u16> = name.encode_utf16().collect();
+        let mut key = Vec::new();
+        key.extend_from_slice(&((6 + units.len() * 2) as u16).to_be_bytes());
+        key.extend_from_slice(&parent.to_be_bytes());
+        key.extend_from_slice(&(units.len() as u16).to_be_bytes());
+        for u in units {
+            key.extend_from_slice(&u.to_be_bytes());
+        }
+        key
+    }
+
+    fn fork(size: u64, start: u32, blocks: u32) -> [u8; 80] {
+        let mut f = [0u8; 80];
+        f[0..8].copy_from_slice(&size.to_be_bytes());
+        f[12..16].copy_from_slice(&blocks.to_be_bytes());
+        f[16..20].copy_from_slice(&start.to_be_bytes());
+        f[20..24].copy_from_slice(&blocks.to_be_bytes());
+        f
+    }
+
+    /// Build a minimal single-leaf HFS+ volume with the given entries.
+    /// Folders have `data == None`; file data is laid out one block each.
+    pub(crate) fn build_hfs(entries: &[(&str, Option<&[u8]>, u16)]) -> Vec<u8> {
+        // Blocks: 0 = boot + header, 1-2 = catalog (header node + leaf), 3+ = file data.
+        let mut folder_ids: HashMap<String, u32> = HashMap::new();
+        folder_ids.insert(String::new(), ROOT_FOLDER_ID);
+
+        let mut list = Vec::new();
+        for ((path, data, mode), next_id) in entries.iter().zip(16u32..) {
+            let (dir, name) = path.rsplit_once('/').unwrap_or(("", path));
+            list.push(Entry {
+                parent: folder_ids[dir],
+                id: next_id,
+                name,
+                data: *data,
+                mode: *mode,
+            });
+            if data.is_none() {
+                folder_ids.insert((*path).to_string(), next_id);
+            }
+        }
+        build_catalog(&list)
+    }
+
+    /// Build a volume from raw catalog records, in leaf order.
+    fn build_catalog(list: &[Entry<'_>]) -> Vec<u8> {
+        let mut image = vec![0u8; BLOCK * (3 + list.len())];
+        let mut leaf = vec![0u8; NODE];
+        leaf[8] = 0xFF; // kind = leaf
+        leaf[9] = 1;
+        leaf[10..12].copy_from_slice(&(list.len() as u16).to_be_bytes());
+        let mut pos = 14;
+        for (i, e) in list.iter().enumerate() {
+            let key = catalog_key(e.parent, e.name);
+            let mut rec = vec![0u8; 248];
+            rec[8..12].copy_from_slice(&e.id.to_be_bytes());
+            rec[42..44].copy_from_slice(&e.mode.to_be_bytes());
+            if let Some(data) = e.data {
+                rec[0..2].copy_from_slice(&RECORD_FILE.to_be_bytes());
+                let block = (3 + i) as u32;
+                rec[88..168].copy_from_slice(&fork(data.len() as u64, block, 1));
+                let at = block as usize * BLOCK;
+                image[at..at + data.len()].copy_from_slice(data);
+            } else {
+                rec[0..2].copy_from_slice(&RECORD_FOLDER.to_be_bytes());
+                rec.truncate(88);
+            }
+            let at = NODE - 2 * (i + 1);
+            leaf[at..at + 2].copy_from_slice(&(pos as u16).to_be_bytes());
+            leaf[pos..pos + key.len()].copy_from_slice(&key);
+            pos += key.len();
+            leaf[pos..pos + rec.len()].copy_from_slice(&rec);
+            pos += rec.len();
+        }
+        let at = NODE - 2 * (list.len() + 1);
+        leaf[at..at + 2].copy_from_slice(&(pos as u16).to_be_bytes());
+
+        let mut head = vec![0u8; NODE];
+        head[8] = 1; // kind = header
+        head[14 + 10..14 + 14].copy_from_slice(&1u32.to_be_bytes());
+        head[14 + 18..14 + 20].copy_from_slice(&(NODE as u16).to_be_bytes());
+
+        image[BLOCK..2 * BLOCK].copy_from_slice(&head);
+        image[2 * BLOCK..3 * BLOCK].copy_from_slice(&leaf);
+
+        let h = HEADER_OFFSET as usize;
+        image[h..h + 2].copy_from_slice(b"H+");
+        image[h + 40..h + 44].copy_from_slice(&(BLOCK as u32).to_be_bytes());
+        image[h + 272..h + 352].copy_from_slice(&fork((2 * NODE) as u64, 1, 2));
+        image
+    }
+
+    #[test]
+    fn test_extract_hfs() {
+        let image = build_hfs(&[
+            ("Demo.app", None, 0o040_755),
+            ("Demo.app/Contents", None, 0o040_755),
+            (
+                "Demo.app/Contents/demo",
+                Some(b"#!/bin/sh\necho hi\n"),
+                0o100_755,
+            ),
+            ("notes.txt", Some(b"notes"), 0o100_644),
+            ("Link", Some(b"Demo.app"), 0o120_755),
+            (".journal", Some(b"xxxx"), 0o100_644),
+        ]);
+        assert!(probe(&image.as_slice()));
+
+        let dir = tempfile::tempdir().unwrap();
+        extract(&image.as_slice(), dir.path(), &mut ExtractGuard::default()).unwrap();
+
+        let exe = dir.path().join("Demo.app/Contents/demo");
+        assert_eq!(fs::read(&exe).unwrap(), b"#!/bin/sh\necho hi\n");
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(
+                fs::metadata(&exe).unwrap().permissions().mode() & 0o777,
+                0o755
+            );
+        }
+        assert_eq!(fs::read(dir.path().join("notes.txt")).unwrap(), b"notes");
+        assert_eq!(
+            fs::read_link(dir.path().join("Link")).unwrap(),
+            Path::new("Demo.app")
+        );
+        assert!(!dir.path().join(".journal").exists());
+    }
+
+    fn violation(result: Result<()>) -> Violation {
+        match result.unwrap_err().downcast::<ExtractError>() {
+            Ok(ExtractError::Unsafe { violation, .. }) => violation,
+            other => panic!("expected a policy violation, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_rejects_malicious_volumes() {
+        let sandbox = tempfile::tempdir().unwrap();
+        let dest = sandbox.path().join("dest");
+        let run = |image: Vec<u8>, guard: &mut ExtractGuard| {
+            let _ = fs::remove_dir_all(&dest);
+            extract(&image.as_slice(), &dest, guard)
+        };
+
+        // Symlink out of the destination
+        let image = build_hfs(&[("Link", Some(b"../../etc"), 0o120_755)]);
+        assert!(matches!(
+            violation(run(image, &mut ExtractGuard::default())),
+            Violation::SymlinkEscape(_)
+        ));
+        assert!(fs::symlink_metadata(dest.join("Link")).is_err());
+
+        // A file written through a symlink left by an earlier record
+        let image = build_catalog(&[
+            Entry {
+                parent: ROOT_FOLDER_ID,
+                id: 16,
+                name: "dir",
+                data: Some(b"sub"),
+                mode: 0o120_755,
+            },
+            Entry {
+                parent: 17,
+                id: 18,
+                name: "x",
+                data: Some(b"x"),
+                mode: 0o100_644,
+            },
+            Entry {
+                parent: ROOT_FOLDER_ID,
+                id: 17,
+                name: "dir",
+                data: None,
+                mode: 0o040_755,
+            },
+        ]);
+        assert_eq!(
+            violation(run(image, &mut ExtractGuard::default())),
+            Violation::ThroughSymlink
+        );
+        assert!(!dest.join("sub/x").exists());
+
+        // Names that aren't a single path component
+        let image = build_hfs(&[("evil\0name", Some(b"x"), 0o100_644)]);
+        let err = run(image, &mut ExtractGuard::default()).unwrap_err();
+        assert!(err.to_string().contains("Invalid file name"), "{err}");
+
+        // Folders that are each other's parent
+        let image = build_catalog(&[
+            Entry {
+                parent: 17,
+                id: 16,
+                name: "a",
+                data: None,
+                mode: 0o040_755,
+            },
+            Entry {
+                parent: 16,
+                id: 17,
+                name: "b",
+                data: None,
+                mode: 0o040_755,
+            },
+        ]);
+        let err = run(image, &mut ExtractGuard::default()).unwrap_err();
+        assert!(err.to_string().contains("cycle"), "{err}");
+
+        // Output past the size limit
+        let policy = ExtractPolicy {
+            max_total_size: 4,
+            ..ExtractPolicy::default()
+        };
+        let image = build_hfs(&[("big", Some(b"12345"), 0o100_644)]);
+        assert_eq!(
+            violation(run(image, &mut ExtractGuard::new(policy, None))),
+            Violation::TooLarge(4)
+        );
+    }
+
+    #[test]
+    fn test_probe_rejects_other() {
+        assert!(!probe(&vec![0u8; 4096].as_slice()));
+    }
+}
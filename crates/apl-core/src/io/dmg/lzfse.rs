@@ -0,0 +1,775 @@
+//! LZFSE decoder for ULFO chunks.
+//!
+//! An LZFSE stream is a sequence of blocks, each starting with a 4-byte
+//! magic: `bvx-` (stored bytes), `bvxn` (LZVN, a byte-oriented LZ77 used
+//! for small inputs), `bvx1`/`bvx2` (literals and match triples coded with
+//! finite state entropy, `bvx2` packing the header) and `bvx$` (end of
+//! stream). Matches may reach back into earlier blocks of the same stream.
+
+use std::io;
+
+const END_MAGIC: u32 = u32::from_le_bytes(*b"bvx$");
+const RAW_MAGIC: u32 = u32::from_le_bytes(*b"bvx-");
+const V1_MAGIC: u32 = u32::from_le_bytes(*b"bvx1");
+const V2_MAGIC: u32 = u32::from_le_bytes(*b"bvx2");
+const LZVN_MAGIC: u32 = u32::from_le_bytes(*b"bvxn");
+
+const V1_HEADER_LEN: usize = 772;
+const V2_HEADER_LEN: usize = 32;
+
+const LITERALS_PER_BLOCK: usize = 4 * 10000;
+const MATCHES_PER_BLOCK: usize = 10000;
+
+const L_STATES: usize = 64;
+const M_STATES: usize = 64;
+const D_STATES: usize = 256;
+const LITERAL_STATES: usize = 1024;
+
+const L_SYMBOLS: usize = 20;
+const M_SYMBOLS: usize = 20;
+const D_SYMBOLS: usize = 64;
+const LITERAL_SYMBOLS: usize = 256;
+
+const L_EXTRA_BITS: [u8; L_SYMBOLS] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2, 3, 5, 8];
+const L_BASE: [u32; L_SYMBOLS] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 20, 28, 60,
+];
+const M_EXTRA_BITS: [u8; M_SYMBOLS] = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 3, 5, 8, 11];
+const M_BASE: [u32; M_SYMBOLS] = [
+    0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 24, 56, 312,
+];
+
+fn corrupt(what: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("Corrupt LZFSE chunk: {what}"),
+    )
+}
+
+/// Decompress an LZFSE stream, failing if it decodes to more than `limit`
+/// bytes.
+pub(super) fn decompress(input: &[u8], limit: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(limit);
+    let mut src = input;
+
+    loop {
+        let block_len = match le_u32(src, 0)? {
+            END_MAGIC => return Ok(out),
+            RAW_MAGIC => {
+                let n_raw = le_u32(src, 4)? as usize;
+                let end = block_end(&out, n_raw, limit)?;
+                let data = src.get(8..8 + n_raw).ok_or_else(|| corrupt("truncated"))?;
+                out.extend_from_slice(data);
+                debug_assert_eq!(out.len(), end);
+                8 + n_raw
+            }
+            LZVN_MAGIC => {
+                let n_raw = le_u32(src, 4)? as usize;
+                let n_payload = le_u32(src, 8)? as usize;
+                let end = block_end(&out, n_raw, limit)?;
+                let payload = src
+                    .get(12..12 + n_payload)
+                    .ok_or_else(|| corrupt("truncated"))?;
+                lzvn_decode(payload, &mut out, end)?;
+                12 + n_payload
+            }
+            magic @ (V1_MAGIC | V2_MAGIC) => {
+                let (header, header_len) = if magic == V1_MAGIC {
+                    (parse_v1(src)?, V1_HEADER_LEN)
+                } else {
+                    parse_v2(src)?
+                };
+                let end = block_end(&out, header.n_raw_bytes, limit)?;
+                let payload_len = header.n_literal_payload_bytes + header.n_lmd_payload_bytes;
+                let payload = src
+                    .get(header_len..header_len + payload_len)
+                    .ok_or_else(|| corrupt("truncated"))?;
+                decode_fse_block(&header, payload, &mut out, end)?;
+                header_len + payload_len
+            }
+            _ => return Err(corrupt("bad block magic")),
+        };
+        src = &src[block_len..];
+    }
+}
+
+/// Output length once a block of `n_raw` bytes has been decoded.
+fn block_end(out: &[u8], n_raw: usize, limit: usize) -> io::Result<usize> {
+    out.len()
+        .checked_add(n_raw)
+        .filter(|&end| end <= limit)
+        .ok_or_else(|| corrupt("decodes past the end of the chunk"))
+}
+
+fn le_u32(buf: &[u8], off: usize) -> io::Result<u32> {
+    buf.get(off..off + 4)
+        .and_then(|b| b.try_into().ok())
+        .map(u32::from_le_bytes)
+        .ok_or_else(|| corrupt("truncated"))
+}
+
+fn le_u64(buf: &[u8], off: usize) -> io::Result<u64> {
+    buf.get(off..off + 8)
+        .and_then(|b| b.try_into().ok())
+        .map(u64::from_le_bytes)
+        .ok_or_else(|| corrupt("truncated"))
+}
+
+fn le_u16(buf: &[u8], off: usize) -> io::Result<u16> {
+    buf.get(off..off + 2)
+        .and_then(|b| b.try_into().ok())
+        .map(u16::from_le_bytes)
+        .ok_or_else(|| corrupt("truncated"))
+}
+
+/// Append a match of `len` bytes starting `distance` bytes back.
+fn copy_match(out: &mut Vec<u8>, distance: usize, len: usize) -> io::Result<()> {
+    if distance == 0 || distance > out.len() {
+        return Err(corrupt("match distance out of range"));
+    }
+    let start = out.len() - distance;
+    if distance >= len {
+        out.extend_from_within(start..start + len);
+    } else {
+        // Overlapping copy repeats the last `distance` bytes
+        for i in 0..len {
+            out.push(out[start + i]);
+        }
+    }
+    Ok(())
+}
+
+/// Decode an LZVN payload, appending exactly up to `end` bytes of output.
+fn lzvn_decode(src: &[u8], out: &mut Vec<u8>, end: usize) -> io::Result<()> {
+    let byte = |i: usize| {
+        src.get(i)
+            .copied()
+            .map(usize::from)
+            .ok_or_else(|| corrupt("truncated LZVN payload"))
+    };
+    let mut i = 0;
+    let mut distance = 0;
+
+    loop {
+        let op = byte(i)?;
+        // (opcode length, literals, match length, new distance)
+        let (len, literals, matched, new_distance) = match op {
+            0x06 => break,
+            0x0e | 0x16 => (1, 0, 0, None),
+            0xd0..=0xdf => return Err(corrupt("undefined LZVN opcode")),
+            0xe0 => (2, byte(i + 1)? + 16, 0, None),
+            0xe1..=0xef => (1, op & 0xf, 0, None),
+            0xf0 => (2, 0, byte(i + 1)? + 16, None),
+            0xf1..=0xff => (1, 0, op & 0xf, None),
+            0xa0..=0xbf => {
+                let (b1, b2) = (byte(i + 1)?, byte(i + 2)?);
+                let matched = (((op & 7) << 2) | (b1 & 3)) + 3;
+                (3, (op >> 3) & 3, matched, Some((b1 >> 2) | (b2 << 6)))
+            }
+            _ if op & 7 == 7 => {
+                let d = byte(i + 1)? | (byte(i + 2)? << 8);
+                (3, op >> 6, ((op >> 3) & 7) + 3, Some(d))
+            }
+            _ if op & 7 == 6 => {
+                if op < 0x40 {
+                    return Err(corrupt("undefined LZVN opcode"));
+                }
+                (1, op >> 6, ((op >> 3) & 7) + 3, None)
+            }
+            _ => {
+                let d = ((op & 7) << 8) | byte(i + 1)?;
+                (2, op >> 6, ((op >> 3) & 7) + 3, Some(d))
+            }
+        };
+        if let Some(d) = new_distance {
+            distance = d;
+        }
+
+        i += len;
+        if out.len() + literals + matched > end {
+            return Err(corrupt("LZVN block decodes past its size"));
+        }
+        let lit = src
+            .get(i..i + literals)
+            .ok_or_else(|| corrupt("truncated LZVN payload"))?;
+        out.extend_from_slice(lit);
+        i += literals;
+        if matched > 0 {
+            copy_match(out, distance, matched)?;
+        }
+    }
+
+    if out.len() != end {
+        return Err(corrupt("LZVN block is shorter than its size"));
+    }
+    Ok(())
+}
+
+/// Decoded `bvx1`/`bvx2` block header.
+struct BlockHeader {
+    n_raw_bytes: usize,
+    n_literals: usize,
+    n_matches: usize,
+    n_literal_payload_bytes: usize,
+    n_lmd_payload_bytes: usize,
+    literal_bits: i32,
+    literal_state: [u16; 4],
+    lmd_bits: i32,
+    l_state: u16,
+    m_state: u16,
+    d_state: u16,
+    l_freq: [u16; L_SYMBOLS],
+    m_freq: [u16; M_SYMBOLS],
+    d_freq: [u16; D_SYMBOLS],
+    literal_freq: [u16; LITERAL_SYMBOLS],
+}
+
+impl BlockHeader {
+    fn empty() -> Self {
+        Self {
+            n_raw_bytes: 0,
+            n_literals: 0,
+            n_matches: 0,
+            n_literal_payload_bytes: 0,
+            n_lmd_payload_bytes: 0,
+            literal_bits: 0,
+            literal_state: [0; 4],
+            lmd_bits: 0,
+            l_state: 0,
+            m_state: 0,
+            d_state: 0,
+            l_freq: [0; L_SYMBOLS],
+            m_freq: [0; M_SYMBOLS],
+            d_freq: [0; D_SYMBOLS],
+            literal_freq: [0; LITERAL_SYMBOLS],
+        }
+    }
+
+    fn freqs_mut(&mut self) -> impl Iterator<Item = &mut u16> {
+        self.l_freq
+            .iter_mut()
+            .chain(self.m_freq.iter_mut())
+            .chain(self.d_freq.iter_mut())
+            .chain(self.literal_freq.iter_mut())
+    }
+}
+
+fn parse_v1(src: &[u8]) -> io::Result<BlockHeader> {
+    if src.len() < V1_HEADER_LEN {
+        return Err(corrupt("truncated"));
+    }
+    let u32_at = |off| le_u32(src, off).map(|v| v as usize);
+    let mut header = BlockHeader::empty();
+    header.n_raw_bytes = u32_at(4)?;
+    header.n_literals = u32_at(12)?;
+    header.n_matches = u32_at(16)?;
+    header.n_literal_payload_bytes = u32_at(20)?;
+    header.n_lmd_payload_bytes = u32_at(24)?;
+    header.literal_bits = le_u32(src, 28)?.cast_signed();
+    for (i, state) in header.literal_state.iter_mut().enumerate() {
+        *state = le_u16(src, 32 + 2 * i)?;
+    }
+    header.lmd_bits = le_u32(src, 40)?.cast_signed();
+    header.l_state = le_u16(src, 44)?;
+    header.m_state = le_u16(src, 46)?;
+    header.d_state = le_u16(src, 48)?;
+    for (i, freq) in header.freqs_mut().enumerate() {
+        *freq = le_u16(src, 50 + 2 * i)?;
+    }
+    Ok(header)
+}
+
+/// Parse a `bvx2` header, returning it and its length in bytes.
+fn parse_v2(src: &[u8]) -> io::Result<(BlockHeader, usize)> {
+    fn field(v: u64, offset: u32, bits: u32) -> usize {
+        ((v >> offset) & ((1 << bits) - 1)) as usize
+    }
+
+    let (v0, v1, v2) = (le_u64(src, 8)?, le_u64(src, 16)?, le_u64(src, 24)?);
+    let header_len = field(v2, 0, 32);
+    let freq_bytes = src
+        .get(V2_HEADER_LEN..header_len)
+        .ok_or_else(|| corrupt("bad header size"))?;
+
+    let mut header = BlockHeader::empty();
+    header.n_raw_bytes = le_u32(src, 4)? as usize;
+    header.n_literals = field(v0, 0, 20);
+    header.n_literal_payload_bytes = field(v0, 20, 20);
+    header.n_matches = field(v0, 40, 20);
+    header.literal_bits = field(v0, 60, 3) as i32 - 7;
+    for (i, state) in header.literal_state.iter_mut().enumerate() {
+        *state = field(v1, 10 * i as u32, 10) as u16;
+    }
+    header.n_lmd_payload_bytes = field(v1, 40, 20);
+    header.lmd_bits = field(v1, 60, 3) as i32 - 7;
+    header.l_state = field(v2, 32, 10) as u16;
+    header.m_state = field(v2, 42, 10) as u16;
+    header.d_state = field(v2, 52, 10) as u16;
+
+    // Frequencies are variable-length codes, read LSB first; a header with
+    // no room for them leaves every frequency at zero.
+    if !freq_bytes.is_empty() {
+        let mut bytes = freq_bytes.iter();
+        let mut accum = 0u32;
+        let mut nbits = 0u32;
+        for freq in header.freqs_mut() {
+            while nbits + 8 <= 32 {
+                let Some(&b) = bytes.next() else { break };
+                accum |= u32::from(b) << nbits;
+                nbits += 8;
+            }
+            let (len, value) = decode_freq(accum);
+            if len > nbits {
+                return Err(corrupt("truncated frequency table"));
+            }
+            *freq = value;
+            accum >>= len;
+            nbits -= len;
+        }
+        if nbits >= 8 || bytes.next().is_some() {
+            return Err(corrupt("bad frequency table"));
+        }
+    }
+
+    Ok((header, header_len))
+}
+
+/// Decode one frequency code from the low bits of `bits`, returning its
+/// length and value.
+fn decode_freq(bits: u32) -> (u32, u16) {
+    const LEN: [u8; 16] = [2, 3, 2, 5, 2, 3, 2, 8, 2, 3, 2, 5, 2, 3, 2, 14];
+    const VALUE: [u8; 32] = [
+        0, 2, 1, 4, 0, 3, 1, 0, 0, 2, 1, 5, 0, 3, 1, 0, 0, 2, 1, 6, 0, 3, 1, 0, 0, 2, 1, 7, 0, 3,
+        1, 0,
+    ];
+    let b = (bits & 31) as usize;
+    match LEN[b & 15] {
+        8 => (8, 8 + ((bits >> 4) & 0xf) as u16),
+        14 => (14, 24 + ((bits >> 4) & 0x3ff) as u16),
+        len => (u32::from(len), u16::from(VALUE[b])),
+    }
+}
+
+/// Entry of a decoding table: a symbol (or value base and extra bits) plus
+/// the state transition.
+#[derive(Clone, Copy)]
+struct Entry {
+    bits: u32,
+    delta: u32,
+    value_bits: u32,
+    value: u32,
+}
+
+/// FSE decoding table for one alphabet.
+struct Table(Vec<Entry>);
+
+impl Table {
+    /// Build the table for `freqs` spread over `states` states; symbol `i`
+    /// decodes to `base[i]` plus `extra[i]` raw bits.
+    fn new(states: usize, freqs: &[u16], extra: &[u8], base: &[u32]) -> io::Result<Self> {
+        let total: usize = freqs.iter().map(|&f| usize::from(f)).sum();
+        if total > states {
+            return Err(corrupt("bad frequency table"));
+        }
+
+        let states_clz = (states as u32).leading_zeros();
+        let mut entries = Vec::with_capacity(total);
+        for (i, &f) in freqs.iter().enumerate() {
+            if f == 0 {
+                continue;
+            }
+            let f = u32::from(f);
+            let k = f.leading_zeros() - states_clz;
+            let j0 = ((2 * states as u32) >> k) - f;
+            for j in 0..f {
+                let (bits, delta) = if j < j0 {
+                    (k, ((f + j) << k) - states as u32)
+                } else {
+                    (k - 1, (j - j0) << (k - 1))
+                };
+                entries.push(Entry {
+                    bits,
+                    delta,
+                    value_bits: u32::from(extra[i]),
+                    value: base[i],
+                });
+            }
+        }
+        Ok(Self(entries))
+    }
+
+    /// Decode one value, advancing `state`.
+    fn decode(&self, state: &mut u32, input: &mut BitReader<'_>) -> io::Result<u32> {
+        let e = self
+            .0
+            .get(*state as usize)
+            .ok_or_else(|| corrupt("bad FSE state"))?;
+        let x = input.pull(e.bits + e.value_bits)?;
+        *state = e.delta + (x >> e.value_bits) as u32;
+        Ok(e.value + (x & ((1 << e.value_bits) - 1)) as u32)
+    }
+}
+
+/// FSE bit stream, consumed from the end of its buffer towards the start.
+struct BitReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+    accum: u64,
+    nbits: u32,
+}
+
+impl<'a> BitReader<'a> {
+    /// Start reading `buf`, whose final byte holds `-n` padding bits.
+    fn new(buf: &'a [u8], n: i32) -> io::Result<Self> {
+        let (bytes, nbits) = if n == 0 { (7, 56) } else { (8, 64 + n) };
+        let pos = buf
+            .len()
+            .checked_sub(bytes)
+            .ok_or_else(|| corrupt("truncated bit stream"))?;
+        let nbits = u32::try_from(nbits)
+            .ok()
+            .filter(|n| (56..64).contains(n))
+            .ok_or_else(|| corrupt("bad bit stream header"))?;
+        let accum = read_le(&buf[pos..pos + bytes]);
+        if accum >> nbits != 0 {
+            return Err(corrupt("bad bit stream header"));
+        }
+        Ok(Self {
+            buf,
+            pos,
+            accum,
+            nbits,
+        })
+    }
+
+    /// Refill the accumulator to at least 56 bits.
+    fn flush(&mut self) -> io::Result<()> {
+        let add = (63 - self.nbits) & !7;
+        let bytes = (add / 8) as usize;
+        self.pos = self
+            .pos
+            .checked_sub(bytes)
+            .ok_or_else(|| corrupt("truncated bit stream"))?;
+        if add > 0 {
+            self.accum = (self.accum << add) | read_le(&self.buf[self.pos..self.pos + bytes]);
+            self.nbits += add;
+        }
+        Ok(())
+    }
+
+    fn pull(&mut self, n: u32) -> io::Result<u64> {
+        self.nbits = self
+            .nbits
+            .checked_sub(n)
+            .ok_or_else(|| corrupt("bit stream underflow"))?;
+        let result = self.accum >> self.nbits;
+        self.accum &= (1 << self.nbits) - 1;
+        Ok(result)
+    }
+}
+
+fn read_le(bytes: &[u8]) -> u64 {
+    bytes
+        .iter()
+        .rev()
+        .fold(0, |acc, &b| (acc << 8) | u64::from(b))
+}
+
+fn decode_fse_block(
+    header: &BlockHeader,
+    payload: &[u8],
+    out: &mut Vec<u8>,
+    end: usize,
+) -> io::Result<()> {
+    if header.n_literals > LITERALS_PER_BLOCK
+        || header.n_matches > MATCHES_PER_BLOCK
+        || !(-7..=0).contains(&header.literal_bits)
+        || !(-7..=0).contains(&header.lmd_bits)
+    {
+        return Err(corrupt("bad block header"));
+    }
+
+    // Literals are decoded four at a time from interleaved states
+    let literal_base: Vec<u32> = (0..LITERAL_SYMBOLS as u32).collect();
+    let literal_table = Table::new(
+        LITERAL_STATES,
+        &header.literal_freq,
+        &[0; LITERAL_SYMBOLS],
+        &literal_base,
+    )?;
+    let mut states = header.literal_state.map(u32::from);
+    let mut input = BitReader::new(
+        &payload[..header.n_literal_payload_bytes],
+        header.literal_bits,
+    )?;
+    let mut literals = Vec::with_capacity(header.n_literals.next_multiple_of(4));
+    while literals.len() < header.n_literals {
+        input.flush()?;
+        for state in &mut states {
+            literals.push(literal_table.decode(state, &mut input)? as u8);
+        }
+    }
+    literals.truncate(header.n_literals);
+
+    let d_extra: Vec<u8> = (0..D_SYMBOLS).map(|i| (i / 4) as u8).collect();
+    let d_base: Vec<u32> = d_extra
+        .iter()
+        .scan(0, |next, &extra| {
+            let base = *next;
+            *next += 1 << extra;
+            Some(base)
+        })
+        .collect();
+    let l_table = Table::new(L_STATES, &header.l_freq, &L_EXTRA_BITS, &L_BASE)?;
+    let m_table = Table::new(M_STATES, &header.m_freq, &M_EXTRA_BITS, &M_BASE)?;
+    let d_table = Table::new(D_STATES, &header.d_freq, &d_extra, &d_base)?;
+
+    let mut input = BitReader::new(&payload[header.n_literal_payload_bytes..], header.lmd_bits)?;
+    let (mut l_state, mut m_state, mut d_state) = (
+        u32::from(header.l_state),
+        u32::from(header.m_state),
+        u32::from(header.d_state),
+    );
+    let mut literals = literals.as_slice();
+    let mut distance = 0;
+    for _ in 0..header.n_matches {
+        input.flush()?;
+        let l = l_table.decode(&mut l_state, &mut input)? as usize;
+        let m = m_table.decode(&mut m_state, &mut input)? as usize;
+        input.flush()?;
+        let d = d_table.decode(&mut d_state, &mut input)? as usize;
+        if d != 0 {
+            distance = d;
+        }
+
+        if l > literals.len() || out.len() + l + m > end {
+            return Err(corrupt("match runs past the end of the block"));
+        }
+        out.extend_from_slice(&literals[..l]);
+        literals = &literals[l..];
+        if m > 0 {
+            copy_match(out, distance, m)?;
+        }
+    }
+
+    if out.len() != end {
+        return Err(corrupt("block is shorter than its size"));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw_block(data: &[u8]) -> Vec<u8> {
+        let mut block = b"bvx-".to_vec();
+        block.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        block.extend_from_slice(data);
+        block
+    }
+
+    fn lzvn_block(n_raw: usize, payload: &[u8]) -> Vec<u8> {
+        let mut block = b"bvxn".to_vec();
+        block.extend_from_slice(&(n_raw as u32).to_le_bytes());
+        block.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        block.extend_from_slice(payload);
+        block
+    }
+
+    const EOS: [u8; 8] = [0x06, 0, 0, 0, 0, 0, 0, 0];
+
+    #[test]
+    fn test_raw_and_lzvn_blocks() {
+        let mut payload = vec![0xe0, 4];
+        payload.extend_from_slice(b"0123456789abcdefghij");
+        // lrg_d: 1 literal, 4 bytes from 20 back
+        payload.extend_from_slice(&[0x4f, 20, 0, b'X']);
+        // pre_d: 1 literal, 3 bytes from the same distance
+        payload.extend_from_slice(&[0x46, b'Y']);
+        // med_d: 5 bytes from 10 back
+        payload.extend_from_slice(&[0xa0, (10 << 2) | 2, 0]);
+        // sml_m, nop, lrg_m
+        payload.extend_from_slice(&[0xf2, 0x0e, 0xf0, 1]);
+        // sml_l then sml_d reaching into the raw block
+        payload.extend_from_slice(&[0xe1, b'Z', 0x08, 58]);
+        payload.extend_from_slice(&EOS);
+
+        let mut stream = raw_block(b"hello");
+        stream.extend(lzvn_block(58, &payload));
+        stream.extend_from_slice(b"bvx$");
+
+        let out = decompress(&stream, 1024).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "hello0123456789abcdefghijX1234Y678jX1234Y678jX1234Y678jX12Zello"
+        );
+    }
+
+    #[test]
+    fn test_rejects_bad_streams() {
+        // Output past the limit
+        let mut stream = raw_block(b"hello");
+        stream.extend_from_slice(b"bvx$");
+        assert!(decompress(&stream, 4).is_err());
+
+        // Match before the start of the output
+        let mut payload = vec![0xe1, b'a', 0x00, 2];
+        payload.extend_from_slice(&EOS);
+        let mut stream = lzvn_block(4, &payload);
+        stream.extend_from_slice(b"bvx$");
+        assert!(decompress(&stream, 1024).is_err());
+
+        // Missing end-of-stream marker
+        assert!(decompress(&raw_block(b"hello"), 1024).is_err());
+        assert!(decompress(b"junk", 1024).is_err());
+    }
+
+    /// Frequency code for `value`, as `(bits, length)`.
+    fn encode_freq(value: u16) -> (u32, u32) {
+        match value {
+            0 => (0b00, 2),
+            1 => (0b10, 2),
+            2 => (0b001, 3),
+            3 => (0b101, 3),
+            4..=7 => (0b00011 | (u32::from(value - 4) << 3), 5),
+            8..=23 => (0b0111 | (u32::from(value - 8) << 4), 8),
+            _ => (0b1111 | (u32::from(value - 24) << 4), 14),
+        }
+    }
+
+    /// A block decoding to "ababaaab" followed by a 3-byte match at
+    /// distance 7, whose low distance bit comes from the LMD stream.
+    fn fse_header() -> BlockHeader {
+        let mut header = BlockHeader::empty();
+        header.n_raw_bytes = 11;
+        header.n_literals = 8;
+        header.n_matches = 1;
+        header.n_literal_payload_bytes = 8;
+        header.n_lmd_payload_bytes = 8;
+        header.literal_bits = -4;
+        header.literal_state = [0, 600, 5, 1023];
+        header.lmd_bits = -7;
+        header.l_freq[8] = 64;
+        header.m_freq[3] = 64;
+        // Symbol 5 is distance 6 plus one extra bit
+        header.d_freq[5] = 256;
+        header.literal_freq[usize::from(b'a')] = 512;
+        header.literal_freq[usize::from(b'b')] = 512;
+        header
+    }
+
+    fn fse_payload() -> Vec<u8> {
+        let mut payload = vec![0; 8];
+        payload.extend_from_slice(&[0, 0, 0, 0, 0, 0, 0, 1]);
+        payload
+    }
+
+    fn v2_block(header: &mut BlockHeader) -> Vec<u8> {
+        let mut freq_bytes = Vec::new();
+        let (mut accum, mut nbits) = (0u64, 0);
+        for freq in header.freqs_mut().map(|f| *f) {
+            let (bits, len) = encode_freq(freq);
+            accum |= u64::from(bits) << nbits;
+            nbits += len;
+            while nbits >= 8 {
+                freq_bytes.push(accum as u8);
+                accum >>= 8;
+                nbits -= 8;
+            }
+        }
+        if nbits > 0 {
+            freq_bytes.push(accum as u8);
+        }
+
+        let h = &*header;
+        let v0 = h.n_literals as u64
+            | (h.n_literal_payload_bytes as u64) << 20
+            | (h.n_matches as u64) << 40
+            | ((h.literal_bits + 7) as u64) << 60;
+        let v1 = h
+            .literal_state
+            .iter()
+            .enumerate()
+            .fold(0u64, |v, (i, &s)| v | u64::from(s) << (10 * i))
+            | (h.n_lmd_payload_bytes as u64) << 40
+            | ((h.lmd_bits + 7) as u64) << 60;
+        let v2 = (V2_HEADER_LEN + freq_bytes.len()) as u64
+            | u64::from(h.l_state) << 32
+            | u64::from(h.m_state) << 42
+            | u64::from(h.d_state) << 52;
+
+        let mut block = b"bvx2".to_vec();
+        block.extend_from_slice(&(h.n_raw_bytes as u32).to_le_bytes());
+        for v in [v0, v1, v2] {
+            block.extend_from_slice(&v.to_le_bytes());
+        }
+        block.extend(freq_bytes);
+        block
+    }
+
+    fn v1_block(header: &mut BlockHeader) -> Vec<u8> {
+        let h = &*header;
+        let mut block = b"bvx1".to_vec();
+        for n in [
+            h.n_raw_bytes,
+            h.n_literal_payload_bytes + h.n_lmd_payload_bytes,
+            h.n_literals,
+            h.n_matches,
+            h.n_literal_payload_bytes,
+            h.n_lmd_payload_bytes,
+        ] {
+            block.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        block.extend_from_slice(&h.literal_bits.to_le_bytes());
+        for s in h.literal_state {
+            block.extend_from_slice(&s.to_le_bytes());
+        }
+        block.extend_from_slice(&h.lmd_bits.to_le_bytes());
+        for s in [h.l_state, h.m_state, h.d_state] {
+            block.extend_from_slice(&s.to_le_bytes());
+        }
+        for freq in header.freqs_mut().map(|f| *f) {
+            block.extend_from_slice(&freq.to_le_bytes());
+        }
+        block.extend_from_slice(&[0, 0]);
+        assert_eq!(block.len(), V1_HEADER_LEN);
+        block
+    }
+
+    #[test]
+    fn test_fse_blocks() {
+        for build in [v1_block, v2_block] {
+            let mut stream = build(&mut fse_header());
+            stream.extend(fse_payload());
+            stream.extend_from_slice(b"bvx$");
+            let out = decompress(&stream, 1024).unwrap();
+            assert_eq!(out, b"ababaaabbab");
+        }
+    }
+
+    #[test]
+    fn test_rejects_bad_fse_blocks() {
+        // Frequencies summing past the state count
+        let mut header = fse_header();
+        header.literal_freq[usize::from(b'c')] = 1;
+        let mut stream = v2_block(&mut header);
+        stream.extend(fse_payload());
+        stream.extend_from_slice(b"bvx$");
+        assert!(decompress(&stream, 1024).is_err());
+
+        // A literal state with no symbol behind it
+        let mut header = fse_header();
+        header.literal_freq[usize::from(b'b')] = 0;
+        let mut stream = v2_block(&mut header);
+        stream.extend(fse_payload());
+        stream.extend_from_slice(b"bvx$");
+        assert!(decompress(&stream, 1024).is_err());
+
+        // Block larger than the limit
+        let mut stream = v2_block(&mut fse_header());
+        stream.extend(fse_payload());
+        stream.extend_from_slice(b"bvx$");
+        assert!(decompress(&stream, 10).is_err());
+    }
+}
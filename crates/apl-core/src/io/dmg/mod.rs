@@ -0,0 +1,477 @@
+//! DMG handling.
+//!
+//! [`extract`] reads UDIF images directly and copies the HFS+ or APFS volume
+//! inside out without mounting anything. [`attach`]/[`detach`] wrap
+//! `hdiutil` and remain the fallback for images the native reader can't
+//! decode (encrypted or compressed volumes).
+
+pub mod apfs;
+pub mod hfs;
+mod lzfse;
+pub mod udif;
+
+use super::extract::{ExtractError, ExtractGuard, ExtractPolicy};
+
+use anyhow::{Context, Result, bail};
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// Random access to a byte range, used by the filesystem walkers so they can
+/// read from a decoded UDIF partition or a plain image alike.
+pub trait ReadAt {
+    /// Fill `buf` with the bytes starting at `offset`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the range extends past the end of the source or
+    /// the underlying read fails.
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()>;
+
+    /// Total size of the source in bytes.
+    fn size(&self) -> u64;
+}
+
+impl ReadAt for &[u8] {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let start = usize::try_from(offset).unwrap_or(usize::MAX);
+        let src = start
+            .checked_add(buf.len())
+            .and_then(|end| self.get(start..end))
+            .ok_or_else(|| io::Error::from(io::ErrorKind::UnexpectedEof))?;
+        buf.copy_from_slice(src);
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.len() as u64
+    }
+}
+
+#[cfg(unix)]
+impl ReadAt for File {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(self, buf, offset)
+    }
+
+    fn size(&self) -> u64 {
+        self.metadata().map_or(0, |m| m.len())
+    }
+}
+
+/// A window into another [`ReadAt`] source, e.g. one GPT partition.
+#[derive(Debug)]
+pub struct Slice<'a, R: ReadAt> {
+    inner: &'a R,
+    offset: u64,
+    len: u64,
+}
+
+impl<'a, R: ReadAt> Slice<'a, R> {
+    /// Expose `len` bytes of `inner` starting at `offset`.
+    pub fn new(inner: &'a R, offset: u64, len: u64) -> Self {
+        Self { inner, offset, len }
+    }
+}
+
+impl<R: ReadAt> ReadAt for Slice<'_, R> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let end = offset.checked_add(buf.len() as u64);
+        let start = self.offset.checked_add(offset);
+        match (end, start) {
+            (Some(end), Some(start)) if end <= self.len => self.inner.read_at(start, buf),
+            _ => Err(io::ErrorKind::UnexpectedEof.into()),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        self.len
+    }
+}
+
+/// Copy the contents of a DMG into `dest` without mounting it.
+///
+/// Compressed UDIF images are decoded in-process; images without a `koly`
+/// trailer are read as raw disk images. The first HFS+ or APFS volume found
+/// (either directly or inside a GPT partition) is extracted, checked
+/// against the [global extraction policy](ExtractPolicy::global) like any
+/// other archive.
+///
+/// # Errors
+///
+/// Returns an error if the image uses a feature the native reader doesn't
+/// support (encryption, compressed files) or contains no
+/// recognisable volume. Callers should fall back to [`attach`].
+pub fn extract(dmg_path: &Path, dest: &Path) -> Result<()> {
+    if !dmg_path.exists() {
+        bail!("DMG file not found: {}", dmg_path.display());
+    }
+    tracing::debug!("Extracting DMG natively: {}", dmg_path.display());
+    let mut guard = ExtractGuard::new(
+        *ExtractPolicy::global(),
+        fs::metadata(dmg_path).ok().map(|m| m.len()),
+    );
+
+    if let Some(image) = udif::UdifImage::open(dmg_path)? {
+        for (index, table) in image.tables().iter().enumerate() {
+            let partition = image.partition(index);
+            if extract_volume(&partition, dest, &mut guard)? {
+                tracing::debug!("Extracted DMG partition '{}'", table.name);
+                return Ok(());
+            }
+        }
+        bail!("No HFS+ or APFS volume found in {}", dmg_path.display());
+    }
+
+    let file = File::open(dmg_path)
+        .with_context(|| format!("Failed to open DMG {}", dmg_path.display()))?;
+    extract_raw(&file, dest, &mut guard)?;
+    Ok(())
+}
+
+#[cfg(unix)]
+fn extract_raw(file: &File, dest: &Path, guard: &mut ExtractGuard) -> Result<()> {
+    if extract_volume(file, dest, guard)? {
+        return Ok(());
+    }
+    for (start, len) in gpt_partitions(file)? {
+        if extract_volume(&Slice::new(file, start, len), dest, guard)? {
+            return Ok(());
+        }
+    }
+    bail!("No HFS+ or APFS volume found in raw disk image");
+}
+
+#[cfg(not(unix))]
+fn extract_raw(_file: &File, _dest: &Path, _guard: &mut ExtractGuard) -> Result<()> {
+    bail!("Raw disk images are only supported on Unix");
+}
+
+/// Extract `source` if it holds a filesystem we understand.
+/// Returns `Ok(false)` if it doesn't look like one.
+fn extract_volume<R: ReadAt>(source: &R, dest: &Path, guard: &mut ExtractGuard) -> Result<bool> {
+    if hfs::probe(source) {
+        hfs::extract(source, dest, guard)?;
+    } else if apfs::probe(source) {
+        apfs::extract(source, dest, guard)?;
+    } else {
+        return Ok(false);
+    }
+    Ok(true)
+}
+
+/// Reject a catalog name that isn't a single path component. The guard
+/// catches `..`, but a `/` or NUL inside one name would change how many
+/// components the joined path has.
+fn check_name(name: &str) -> Result<(), ExtractError> {
+    if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\0']) {
+        return Err(ExtractError::Archive(format!(
+            "Invalid file name {name:?} in volume"
+        )));
+    }
+    Ok(())
+}
+
+/// Create a file at `path` (already resolved by [`ExtractGuard::entry`])
+/// without following anything left there.
+fn create_file(path: &Path) -> io::Result<File> {
+    if fs::symlink_metadata(path).is_ok_and(|m| !m.is_dir()) {
+        fs::remove_file(path)?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.custom_flags(libc::O_NOFOLLOW);
+    }
+    options.open(path)
+}
+
+/// Create a symlink at `path` (already resolved by [`ExtractGuard::entry`])
+/// once the guard has accepted `target`.
+fn create_symlink(
+    guard: &ExtractGuard,
+    dest: &Path,
+    entry: &Path,
+    path: &Path,
+    target: &str,
+) -> Result<(), ExtractError> {
+    guard.symlink(dest, entry, path, Path::new(target))?;
+    if fs::symlink_metadata(path).is_ok_and(|m| !m.is_dir()) {
+        fs::remove_file(path)?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    #[cfg(unix)]
+    std::os::unix::fs::symlink(target, path)?;
+    Ok(())
+}
+
+/// Byte ranges of the used entries in a GPT partition table.
+fn gpt_partitions<R: ReadAt>(source: &R) -> Result<Vec<(u64, u64)>> {
+    const SECTOR: u64 = 512;
+    let mut header = [0u8; 92];
+    if source.read_at(SECTOR, &mut header).is_err() || &header[0..8] != b"EFI PART" {
+        return Ok(Vec::new());
+    }
+    let le_u64 =
+        |b: &[u8], o: usize| u64::from_le_bytes(b[o..o + 8].try_into().unwrap_or_default());
+    let le_u32 =
+        |b: &[u8], o: usize| u32::from_le_bytes(b[o..o + 4].try_into().unwrap_or_default());
+
+    let entries_lba = le_u64(&header, 72);
+    let count = le_u32(&header, 80).min(256);
+    let entry_size = u64::from(le_u32(&header, 84));
+    if entry_size < 128 {
+        bail!("Invalid GPT entry size {entry_size}");
+    }
+
+    let mut parts = Vec::new();
+    let mut entry = vec![0u8; 128];
+    for i in 0..u64::from(count) {
+        let Some(offset) = entries_lba
+            .checked_mul(SECTOR)
+            .and_then(|o| o.checked_add(i * entry_size))
+        else {
+            bail!("Invalid GPT entry offset");
+        };
+        source.read_at(offset, &mut entry)?;
+        if entry[0..16].iter().all(|b| *b == 0) {
+            continue;
+        }
+        let (first, last) = (le_u64(&entry, 32), le_u64(&entry, 40));
+        let range = last
+            .checked_sub(first)
+            .and_then(|n| n.checked_add(1)?.checked_mul(SECTOR))
+            .zip(first.checked_mul(SECTOR));
+        if let Some((len, start)) = range {
+            parts.push((start, len));
+        }
+    }
+    Ok(parts)
+}
+
+/// Represents a mounted DMG volume. Dropping this struct will detach the volume.
+#[derive(Debug)]
+pub struct MountPoint {
+    /// Filesystem path where the DMG is mounted (e.g. `/Volumes/MyApp`).
+    pub path: PathBuf,
+}
+
+impl Drop for MountPoint {
+    fn drop(&mut self) {
+        let _ = detach(&self.path);
+    }
+}
+
+/// Attach a DMG file and return its [`MountPoint`].
+///
+/// The volume is mounted read-only and will not open in Finder.
+///
+/// # Timeout
+///
+/// Gives up after `timeouts.dmg_mount` seconds (30 by default) to prevent
+/// hanging on interactive DMGs.
+///
+/// # Errors
+///
+/// Returns an error if the DMG file does not exist, `hdiutil` fails or
+/// times out, or the mount point cannot be parsed from the command output.
+/// # Panics
+/// Panics if the child process `stdout` cannot be captured (should never happen).
+pub fn attach(dmg_path: &Path) -> Result<MountPoint> {
+    if !dmg_path.exists() {
+        bail!("DMG file not found: {}", dmg_path.display());
+    }
+
+    tracing::debug!("Attaching DMG: {}", dmg_path.display());
+
+    // Spawn with comprehensive flags
+    let mut child = Command::new("hdiutil")
+        .arg("attach")
+        .arg("-nobrowse") // Don't open in Finder
+        .arg("-readonly") // Mount read-only
+        .arg("-noverify") // Skip image verification (faster)
+        .arg("-noautoopen") // Don't auto-open volumes
+        .arg("-quiet") // Suppress verbose output
+        .arg(dmg_path)
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .context("Failed to spawn hdiutil")?;
+
+    // Wait with timeout
+    let timeout_secs = crate::config::Config::global().timeouts.dmg_mount;
+    let timeout = Duration::from_secs(timeout_secs);
+    let result = wait_timeout::ChildExt::wait_timeout(&mut child, timeout)
+        .context("Failed to wait for hdiutil")?;
+
+    let output = if let Some(status) = result {
+        // Process completed within timeout
+        let stdout = {
+            use std::io::Read;
+            let mut buf = Vec::new();
+            child.stdout.take().unwrap().read_to_end(&mut buf)?;
+            buf
+        };
+        let stderr = {
+            use std::io::Read;
+            let mut buf = Vec::new();
+            child.stderr.take().unwrap().read_to_end(&mut buf)?;
+            buf
+        };
+
+        std::process::Output {
+            status,
+            stdout,
+            stderr,
+        }
+    } else {
+        // Timeout - kill the process
+        let _ = child.kill();
+        let _ = child.wait();
+        bail!(
+            "hdiutil attach timed out after {timeout_secs}s. This DMG may require user interaction (EULA acceptance). \
+             Try manually opening: open '{}'",
+            dmg_path.display()
+        );
+    };
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        bail!(
+            "hdiutil attach failed: {stderr}\n\
+             This may indicate:\n\
+             1. DMG requires user interaction (EULA)\n\
+             2. DMG is corrupted or incompatible\n\
+             3. Insufficient disk space\n\
+             4. Another process is using the file"
+        );
+    }
+
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    tracing::debug!("hdiutil output: {}", stdout);
+
+    // Parse mount point - look for /Volumes/...
+    for line in stdout.lines() {
+        if let Some(idx) = line.find("/Volumes/") {
+            let mount_str = line[idx..].trim();
+            let path = PathBuf::from(mount_str);
+
+            if path.exists() {
+                tracing::info!("Mounted DMG at: {}", path.display());
+                return Ok(MountPoint { path });
+            }
+        }
+    }
+
+    bail!("Could not find mount point in hdiutil output:\n{stdout}");
+}
+
+/// Detach a mounted volume with up to 5 retries.
+///
+/// Uses `hdiutil detach -force` to ensure the volume is unmounted.
+///
+/// # Errors
+///
+/// Returns an error if all 5 detach attempts fail.
+pub fn detach(mount_point: &Path) -> Result<()> {
+    tracing::debug!("Detaching volume: {}", mount_point.display());
+
+    for attempt in 1..=5 {
+        let status = Command::new("hdiutil")
+            .arg("detach")
+            .arg(mount_point)
+            .arg("-force")
+            .arg("-quiet")
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .status();
+
+        match status {
+            Ok(s) if s.success() => {
+                tracing::info!("Successfully detached {}", mount_point.display());
+                return Ok(());
+            }
+            _ => {
+                if attempt < 5 {
+                    tracing::warn!("Detach attempt {} failed, retrying...", attempt);
+                    std::thread::sleep(Duration::from_millis(500));
+                }
+            }
+        }
+    }
+
+    bail!(
+        "Failed to detach {} after 5 attempts",
+        mount_point.display()
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_extract_udif_hfs() {
+        let volume = hfs::tests::build_hfs(&[
+            ("Demo.app", None, 0o040_755),
+            ("Demo.app/Contents", None, 0o040_755),
+            ("Demo.app/Contents/demo", Some(b"payload"), 0o100_755),
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let dmg = dir.path().join("demo.dmg");
+        std::fs::write(&dmg, udif::tests::build_udif(&volume, 8)).unwrap();
+
+        let dest = dir.path().join("out");
+        extract(&dmg, &dest).unwrap();
+        assert_eq!(
+            std::fs::read(dest.join("Demo.app/Contents/demo")).unwrap(),
+            b"payload"
+        );
+    }
+
+    #[test]
+    fn test_extract_udif_apfs() {
+        let volume = apfs::tests::build_apfs(b"apfs payload");
+        let dir = tempfile::tempdir().unwrap();
+        let dmg = dir.path().join("demo.dmg");
+        std::fs::write(&dmg, udif::tests::build_udif(&volume, 8)).unwrap();
+
+        let dest = dir.path().join("out");
+        extract(&dmg, &dest).unwrap();
+        assert_eq!(
+            std::fs::read(dest.join("Demo.app/Contents/demo")).unwrap(),
+            b"apfs payload"
+        );
+    }
+
+    #[test]
+    fn test_extract_raw_image_without_volume() {
+        let dir = tempfile::tempdir().unwrap();
+        let dmg = dir.path().join("blank.dmg");
+        std::fs::write(&dmg, vec![0u8; 64 * 1024]).unwrap();
+        assert!(extract(&dmg, &dir.path().join("out")).is_err());
+    }
+
+    #[test]
+    fn test_attach_nonexistent_dmg() {
+        let result = attach(Path::new("/tmp/nonexistent.dmg"));
+        assert!(result.is_err());
+        let err_msg = result.unwrap_err().to_string();
+        assert!(err_msg.contains("not found"));
+    }
+
+    #[test]
+    fn test_detach_nonexistent_volume() {
+        let result = detach(Path::new("/Volumes/NonexistentVolume"));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,571 @@
+//! UDIF (Universal Disk Image Format) reader.
+//!
+//! A compressed `.dmg` is a data fork of compressed chunks followed by an
+//! XML property list and a 512-byte `koly` trailer. The plist's `blkx`
+//! array holds one `mish` block table per partition, mapping sector ranges
+//! to chunks in the data fork. [`PartitionReader`] exposes a decoded
+//! partition as random-access bytes without writing it to disk.
+
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+use anyhow::{Context, Result, bail};
+use base64::Engine;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+use super::{ReadAt, lzfse};
+
+const KOLY_MAGIC: &[u8; 4] = b"koly";
+const MISH_MAGIC: &[u8; 4] = b"mish";
+const KOLY_LEN: u64 = 512;
+const MISH_HEADER_LEN: usize = 0xCC;
+const CHUNK_LEN: usize = 40;
+/// Upper bound on the XML block map; real ones are a few hundred KiB.
+const MAX_XML_LEN: u64 = 64 * 1024 * 1024;
+/// Upper bound on the decoded size of one chunk; `hdiutil` writes 1 MiB.
+const MAX_CHUNK_SECTORS: u64 = 64 * 1024 * 1024 / SECTOR_SIZE;
+
+/// Bytes per UDIF sector.
+pub const SECTOR_SIZE: u64 = 512;
+
+/// Compression type of a block chunk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkKind {
+    /// Sectors that read as zeros (also used for "ignored" ranges).
+    Zero,
+    /// Stored uncompressed.
+    Raw,
+    /// Apple Data Compression.
+    Adc,
+    /// zlib stream.
+    Zlib,
+    /// bzip2 stream.
+    Bzip2,
+    /// LZFSE stream.
+    Lzfse,
+    /// LZMA/xz stream.
+    Lzma,
+}
+
+impl ChunkKind {
+    fn from_raw(kind: u32) -> Option<Self> {
+        match kind {
+            0x0000_0000 | 0x0000_0002 => Some(Self::Zero),
+            0x0000_0001 => Some(Self::Raw),
+            0x8000_0004 => Some(Self::Adc),
+            0x8000_0005 => Some(Self::Zlib),
+            0x8000_0006 => Some(Self::Bzip2),
+            0x8000_0007 => Some(Self::Lzfse),
+            0x8000_0008 => Some(Self::Lzma),
+            // Comments (0x7ffffffe) and the terminator (0xffffffff) carry no data.
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Chunk {
+    kind: ChunkKind,
+    /// First sector, relative to the start of the partition.
+    sector: u64,
+    sectors: u64,
+    /// Absolute offset of the compressed bytes in the image file.
+    offset: u64,
+    length: u64,
+}
+
+/// One partition described by a `blkx` entry.
+#[derive(Debug, Clone)]
+pub struct BlockTable {
+    /// Partition name from the plist (e.g. `disk image (Apple_HFS : 2)`).
+    pub name: String,
+    /// Number of 512-byte sectors in the partition.
+    pub sector_count: u64,
+    chunks: Vec<Chunk>,
+}
+
+/// An opened UDIF image.
+#[derive(Debug)]
+pub struct UdifImage {
+    file: RefCell<File>,
+    tables: Vec<BlockTable>,
+}
+
+impl UdifImage {
+    /// Open an image and parse its `koly` trailer and block tables.
+    ///
+    /// Returns `Ok(None)` if the file has no `koly` trailer (a raw image).
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the file cannot be read or the trailer/plist is
+    /// malformed.
+    pub fn open(path: &Path) -> Result<Option<Self>> {
+        let mut file =
+            File::open(path).with_context(|| format!("Failed to open DMG {}", path.display()))?;
+        let len = file.metadata()?.len();
+        if len < KOLY_LEN {
+            return Ok(None);
+        }
+
+        let mut koly = [0u8; KOLY_LEN as usize];
+        file.seek(SeekFrom::Start(len - KOLY_LEN))?;
+        file.read_exact(&mut koly)?;
+        if &koly[0..4] != KOLY_MAGIC {
+            return Ok(None);
+        }
+
+        let data_fork_offset = be_u64(&koly, 0x18);
+        let xml_offset = be_u64(&koly, 0xD8);
+        let xml_length = be_u64(&koly, 0xE0);
+        if xml_length == 0 {
+            bail!("DMG has no XML block map (legacy resource-fork images are not supported)");
+        }
+        if xml_length > MAX_XML_LEN || xml_offset.checked_add(xml_length).is_none_or(|e| e > len) {
+            bail!("DMG XML block map is out of bounds");
+        }
+
+        let mut xml = vec![0u8; xml_length as usize];
+        file.seek(SeekFrom::Start(xml_offset))?;
+        file.read_exact(&mut xml)?;
+
+        let tables = parse_plist(&String::from_utf8_lossy(&xml))?
+            .into_iter()
+            .map(|(name, mish)| parse_mish(name, &mish, data_fork_offset, len))
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Some(Self {
+            file: RefCell::new(file),
+            tables,
+        }))
+    }
+
+    /// Partitions in the image, in plist order.
+    pub fn tables(&self) -> &[BlockTable] {
+        &self.tables
+    }
+
+    /// Random-access reader over the decoded contents of one partition.
+    pub fn partition(&self, index: usize) -> PartitionReader<'_> {
+        PartitionReader {
+            image: self,
+            table: &self.tables[index],
+            cache: RefCell::new(None),
+        }
+    }
+
+    fn read_raw(&self, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+        let mut file = self.file.borrow_mut();
+        file.seek(SeekFrom::Start(offset))?;
+        let mut buf = vec![0u8; len as usize];
+        file.read_exact(&mut buf)?;
+        Ok(buf)
+    }
+}
+
+/// Decoded view of one UDIF partition.
+///
+/// Chunks are decompressed on demand; the most recently used chunk is cached
+/// since filesystem walkers tend to read neighbouring blocks.
+#[derive(Debug)]
+pub struct PartitionReader<'a> {
+    image: &'a UdifImage,
+    table: &'a BlockTable,
+    cache: RefCell<Option<(usize, Vec<u8>)>>,
+}
+
+impl PartitionReader<'_> {
+    fn decode_chunk(&self, index: usize) -> io::Result<Vec<u8>> {
+        let chunk = &self.table.chunks[index];
+        let size = (chunk.sectors * SECTOR_SIZE) as usize;
+
+        let mut out = match chunk.kind {
+            ChunkKind::Zero => return Ok(vec![0u8; size]),
+            ChunkKind::Raw => self.image.read_raw(chunk.offset, chunk.length)?,
+            ChunkKind::Adc => {
+                adc_decompress(&self.image.read_raw(chunk.offset, chunk.length)?, size)?
+            }
+            ChunkKind::Zlib => {
+                let raw = self.image.read_raw(chunk.offset, chunk.length)?;
+                read_bounded(flate2::read::ZlibDecoder::new(raw.as_slice()), size)?
+            }
+            ChunkKind::Bzip2 => {
+                let raw = self.image.read_raw(chunk.offset, chunk.length)?;
+                read_bounded(bzip2::read::BzDecoder::new(raw.as_slice()), size)?
+            }
+            ChunkKind::Lzma => {
+                let raw = self.image.read_raw(chunk.offset, chunk.length)?;
+                read_bounded(xz2::read::XzDecoder::new(raw.as_slice()), size)?
+            }
+            ChunkKind::Lzfse => {
+                lzfse::decompress(&self.image.read_raw(chunk.offset, chunk.length)?, size)?
+            }
+        };
+        out.resize(size, 0);
+        Ok(out)
+    }
+}
+
+/// Decode at most `size` bytes; chunks never expand past their sector count.
+fn read_bounded(reader: impl Read, size: usize) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(size);
+    reader.take(size as u64).read_to_end(&mut out)?;
+    Ok(out)
+}
+
+impl ReadAt for PartitionReader<'_> {
+    fn read_at(&self, offset: u64, buf: &mut [u8]) -> io::Result<()> {
+        let mut pos = offset;
+        let mut filled = 0;
+
+        while filled < buf.len() {
+            let sector = pos / SECTOR_SIZE;
+            let index = self
+                .table
+                .chunks
+                .partition_point(|c| c.sector + c.sectors <= sector);
+            let Some(chunk) = self.table.chunks.get(index).filter(|c| c.sector <= sector) else {
+                return Err(io::Error::new(
+                    io::ErrorKind::UnexpectedEof,
+                    format!("Read past end of partition at offset {pos}"),
+                ));
+            };
+
+            let mut cache = self.cache.borrow_mut();
+            if cache.as_ref().is_none_or(|(i, _)| *i != index) {
+                *cache = Some((index, self.decode_chunk(index)?));
+            }
+            let data = &cache.as_ref().map_or(&[][..], |(_, d)| d.as_slice());
+
+            let start = (pos - chunk.sector * SECTOR_SIZE) as usize;
+            let n = (buf.len() - filled).min(data.len() - start);
+            buf[filled..filled + n].copy_from_slice(&data[start..start + n]);
+            filled += n;
+            pos += n as u64;
+        }
+        Ok(())
+    }
+
+    fn size(&self) -> u64 {
+        self.table.sector_count * SECTOR_SIZE
+    }
+}
+
+/// Extract `(Name, mish bytes)` pairs from the `blkx` array of the plist.
+fn parse_plist(xml: &str) -> Result<Vec<(String, Vec<u8>)>> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut tables = Vec::new();
+    let mut last_key = String::new();
+    let mut current_tag = Vec::new();
+    let mut name = String::new();
+    let mut data: Option<Vec<u8>> = None;
+    let mut in_blkx = false;
+    let mut depth_at_blkx = 0usize;
+    let mut depth = 0usize;
+
+    loop {
+        match reader.read_event().context("Invalid DMG plist")? {
+            Event::Start(e) => {
+                depth += 1;
+                current_tag = e.local_name().as_ref().to_vec();
+                if current_tag == b"array" && last_key == "blkx" {
+                    in_blkx = true;
+                    depth_at_blkx = depth;
+                }
+            }
+            Event::End(e) => {
+                if in_blkx && e.local_name().as_ref() == b"dict" && depth == depth_at_blkx + 1 {
+                    if let Some(mish) = data.take() {
+                        tables.push((std::mem::take(&mut name), mish));
+                    }
+                }
+                if in_blkx && depth == depth_at_blkx {
+                    in_blkx = false;
+                }
+                depth -= 1;
+                current_tag.clear();
+            }
+            Event::Text(t) => {
+                let text = t.unescape().context("Invalid DMG plist")?;
+                match current_tag.as_slice() {
+                    b"key" => last_key = text.into_owned(),
+                    b"string" if in_blkx && last_key == "Name" => name = text.into_owned(),
+                    b"data" if in_blkx && last_key == "Data" => {
+                        let compact: String = text.split_whitespace().collect();
+                        data = Some(
+                            base64::engine::general_purpose::STANDARD
+                                .decode(compact)
+                                .context("Invalid base64 in DMG plist")?,
+                        );
+                    }
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if tables.is_empty() {
+        bail!("DMG plist contains no blkx tables");
+    }
+    Ok(tables)
+}
+
+/// Parse a partition's block table, checking every chunk lies inside the
+/// partition and its compressed bytes inside the `len`-byte image.
+fn parse_mish(name: String, mish: &[u8], data_fork_offset: u64, len: u64) -> Result<BlockTable> {
+    if mish.len() < MISH_HEADER_LEN || &mish[0..4] != MISH_MAGIC {
+        bail!("Invalid mish block for partition '{name}'");
+    }
+    let sector_count = be_u64(mish, 0x10);
+    let table_data_offset = be_u64(mish, 0x18);
+    if sector_count.checked_mul(SECTOR_SIZE).is_none() {
+        bail!("Invalid sector count for partition '{name}'");
+    }
+    let chunk_count = be_u32(mish, 0xC8) as usize;
+
+    let mut chunks = Vec::with_capacity(chunk_count);
+    for i in 0..chunk_count {
+        let base = MISH_HEADER_LEN + i * CHUNK_LEN;
+        if base + CHUNK_LEN > mish.len() {
+            bail!("Truncated mish block for partition '{name}'");
+        }
+        let Some(kind) = ChunkKind::from_raw(be_u32(mish, base)) else {
+            continue;
+        };
+        let sector = be_u64(mish, base + 8);
+        let sectors = be_u64(mish, base + 16);
+        let length = be_u64(mish, base + 32);
+        let offset = data_fork_offset
+            .checked_add(table_data_offset)
+            .and_then(|o| o.checked_add(be_u64(mish, base + 24)));
+        let in_partition = sector
+            .checked_add(sectors)
+            .is_some_and(|end| end <= sector_count);
+        let in_image = kind == ChunkKind::Zero
+            || offset
+                .and_then(|o| o.checked_add(length))
+                .is_some_and(|end| end <= len);
+        if sectors > MAX_CHUNK_SECTORS || !in_partition || !in_image {
+            bail!("Invalid chunk {i} in mish block for partition '{name}'");
+        }
+        chunks.push(Chunk {
+            kind,
+            sector,
+            sectors,
+            offset: offset.unwrap_or_default(),
+            length,
+        });
+    }
+    chunks.sort_by_key(|c| c.sector);
+
+    Ok(BlockTable {
+        name,
+        sector_count,
+        chunks,
+    })
+}
+
+/// Decompress Apple Data Compression (ADC), a simple LZ77 variant.
+fn adc_decompress(input: &[u8], expected: usize) -> io::Result<Vec<u8>> {
+    let corrupt = || io::Error::new(io::ErrorKind::InvalidData, "Corrupt ADC chunk");
+    let mut out = Vec::with_capacity(expected);
+    let mut i = 0;
+
+    while i < input.len() && out.len() < expected {
+        let b = input[i];
+        if b & 0x80 != 0 {
+            let len = usize::from(b & 0x7f) + 1;
+            let lit = input.get(i + 1..i + 1 + len).ok_or_else(corrupt)?;
+            out.extend_from_slice(lit);
+            i += 1 + len;
+            continue;
+        }
+
+        let (len, dist) = if b & 0x40 != 0 {
+            let hi = *input.get(i + 1).ok_or_else(corrupt)?;
+            let lo = *input.get(i + 2).ok_or_else(corrupt)?;
+            i += 3;
+            (
+                usize::from(b & 0x3f) + 4,
+                (usize::from(hi) << 8 | usize::from(lo)) + 1,
+            )
+        } else {
+            let lo = *input.get(i + 1).ok_or_else(corrupt)?;
+            i += 2;
+            (
+                usize::from((b & 0x3f) >> 2) + 3,
+                (usize::from(b & 0x03) << 8 | usize::from(lo)) + 1,
+            )
+        };
+
+        if dist > out.len() {
+            return Err(corrupt());
+        }
+        for _ in 0..len {
+            out.push(out[out.len() - dist]);
+        }
+    }
+
+    Ok(out)
+}
+
+pub(super) fn be_u32(buf: &[u8], off: usize) -> u32 {
+    u32::from_be_bytes(buf[off..off + 4].try_into().unwrap_or_default())
+}
+
+pub(super) fn be_u64(buf: &[u8], off: usize) -> u64 {
+    u64::from_be_bytes(buf[off..off + 8].try_into().unwrap_or_default())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::io::Write;
+
+    /// Wrap a raw partition image in a UDIF container using zlib chunks of
+    /// `chunk_sectors` sectors each, with all-zero chunks stored as zero-fill.
+    pub(crate) fn build_udif(partition: &[u8], chunk_sectors: u64) -> Vec<u8> {
+        assert_eq!(partition.len() as u64 % SECTOR_SIZE, 0);
+        let sectors = partition.len() as u64 / SECTOR_SIZE;
+
+        let mut data_fork = Vec::new();
+        let mut chunks = Vec::new();
+        let mut sector = 0;
+        while sector < sectors {
+            let count = chunk_sectors.min(sectors - sector);
+            let bytes = &partition
+                [(sector * SECTOR_SIZE) as usize..((sector + count) * SECTOR_SIZE) as usize];
+            if bytes.iter().all(|b| *b == 0) {
+                chunks.push((0u32, sector, count, data_fork.len() as u64, 0u64));
+            } else {
+                let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
+                enc.write_all(bytes).unwrap();
+                let z = enc.finish().unwrap();
+                chunks.push((
+                    0x8000_0005,
+                    sector,
+                    count,
+                    data_fork.len() as u64,
+                    z.len() as u64,
+                ));
+                data_fork.extend_from_slice(&z);
+            }
+            sector += count;
+        }
+        chunks.push((0xffff_ffff, sectors, 0, data_fork.len() as u64, 0));
+
+        let mut mish = vec![0u8; MISH_HEADER_LEN];
+        mish[0..4].copy_from_slice(MISH_MAGIC);
+        mish[4..8].copy_from_slice(&1u32.to_be_bytes());
+        mish[0x10..0x18].copy_from_slice(&sectors.to_be_bytes());
+        mish[0xC8..0xCC].copy_from_slice(&(chunks.len() as u32).to_be_bytes());
+        for (kind, sector, count, off, len) in chunks {
+            mish.extend_from_slice(&kind.to_be_bytes());
+            mish.extend_from_slice(&0u32.to_be_bytes());
+            mish.extend_from_slice(&sector.to_be_bytes());
+            mish.extend_from_slice(&count.to_be_bytes());
+            mish.extend_from_slice(&off.to_be_bytes());
+            mish.extend_from_slice(&len.to_be_bytes());
+        }
+
+        let plist = format!(
+            "<?xml version=\"1.0\"?><plist version=\"1.0\"><dict>\
+             <key>resource-fork</key><dict><key>blkx</key><array><dict>\
+             <key>Attributes</key><string>0x0050</string>\
+             <key>Data</key><data>{}</data>\
+             <key>Name</key><string>disk image (Apple_HFS : 1)</string>\
+             </dict></array></dict></dict></plist>",
+            base64::engine::general_purpose::STANDARD.encode(&mish)
+        );
+
+        let mut out = data_fork;
+        let xml_offset = out.len() as u64;
+        out.extend_from_slice(plist.as_bytes());
+
+        let mut koly = vec![0u8; KOLY_LEN as usize];
+        koly[0..4].copy_from_slice(KOLY_MAGIC);
+        koly[4..8].copy_from_slice(&4u32.to_be_bytes());
+        koly[8..12].copy_from_slice(&512u32.to_be_bytes());
+        koly[0x20..0x28].copy_from_slice(&xml_offset.to_be_bytes());
+        koly[0xD8..0xE0].copy_from_slice(&xml_offset.to_be_bytes());
+        koly[0xE0..0xE8].copy_from_slice(&(plist.len() as u64).to_be_bytes());
+        koly[0x1EC..0x1F4].copy_from_slice(&sectors.to_be_bytes());
+        out.extend_from_slice(&koly);
+        out
+    }
+
+    #[test]
+    fn test_udif_roundtrip() {
+        let mut partition = vec![0u8; 16 * 512];
+        partition[1024..1028].copy_from_slice(b"H+\0\x04");
+        partition[5000] = 0xAB;
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.dmg");
+        std::fs::write(&path, build_udif(&partition, 4)).unwrap();
+
+        let image = UdifImage::open(&path).unwrap().unwrap();
+        assert_eq!(image.tables().len(), 1);
+        assert!(image.tables()[0].name.contains("Apple_HFS"));
+
+        let reader = image.partition(0);
+        assert_eq!(reader.size(), partition.len() as u64);
+
+        let mut buf = vec![0u8; partition.len()];
+        reader.read_at(0, &mut buf).unwrap();
+        assert_eq!(buf, partition);
+
+        // Unaligned read spanning a chunk boundary.
+        let mut small = [0u8; 8];
+        reader.read_at(2044, &mut small).unwrap();
+        assert_eq!(small, partition[2044..2052]);
+    }
+
+    #[test]
+    fn test_raw_file_has_no_koly() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("raw.img");
+        std::fs::write(&path, vec![0u8; 4096]).unwrap();
+        assert!(UdifImage::open(&path).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_rejects_out_of_bounds_tables() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.dmg");
+        let image = build_udif(&[1u8; 8 * 512], 4);
+        let koly = image.len() - KOLY_LEN as usize;
+
+        // XML offset that wraps around when the length is added
+        let mut bad = image.clone();
+        bad[koly + 0xD8..koly + 0xE0].copy_from_slice(&u64::MAX.to_be_bytes());
+        std::fs::write(&path, &bad).unwrap();
+        assert!(UdifImage::open(&path).is_err());
+
+        // Data fork offset that pushes every chunk past the end of the file
+        let mut bad = image;
+        bad[koly + 0x18..koly + 0x20].copy_from_slice(&(u64::MAX - 1).to_be_bytes());
+        std::fs::write(&path, &bad).unwrap();
+        assert!(UdifImage::open(&path).is_err());
+    }
+
+    #[test]
+    fn test_adc_decompress() {
+        // Literal "abc", then a 2-byte back-reference copying 3 bytes from distance 3.
+        let input = [0x82, b'a', b'b', b'c', 0x00, 0x02];
+        assert_eq!(adc_decompress(&input, 6).unwrap(), b"abcabc");
+
+        // 3-byte form: copy 4 bytes from distance 1.
+        let input = [0x80, b'z', 0x40, 0x00, 0x00];
+        assert_eq!(adc_decompress(&input, 5).unwrap(), b"zzzzz");
+    }
+}
@@ -27,6 +27,11 @@ pub enum DownloadError {
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
 
+    /// The archive could not be extracted, or an entry broke the
+    /// [`ExtractPolicy`](crate::io::extract::ExtractPolicy).
+    #[error(transparent)]
+    Extract(#[from] crate::io::extract::ExtractError),
+
     /// The SHA-256 hash of the downloaded file does not match the expected value.
     #[error("Hash mismatch: expected {expected}, got {actual}")]
     HashMismatch {
@@ -60,6 +65,9 @@ pub struct DownloadRequest<'a, R: Reporter + Clone + 'static> {
     pub reporter: &'a R,
     /// Optional directory to extract the archive into after downloading.
     pub extract_dest: Option<&'a Path>,
+    /// Format the package declares for the artifact. The content still has
+    /// the final say; see [`crate::io::extract::reconcile_format`].
+    pub format: Option<ArtifactFormat>,
 }
 
 impl<'a, R: Reporter + Clone + 'static> DownloadRequest<'a, R> {
@@ -85,6 +93,7 @@ impl<'a, R: Reporter + Clone + 'static> DownloadRequest<'a, R> {
             expected_hash,
             reporter,
             extract_dest: None,
+            format: None,
         }
     }
 
@@ -95,6 +104,13 @@ impl<'a, R: Reporter + Clone + 'static> DownloadRequest<'a, R> {
         self
     }
 
+    /// Declare the artifact's format. Without it the URL's extension is used
+    /// as the declaration.
+    pub fn with_format(mut self, format: ArtifactFormat) -> Self {
+        self.format = Some(format);
+        self
+    }
+
     /// Execute the download (and extraction if requested).
     ///
     /// Returns the hex-encoded SHA-256 hash of the downloaded file on success.
@@ -511,6 +527,43 @@ async fn download_chunked<R: Reporter + Clone + 'static>(
     Ok(actual_hash)
 }
 
+/// Fetch the first few KiB of `url` and identify its format from the bytes.
+///
+/// Asks for a byte range so large artifacts are not transferred in full;
+/// servers that ignore the range are cut off after [`SNIFF_LEN`] bytes.
+/// Returns `None` when the content is not recognised.
+///
+/// [`SNIFF_LEN`]: crate::io::extract::SNIFF_LEN
+///
+/// # Errors
+///
+/// Returns [`DownloadError::Http`] if the request fails or the server
+/// answers with an error status.
+pub async fn sniff_remote(
+    client: &Client,
+    url: &str,
+) -> Result<Option<ArtifactFormat>, DownloadError> {
+    let limit = crate::io::extract::SNIFF_LEN;
+    let response = client
+        .get(url)
+        .header(reqwest::header::USER_AGENT, crate::USER_AGENT)
+        .header(reqwest::header::RANGE, format!("bytes=0-{}", limit - 1))
+        .send()
+        .await?
+        .error_for_status()?;
+
+    let mut stream = response.bytes_stream();
+    let mut head = Vec::with_capacity(limit);
+    while head.len() < limit {
+        match stream.next().await {
+            Some(chunk) => head.extend_from_slice(&chunk?),
+            None => break,
+        }
+    }
+    head.truncate(limit);
+    Ok(crate::io::extract::sniff_bytes(&head, Path::new(url)))
+}
+
 /// Simultaneously downloads, caches, and extracts an archive via a streaming pipeline.
 ///
 /// The download stream is tee'd: one copy is written to `cache_dest` for
@@ -527,10 +580,6 @@ async fn download_chunked<R: Reporter + Clone + 'static>(
 pub async fn download_and_extract<R: Reporter + Clone + 'static>(
     req: DownloadRequest<'_, R>,
 ) -> Result<String, DownloadError> {
-    use async_compression::tokio::bufread::ZstdDecoder;
-    use tokio_tar::Archive;
-    use tokio_util::io::StreamReader;
-
     let client = req.client;
     let pkg_name = req.pkg_name;
     let version = req.version;
@@ -566,24 +615,49 @@ pub async fn download_and_extract<R: Reporter + Clone + 'static>(
         .error_for_status()?;
 
     let mut stream = response.bytes_stream();
-    let mut file = File::create(cache_dest).await?;
-    let mut hasher = Sha256::new();
-    let mut downloaded: u64 = 0;
-
-    // Channel for Pipelined Extraction
-    let (tx, rx) = tokio::sync::mpsc::channel::<Result<bytes::Bytes, std::io::Error>>(32);
-    let stream_reader = StreamReader::new(tokio_stream::wrappers::ReceiverStream::new(rx));
-
-    let format = crate::io::extract::detect_format(Path::new(url));
-    let is_gzip = format == ArtifactFormat::TarGz;
-    let is_zip = format == ArtifactFormat::Zip;
-    let is_pkg = format == ArtifactFormat::Pkg;
-    let is_raw = format == ArtifactFormat::Binary;
-
-    // Fast path for non-tar formats (zip/raw/pkg)
-    if is_zip || is_raw || is_pkg {
-        drop(tx);
-        let opts = SimpleDownloadOptions {
+    let file = File::create(cache_dest).await?;
+    let hasher = Sha256::new();
+
+    // Buffer the first few KiB so the format comes from the bytes
+    // themselves; mirrors and redirects don't always keep the extension.
+    let mut head = Vec::new();
+    let mut head_len = 0;
+    while head_len < crate::io::extract::SNIFF_LEN {
+        match stream.next().await {
+            Some(chunk) => {
+                let chunk = chunk?;
+                head_len += chunk.len();
+                head.push(chunk);
+            }
+            None => break,
+        }
+    }
+    let head_bytes: Vec<u8> = head.iter().flat_map(|c| c.iter().copied()).collect();
+    let declared = req
+        .format
+        .unwrap_or_else(|| crate::io::extract::format_from_extension(Path::new(url)));
+    let format = crate::io::extract::reconcile_format(
+        declared,
+        crate::io::extract::sniff_bytes(&head_bytes, Path::new(url)),
+    );
+    if format != declared && declared != ArtifactFormat::Binary {
+        reporter.warning(&format!(
+            "{pkg_name}: declared as {declared} but the download is {format}; extracting as {format}"
+        ));
+    }
+    let stream = futures::stream::iter(head.into_iter().map(Ok)).chain(stream);
+
+    // Only tarballs are extracted while downloading; everything else needs
+    // the complete file (zip/7z central directories, pkg TOC, ...).
+    if !matches!(
+        format,
+        ArtifactFormat::Tar
+            | ArtifactFormat::TarGz
+            | ArtifactFormat::TarZst
+            | ArtifactFormat::TarXz
+            | ArtifactFormat::TarBz2
+    ) {
+        let opts = DownloadOptions {
             pkg_name,
             version,
             total_size,
@@ -596,20 +670,67 @@ pub async fn download_and_extract<R: Reporter + Clone + 'static>(
         return run_simple_download(stream, file, hasher, opts).await;
     }
 
-    // Spawn Extractor Task
+    run_pipelined_download(
+        stream,
+        file,
+        hasher,
+        DownloadOptions {
+            pkg_name,
+            version,
+            total_size,
+            reporter,
+            expected_hash,
+            cache_dest,
+            extract_dest,
+            format,
+        },
+    )
+    .await
+}
+
+/// Stream a tarball to disk while a second task unpacks it from a channel.
+async fn run_pipelined_download<R: Reporter + Clone + 'static>(
+    mut stream: impl Unpin + futures::Stream<Item = reqwest::Result<bytes::Bytes>>,
+    mut file: File,
+    mut hasher: Sha256,
+    opts: DownloadOptions<'_, R>,
+) -> Result<String, DownloadError> {
+    let DownloadOptions {
+        pkg_name,
+        version,
+        total_size,
+        reporter,
+        expected_hash,
+        cache_dest,
+        extract_dest,
+        format,
+    } = opts;
+    let mut downloaded: u64 = 0;
+
+    // Channel for Pipelined Extraction
+    let (tx, rx) = tokio::sync::mpsc::channel::<Result<bytes::Bytes, std::io::Error>>(32);
+
+    // Spawn Extractor Task. Decoding and unpacking are blocking, so the
+    // extractor reads the channel from its own thread and every entry goes
+    // through the same ExtractPolicy as on-disk archives.
     let extract_dest_owned = extract_dest.to_path_buf();
-    let extractor_handle = tokio::spawn(async move {
-        if is_gzip {
-            use async_compression::tokio::bufread::GzipDecoder;
-            let decoder = GzipDecoder::new(stream_reader);
-            let mut archive = Archive::new(decoder);
-            archive.unpack(&extract_dest_owned).await?;
-        } else {
-            let decoder = ZstdDecoder::new(stream_reader);
-            let mut archive = Archive::new(decoder);
-            archive.unpack(&extract_dest_owned).await?;
-        }
-        Ok::<(), std::io::Error>(())
+    let compressed_size = Some(total_size);
+    let extractor_handle = tokio::task::spawn_blocking(move || {
+        use crate::io::extract::{ExtractGuard, ExtractPolicy, extract_tar};
+
+        let mut guard = ExtractGuard::new(*ExtractPolicy::global(), compressed_size);
+        let mut reader = ChannelReader::new(rx);
+        let decoder: Box<dyn std::io::Read + '_> = match format {
+            ArtifactFormat::TarGz => Box::new(flate2::read::MultiGzDecoder::new(&mut reader)),
+            ArtifactFormat::TarXz => Box::new(xz2::read::XzDecoder::new_multi_decoder(&mut reader)),
+            ArtifactFormat::TarBz2 => Box::new(bzip2::read::MultiBzDecoder::new(&mut reader)),
+            ArtifactFormat::TarZst => Box::new(zstd::stream::Decoder::new(&mut reader)?),
+            _ => Box::new(&mut reader),
+        };
+        extract_tar(decoder, &extract_dest_owned, &mut guard)?;
+        // Drain trailing padding so the sender never sees a closed channel.
+        std::io::copy(&mut reader, &mut std::io::sink())?;
+        Ok::<(), crate::io::extract::ExtractError>(())
     });
 
     while let Some(chunk_res) = stream.next().await {
@@ -621,9 +742,13 @@ pub async fn download_and_extract<R: Reporter + Clone + 'static>(
         reporter.downloading(pkg_name, version, downloaded, Some(total_size));
 
         if tx.send(Ok(chunk)).await.is_err() {
-            return Err(
-                std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Extractor died").into(),
-            );
+            // The extractor gave up early; report why rather than the broken pipe.
+            return match extractor_handle.await {
+                Ok(Err(e)) => Err(e.into()),
+                _ => Err(
+                    std::io::Error::new(std::io::ErrorKind::BrokenPipe, "Extractor died").into(),
+                ),
+            };
         }
     }
     drop(tx);
@@ -642,12 +767,12 @@ pub async fn download_and_extract<R: Reporter + Clone + 'static>(
 
     match extractor_handle.await {
         Ok(Ok(())) => Ok(actual_hash),
-        Ok(Err(e)) => Err(DownloadError::Io(e)),
+        Ok(Err(e)) => Err(e.into()),
         Err(e) => Err(DownloadError::Io(std::io::Error::other(e))),
     }
 }
 
-struct SimpleDownloadOptions<'a, R: Reporter> {
+struct DownloadOptions<'a, R: Reporter> {
     pkg_name: &'a PackageName,
     version: &'a Version,
     total_size: u64,
@@ -658,11 +783,11 @@ struct SimpleDownloadOptions<'a, R: Reporter> {
     format: ArtifactFormat,
 }
 
-async fn run_simple_download<R: Reporter>(
+async fn run_simple_download<R: Reporter + Clone + 'static>(
     mut stream: impl Unpin + futures::Stream<Item = reqwest::Result<bytes::Bytes>>,
     mut file: File,
     mut hasher: Sha256,
-    opts: SimpleDownloadOptions<'_, R>,
+    opts: DownloadOptions<'_, R>,
 ) -> Result<String, DownloadError> {
     let mut downloaded = 0;
     while let Some(chunk_res) = stream.next().await {
@@ -690,17 +815,23 @@ async fn run_simple_download<R: Reporter>(
         });
     }
 
-    // format is already imported from types
-
     match opts.format {
         ArtifactFormat::Zip => {
             let cache_path = opts.cache_dest.to_path_buf();
             let extract_path = opts.extract_dest.to_path_buf();
+            let reporter = opts.reporter.clone();
+            let pkg_name = opts.pkg_name.clone();
+            let version = opts.version.clone();
+            let total = Some(opts.total_size);
             tokio::task::spawn_blocking(move || {
-                let file = std::fs::File::open(&cache_path)?;
-                let mut archive = zip::ZipArchive::new(file)?;
-                archive.extract(&extract_path)?;
-                Ok::<(), std::io::Error>(())
+                crate::io::extract::extract_zip(
+                    &cache_path,
+                    &extract_path,
+                    &reporter,
+                    &pkg_name,
+                    &version,
+                    total,
+                )
             })
             .await
             .map_err(std::io::Error::other)??;
@@ -720,10 +851,58 @@ async fn run_simple_download<R: Reporter>(
         ArtifactFormat::Pkg => {
             let cache_path = opts.cache_dest.to_path_buf();
             let extract_path = opts.extract_dest.to_path_buf();
+            let reporter = opts.reporter.clone();
+            let pkg_name = opts.pkg_name.clone();
+            let version = opts.version.clone();
             tokio::task::spawn_blocking(move || {
-                crate::io::extract::extract_pkg(&cache_path, &extract_path)
-                    .map_err(std::io::Error::other)?;
-                Ok::<(), std::io::Error>(())
+                crate::io::extract::extract_pkg(
+                    &cache_path,
+                    &extract_path,
+                    &reporter,
+                    &pkg_name,
+                    &version,
+                )
+            })
+            .await
+            .map_err(std::io::Error::other)??;
+        }
+        ArtifactFormat::SevenZ => {
+            let cache_path = opts.cache_dest.to_path_buf();
+            let extract_path = opts.extract_dest.to_path_buf();
+            let reporter = opts.reporter.clone();
+            let pkg_name = opts.pkg_name.clone();
+            let version = opts.version.clone();
+            let total = Some(opts.total_size);
+            tokio::task::spawn_blocking(move || {
+                crate::io::extract::extract_7z(
+                    &cache_path,
+                    &extract_path,
+                    &reporter,
+                    &pkg_name,
+                    &version,
+                    total,
+                )
+            })
+            .await
+            .map_err(std::io::Error::other)??;
+        }
+        format @ (ArtifactFormat::Gz | ArtifactFormat::Xz | ArtifactFormat::Zst) => {
+            let cache_path = opts.cache_dest.to_path_buf();
+            let dest_path = opts.extract_dest.join(opts.pkg_name.as_str());
+            let reporter = opts.reporter.clone();
+            let pkg_name = opts.pkg_name.clone();
+            let version = opts.version.clone();
+            let total = Some(opts.total_size);
+            tokio::task::spawn_blocking(move || {
+                crate::io::extract::extract_compressed_binary(
+                    &cache_path,
+                    &dest_path,
+                    format,
+                    &reporter,
+                    &pkg_name,
+                    &version,
+                    total,
+                )
             })
             .await
             .map_err(std::io::Error::other)??;
@@ -733,3 +912,34 @@ async fn run_simple_download<R: Reporter>(
 
     Ok(actual_hash)
 }
+
+/// Blocking [`std::io::Read`] over the pipelined download channel, for
+/// decoders that have no async counterpart.
+struct ChannelReader {
+    rx: tokio::sync::mpsc::Receiver<Result<bytes::Bytes, std::io::Error>>,
+    buf: bytes::Bytes,
+}
+
+impl ChannelReader {
+    fn new(rx: tokio::sync::mpsc::Receiver<Result<bytes::Bytes, std::io::Error>>) -> Self {
+        Self {
+            rx,
+            buf: bytes::Bytes::new(),
+        }
+    }
+}
+
+impl std::io::Read for ChannelReader {
+    fn read(&mut self, out: &mut [u8]) -> std::io::Result<usize> {
+        while self.buf.is_empty() {
+            match self.rx.blocking_recv() {
+                Some(Ok(chunk)) => self.buf = chunk,
+                Some(Err(e)) => return Err(e),
+                None => return Ok(0),
+            }
+        }
+        let n = out.len().min(self.buf.len());
+        out[..n].copy_from_slice(&self.buf.split_to(n));
+        Ok(n)
+    }
+}
@@ -1,6 +1,8 @@
 //! Archive extraction module
 //!
-//! Handles tar.zst, tar.gz, and other archive formats.
+//! Handles tar (plain, gzip, zstd, xz, bzip2), zip, 7z, pkg, and single
+//! compressed executables. Formats are identified from magic bytes where
+//! possible, falling back to the file name.
 
 use std::fs::{self, File};
 use std::io::{self, BufReader, Read, Seek, SeekFrom};
@@ -28,6 +30,332 @@ pub enum ExtractError {
     /// The archive is malformed, corrupt, or contains invalid entries.
     #[error("Archive error: {0}")]
     Archive(String),
+
+    /// An entry was rejected by the [`ExtractPolicy`].
+    #[error("Refusing to extract '{entry}': {violation}")]
+    Unsafe {
+        /// Path of the offending entry as recorded in the archive.
+        entry: String,
+        /// The rule it broke.
+        violation: Violation,
+    },
+}
+
+/// A rule of the [`ExtractPolicy`] broken by an archive entry.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    /// The entry path is absolute.
+    #[error("absolute path")]
+    AbsolutePath,
+    /// The entry path climbs out of the destination with `..`.
+    #[error("path escapes the destination")]
+    Traversal,
+    /// The entry would be written through a symlink extracted earlier.
+    #[error("path passes through a symlink")]
+    ThroughSymlink,
+    /// A symlink points outside the destination.
+    #[error("symlink target '{0}' escapes the destination")]
+    SymlinkEscape(String),
+    /// A hard link refers to something outside the destination, or to
+    /// anything other than a regular file extracted earlier.
+    #[error("hard link target '{0}' is not a file in this archive")]
+    HardlinkEscape(String),
+    /// Symlinks are disabled by the policy.
+    #[error("symlinks are not allowed")]
+    SymlinkDenied,
+    /// Hard links are disabled by the policy.
+    #[error("hard links are not allowed")]
+    HardlinkDenied,
+    /// Device nodes, FIFOs and sockets are never extracted.
+    #[error("device or special file")]
+    SpecialFile,
+    /// The archive expands past [`ExtractPolicy::max_total_size`].
+    #[error("archive expands beyond {0} bytes")]
+    TooLarge(u64),
+    /// The archive holds more than [`ExtractPolicy::max_entries`] entries.
+    #[error("archive has more than {0} entries")]
+    TooManyEntries(u64),
+    /// Output outgrew the compressed input by more than
+    /// [`ExtractPolicy::max_ratio`].
+    #[error("compression ratio exceeds {0}:1")]
+    RatioExceeded(u64),
+}
+
+const S_IFMT: u32 = 0o170_000;
+const S_IFLNK: u32 = 0o120_000;
+const S_IFCHR: u32 = 0o020_000;
+const S_IFBLK: u32 = 0o060_000;
+const S_IFIFO: u32 = 0o010_000;
+const S_IFSOCK: u32 = 0o140_000;
+
+/// Rules every extractor applies before an entry touches disk.
+///
+/// Paths must stay inside the destination, symlinks must resolve inside it,
+/// hard links may only point at files already extracted, and special files
+/// are refused. Limits bound the damage a decompression bomb can do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExtractPolicy {
+    /// Maximum bytes written across all entries.
+    pub max_total_size: u64,
+    /// Maximum number of entries (files, directories and links).
+    pub max_entries: u64,
+    /// Maximum bytes written per compressed byte read, checked once output
+    /// passes `ratio_grace` and only when the compressed size is known.
+    pub max_ratio: u64,
+    /// Output size below which the ratio is not checked; small, highly
+    /// repetitive archives are legitimate.
+    pub ratio_grace: u64,
+    /// Whether symlinks (inside the destination) are extracted.
+    pub allow_symlinks: bool,
+    /// Whether hard links (to files already extracted) are extracted.
+    pub allow_hardlinks: bool,
+}
+
+impl Default for ExtractPolicy {
+    fn default() -> Self {
+        Self {
+            max_total_size: 32 * 1024 * 1024 * 1024,
+            max_entries: 2_000_000,
+            max_ratio: 200,
+            ratio_grace: 64 * 1024 * 1024,
+            allow_symlinks: true,
+            allow_hardlinks: true,
+        }
+    }
+}
+
+impl ExtractPolicy {
+    /// The default policy with the limits from the `[extract]` settings.
+    ///
+    /// `APL_EXTRACT_MAX_SIZE`, `APL_EXTRACT_MAX_ENTRIES` and
+    /// `APL_EXTRACT_MAX_RATIO` set them from the environment.
+    pub fn from_config(config: &crate::config::ExtractConfig) -> Self {
+        Self {
+            max_total_size: config.max_size.0,
+            max_entries: config.max_entries,
+            max_ratio: config.max_ratio,
+            ..Self::default()
+        }
+    }
+
+    /// The process-wide policy, from the [global configuration](crate::config::Config::global).
+    pub fn global() -> &'static Self {
+        static POLICY: std::sync::OnceLock<ExtractPolicy> = std::sync::OnceLock::new();
+        POLICY.get_or_init(|| Self::from_config(&crate::config::Config::global().extract))
+    }
+}
+
+/// Running state of one extraction checked against an [`ExtractPolicy`].
+///
+/// A single guard is shared by every entry (and, for `.pkg`, every payload)
+/// so the limits apply to the archive as a whole.
+#[derive(Debug, Clone)]
+pub struct ExtractGuard {
+    policy: ExtractPolicy,
+    compressed_size: Option<u64>,
+    entries: u64,
+    written: u64,
+}
+
+impl Default for ExtractGuard {
+    fn default() -> Self {
+        Self::new(ExtractPolicy::default(), None)
+    }
+}
+
+impl ExtractGuard {
+    /// Start a guard for an archive of `compressed_size` bytes, if known.
+    pub fn new(policy: ExtractPolicy, compressed_size: Option<u64>) -> Self {
+        Self {
+            policy,
+            compressed_size: compressed_size.filter(|&n| n > 0),
+            entries: 0,
+            written: 0,
+        }
+    }
+
+    /// Guard using the [global policy](ExtractPolicy::global) for the
+    /// archive open as `file`.
+    fn for_file(file: &File) -> Self {
+        Self::new(
+            *ExtractPolicy::global(),
+            file.metadata().ok().map(|m| m.len()),
+        )
+    }
+
+    /// Total bytes written so far.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Count an entry and resolve where it lands under `dest_dir`.
+    ///
+    /// Any symlink already sitting at the resolved path is removed so the
+    /// write cannot follow it.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExtractError::Unsafe`] if the entry limit is exceeded, the
+    /// path is absolute or escapes `dest_dir`, or an intermediate directory
+    /// is a symlink.
+    pub fn entry(&mut self, dest_dir: &Path, entry_path: &Path) -> Result<PathBuf, ExtractError> {
+        self.entries += 1;
+        if self.entries > self.policy.max_entries {
+            return Err(unsafe_entry(
+                entry_path,
+                Violation::TooManyEntries(self.policy.max_entries),
+            ));
+        }
+
+        let absolute_path = validate_safe_path(dest_dir, entry_path)?;
+        let mut current = absolute_path.as_path();
+        while let Some(parent) = current.parent() {
+            if !parent.starts_with(dest_dir) || parent == dest_dir {
+                break;
+            }
+            if fs::symlink_metadata(parent).is_ok_and(|m| m.file_type().is_symlink()) {
+                return Err(unsafe_entry(entry_path, Violation::ThroughSymlink));
+            }
+            current = parent;
+        }
+        if fs::symlink_metadata(&absolute_path).is_ok_and(|m| m.file_type().is_symlink()) {
+            fs::remove_file(&absolute_path)?;
+        }
+        Ok(absolute_path)
+    }
+
+    /// Check a symlink at `link_path` (already resolved by
+    /// [`entry`](Self::entry)) pointing at `target`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExtractError::Unsafe`] if symlinks are disabled or the
+    /// target is absolute or resolves outside `dest_dir`.
+    pub fn symlink(
+        &self,
+        dest_dir: &Path,
+        entry_path: &Path,
+        link_path: &Path,
+        target: &Path,
+    ) -> Result<(), ExtractError> {
+        if !self.policy.allow_symlinks {
+            return Err(unsafe_entry(entry_path, Violation::SymlinkDenied));
+        }
+        let escape = || {
+            unsafe_entry(
+                entry_path,
+                Violation::SymlinkEscape(target.display().to_string()),
+            )
+        };
+        if target.has_root() {
+            return Err(escape());
+        }
+        let base = link_path.parent().unwrap_or(dest_dir);
+        let relative_base = base.strip_prefix(dest_dir).unwrap_or(Path::new(""));
+        validate_safe_path(dest_dir, &relative_base.join(target)).map_err(|_| escape())?;
+        Ok(())
+    }
+
+    /// Resolve the target of a hard link named `target` in the archive.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExtractError::Unsafe`] if hard links are disabled, or the
+    /// target escapes `dest_dir` or is not a regular file extracted earlier.
+    pub fn hardlink(
+        &self,
+        dest_dir: &Path,
+        entry_path: &Path,
+        target: &Path,
+    ) -> Result<PathBuf, ExtractError> {
+        if !self.policy.allow_hardlinks {
+            return Err(unsafe_entry(entry_path, Violation::HardlinkDenied));
+        }
+        let escape = || {
+            unsafe_entry(
+                entry_path,
+                Violation::HardlinkEscape(target.display().to_string()),
+            )
+        };
+        let resolved = validate_safe_path(dest_dir, target).map_err(|_| escape())?;
+        let is_plain_file = fs::symlink_metadata(&resolved).is_ok_and(|m| m.file_type().is_file());
+        let through_link = resolved
+            .ancestors()
+            .skip(1)
+            .take_while(|p| p.starts_with(dest_dir) && *p != dest_dir)
+            .any(|p| fs::symlink_metadata(p).is_ok_and(|m| m.file_type().is_symlink()));
+        if !is_plain_file || through_link {
+            return Err(escape());
+        }
+        Ok(resolved)
+    }
+
+    /// Reject a device node, FIFO or socket.
+    pub fn special_file(entry_path: &Path) -> ExtractError {
+        unsafe_entry(entry_path, Violation::SpecialFile)
+    }
+
+    /// Account for `bytes` about to be written for `entry_path`.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExtractError::Unsafe`] if the total size or compression
+    /// ratio limit would be exceeded.
+    pub fn reserve(&mut self, entry_path: &Path, bytes: u64) -> Result<(), ExtractError> {
+        self.written = self.written.saturating_add(bytes);
+        if self.written > self.policy.max_total_size {
+            return Err(unsafe_entry(
+                entry_path,
+                Violation::TooLarge(self.policy.max_total_size),
+            ));
+        }
+        if let Some(compressed) = self.compressed_size
+            && self.written > self.policy.ratio_grace
+            && self.written / compressed > self.policy.max_ratio
+        {
+            return Err(unsafe_entry(
+                entry_path,
+                Violation::RatioExceeded(self.policy.max_ratio),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Copy an entry's data, enforcing the size and ratio limits as bytes
+    /// arrive rather than trusting the size the archive declares.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExtractError::Unsafe`] when a limit is crossed, or an I/O
+    /// error from either side.
+    pub fn copy<R: Read + ?Sized, W: io::Write + ?Sized>(
+        &mut self,
+        entry_path: &Path,
+        reader: &mut R,
+        writer: &mut W,
+    ) -> Result<u64, ExtractError> {
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut copied = 0u64;
+        loop {
+            let n = match reader.read(&mut buf) {
+                Ok(0) => break,
+                Ok(n) => n,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e.into()),
+            };
+            self.reserve(entry_path, n as u64)?;
+            writer.write_all(&buf[..n])?;
+            copied += n as u64;
+        }
+        Ok(copied)
+    }
+}
+
+fn unsafe_entry(entry_path: &Path, violation: Violation) -> ExtractError {
+    ExtractError::Unsafe {
+        entry: entry_path.display().to_string(),
+        violation,
+    }
 }
 
 /// Information about an extracted file
@@ -41,13 +369,14 @@ pub struct ExtractedFile {
     pub is_executable: bool,
 }
 
-struct ProgressReader<'a, R, Rep> {
-    inner: R,
-    reporter: &'a Rep,
-    name: &'a PackageName,
-    version: &'a Version,
-    current: u64,
-    total: Option<u64>,
+/// Reader adapter that reports bytes consumed via [`Reporter::extracting`].
+pub(crate) struct ProgressReader<'a, R, Rep> {
+    pub(crate) inner: R,
+    pub(crate) reporter: &'a Rep,
+    pub(crate) name: &'a PackageName,
+    pub(crate) version: &'a Version,
+    pub(crate) current: u64,
+    pub(crate) total: Option<u64>,
 }
 
 impl<R: Read, Rep: Reporter> Read for ProgressReader<'_, R, Rep> {
@@ -83,6 +412,7 @@ pub fn extract_tar_zst<R: Reporter>(
     total: Option<u64>,
 ) -> Result<Vec<ExtractedFile>, ExtractError> {
     let file = File::open(archive_path)?;
+    let mut guard = ExtractGuard::for_file(&file);
     let reader = BufReader::new(file);
     let progress = ProgressReader {
         inner: reader,
@@ -94,7 +424,7 @@ pub fn extract_tar_zst<R: Reporter>(
     };
     let zstd_decoder = ZstdDecoder::new(progress)?;
 
-    extract_tar(zstd_decoder, dest_dir)
+    extract_tar(zstd_decoder, dest_dir, &mut guard)
 }
 
 /// Extract a `tar.gz` archive to a destination directory.
@@ -114,6 +444,7 @@ pub fn extract_tar_gz<R: Reporter>(
     total: Option<u64>,
 ) -> Result<Vec<ExtractedFile>, ExtractError> {
     let file = File::open(archive_path)?;
+    let mut guard = ExtractGuard::for_file(&file);
     let reader = BufReader::new(file);
     let progress = ProgressReader {
         inner: reader,
@@ -125,11 +456,81 @@ pub fn extract_tar_gz<R: Reporter>(
     };
     let gz_decoder = flate2::read::GzDecoder::new(progress);
 
-    extract_tar(gz_decoder, dest_dir)
+    extract_tar(gz_decoder, dest_dir, &mut guard)
+}
+
+/// Extract a `tar.xz` archive to a destination directory.
+///
+/// Reports extraction progress through the provided [`Reporter`].
+///
+/// # Errors
+///
+/// Returns [`ExtractError`] if the archive cannot be opened, decoded, or
+/// if any entry cannot be written to `dest_dir`.
+pub fn extract_tar_xz<R: Reporter>(
+    archive_path: &Path,
+    dest_dir: &Path,
+    reporter: &R,
+    name: &PackageName,
+    version: &Version,
+    total: Option<u64>,
+) -> Result<Vec<ExtractedFile>, ExtractError> {
+    let file = File::open(archive_path)?;
+    let mut guard = ExtractGuard::for_file(&file);
+    let reader = BufReader::new(file);
+    let progress = ProgressReader {
+        inner: reader,
+        reporter,
+        name,
+        version,
+        current: 0,
+        total,
+    };
+    let xz_decoder = xz2::read::XzDecoder::new_multi_decoder(progress);
+
+    extract_tar(xz_decoder, dest_dir, &mut guard)
+}
+
+/// Extract a `tar.bz2` archive to a destination directory.
+///
+/// Reports extraction progress through the provided [`Reporter`].
+///
+/// # Errors
+///
+/// Returns [`ExtractError`] if the archive cannot be opened, decoded, or
+/// if any entry cannot be written to `dest_dir`.
+pub fn extract_tar_bz2<R: Reporter>(
+    archive_path: &Path,
+    dest_dir: &Path,
+    reporter: &R,
+    name: &PackageName,
+    version: &Version,
+    total: Option<u64>,
+) -> Result<Vec<ExtractedFile>, ExtractError> {
+    let file = File::open(archive_path)?;
+    let mut guard = ExtractGuard::for_file(&file);
+    let reader = BufReader::new(file);
+    let progress = ProgressReader {
+        inner: reader,
+        reporter,
+        name,
+        version,
+        current: 0,
+        total,
+    };
+    let bz_decoder = bzip2::read::MultiBzDecoder::new(progress);
+
+    extract_tar(bz_decoder, dest_dir, &mut guard)
 }
 
-/// Extract a tar archive from a reader
-fn extract_tar<R: Read>(reader: R, dest_dir: &Path) -> Result<Vec<ExtractedFile>, ExtractError> {
+/// Extract a tar archive from a reader, checking every entry against `guard`.
+pub(crate) fn extract_tar<R: Read>(
+    reader: R,
+    dest_dir: &Path,
+    guard: &mut ExtractGuard,
+) -> Result<Vec<ExtractedFile>, ExtractError> {
+    use tar::EntryType;
+
     fs::create_dir_all(dest_dir)?;
 
     let mut archive = tar::Archive::new(reader);
@@ -137,25 +538,82 @@ fn extract_tar<R: Read>(reader: R, dest_dir: &Path) -> Result<Vec<ExtractedFile>
 
     for entry in archive.entries()? {
         let mut entry = entry?;
-        let entry_path = entry.path()?;
-
-        // Skip directories
-        if entry.header().entry_type().is_dir() {
+        let entry_path = entry.path()?.into_owned();
+        let entry_type = entry.header().entry_type();
+
+        // Metadata-only records; the tar crate already folds them into
+        // the entries they describe.
+        if matches!(
+            entry_type,
+            EntryType::XGlobalHeader
+                | EntryType::XHeader
+                | EntryType::GNULongName
+                | EntryType::GNULongLink
+        ) {
             continue;
         }
 
-        // Sanitize path to prevent Zip Slip (path traversal)
-        let absolute_path = validate_safe_path(dest_dir, &entry_path)?;
+        let absolute_path = guard.entry(dest_dir, &entry_path)?;
         let relative_path = absolute_path
             .strip_prefix(dest_dir)
-            .map_or_else(|_| entry_path.to_path_buf(), Path::to_path_buf);
+            .map_or_else(|_| entry_path.clone(), Path::to_path_buf);
 
+        if entry_type.is_dir() {
+            fs::create_dir_all(&absolute_path)?;
+            continue;
+        }
         if let Some(parent) = absolute_path.parent() {
             fs::create_dir_all(parent)?;
         }
 
-        // Extract the file
-        entry.unpack(&absolute_path)?;
+        match entry_type {
+            EntryType::Symlink => {
+                let target = entry
+                    .link_name()?
+                    .ok_or_else(|| {
+                        ExtractError::Archive(format!(
+                            "Symlink without target: {}",
+                            entry_path.display()
+                        ))
+                    })?
+                    .into_owned();
+                guard.symlink(dest_dir, &entry_path, &absolute_path, &target)?;
+                remove_existing(&absolute_path)?;
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&target, &absolute_path)?;
+                continue;
+            }
+            EntryType::Link => {
+                let target = entry
+                    .link_name()?
+                    .ok_or_else(|| {
+                        ExtractError::Archive(format!(
+                            "Hard link without target: {}",
+                            entry_path.display()
+                        ))
+                    })?
+                    .into_owned();
+                let source = guard.hardlink(dest_dir, &entry_path, &target)?;
+                remove_existing(&absolute_path)?;
+                fs::hard_link(&source, &absolute_path)?;
+            }
+            EntryType::Regular | EntryType::Continuous | EntryType::GNUSparse => {
+                remove_existing(&absolute_path)?;
+                let mut out = File::create(&absolute_path)?;
+                guard.copy(&entry_path, &mut entry, &mut out)?;
+                if let Ok(mtime) = entry.header().mtime() {
+                    let _ = out.set_modified(
+                        std::time::UNIX_EPOCH + std::time::Duration::from_secs(mtime),
+                    );
+                }
+                #[cfg(unix)]
+                if let Ok(mode) = entry.header().mode() {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(&absolute_path, fs::Permissions::from_mode(mode & 0o777))?;
+                }
+            }
+            _ => return Err(ExtractGuard::special_file(&entry_path)),
+        }
 
         // Check if executable (Unix mode has execute bit)
         let is_executable = entry
@@ -174,6 +632,14 @@ fn extract_tar<R: Read>(reader: R, dest_dir: &Path) -> Result<Vec<ExtractedFile>
     Ok(extracted_files)
 }
 
+/// Remove a file or symlink left at `path` by an earlier entry.
+fn remove_existing(path: &Path) -> io::Result<()> {
+    match fs::symlink_metadata(path) {
+        Ok(meta) if !meta.is_dir() => fs::remove_file(path),
+        _ => Ok(()),
+    }
+}
+
 /// Extract a zip archive to a destination directory.
 ///
 /// Reports extraction progress through the provided [`Reporter`].
@@ -191,6 +657,7 @@ pub fn extract_zip<R: Reporter>(
     total: Option<u64>,
 ) -> Result<Vec<ExtractedFile>, ExtractError> {
     let file = File::open(archive_path)?;
+    let mut guard = ExtractGuard::for_file(&file);
     let progress = ProgressReader {
         inner: file,
         reporter,
@@ -210,13 +677,10 @@ pub fn extract_zip<R: Reporter>(
             .by_index(i)
             .map_err(|e| ExtractError::Archive(e.to_string()))?;
 
-        // zip-rs's enclosed_name() already performs basic sanitization,
-        // but we apply our own validation for'defense-in-depth'.
-        let entry_path = file.enclosed_name().ok_or_else(|| {
-            ExtractError::Archive(format!("Invalid path in zip: {}", file.name()))
-        })?;
-
-        let absolute_path = validate_safe_path(dest_dir, &entry_path)?;
+        // Check the raw name rather than enclosed_name(), which silently
+        // drops unsafe entries instead of reporting them.
+        let entry_path = PathBuf::from(file.name());
+        let absolute_path = guard.entry(dest_dir, &entry_path)?;
         let relative_path = absolute_path
             .strip_prefix(dest_dir)
             .map_or_else(|_| entry_path.clone(), Path::to_path_buf);
@@ -229,14 +693,32 @@ pub fn extract_zip<R: Reporter>(
         if let Some(p) = absolute_path.parent() {
             fs::create_dir_all(p)?;
         }
+        remove_existing(&absolute_path)?;
+
+        let mode = file.unix_mode();
+        match mode.map(|m| m & S_IFMT) {
+            Some(S_IFLNK) => {
+                let mut target = Vec::new();
+                guard.copy(&entry_path, &mut file, &mut target)?;
+                let target = PathBuf::from(String::from_utf8_lossy(&target).into_owned());
+                guard.symlink(dest_dir, &entry_path, &absolute_path, &target)?;
+                #[cfg(unix)]
+                std::os::unix::fs::symlink(&target, &absolute_path)?;
+                continue;
+            }
+            Some(S_IFCHR | S_IFBLK | S_IFIFO | S_IFSOCK) => {
+                return Err(ExtractGuard::special_file(&entry_path));
+            }
+            _ => {}
+        }
 
         let mut outfile = File::create(&absolute_path)?;
-        io::copy(&mut file, &mut outfile)?;
+        guard.copy(&entry_path, &mut file, &mut outfile)?;
 
         #[cfg(unix)]
-        let is_executable = if let Some(mode) = file.unix_mode() {
+        let is_executable = if let Some(mode) = mode {
             use std::os::unix::fs::PermissionsExt;
-            fs::set_permissions(&absolute_path, fs::Permissions::from_mode(mode))?;
+            fs::set_permissions(&absolute_path, fs::Permissions::from_mode(mode & 0o7777))?;
             mode & 0o111 != 0
         } else {
             false
@@ -254,74 +736,365 @@ pub fn extract_zip<R: Reporter>(
     Ok(extracted_files)
 }
 
+/// Extract a 7z archive to a destination directory.
+///
+/// Reports extraction progress through the provided [`Reporter`]. See
+/// [`crate::io::sevenz`] for the supported coders.
+///
+/// # Errors
+///
+/// Returns [`ExtractError`] if the archive cannot be opened, uses an
+/// unsupported coder, or if any entry cannot be written to `dest_dir`.
+pub fn extract_7z<R: Reporter>(
+    archive_path: &Path,
+    dest_dir: &Path,
+    reporter: &R,
+    name: &PackageName,
+    version: &Version,
+    total: Option<u64>,
+) -> Result<Vec<ExtractedFile>, ExtractError> {
+    let file = File::open(archive_path)?;
+    let mut guard = ExtractGuard::for_file(&file);
+    let progress = ProgressReader {
+        inner: BufReader::new(file),
+        reporter,
+        name,
+        version,
+        current: 0,
+        total,
+    };
+    crate::io::sevenz::extract(progress, dest_dir, &mut guard)
+}
+
+/// Decompress a single gzip/xz/zstd-compressed executable to `dest_path`.
+///
+/// The output is marked executable. Reports progress through the provided
+/// [`Reporter`].
+///
+/// # Errors
+///
+/// Returns [`ExtractError::UnsupportedFormat`] if `format` is not one of
+/// [`ArtifactFormat::Gz`], [`ArtifactFormat::Xz`] or [`ArtifactFormat::Zst`],
+/// or an I/O error if decoding or writing fails.
+pub fn extract_compressed_binary<R: Reporter>(
+    archive_path: &Path,
+    dest_path: &Path,
+    format: ArtifactFormat,
+    reporter: &R,
+    name: &PackageName,
+    version: &Version,
+    total: Option<u64>,
+) -> Result<ExtractedFile, ExtractError> {
+    let file = File::open(archive_path)?;
+    let mut guard = ExtractGuard::for_file(&file);
+    let progress = ProgressReader {
+        inner: BufReader::new(file),
+        reporter,
+        name,
+        version,
+        current: 0,
+        total,
+    };
+    let mut decoder: Box<dyn Read + '_> = match format {
+        ArtifactFormat::Gz => Box::new(flate2::read::MultiGzDecoder::new(progress)),
+        ArtifactFormat::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(progress)),
+        ArtifactFormat::Zst => Box::new(ZstdDecoder::new(progress)?),
+        other => {
+            return Err(ExtractError::UnsupportedFormat(format!(
+                "{other:?} is not a compressed binary"
+            )));
+        }
+    };
+
+    if let Some(parent) = dest_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    remove_existing(dest_path)?;
+    let mut out = File::create(dest_path)?;
+    let entry_path = dest_path.file_name().map_or(dest_path, Path::new);
+    guard.copy(entry_path, &mut decoder, &mut out)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(dest_path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    let relative_path = dest_path
+        .file_name()
+        .map_or_else(|| dest_path.to_path_buf(), PathBuf::from);
+    Ok(ExtractedFile {
+        relative_path,
+        absolute_path: dest_path.to_path_buf(),
+        is_executable: true,
+    })
+}
+
 /// Validates that an archive entry path does not escape the destination directory.
-/// Returns the canonicalized absolute path if safe.
-fn validate_safe_path(dest_dir: &Path, entry_path: &Path) -> Result<PathBuf, ExtractError> {
-    // Join paths -- this handles absolute entry paths by overriding dest_dir
-    // if entry_path is absolute (which is exactly what we want to detect).
-    let joined = dest_dir.join(entry_path);
+/// Returns the normalized absolute path if safe.
+pub(crate) fn validate_safe_path(
+    dest_dir: &Path,
+    entry_path: &Path,
+) -> Result<PathBuf, ExtractError> {
+    if entry_path.has_root() {
+        return Err(unsafe_entry(entry_path, Violation::AbsolutePath));
+    }
 
     // Normalize components (remove . and ..) without hitting the disk
     // because the files don't exist yet.
-    let mut absolute_path = PathBuf::new();
-    for component in joined.components() {
+    let mut relative = PathBuf::new();
+    for component in entry_path.components() {
         match component {
-            std::path::Component::Normal(p) => absolute_path.push(p),
+            std::path::Component::Normal(p) => relative.push(p),
             std::path::Component::ParentDir => {
-                if !absolute_path.pop() {
-                    return Err(ExtractError::Archive(format!(
-                        "Path traversal detected: {}",
-                        entry_path.display()
-                    )));
+                if !relative.pop() {
+                    return Err(unsafe_entry(entry_path, Violation::Traversal));
                 }
             }
-            std::path::Component::RootDir => {
-                // If it's a root component, we reset the path to the root
-                // effectively detecting an absolute path entry.
-                absolute_path = PathBuf::from("/");
+            std::path::Component::RootDir | std::path::Component::Prefix(_) => {
+                return Err(unsafe_entry(entry_path, Violation::AbsolutePath));
             }
-            _ => {} // Ignore . and Prefix
+            std::path::Component::CurDir => {}
         }
     }
 
-    // 3. Ensure the resulting path still starts with dest_dir
-    // We normalize dest_dir too for comparison.
-    if !absolute_path.starts_with(dest_dir) {
-        return Err(ExtractError::Archive(format!(
-            "Path traversal detected: {}",
-            entry_path.display()
-        )));
-    }
-
-    Ok(absolute_path)
+    Ok(dest_dir.join(relative))
 }
 
-/// Detect the [`ArtifactFormat`] from a file's extension.
+/// Number of leading bytes [`sniff_bytes`] wants to see.
+pub const SNIFF_LEN: usize = 8 * 1024;
+
+const TAR_MAGIC_OFFSET: usize = 257;
+const GZIP_MAGIC: &[u8] = &[0x1F, 0x8B];
+const XZ_MAGIC: &[u8] = &[0xFD, b'7', b'z', b'X', b'Z', 0x00];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xB5, 0x2F, 0xFD];
+const BZIP2_MAGIC: &[u8] = b"BZh";
+const EXECUTABLE_MAGICS: &[&[u8]] = &[
+    &[0xCF, 0xFA, 0xED, 0xFE], // Mach-O 64-bit
+    &[0xCE, 0xFA, 0xED, 0xFE], // Mach-O 32-bit
+    &[0xCA, 0xFE, 0xBA, 0xBE], // Mach-O universal
+    &[0x7F, b'E', b'L', b'F'],
+    b"#!",
+];
+
+/// Detect the [`ArtifactFormat`] from a file's extension alone.
 ///
 /// The comparison is case-insensitive. Returns [`ArtifactFormat::Binary`] as
 /// the fallback when no known extension is matched.
 #[allow(clippy::case_sensitive_file_extension_comparisons)]
-pub fn detect_format(path: &Path) -> ArtifactFormat {
+pub fn format_from_extension(path: &Path) -> ArtifactFormat {
     let path_str = path.to_string_lossy().to_lowercase();
+    // URLs: `foo.tar.gz?download=1` and `foo.zip#sha256=...` name the same file.
+    let path_str = path_str
+        .split(['?', '#'])
+        .next()
+        .unwrap_or_default()
+        .trim_end_matches('/');
 
     if path_str.ends_with(".tar.zst") || path_str.ends_with(".tzst") {
         ArtifactFormat::TarZst
     } else if path_str.ends_with(".tar.gz") || path_str.ends_with(".tgz") {
         ArtifactFormat::TarGz
+    } else if path_str.ends_with(".tar.xz") || path_str.ends_with(".txz") {
+        ArtifactFormat::TarXz
+    } else if path_str.ends_with(".tar.bz2")
+        || path_str.ends_with(".tbz2")
+        || path_str.ends_with(".tbz")
+    {
+        ArtifactFormat::TarBz2
     } else if path_str.ends_with(".tar") {
         ArtifactFormat::Tar
     } else if path_str.ends_with(".zip") {
         ArtifactFormat::Zip
+    } else if path_str.ends_with(".7z") {
+        ArtifactFormat::SevenZ
     } else if path_str.ends_with(".pkg") {
         ArtifactFormat::Pkg
     } else if path_str.ends_with(".dmg") {
         ArtifactFormat::Dmg
+    } else if path_str.ends_with(".gz") {
+        ArtifactFormat::Gz
+    } else if path_str.ends_with(".xz") {
+        ArtifactFormat::Xz
+    } else if path_str.ends_with(".zst") {
+        ArtifactFormat::Zst
     } else {
         ArtifactFormat::Binary
     }
 }
 
-/// Extract an archive, auto-detecting its format from the file extension.
+/// Identify a format from the first bytes of a file.
+///
+/// Compressed streams are partially decoded to tell a tarball from a single
+/// compressed executable; if too little was decoded to decide, `hint`'s
+/// extension breaks the tie. Returns `None` when the bytes match nothing we
+/// know (DMGs, whose signature lives at the end of the file, included).
+pub fn sniff_bytes(head: &[u8], hint: &Path) -> Option<ArtifactFormat> {
+    let hinted_tar = matches!(
+        format_from_extension(hint),
+        ArtifactFormat::Tar
+            | ArtifactFormat::TarGz
+            | ArtifactFormat::TarZst
+            | ArtifactFormat::TarXz
+            | ArtifactFormat::TarBz2
+    );
+
+    if head.starts_with(crate::io::sevenz::SIGNATURE) {
+        return Some(ArtifactFormat::SevenZ);
+    }
+    if head.starts_with(b"PK\x03\x04") || head.starts_with(b"PK\x05\x06") {
+        return Some(ArtifactFormat::Zip);
+    }
+    if head.starts_with(&crate::io::xar::XAR_MAGIC) {
+        return Some(ArtifactFormat::Pkg);
+    }
+    if is_tar_header(head) {
+        return Some(ArtifactFormat::Tar);
+    }
+
+    let (tar, raw, prefix) = if head.starts_with(GZIP_MAGIC) {
+        (
+            ArtifactFormat::TarGz,
+            Some(ArtifactFormat::Gz),
+            read_prefix(flate2::read::GzDecoder::new(head)),
+        )
+    } else if head.starts_with(XZ_MAGIC) {
+        (
+            ArtifactFormat::TarXz,
+            Some(ArtifactFormat::Xz),
+            read_prefix(xz2::read::XzDecoder::new(head)),
+        )
+    } else if head.starts_with(ZSTD_MAGIC) {
+        let prefix = ZstdDecoder::new(head).map(read_prefix).unwrap_or_default();
+        (ArtifactFormat::TarZst, Some(ArtifactFormat::Zst), prefix)
+    } else if head.starts_with(BZIP2_MAGIC) {
+        (
+            ArtifactFormat::TarBz2,
+            None,
+            read_prefix(bzip2::read::BzDecoder::new(head)),
+        )
+    } else if EXECUTABLE_MAGICS.iter().any(|m| head.starts_with(m)) {
+        return Some(ArtifactFormat::Binary);
+    } else {
+        return None;
+    };
+
+    if is_tar_header(&prefix) {
+        Some(tar)
+    } else if prefix.len() > TAR_MAGIC_OFFSET + 5 {
+        raw
+    } else if hinted_tar || raw.is_none() {
+        Some(tar)
+    } else {
+        raw
+    }
+}
+
+/// Identify a file's format from its contents.
+///
+/// Checks the leading bytes with [`sniff_bytes`] and the trailing UDIF
+/// `koly` block for disk images.
+///
+/// # Errors
+///
+/// Returns an I/O error if the file cannot be opened or read.
+pub fn sniff_format(path: &Path) -> io::Result<Option<ArtifactFormat>> {
+    let mut file = File::open(path)?;
+
+    // The trailer is definitive; a UDIF image's leading chunk data can look
+    // like any compressed stream.
+    let len = file.metadata()?.len();
+    if len >= 512 {
+        let mut tail = [0u8; 4];
+        file.seek(SeekFrom::Start(len - 512))?;
+        file.read_exact(&mut tail)?;
+        if &tail == b"koly" {
+            return Ok(Some(ArtifactFormat::Dmg));
+        }
+        file.seek(SeekFrom::Start(0))?;
+    }
+
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    (&mut file).take(SNIFF_LEN as u64).read_to_end(&mut head)?;
+    Ok(sniff_bytes(&head, path))
+}
+
+/// Settle on the format to extract with, given what the package declares
+/// and what [`sniff_bytes`]/[`sniff_format`] found in the content.
+///
+/// Recognised content wins, since declarations and URLs are often wrong.
+/// The one exception is a declared DMG whose head merely looks like a
+/// compressed stream: only the trailer can confirm or refute a disk image,
+/// and a head-only sniff never sees it.
+pub fn reconcile_format(
+    declared: ArtifactFormat,
+    detected: Option<ArtifactFormat>,
+) -> ArtifactFormat {
+    match detected {
+        None => declared,
+        Some(
+            ArtifactFormat::TarGz
+            | ArtifactFormat::TarZst
+            | ArtifactFormat::TarXz
+            | ArtifactFormat::TarBz2
+            | ArtifactFormat::Gz
+            | ArtifactFormat::Xz
+            | ArtifactFormat::Zst,
+        ) if declared == ArtifactFormat::Dmg => declared,
+        Some(detected) => detected,
+    }
+}
+
+/// Detect the [`ArtifactFormat`] of a file.
+///
+/// Existing files are identified by their magic bytes ([`sniff_format`]);
+/// otherwise, or when the contents are not recognised, the extension decides
+/// ([`format_from_extension`]).
+pub fn detect_format(path: &Path) -> ArtifactFormat {
+    sniff_format(path)
+        .ok()
+        .flatten()
+        .unwrap_or_else(|| format_from_extension(path))
+}
+
+fn is_tar_header(block: &[u8]) -> bool {
+    block
+        .get(TAR_MAGIC_OFFSET..TAR_MAGIC_OFFSET + 5)
+        .is_some_and(|m| m == b"ustar")
+}
+
+/// Decode as much of a (possibly truncated) stream as fits in one tar block.
+fn read_prefix<R: Read>(mut reader: R) -> Vec<u8> {
+    let mut buf = vec![0u8; 512];
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) | Err(_) => break,
+            Ok(n) => filled += n,
+        }
+    }
+    buf.truncate(filled);
+    buf
+}
+
+/// File name for a decompressed single binary: the archive name without its
+/// compression suffix.
+fn decompressed_name(path: &Path) -> PathBuf {
+    let name = path
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let lower = name.to_lowercase();
+    [".gz", ".xz", ".zst"]
+        .iter()
+        .find(|suffix| lower.ends_with(*suffix))
+        .map_or_else(
+            || PathBuf::from(&name),
+            |suffix| PathBuf::from(&name[..name.len() - suffix.len()]),
+        )
+}
+
+/// Extract an archive, auto-detecting its format.
 ///
 /// Delegates to the format-specific extractor (e.g. [`extract_tar_zst`],
 /// [`extract_zip`]) after calling [`detect_format`].
@@ -345,8 +1118,15 @@ pub fn extract_auto<R: Reporter>(
         ArtifactFormat::TarGz => {
             extract_tar_gz(archive_path, dest_dir, reporter, name, version, total)
         }
+        ArtifactFormat::TarXz => {
+            extract_tar_xz(archive_path, dest_dir, reporter, name, version, total)
+        }
+        ArtifactFormat::TarBz2 => {
+            extract_tar_bz2(archive_path, dest_dir, reporter, name, version, total)
+        }
         ArtifactFormat::Tar => {
             let file = File::open(archive_path)?;
+            let mut guard = ExtractGuard::for_file(&file);
             let reader = BufReader::new(file);
             let progress = ProgressReader {
                 inner: reader,
@@ -356,10 +1136,26 @@ pub fn extract_auto<R: Reporter>(
                 current: 0,
                 total,
             };
-            extract_tar(progress, dest_dir)
+            extract_tar(progress, dest_dir, &mut guard)
         }
         ArtifactFormat::Zip => extract_zip(archive_path, dest_dir, reporter, name, version, total),
-        ArtifactFormat::Pkg => extract_pkg(archive_path, dest_dir), // PKG requires xar command, hard to track progress this way
+        ArtifactFormat::SevenZ => {
+            extract_7z(archive_path, dest_dir, reporter, name, version, total)
+        }
+        format @ (ArtifactFormat::Gz | ArtifactFormat::Xz | ArtifactFormat::Zst) => {
+            let dest_path = dest_dir.join(decompressed_name(archive_path));
+            extract_compressed_binary(
+                archive_path,
+                &dest_path,
+                format,
+                reporter,
+                name,
+                version,
+                total,
+            )
+            .map(|file| vec![file])
+        }
+        ArtifactFormat::Pkg => extract_pkg(archive_path, dest_dir, reporter, name, version),
         ArtifactFormat::Binary | ArtifactFormat::Dmg => {
             // For raw binaries and DMGs, just copy the file
             fs::create_dir_all(dest_dir)?;
@@ -378,143 +1174,24 @@ pub fn extract_auto<R: Reporter>(
     }
 }
 
-/// Extract a macOS `.pkg` installer using `xar` and `cpio`.
+/// Extract a macOS `.pkg` installer without external tools.
 ///
-/// Shells out to `xar` to expand the outer package, locates the `Payload`
-/// file, then pipes it through `gunzip` and `cpio` to produce the final
-/// directory tree.
+/// Every component payload is unpacked into `dest_dir`; install scripts and
+/// `PackageInfo`/`Distribution` metadata are written beside it, in
+/// [`crate::io::pkg::metadata_dir`]. See [`crate::io::pkg::extract`].
 ///
 /// # Errors
 ///
-/// Returns [`ExtractError`] if `xar` or `cpio` is not available, the
-/// archive is malformed, or any I/O operation fails.
-pub fn extract_pkg(
+/// Returns [`ExtractError`] if the archive is malformed or any I/O
+/// operation fails.
+pub fn extract_pkg<R: Reporter>(
     archive_path: &Path,
     dest_dir: &Path,
+    reporter: &R,
+    name: &PackageName,
+    version: &Version,
 ) -> Result<Vec<ExtractedFile>, ExtractError> {
-    // 1. Create temp dir for xar expansion
-    let temp_dir = tempfile::Builder::new()
-        .prefix("apl-pkg-")
-        .tempdir()
-        .map_err(ExtractError::Io)?;
-
-    // 2. Run: xar -xf <archive> -C <temp>
-    let status = std::process::Command::new("xar")
-        .arg("-xf")
-        .arg(archive_path)
-        .arg("-C")
-        .arg(temp_dir.path())
-        .stdout(std::process::Stdio::null())
-        .stderr(std::process::Stdio::inherit())
-        .status()
-        .map_err(ExtractError::Io)?;
-
-    if !status.success() {
-        return Err(ExtractError::Archive("xar extraction failed".to_string()));
-    }
-
-    // 3. Find Payload file (bfs/dfs)
-    // We look for any file named "Payload". Typical path: "Name.pkg/Payload"
-    let mut payload_path = None;
-    let mut stack = vec![temp_dir.path().to_path_buf()];
-
-    while let Some(dir) = stack.pop() {
-        let Ok(entries) = fs::read_dir(&dir) else {
-            continue;
-        };
-
-        for entry_res in entries {
-            let Ok(entry) = entry_res else {
-                continue;
-            };
-            let path = entry.path();
-            if path.file_name().and_then(|n| n.to_str()) == Some("Payload") {
-                payload_path = Some(path);
-                break;
-            } else if path.is_dir() {
-                stack.push(path);
-            }
-        }
-        if payload_path.is_some() {
-            break;
-        }
-    }
-
-    let payload =
-        payload_path.ok_or_else(|| ExtractError::Archive("No Payload found in pkg".to_string()))?;
-
-    // 4. Extract Payload: cat Payload | gzip -d | cpio -i
-    // We must run cpio in the dest_dir
-    fs::create_dir_all(dest_dir)?;
-
-    let cat = std::process::Command::new("cat")
-        .arg(payload)
-        .stdout(std::process::Stdio::piped())
-        .spawn()
-        .map_err(ExtractError::Io)?;
-
-    let cat_stdout = cat
-        .stdout
-        .ok_or_else(|| ExtractError::Archive("Failed to capture cat stdout".to_string()))?;
-
-    let gunzip = std::process::Command::new("gunzip")
-        .stdin(cat_stdout)
-        .stdout(std::process::Stdio::piped())
-        .spawn()
-        .map_err(ExtractError::Io)?;
-
-    let gunzip_stdout = gunzip
-        .stdout
-        .ok_or_else(|| ExtractError::Archive("Failed to capture gunzip stdout".to_string()))?;
-
-    let cpio = std::process::Command::new("cpio")
-        .arg("-i")
-        .arg("--quiet")
-        .stdin(gunzip_stdout)
-        .current_dir(dest_dir)
-        .status()
-        .map_err(ExtractError::Io)?;
-
-    if !cpio.success() {
-        return Err(ExtractError::Archive("cpio extraction failed".to_string()));
-    }
-
-    // 5. Walk dest_dir to build ExtractedFile list
-    // This is a bit inefficient (re-walking) but robust
-    let mut extracted_files = Vec::new();
-    let mut stack = vec![dest_dir.to_path_buf()];
-
-    while let Some(dir) = stack.pop() {
-        let Ok(entries) = fs::read_dir(&dir) else {
-            continue;
-        };
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.is_dir() {
-                stack.push(path);
-            } else {
-                let relative_path = path
-                    .strip_prefix(dest_dir)
-                    .map(std::path::Path::to_path_buf)
-                    .unwrap_or(path.clone());
-                // Check executable (simple heuristic or check mode)
-                let is_executable = if let Ok(metadata) = path.metadata() {
-                    use std::os::unix::fs::PermissionsExt;
-                    metadata.permissions().mode() & 0o111 != 0
-                } else {
-                    false
-                };
-
-                extracted_files.push(ExtractedFile {
-                    relative_path,
-                    absolute_path: path,
-                    is_executable,
-                });
-            }
-        }
-    }
-
-    Ok(extracted_files)
+    crate::io::pkg::extract(archive_path, dest_dir, reporter, name, version).map(|(files, _)| files)
 }
 
 /// Detect if a directory has a single top-level directory and strip it by
@@ -704,6 +1381,210 @@ mod tests {
         );
     }
 
+    fn tar_bytes(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut builder = tar::Builder::new(Vec::new());
+        for (path, data) in files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(data.len() as u64);
+            header.set_mode(0o755);
+            header.set_cksum();
+            builder.append_data(&mut header, path, *data).unwrap();
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut enc = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        enc.write_all(data).unwrap();
+        enc.finish().unwrap()
+    }
+
+    fn xz(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut enc = xz2::write::XzEncoder::new(Vec::new(), 6);
+        enc.write_all(data).unwrap();
+        enc.finish().unwrap()
+    }
+
+    fn bzip2(data: &[u8]) -> Vec<u8> {
+        use std::io::Write;
+        let mut enc = bzip2::write::BzEncoder::new(Vec::new(), bzip2::Compression::default());
+        enc.write_all(data).unwrap();
+        enc.finish().unwrap()
+    }
+
+    fn extract_to(src: &Path, dest: &Path) -> Vec<ExtractedFile> {
+        extract_auto(
+            src,
+            dest,
+            &crate::reporter::NullReporter,
+            &crate::types::PackageName::from("test"),
+            &crate::types::Version::from("0.0.0"),
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_format_from_extension_new_formats() {
+        let cases = [
+            ("a.tar.xz", ArtifactFormat::TarXz),
+            ("a.txz", ArtifactFormat::TarXz),
+            ("a.tar.bz2", ArtifactFormat::TarBz2),
+            ("a.tbz2", ArtifactFormat::TarBz2),
+            ("a.7z", ArtifactFormat::SevenZ),
+            ("tool-darwin-arm64.gz", ArtifactFormat::Gz),
+            ("tool.xz", ArtifactFormat::Xz),
+            ("tool.zst", ArtifactFormat::Zst),
+        ];
+        for (name, expected) in cases {
+            assert_eq!(format_from_extension(Path::new(name)), expected, "{name}");
+        }
+    }
+
+    #[test]
+    fn test_sniff_bytes() {
+        let tar = tar_bytes(&[("bin/tool", b"hello")]);
+        let hint = Path::new("download");
+
+        assert_eq!(sniff_bytes(&tar, hint), Some(ArtifactFormat::Tar));
+        assert_eq!(sniff_bytes(&gzip(&tar), hint), Some(ArtifactFormat::TarGz));
+        assert_eq!(sniff_bytes(&xz(&tar), hint), Some(ArtifactFormat::TarXz));
+        assert_eq!(
+            sniff_bytes(&bzip2(&tar), hint),
+            Some(ArtifactFormat::TarBz2)
+        );
+        assert_eq!(
+            sniff_bytes(&zstd::encode_all(tar.as_slice(), 3).unwrap(), hint),
+            Some(ArtifactFormat::TarZst)
+        );
+
+        let binary = vec![0xCFu8; 4096];
+        assert_eq!(sniff_bytes(&binary, hint), None);
+        assert_eq!(sniff_bytes(&gzip(&binary), hint), Some(ArtifactFormat::Gz));
+        assert_eq!(sniff_bytes(&xz(&binary), hint), Some(ArtifactFormat::Xz));
+        assert_eq!(
+            sniff_bytes(&[0xCF, 0xFA, 0xED, 0xFE, 0, 0], hint),
+            Some(ArtifactFormat::Binary)
+        );
+        assert_eq!(
+            sniff_bytes(b"PK\x03\x04rest", hint),
+            Some(ArtifactFormat::Zip)
+        );
+    }
+
+    #[test]
+    fn test_detect_format_prefers_contents() {
+        let dir = tempdir().unwrap();
+        // A tar.xz saved under an extension-less cache name.
+        let path = dir.path().join("dep.archive");
+        fs::write(&path, xz(&tar_bytes(&[("tool", b"x")]))).unwrap();
+        assert_eq!(detect_format(&path), ArtifactFormat::TarXz);
+    }
+
+    #[test]
+    fn test_format_from_extension_ignores_query() {
+        let cases = [
+            (
+                "https://example.com/tool-1.0.tar.gz?download=1",
+                ArtifactFormat::TarGz,
+            ),
+            (
+                "https://example.com/Tool.dmg#sha256=abc",
+                ArtifactFormat::Dmg,
+            ),
+            ("https://example.com/tool.zip?a=b#c", ArtifactFormat::Zip),
+            (
+                "https://example.com/download?file=x",
+                ArtifactFormat::Binary,
+            ),
+        ];
+        for (url, expected) in cases {
+            assert_eq!(format_from_extension(Path::new(url)), expected, "{url}");
+        }
+    }
+
+    #[test]
+    fn test_reconcile_format() {
+        use ArtifactFormat::{Binary, Dmg, Gz, Pkg, TarBz2, TarGz, Zip};
+
+        // Unrecognised content keeps the declaration.
+        assert_eq!(reconcile_format(TarGz, None), TarGz);
+        // Recognised content overrides it.
+        assert_eq!(reconcile_format(Binary, Some(TarGz)), TarGz);
+        assert_eq!(reconcile_format(TarGz, Some(Zip)), Zip);
+        assert_eq!(reconcile_format(Binary, Some(Gz)), Gz);
+        assert_eq!(reconcile_format(Dmg, Some(Pkg)), Pkg);
+        // A DMG's first chunk may be bzip2 data; that alone proves nothing.
+        assert_eq!(reconcile_format(Dmg, Some(TarBz2)), Dmg);
+    }
+
+    #[test]
+    fn test_sniff_format_prefers_udif_trailer() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("download");
+        let mut image = bzip2(&[0u8; 4096]);
+        image.resize(image.len() + 512, 0);
+        let trailer = image.len() - 512;
+        image[trailer..trailer + 4].copy_from_slice(b"koly");
+        fs::write(&path, image).unwrap();
+        assert_eq!(sniff_format(&path).unwrap(), Some(ArtifactFormat::Dmg));
+    }
+
+    #[test]
+    fn test_extract_tar_xz_and_bz2() {
+        let dir = tempdir().unwrap();
+        let tar = tar_bytes(&[("pkg/bin/tool", b"#!/bin/sh\n")]);
+
+        for (name, data) in [("a.tar.xz", xz(&tar)), ("b.tar.bz2", bzip2(&tar))] {
+            let src = dir.path().join(name);
+            fs::write(&src, data).unwrap();
+            let dest = dir.path().join(format!("out-{name}"));
+            let files = extract_to(&src, &dest);
+            assert_eq!(files.len(), 1);
+            assert!(files[0].is_executable);
+            assert_eq!(fs::read(dest.join("pkg/bin/tool")).unwrap(), b"#!/bin/sh\n");
+        }
+    }
+
+    #[test]
+    fn test_extract_compressed_binary() {
+        let dir = tempdir().unwrap();
+        let payload = vec![0x42u8; 2048];
+        let compressed = [
+            ("tool.gz", gzip(&payload)),
+            ("tool.xz", xz(&payload)),
+            ("tool.zst", zstd::encode_all(payload.as_slice(), 3).unwrap()),
+        ];
+
+        for (name, data) in compressed {
+            let src = dir.path().join(name);
+            fs::write(&src, data).unwrap();
+            let dest = dir.path().join(format!("out-{name}"));
+            let files = extract_to(&src, &dest);
+            assert_eq!(files.len(), 1);
+            assert_eq!(files[0].relative_path, Path::new("tool"));
+            assert_eq!(fs::read(dest.join("tool")).unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn test_extract_auto_7z() {
+        let dir = tempdir().unwrap();
+        let src = dir.path().join("tool.7z");
+        fs::write(
+            &src,
+            crate::io::sevenz::tests::build_7z(&[], &[("bin/tool", 0o100_755, b"7z!")], true),
+        )
+        .unwrap();
+
+        let dest = dir.path().join("out");
+        let files = extract_to(&src, &dest);
+        assert_eq!(files.len(), 1);
+        assert_eq!(fs::read(dest.join("bin/tool")).unwrap(), b"7z!");
+    }
+
     #[test]
     fn test_validate_safe_path() {
         let dest = Path::new("/tmp/apl-test");
@@ -739,4 +1620,371 @@ mod tests {
         // Note: dest_dir.join("/foo") on Unix returns "/foo"
         assert!(validate_safe_path(dest, Path::new("/bin/tool")).is_err());
     }
+
+    /// Build a ustar archive without the tar crate's path sanitising, so
+    /// fixtures can carry `..`, absolute names and arbitrary link targets.
+    fn raw_tar(entries: &[(&str, tar::EntryType, &str, &[u8])]) -> Vec<u8> {
+        let mut out = Vec::new();
+        for (name, kind, link, data) in entries {
+            let mut header = tar::Header::new_ustar();
+            header.set_entry_type(*kind);
+            header.set_size(data.len() as u64);
+            header.set_mode(0o755);
+            let old = header.as_old_mut();
+            old.name[..name.len()].copy_from_slice(name.as_bytes());
+            old.linkname[..link.len()].copy_from_slice(link.as_bytes());
+            header.set_cksum();
+            out.extend_from_slice(header.as_bytes());
+            out.extend_from_slice(data);
+            out.resize(out.len().next_multiple_of(512), 0);
+        }
+        out.resize(out.len() + 1024, 0);
+        out
+    }
+
+    fn violation(result: Result<Vec<ExtractedFile>, ExtractError>) -> (String, Violation) {
+        match result {
+            Err(ExtractError::Unsafe { entry, violation }) => (entry, violation),
+            other => panic!("expected a policy violation, got {other:?}"),
+        }
+    }
+
+    /// Everything in `sandbox` other than `allowed` was written by an escape.
+    fn assert_contained(sandbox: &Path, allowed: &[&str]) {
+        for entry in fs::read_dir(sandbox).unwrap() {
+            let name = entry.unwrap().file_name();
+            assert!(
+                allowed.iter().any(|a| name == *a),
+                "entry escaped the destination: {}",
+                name.to_string_lossy()
+            );
+        }
+    }
+
+    #[test]
+    fn test_tar_rejects_malicious_entries() {
+        use tar::EntryType::{Block, Char, Fifo, Link, Regular, Symlink};
+
+        let cases: Vec<(Vec<u8>, &str, Violation)> = vec![
+            (
+                raw_tar(&[("../evil", Regular, "", b"x")]),
+                "../evil",
+                Violation::Traversal,
+            ),
+            (
+                raw_tar(&[("a/../../evil", Regular, "", b"x")]),
+                "a/../../evil",
+                Violation::Traversal,
+            ),
+            (
+                raw_tar(&[("/tmp/evil", Regular, "", b"x")]),
+                "/tmp/evil",
+                Violation::AbsolutePath,
+            ),
+            (
+                raw_tar(&[("link", Symlink, "../outside", b"")]),
+                "link",
+                Violation::SymlinkEscape("../outside".to_string()),
+            ),
+            (
+                raw_tar(&[("bin/link", Symlink, "/etc/passwd", b"")]),
+                "bin/link",
+                Violation::SymlinkEscape("/etc/passwd".to_string()),
+            ),
+            (
+                raw_tar(&[("hard", Link, "../outside", b"")]),
+                "hard",
+                Violation::HardlinkEscape("../outside".to_string()),
+            ),
+            (
+                raw_tar(&[("hard", Link, "/etc/passwd", b"")]),
+                "hard",
+                Violation::HardlinkEscape("/etc/passwd".to_string()),
+            ),
+            (
+                raw_tar(&[("hard", Link, "missing", b"")]),
+                "hard",
+                Violation::HardlinkEscape("missing".to_string()),
+            ),
+            (
+                raw_tar(&[("lib", Symlink, "real", b""), ("lib/x", Regular, "", b"x")]),
+                "lib/x",
+                Violation::ThroughSymlink,
+            ),
+            (
+                raw_tar(&[
+                    ("real", Regular, "", b"x"),
+                    ("soft", Symlink, "real", b""),
+                    ("hard", Link, "soft", b""),
+                ]),
+                "hard",
+                Violation::HardlinkEscape("soft".to_string()),
+            ),
+            (
+                raw_tar(&[("dev/null", Char, "", b"")]),
+                "dev/null",
+                Violation::SpecialFile,
+            ),
+            (
+                raw_tar(&[("dev/disk", Block, "", b"")]),
+                "dev/disk",
+                Violation::SpecialFile,
+            ),
+            (
+                raw_tar(&[("pipe", Fifo, "", b"")]),
+                "pipe",
+                Violation::SpecialFile,
+            ),
+        ];
+
+        for (archive, entry, expected) in cases {
+            let sandbox = tempdir().unwrap();
+            let dest = sandbox.path().join("out");
+            let result = extract_tar(archive.as_slice(), &dest, &mut ExtractGuard::default());
+            assert_eq!(violation(result), (entry.to_string(), expected));
+            assert_contained(sandbox.path(), &["out"]);
+        }
+    }
+
+    #[test]
+    fn test_tar_keeps_links_inside_dest() {
+        use tar::EntryType::{Directory, Link, Regular, Symlink};
+
+        let dir = tempdir().unwrap();
+        let archive = raw_tar(&[
+            ("pkg/", Directory, "", b""),
+            ("pkg/libexec/tool", Regular, "", b"#!/bin/sh\n"),
+            ("pkg/bin/tool", Symlink, "../libexec/tool", b""),
+            ("pkg/bin/alias", Link, "pkg/libexec/tool", b""),
+        ]);
+
+        let files =
+            extract_tar(archive.as_slice(), dir.path(), &mut ExtractGuard::default()).unwrap();
+        assert_eq!(files.len(), 2);
+        assert_eq!(
+            fs::read_link(dir.path().join("pkg/bin/tool")).unwrap(),
+            Path::new("../libexec/tool")
+        );
+        assert_eq!(
+            fs::read(dir.path().join("pkg/bin/alias")).unwrap(),
+            b"#!/bin/sh\n"
+        );
+    }
+
+    #[test]
+    fn test_extract_limits() {
+        let three = tar_bytes(&[("a", b"1"), ("b", b"2"), ("c", b"3")]);
+        let policy = ExtractPolicy {
+            max_entries: 2,
+            ..ExtractPolicy::default()
+        };
+        let dir = tempdir().unwrap();
+        let result = extract_tar(
+            three.as_slice(),
+            dir.path(),
+            &mut ExtractGuard::new(policy, None),
+        );
+        assert_eq!(
+            violation(result),
+            ("c".to_string(), Violation::TooManyEntries(2))
+        );
+
+        let big = tar_bytes(&[("big", &[0u8; 64])]);
+        let policy = ExtractPolicy {
+            max_total_size: 16,
+            ..ExtractPolicy::default()
+        };
+        let dir = tempdir().unwrap();
+        let result = extract_tar(
+            big.as_slice(),
+            dir.path(),
+            &mut ExtractGuard::new(policy, None),
+        );
+        assert_eq!(
+            violation(result),
+            ("big".to_string(), Violation::TooLarge(16))
+        );
+
+        // A classic bomb: a megabyte of zeros squeezed into a few hundred bytes.
+        let bomb = gzip(&tar_bytes(&[("zeros", &vec![0u8; 1024 * 1024])]));
+        let policy = ExtractPolicy {
+            max_ratio: 10,
+            ratio_grace: 4096,
+            ..ExtractPolicy::default()
+        };
+        let dir = tempdir().unwrap();
+        let result = extract_tar(
+            flate2::read::GzDecoder::new(bomb.as_slice()),
+            dir.path(),
+            &mut ExtractGuard::new(policy, Some(bomb.len() as u64)),
+        );
+        assert_eq!(
+            violation(result),
+            ("zeros".to_string(), Violation::RatioExceeded(10))
+        );
+    }
+
+    #[test]
+    fn test_zip_rejects_malicious_entries() {
+        use std::io::Write;
+        use zip::write::SimpleFileOptions;
+        type Writer = zip::ZipWriter<io::Cursor<Vec<u8>>>;
+
+        let options =
+            SimpleFileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let build = |add: &dyn Fn(&mut Writer)| {
+            let mut writer = Writer::new(io::Cursor::new(Vec::new()));
+            add(&mut writer);
+            writer.finish().unwrap().into_inner()
+        };
+
+        let cases = [
+            (
+                build(&|w| {
+                    w.start_file("../evil.txt", options).unwrap();
+                    w.write_all(b"x").unwrap();
+                }),
+                "../evil.txt",
+                Violation::Traversal,
+            ),
+            (
+                build(&|w| {
+                    w.start_file("/tmp/evil.txt", options).unwrap();
+                    w.write_all(b"x").unwrap();
+                }),
+                "/tmp/evil.txt",
+                Violation::AbsolutePath,
+            ),
+            (
+                build(&|w| w.add_symlink("bin/sh", "../../bin/sh", options).unwrap()),
+                "bin/sh",
+                Violation::SymlinkEscape("../../bin/sh".to_string()),
+            ),
+        ];
+
+        for (archive, entry, expected) in cases {
+            let sandbox = tempdir().unwrap();
+            let path = sandbox.path().join("evil.zip");
+            fs::write(&path, archive).unwrap();
+            let result = extract_zip(
+                &path,
+                &sandbox.path().join("out"),
+                &crate::reporter::NullReporter,
+                &crate::types::PackageName::from("test"),
+                &crate::types::Version::from("0.0.0"),
+                None,
+            );
+            assert_eq!(violation(result), (entry.to_string(), expected));
+            assert_contained(sandbox.path(), &["evil.zip", "out"]);
+        }
+    }
+
+    #[test]
+    fn test_7z_and_cpio_reject_escaping_links() {
+        const S_IFREG: u32 = 0o100_000;
+
+        let sandbox = tempdir().unwrap();
+        let archive = crate::io::sevenz::tests::build_7z(
+            &[],
+            &[("link", S_IFLNK | 0o777, b"../../etc/passwd")],
+            false,
+        );
+        let result = crate::io::sevenz::extract(
+            io::Cursor::new(archive),
+            &sandbox.path().join("out"),
+            &mut ExtractGuard::default(),
+        );
+        assert_eq!(
+            violation(result),
+            (
+                "link".to_string(),
+                Violation::SymlinkEscape("../../etc/passwd".to_string())
+            )
+        );
+
+        let archive = crate::io::cpio::tests::build_odc(&[
+            ("./tool", S_IFREG | 0o755, b"x"),
+            ("./dev/tty", S_IFCHR | 0o666, b""),
+        ]);
+        let result = crate::io::cpio::extract_cpio(
+            archive.as_slice(),
+            &sandbox.path().join("out"),
+            &mut ExtractGuard::default(),
+        );
+        assert_eq!(
+            violation(result),
+            ("dev/tty".to_string(), Violation::SpecialFile)
+        );
+        assert_contained(sandbox.path(), &["out"]);
+    }
+
+    /// Randomly corrupt valid archives and make sure nothing panics or lands
+    /// outside the destination, whatever the extractor makes of them.
+    #[test]
+    fn test_fuzz_mutated_archives_stay_contained() {
+        use tar::EntryType::{Regular, Symlink};
+
+        let payload = raw_tar(&[
+            ("pkg/bin/tool", Regular, "", b"#!/bin/sh\necho hi\n"),
+            ("pkg/bin/link", Symlink, "tool", b""),
+            ("pkg/share/doc", Regular, "", &[b'd'; 300]),
+        ]);
+        let zip = {
+            use std::io::Write;
+            let options = zip::write::SimpleFileOptions::default();
+            let mut w = zip::ZipWriter::new(io::Cursor::new(Vec::new()));
+            w.start_file("pkg/bin/tool", options).unwrap();
+            w.write_all(b"#!/bin/sh\necho hi\n").unwrap();
+            w.add_symlink("pkg/bin/link", "tool", options).unwrap();
+            w.finish().unwrap().into_inner()
+        };
+        let seven = crate::io::sevenz::tests::build_7z(
+            &["pkg"],
+            &[
+                ("pkg/tool", 0o100_755, b"#!/bin/sh\n"),
+                ("pkg/link", S_IFLNK | 0o777, b"tool"),
+            ],
+            true,
+        );
+        let fixtures = [
+            ("a.tar", payload.clone()),
+            ("a.tar.gz", gzip(&payload)),
+            ("a.zip", zip),
+            ("a.7z", seven),
+        ];
+
+        // xorshift64: deterministic, so any failure reproduces.
+        let mut state: u64 = 0x9E37_79B9_7F4A_7C15;
+        let mut next = move || {
+            state ^= state << 13;
+            state ^= state >> 7;
+            state ^= state << 17;
+            state
+        };
+
+        let sandbox = tempdir().unwrap();
+        let dest = sandbox.path().join("out");
+        for (name, original) in &fixtures {
+            let path = sandbox.path().join(name);
+            for _ in 0..150 {
+                let mut data = original.clone();
+                for _ in 0..=(next() % 4) {
+                    let at = usize::try_from(next() % data.len() as u64).unwrap();
+                    data[at] ^= u8::try_from(next() % 255 + 1).unwrap();
+                }
+                fs::write(&path, &data).unwrap();
+                let _ = fs::remove_dir_all(&dest);
+                let _ = extract_auto(
+                    &path,
+                    &dest,
+                    &crate::reporter::NullReporter,
+                    &crate::types::PackageName::from("fuzz"),
+                    &crate::types::Version::from("0.0.0"),
+                    None,
+                );
+                assert_contained(sandbox.path(), &[name, "out"]);
+            }
+            fs::remove_file(&path).unwrap();
+        }
+    }
 }
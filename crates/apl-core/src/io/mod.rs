@@ -2,6 +2,10 @@
 
 pub mod artifacts;
 pub mod chunked;
+pub mod cpio;
 pub mod dmg;
 pub mod download;
 pub mod extract;
+pub mod pkg;
+pub mod sevenz;
+pub mod xar;
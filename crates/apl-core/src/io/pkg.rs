@@ -0,0 +1,435 @@
+//! Native extraction of macOS installer packages (`.pkg`).
+//!
+//! A `.pkg` is a xar archive containing either a single component
+//! (`Payload`, `Scripts`, `PackageInfo` at the root) or a product archive
+//! (`Distribution` plus one `<name>.pkg/` directory per component). Every
+//! component's payload is unpacked into the destination; install scripts and
+//! metadata are kept beside it, in [`metadata_dir`], so the installer can
+//! inspect them without them ending up in the package tree.
+
+use std::fs;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use quick_xml::Reader;
+use quick_xml::events::Event;
+use serde::{Deserialize, Serialize};
+
+use super::cpio::extract_cpio;
+use super::extract::{ExtractError, ExtractGuard, ExtractPolicy, ExtractedFile, ProgressReader};
+use super::xar::{XarArchive, XarEntryKind};
+use crate::Reporter;
+use crate::types::{PackageName, Version};
+
+const METADATA_FILE: &str = "metadata.json";
+const PBZX_MAGIC: &[u8] = b"pbzx";
+/// Largest pbzx chunk, stored or decoded. Apple writes 16 MiB chunks; the
+/// length field is untrusted and sizes an allocation.
+const MAX_PBZX_CHUNK: u64 = 64 * 1024 * 1024;
+const XZ_MAGIC: &[u8] = &[0xfd, b'7', b'z', b'X', b'Z', 0x00];
+
+/// One component package inside a `.pkg`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PkgComponent {
+    /// Directory name of the component inside the xar (empty for flat packages).
+    pub name: String,
+    /// Bundle identifier from `PackageInfo` (e.g. `com.example.tool`).
+    pub identifier: Option<String>,
+    /// Component version from `PackageInfo`.
+    pub version: Option<String>,
+    /// Intended install location from `PackageInfo` (e.g. `/usr/local`).
+    pub install_location: Option<String>,
+    /// Extracted install scripts, relative to the [`metadata_dir`].
+    pub scripts: Vec<PathBuf>,
+}
+
+/// Metadata collected while extracting a `.pkg`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PkgMetadata {
+    /// Raw `Distribution` XML for product archives.
+    pub distribution: Option<String>,
+    /// Components, sorted by their path inside the archive.
+    pub components: Vec<PkgComponent>,
+}
+
+impl PkgMetadata {
+    /// Load metadata previously written by [`extract`] into `root`.
+    pub fn load(root: &Path) -> Option<Self> {
+        let content = fs::read_to_string(metadata_dir(root).join(METADATA_FILE)).ok()?;
+        serde_json::from_str(&content).ok()
+    }
+
+    /// Returns `true` if any component ships pre/post-install scripts.
+    pub fn has_scripts(&self) -> bool {
+        self.components.iter().any(|c| !c.scripts.is_empty())
+    }
+}
+
+/// Directory beside the extraction root `root` (`<root>.apl-pkg`) that
+/// holds install scripts and metadata.
+pub fn metadata_dir(root: &Path) -> PathBuf {
+    let mut name = root.file_name().unwrap_or_default().to_os_string();
+    name.push(".apl-pkg");
+    root.with_file_name(name)
+}
+
+/// Extract every component of a `.pkg` into `dest_dir`.
+///
+/// Payloads may be gzip, bzip2, xz or pbzx compressed cpio archives.
+/// Extraction progress (compressed payload bytes consumed) is reported via
+/// [`Reporter::extracting`].
+///
+/// # Errors
+///
+/// Returns [`ExtractError`] if the xar container or any payload is
+/// malformed, or if files cannot be written to `dest_dir`.
+pub fn extract<R: Reporter>(
+    archive_path: &Path,
+    dest_dir: &Path,
+    reporter: &R,
+    name: &PackageName,
+    version: &Version,
+) -> Result<(Vec<ExtractedFile>, PkgMetadata), ExtractError> {
+    let mut xar = XarArchive::open(archive_path)?;
+    fs::create_dir_all(dest_dir)?;
+    let meta_dir = metadata_dir(dest_dir);
+    let mut guard = ExtractGuard::new(
+        *ExtractPolicy::global(),
+        fs::metadata(archive_path).ok().map(|m| m.len()),
+    );
+
+    // A component is any directory (or the root) holding a Payload, Scripts or PackageInfo.
+    let mut component_dirs: Vec<PathBuf> = Vec::new();
+    for entry in xar.entries() {
+        if entry.kind != XarEntryKind::File {
+            continue;
+        }
+        let is_member = entry
+            .path
+            .file_name()
+            .is_some_and(|n| n == "Payload" || n == "Scripts" || n == "PackageInfo");
+        let parent = entry.path.parent().unwrap_or(Path::new("")).to_path_buf();
+        if is_member && !component_dirs.contains(&parent) {
+            component_dirs.push(parent);
+        }
+    }
+
+    if component_dirs.is_empty() {
+        return Err(ExtractError::Archive("No Payload found in pkg".to_string()));
+    }
+
+    let total: u64 = component_dirs
+        .iter()
+        .filter_map(|dir| xar.find(&dir.join("Payload")).and_then(|e| e.data))
+        .map(|d| d.length)
+        .sum();
+    let mut done: u64 = 0;
+
+    let mut metadata = PkgMetadata {
+        distribution: match xar.find(Path::new("Distribution")).and_then(|e| e.data) {
+            Some(data) => Some(String::from_utf8_lossy(&xar.read_to_vec(&data)?).into_owned()),
+            None => None,
+        },
+        components: Vec::new(),
+    };
+    let mut extracted = Vec::new();
+
+    for dir in component_dirs {
+        let mut component = PkgComponent {
+            name: dir.to_string_lossy().into_owned(),
+            ..PkgComponent::default()
+        };
+
+        if let Some(data) = xar.find(&dir.join("PackageInfo")).and_then(|e| e.data) {
+            let xml = xar.read_to_vec(&data)?;
+            parse_package_info(&String::from_utf8_lossy(&xml), &mut component);
+        }
+
+        if let Some(data) = xar.find(&dir.join("Payload")).and_then(|e| e.data) {
+            let progress = ProgressReader {
+                inner: xar.raw_reader(&data)?,
+                reporter,
+                name,
+                version,
+                current: done,
+                total: Some(total),
+            };
+            let decoded = super::xar::decode(progress, data.encoding);
+            extracted.extend(extract_cpio(decompress(decoded)?, dest_dir, &mut guard)?);
+            done += data.length;
+        }
+
+        if let Some(data) = xar.find(&dir.join("Scripts")).and_then(|e| e.data) {
+            let label = if component.name.is_empty() {
+                "root"
+            } else {
+                component.name.as_str()
+            };
+            let scripts_rel = Path::new("scripts").join(label);
+            let scripts_dir = meta_dir.join(&scripts_rel);
+            let files = extract_cpio(decompress(xar.reader(&data)?)?, &scripts_dir, &mut guard)?;
+            component.scripts = files
+                .into_iter()
+                .map(|f| scripts_rel.join(f.relative_path))
+                .collect();
+        }
+
+        metadata.components.push(component);
+    }
+
+    let meta_json = serde_json::to_string_pretty(&metadata)
+        .map_err(|e| ExtractError::Archive(e.to_string()))?;
+    fs::create_dir_all(&meta_dir)?;
+    fs::write(meta_dir.join(METADATA_FILE), meta_json)?;
+
+    Ok((extracted, metadata))
+}
+
+/// Pick a decompressor for a payload by sniffing its first bytes.
+fn decompress<'a, R: Read + 'a>(reader: R) -> io::Result<Box<dyn Read + 'a>> {
+    let mut reader = BufReader::new(reader);
+    let head = reader.fill_buf()?;
+
+    Ok(if head.starts_with(&[0x1f, 0x8b]) {
+        Box::new(flate2::read::MultiGzDecoder::new(reader))
+    } else if head.starts_with(b"BZh") {
+        Box::new(bzip2::read::MultiBzDecoder::new(reader))
+    } else if head.starts_with(PBZX_MAGIC) {
+        Box::new(PbzxReader::new(reader))
+    } else if head.starts_with(XZ_MAGIC) {
+        Box::new(xz2::read::XzDecoder::new_multi_decoder(reader))
+    } else {
+        Box::new(reader)
+    })
+}
+
+/// Decoder for Apple's `pbzx` framing: a sequence of independently
+/// xz-compressed (or stored) chunks, each prefixed by flags and length.
+struct PbzxReader<R> {
+    inner: R,
+    started: bool,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> PbzxReader<R> {
+    fn new(inner: R) -> Self {
+        Self {
+            inner,
+            started: false,
+            buf: Vec::new(),
+            pos: 0,
+        }
+    }
+
+    /// Load the next chunk into `buf`. Returns `false` at end of stream.
+    fn next_chunk(&mut self) -> io::Result<bool> {
+        if !self.started {
+            let mut header = [0u8; 12];
+            self.inner.read_exact(&mut header)?;
+            if &header[..4] != PBZX_MAGIC {
+                return Err(io::Error::new(io::ErrorKind::InvalidData, "bad pbzx magic"));
+            }
+            self.started = true;
+        }
+
+        let mut chunk_header = [0u8; 16];
+        match self.inner.read_exact(&mut chunk_header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+            Err(e) => return Err(e),
+        }
+        let len = u64::from_be_bytes(chunk_header[8..16].try_into().unwrap_or_default());
+        if len > MAX_PBZX_CHUNK {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("pbzx chunk of {len} bytes is too large"),
+            ));
+        }
+
+        let mut raw = Vec::with_capacity(len as usize);
+        (&mut self.inner).take(len).read_to_end(&mut raw)?;
+        if (raw.len() as u64) < len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "truncated pbzx chunk",
+            ));
+        }
+
+        self.buf.clear();
+        self.pos = 0;
+        if raw.starts_with(XZ_MAGIC) {
+            xz2::read::XzDecoder::new(raw.as_slice())
+                .take(MAX_PBZX_CHUNK + 1)
+                .read_to_end(&mut self.buf)?;
+            if self.buf.len() as u64 > MAX_PBZX_CHUNK {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "pbzx chunk decodes past the chunk limit",
+                ));
+            }
+        } else {
+            self.buf = raw;
+        }
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for PbzxReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        while self.pos >= self.buf.len() {
+            if !self.next_chunk()? {
+                return Ok(0);
+            }
+        }
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+        Ok(n)
+    }
+}
+
+/// Read identifier, version and install location from `PackageInfo` XML.
+fn parse_package_info(xml: &str, component: &mut PkgComponent) {
+    let mut reader = Reader::from_str(xml);
+    loop {
+        match reader.read_event() {
+            Ok(Event::Start(e) | Event::Empty(e)) if e.local_name().as_ref() == b"pkg-info" => {
+                for attr in e.attributes().flatten() {
+                    let Ok(value) = attr.unescape_value() else {
+                        continue;
+                    };
+                    match attr.key.as_ref() {
+                        b"identifier" => component.identifier = Some(value.into_owned()),
+                        b"version" => component.version = Some(value.into_owned()),
+                        b"install-location" => {
+                            component.install_location = Some(value.into_owned());
+                        }
+                        _ => {}
+                    }
+                }
+                return;
+            }
+            Ok(Event::Eof) | Err(_) => return,
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::cpio::tests::build_odc;
+    use crate::io::xar::tests::build_xar;
+    use flate2::Compression;
+    use flate2::write::GzEncoder;
+    use std::io::Write;
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut enc = GzEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(data).unwrap();
+        enc.finish().unwrap()
+    }
+
+    fn pbzx(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        out.extend_from_slice(PBZX_MAGIC);
+        out.extend_from_slice(&0x0100_0000u64.to_be_bytes());
+        let mut xz = Vec::new();
+        xz2::read::XzEncoder::new(data, 6)
+            .read_to_end(&mut xz)
+            .unwrap();
+        out.extend_from_slice(&0u64.to_be_bytes());
+        out.extend_from_slice(&(xz.len() as u64).to_be_bytes());
+        out.extend_from_slice(&xz);
+        out
+    }
+
+    #[test]
+    fn test_extract_product_archive() {
+        let dir = tempfile::tempdir().unwrap();
+        let payload_a = gzip(&build_odc(&[("./bin/a", 0o100_755, b"aaa")]));
+        let payload_b = pbzx(&build_odc(&[("./bin/b", 0o100_755, b"bbb")]));
+        let scripts = gzip(&build_odc(&[("./postinstall", 0o100_755, b"#!/bin/sh")]));
+        let info = br#"<pkg-info identifier="com.example.a" version="1.2" install-location="/usr/local"/>"#;
+
+        let pkg_path = dir.path().join("tool.pkg");
+        fs::write(
+            &pkg_path,
+            build_xar(&[
+                ("Distribution", b"<installer-gui-script/>"),
+                ("a.pkg/PackageInfo", info),
+                ("a.pkg/Payload", &payload_a),
+                ("a.pkg/Scripts", &scripts),
+                ("b.pkg/Payload", &payload_b),
+            ]),
+        )
+        .unwrap();
+
+        let dest = dir.path().join("out");
+        let (files, meta) = extract(
+            &pkg_path,
+            &dest,
+            &crate::reporter::NullReporter,
+            &PackageName::from("tool"),
+            &Version::from("1.2"),
+        )
+        .unwrap();
+
+        assert_eq!(files.len(), 2);
+        assert_eq!(fs::read(dest.join("bin/a")).unwrap(), b"aaa");
+        assert_eq!(fs::read(dest.join("bin/b")).unwrap(), b"bbb");
+
+        assert_eq!(meta.components.len(), 2);
+        assert_eq!(
+            meta.components[0].identifier.as_deref(),
+            Some("com.example.a")
+        );
+        assert_eq!(
+            meta.components[0].install_location.as_deref(),
+            Some("/usr/local")
+        );
+        assert!(meta.has_scripts());
+        assert!(
+            metadata_dir(&dest)
+                .join(&meta.components[0].scripts[0])
+                .exists()
+        );
+        assert_eq!(PkgMetadata::load(&dest), Some(meta));
+        // Only the payloads end up in the extracted tree.
+        let mut top: Vec<_> = fs::read_dir(&dest)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        top.sort();
+        assert_eq!(top, ["bin"]);
+    }
+
+    #[test]
+    fn test_rejects_oversized_pbzx_chunk() {
+        let mut payload = pbzx(b"data");
+        // length of the first chunk, after the 12-byte header and 8-byte flags
+        payload[20..28].copy_from_slice(&u64::MAX.to_be_bytes());
+        let mut out = Vec::new();
+        let err = PbzxReader::new(payload.as_slice())
+            .read_to_end(&mut out)
+            .unwrap_err();
+        assert_eq!(err.kind(), io::ErrorKind::InvalidData);
+    }
+
+    #[test]
+    fn test_extract_without_payload_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let pkg_path = dir.path().join("empty.pkg");
+        fs::write(&pkg_path, build_xar(&[("Distribution", b"<x/>")])).unwrap();
+
+        let result = extract(
+            &pkg_path,
+            &dir.path().join("out"),
+            &crate::reporter::NullReporter,
+            &PackageName::from("empty"),
+            &Version::from("0"),
+        );
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,1021 @@
+//! Reader for 7z archives.
+//!
+//! Parses the (optionally encoded) header and streams each folder through
+//! its decoder, splitting the output into files as it goes. Folders must use
+//! a single coder: Copy, LZMA, LZMA2, Deflate or `BZip2`. Filter chains
+//! (BCJ/BCJ2) and encryption are rejected with
+//! [`ExtractError::UnsupportedFormat`].
+
+use std::fs::{self, File};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use super::extract::{ExtractError, ExtractGuard, ExtractedFile};
+
+/// Six-byte signature at the start of every 7z archive.
+pub const SIGNATURE: &[u8; 6] = b"7z\xBC\xAF\x27\x1C";
+const SIGNATURE_HEADER_LEN: u64 = 32;
+/// Decoded headers are held in memory; real ones are a few megabytes at most.
+const MAX_HEADER_LEN: u64 = 256 * 1024 * 1024;
+
+const K_END: u8 = 0x00;
+const K_HEADER: u8 = 0x01;
+const K_ARCHIVE_PROPERTIES: u8 = 0x02;
+const K_ADDITIONAL_STREAMS_INFO: u8 = 0x03;
+const K_MAIN_STREAMS_INFO: u8 = 0x04;
+const K_FILES_INFO: u8 = 0x05;
+const K_PACK_INFO: u8 = 0x06;
+const K_UNPACK_INFO: u8 = 0x07;
+const K_SUBSTREAMS_INFO: u8 = 0x08;
+const K_SIZE: u8 = 0x09;
+const K_CRC: u8 = 0x0A;
+const K_FOLDER: u8 = 0x0B;
+const K_CODERS_UNPACK_SIZE: u8 = 0x0C;
+const K_NUM_UNPACK_STREAM: u8 = 0x0D;
+const K_EMPTY_STREAM: u8 = 0x0E;
+const K_EMPTY_FILE: u8 = 0x0F;
+const K_NAME: u8 = 0x11;
+const K_WIN_ATTRIBUTES: u8 = 0x15;
+const K_ENCODED_HEADER: u8 = 0x17;
+
+const CODEC_COPY: &[u8] = &[0x00];
+const CODEC_LZMA: &[u8] = &[0x03, 0x01, 0x01];
+const CODEC_LZMA2: &[u8] = &[0x21];
+const CODEC_DEFLATE: &[u8] = &[0x04, 0x01, 0x08];
+const CODEC_BZIP2: &[u8] = &[0x04, 0x02, 0x02];
+const CODEC_AES: &[u8] = &[0x06, 0xF1, 0x07, 0x01];
+
+const FILE_ATTRIBUTE_UNIX_EXTENSION: u32 = 0x8000;
+
+const S_IFMT: u32 = 0o170_000;
+const S_IFREG: u32 = 0o100_000;
+const S_IFDIR: u32 = 0o040_000;
+const S_IFLNK: u32 = 0o120_000;
+
+#[derive(Debug, Default)]
+struct Coder {
+    id: Vec<u8>,
+    props: Vec<u8>,
+}
+
+#[derive(Debug, Default)]
+struct Folder {
+    coder: Coder,
+    unpack_size: u64,
+    crc_defined: bool,
+}
+
+#[derive(Debug, Default)]
+struct StreamsInfo {
+    pack_pos: u64,
+    pack_sizes: Vec<u64>,
+    folders: Vec<Folder>,
+    /// Number of files packed into each folder.
+    substreams: Vec<u64>,
+    /// Sizes of every substream, in folder order.
+    substream_sizes: Vec<u64>,
+}
+
+#[derive(Debug, Default)]
+struct Entry {
+    name: String,
+    has_stream: bool,
+    is_dir: bool,
+    attributes: Option<u32>,
+}
+
+impl Entry {
+    fn unix_mode(&self) -> Option<u32> {
+        self.attributes
+            .filter(|a| a & FILE_ATTRIBUTE_UNIX_EXTENSION != 0)
+            .map(|a| a >> 16)
+    }
+
+    /// Reject device nodes, FIFOs and sockets.
+    fn check_kind(&self) -> Result<(), ExtractError> {
+        match self.unix_mode().map(|m| m & S_IFMT) {
+            None | Some(0 | S_IFREG | S_IFDIR | S_IFLNK) => Ok(()),
+            Some(_) => Err(ExtractGuard::special_file(Path::new(&self.name))),
+        }
+    }
+}
+
+/// Extract every entry of a 7z archive into `dest_dir`, checking each one
+/// against `guard`.
+///
+/// # Errors
+///
+/// Returns [`ExtractError`] if the archive is malformed, uses an unsupported
+/// coder, breaks the extraction policy, or an entry cannot be written.
+pub fn extract<R: Read + Seek>(
+    mut source: R,
+    dest_dir: &Path,
+    guard: &mut ExtractGuard,
+) -> Result<Vec<ExtractedFile>, ExtractError> {
+    let mut sig = [0u8; SIGNATURE_HEADER_LEN as usize];
+    source.read_exact(&mut sig)?;
+    if &sig[0..6] != SIGNATURE {
+        return Err(ExtractError::Archive("Not a 7z archive".to_string()));
+    }
+    let next_offset = u64::from_le_bytes(sig[12..20].try_into().unwrap_or_default());
+    let next_size = usize::try_from(u64::from_le_bytes(
+        sig[20..28].try_into().unwrap_or_default(),
+    ))
+    .map_err(|_| corrupt("header too large"))?;
+
+    fs::create_dir_all(dest_dir)?;
+    if next_size == 0 {
+        return Ok(Vec::new());
+    }
+
+    let archive_len = source.seek(SeekFrom::End(0))?;
+    let header_end = SIGNATURE_HEADER_LEN
+        .checked_add(next_offset)
+        .and_then(|start| start.checked_add(next_size as u64));
+    if header_end.is_none_or(|end| end > archive_len) {
+        return Err(corrupt("header lies beyond the end of the archive"));
+    }
+    source.seek(SeekFrom::Start(SIGNATURE_HEADER_LEN + next_offset))?;
+    let mut header = vec![0u8; next_size];
+    source.read_exact(&mut header)?;
+
+    // Real archives wrap the header in another compressed stream.
+    while header.first() == Some(&K_ENCODED_HEADER) {
+        let mut cur = Cursor::new(&header[1..]);
+        let streams = read_streams_info(&mut cur)?;
+        let folder = streams
+            .folders
+            .first()
+            .ok_or_else(|| corrupt("encoded header has no folder"))?;
+        if folder.unpack_size > MAX_HEADER_LEN {
+            return Err(corrupt("encoded header too large"));
+        }
+        let mut decoded = Vec::new();
+        let pack_start = SIGNATURE_HEADER_LEN + streams.pack_pos;
+        source.seek(SeekFrom::Start(pack_start))?;
+        let pack_len = streams.pack_sizes.first().copied().unwrap_or(0);
+        decode_folder(&mut (&mut source).take(pack_len), folder, &mut decoded)?;
+        header = decoded;
+    }
+
+    let mut cur = Cursor::new(&header);
+    if cur.byte()? != K_HEADER {
+        return Err(corrupt("missing header"));
+    }
+
+    let mut streams = StreamsInfo::default();
+    let mut entries = Vec::new();
+    loop {
+        match cur.byte()? {
+            K_END => break,
+            K_ARCHIVE_PROPERTIES => skip_properties(&mut cur)?,
+            K_ADDITIONAL_STREAMS_INFO => {
+                read_streams_info(&mut cur)?;
+            }
+            K_MAIN_STREAMS_INFO => streams = read_streams_info(&mut cur)?,
+            K_FILES_INFO => entries = read_files_info(&mut cur)?,
+            other => return Err(corrupt(&format!("unexpected header property {other:#x}"))),
+        }
+    }
+
+    write_entries(&mut source, &streams, &entries, dest_dir, guard)
+}
+
+fn write_entries<R: Read + Seek>(
+    source: &mut R,
+    streams: &StreamsInfo,
+    entries: &[Entry],
+    dest_dir: &Path,
+    guard: &mut ExtractGuard,
+) -> Result<Vec<ExtractedFile>, ExtractError> {
+    let mut extracted = Vec::new();
+    let mut with_stream = entries.iter().filter(|e| e.has_stream);
+    let mut sizes = streams.substream_sizes.iter();
+    let mut pack_offset = SIGNATURE_HEADER_LEN + streams.pack_pos;
+
+    // Directories and empty files first so that folder output can land in them.
+    for entry in entries.iter().filter(|e| !e.has_stream) {
+        let (absolute_path, relative_path) = resolve(dest_dir, &entry.name, guard)?;
+        entry.check_kind()?;
+        if entry.is_dir {
+            fs::create_dir_all(&absolute_path)?;
+        } else {
+            create_parent(&absolute_path)?;
+            File::create(&absolute_path)?;
+            extracted.push(ExtractedFile {
+                relative_path,
+                absolute_path,
+                is_executable: entry.unix_mode().is_some_and(|m| m & 0o111 != 0),
+            });
+        }
+    }
+
+    for (index, folder) in streams.folders.iter().enumerate() {
+        let count = streams.substreams.get(index).copied().unwrap_or(1);
+        let mut targets = Vec::new();
+        for _ in 0..count {
+            let entry = with_stream
+                .next()
+                .ok_or_else(|| corrupt("more streams than files"))?;
+            let size = *sizes
+                .next()
+                .ok_or_else(|| corrupt("missing substream size"))?;
+            let (absolute_path, relative_path) = resolve(dest_dir, &entry.name, guard)?;
+            entry.check_kind()?;
+            create_parent(&absolute_path)?;
+            targets.push(Target {
+                size,
+                absolute_path,
+                relative_path,
+                mode: entry.unix_mode(),
+            });
+        }
+
+        // Decoders never produce more than the folder's declared size.
+        if let Some(first) = targets.first() {
+            guard.reserve(&first.relative_path, folder.unpack_size)?;
+        }
+
+        let pack_len = streams.pack_sizes.get(index).copied().unwrap_or(0);
+        source.seek(SeekFrom::Start(pack_offset))?;
+        pack_offset += pack_len;
+
+        let mut sink = FolderSink::new(targets);
+        decode_folder(&mut (&mut *source).take(pack_len), folder, &mut sink)?;
+        let (files, links) = sink.finish()?;
+        extracted.extend(files);
+
+        for (target, data) in links {
+            let link = PathBuf::from(String::from_utf8_lossy(&data).into_owned());
+            guard.symlink(
+                dest_dir,
+                &target.relative_path,
+                &target.absolute_path,
+                &link,
+            )?;
+            let _ = fs::remove_file(&target.absolute_path);
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(&link, &target.absolute_path)?;
+        }
+    }
+
+    Ok(extracted)
+}
+
+fn resolve(
+    dest_dir: &Path,
+    name: &str,
+    guard: &mut ExtractGuard,
+) -> Result<(PathBuf, PathBuf), ExtractError> {
+    let entry_path = Path::new(name);
+    let absolute_path = guard.entry(dest_dir, entry_path)?;
+    let relative_path = absolute_path
+        .strip_prefix(dest_dir)
+        .map_or_else(|_| entry_path.to_path_buf(), Path::to_path_buf);
+    Ok((absolute_path, relative_path))
+}
+
+fn create_parent(path: &Path) -> io::Result<()> {
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    Ok(())
+}
+
+/// Run one folder's packed bytes through its coder into `out`.
+fn decode_folder<R: Read, W: Write>(
+    packed: &mut R,
+    folder: &Folder,
+    out: &mut W,
+) -> Result<(), ExtractError> {
+    let id = folder.coder.id.as_slice();
+    let mut limited = out_limit(out, folder.unpack_size);
+    match id {
+        CODEC_COPY => {
+            io::copy(packed, &mut limited)?;
+        }
+        CODEC_LZMA => {
+            let props = folder
+                .coder
+                .props
+                .get(..5)
+                .ok_or_else(|| corrupt("bad LZMA props"))?;
+            let mut input = BufReader::new(props.chain(packed));
+            let options = lzma_rs::decompress::Options {
+                unpacked_size: lzma_rs::decompress::UnpackedSize::UseProvided(Some(
+                    folder.unpack_size,
+                )),
+                ..Default::default()
+            };
+            lzma_rs::lzma_decompress_with_options(&mut input, &mut limited, &options)
+                .map_err(|e| ExtractError::Archive(format!("LZMA: {e}")))?;
+        }
+        CODEC_LZMA2 => {
+            lzma_rs::lzma2_decompress(&mut BufReader::new(packed), &mut limited)
+                .map_err(|e| ExtractError::Archive(format!("LZMA2: {e}")))?;
+        }
+        CODEC_DEFLATE => {
+            io::copy(&mut flate2::read::DeflateDecoder::new(packed), &mut limited)?;
+        }
+        CODEC_BZIP2 => {
+            io::copy(&mut bzip2::read::BzDecoder::new(packed), &mut limited)?;
+        }
+        CODEC_AES => {
+            return Err(ExtractError::UnsupportedFormat(
+                "encrypted 7z archives".to_string(),
+            ));
+        }
+        other => {
+            return Err(ExtractError::UnsupportedFormat(format!(
+                "7z coder {}",
+                hex::encode(other)
+            )));
+        }
+    }
+    limited.flush()?;
+    Ok(())
+}
+
+/// Cap the bytes written to a folder at its declared unpack size; some
+/// coders pad their final block.
+fn out_limit<W: Write>(inner: &mut W, limit: u64) -> LimitedWriter<'_, W> {
+    LimitedWriter {
+        inner,
+        remaining: limit,
+    }
+}
+
+struct LimitedWriter<'a, W> {
+    inner: &'a mut W,
+    remaining: u64,
+}
+
+impl<W: Write> Write for LimitedWriter<'_, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = buf
+            .len()
+            .min(usize::try_from(self.remaining).unwrap_or(usize::MAX));
+        if n > 0 {
+            self.inner.write_all(&buf[..n])?;
+            self.remaining -= n as u64;
+        }
+        // Pretend to accept the excess so decoders don't fail on it.
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+struct Target {
+    size: u64,
+    absolute_path: PathBuf,
+    relative_path: PathBuf,
+    mode: Option<u32>,
+}
+
+/// Splits a solid folder's output stream across the files it contains.
+struct FolderSink {
+    targets: std::vec::IntoIter<Target>,
+    current: Option<(Target, u64, Output)>,
+    done: Vec<ExtractedFile>,
+    /// Symlinks are created once the whole folder is out, after the policy
+    /// has seen their targets.
+    links: Vec<PendingLink>,
+}
+
+/// A symlink entry and the target bytes decoded for it.
+type PendingLink = (Target, Vec<u8>);
+
+enum Output {
+    File(io::BufWriter<File>),
+    Link(Vec<u8>),
+}
+
+impl FolderSink {
+    fn new(targets: Vec<Target>) -> Self {
+        Self {
+            targets: targets.into_iter(),
+            current: None,
+            done: Vec::new(),
+            links: Vec::new(),
+        }
+    }
+
+    fn open_next(&mut self) -> io::Result<bool> {
+        let Some(target) = self.targets.next() else {
+            return Ok(false);
+        };
+        let output = if target.mode.is_some_and(|m| m & S_IFMT == S_IFLNK) {
+            Output::Link(Vec::new())
+        } else {
+            Output::File(io::BufWriter::new(File::create(&target.absolute_path)?))
+        };
+        self.current = Some((target, 0, output));
+        Ok(true)
+    }
+
+    fn close_current(&mut self) -> io::Result<()> {
+        let Some((target, _, output)) = self.current.take() else {
+            return Ok(());
+        };
+        match output {
+            Output::File(mut w) => {
+                w.flush()?;
+                #[cfg(unix)]
+                if let Some(mode) = target.mode {
+                    use std::os::unix::fs::PermissionsExt;
+                    fs::set_permissions(
+                        &target.absolute_path,
+                        fs::Permissions::from_mode(mode & 0o7777),
+                    )?;
+                }
+                self.done.push(ExtractedFile {
+                    is_executable: target.mode.is_some_and(|m| m & 0o111 != 0),
+                    relative_path: target.relative_path,
+                    absolute_path: target.absolute_path,
+                });
+            }
+            Output::Link(data) => self.links.push((target, data)),
+        }
+        Ok(())
+    }
+
+    fn finish(mut self) -> Result<(Vec<ExtractedFile>, Vec<PendingLink>), ExtractError> {
+        if let Some((target, written, _)) = &self.current
+            && *written < target.size
+        {
+            return Err(corrupt("folder ended before its last file"));
+        }
+        self.close_current()?;
+        // Zero-length streams at the end never receive a write.
+        while self.open_next()? {
+            self.close_current()?;
+        }
+        Ok((self.done, self.links))
+    }
+}
+
+impl Write for FolderSink {
+    fn write(&mut self, mut buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len();
+        while !buf.is_empty() {
+            if self.current.is_none() && !self.open_next()? {
+                // Trailing data past the last file; nothing to store it in.
+                return Ok(len);
+            }
+            let Some((target, written, output)) = self.current.as_mut() else {
+                return Ok(len);
+            };
+            let n = buf
+                .len()
+                .min(usize::try_from(target.size - *written).unwrap_or(usize::MAX));
+            match output {
+                Output::File(w) => w.write_all(&buf[..n])?,
+                Output::Link(data) => data.extend_from_slice(&buf[..n]),
+            }
+            *written += n as u64;
+            buf = &buf[n..];
+            if *written == target.size {
+                self.close_current()?;
+            }
+        }
+        Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        if let Some((_, _, Output::File(w))) = self.current.as_mut() {
+            w.flush()?;
+        }
+        Ok(())
+    }
+}
+
+fn read_streams_info(cur: &mut Cursor<'_>) -> Result<StreamsInfo, ExtractError> {
+    let mut info = StreamsInfo::default();
+    loop {
+        match cur.byte()? {
+            K_END => break,
+            K_PACK_INFO => {
+                info.pack_pos = cur.number()?;
+                let count = cur.count()?;
+                loop {
+                    match cur.byte()? {
+                        K_END => break,
+                        K_SIZE => {
+                            info.pack_sizes =
+                                (0..count).map(|_| cur.number()).collect::<Result<_, _>>()?;
+                        }
+                        K_CRC => {
+                            cur.digests(count)?;
+                        }
+                        _ => return Err(corrupt("bad pack info")),
+                    }
+                }
+            }
+            K_UNPACK_INFO => read_unpack_info(cur, &mut info)?,
+            K_SUBSTREAMS_INFO => read_substreams_info(cur, &mut info)?,
+            _ => return Err(corrupt("bad streams info")),
+        }
+    }
+
+    if info.substreams.is_empty() {
+        info.substreams = vec![1; info.folders.len()];
+        info.substream_sizes = info.folders.iter().map(|f| f.unpack_size).collect();
+    }
+    Ok(info)
+}
+
+fn read_unpack_info(cur: &mut Cursor<'_>, info: &mut StreamsInfo) -> Result<(), ExtractError> {
+    if cur.byte()? != K_FOLDER {
+        return Err(corrupt("expected folder list"));
+    }
+    let count = cur.count()?;
+    if cur.byte()? != 0 {
+        return Err(ExtractError::UnsupportedFormat(
+            "7z external folder data".to_string(),
+        ));
+    }
+    let mut out_streams = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (folder, outs) = read_folder(cur)?;
+        info.folders.push(folder);
+        out_streams.push(outs);
+    }
+
+    loop {
+        match cur.byte()? {
+            K_END => break,
+            K_CODERS_UNPACK_SIZE => {
+                for (folder, outs) in info.folders.iter_mut().zip(&out_streams) {
+                    // Single-coder folders have exactly one output.
+                    folder.unpack_size = cur.number()?;
+                    for _ in 1..*outs {
+                        cur.number()?;
+                    }
+                }
+            }
+            K_CRC => {
+                let defined = cur.digests(count)?;
+                for (folder, d) in info.folders.iter_mut().zip(defined) {
+                    folder.crc_defined = d;
+                }
+            }
+            _ => return Err(corrupt("bad unpack info")),
+        }
+    }
+    Ok(())
+}
+
+fn read_folder(cur: &mut Cursor<'_>) -> Result<(Folder, u64), ExtractError> {
+    let num_coders = cur.number()?;
+    if num_coders != 1 {
+        return Err(ExtractError::UnsupportedFormat(
+            "7z coder chains (BCJ/BCJ2 filters)".to_string(),
+        ));
+    }
+    let flags = cur.byte()?;
+    let id = cur.bytes(usize::from(flags & 0x0F))?.to_vec();
+    let (ins, outs) = if flags & 0x10 != 0 {
+        (cur.number()?, cur.number()?)
+    } else {
+        (1, 1)
+    };
+    let props = if flags & 0x20 != 0 {
+        let len = cur.count()?;
+        cur.bytes(len)?.to_vec()
+    } else {
+        Vec::new()
+    };
+    if ins != 1 || outs != 1 {
+        return Err(ExtractError::UnsupportedFormat(
+            "7z multi-stream coders".to_string(),
+        ));
+    }
+    Ok((
+        Folder {
+            coder: Coder { id, props },
+            ..Folder::default()
+        },
+        outs,
+    ))
+}
+
+fn read_substreams_info(cur: &mut Cursor<'_>, info: &mut StreamsInfo) -> Result<(), ExtractError> {
+    info.substreams = vec![1; info.folders.len()];
+    let mut sizes_read = false;
+    loop {
+        match cur.byte()? {
+            K_END => break,
+            K_NUM_UNPACK_STREAM => {
+                for n in &mut info.substreams {
+                    *n = cur.number()?;
+                }
+            }
+            K_SIZE => {
+                sizes_read = true;
+                info.substream_sizes = split_sizes(info, |_| cur.number())?;
+            }
+            K_CRC => {
+                let count = info
+                    .folders
+                    .iter()
+                    .zip(&info.substreams)
+                    .map(|(f, n)| if *n == 1 && f.crc_defined { 0 } else { *n })
+                    .sum::<u64>();
+                cur.digests(usize::try_from(count).map_err(|_| corrupt("too many digests"))?)?;
+            }
+            _ => return Err(corrupt("bad substreams info")),
+        }
+    }
+    if !sizes_read {
+        info.substream_sizes = split_sizes(info, |_| Ok(0))?;
+    }
+    Ok(())
+}
+
+/// Expand per-folder substream counts into sizes; the last size in each
+/// folder is implied by the folder's total.
+fn split_sizes<F>(info: &StreamsInfo, mut next: F) -> Result<Vec<u64>, ExtractError>
+where
+    F: FnMut(usize) -> Result<u64, ExtractError>,
+{
+    let mut sizes = Vec::new();
+    for (folder, count) in info.folders.iter().zip(&info.substreams) {
+        if *count == 0 {
+            continue;
+        }
+        let mut sum = 0u64;
+        for i in 1..*count {
+            let size = next(usize::try_from(i).unwrap_or(0))?;
+            sum += size;
+            sizes.push(size);
+        }
+        sizes.push(
+            folder
+                .unpack_size
+                .checked_sub(sum)
+                .ok_or_else(|| corrupt("substream sizes exceed folder"))?,
+        );
+    }
+    Ok(sizes)
+}
+
+fn read_files_info(cur: &mut Cursor<'_>) -> Result<Vec<Entry>, ExtractError> {
+    let count = cur.count()?;
+    let mut entries: Vec<Entry> = (0..count)
+        .map(|_| Entry {
+            has_stream: true,
+            ..Entry::default()
+        })
+        .collect();
+    let mut empty_streams: Vec<usize> = Vec::new();
+
+    loop {
+        let kind = cur.byte()?;
+        if kind == K_END {
+            break;
+        }
+        let size = cur.count()?;
+        let mut prop = Cursor::new(cur.bytes(size)?);
+        match kind {
+            K_EMPTY_STREAM => {
+                for (i, empty) in prop.bits(count)?.into_iter().enumerate() {
+                    if empty {
+                        entries[i].has_stream = false;
+                        entries[i].is_dir = true;
+                        empty_streams.push(i);
+                    }
+                }
+            }
+            K_EMPTY_FILE => {
+                for (j, empty_file) in prop.bits(empty_streams.len())?.into_iter().enumerate() {
+                    if empty_file {
+                        entries[empty_streams[j]].is_dir = false;
+                    }
+                }
+            }
+            K_NAME => {
+                if prop.byte()? != 0 {
+                    return Err(ExtractError::UnsupportedFormat(
+                        "7z external names".to_string(),
+                    ));
+                }
+                for entry in &mut entries {
+                    let mut units = Vec::new();
+                    loop {
+                        let unit = u16::from_le_bytes([prop.byte()?, prop.byte()?]);
+                        if unit == 0 {
+                            break;
+                        }
+                        units.push(unit);
+                    }
+                    entry.name = String::from_utf16_lossy(&units).replace('\\', "/");
+                }
+            }
+            K_WIN_ATTRIBUTES => {
+                let defined = prop.defined_vector(count)?;
+                if prop.byte()? != 0 {
+                    return Err(ExtractError::UnsupportedFormat(
+                        "7z external attributes".to_string(),
+                    ));
+                }
+                for (entry, d) in entries.iter_mut().zip(defined) {
+                    if d {
+                        entry.attributes = Some(prop.u32()?);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    Ok(entries)
+}
+
+fn skip_properties(cur: &mut Cursor<'_>) -> Result<(), ExtractError> {
+    loop {
+        if cur.byte()? == K_END {
+            return Ok(());
+        }
+        let size = cur.count()?;
+        cur.bytes(size)?;
+    }
+}
+
+fn corrupt(what: &str) -> ExtractError {
+    ExtractError::Archive(format!("Corrupt 7z archive: {what}"))
+}
+
+struct Cursor<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Cursor<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn byte(&mut self) -> Result<u8, ExtractError> {
+        let b = *self
+            .buf
+            .get(self.pos)
+            .ok_or_else(|| corrupt("truncated header"))?;
+        self.pos += 1;
+        Ok(b)
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], ExtractError> {
+        let out = self
+            .pos
+            .checked_add(n)
+            .and_then(|end| self.buf.get(self.pos..end))
+            .ok_or_else(|| corrupt("truncated header"))?;
+        self.pos += n;
+        Ok(out)
+    }
+
+    fn u32(&mut self) -> Result<u32, ExtractError> {
+        let b = self.bytes(4)?;
+        Ok(u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+    }
+
+    /// 7z variable-length integer: the count of leading one bits in the
+    /// first byte gives the number of little-endian bytes that follow.
+    fn number(&mut self) -> Result<u64, ExtractError> {
+        let first = self.byte()?;
+        let mut mask = 0x80u8;
+        let mut value = 0u64;
+        for i in 0..8 {
+            if first & mask == 0 {
+                let high = u64::from(first & mask.wrapping_sub(1));
+                return Ok(value | (high << (8 * i)));
+            }
+            value |= u64::from(self.byte()?) << (8 * i);
+            mask >>= 1;
+        }
+        Ok(value)
+    }
+
+    fn count(&mut self) -> Result<usize, ExtractError> {
+        let n = self.number()?;
+        usize::try_from(n)
+            .ok()
+            .filter(|n| *n <= self.buf.len().max(1 << 20))
+            .ok_or_else(|| corrupt("implausible count"))
+    }
+
+    fn bits(&mut self, n: usize) -> Result<Vec<bool>, ExtractError> {
+        let bytes = self.bytes(n.div_ceil(8))?;
+        Ok((0..n)
+            .map(|i| bytes[i / 8] & (0x80 >> (i % 8)) != 0)
+            .collect())
+    }
+
+    fn defined_vector(&mut self, n: usize) -> Result<Vec<bool>, ExtractError> {
+        if self.byte()? == 0 {
+            self.bits(n)
+        } else {
+            Ok(vec![true; n])
+        }
+    }
+
+    /// Skip a digest list, returning which entries had a CRC.
+    fn digests(&mut self, n: usize) -> Result<Vec<bool>, ExtractError> {
+        let defined = self.defined_vector(n)?;
+        for _ in defined.iter().filter(|d| **d) {
+            self.u32()?;
+        }
+        Ok(defined)
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    const FILE_ATTRIBUTE_DIRECTORY: u32 = 0x10;
+
+    fn number(out: &mut Vec<u8>, n: u64) {
+        // Values used by the tests stay below 2^14.
+        if n < 0x80 {
+            out.push(n as u8);
+        } else {
+            out.push(0x80 | (n >> 8) as u8);
+            out.push((n & 0xFF) as u8);
+        }
+    }
+
+    /// Build a 7z archive with a single LZMA2 folder holding `files` (solid),
+    /// plus `dirs` as empty-stream directory entries. The header is wrapped in
+    /// a Copy-coded encoded header when `encode_header` is set.
+    pub(crate) fn build_7z(
+        dirs: &[&str],
+        files: &[(&str, u32, &[u8])],
+        encode_header: bool,
+    ) -> Vec<u8> {
+        let plain: Vec<u8> = files
+            .iter()
+            .flat_map(|(_, _, d)| d.iter().copied())
+            .collect();
+        let mut packed = Vec::new();
+        lzma_rs::lzma2_compress(&mut plain.as_slice(), &mut packed).unwrap();
+
+        let mut h = vec![K_HEADER, K_MAIN_STREAMS_INFO];
+        // PackInfo
+        h.push(K_PACK_INFO);
+        number(&mut h, 0);
+        number(&mut h, 1);
+        h.push(K_SIZE);
+        number(&mut h, packed.len() as u64);
+        h.push(K_END);
+        // UnpackInfo with one LZMA2 folder
+        h.extend_from_slice(&[K_UNPACK_INFO, K_FOLDER]);
+        number(&mut h, 1);
+        h.push(0);
+        number(&mut h, 1);
+        h.extend_from_slice(&[0x21, 0x21, 1, 0x18]);
+        h.push(K_CODERS_UNPACK_SIZE);
+        number(&mut h, plain.len() as u64);
+        h.push(K_END);
+        // SubStreamsInfo
+        h.push(K_SUBSTREAMS_INFO);
+        h.push(K_NUM_UNPACK_STREAM);
+        number(&mut h, files.len() as u64);
+        h.push(K_SIZE);
+        for (_, _, d) in &files[..files.len() - 1] {
+            number(&mut h, d.len() as u64);
+        }
+        h.extend_from_slice(&[K_END, K_END]);
+
+        // FilesInfo: files with streams first, then directories.
+        let total = files.len() + dirs.len();
+        h.push(K_FILES_INFO);
+        number(&mut h, total as u64);
+
+        let mut bits = vec![0u8; total.div_ceil(8)];
+        for i in files.len()..total {
+            bits[i / 8] |= 0x80 >> (i % 8);
+        }
+        h.push(K_EMPTY_STREAM);
+        number(&mut h, bits.len() as u64);
+        h.extend_from_slice(&bits);
+
+        let mut names = vec![0u8];
+        for name in files.iter().map(|f| f.0).chain(dirs.iter().copied()) {
+            for u in name.encode_utf16() {
+                names.extend_from_slice(&u.to_le_bytes());
+            }
+            names.extend_from_slice(&[0, 0]);
+        }
+        h.push(K_NAME);
+        number(&mut h, names.len() as u64);
+        h.extend_from_slice(&names);
+
+        let mut attrs = vec![1u8, 0];
+        for (_, mode, _) in files {
+            attrs.extend_from_slice(&(FILE_ATTRIBUTE_UNIX_EXTENSION | (mode << 16)).to_le_bytes());
+        }
+        for _ in dirs {
+            attrs.extend_from_slice(
+                &(FILE_ATTRIBUTE_DIRECTORY
+                    | FILE_ATTRIBUTE_UNIX_EXTENSION
+                    | ((S_IFDIR | 0o755) << 16))
+                    .to_le_bytes(),
+            );
+        }
+        h.push(K_WIN_ATTRIBUTES);
+        number(&mut h, attrs.len() as u64);
+        h.extend_from_slice(&attrs);
+        h.extend_from_slice(&[K_END, K_END]);
+
+        let mut body = packed;
+        let header = if encode_header {
+            let pos = body.len() as u64;
+            body.extend_from_slice(&h);
+            let mut e = vec![K_ENCODED_HEADER, K_PACK_INFO];
+            number(&mut e, pos);
+            number(&mut e, 1);
+            e.push(K_SIZE);
+            number(&mut e, h.len() as u64);
+            e.extend_from_slice(&[K_END, K_UNPACK_INFO, K_FOLDER]);
+            number(&mut e, 1);
+            e.push(0);
+            number(&mut e, 1);
+            e.extend_from_slice(&[0x01, 0x00]);
+            e.push(K_CODERS_UNPACK_SIZE);
+            number(&mut e, h.len() as u64);
+            e.extend_from_slice(&[K_END, K_END]);
+            e
+        } else {
+            h
+        };
+
+        let mut out = SIGNATURE.to_vec();
+        out.extend_from_slice(&[0, 4, 0, 0, 0, 0]);
+        out.extend_from_slice(&(body.len() as u64).to_le_bytes());
+        out.extend_from_slice(&(header.len() as u64).to_le_bytes());
+        out.extend_from_slice(&[0; 4]);
+        out.extend_from_slice(&body);
+        out.extend_from_slice(&header);
+        out
+    }
+
+    #[test]
+    fn test_extract_7z() {
+        for encode_header in [false, true] {
+            let dir = tempfile::tempdir().unwrap();
+            let archive = build_7z(
+                &["tool/bin"],
+                &[
+                    ("tool/bin/tool", 0o100_755, b"#!/bin/sh\necho 7z\n"),
+                    ("tool/README", 0o100_644, b"readme"),
+                    ("tool/bin/link", S_IFLNK | 0o777, b"tool"),
+                ],
+                encode_header,
+            );
+
+            let files = extract(
+                std::io::Cursor::new(archive),
+                dir.path(),
+                &mut ExtractGuard::default(),
+            )
+            .unwrap();
+            assert_eq!(files.len(), 2);
+            assert_eq!(
+                fs::read(dir.path().join("tool/bin/tool")).unwrap(),
+                b"#!/bin/sh\necho 7z\n"
+            );
+            assert!(files.iter().any(|f| f.is_executable));
+            assert_eq!(fs::read(dir.path().join("tool/README")).unwrap(), b"readme");
+            assert_eq!(
+                fs::read_link(dir.path().join("tool/bin/link")).unwrap(),
+                Path::new("tool")
+            );
+        }
+    }
+
+    #[test]
+    fn test_rejects_traversal() {
+        let dir = tempfile::tempdir().unwrap();
+        let archive = build_7z(&[], &[("../evil", 0o100_644, b"x")], false);
+        assert!(
+            extract(
+                std::io::Cursor::new(archive),
+                dir.path(),
+                &mut ExtractGuard::default()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_number_encoding() {
+        let mut c = Cursor::new(&[0x05, 0x81, 0x02, 0xC0, 0x34, 0x12]);
+        assert_eq!(c.number().unwrap(), 5);
+        assert_eq!(c.number().unwrap(), 0x102);
+        assert_eq!(c.number().unwrap(), 0x1234);
+    }
+}
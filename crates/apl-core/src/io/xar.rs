@@ -0,0 +1,501 @@
+//! Minimal reader for xar archives (the container format of macOS `.pkg` files).
+//!
+//! A xar file is a fixed 28-byte header, a zlib-compressed XML table of
+//! contents (TOC), and a heap holding the (optionally compressed) file data.
+//! Only the subset needed to unpack installer packages is implemented:
+//! walking the TOC and streaming individual entries out of the heap.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+
+use flate2::read::ZlibDecoder;
+use quick_xml::Reader;
+use quick_xml::events::Event;
+
+use super::extract::ExtractError;
+
+/// Magic number at the start of every xar archive (`"xar!"`).
+pub const XAR_MAGIC: [u8; 4] = *b"xar!";
+
+const HEADER_LEN: usize = 28;
+
+/// Largest table of contents read. The header's sizes are untrusted; real
+/// TOCs list a handful of files and are a few KiB.
+const MAX_TOC_LEN: u64 = 64 * 1024 * 1024;
+
+/// Largest entry [`XarArchive::read_to_vec`] reads into memory.
+pub const MAX_READ_LEN: u64 = 64 * 1024 * 1024;
+
+/// The kind of an entry in the xar table of contents.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XarEntryKind {
+    /// A regular file with data in the heap.
+    File,
+    /// A directory; its children appear as separate entries.
+    Directory,
+    /// Any other entry type (symlinks, devices), which installers do not use.
+    Other,
+}
+
+/// Compression applied to an entry's data in the heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum XarEncoding {
+    /// Stored uncompressed (`application/octet-stream`).
+    None,
+    /// zlib stream (`application/x-gzip`, despite the name).
+    Zlib,
+    /// bzip2 stream (`application/x-bzip2`).
+    Bzip2,
+    /// xz/lzma stream (`application/x-lzma`, `application/x-xz`).
+    Xz,
+}
+
+impl XarEncoding {
+    fn from_style(style: &str) -> Result<Self, ExtractError> {
+        match style {
+            "application/octet-stream" => Ok(Self::None),
+            "application/x-gzip" => Ok(Self::Zlib),
+            "application/x-bzip2" => Ok(Self::Bzip2),
+            "application/x-lzma" | "application/x-xz" => Ok(Self::Xz),
+            other => Err(ExtractError::UnsupportedFormat(format!(
+                "xar encoding {other}"
+            ))),
+        }
+    }
+}
+
+/// Location of an entry's data inside the heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct XarData {
+    /// Offset relative to the start of the heap.
+    pub offset: u64,
+    /// Number of bytes stored in the heap (after encoding).
+    pub length: u64,
+    /// Size of the data once decoded.
+    pub size: u64,
+    /// Encoding applied to the stored bytes.
+    pub encoding: XarEncoding,
+}
+
+/// A single entry from the xar table of contents.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct XarEntry {
+    /// Path of the entry relative to the archive root.
+    pub path: PathBuf,
+    /// Entry type.
+    pub kind: XarEntryKind,
+    /// Heap location for file entries.
+    pub data: Option<XarData>,
+}
+
+/// An opened xar archive.
+#[derive(Debug)]
+pub struct XarArchive {
+    file: BufReader<File>,
+    len: u64,
+    heap_start: u64,
+    entries: Vec<XarEntry>,
+}
+
+impl XarArchive {
+    /// Open a xar archive and parse its table of contents.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ExtractError`] if the file cannot be read, the header magic
+    /// is wrong, or the TOC cannot be decompressed or parsed.
+    pub fn open(path: &Path) -> Result<Self, ExtractError> {
+        let file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut file = BufReader::new(file);
+
+        let mut header = [0u8; HEADER_LEN];
+        file.read_exact(&mut header)?;
+        if header[0..4] != XAR_MAGIC {
+            return Err(ExtractError::Archive(format!(
+                "{} is not a xar archive",
+                path.display()
+            )));
+        }
+
+        let header_size = u64::from(u16::from_be_bytes([header[4], header[5]]));
+        let toc_compressed = u64::from_be_bytes(header[8..16].try_into().unwrap_or_default());
+        let toc_uncompressed = u64::from_be_bytes(header[16..24].try_into().unwrap_or_default());
+
+        let heap_start = header_size
+            .checked_add(toc_compressed)
+            .filter(|&end| end <= len)
+            .ok_or_else(|| ExtractError::Archive("xar TOC extends past the file".to_string()))?;
+        if toc_uncompressed > MAX_TOC_LEN {
+            return Err(ExtractError::Archive(format!(
+                "xar TOC of {toc_uncompressed} bytes is too large"
+            )));
+        }
+
+        file.seek(SeekFrom::Start(header_size))?;
+        let mut toc_xml = String::with_capacity(toc_uncompressed as usize);
+        ZlibDecoder::new((&mut file).take(toc_compressed))
+            .take(toc_uncompressed)
+            .read_to_string(&mut toc_xml)
+            .map_err(|e| ExtractError::Archive(format!("Corrupt xar TOC: {e}")))?;
+
+        let entries = parse_toc(&toc_xml)?;
+
+        Ok(Self {
+            file,
+            len,
+            heap_start,
+            entries,
+        })
+    }
+
+    /// All entries in the table of contents, sorted by path.
+    pub fn entries(&self) -> &[XarEntry] {
+        &self.entries
+    }
+
+    /// Find a file entry by its archive path.
+    pub fn find(&self, path: &Path) -> Option<&XarEntry> {
+        self.entries
+            .iter()
+            .find(|e| e.kind == XarEntryKind::File && e.path == path)
+    }
+
+    /// Stream the raw (still encoded) bytes of an entry out of the heap.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the entry lies outside the file or the heap
+    /// cannot be seeked.
+    pub fn raw_reader(&mut self, data: &XarData) -> io::Result<impl Read + '_> {
+        let start = self
+            .heap_start
+            .checked_add(data.offset)
+            .filter(|start| {
+                start
+                    .checked_add(data.length)
+                    .is_some_and(|end| end <= self.len)
+            })
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "xar entry extends past the file",
+                )
+            })?;
+        self.file.seek(SeekFrom::Start(start))?;
+        Ok((&mut self.file).take(data.length))
+    }
+
+    /// Stream the decoded bytes of an entry.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the heap cannot be seeked.
+    pub fn reader(&mut self, data: &XarData) -> io::Result<Box<dyn Read + '_>> {
+        let encoding = data.encoding;
+        Ok(decode(self.raw_reader(data)?, encoding))
+    }
+
+    /// Read a (small) entry fully into memory.
+    ///
+    /// # Errors
+    ///
+    /// Returns an I/O error if the entry cannot be read or decoded, or
+    /// decodes to more than [`MAX_READ_LEN`] bytes.
+    pub fn read_to_vec(&mut self, data: &XarData) -> io::Result<Vec<u8>> {
+        let too_large = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("xar entry larger than {MAX_READ_LEN} bytes"),
+            )
+        };
+        if data.size > MAX_READ_LEN {
+            return Err(too_large());
+        }
+        let mut buf = Vec::with_capacity(data.size as usize);
+        self.reader(data)?
+            .take(MAX_READ_LEN + 1)
+            .read_to_end(&mut buf)?;
+        if buf.len() as u64 > MAX_READ_LEN {
+            return Err(too_large());
+        }
+        Ok(buf)
+    }
+}
+
+/// Wrap a raw heap reader in the decoder for `encoding`.
+pub fn decode<'a, R: Read + 'a>(raw: R, encoding: XarEncoding) -> Box<dyn Read + 'a> {
+    match encoding {
+        XarEncoding::None => Box::new(raw),
+        XarEncoding::Zlib => Box::new(ZlibDecoder::new(raw)),
+        XarEncoding::Bzip2 => Box::new(bzip2::read::BzDecoder::new(raw)),
+        XarEncoding::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(raw)),
+    }
+}
+
+/// In-progress `<file>` element while walking the TOC.
+#[derive(Default)]
+struct PendingFile {
+    name: String,
+    kind: Option<String>,
+    offset: Option<u64>,
+    length: Option<u64>,
+    size: Option<u64>,
+    encoding: Option<String>,
+}
+
+impl PendingFile {
+    fn finish(self, parent: &Path) -> Result<XarEntry, ExtractError> {
+        let kind = match self.kind.as_deref() {
+            Some("file") => XarEntryKind::File,
+            Some("directory") => XarEntryKind::Directory,
+            _ => XarEntryKind::Other,
+        };
+
+        let data = match (kind, self.offset, self.length) {
+            (XarEntryKind::File, Some(offset), Some(length)) => Some(XarData {
+                offset,
+                length,
+                size: self.size.unwrap_or(length),
+                encoding: XarEncoding::from_style(
+                    self.encoding
+                        .as_deref()
+                        .unwrap_or("application/octet-stream"),
+                )?,
+            }),
+            _ => None,
+        };
+
+        Ok(XarEntry {
+            path: parent.join(self.name),
+            kind,
+            data,
+        })
+    }
+}
+
+/// Parse the XML table of contents into a flat list of entries.
+fn parse_toc(xml: &str) -> Result<Vec<XarEntry>, ExtractError> {
+    let mut reader = Reader::from_str(xml);
+    reader.trim_text(true);
+
+    let mut entries = Vec::new();
+    // Stack of open <file> elements; the path of each is its ancestors' names joined.
+    let mut stack: Vec<PendingFile> = Vec::new();
+    // Element names since the innermost <file>, to know what a text node belongs to.
+    let mut element_path: Vec<Vec<u8>> = Vec::new();
+
+    loop {
+        let event = reader
+            .read_event()
+            .map_err(|e| ExtractError::Archive(format!("Invalid xar TOC: {e}")))?;
+
+        match event {
+            Event::Start(e) => {
+                let name = e.local_name().as_ref().to_vec();
+                if name == b"file" {
+                    stack.push(PendingFile::default());
+                    element_path.clear();
+                } else {
+                    if name == b"encoding" {
+                        set_encoding(&e, stack.last_mut());
+                    }
+                    element_path.push(name);
+                }
+            }
+            Event::Empty(e) => {
+                if e.local_name().as_ref() == b"encoding" {
+                    set_encoding(&e, stack.last_mut());
+                }
+            }
+            Event::Text(t) => {
+                let Some(current) = stack.last_mut() else {
+                    continue;
+                };
+                let text = t
+                    .unescape()
+                    .map_err(|e| ExtractError::Archive(format!("Invalid xar TOC: {e}")))?;
+                let field = element_path.iter().map(Vec::as_slice).collect::<Vec<_>>();
+                match field.as_slice() {
+                    [b"name"] => current.name = text.into_owned(),
+                    [b"type"] => current.kind = Some(text.into_owned()),
+                    [b"data", b"offset"] => current.offset = text.parse().ok(),
+                    [b"data", b"length"] => current.length = text.parse().ok(),
+                    [b"data", b"size"] => current.size = text.parse().ok(),
+                    _ => {}
+                }
+            }
+            Event::End(e) => {
+                if e.local_name().as_ref() == b"file" {
+                    let done = stack
+                        .pop()
+                        .ok_or_else(|| ExtractError::Archive("Unbalanced xar TOC".to_string()))?;
+                    let parent: PathBuf = stack.iter().map(|f| f.name.as_str()).collect();
+                    entries.push(done.finish(&parent)?);
+                    element_path.clear();
+                } else {
+                    element_path.pop();
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    // Children close before their parents; present parents first.
+    entries.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(entries)
+}
+
+fn set_encoding(e: &quick_xml::events::BytesStart<'_>, current: Option<&mut PendingFile>) {
+    let Some(current) = current else {
+        return;
+    };
+    for attr in e.attributes().flatten() {
+        if attr.key.as_ref() == b"style" {
+            if let Ok(v) = attr.unescape_value() {
+                current.encoding = Some(v.into_owned());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use flate2::Compression;
+    use flate2::write::ZlibEncoder;
+    use std::fmt::Write as _;
+    use std::io::Write;
+
+    /// Build a xar archive from `(path, contents)` pairs, zlib-encoding each file.
+    ///
+    /// Nested paths (`a.pkg/Payload`) produce nested `<file>` elements.
+    pub(crate) fn build_xar(files: &[(&str, &[u8])]) -> Vec<u8> {
+        let mut heap = Vec::new();
+        let mut toc = String::from("<?xml version=\"1.0\"?><xar><toc>");
+        let mut open: Vec<&str> = Vec::new();
+        let mut id = 0;
+
+        for (path, contents) in files {
+            let parts: Vec<&str> = path.split('/').collect();
+            let (dirs, name) = parts.split_at(parts.len() - 1);
+
+            while !dirs.starts_with(&open) {
+                open.pop();
+                toc.push_str("</file>");
+            }
+            for dir in &dirs[open.len()..] {
+                id += 1;
+                let _ = write!(
+                    toc,
+                    "<file id=\"{id}\"><name>{dir}</name><type>directory</type>"
+                );
+                open.push(dir);
+            }
+
+            let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
+            enc.write_all(contents).unwrap();
+            let stored = enc.finish().unwrap();
+
+            id += 1;
+            let _ = write!(
+                toc,
+                "<file id=\"{id}\"><name>{}</name><type>file</type><data>\
+                 <length>{}</length><offset>{}</offset><size>{}</size>\
+                 <encoding style=\"application/x-gzip\"/></data></file>",
+                name[0],
+                stored.len(),
+                heap.len(),
+                contents.len()
+            );
+            heap.extend_from_slice(&stored);
+        }
+        for _ in open {
+            toc.push_str("</file>");
+        }
+        toc.push_str("</toc></xar>");
+
+        let mut enc = ZlibEncoder::new(Vec::new(), Compression::default());
+        enc.write_all(toc.as_bytes()).unwrap();
+        let toc_z = enc.finish().unwrap();
+
+        let mut out = Vec::new();
+        out.extend_from_slice(&XAR_MAGIC);
+        out.extend_from_slice(&(HEADER_LEN as u16).to_be_bytes());
+        out.extend_from_slice(&1u16.to_be_bytes());
+        out.extend_from_slice(&(toc_z.len() as u64).to_be_bytes());
+        out.extend_from_slice(&(toc.len() as u64).to_be_bytes());
+        out.extend_from_slice(&0u32.to_be_bytes());
+        out.extend_from_slice(&toc_z);
+        out.extend_from_slice(&heap);
+        out
+    }
+
+    #[test]
+    fn test_read_nested_entries() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.xar");
+        std::fs::write(
+            &path,
+            build_xar(&[
+                ("Distribution", b"<installer-gui-script/>"),
+                ("tool.pkg/PackageInfo", b"<pkg-info/>"),
+                ("tool.pkg/Payload", b"payload bytes"),
+            ]),
+        )
+        .unwrap();
+
+        let mut xar = XarArchive::open(&path).unwrap();
+        let paths: Vec<_> = xar.entries().iter().map(|e| e.path.clone()).collect();
+        assert!(paths.contains(&PathBuf::from("tool.pkg")));
+        assert!(paths.contains(&PathBuf::from("tool.pkg/Payload")));
+
+        let data = xar
+            .find(Path::new("tool.pkg/Payload"))
+            .and_then(|e| e.data)
+            .unwrap();
+        assert_eq!(xar.read_to_vec(&data).unwrap(), b"payload bytes");
+    }
+
+    #[test]
+    fn test_rejects_oversized_headers() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("test.xar");
+        let xar = build_xar(&[("Payload", b"payload bytes")]);
+
+        // toc_uncompressed far beyond any real TOC
+        let mut huge_toc = xar.clone();
+        huge_toc[16..24].copy_from_slice(&u64::MAX.to_be_bytes());
+        std::fs::write(&path, &huge_toc).unwrap();
+        assert!(XarArchive::open(&path).is_err());
+
+        // toc_compressed past the end of the file
+        let mut past_end = xar.clone();
+        past_end[8..16].copy_from_slice(&u64::MAX.to_be_bytes());
+        std::fs::write(&path, &past_end).unwrap();
+        assert!(XarArchive::open(&path).is_err());
+
+        // An entry offset that overflows the heap position
+        std::fs::write(&path, &xar).unwrap();
+        let mut archive = XarArchive::open(&path).unwrap();
+        let mut data = archive
+            .find(Path::new("Payload"))
+            .and_then(|e| e.data)
+            .unwrap();
+        data.offset = u64::MAX;
+        assert!(archive.read_to_vec(&data).is_err());
+        data.offset = 0;
+        data.size = u64::MAX;
+        assert!(archive.read_to_vec(&data).is_err());
+    }
+
+    #[test]
+    fn test_rejects_bad_magic() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("bad.xar");
+        std::fs::write(&path, [0u8; 64]).unwrap();
+        assert!(XarArchive::open(&path).is_err());
+    }
+}
@@ -6,6 +6,8 @@
 
 /// Package building subsystem for compiling packages from source.
 pub mod builder;
+/// Layered user configuration (`config.toml`).
+pub mod config;
 /// Indexing subsystem for discovering and cataloging available packages.
 pub mod indexer;
 /// I/O utilities for downloading, extracting, and verifying artifacts.
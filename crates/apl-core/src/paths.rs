@@ -29,7 +29,12 @@ pub fn store_path() -> PathBuf {
     apl_home().join("store")
 }
 
-/// Binary installation target: ~/.apl/bin
+/// Profile generations: ~/.apl/profiles
+pub fn profiles_path() -> PathBuf {
+    apl_home().join("profiles")
+}
+
+/// Binary installation target: ~/.apl/bin (links to the current profile generation)
 pub fn bin_path() -> PathBuf {
     apl_home().join("bin")
 }
@@ -60,6 +65,36 @@ pub fn tmp_path() -> PathBuf {
     apl_home().join("tmp")
 }
 
+/// Operation journal directory: ~/.apl/journal
+pub fn journal_path() -> PathBuf {
+    apl_home().join("journal")
+}
+
+/// User configuration: ~/.apl/config.toml
+pub fn config_path() -> PathBuf {
+    apl_home().join("config.toml")
+}
+
+/// Local copy of the default package index: ~/.apl/index
+pub fn index_path() -> PathBuf {
+    apl_home().join("index")
+}
+
+/// Third-party package sources: ~/.apl/sources
+pub fn sources_path() -> PathBuf {
+    apl_home().join("sources")
+}
+
+/// Declarative global toolset: ~/.apl/Aplfile
+pub fn aplfile_path() -> PathBuf {
+    apl_home().join("Aplfile")
+}
+
+/// Process lock directory: ~/.apl/lock
+pub fn lock_path() -> PathBuf {
+    apl_home().join("lock")
+}
+
 /// Extract the filename from a URL.
 pub fn filename_from_url(url: &str) -> &str {
     url.split('/').next_back().unwrap_or("")
@@ -180,6 +180,7 @@ mod tests {
                 build_script: String::new(),
             }],
             tags: vec![],
+            license: String::new(),
         }
     }
 
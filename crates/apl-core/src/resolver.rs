@@ -3,7 +3,8 @@ use apl_schema::PackageIndex;
 use apl_schema::index::{IndexEntry, VersionInfo};
 use apl_schema::types::PackageName;
 use apl_schema::version::is_newer;
-use std::collections::{HashMap, HashSet, VecDeque};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
 
 /// Resolves dependencies for a set of packages and returns them in installation order.
 ///
@@ -38,82 +39,228 @@ pub fn resolve_dependencies(
 /// Resolves a build plan for the entire index, returning layers of packages
 /// that can be built in parallel.
 ///
-/// Uses Kahn's algorithm for topological sorting. Each layer contains
-/// packages whose dependencies have all been resolved in prior layers.
+/// Uses Kahn's algorithm for topological sorting (see [`DepGraph::layers`]).
+/// Each layer contains packages whose dependencies have all been resolved in
+/// prior layers.
 ///
 /// # Errors
 ///
 /// Returns an error if a circular dependency is detected in the build graph.
-/// # Panics
-/// Panics if the internal graph structure is inconsistent (e.g. missing node degree).
 pub fn resolve_build_plan(index: &PackageIndex) -> Result<Vec<Vec<PackageName>>> {
-    let mut adjacency: HashMap<PackageName, Vec<PackageName>> = HashMap::new();
-    let mut in_degree: HashMap<PackageName, usize> = HashMap::new();
-    let mut all_packages = HashSet::new();
-
-    for entry in &index.packages {
-        let pkg_name = PackageName::new(&entry.name);
-        all_packages.insert(pkg_name.clone());
-
-        if let Some(latest) = entry.latest() {
-            for dep in latest.build_deps.iter().chain(latest.deps.iter()) {
-                let dep_name = PackageName::new(dep);
-                adjacency
-                    .entry(dep_name.clone())
-                    .or_default()
-                    .push(pkg_name.clone());
-                *in_degree.entry(pkg_name.clone()).or_default() += 1;
-                all_packages.insert(dep_name);
+    let layering = DepGraph::from_index(index, true).layers();
+    if !layering.blocked.is_empty() {
+        bail!("Circular dependency detected in build graph");
+    }
+    Ok(layering.layers)
+}
+
+/// How one package depends on another.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DepKind {
+    /// Needed at run time (`deps`)
+    Runtime,
+    /// Needed only to build from source (`build_deps`)
+    Build,
+}
+
+/// A directed graph from packages to the packages they depend on.
+///
+/// Nodes and edges are kept sorted so every traversal is deterministic.
+#[derive(Debug, Clone, Default)]
+pub struct DepGraph {
+    edges: BTreeMap<PackageName, BTreeMap<PackageName, DepKind>>,
+}
+
+/// Result of [`DepGraph::layers`].
+#[derive(Debug, Clone, Default)]
+pub struct Layering {
+    /// Layers in dependency order; each depends only on earlier layers
+    pub layers: Vec<Vec<PackageName>>,
+    /// Packages on a cycle or depending on one, which no layer can hold
+    pub blocked: BTreeSet<PackageName>,
+}
+
+impl DepGraph {
+    /// The dependencies of every package's latest release in `index`,
+    /// including build dependencies if `include_build` is set.
+    /// Dependencies missing from the index become nodes without edges.
+    pub fn from_index(index: &PackageIndex, include_build: bool) -> Self {
+        let mut graph = Self::default();
+        for entry in index.iter() {
+            graph.add_release(&entry.name, entry.latest(), include_build);
+        }
+        graph
+    }
+
+    /// The dependencies reachable from `roots` through the latest releases
+    /// in `index`.
+    pub fn reachable_from(
+        index: &PackageIndex,
+        roots: &[PackageName],
+        include_build: bool,
+    ) -> Self {
+        let mut graph = Self::default();
+        let mut visited = HashSet::new();
+        let mut queue: VecDeque<PackageName> = roots.iter().cloned().collect();
+        while let Some(name) = queue.pop_front() {
+            if !visited.insert(name.clone()) {
+                continue;
             }
+            let latest = index.find(&name).and_then(IndexEntry::latest);
+            graph.add_release(&name, latest, include_build);
+            queue.extend(graph.deps(&name).map(|(dep, _)| dep.clone()));
         }
+        graph
     }
 
-    let mut layers = Vec::new();
-    let queue: VecDeque<PackageName> = all_packages
-        .iter()
-        .filter(|p| in_degree.get(*p).copied().unwrap_or(0) == 0)
-        .cloned()
-        .collect();
+    fn add_release(&mut self, name: &str, release: Option<&VersionInfo>, include_build: bool) {
+        let name = PackageName::new(name);
+        self.add_node(name.clone());
+        let Some(release) = release else {
+            return;
+        };
+        let build = release.build_deps.iter().filter(|_| include_build);
+        let deps = release.deps.iter().map(|d| (d, DepKind::Runtime));
+        for (dep, kind) in deps.chain(build.map(|d| (d, DepKind::Build))) {
+            self.add_edge(name.clone(), PackageName::new(dep), kind);
+        }
+    }
 
-    // Sort queue for deterministic output
-    let mut sorted_queue: Vec<PackageName> = queue.into_iter().collect();
-    sorted_queue.sort();
-    let mut queue = VecDeque::from(sorted_queue);
+    /// Adds a package with no dependencies, if it is not already present.
+    pub fn add_node(&mut self, name: PackageName) {
+        self.edges.entry(name).or_default();
+    }
 
-    while !queue.is_empty() {
-        let mut current_layer = Vec::new();
-        let mut next_queue_vec = Vec::new();
+    /// Records that `from` depends on `to`. A runtime edge wins over a build
+    /// edge between the same packages.
+    pub fn add_edge(&mut self, from: PackageName, to: PackageName, kind: DepKind) {
+        self.add_node(to.clone());
+        let existing = self
+            .edges
+            .entry(from)
+            .or_default()
+            .entry(to)
+            .or_insert(kind);
+        *existing = (*existing).min(kind);
+    }
 
-        while let Some(u) = queue.pop_front() {
-            current_layer.push(u.clone());
+    /// Whether `name` is a node of the graph.
+    pub fn contains(&self, name: &PackageName) -> bool {
+        self.edges.contains_key(name)
+    }
 
-            if let Some(neighbors) = adjacency.get(&u) {
-                for v in neighbors {
-                    let degree = in_degree.get_mut(v).unwrap();
-                    *degree -= 1;
-                    if *degree == 0 {
-                        next_queue_vec.push(v.clone());
-                    }
-                }
+    /// Every package in the graph, sorted.
+    pub fn nodes(&self) -> impl Iterator<Item = &PackageName> {
+        self.edges.keys()
+    }
+
+    /// Direct dependencies of `name`, sorted.
+    pub fn deps(&self, name: &PackageName) -> impl Iterator<Item = (&PackageName, DepKind)> {
+        self.edges
+            .get(name)
+            .into_iter()
+            .flatten()
+            .map(|(dep, kind)| (dep, *kind))
+    }
+
+    /// The same graph with every edge pointing the other way, so `deps`
+    /// answers "who depends on this".
+    #[must_use]
+    pub fn reverse(&self) -> Self {
+        let mut reversed = Self::default();
+        for (from, deps) in &self.edges {
+            reversed.add_node(from.clone());
+            for (to, kind) in deps {
+                reversed.add_edge(to.clone(), from.clone(), *kind);
             }
         }
+        reversed
+    }
 
-        if !current_layer.is_empty() {
-            current_layer.sort();
-            layers.push(current_layer);
+    /// The part of the graph reachable from `roots`.
+    #[must_use]
+    pub fn subgraph(&self, roots: &[PackageName]) -> Self {
+        let mut graph = Self::default();
+        let mut visited = HashSet::new();
+        let mut queue: VecDeque<&PackageName> = roots.iter().filter(|r| self.contains(r)).collect();
+        while let Some(name) = queue.pop_front() {
+            if !visited.insert(name) {
+                continue;
+            }
+            graph.add_node(name.clone());
+            for (dep, kind) in self.deps(name) {
+                graph.add_edge(name.clone(), dep.clone(), kind);
+                queue.push_back(dep);
+            }
         }
-
-        next_queue_vec.sort();
-        queue = VecDeque::from(next_queue_vec);
+        graph
     }
 
-    // Check for cycles
-    let total_sorted: usize = layers.iter().map(Vec::len).sum();
-    if total_sorted < all_packages.len() {
-        bail!("Circular dependency detected in build graph");
+    /// Orders the graph into layers with Kahn's algorithm: the first layer
+    /// has no dependencies and each later one depends only on earlier ones.
+    pub fn layers(&self) -> Layering {
+        let mut in_degree: HashMap<&PackageName, usize> = self
+            .edges
+            .iter()
+            .map(|(name, deps)| (name, deps.len()))
+            .collect();
+        let dependents = self.reverse();
+
+        let mut layers = Vec::new();
+        let mut queue: Vec<&PackageName> =
+            self.nodes().filter(|name| in_degree[name] == 0).collect();
+        while !queue.is_empty() {
+            let mut next = Vec::new();
+            for name in &queue {
+                for (dependent, _) in dependents.deps(name) {
+                    let Some((key, degree)) = in_degree.get_key_value(dependent) else {
+                        continue;
+                    };
+                    let (key, degree) = (*key, *degree - 1);
+                    in_degree.insert(key, degree);
+                    if degree == 0 {
+                        next.push(key);
+                    }
+                }
+            }
+            layers.push(queue.into_iter().cloned().collect());
+            next.sort();
+            queue = next;
+        }
+
+        let blocked = in_degree
+            .into_iter()
+            .filter(|(_, degree)| *degree > 0)
+            .map(|(name, _)| name.clone())
+            .collect();
+        Layering { layers, blocked }
     }
 
-    Ok(layers)
+    /// Packages that lie on a dependency cycle.
+    ///
+    /// Only packages [`layers`](Self::layers) could not place are checked,
+    /// since a cycle keeps all of its members out of every layer.
+    pub fn cyclic(&self) -> BTreeSet<PackageName> {
+        let blocked = self.layers().blocked;
+        blocked
+            .iter()
+            .filter(|start| {
+                let mut seen = HashSet::new();
+                let mut stack: Vec<&PackageName> = self.deps(start).map(|(d, _)| d).collect();
+                while let Some(name) = stack.pop() {
+                    if name == *start {
+                        return true;
+                    }
+                    if seen.insert(name) {
+                        stack.extend(self.deps(name).map(|(d, _)| d));
+                    }
+                }
+                false
+            })
+            .cloned()
+            .collect()
+    }
 }
 
 /// Resolves a package spec (e.g. `pkg@v1.0.0`) against the index.
@@ -283,6 +430,7 @@ mod tests {
                 build_script: String::new(),
             }],
             tags: vec![],
+            license: String::new(),
         }
     }
 
@@ -392,4 +540,39 @@ mod tests {
         assert_eq!(layers[3], vec![PackageName::new("b")]);
         assert_eq!(layers[4], vec![PackageName::new("a")]);
     }
+
+    #[test]
+    fn test_graph_cycles_and_reverse() {
+        let mut entry_c = simple_entry("c", vec!["a".into()]);
+        entry_c.releases[0].build_deps = vec!["d".to_string()];
+        let index = mock_index(vec![
+            simple_entry("a", vec!["b".into()]),
+            simple_entry("b", vec!["a".into()]),
+            entry_c,
+        ]);
+
+        let graph = DepGraph::reachable_from(&index, &["c".into()], false);
+        assert_eq!(graph.nodes().count(), 3);
+        let layering = graph.layers();
+        assert!(layering.layers.is_empty());
+        assert_eq!(layering.blocked.len(), 3);
+        assert_eq!(
+            graph.cyclic(),
+            BTreeSet::from([PackageName::new("a"), PackageName::new("b")])
+        );
+
+        let graph = DepGraph::from_index(&index, true);
+        assert_eq!(
+            graph.deps(&"c".into()).collect::<Vec<_>>(),
+            vec![
+                (&PackageName::new("a"), DepKind::Runtime),
+                (&PackageName::new("d"), DepKind::Build),
+            ]
+        );
+        let dependents = graph.reverse();
+        let names =
+            |graph: DepGraph| -> Vec<String> { graph.nodes().map(ToString::to_string).collect() };
+        assert_eq!(names(dependents.subgraph(&["d".into()])), ["c", "d"]);
+        assert_eq!(names(dependents.subgraph(&["a".into()])), ["a", "b", "c"]);
+    }
 }
@@ -1,7 +1,8 @@
 //! `apl-pkg` - The APL Package Registry Maintainer Tool.
 //!
 //! This binary provides commands for maintaining the APL package registry,
-//! including indexing, signing, and syncing packages.
+//! including indexing, signing, and syncing packages, and for running a
+//! private registry of one's own (see [`registry`]).
 
 use anyhow::{Context, Result};
 use apl_core::indexer::forges::github::{self, build_client};
@@ -9,7 +10,9 @@ use apl_core::package::Package;
 use clap::{Parser, Subcommand};
 use std::collections::HashMap;
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+
+mod registry;
 
 #[derive(Parser)]
 #[command(name = "apl-pkg")]
@@ -80,6 +83,62 @@ enum Commands {
         #[arg(short, long)]
         output: std::path::PathBuf,
     },
+    /// Maintain a self-hosted private registry in the `--registry` directory
+    Private {
+        #[command(subcommand)]
+        command: PrivateCommands,
+    },
+}
+
+#[derive(Subcommand)]
+enum PrivateCommands {
+    /// Create `registry.toml`, generating the team key if `--key` does not exist
+    Init {
+        /// Base URL the registry will be served from
+        #[arg(long)]
+        url: String,
+        /// Signing key file
+        #[arg(long, default_value = "apl.key")]
+        key: PathBuf,
+    },
+    /// Add a local artifact as a release of a package
+    Add {
+        /// Package name
+        name: String,
+        /// Release version
+        version: String,
+        /// Artifact file (tarball, zip, dmg, pkg or binary)
+        file: PathBuf,
+        /// Architecture the artifact runs on
+        #[arg(long, default_value = "universal")]
+        arch: apl_schema::Arch,
+        /// Binary to link (repeatable; defaults to the package name)
+        #[arg(long)]
+        bin: Vec<String>,
+        /// Runtime dependency (repeatable)
+        #[arg(long)]
+        dep: Vec<String>,
+        /// Package description
+        #[arg(long)]
+        description: Option<String>,
+    },
+    /// Build and sign the index
+    Build {
+        /// Signing key file (defaults to `APL_SIGNING_KEY`)
+        #[arg(long)]
+        key: Option<PathBuf>,
+    },
+    /// Upload artifacts, index and signature
+    Publish {
+        /// Destination directory, or `s3://bucket/prefix`
+        to: String,
+        /// S3-compatible endpoint (e.g. `https://<account>.r2.cloudflarestorage.com`)
+        #[arg(long)]
+        endpoint: Option<String>,
+        /// S3 region
+        #[arg(long, default_value = "auto")]
+        region: String,
+    },
 }
 
 #[tokio::main]
@@ -303,6 +362,9 @@ async fn main() -> Result<()> {
         Commands::Verify { package } => {
             cli_verify(&client, &package).await?;
         }
+        Commands::Private { command } => {
+            cli_private(&registry_dir, command).await?;
+        }
     }
 
     Ok(())
@@ -378,23 +440,32 @@ async fn cli_index(
     Ok(())
 }
 
-fn cli_sign(input: &Path, output: &Path) -> Result<()> {
+/// Reads a base64 Ed25519 private key from `path`, or from `APL_SIGNING_KEY`.
+fn load_signing_key(path: Option<&Path>) -> Result<ed25519_dalek::SigningKey> {
     use base64::Engine;
-    use ed25519_dalek::{Signer, SigningKey};
 
-    let secret_b64 = std::env::var("APL_SIGNING_KEY").context("APL_SIGNING_KEY not set")?;
+    let secret_b64 = match path {
+        Some(path) => fs::read_to_string(path)
+            .with_context(|| format!("Failed to read key file {}", path.display()))?,
+        None => std::env::var("APL_SIGNING_KEY").context("APL_SIGNING_KEY not set")?,
+    };
 
     let secret_bytes = base64::engine::general_purpose::STANDARD
         .decode(secret_b64.trim())
         .context("Invalid Base64 signing key")?;
 
-    if secret_bytes.len() != 32 {
-        anyhow::bail!("APL_SIGNING_KEY must be a 32-byte Ed25519 private key");
-    }
+    let key_arr: [u8; 32] = secret_bytes
+        .as_slice()
+        .try_into()
+        .context("Signing key must be a 32-byte Ed25519 private key")?;
+    Ok(ed25519_dalek::SigningKey::from_bytes(&key_arr))
+}
+
+fn cli_sign(input: &Path, output: &Path) -> Result<()> {
+    use base64::Engine;
+    use ed25519_dalek::Signer;
 
-    let mut key_arr = [0u8; 32];
-    key_arr.copy_from_slice(&secret_bytes);
-    let signing_key = SigningKey::from_bytes(&key_arr);
+    let signing_key = load_signing_key(None)?;
 
     let data = fs::read(input).context("Failed to read input file")?;
     let signature = signing_key.sign(&data);
@@ -496,6 +567,92 @@ async fn add_package(client: &reqwest::Client, repo: &str, out_dir: &Path) -> Re
     Ok(())
 }
 
+async fn cli_private(registry_dir: &Path, command: PrivateCommands) -> Result<()> {
+    match command {
+        PrivateCommands::Init { url, key } => {
+            if !key.exists() {
+                write_new_key(&key)?;
+                println!("  wrote {}", key.display());
+            }
+            let config = registry::init(registry_dir, &url, &load_signing_key(Some(&key))?)?;
+            println!("  initialised {}", registry_dir.display());
+            println!();
+            println!("  clients add it with:");
+            println!(
+                "  apl source add <name> {}/index --key {}",
+                config.url, config.key
+            );
+        }
+        PrivateCommands::Add {
+            name,
+            version,
+            file,
+            arch,
+            bin,
+            dep,
+            description,
+        } => {
+            let hash = registry::add(
+                registry_dir,
+                &registry::NewArtifact {
+                    name: &name,
+                    version: &version,
+                    file: &file,
+                    arch,
+                    bin: &bin,
+                    deps: &dep,
+                    description: description.as_deref(),
+                },
+            )?;
+            println!("  added {name} {version} ({arch}) -> cas/{hash}");
+        }
+        PrivateCommands::Build { key } => {
+            let count = registry::build(registry_dir, &load_signing_key(key.as_deref())?)?;
+            println!("  built index ({count} packages), signed");
+        }
+        PrivateCommands::Publish {
+            to,
+            endpoint,
+            region,
+        } => {
+            let target = if to.starts_with("s3://") {
+                registry::Target::S3 {
+                    url: &to,
+                    endpoint: endpoint.as_deref(),
+                    region: &region,
+                }
+            } else {
+                registry::Target::Dir(Path::new(&to))
+            };
+            let uploaded = registry::publish(registry_dir, &target).await?;
+            println!("  published to {to} ({uploaded} new artifacts)");
+        }
+    }
+    Ok(())
+}
+
+/// Writes a new base64 Ed25519 private key to `path`, readable by the owner only.
+fn write_new_key(path: &Path) -> Result<()> {
+    use base64::Engine;
+    use rand::RngCore;
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut secret_bytes = [0u8; 32];
+    rand::rng().fill_bytes(&mut secret_bytes);
+    let mut f = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(path)?;
+    f.write_all(
+        base64::engine::general_purpose::STANDARD
+            .encode(secret_bytes)
+            .as_bytes(),
+    )?;
+    Ok(())
+}
+
 fn cli_keygen() -> Result<()> {
     use base64::Engine;
     use ed25519_dalek::SigningKey;
@@ -0,0 +1,487 @@
+//! Self-hosted private registries.
+//!
+//! A private registry is a directory holding everything needed to serve
+//! packages to `apl source add`:
+//!
+//! ```text
+//! internal/
+//! ├── registry.toml      public URL and the team's public key
+//! ├── packages/<name>.toml
+//! ├── cas/<sha256>       artifacts, added from local files
+//! ├── index              built by `private build`
+//! └── index.sig
+//! ```
+//!
+//! `private publish` copies `cas/`, `index` and `index.sig` to a directory
+//! or an S3-compatible bucket: the layout the client downloads from.
+
+use anyhow::{Context, Result, bail};
+use apl_schema::index::{HashType, IndexBinary, PackageIndex, VersionInfo};
+use apl_schema::{Arch, Sha256Hash};
+use ed25519_dalek::{Signer, SigningKey};
+use opendal::Operator;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const REGISTRY_FILE: &str = "registry.toml";
+
+/// `registry.toml`
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct RegistryConfig {
+    /// Base URL the registry is served from; artifacts are at `{url}/cas/<hash>`
+    pub url: String,
+    /// Base64 Ed25519 public key the index is signed for
+    pub key: String,
+}
+
+/// `packages/<name>.toml`
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct PackageRecord {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    #[serde(default)]
+    pub homepage: String,
+    #[serde(default)]
+    pub license: String,
+    #[serde(default)]
+    pub releases: Vec<ReleaseRecord>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub(crate) struct ReleaseRecord {
+    pub version: String,
+    #[serde(default)]
+    pub bin: Vec<String>,
+    #[serde(default)]
+    pub deps: Vec<String>,
+    #[serde(default)]
+    pub artifacts: Vec<ArtifactRecord>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct ArtifactRecord {
+    pub arch: Arch,
+    /// SHA-256 of the file, which is stored as `cas/<hash>`
+    pub hash: String,
+}
+
+/// What `private add` records about an artifact
+#[derive(Debug)]
+pub(crate) struct NewArtifact<'a> {
+    pub name: &'a str,
+    pub version: &'a str,
+    pub file: &'a Path,
+    pub arch: Arch,
+    pub bin: &'a [String],
+    pub deps: &'a [String],
+    pub description: Option<&'a str>,
+}
+
+/// Where `private publish` copies the registry to
+#[derive(Debug)]
+pub(crate) enum Target<'a> {
+    Dir(&'a Path),
+    /// `s3://bucket/prefix` at an S3-compatible endpoint; credentials come
+    /// from the usual `AWS_*` environment variables
+    S3 {
+        url: &'a str,
+        endpoint: Option<&'a str>,
+        region: &'a str,
+    },
+}
+
+fn config_path(dir: &Path) -> PathBuf {
+    dir.join(REGISTRY_FILE)
+}
+
+fn package_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join("packages").join(format!("{name}.toml"))
+}
+
+/// Package names follow the client's rule for source names: lowercase
+/// letters, digits, `-` and `_`. This also keeps `packages/<name>.toml`
+/// inside the registry.
+fn check_name(name: &str) -> Result<()> {
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-' || c == '_');
+    if !valid {
+        bail!("Invalid package name '{name}': use lowercase letters, digits, '-' and '_'");
+    }
+    Ok(())
+}
+
+fn load_config(dir: &Path) -> Result<RegistryConfig> {
+    let path = config_path(dir);
+    let content = fs::read_to_string(&path).with_context(|| {
+        format!(
+            "{} not found; run 'apl-pkg private init' first",
+            path.display()
+        )
+    })?;
+    toml::from_str(&content).with_context(|| format!("Invalid {}", path.display()))
+}
+
+fn public_key(key: &SigningKey) -> String {
+    use base64::Engine;
+    base64::engine::general_purpose::STANDARD.encode(key.verifying_key().to_bytes())
+}
+
+/// Creates `registry.toml` for a registry served from `url`, trusting `key`.
+pub(crate) fn init(dir: &Path, url: &str, key: &SigningKey) -> Result<RegistryConfig> {
+    let path = config_path(dir);
+    if path.exists() {
+        bail!("{} already exists", path.display());
+    }
+    let config = RegistryConfig {
+        url: url.trim_end_matches('/').to_string(),
+        key: public_key(key),
+    };
+    fs::create_dir_all(dir.join("packages"))?;
+    fs::create_dir_all(dir.join("cas"))?;
+    fs::write(&path, toml::to_string_pretty(&config)?)?;
+    Ok(config)
+}
+
+/// Stores an artifact under `cas/` and records it as a release of its
+/// package, replacing an artifact of the same architecture. Returns its hash.
+pub(crate) fn add(dir: &Path, artifact: &NewArtifact<'_>) -> Result<String> {
+    use sha2::{Digest, Sha256};
+
+    check_name(artifact.name)?;
+    load_config(dir)?;
+    let mut file = fs::File::open(artifact.file)
+        .with_context(|| format!("Failed to open {}", artifact.file.display()))?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    let hash = hex::encode(hasher.finalize());
+
+    let cas = dir.join("cas").join(&hash);
+    if !cas.exists() {
+        fs::create_dir_all(dir.join("cas"))?;
+        fs::copy(artifact.file, &cas)?;
+    }
+
+    let path = package_path(dir, artifact.name);
+    let mut record = match fs::read_to_string(&path) {
+        Ok(content) => {
+            toml::from_str(&content).with_context(|| format!("Invalid {}", path.display()))?
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => PackageRecord {
+            name: artifact.name.to_string(),
+            ..PackageRecord::default()
+        },
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(description) = artifact.description {
+        record.description = description.to_string();
+    }
+
+    if !record
+        .releases
+        .iter()
+        .any(|r| r.version == artifact.version)
+    {
+        record.releases.push(ReleaseRecord {
+            version: artifact.version.to_string(),
+            ..ReleaseRecord::default()
+        });
+    }
+    let release = record
+        .releases
+        .iter_mut()
+        .find(|r| r.version == artifact.version)
+        .expect("release was just added");
+    if !artifact.bin.is_empty() {
+        release.bin = artifact.bin.to_vec();
+    }
+    if !artifact.deps.is_empty() {
+        release.deps = artifact.deps.to_vec();
+    }
+    release.artifacts.retain(|a| a.arch != artifact.arch);
+    release.artifacts.push(ArtifactRecord {
+        arch: artifact.arch,
+        hash: hash.clone(),
+    });
+
+    fs::create_dir_all(dir.join("packages"))?;
+    fs::write(&path, toml::to_string_pretty(&record)?)?;
+    Ok(hash)
+}
+
+/// Builds the index from `packages/` and signs it with `key`, writing
+/// `index` and `index.sig`. Returns the number of packages.
+pub(crate) fn build(dir: &Path, key: &SigningKey) -> Result<usize> {
+    use base64::Engine;
+
+    let config = load_config(dir)?;
+    if public_key(key) != config.key {
+        bail!(
+            "Signing key does not match the public key in {}",
+            config_path(dir).display()
+        );
+    }
+
+    let mut index = PackageIndex::new();
+    let packages = dir.join("packages");
+    let mut paths: Vec<_> = fs::read_dir(&packages)
+        .with_context(|| format!("Failed to read {}", packages.display()))?
+        .filter_map(|e| e.ok().map(|e| e.path()))
+        .filter(|p| p.extension().is_some_and(|e| e == "toml"))
+        .collect();
+    paths.sort();
+
+    for path in paths {
+        let record: PackageRecord = toml::from_str(&fs::read_to_string(&path)?)
+            .with_context(|| format!("Invalid {}", path.display()))?;
+        for release in record.releases {
+            let mut binaries = Vec::new();
+            for artifact in release.artifacts {
+                if !dir.join("cas").join(&artifact.hash).exists() {
+                    bail!(
+                        "{} {}: artifact cas/{} is missing",
+                        record.name,
+                        release.version,
+                        artifact.hash
+                    );
+                }
+                binaries.push(IndexBinary {
+                    arch: artifact.arch,
+                    url: format!("{}/cas/{}", config.url, artifact.hash),
+                    hash: Sha256Hash::new(artifact.hash),
+                    hash_type: HashType::Sha256,
+                });
+            }
+            index.upsert_release(
+                &record.name,
+                &record.description,
+                "cli",
+                Vec::new(),
+                VersionInfo {
+                    version: release.version,
+                    binaries,
+                    deps: release.deps,
+                    bin: release.bin,
+                    ..VersionInfo::default()
+                },
+            );
+        }
+        if let Some(entry) = index.find_mut(&record.name) {
+            entry.homepage = record.homepage;
+            entry.license = record.license;
+        }
+    }
+
+    index.mirror_base_url = Some(config.url);
+    index.updated_at = chrono::Utc::now().timestamp();
+
+    let index_path = dir.join("index");
+    index.save_compressed(&index_path)?;
+    let signature = key.sign(&fs::read(&index_path)?);
+    fs::write(
+        dir.join("index.sig"),
+        base64::engine::general_purpose::STANDARD.encode(signature.to_bytes()),
+    )?;
+    Ok(index.len())
+}
+
+fn operator(target: &Target<'_>) -> Result<Operator> {
+    use opendal::services::{Fs, S3};
+
+    match target {
+        Target::Dir(path) => {
+            fs::create_dir_all(path)?;
+            let root = fs::canonicalize(path)?;
+            let mut builder = Fs::default();
+            builder.root(&root.to_string_lossy());
+            Ok(Operator::new(builder)?.finish())
+        }
+        Target::S3 {
+            url,
+            endpoint,
+            region,
+        } => {
+            let Some((bucket, prefix)) = url
+                .strip_prefix("s3://")
+                .map(|rest| rest.split_once('/').unwrap_or((rest, "")))
+            else {
+                bail!("Expected an s3://bucket/prefix URL, got {url}");
+            };
+            let mut builder = S3::default();
+            builder.bucket(bucket);
+            builder.root(&format!("/{prefix}"));
+            builder.region(region);
+            if let Some(endpoint) = endpoint {
+                builder.endpoint(endpoint);
+            }
+            Ok(Operator::new(builder)?.finish())
+        }
+    }
+}
+
+/// Copies the built registry to `target`: new artifacts first, then the
+/// index and its signature, so clients never see an index whose artifacts
+/// are missing. Returns the number of artifacts uploaded.
+///
+/// The index and signature are uploaded under temporary keys and then moved
+/// into place, which leaves only the moves themselves (two server-side
+/// copies on S3) during which a client can fetch a mismatched pair. Such a
+/// client fails signature verification and gets the new pair on its next
+/// `apl update`.
+pub(crate) async fn publish(dir: &Path, target: &Target<'_>) -> Result<usize> {
+    for file in ["index", "index.sig"] {
+        if !dir.join(file).exists() {
+            bail!("{file} not found; run 'apl-pkg private build' first");
+        }
+    }
+    let op = operator(target)?;
+
+    let mut uploaded = 0;
+    for entry in fs::read_dir(dir.join("cas"))? {
+        let path = entry?.path();
+        let key = format!("cas/{}", path.file_name().unwrap().to_string_lossy());
+        // Artifacts are content-addressed, so an existing one is the same file
+        if op.is_exist(&key).await? {
+            continue;
+        }
+        op.write(&key, fs::read(&path)?)
+            .await
+            .with_context(|| format!("Failed to upload {key}"))?;
+        uploaded += 1;
+    }
+    let staged =
+        ["index", "index.sig"].map(|file| (file, format!(".{file}.{}", std::process::id())));
+    for (file, temp) in &staged {
+        op.write(temp, fs::read(dir.join(file))?)
+            .await
+            .with_context(|| format!("Failed to upload {file}"))?;
+    }
+    let rename = op.info().full_capability().rename;
+    for (file, temp) in &staged {
+        if rename {
+            op.rename(temp, file).await
+        } else {
+            op.copy(temp, file).await?;
+            op.delete(temp).await
+        }
+        .with_context(|| format!("Failed to move {file} into place"))?;
+    }
+    Ok(uploaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ed25519_dalek::Verifier;
+
+    #[tokio::test]
+    async fn test_add_build_and_publish() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("internal");
+        let key = SigningKey::from_bytes(&[7; 32]);
+        init(&dir, "https://pkgs.example.com/", &key).unwrap();
+
+        let artifact = temp.path().join("tool.tar.gz");
+        fs::write(&artifact, b"tool").unwrap();
+        let bin = vec!["tool".to_string()];
+        for (version, arch) in [
+            ("1.0.0", Arch::Arm64),
+            ("1.1.0", Arch::Arm64),
+            ("1.1.0", Arch::X86_64),
+        ] {
+            add(
+                &dir,
+                &NewArtifact {
+                    name: "tool",
+                    version,
+                    file: &artifact,
+                    arch,
+                    bin: &bin,
+                    deps: &[],
+                    description: Some("Internal tool"),
+                },
+            )
+            .unwrap();
+        }
+
+        // Only the team key can sign the index.
+        assert!(build(&dir, &SigningKey::from_bytes(&[8; 32])).is_err());
+        assert_eq!(build(&dir, &key).unwrap(), 1);
+
+        let bytes = fs::read(dir.join("index")).unwrap();
+        let signature = {
+            use base64::Engine;
+            let sig = base64::engine::general_purpose::STANDARD
+                .decode(fs::read(dir.join("index.sig")).unwrap())
+                .unwrap();
+            ed25519_dalek::Signature::from_slice(&sig).unwrap()
+        };
+        key.verifying_key().verify(&bytes, &signature).unwrap();
+
+        let index = PackageIndex::from_bytes(&zstd::decode_all(bytes.as_slice()).unwrap()).unwrap();
+        let entry = index.find("tool").unwrap();
+        assert_eq!(entry.description, "Internal tool");
+        assert_eq!(entry.latest().unwrap().version, "1.1.0");
+        assert_eq!(entry.latest().unwrap().binaries.len(), 2);
+        let hash = entry.latest().unwrap().binaries[0].hash.to_string();
+        assert_eq!(
+            entry.latest().unwrap().binaries[0].url,
+            format!("https://pkgs.example.com/cas/{hash}")
+        );
+        assert_eq!(
+            index.mirror_base_url.as_deref(),
+            Some("https://pkgs.example.com")
+        );
+
+        let public = temp.path().join("public");
+        assert_eq!(publish(&dir, &Target::Dir(&public)).await.unwrap(), 1);
+        assert_eq!(fs::read(public.join("index")).unwrap(), bytes);
+        assert!(public.join("index.sig").exists());
+        assert!(public.join("cas").join(&hash).exists());
+        assert!(!public.join("packages").exists());
+        let mut published: Vec<_> = fs::read_dir(&public)
+            .unwrap()
+            .map(|e| e.unwrap().file_name())
+            .collect();
+        published.sort();
+        assert_eq!(published, ["cas", "index", "index.sig"]);
+        // Artifacts already published are not uploaded again.
+        assert_eq!(publish(&dir, &Target::Dir(&public)).await.unwrap(), 0);
+    }
+
+    #[test]
+    fn test_add_rejects_bad_names() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().join("internal");
+        init(
+            &dir,
+            "https://pkgs.example.com",
+            &SigningKey::from_bytes(&[7; 32]),
+        )
+        .unwrap();
+        let artifact = temp.path().join("tool.tar.gz");
+        fs::write(&artifact, b"tool").unwrap();
+
+        for name in ["../escape", "Tool", "", "a/b"] {
+            let result = add(
+                &dir,
+                &NewArtifact {
+                    name,
+                    version: "1.0.0",
+                    file: &artifact,
+                    arch: Arch::Arm64,
+                    bin: &[],
+                    deps: &[],
+                    description: None,
+                },
+            );
+            assert!(result.is_err(), "{name:?} accepted");
+        }
+        assert!(!temp.path().join("escape.toml").exists());
+        assert_eq!(fs::read_dir(dir.join("packages")).unwrap().count(), 0);
+        assert_eq!(fs::read_dir(dir.join("cas")).unwrap().count(), 0);
+    }
+}
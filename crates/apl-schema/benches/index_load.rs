@@ -0,0 +1,79 @@
+//! Compares loading the local index in the mapped layout against the
+//! previous fully decoded Postcard file.
+//!
+//! Run with `cargo bench -p apl-schema --bench index_load`.
+#![allow(missing_docs)]
+
+use std::hint::black_box;
+use std::path::Path;
+
+use apl_schema::index::{HashType, IndexBinary, IndexEntry, PackageIndex, VersionInfo};
+use apl_schema::{Arch, Sha256Hash};
+use criterion::{Criterion, criterion_group, criterion_main};
+
+const PACKAGES: usize = 20_000;
+
+/// A synthetic index roughly the shape of the real one: a few releases per
+/// package, each with two binaries.
+fn synthetic_index() -> PackageIndex {
+    let mut index = PackageIndex::new();
+    for i in 0..PACKAGES {
+        let name = format!("package-{i:05}");
+        let releases = (0..3)
+            .map(|minor| VersionInfo {
+                version: format!("1.{minor}.0"),
+                binaries: [Arch::Arm64, Arch::X86_64]
+                    .into_iter()
+                    .map(|arch| IndexBinary {
+                        arch,
+                        url: format!("https://example.com/{name}/1.{minor}.0/{arch:?}.tar.gz"),
+                        hash: Sha256Hash::new("0".repeat(64)),
+                        hash_type: HashType::Sha256,
+                    })
+                    .collect(),
+                deps: vec!["openssl".to_string()],
+                bin: vec![name.clone()],
+                ..VersionInfo::default()
+            })
+            .collect();
+        index.upsert(IndexEntry {
+            description: format!("Synthetic package number {i}"),
+            homepage: format!("https://example.com/{name}"),
+            type_: "cli".to_string(),
+            bins: vec![name.clone()],
+            name,
+            releases,
+            tags: vec!["bench".to_string()],
+            license: String::new(),
+        });
+    }
+    index
+}
+
+fn lookup(path: &Path) {
+    let index = PackageIndex::load(path).unwrap();
+    black_box(index.find("package-12345"));
+}
+
+fn bench_load(c: &mut Criterion) {
+    let dir = tempfile::tempdir().unwrap();
+    let legacy = dir.path().join("legacy");
+    let mapped = dir.path().join("mapped");
+    let index = synthetic_index();
+    std::fs::write(&legacy, index.to_bytes().unwrap()).unwrap();
+    index.save(&mapped).unwrap();
+
+    let mut group = c.benchmark_group("index");
+    group.bench_function("load_and_find/postcard", |b| b.iter(|| lookup(&legacy)));
+    group.bench_function("load_and_find/mapped", |b| b.iter(|| lookup(&mapped)));
+    group.bench_function("load_and_iterate/postcard", |b| {
+        b.iter(|| PackageIndex::load(&legacy).unwrap().iter().count());
+    });
+    group.bench_function("load_and_iterate/mapped", |b| {
+        b.iter(|| PackageIndex::load(&mapped).unwrap().iter().count());
+    });
+    group.finish();
+}
+
+criterion_group!(benches, bench_load);
+criterion_main!(benches);
@@ -15,9 +15,9 @@ fn main() -> anyhow::Result<()> {
     let index = PackageIndex::load(Path::new(&path))?;
     println!("Index Version: {}", index.version);
     println!("Updated At: {}", index.updated_at);
-    println!("Packages ({}):", index.packages.len());
+    println!("Packages ({}):", index.len());
 
-    for p in &index.packages {
+    for p in index.iter() {
         println!(" - {}", p.name);
     }
 
@@ -1,18 +1,32 @@
 //! Index definition and serialization via Postcard/Zstd.
 //!
-//! Low-overhead binary package registry format.
+//! Low-overhead binary package registry format. The index travels over the
+//! network as (compressed) Postcard; the local copy is saved in the mapped
+//! layout of [`mapped`] so loading it decodes only the entries a command
+//! actually looks at.
+//!
+//! Indexes from several named sources are combined with
+//! [`PackageIndex::merge`]: a package comes from the highest-priority source
+//! that has it, and `source::name` looks it up in one source in particular.
+//! The merged index looks entries up in the source indexes, so mapped
+//! sources still decode only what is used.
 
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use std::fs;
 use std::io;
 use std::path::Path;
+use std::sync::Arc;
 
-use fuzzy_matcher::FuzzyMatcher;
-use fuzzy_matcher::skim::SkimMatcherV2;
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Serialize, Serializer};
 use thiserror::Error;
 
+use crate::search::{Query, SearchOptions, tokenize};
 use crate::{Arch, Blake3Hash, Sha256Hash};
 
+mod mapped;
+
+use mapped::{MappedEntries, MappedHeader};
+
 /// Hash algorithm type for binary verification
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
 #[serde(rename_all = "lowercase")]
@@ -131,9 +145,81 @@ pub struct IndexEntry {
     /// Categories/Tags for the package
     #[serde(default)]
     pub tags: Vec<String>,
+    /// SPDX license identifier (index version 7+)
+    #[serde(default)]
+    pub license: String,
+}
+
+/// Package entry as encoded before index version 7 added `license`.
+#[derive(Deserialize)]
+struct IndexEntryV6 {
+    name: String,
+    description: String,
+    homepage: String,
+    type_: String,
+    bins: Vec<String>,
+    releases: Vec<VersionInfo>,
+    tags: Vec<String>,
+}
+
+impl From<IndexEntryV6> for IndexEntry {
+    fn from(v6: IndexEntryV6) -> Self {
+        Self {
+            name: v6.name,
+            description: v6.description,
+            homepage: v6.homepage,
+            type_: v6.type_,
+            bins: v6.bins,
+            releases: v6.releases,
+            tags: v6.tags,
+            license: String::new(),
+        }
+    }
+}
+
+/// Decodes one Postcard entry written by an index of format `version`.
+fn decode_entry(record: &[u8], version: u32) -> Result<IndexEntry, postcard::Error> {
+    if version < INDEX_VERSION {
+        postcard::from_bytes::<IndexEntryV6>(record).map(Into::into)
+    } else {
+        postcard::from_bytes(record)
+    }
 }
 
 impl IndexEntry {
+    /// Binary names any release provides, sorted. A package that declares
+    /// none provides a binary named after itself.
+    pub fn provided_bins(&self) -> BTreeSet<&str> {
+        let mut bins: BTreeSet<&str> = self
+            .bins
+            .iter()
+            .chain(self.releases.iter().flat_map(|r| &r.bin))
+            .map(String::as_str)
+            .collect();
+        if bins.is_empty() {
+            bins.insert(&self.name);
+        }
+        bins
+    }
+
+    /// Lowercase words of the name, binaries, tags, type and description,
+    /// plus the whole name and binary names. These make up the inverted
+    /// token index searched by [`crate::search`].
+    pub fn search_tokens(&self) -> BTreeSet<String> {
+        let mut whole = self.provided_bins();
+        whole.insert(&self.name);
+        let words = whole
+            .iter()
+            .copied()
+            .chain(self.tags.iter().map(String::as_str))
+            .chain([self.type_.as_str(), self.description.as_str()]);
+        whole
+            .iter()
+            .map(|w| w.to_lowercase())
+            .chain(words.flat_map(tokenize))
+            .collect()
+    }
+
     /// Get the latest release (if any)
     pub fn latest(&self) -> Option<&VersionInfo> {
         self.releases.first()
@@ -152,37 +238,110 @@ impl IndexEntry {
     }
 }
 
+/// Separates a source name from a package name, as in `internal::tool`.
+pub const SOURCE_SEPARATOR: &str = "::";
+
+/// Splits `source::name` into its source and package name.
+pub fn split_source(name: &str) -> (Option<&str>, &str) {
+    match name.split_once(SOURCE_SEPARATOR) {
+        Some((source, name)) => (Some(source), name),
+        None => (None, name),
+    }
+}
+
+/// Current index format version. Version 7 added [`IndexEntry::license`];
+/// older indexes still load.
+pub const INDEX_VERSION: u32 = 7;
+
 /// Package index (binary format)
+///
+/// An index loaded from disk keeps its entries in the memory-mapped file and
+/// decodes each one on first access; mutating it decodes them all first.
 #[allow(clippy::unsafe_derive_deserialize)]
-#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Deserialize)]
 pub struct PackageIndex {
-    /// Index format version (Bumped to 6 for Merkle root support)
+    /// Index format version (6 added Merkle roots, 7 package licenses)
     pub version: u32,
     /// Unix timestamp of last update
     pub updated_at: i64,
-    /// Package entries
-    pub packages: Vec<IndexEntry>,
+    /// Package entries, sorted by name (empty while `mapped` is set)
+    packages: Vec<IndexEntry>,
     /// Base URL for artifact mirror (CAS layout: `{base_url}/cas/{hash}`).
     #[serde(default)]
     pub mirror_base_url: Option<String>,
     /// Merkle tree root hash (BLAKE3) for integrity verification
     #[serde(default)]
     pub merkle_root: Option<Blake3Hash>,
+    /// Lazily decoded entries of an index loaded in the mapped layout
+    #[serde(skip)]
+    mapped: Option<Arc<MappedEntries>>,
+    /// Named sources of an index built by [`PackageIndex::merge`]
+    #[serde(skip)]
+    sources: Option<Arc<Sources>>,
+}
+
+/// The indexes a merged index was built from.
+#[derive(Debug, Clone)]
+struct Sources {
+    /// Source names and indexes, highest priority first
+    layers: Vec<(String, PackageIndex)>,
+    /// Packages taken from another source than the one that would win,
+    /// by name, with the position of their source in `layers`
+    selected: HashMap<String, usize>,
+    /// Every package in name order, as the position of its source in
+    /// `layers` and of the entry in that source; `None` for a single source
+    /// and once the entries were copied out to modify the index
+    slots: Option<Vec<(usize, usize)>>,
+}
+
+impl Sources {
+    fn position(&self, source: &str) -> Option<usize> {
+        self.layers.iter().position(|(name, _)| name == source)
+    }
+
+    fn entry(&self, (layer, i): (usize, usize)) -> Option<&IndexEntry> {
+        self.layers.get(layer)?.1.get(i)
+    }
+
+    fn name(&self, (layer, i): (usize, usize)) -> Option<&str> {
+        self.layers.get(layer)?.1.name_at(i)
+    }
+}
+
+impl Serialize for PackageIndex {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        // Field order is the wire format; it must match the struct above.
+        #[derive(Serialize)]
+        struct Wire<'a> {
+            version: u32,
+            updated_at: i64,
+            packages: Vec<&'a IndexEntry>,
+            mirror_base_url: &'a Option<String>,
+            merkle_root: &'a Option<Blake3Hash>,
+        }
+
+        Wire {
+            version: self.version,
+            updated_at: self.updated_at,
+            packages: self.iter().collect(),
+            mirror_base_url: &self.mirror_base_url,
+            merkle_root: &self.merkle_root,
+        }
+        .serialize(serializer)
+    }
 }
 
 impl PackageIndex {
     /// Create a new empty index
     pub fn new() -> Self {
         Self {
-            version: 6,
-            updated_at: 0,
-            mirror_base_url: None,
-            merkle_root: None,
-            packages: Vec::new(),
+            version: INDEX_VERSION,
+            ..Self::default()
         }
     }
 
-    /// Memory-maps and deserializes the index, auto-detecting Zstd compression.
+    /// Memory-maps the index. Files in the mapped layout are decoded lazily;
+    /// older Postcard files (optionally Zstd-compressed) are decoded in full.
     ///
     /// # Errors
     ///
@@ -193,42 +352,76 @@ impl PackageIndex {
     pub fn load(path: &Path) -> Result<Self, IndexError> {
         let file = fs::File::open(path)?;
         // SAFETY: The file is opened read-only and we hold the File handle for
-        // the lifetime of the Mmap. No concurrent writers exist because the CLI
-        // takes an advisory lock before writing the index.
+        // the lifetime of the Mmap. The file is never modified in place:
+        // `save` writes a new file and renames it over this one, so a map
+        // keeps the old inode even when another process (or a long-lived one
+        // like `apl browse` that holds no lock) updates the index.
         let mmap = unsafe { memmap2::Mmap::map(&file)? };
 
-        // Implementation Note: Memory Mapping and Zero-Copy
+        // Implementation Note: Memory Mapping and Lazy Decoding
         //
         // Instead of reading the file into a `Vec<u8>` (heap allocation), we use `mmap`.
         // This maps the file directly into process memory. The OS handles paging it in/out.
-        // `postcard::from_bytes` then deserializes structs pointing *directly* to this memory
-        // where possible (borrowing), avoiding string copies.
-        //
-        // This makes startup for large indices (10k+ packages) nearly instantaneous.
-        let mut index = if mmap.len() >= 4 && mmap[0..4] == crate::ZSTD_MAGIC {
+        // In the mapped layout, lookups binary-search the sorted offset table in place and
+        // decode only the entries they return, so startup cost no longer grows with the
+        // number of packages.
+        if mapped::is_mapped(&mmap) {
+            let (header, entries) = MappedEntries::open(mmap)?;
+            if header.version < 4 {
+                return Err(IndexError::VersionMismatch(header.version, 4));
+            }
+            // Older records are upgraded as they are decoded.
+            return Ok(Self {
+                version: header.version.max(INDEX_VERSION),
+                updated_at: header.updated_at,
+                packages: Vec::new(),
+                mirror_base_url: header.mirror_base_url,
+                merkle_root: header.merkle_root,
+                mapped: Some(Arc::new(entries)),
+                sources: None,
+            });
+        }
+
+        if mmap.len() >= 4 && mmap[0..4] == crate::ZSTD_MAGIC {
             let decompressed = zstd::decode_all(&mmap[..])?;
-            postcard::from_bytes(&decompressed)
-                .map_err(|_| IndexError::Postcard(postcard::Error::DeserializeBadVarint))?
+            Self::from_bytes(&decompressed)
         } else {
-            // Postcard header check (version defined in from_bytes)
-            Self::from_bytes(&mmap)?
-        };
-
-        // Ensure sorted for O(log n) lookups
-        index.ensure_sorted();
-        Ok(index)
+            Self::from_bytes(&mmap)
+        }
     }
 
-    /// Serializes to an uncompressed Postcard file, optimized for MMAP usage.
+    /// Writes the index in the mapped layout, optimized for lazy MMAP loading.
+    ///
+    /// The file is written and synced beside `path`, then renamed over it,
+    /// so indexes already mapped from `path` keep reading the old file.
     ///
     /// # Errors
     ///
     /// Returns [`IndexError::Postcard`] on serialization failure or
     /// [`IndexError::Io`] if the file cannot be written.
     pub fn save(&self, path: &Path) -> Result<(), IndexError> {
-        let buf = postcard::to_allocvec(self)?;
-        fs::write(path, &buf)?;
-        Ok(())
+        let header = MappedHeader {
+            version: self.version,
+            updated_at: self.updated_at,
+            mirror_base_url: self.mirror_base_url.clone(),
+            merkle_root: self.merkle_root.clone(),
+        };
+        let entries: Vec<&IndexEntry> = self.iter().collect();
+        let buf = mapped::encode(&header, &entries)?;
+
+        let mut staging = path.as_os_str().to_owned();
+        staging.push(format!(".{}.tmp", std::process::id()));
+        let staging = std::path::PathBuf::from(staging);
+        let written = (|| {
+            let mut file = fs::File::create(&staging)?;
+            io::Write::write_all(&mut file, &buf)?;
+            file.sync_all()?;
+            fs::rename(&staging, path)
+        })();
+        if written.is_err() {
+            let _ = fs::remove_file(&staging);
+        }
+        Ok(written?)
     }
 
     /// Serializes and compresses the index for network distribution.
@@ -276,11 +469,65 @@ impl PackageIndex {
             return Err(IndexError::VersionMismatch(header.version, 4));
         }
 
-        Ok(postcard::from_bytes(data)?)
+        let mut index: Self = if header.version < INDEX_VERSION {
+            #[derive(Deserialize)]
+            struct LegacyIndex {
+                _version: u32,
+                updated_at: i64,
+                packages: Vec<IndexEntryV6>,
+                mirror_base_url: Option<String>,
+                merkle_root: Option<Blake3Hash>,
+            }
+            let legacy: LegacyIndex = postcard::from_bytes(data)?;
+            Self {
+                updated_at: legacy.updated_at,
+                packages: legacy.packages.into_iter().map(Into::into).collect(),
+                mirror_base_url: legacy.mirror_base_url,
+                merkle_root: legacy.merkle_root,
+                ..Self::new()
+            }
+        } else {
+            postcard::from_bytes(data)?
+        };
+        // Ensure sorted for O(log n) lookups
+        index.ensure_sorted();
+        Ok(index)
+    }
+
+    /// Number of packages.
+    pub fn len(&self) -> usize {
+        if let Some((_, slots)) = self.layered() {
+            return slots.len();
+        }
+        self.packages.len() + self.mapped.as_ref().map_or(0, |m| m.len())
+    }
+
+    /// Whether the index has no packages.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Every package, in name order.
+    pub fn iter(&self) -> impl Iterator<Item = &IndexEntry> {
+        self.packages
+            .iter()
+            .chain(self.mapped.iter().flat_map(|m| m.iter()))
+            .chain(
+                self.layered().into_iter().flat_map(|(sources, slots)| {
+                    slots.iter().filter_map(|&slot| sources.entry(slot))
+                }),
+            )
+    }
+
+    /// Keeps only the packages for which `keep` returns true.
+    pub fn retain(&mut self, keep: impl FnMut(&IndexEntry) -> bool) {
+        self.materialize();
+        self.packages.retain(keep);
     }
 
     /// Add or update a package entry (full entry)
     pub fn upsert(&mut self, entry: IndexEntry) {
+        self.materialize();
         match self.packages.binary_search_by(|e| e.name.cmp(&entry.name)) {
             Ok(idx) => self.packages[idx] = entry,
             Err(idx) => self.packages.insert(idx, entry),
@@ -296,6 +543,7 @@ impl PackageIndex {
         tags: Vec<String>,
         release: VersionInfo,
     ) {
+        self.materialize();
         match self
             .packages
             .binary_search_by(|e| e.name.as_str().cmp(name))
@@ -350,94 +598,104 @@ impl PackageIndex {
                         bins,
                         releases: vec![release],
                         tags,
+                        license: String::new(),
                     },
                 );
             }
         }
     }
 
+    /// Find a package by name for modification.
+    pub fn find_mut(&mut self, name: impl AsRef<str>) -> Option<&mut IndexEntry> {
+        self.materialize();
+        let n = name.as_ref();
+        self.packages
+            .binary_search_by(|e| e.name.as_str().cmp(n))
+            .ok()
+            .map(|idx| &mut self.packages[idx])
+    }
+
     /// Find a package by name - O(log n) binary search
+    ///
+    /// In a merged index, `source::name` finds the package in that source
+    /// even when another source's package of the same name wins.
     pub fn find(&self, name: impl AsRef<str>) -> Option<&IndexEntry> {
         let n = name.as_ref();
+        if let (Some(source), name) = split_source(n) {
+            let sources = self.sources.as_ref()?;
+            return sources.layers[sources.position(source)?].1.find(name);
+        }
+        if let Some(mapped) = &self.mapped {
+            return mapped.find(n);
+        }
+        if self.layered().is_some() {
+            return self.get(self.position(n).ok()?);
+        }
         self.packages
             .binary_search_by(|e| e.name.as_str().cmp(n))
             .ok()
             .map(|idx| &self.packages[idx])
     }
 
-    /// Search packages by query (matches name or description)
+    /// Search packages with the [`crate::search`] query language.
     ///
-    /// Supports fuzzy matching via `SkimMatcherV2` and tag filtering via `tag:<name>`.
-    /// Results are ranked by match score.
+    /// Returns the 50 best matches. Queries that fail to parse match
+    /// nothing; use [`crate::search::search`] for errors, pagination and
+    /// `installed:` filters.
     pub fn search(&self, query: &str) -> Vec<&IndexEntry> {
-        const MIN_SCORE: i64 = 50;
+        let Ok(query) = query.parse::<Query>() else {
+            return Vec::new();
+        };
+        let options = SearchOptions {
+            limit: Some(50),
+            ..SearchOptions::default()
+        };
+        crate::search::search(self, &query, &options)
+            .hits
+            .into_iter()
+            .map(|hit| hit.entry)
+            .collect()
+    }
 
-        if query.is_empty() {
-            return self.packages.iter().take(50).collect();
+    /// Packages providing binary `bin`, in name order.
+    ///
+    /// Indexes loaded from disk answer from the binary table saved with
+    /// them; others scan every entry.
+    pub fn providers(&self, bin: &str) -> Vec<&IndexEntry> {
+        if let Some(mapped) = &self.mapped {
+            return mapped.providers(bin);
         }
-
-        let matcher = SkimMatcherV2::default();
-        let query_lower = query.to_lowercase();
-
-        // 1. Check for tag: filter
-        if let Some(tag_query) = query_lower.strip_prefix("tag:") {
-            return self
-                .packages
+        if let Some((sources, slots)) = self.layered() {
+            let mut providers: Vec<&IndexEntry> = sources
+                .layers
                 .iter()
-                .filter(|e| e.tags.iter().any(|t| t.to_lowercase() == tag_query))
+                .enumerate()
+                .flat_map(|(layer, (_, index))| {
+                    index.providers(bin).into_iter().filter(
+                        move |e| matches!(self.position(&e.name), Ok(i) if slots[i].0 == layer),
+                    )
+                })
                 .collect();
+            providers.sort_by(|a, b| a.name.cmp(&b.name));
+            return providers;
         }
-
-        // 2. Perform fuzzy search
-        let mut results: Vec<(i64, &IndexEntry)> = self
-            .packages
-            .iter()
-            .filter_map(|e| {
-                // We score name, description, and bins
-                let mut best_score = matcher.fuzzy_match(&e.name, query);
-
-                if let Some(desc_score) = matcher.fuzzy_match(&e.description, query) {
-                    // Description matches are slightly less weighted than name matches
-                    let adjusted = desc_score / 2;
-                    best_score = Some(best_score.unwrap_or(0).max(adjusted));
-                }
-
-                for b in &e.bins {
-                    if let Some(bin_score) = matcher.fuzzy_match(b, query) {
-                        best_score = Some(best_score.unwrap_or(0).max(bin_score));
-                    }
-                }
-
-                // 3. Fallback for typos: if query starts with name or name starts with query (and long enough)
-                if best_score.unwrap_or(0) < MIN_SCORE {
-                    if (query.len() > 3 && e.name.starts_with(query))
-                        || (e.name.len() > 2 && query.starts_with(&e.name))
-                    {
-                        best_score = Some(best_score.unwrap_or(0).max(MIN_SCORE));
-                    }
-
-                    for b in &e.bins {
-                        if (query.len() > 3 && b.starts_with(query))
-                            || (b.len() > 2 && query.starts_with(b))
-                        {
-                            best_score = Some(best_score.unwrap_or(0).max(MIN_SCORE));
-                        }
-                    }
-                }
-
-                // Only include results above minimum threshold
-                best_score.filter(|s| *s >= MIN_SCORE).map(|s| (s, e))
-            })
-            .collect();
-
-        // 3. Rank by score descending
-        results.sort_by(|a, b| b.0.cmp(&a.0));
-
-        results.into_iter().map(|(_, e)| e).take(50).collect()
+        self.iter()
+            .filter(|e| e.provided_bins().contains(bin))
+            .collect()
     }
 
     /// Search packages by name prefix - O(log n) using binary search
     pub fn search_prefix(&self, prefix: &str) -> Vec<&IndexEntry> {
+        if let Some(mapped) = &self.mapped {
+            return mapped.prefix(prefix).collect();
+        }
+        if self.layered().is_some() {
+            let start = self.position(prefix).unwrap_or_else(|i| i);
+            return (start..self.len())
+                .take_while(|&i| self.name_at(i).is_some_and(|n| n.starts_with(prefix)))
+                .filter_map(|i| self.get(i))
+                .collect();
+        }
         let start = self.packages.partition_point(|e| e.name.as_str() < prefix);
 
         // Collect all entries that start with prefix
@@ -452,6 +710,197 @@ impl PackageIndex {
     fn ensure_sorted(&mut self) {
         self.packages.sort_by(|a, b| a.name.cmp(&b.name));
     }
+
+    /// Entry `i` in name order.
+    pub(crate) fn get(&self, i: usize) -> Option<&IndexEntry> {
+        if let Some((sources, slots)) = self.layered() {
+            return sources.entry(*slots.get(i)?);
+        }
+        match &self.mapped {
+            Some(mapped) => mapped.get(i),
+            None => self.packages.get(i),
+        }
+    }
+
+    /// Positions of the entries with a search token starting with `prefix`.
+    ///
+    /// Indexes loaded from disk answer from the token table saved with them;
+    /// others tokenize every entry.
+    pub(crate) fn token_prefix(&self, prefix: &str) -> BTreeSet<usize> {
+        if let Some(mapped) = &self.mapped {
+            return mapped.token_prefix(prefix);
+        }
+        if let Some((sources, slots)) = self.layered() {
+            let mut positions = BTreeSet::new();
+            for (layer, (_, index)) in sources.layers.iter().enumerate() {
+                for i in index.token_prefix(prefix) {
+                    let Some(name) = index.name_at(i) else {
+                        continue;
+                    };
+                    if let Ok(position) = self.position(name)
+                        && slots[position].0 == layer
+                    {
+                        positions.insert(position);
+                    }
+                }
+            }
+            return positions;
+        }
+        self.iter()
+            .enumerate()
+            .filter(|(_, e)| e.search_tokens().iter().any(|t| t.starts_with(prefix)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// Name of entry `i` in name order, read without decoding the entry.
+    fn name_at(&self, i: usize) -> Option<&str> {
+        if let Some((sources, slots)) = self.layered() {
+            return sources.name(*slots.get(i)?);
+        }
+        match &self.mapped {
+            Some(mapped) => mapped.name(i),
+            None => self.packages.get(i).map(|e| e.name.as_str()),
+        }
+    }
+
+    /// Binary search by name over [`Self::name_at`].
+    fn position(&self, name: &str) -> Result<usize, usize> {
+        let (mut lo, mut hi) = (0, self.len());
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.name_at(mid).unwrap_or_default().cmp(name) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(lo)
+    }
+
+    /// Sources and package slots of a merged index whose entries still live
+    /// in the indexes it was merged from.
+    fn layered(&self) -> Option<(&Sources, &[(usize, usize)])> {
+        let sources = self.sources.as_deref()?;
+        Some((sources, sources.slots.as_deref()?))
+    }
+
+    /// Combines the indexes of several named sources, highest priority first.
+    ///
+    /// Each package comes from the first source that has it. Entries stay in
+    /// the source indexes and are only decoded when looked up.
+    pub fn merge(layers: Vec<(String, Self)>) -> Self {
+        let mut merged = match layers.as_slice() {
+            [] => Self::new(),
+            [(_, only)] => only.clone(),
+            [(_, first), ..] => Self {
+                updated_at: layers.iter().map(|(_, i)| i.updated_at).max().unwrap_or(0),
+                mirror_base_url: first.mirror_base_url.clone(),
+                ..Self::new()
+            },
+        };
+        let slots = (layers.len() > 1).then(|| {
+            let mut slots = BTreeMap::new();
+            for (layer, (_, index)) in layers.iter().enumerate() {
+                for i in 0..index.len() {
+                    if let Some(name) = index.name_at(i) {
+                        slots.entry(name).or_insert((layer, i));
+                    }
+                }
+            }
+            slots.into_values().collect()
+        });
+        merged.sources = Some(Arc::new(Sources {
+            layers,
+            selected: HashMap::new(),
+            slots,
+        }));
+        merged
+    }
+
+    /// Names of the sources this index was merged from, highest priority
+    /// first; empty for an index that was not merged.
+    pub fn source_names(&self) -> impl Iterator<Item = &str> {
+        self.sources
+            .iter()
+            .flat_map(|s| s.layers.iter().map(|(name, _)| name.as_str()))
+    }
+
+    /// The source package `name` comes from in a merged index.
+    pub fn source_of(&self, name: &str) -> Option<&str> {
+        let sources = self.sources.as_ref()?;
+        let position = match sources.selected.get(name) {
+            Some(&position) => position,
+            None => sources
+                .layers
+                .iter()
+                .position(|(_, index)| index.position(name).is_ok())?,
+        };
+        Some(sources.layers[position].0.as_str())
+    }
+
+    /// Mirror base URL for package `name`: that of the source it comes
+    /// from in a merged index, this index's own otherwise.
+    pub fn mirror_of(&self, name: &str) -> Option<&str> {
+        let Some(sources) = &self.sources else {
+            return self.mirror_base_url.as_deref();
+        };
+        let position = sources.position(self.source_of(name)?)?;
+        sources.layers[position].1.mirror_base_url.as_deref()
+    }
+
+    /// Makes `name` come from `source` rather than the source that would
+    /// win. Returns `false` if the source is unknown or lacks the package.
+    pub fn select(&mut self, name: &str, source: &str) -> bool {
+        if self.source_of(name) == Some(source) {
+            return true;
+        }
+        let Some(sources) = &self.sources else {
+            return false;
+        };
+        let Some(position) = sources.position(source) else {
+            return false;
+        };
+        let layer = &sources.layers[position].1;
+        let Ok(at) = layer.position(name) else {
+            return false;
+        };
+        // A layered index only has to point the package at the other source.
+        let slot = match self.layered() {
+            Some(_) => self.position(name).ok(),
+            None => None,
+        };
+        let entry = match slot {
+            Some(_) => None,
+            None => layer.get(at).cloned(),
+        };
+
+        let Some(sources) = self.sources.as_mut() else {
+            return false;
+        };
+        let sources = Arc::make_mut(sources);
+        sources.selected.insert(name.to_string(), position);
+        if let (Some(slot), Some(slots)) = (slot, sources.slots.as_mut()) {
+            slots[slot] = (position, at);
+        }
+        if let Some(entry) = entry {
+            self.upsert(entry);
+        }
+        true
+    }
+
+    /// Decodes every lazily loaded entry so the index can be modified.
+    fn materialize(&mut self) {
+        if self.layered().is_some() {
+            self.packages = self.iter().cloned().collect();
+            if let Some(sources) = self.sources.as_mut() {
+                Arc::make_mut(sources).slots = None;
+            }
+        }
+        if let Some(mapped) = self.mapped.take() {
+            self.packages = mapped.iter().cloned().collect();
+        }
+    }
 }
 
 #[cfg(test)]
@@ -485,14 +934,17 @@ mod tests {
                 source: None,
             }],
             tags: vec![],
+            license: String::new(),
         });
 
         let bytes = index.to_bytes().unwrap();
         let restored = PackageIndex::from_bytes(&bytes).unwrap();
 
-        assert_eq!(restored.packages.len(), 1);
-        assert_eq!(restored.packages[0].name, "neovim");
-        assert_eq!(restored.packages[0].releases[0].version, "0.10.0");
+        assert_eq!(restored.len(), 1);
+        assert_eq!(
+            restored.find("neovim").unwrap().releases[0].version,
+            "0.10.0"
+        );
     }
 
     #[test]
@@ -579,7 +1031,215 @@ mod tests {
         let loaded = PackageIndex::load(&path).unwrap();
 
         assert_eq!(loaded.updated_at, 1_234_567_890);
-        assert_eq!(loaded.packages[0].name, "ripgrep");
+        assert_eq!(
+            loaded.find("ripgrep").unwrap().releases[0].version,
+            "14.0.0"
+        );
+    }
+
+    fn release(version: &str) -> VersionInfo {
+        VersionInfo {
+            version: version.to_string(),
+            ..VersionInfo::default()
+        }
+    }
+
+    #[test]
+    fn test_mapped_lookups() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("index");
+
+        let mut index = PackageIndex::new();
+        index.mirror_base_url = Some("https://mirror.example".to_string());
+        for name in ["ripgrep", "fd", "rip", "fzf", "bat", "ripsecrets"] {
+            index.upsert_release(name, "", "cli", vec![], release("1.0.0"));
+        }
+        let mut rg = release("14.0.0");
+        rg.bin = vec!["rg".to_string()];
+        index.upsert_release("ripgrep", "", "cli", vec![], rg);
+        let mut fdfind = release("9.0.0");
+        fdfind.bin = vec!["fd".to_string(), "fdfind".to_string()];
+        index.upsert_release("fdfind", "", "cli", vec![], fdfind);
+        index.save(&path).unwrap();
+        assert!(fs::read(&path).unwrap().starts_with(mapped::MAGIC));
+
+        let loaded = PackageIndex::load(&path).unwrap();
+        assert!(loaded.mapped.is_some());
+        assert_eq!(
+            loaded.mirror_base_url.as_deref(),
+            Some("https://mirror.example")
+        );
+        assert_eq!(loaded.len(), 7);
+        assert_eq!(loaded.find("fzf").unwrap().name, "fzf");
+        assert!(loaded.find("fz").is_none());
+        assert!(loaded.find("zsh").is_none());
+        let names = |entries: Vec<&IndexEntry>| -> Vec<String> {
+            entries.into_iter().map(|e| e.name.clone()).collect()
+        };
+        assert_eq!(
+            names(loaded.search_prefix("rip")),
+            vec!["rip", "ripgrep", "ripsecrets"]
+        );
+        assert!(loaded.search_prefix("x").is_empty());
+        assert_eq!(names(loaded.providers("rg")), vec!["ripgrep"]);
+        assert_eq!(names(loaded.providers("fd")), vec!["fd", "fdfind"]);
+        assert!(loaded.providers("grep").is_empty());
+        // Full-text search answers from the saved token table.
+        assert_eq!(loaded.token_prefix("rip"), restored_tokens(&loaded, "rip"));
+        assert_eq!(names(loaded.search("rg")), vec!["ripgrep"]);
+        assert_eq!(names(loaded.search("fd"))[0], "fd");
+        assert_eq!(
+            names(loaded.iter().collect()),
+            vec!["bat", "fd", "fdfind", "fzf", "rip", "ripgrep", "ripsecrets"]
+        );
+        // The wire format still carries every entry, and scanning it agrees.
+        let restored = PackageIndex::from_bytes(&loaded.to_bytes().unwrap()).unwrap();
+        assert_eq!(restored.len(), 7);
+        assert_eq!(names(restored.providers("fd")), vec!["fd", "fdfind"]);
+
+        // Saving over the file leaves existing maps reading the old one.
+        let before = PackageIndex::load(&path).unwrap();
+        let mut replacement = PackageIndex::new();
+        replacement.upsert_release("zsh", "", "cli", vec![], release("5.9.0"));
+        replacement.save(&path).unwrap();
+        assert_eq!(before.find("fzf").unwrap().name, "fzf");
+        assert!(before.find("zsh").is_none());
+        assert_eq!(PackageIndex::load(&path).unwrap().len(), 1);
+        assert_eq!(fs::read_dir(dir.path()).unwrap().count(), 1);
+
+        // Modifying a mapped index decodes it first.
+        let mut loaded = loaded;
+        loaded.upsert_release("fd", "", "cli", vec![], release("2.0.0"));
+        loaded.retain(|e| e.name != "bat");
+        assert!(loaded.mapped.is_none());
+        assert_eq!(loaded.len(), 6);
+        assert_eq!(
+            loaded.find("fd").unwrap().latest().unwrap().version,
+            "2.0.0"
+        );
+    }
+
+    #[test]
+    fn test_merged_sources() {
+        let dir = tempdir().unwrap();
+        let mut public = PackageIndex::new();
+        public.mirror_base_url = Some("https://public.example".to_string());
+        let mut tool = release("2.0.0");
+        tool.bin = vec!["tool".to_string()];
+        public.upsert_release("tool", "public", "cli", vec![], tool);
+        public.upsert_release("jq", "", "cli", vec![], release("1.7.1"));
+        public.save(&dir.path().join("public")).unwrap();
+        let mut internal = PackageIndex::new();
+        let mut tool = release("0.3.0");
+        tool.bin = vec!["tool".to_string()];
+        internal.upsert_release("tool", "internal", "cli", vec![], tool);
+        internal.upsert_release("deploy", "", "cli", vec![], release("1.0.0"));
+
+        let mut merged = PackageIndex::merge(vec![
+            (
+                "apl".to_string(),
+                PackageIndex::load(&dir.path().join("public")).unwrap(),
+            ),
+            ("internal".to_string(), internal),
+        ]);
+        // Entries stay in the source indexes.
+        assert!(merged.packages.is_empty() && merged.mapped.is_none());
+        assert_eq!(merged.len(), 3);
+        let names = |entries: Vec<&IndexEntry>| -> Vec<String> {
+            entries.into_iter().map(|e| e.name.clone()).collect()
+        };
+        assert_eq!(names(merged.iter().collect()), vec!["deploy", "jq", "tool"]);
+        assert_eq!(names(merged.search_prefix("d")), vec!["deploy"]);
+        assert_eq!(merged.providers("tool")[0].description, "public");
+        assert_eq!(
+            merged.token_prefix("tool"),
+            restored_tokens(&merged, "tool")
+        );
+        assert_eq!(names(merged.search("deploy")), vec!["deploy"]);
+        assert_eq!(
+            merged.source_names().collect::<Vec<_>>(),
+            ["apl", "internal"]
+        );
+        assert_eq!(merged.find("tool").unwrap().description, "public");
+        assert_eq!(merged.source_of("tool"), Some("apl"));
+        assert_eq!(merged.source_of("deploy"), Some("internal"));
+        assert_eq!(
+            merged.find("internal::tool").unwrap().description,
+            "internal"
+        );
+        assert!(merged.find("internal::jq").is_none());
+        assert!(merged.find("nope::tool").is_none());
+        assert_eq!(merged.mirror_of("jq"), Some("https://public.example"));
+        assert_eq!(merged.mirror_of("deploy"), None);
+
+        assert!(merged.select("tool", "internal"));
+        assert_eq!(merged.find("tool").unwrap().description, "internal");
+        assert_eq!(merged.source_of("tool"), Some("internal"));
+        assert_eq!(merged.providers("tool")[0].description, "internal");
+        assert_eq!(merged.len(), 3);
+        assert!(!merged.select("jq", "internal"));
+        assert!(!PackageIndex::new().select("tool", "apl"));
+        assert!(PackageIndex::new().find("apl::tool").is_none());
+    }
+
+    #[test]
+    fn test_legacy_file_still_loads() {
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("index");
+
+        let mut index = PackageIndex::new();
+        index.upsert_release("jq", "", "cli", vec![], release("1.7.1"));
+        fs::write(&path, index.to_bytes().unwrap()).unwrap();
+
+        let loaded = PackageIndex::load(&path).unwrap();
+        assert!(loaded.mapped.is_none());
+        assert_eq!(
+            loaded.find("jq").unwrap().latest().unwrap().version,
+            "1.7.1"
+        );
+    }
+
+    /// Positions found by tokenizing every entry, ignoring any token table.
+    fn restored_tokens(index: &PackageIndex, prefix: &str) -> BTreeSet<usize> {
+        index
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.search_tokens().iter().any(|t| t.starts_with(prefix)))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    #[test]
+    fn test_version_6_index_loads() {
+        // Version 6 entries end at `tags`; there is no license.
+        #[derive(Serialize)]
+        struct EntryV6 {
+            name: String,
+            description: String,
+            homepage: String,
+            type_: String,
+            bins: Vec<String>,
+            releases: Vec<VersionInfo>,
+            tags: Vec<String>,
+        }
+        let entry = EntryV6 {
+            name: "jq".to_string(),
+            description: "JSON processor".to_string(),
+            homepage: String::new(),
+            type_: "cli".to_string(),
+            bins: vec![],
+            releases: vec![release("1.7.1")],
+            tags: vec!["json".to_string()],
+        };
+        let bytes =
+            postcard::to_allocvec(&(6u32, 0i64, vec![entry], None::<String>, None::<Blake3Hash>))
+                .unwrap();
+
+        let index = PackageIndex::from_bytes(&bytes).unwrap();
+        assert_eq!(index.version, INDEX_VERSION);
+        let jq = index.find("jq").unwrap();
+        assert_eq!(jq.tags, vec!["json"]);
+        assert!(jq.license.is_empty());
     }
 
     /// Regression test: semver sorting must handle 0.12.0 > 0.9.1 correctly.
@@ -0,0 +1,316 @@
+//! Lazily decoded on-disk index layout.
+//!
+//! The local `~/.apl/index` file is laid out so a lookup touches only the
+//! pages it needs:
+//!
+//! ```text
+//! magic        8 bytes   "APLIDX01"
+//! header_len   u32 LE
+//! header       postcard  MappedHeader (version, updated_at, mirror, merkle root)
+//! count        u32 LE
+//! table        count × 16 bytes, sorted by name:
+//!                name_off u32, name_len u32, entry_off u32, entry_len u32
+//! bin_count    u32 LE
+//! bin_table    bin_count × 16 bytes, sorted by binary name:
+//!                bin_off u32, bin_len u32, list_off u32, list_len u32
+//! token_count  u32 LE
+//! token_table  token_count × 16 bytes, sorted by search token:
+//!                token_off u32, token_len u32, list_off u32, list_len u32
+//! data         package names, postcard-encoded `IndexEntry` records,
+//!              binary names, search tokens and their lists of u32 LE
+//!              entry numbers
+//! ```
+//!
+//! Offsets are absolute. Names are compared as bytes straight from the map
+//! and an entry is only decoded the first time it is asked for. The token
+//! table is the inverted index behind [`crate::search`]; building it when
+//! the index is written keeps a full-text query from decoding every entry.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::sync::OnceLock;
+
+use serde::{Deserialize, Serialize};
+
+use super::{IndexEntry, IndexError};
+use crate::Blake3Hash;
+
+/// Leading bytes of a mapped index file.
+pub(super) const MAGIC: &[u8; 8] = b"APLIDX01";
+
+const TABLE_ROW: usize = 16;
+
+/// Whether `bytes` start like a mapped index file.
+pub(super) fn is_mapped(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// Index-wide fields stored ahead of the offset table.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub(super) struct MappedHeader {
+    pub(super) version: u32,
+    pub(super) updated_at: i64,
+    pub(super) mirror_base_url: Option<String>,
+    pub(super) merkle_root: Option<Blake3Hash>,
+}
+
+/// A sorted table of `(key, value)` spans.
+#[derive(Debug, Clone, Copy)]
+struct Table {
+    start: usize,
+    len: usize,
+}
+
+/// Entries of a mapped index, decoded on first access.
+pub(super) struct MappedEntries {
+    mmap: memmap2::Mmap,
+    entries: Table,
+    /// Binary name to providing entries
+    bins: Table,
+    /// Search token to matching entries
+    tokens: Table,
+    /// Index format version the entry records were written with
+    version: u32,
+    cache: Vec<OnceLock<Option<IndexEntry>>>,
+}
+
+impl fmt::Debug for MappedEntries {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("MappedEntries")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl MappedEntries {
+    /// Parses the header and checks the offset tables against the data.
+    ///
+    /// Entries themselves are not decoded; a corrupt record surfaces as a
+    /// missing package when it is first looked up.
+    pub(super) fn open(mmap: memmap2::Mmap) -> Result<(MappedHeader, Self), IndexError> {
+        let bytes = &mmap[..];
+        let corrupt = || IndexError::Postcard(postcard::Error::DeserializeUnexpectedEnd);
+
+        if !is_mapped(bytes) {
+            return Err(corrupt());
+        }
+        let header_len = read_u32(bytes, MAGIC.len()).ok_or_else(corrupt)? as usize;
+        let header_start = MAGIC.len() + 4;
+        let header_bytes = bytes
+            .get(header_start..header_start + header_len)
+            .ok_or_else(corrupt)?;
+        let header: MappedHeader = postcard::from_bytes(header_bytes)?;
+
+        let read_table = |at: usize| -> Option<Table> {
+            let len = read_u32(bytes, at)? as usize;
+            let start = at + 4;
+            (bytes.len() >= start + len * TABLE_ROW).then_some(Table { start, len })
+        };
+        let entries = read_table(header_start + header_len).ok_or_else(corrupt)?;
+        let next = |table: Table| -> Result<Table, IndexError> {
+            read_table(table.start + table.len * TABLE_ROW).ok_or_else(corrupt)
+        };
+        let bins = next(entries)?;
+        let tokens = next(bins)?;
+
+        let mapped = Self {
+            entries,
+            bins,
+            tokens,
+            version: header.version,
+            cache: (0..entries.len).map(|_| OnceLock::new()).collect(),
+            mmap,
+        };
+        for table in [entries, bins, tokens] {
+            for i in 0..table.len {
+                let (key, value) = mapped.row(table, i);
+                if mapped.slice(key).is_none() || mapped.slice(value).is_none() {
+                    return Err(corrupt());
+                }
+            }
+        }
+        Ok((header, mapped))
+    }
+
+    /// Number of entries.
+    pub(super) fn len(&self) -> usize {
+        self.cache.len()
+    }
+
+    /// Entry `i`, decoded on first access.
+    pub(super) fn get(&self, i: usize) -> Option<&IndexEntry> {
+        self.cache
+            .get(i)?
+            .get_or_init(|| {
+                let record = self.slice(self.row(self.entries, i).1)?;
+                super::decode_entry(record, self.version).ok()
+            })
+            .as_ref()
+    }
+
+    /// Name of entry `i`, read without decoding the entry.
+    pub(super) fn name(&self, i: usize) -> Option<&str> {
+        if i >= self.len() {
+            return None;
+        }
+        std::str::from_utf8(self.key(self.entries, i)).ok()
+    }
+
+    /// Binary search by name.
+    pub(super) fn find(&self, name: &str) -> Option<&IndexEntry> {
+        let i = self.position(self.entries, name).ok()?;
+        self.get(i)
+    }
+
+    /// Entries whose name starts with `prefix`, in name order.
+    pub(super) fn prefix(&self, prefix: &str) -> impl Iterator<Item = &IndexEntry> {
+        let start = self.position(self.entries, prefix).unwrap_or_else(|i| i);
+        (start..self.len())
+            .take_while(move |&i| self.key(self.entries, i).starts_with(prefix.as_bytes()))
+            .filter_map(|i| self.get(i))
+    }
+
+    /// Every entry in name order.
+    pub(super) fn iter(&self) -> impl Iterator<Item = &IndexEntry> {
+        (0..self.len()).filter_map(|i| self.get(i))
+    }
+
+    /// Entries providing binary `bin`.
+    pub(super) fn providers(&self, bin: &str) -> Vec<&IndexEntry> {
+        let Ok(i) = self.position(self.bins, bin) else {
+            return Vec::new();
+        };
+        self.list(self.bins, i)
+            .filter_map(|n| self.get(n))
+            .collect()
+    }
+
+    /// Entry numbers with a search token starting with `prefix`.
+    pub(super) fn token_prefix(&self, prefix: &str) -> BTreeSet<usize> {
+        let table = self.tokens;
+        let start = self.position(table, prefix).unwrap_or_else(|i| i);
+        (start..table.len)
+            .take_while(|&i| self.key(table, i).starts_with(prefix.as_bytes()))
+            .flat_map(|i| self.list(table, i))
+            .collect()
+    }
+
+    /// Entry numbers stored as the value of row `i`.
+    fn list(&self, table: Table, i: usize) -> impl Iterator<Item = usize> + '_ {
+        self.slice(self.row(table, i).1)
+            .unwrap_or_default()
+            .chunks_exact(4)
+            .filter_map(|n| Some(u32::from_le_bytes(n.try_into().ok()?) as usize))
+    }
+
+    fn position(&self, table: Table, key: &str) -> Result<usize, usize> {
+        let (mut lo, mut hi) = (0, table.len);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.key(table, mid).cmp(key.as_bytes()) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Greater => hi = mid,
+                std::cmp::Ordering::Equal => return Ok(mid),
+            }
+        }
+        Err(lo)
+    }
+
+    /// Key of row `i`, compared without decoding anything.
+    fn key(&self, table: Table, i: usize) -> &[u8] {
+        self.slice(self.row(table, i).0).unwrap_or_default()
+    }
+
+    /// `(key, value)` spans of row `i`.
+    fn row(&self, table: Table, i: usize) -> ((usize, usize), (usize, usize)) {
+        let at = table.start + i * TABLE_ROW;
+        let field = |n: usize| read_u32(&self.mmap, at + n * 4).unwrap_or_default() as usize;
+        ((field(0), field(1)), (field(2), field(3)))
+    }
+
+    fn slice(&self, (offset, len): (usize, usize)) -> Option<&[u8]> {
+        self.mmap.get(offset..offset.checked_add(len)?)
+    }
+}
+
+/// Lays out `entries` (sorted by name) in the mapped format.
+pub(super) fn encode(
+    header: &MappedHeader,
+    entries: &[&IndexEntry],
+) -> Result<Vec<u8>, IndexError> {
+    let header = postcard::to_allocvec(header)?;
+
+    let mut bins: BTreeMap<&str, Vec<u8>> = BTreeMap::new();
+    let mut tokens: BTreeMap<String, Vec<u8>> = BTreeMap::new();
+    for (i, entry) in entries.iter().enumerate() {
+        let i = to_u32(i)?.to_le_bytes();
+        for bin in entry.provided_bins() {
+            bins.entry(bin).or_default().extend_from_slice(&i);
+        }
+        for token in entry.search_tokens() {
+            tokens.entry(token).or_default().extend_from_slice(&i);
+        }
+    }
+
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(&to_u32(header.len())?.to_le_bytes());
+    out.extend_from_slice(&header);
+
+    out.extend_from_slice(&to_u32(entries.len())?.to_le_bytes());
+    let entry_table = out.len();
+    out.resize(entry_table + entries.len() * TABLE_ROW, 0);
+    out.extend_from_slice(&to_u32(bins.len())?.to_le_bytes());
+    let bin_table = out.len();
+    out.resize(bin_table + bins.len() * TABLE_ROW, 0);
+    out.extend_from_slice(&to_u32(tokens.len())?.to_le_bytes());
+    let token_table = out.len();
+    out.resize(token_table + tokens.len() * TABLE_ROW, 0);
+
+    for (i, entry) in entries.iter().enumerate() {
+        let record = postcard::to_allocvec(entry)?;
+        write_row(
+            &mut out,
+            entry_table + i * TABLE_ROW,
+            entry.name.as_bytes(),
+            &record,
+        )?;
+    }
+    for (i, (bin, list)) in bins.iter().enumerate() {
+        write_row(&mut out, bin_table + i * TABLE_ROW, bin.as_bytes(), list)?;
+    }
+    for (i, (token, list)) in tokens.iter().enumerate() {
+        write_row(
+            &mut out,
+            token_table + i * TABLE_ROW,
+            token.as_bytes(),
+            list,
+        )?;
+    }
+    Ok(out)
+}
+
+/// Appends `key` and `value` to the data and points row `at` at them.
+fn write_row(out: &mut Vec<u8>, at: usize, key: &[u8], value: &[u8]) -> Result<(), IndexError> {
+    let key_off = out.len();
+    out.extend_from_slice(key);
+    let value_off = out.len();
+    out.extend_from_slice(value);
+
+    let row = [key_off, key.len(), value_off, value.len()];
+    for (n, field) in row.into_iter().enumerate() {
+        let field_at = at + n * 4;
+        out[field_at..field_at + 4].copy_from_slice(&to_u32(field)?.to_le_bytes());
+    }
+    Ok(())
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    let raw = bytes.get(at..at + 4)?;
+    Some(u32::from_le_bytes(raw.try_into().ok()?))
+}
+
+fn to_u32(value: usize) -> Result<u32, IndexError> {
+    u32::try_from(value)
+        .map_err(|_| IndexError::Package("index too large for the mapped layout".to_string()))
+}
@@ -16,6 +16,8 @@ pub mod hash;
 pub mod index;
 /// Merkle tree for index integrity verification.
 pub mod merkle;
+/// Package search: query language, ranking and pagination.
+pub mod search;
 /// Core domain types: artifacts, port configs, package names, and versions.
 pub mod types;
 /// Version parsing, comparison, and requirement matching.
@@ -0,0 +1,495 @@
+//! Package search over a [`PackageIndex`].
+//!
+//! A query is a list of terms, all of which must match. `OR` (or `|`)
+//! separates alternatives; `AND` is accepted and ignored:
+//!
+//! ```text
+//! json parser                 text: every word must match a search token
+//! tag:editor                  a tag, case-insensitive
+//! type:app                    package type ("cli" or "app")
+//! license:apache              license, case-insensitive prefix
+//! has-bin:rg                  ships a binary with this exact name
+//! installed:yes               installed (or not, with "no")
+//! tag:editor type:app OR vim  either group matches
+//! ```
+//!
+//! Words are looked up in the inverted token index written with the index
+//! (see [`IndexEntry::search_tokens`]) as prefixes, so `neo` finds `neovim`.
+//! Hits are ranked so an exact package name beats an exact binary name,
+//! which beats name prefixes, name words, binary and tag words and finally
+//! description words. When no package matches a group's words, the group
+//! falls back to fuzzy matching to catch typos.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+use fuzzy_matcher::FuzzyMatcher;
+use fuzzy_matcher::skim::SkimMatcherV2;
+use thiserror::Error;
+
+use crate::index::{IndexEntry, PackageIndex};
+
+/// Lowest fuzzy score that still counts as a match.
+const MIN_FUZZY_SCORE: i64 = 50;
+
+/// Errors from parsing a search query.
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum QueryError {
+    /// `<filter>:` is not one of the known filters.
+    #[error("unknown filter '{0}:' (expected tag, type, license, has-bin or installed)")]
+    UnknownFilter(String),
+    /// The filter's value is empty or not allowed.
+    #[error("invalid value '{value}' for '{filter}:'")]
+    InvalidValue {
+        /// Filter name, lowercased
+        filter: String,
+        /// The rejected value
+        value: String,
+    },
+    /// `OR` with nothing on one side.
+    #[error("'OR' needs a term on both sides")]
+    DanglingOr,
+}
+
+/// One condition of a query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Term {
+    /// Free text, lowercased
+    Text(String),
+    /// `tag:<tag>`
+    Tag(String),
+    /// `type:<type>`
+    Type(String),
+    /// `license:<prefix>`
+    License(String),
+    /// `has-bin:<binary>`
+    HasBin(String),
+    /// `installed:yes|no`
+    Installed(bool),
+}
+
+impl Term {
+    /// Whether a non-text term holds for `entry`. Text terms always hold
+    /// here; they are matched against the token index instead.
+    fn filter(&self, entry: &IndexEntry, installed: &dyn Fn(&str) -> bool) -> bool {
+        match self {
+            Self::Text(_) => true,
+            Self::Tag(tag) => entry.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)),
+            Self::Type(type_) => entry.type_.eq_ignore_ascii_case(type_),
+            Self::License(prefix) => entry.license.to_lowercase().starts_with(prefix),
+            Self::HasBin(bin) => entry.provided_bins().contains(bin.as_str()),
+            Self::Installed(wanted) => installed(&entry.name) == *wanted,
+        }
+    }
+}
+
+/// A parsed query: alternatives (`OR`) of terms that must all match.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Query {
+    groups: Vec<Vec<Term>>,
+}
+
+impl Query {
+    /// Parses `input`; see the [module docs](self) for the syntax.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`QueryError`] for unknown filters, bad filter values or a
+    /// dangling `OR`.
+    pub fn parse(input: &str) -> Result<Self, QueryError> {
+        let mut groups = Vec::new();
+        let mut group = Vec::new();
+        for word in input.split_whitespace() {
+            match word {
+                "OR" | "|" => groups.push(std::mem::take(&mut group)),
+                "AND" => {}
+                _ => group.push(parse_term(word)?),
+            }
+        }
+        groups.push(group);
+        match groups.as_slice() {
+            [only] if only.is_empty() => Ok(Self::default()),
+            _ if groups.iter().any(Vec::is_empty) => Err(QueryError::DanglingOr),
+            _ => Ok(Self { groups }),
+        }
+    }
+
+    /// Whether the query has no terms and so matches every package.
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+
+    /// Alternatives of the query, each a list of terms that must all match.
+    pub fn groups(&self) -> &[Vec<Term>] {
+        &self.groups
+    }
+}
+
+impl FromStr for Query {
+    type Err = QueryError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+fn parse_term(word: &str) -> Result<Term, QueryError> {
+    let Some((filter, value)) = word.split_once(':') else {
+        return Ok(Term::Text(word.to_lowercase()));
+    };
+    let filter = filter.to_lowercase();
+    let value = value.to_lowercase();
+    let invalid = || QueryError::InvalidValue {
+        filter: filter.clone(),
+        value: value.clone(),
+    };
+    if value.is_empty() {
+        return Err(invalid());
+    }
+    Ok(match filter.as_str() {
+        "tag" => Term::Tag(value),
+        "type" => Term::Type(value),
+        "license" => Term::License(value),
+        "has-bin" | "bin" => Term::HasBin(value),
+        "installed" => match value.as_str() {
+            "yes" | "true" => Term::Installed(true),
+            "no" | "false" => Term::Installed(false),
+            _ => return Err(invalid()),
+        },
+        _ => return Err(QueryError::UnknownFilter(filter)),
+    })
+}
+
+/// Splits `text` into lowercase alphanumeric words.
+pub fn tokenize(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+        .map(str::to_lowercase)
+}
+
+/// Options for [`search`].
+#[derive(Default)]
+pub struct SearchOptions<'a> {
+    /// Whether a package is installed, for `installed:` terms. Without it
+    /// no package counts as installed.
+    pub installed: Option<&'a dyn Fn(&str) -> bool>,
+    /// Number of ranked hits to skip
+    pub offset: usize,
+    /// Maximum number of hits to return; `None` for all
+    pub limit: Option<usize>,
+}
+
+impl fmt::Debug for SearchOptions<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SearchOptions")
+            .field("installed", &self.installed.is_some())
+            .field("offset", &self.offset)
+            .field("limit", &self.limit)
+            .finish()
+    }
+}
+
+/// A matching package and its rank.
+#[derive(Debug, Clone)]
+pub struct Hit<'a> {
+    /// The matching package
+    pub entry: &'a IndexEntry,
+    /// Higher is better; only comparable within one search
+    pub score: i64,
+}
+
+/// One page of search results.
+#[derive(Debug, Clone)]
+pub struct SearchPage<'a> {
+    /// Hits on this page, best first
+    pub hits: Vec<Hit<'a>>,
+    /// Number of matching packages across all pages
+    pub total: usize,
+}
+
+/// Runs `query` against `index` and returns the requested page of hits.
+///
+/// Hits are ordered by score, then by name. An empty query matches every
+/// package in name order.
+pub fn search<'a>(
+    index: &'a PackageIndex,
+    query: &Query,
+    options: &SearchOptions<'_>,
+) -> SearchPage<'a> {
+    let not_installed = |_: &str| false;
+    let installed = options.installed.unwrap_or(&not_installed);
+
+    // Position in the index -> best score over all groups
+    let mut scores: BTreeMap<usize, i64> = BTreeMap::new();
+    if query.is_empty() {
+        scores.extend((0..index.len()).map(|i| (i, 0)));
+    }
+    for group in query.groups() {
+        for (i, score) in search_group(index, group, installed) {
+            let best = scores.entry(i).or_insert(score);
+            *best = (*best).max(score);
+        }
+    }
+
+    let mut hits: Vec<Hit<'a>> = scores
+        .into_iter()
+        .filter_map(|(i, score)| {
+            Some(Hit {
+                entry: index.get(i)?,
+                score,
+            })
+        })
+        .collect();
+    // Positions are in name order, so a stable sort keeps ties by name.
+    hits.sort_by(|a, b| b.score.cmp(&a.score));
+
+    let total = hits.len();
+    let hits = hits
+        .into_iter()
+        .skip(options.offset)
+        .take(options.limit.unwrap_or(usize::MAX))
+        .collect();
+    SearchPage { hits, total }
+}
+
+/// Positions and scores of the entries matching every term of `group`.
+fn search_group(
+    index: &PackageIndex,
+    group: &[Term],
+    installed: &dyn Fn(&str) -> bool,
+) -> Vec<(usize, i64)> {
+    let words: Vec<&str> = group
+        .iter()
+        .filter_map(|term| match term {
+            Term::Text(word) => Some(word.as_str()),
+            _ => None,
+        })
+        .collect();
+    let keep = |entry: &IndexEntry| group.iter().all(|term| term.filter(entry, installed));
+
+    // Every token of every word must prefix a token of the entry.
+    let mut candidates: Option<std::collections::BTreeSet<usize>> = None;
+    for token in words.iter().flat_map(|word| tokenize(word)) {
+        let matching = index.token_prefix(&token);
+        candidates = Some(match candidates {
+            Some(found) => found.intersection(&matching).copied().collect(),
+            None => matching,
+        });
+    }
+
+    let Some(candidates) = candidates else {
+        return index
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| keep(entry))
+            .map(|(i, _)| (i, 0))
+            .collect();
+    };
+    let hits: Vec<(usize, i64)> = candidates
+        .into_iter()
+        .filter_map(|i| {
+            let entry = index.get(i)?;
+            keep(entry).then(|| (i, words.iter().map(|w| word_score(entry, w)).sum()))
+        })
+        .collect();
+    if !hits.is_empty() {
+        return hits;
+    }
+
+    let pattern = words.join(" ");
+    index
+        .iter()
+        .enumerate()
+        .filter(|(_, entry)| keep(entry))
+        .filter_map(|(i, entry)| Some((i, fuzzy_score(entry, &pattern)?)))
+        .collect()
+}
+
+/// Rank of `entry` for a query word it matched in the token index.
+fn word_score(entry: &IndexEntry, word: &str) -> i64 {
+    let name = entry.name.to_lowercase();
+    let has_prefix = |text: &str| tokenize(text).any(|t| t.starts_with(word));
+    if name == word {
+        1000
+    } else if entry
+        .provided_bins()
+        .iter()
+        .any(|b| b.eq_ignore_ascii_case(word))
+    {
+        800
+    } else if name.starts_with(word) {
+        400
+    } else if has_prefix(&name) {
+        300
+    } else if entry.provided_bins().iter().any(|b| has_prefix(b))
+        || entry.tags.iter().any(|t| has_prefix(t))
+    {
+        200
+    } else {
+        100
+    }
+}
+
+/// Fuzzy score of `entry` for a query with no token matches, if it is
+/// close enough to count.
+fn fuzzy_score(entry: &IndexEntry, query: &str) -> Option<i64> {
+    let matcher = SkimMatcherV2::default();
+    // Description matches are weighted below name and binary matches
+    let mut best = matcher.fuzzy_match(&entry.name, query);
+    if let Some(desc_score) = matcher.fuzzy_match(&entry.description, query) {
+        best = Some(best.unwrap_or(0).max(desc_score / 2));
+    }
+    for bin in &entry.bins {
+        if let Some(bin_score) = matcher.fuzzy_match(bin, query) {
+            best = Some(best.unwrap_or(0).max(bin_score));
+        }
+    }
+
+    // Fallback for typos: the query extends a name or a name extends the query
+    let close = |name: &str| {
+        (query.len() > 3 && name.starts_with(query)) || (name.len() > 2 && query.starts_with(name))
+    };
+    if best.unwrap_or(0) < MIN_FUZZY_SCORE
+        && (close(&entry.name) || entry.bins.iter().any(|b| close(b)))
+    {
+        best = Some(best.unwrap_or(0).max(MIN_FUZZY_SCORE));
+    }
+
+    best.filter(|s| *s >= MIN_FUZZY_SCORE)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::index::VersionInfo;
+
+    fn entry(name: &str, description: &str, bins: &[&str], tags: &[&str]) -> IndexEntry {
+        IndexEntry {
+            name: name.to_string(),
+            description: description.to_string(),
+            type_: "cli".to_string(),
+            bins: bins.iter().map(ToString::to_string).collect(),
+            releases: vec![VersionInfo {
+                version: "1.0.0".to_string(),
+                ..VersionInfo::default()
+            }],
+            tags: tags.iter().map(ToString::to_string).collect(),
+            ..IndexEntry::default()
+        }
+    }
+
+    fn sample() -> PackageIndex {
+        let mut index = PackageIndex::new();
+        index.upsert(IndexEntry {
+            license: "Apache-2.0".to_string(),
+            ..entry(
+                "neovim",
+                "Vim-fork focused on extensibility",
+                &["nvim"],
+                &["editor"],
+            )
+        });
+        index.upsert(IndexEntry {
+            license: "MIT".to_string(),
+            ..entry(
+                "ripgrep",
+                "Recursively search directories",
+                &["rg"],
+                &["search"],
+            )
+        });
+        index.upsert(entry("rg-wrapper", "Helpers around rg", &["rgw"], &[]));
+        index.upsert(IndexEntry {
+            type_: "app".to_string(),
+            ..entry("zed", "A fast code editor", &[], &["editor"])
+        });
+        index
+    }
+
+    fn names(page: &SearchPage<'_>) -> Vec<String> {
+        page.hits.iter().map(|h| h.entry.name.clone()).collect()
+    }
+
+    fn run(index: &PackageIndex, query: &str) -> Vec<String> {
+        names(&search(
+            index,
+            &query.parse().unwrap(),
+            &SearchOptions::default(),
+        ))
+    }
+
+    #[test]
+    fn test_parse() {
+        let query = Query::parse("Editor tag:CLI AND has-bin:rg OR installed:no").unwrap();
+        assert_eq!(
+            query.groups(),
+            [
+                vec![
+                    Term::Text("editor".to_string()),
+                    Term::Tag("cli".to_string()),
+                    Term::HasBin("rg".to_string()),
+                ],
+                vec![Term::Installed(false)],
+            ]
+        );
+        assert!(Query::parse("  ").unwrap().is_empty());
+        assert_eq!(
+            Query::parse("colour:red"),
+            Err(QueryError::UnknownFilter("colour".to_string()))
+        );
+        assert!(matches!(
+            Query::parse("installed:maybe"),
+            Err(QueryError::InvalidValue { .. })
+        ));
+        assert_eq!(Query::parse("vim OR"), Err(QueryError::DanglingOr));
+    }
+
+    #[test]
+    fn test_ranking() {
+        let index = sample();
+        // Exact binary match beats a name prefix, which beats a description word
+        assert_eq!(run(&index, "rg"), ["ripgrep", "rg-wrapper"]);
+        assert_eq!(run(&index, "editor"), ["neovim", "zed"]);
+        assert_eq!(run(&index, "neo"), ["neovim"]);
+        // Every word must match
+        assert_eq!(run(&index, "fast editor"), ["zed"]);
+        // Typos fall back to fuzzy matching
+        assert_eq!(run(&index, "novim"), ["neovim"]);
+    }
+
+    #[test]
+    fn test_filters() {
+        let index = sample();
+        assert_eq!(run(&index, "tag:editor type:app"), ["zed"]);
+        assert_eq!(run(&index, "license:apache"), ["neovim"]);
+        assert_eq!(run(&index, "has-bin:rg"), ["ripgrep"]);
+        assert_eq!(
+            run(&index, "has-bin:zed OR license:mit"),
+            ["ripgrep", "zed"]
+        );
+
+        let installed = |name: &str| name == "zed";
+        let options = SearchOptions {
+            installed: Some(&installed),
+            ..SearchOptions::default()
+        };
+        let page = search(&index, &"installed:yes".parse().unwrap(), &options);
+        assert_eq!(names(&page), ["zed"]);
+        let page = search(&index, &"editor installed:no".parse().unwrap(), &options);
+        assert_eq!(names(&page), ["neovim"]);
+    }
+
+    #[test]
+    fn test_pagination() {
+        let index = sample();
+        let options = SearchOptions {
+            offset: 1,
+            limit: Some(2),
+            ..SearchOptions::default()
+        };
+        let page = search(&index, &Query::default(), &options);
+        assert_eq!(page.total, 4);
+        assert_eq!(names(&page), ["rg-wrapper", "ripgrep"]);
+    }
+}
@@ -91,6 +91,12 @@ pub enum ArtifactFormat {
     /// Zstandard-compressed tar archive (`.tar.zst`).
     #[serde(rename = "tar.zst")]
     TarZst,
+    /// XZ-compressed tar archive (`.tar.xz` / `.txz`).
+    #[serde(rename = "tar.xz")]
+    TarXz,
+    /// Bzip2-compressed tar archive (`.tar.bz2` / `.tbz2`).
+    #[serde(rename = "tar.bz2")]
+    TarBz2,
     /// Uncompressed tar archive (`.tar`).
     Tar,
     /// Zip archive (`.zip`).
@@ -99,10 +105,46 @@ pub enum ArtifactFormat {
     Dmg,
     /// macOS installer package (`.pkg`).
     Pkg,
+    /// 7-Zip archive (`.7z`).
+    #[serde(rename = "7z")]
+    SevenZ,
+    /// Single gzip-compressed executable (`.gz`).
+    Gz,
+    /// Single XZ-compressed executable (`.xz`).
+    Xz,
+    /// Single Zstandard-compressed executable (`.zst`).
+    Zst,
     /// Standalone executable with no archive wrapper.
     Binary,
 }
 
+impl ArtifactFormat {
+    /// The name used for this format in package files (e.g. `tar.gz`).
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::TarGz => "tar.gz",
+            Self::TarZst => "tar.zst",
+            Self::TarXz => "tar.xz",
+            Self::TarBz2 => "tar.bz2",
+            Self::Tar => "tar",
+            Self::Zip => "zip",
+            Self::Dmg => "dmg",
+            Self::Pkg => "pkg",
+            Self::SevenZ => "7z",
+            Self::Gz => "gz",
+            Self::Xz => "xz",
+            Self::Zst => "zst",
+            Self::Binary => "binary",
+        }
+    }
+}
+
+impl std::fmt::Display for ArtifactFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
 /// How a package should be installed after extraction.
 #[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]